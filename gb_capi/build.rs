@@ -0,0 +1,7 @@
+fn main() {
+    // tests/c_api.rs compiles a C program for the same target with the cc crate
+    println!(
+        "cargo:rustc-env=GB_CAPI_TARGET={}",
+        std::env::var("TARGET").unwrap()
+    );
+}
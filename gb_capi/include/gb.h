@@ -0,0 +1,187 @@
+#ifndef GB_H
+#define GB_H
+
+/* Regenerate with cbindgen from gb_capi/src/lib.rs rather than editing by hand */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Size in bytes of a frame returned by [`gb_run_frame`]
+ */
+#define GB_FRAME_BYTES ((160 * 144) * 4)
+
+/**
+ * The result of a call
+ */
+typedef enum GbStatus {
+  GB_STATUS_OK = 0,
+  /**
+   * A pointer that must not be null was null
+   */
+  GB_STATUS_NULL_POINTER = 1,
+  /**
+   * The ROM image is malformed
+   */
+  GB_STATUS_INVALID_ROM = 2,
+  /**
+   * The cartridge uses a mapper that isn't implemented
+   */
+  GB_STATUS_UNSUPPORTED_MAPPER = 3,
+  /**
+   * A buffer is the wrong size
+   */
+  GB_STATUS_BUFFER_SIZE = 4,
+  /**
+   * A save file or save state doesn't match the cartridge
+   */
+  GB_STATUS_INVALID_SAVE_DATA = 5,
+  /**
+   * The cartridge has no RAM, or the operation isn't implemented yet
+   */
+  GB_STATUS_UNSUPPORTED = 6,
+  /**
+   * The emulator panicked, and the handle can no longer be used
+   */
+  GB_STATUS_PANIC = 7,
+  /**
+   * Any other error from the emulator
+   */
+  GB_STATUS_ERROR = 8,
+} GbStatus;
+
+/**
+ * A Gameboy, created by [`gb_create`] and freed by [`gb_destroy`]. Opaque to C.
+ */
+typedef struct GbHandle GbHandle;
+
+/**
+ * Called with each byte shifted out of the serial port, and the `user_data` it was registered
+ * with
+ */
+typedef void (*GbSerialCallback)(void *user_data, uint8_t byte);
+
+/**
+ * Load a ROM image and reset the machine, ready to run. The ROM is copied, so it can be freed as
+ * soon as this returns.
+ *
+ * Returns null if the ROM can't be loaded, and writes the reason to `err_out` unless it is null.
+ *
+ * # Safety
+ * `rom` must be valid for reads of `rom_len` bytes, and `err_out` must be null or valid for a
+ * write.
+ */
+GbHandle *gb_create(const uint8_t *rom, size_t rom_len, GbStatus *err_out);
+
+/**
+ * Free a handle from [`gb_create`]. Does nothing if `handle` is null.
+ *
+ * # Safety
+ * `handle` must be null or a live handle from [`gb_create`]. It can't be used again afterwards.
+ */
+void gb_destroy(GbHandle *handle);
+
+/**
+ * Run until the next frame is complete, and return it as 160x144 pixels of 4 bytes each, in the
+ * order red, green, blue, alpha, row by row from the top left. That is [`GB_FRAME_BYTES`] bytes.
+ *
+ * The pixels belong to the handle. They stay valid until the next call to `gb_run_frame` or
+ * [`gb_destroy`] on the same handle, and must not be freed or written to.
+ *
+ * Returns null if `handle` is null or the emulator panicked.
+ *
+ * # Safety
+ * `handle` must be null or a live handle from [`gb_create`] that isn't in use by another thread.
+ */
+const uint8_t *gb_run_frame(GbHandle *handle);
+
+/**
+ * Set the state of every button at once. A set bit means the button is held.
+ *
+ * From bit 0 to bit 7: A, B, Select, Start, Right, Left, Up, Down
+ *
+ * # Safety
+ * `handle` must be null or a live handle from [`gb_create`] that isn't in use by another thread.
+ */
+GbStatus gb_set_buttons(GbHandle *handle, uint8_t buttons);
+
+/**
+ * Size in bytes of the cartridge RAM, or 0 if the cartridge has none or `handle` is unusable
+ *
+ * # Safety
+ * `handle` must be null or a live handle from [`gb_create`] that isn't in use by another thread.
+ */
+size_t gb_save_ram_size(GbHandle *handle);
+
+/**
+ * Copy the cartridge RAM into `buf`, which must be exactly [`gb_save_ram_size`] bytes long
+ *
+ * Returns [`GbStatus::Unsupported`] if the cartridge has no RAM, or [`GbStatus::BufferSize`] if
+ * `len` is wrong.
+ *
+ * # Safety
+ * `handle` must be null or a live handle from [`gb_create`] that isn't in use by another thread,
+ * and `buf` must be valid for writes of `len` bytes.
+ */
+GbStatus gb_save_ram_copy(GbHandle *handle, uint8_t *buf, size_t len);
+
+/**
+ * Restore the cartridge RAM from `len` bytes previously copied out with [`gb_save_ram_copy`]
+ *
+ * # Safety
+ * `handle` must be null or a live handle from [`gb_create`] that isn't in use by another thread,
+ * and `buf` must be valid for reads of `len` bytes.
+ */
+GbStatus gb_load_save_ram(GbHandle *handle, const uint8_t *buf, size_t len);
+
+/**
+ * Size in bytes of a save state, or 0 if `handle` is unusable. The size only changes when the
+ * cartridge does.
+ *
+ * # Safety
+ * `handle` must be null or a live handle from [`gb_create`] that isn't in use by another thread.
+ */
+size_t gb_save_state_size(GbHandle *handle);
+
+/**
+ * Copy a save state of the whole machine into `buf`, which must be exactly
+ * [`gb_save_state_size`] bytes long. The CPU finishes the instruction it is in first.
+ *
+ * Returns [`GbStatus::BufferSize`] if `len` is wrong.
+ *
+ * # Safety
+ * `handle` must be null or a live handle from [`gb_create`] that isn't in use by another thread,
+ * and `buf` must be valid for writes of `len` bytes.
+ */
+GbStatus gb_save_state_copy(GbHandle *handle, uint8_t *buf, size_t len);
+
+/**
+ * Restore a save state from [`gb_save_state_copy`], by this or an earlier version of the
+ * library
+ *
+ * Returns [`GbStatus::InvalidSaveData`] without changing anything if the state can't be read or
+ * was taken with a different type of cartridge.
+ *
+ * # Safety
+ * `handle` must be null or a live handle from [`gb_create`] that isn't in use by another thread,
+ * and `buf` must be valid for reads of `len` bytes.
+ */
+GbStatus gb_load_state(GbHandle *handle, const uint8_t *buf, size_t len);
+
+/**
+ * Call `callback` with every byte shifted out of the serial port, replacing any callback set
+ * before. Pass a null callback to remove it.
+ *
+ * The callback runs during [`gb_run_frame`], on the same thread, and must not call back into
+ * the same handle.
+ *
+ * # Safety
+ * `handle` must be null or a live handle from [`gb_create`] that isn't in use by another thread,
+ * and `callback` must be safe to call with `user_data` for as long as it is set.
+ */
+GbStatus gb_set_serial_callback(GbHandle *handle, GbSerialCallback callback, void *user_data);
+
+#endif /* GB_H */
@@ -0,0 +1,372 @@
+//! C bindings for embedding the emulator in frontends written in other languages.
+//!
+//! Build with `cargo build --release -p gb_capi`, which produces both a shared and a static
+//! library, and include `gb_capi/include/gb.h`. After changing anything exported here, regenerate
+//! the header with
+//!
+//! ```text
+//! cbindgen --config gb_capi/cbindgen.toml --crate gb_capi --output gb_capi/include/gb.h
+//! ```
+//!
+//! No function lets a panic unwind into C. If the emulator panics, the call returns
+//! [`GbStatus::Panic`] or a null pointer, and so does every later call on the same handle, since
+//! the machine may have stopped half way through an M-cycle. The handle must still be destroyed.
+//!
+//! A handle may be moved between threads, but must not be used from two threads at once.
+
+use std::{
+    ffi::c_void,
+    panic::{self, AssertUnwindSafe},
+    ptr, slice,
+};
+
+use gb_core::{
+    gameboy::events::{Event, EventMask, SubscriptionId},
+    prelude::{Gameboy, GbError, ResetKind},
+};
+
+/// Size in bytes of a frame returned by [`gb_run_frame`]
+pub const GB_FRAME_BYTES: usize = 160 * 144 * 4;
+
+/// The result of a call
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GbStatus {
+    Ok = 0,
+    /// A pointer that must not be null was null
+    NullPointer = 1,
+    /// The ROM image is malformed
+    InvalidRom = 2,
+    /// The cartridge uses a mapper that isn't implemented
+    UnsupportedMapper = 3,
+    /// A buffer is the wrong size
+    BufferSize = 4,
+    /// A save file or save state doesn't match the cartridge
+    InvalidSaveData = 5,
+    /// The cartridge has no RAM, or the operation isn't implemented yet
+    Unsupported = 6,
+    /// The emulator panicked, and the handle can no longer be used
+    Panic = 7,
+    /// Any other error from the emulator
+    Error = 8,
+}
+
+impl From<GbError> for GbStatus {
+    fn from(error: GbError) -> Self {
+        match error {
+            GbError::InvalidRom(_) => GbStatus::InvalidRom,
+            GbError::UnsupportedMapper(_) => GbStatus::UnsupportedMapper,
+            GbError::BufferSize { .. } => GbStatus::BufferSize,
+            GbError::InvalidSaveData(_) => GbStatus::InvalidSaveData,
+            GbError::AddressOutOfRange(_) | GbError::ChipConflict(_) | GbError::InvalidState(_) => {
+                GbStatus::Error
+            }
+        }
+    }
+}
+
+/// Called with each byte shifted out of the serial port, and the `user_data` it was registered
+/// with
+pub type GbSerialCallback = unsafe extern "C" fn(user_data: *mut c_void, byte: u8);
+
+/// A Gameboy, created by [`gb_create`] and freed by [`gb_destroy`]. Opaque to C.
+pub struct GbHandle {
+    gameboy: Gameboy,
+    /// The frame returned by the last call to [`gb_run_frame`], as RGBA
+    frame: Box<[u8]>,
+    serial_subscription: Option<SubscriptionId>,
+    /// Set once the emulator has panicked
+    poisoned: bool,
+}
+
+/// The user data pointer passed back to a [`GbSerialCallback`]
+struct UserData(*mut c_void);
+
+// Safety: the caller of `gb_set_serial_callback` promises that the callback may be called with
+// `user_data` from whichever thread runs the handle
+unsafe impl Send for UserData {}
+
+impl UserData {
+    fn get(&self) -> *mut c_void {
+        self.0
+    }
+}
+
+/// Run `f` on the handle behind `handle`, catching any panic
+///
+/// # Safety
+/// `handle` must be null or a live handle from [`gb_create`] that isn't in use by another thread
+unsafe fn call<T>(
+    handle: *mut GbHandle,
+    f: impl FnOnce(&mut GbHandle) -> Result<T, GbStatus>,
+) -> Result<T, GbStatus> {
+    let handle = handle.as_mut().ok_or(GbStatus::NullPointer)?;
+    if handle.poisoned {
+        return Err(GbStatus::Panic);
+    }
+    match panic::catch_unwind(AssertUnwindSafe(|| f(&mut *handle))) {
+        Ok(result) => result,
+        Err(_) => {
+            handle.poisoned = true;
+            Err(GbStatus::Panic)
+        }
+    }
+}
+
+fn status(result: Result<(), GbStatus>) -> GbStatus {
+    result.err().unwrap_or(GbStatus::Ok)
+}
+
+/// `len` bytes at `ptr` as a slice. A null pointer is only allowed if `len` is 0.
+///
+/// # Safety
+/// Unless null, `ptr` must be valid for reads of `len` bytes
+unsafe fn bytes<'a>(ptr: *const u8, len: usize) -> Result<&'a [u8], GbStatus> {
+    match (ptr.is_null(), len) {
+        (true, 0) => Ok(&[]),
+        (true, _) => Err(GbStatus::NullPointer),
+        (false, _) => Ok(slice::from_raw_parts(ptr, len)),
+    }
+}
+
+/// Load a ROM image and reset the machine, ready to run. The ROM is copied, so it can be freed as
+/// soon as this returns.
+///
+/// Returns null if the ROM can't be loaded, and writes the reason to `err_out` unless it is null.
+///
+/// # Safety
+/// `rom` must be valid for reads of `rom_len` bytes, and `err_out` must be null or valid for a
+/// write.
+#[no_mangle]
+pub unsafe extern "C" fn gb_create(
+    rom: *const u8,
+    rom_len: usize,
+    err_out: *mut GbStatus,
+) -> *mut GbHandle {
+    let result = bytes(rom, rom_len).and_then(|rom| {
+        panic::catch_unwind(|| {
+            let mut gameboy = Gameboy::builder().rom(rom).build()?;
+            gameboy.reset(ResetKind::PowerCycle);
+            Ok(Box::new(GbHandle {
+                gameboy,
+                frame: vec![0; GB_FRAME_BYTES].into_boxed_slice(),
+                serial_subscription: None,
+                poisoned: false,
+            }))
+        })
+        .unwrap_or(Err(GbStatus::Panic))
+    });
+    if let Some(err_out) = err_out.as_mut() {
+        *err_out = result.as_ref().err().copied().unwrap_or(GbStatus::Ok);
+    }
+    result.map_or(ptr::null_mut(), Box::into_raw)
+}
+
+/// Free a handle from [`gb_create`]. Does nothing if `handle` is null.
+///
+/// # Safety
+/// `handle` must be null or a live handle from [`gb_create`]. It can't be used again afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn gb_destroy(handle: *mut GbHandle) {
+    if !handle.is_null() {
+        let handle = Box::from_raw(handle);
+        // Dropping a poisoned Gameboy could panic again, in which case it is leaked instead
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| drop(handle)));
+    }
+}
+
+/// Run until the next frame is complete, and return it as 160x144 pixels of 4 bytes each, in the
+/// order red, green, blue, alpha, row by row from the top left. That is [`GB_FRAME_BYTES`] bytes.
+///
+/// The pixels belong to the handle. They stay valid until the next call to `gb_run_frame` or
+/// [`gb_destroy`] on the same handle, and must not be freed or written to.
+///
+/// Returns null if `handle` is null or the emulator panicked.
+///
+/// # Safety
+/// `handle` must be null or a live handle from [`gb_create`] that isn't in use by another thread.
+#[no_mangle]
+pub unsafe extern "C" fn gb_run_frame(handle: *mut GbHandle) -> *const u8 {
+    let result = call(handle, |handle| {
+        let frame = handle.gameboy.run_frames(1);
+        for (pixel, color) in handle.frame.chunks_exact_mut(4).zip(frame.colors()) {
+            pixel.copy_from_slice(&color.to_le_bytes());
+        }
+        Ok(handle.frame.as_ptr())
+    });
+    result.unwrap_or(ptr::null())
+}
+
+/// Set the state of every button at once. A set bit means the button is held.
+///
+/// From bit 0 to bit 7: A, B, Select, Start, Right, Left, Up, Down
+///
+/// # Safety
+/// `handle` must be null or a live handle from [`gb_create`] that isn't in use by another thread.
+#[no_mangle]
+pub unsafe extern "C" fn gb_set_buttons(handle: *mut GbHandle, buttons: u8) -> GbStatus {
+    status(call(handle, |handle| {
+        let joypad = &mut handle.gameboy.joypad;
+        joypad.a = buttons & 0x01 != 0;
+        joypad.b = buttons & 0x02 != 0;
+        joypad.select = buttons & 0x04 != 0;
+        joypad.start = buttons & 0x08 != 0;
+        joypad.right = buttons & 0x10 != 0;
+        joypad.left = buttons & 0x20 != 0;
+        joypad.up = buttons & 0x40 != 0;
+        joypad.down = buttons & 0x80 != 0;
+        Ok(())
+    }))
+}
+
+/// Size in bytes of the cartridge RAM, or 0 if the cartridge has none or `handle` is unusable
+///
+/// # Safety
+/// `handle` must be null or a live handle from [`gb_create`] that isn't in use by another thread.
+#[no_mangle]
+pub unsafe extern "C" fn gb_save_ram_size(handle: *mut GbHandle) -> usize {
+    call(handle, |handle| {
+        Ok(handle.gameboy.cart.ram().map_or(0, <[u8]>::len))
+    })
+    .unwrap_or(0)
+}
+
+/// Copy the cartridge RAM into `buf`, which must be exactly [`gb_save_ram_size`] bytes long
+///
+/// Returns [`GbStatus::Unsupported`] if the cartridge has no RAM, or [`GbStatus::BufferSize`] if
+/// `len` is wrong.
+///
+/// # Safety
+/// `handle` must be null or a live handle from [`gb_create`] that isn't in use by another thread,
+/// and `buf` must be valid for writes of `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn gb_save_ram_copy(
+    handle: *mut GbHandle,
+    buf: *mut u8,
+    len: usize,
+) -> GbStatus {
+    status(call(handle, |handle| {
+        let ram = handle.gameboy.cart.ram().ok_or(GbStatus::Unsupported)?;
+        if len != ram.len() {
+            return Err(GbStatus::BufferSize);
+        }
+        if buf.is_null() {
+            return Err(GbStatus::NullPointer);
+        }
+        slice::from_raw_parts_mut(buf, len).copy_from_slice(ram);
+        Ok(())
+    }))
+}
+
+/// Restore the cartridge RAM from `len` bytes previously copied out with [`gb_save_ram_copy`]
+///
+/// # Safety
+/// `handle` must be null or a live handle from [`gb_create`] that isn't in use by another thread,
+/// and `buf` must be valid for reads of `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn gb_load_save_ram(
+    handle: *mut GbHandle,
+    buf: *const u8,
+    len: usize,
+) -> GbStatus {
+    status(call(handle, |handle| {
+        let save = bytes(buf, len)?;
+        handle.gameboy.cart.load_ram(save).map_err(GbStatus::from)
+    }))
+}
+
+/// Size in bytes of a save state, or 0 if `handle` is unusable. The size only changes when the
+/// cartridge does.
+///
+/// # Safety
+/// `handle` must be null or a live handle from [`gb_create`] that isn't in use by another thread.
+#[no_mangle]
+pub unsafe extern "C" fn gb_save_state_size(handle: *mut GbHandle) -> usize {
+    call(handle, |handle| Ok(handle.gameboy.save_state().len())).unwrap_or(0)
+}
+
+/// Copy a save state of the whole machine into `buf`, which must be exactly
+/// [`gb_save_state_size`] bytes long. The CPU finishes the instruction it is in first.
+///
+/// Returns [`GbStatus::BufferSize`] if `len` is wrong.
+///
+/// # Safety
+/// `handle` must be null or a live handle from [`gb_create`] that isn't in use by another thread,
+/// and `buf` must be valid for writes of `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn gb_save_state_copy(
+    handle: *mut GbHandle,
+    buf: *mut u8,
+    len: usize,
+) -> GbStatus {
+    status(call(handle, |handle| {
+        let state = handle.gameboy.save_state();
+        if len != state.len() {
+            return Err(GbStatus::BufferSize);
+        }
+        if buf.is_null() {
+            return Err(GbStatus::NullPointer);
+        }
+        slice::from_raw_parts_mut(buf, len).copy_from_slice(&state);
+        Ok(())
+    }))
+}
+
+/// Restore a save state from [`gb_save_state_copy`], by this or an earlier version of the
+/// library
+///
+/// Returns [`GbStatus::InvalidSaveData`] without changing anything if the state can't be read or
+/// was taken with a different type of cartridge.
+///
+/// # Safety
+/// `handle` must be null or a live handle from [`gb_create`] that isn't in use by another thread,
+/// and `buf` must be valid for reads of `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn gb_load_state(
+    handle: *mut GbHandle,
+    buf: *const u8,
+    len: usize,
+) -> GbStatus {
+    status(call(handle, |handle| {
+        let state = bytes(buf, len)?;
+        handle
+            .gameboy
+            .load_state(state)
+            .map_err(|_| GbStatus::InvalidSaveData)
+    }))
+}
+
+/// Call `callback` with every byte shifted out of the serial port, replacing any callback set
+/// before. Pass a null callback to remove it.
+///
+/// The callback runs during [`gb_run_frame`], on the same thread, and must not call back into
+/// the same handle.
+///
+/// # Safety
+/// `handle` must be null or a live handle from [`gb_create`] that isn't in use by another thread,
+/// and `callback` must be safe to call with `user_data` for as long as it is set.
+#[no_mangle]
+pub unsafe extern "C" fn gb_set_serial_callback(
+    handle: *mut GbHandle,
+    callback: Option<GbSerialCallback>,
+    user_data: *mut c_void,
+) -> GbStatus {
+    status(call(handle, |handle| {
+        if let Some(id) = handle.serial_subscription.take() {
+            handle.gameboy.unsubscribe(id);
+        }
+        if let Some(callback) = callback {
+            let user_data = UserData(user_data);
+            let id = handle
+                .gameboy
+                .subscribe_callback(EventMask::SERIAL_BYTE, move |record| {
+                    if let Event::SerialByte(byte) = record.event {
+                        // Safety: promised by the caller of gb_set_serial_callback
+                        unsafe { callback(user_data.get(), byte) }
+                    }
+                });
+            handle.serial_subscription = Some(id);
+        }
+        Ok(())
+    }))
+}
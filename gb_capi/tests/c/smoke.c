@@ -0,0 +1,108 @@
+/*
+ * Exercises the C API from C. Takes the path of a ROM that sends $42 over the serial port and has
+ * cartridge RAM. Everything allocated is freed again, so that it runs clean under
+ * `valgrind --leak-check=full`.
+ */
+
+#include <stdio.h>
+#include <stdlib.h>
+
+#include "gb.h"
+
+#define CHECK(cond)                                                                   \
+    do {                                                                              \
+        if (!(cond)) {                                                                \
+            fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond); \
+            return 1;                                                                 \
+        }                                                                             \
+    } while (0)
+
+struct serial {
+    int count;
+    uint8_t last;
+};
+
+static void on_serial(void *user_data, uint8_t byte) {
+    struct serial *serial = user_data;
+    serial->count++;
+    serial->last = byte;
+}
+
+static uint8_t *read_file(const char *path, size_t *len) {
+    FILE *file = fopen(path, "rb");
+    if (file == NULL) {
+        return NULL;
+    }
+    fseek(file, 0, SEEK_END);
+    *len = (size_t)ftell(file);
+    fseek(file, 0, SEEK_SET);
+    uint8_t *data = malloc(*len);
+    if (data != NULL && fread(data, 1, *len, file) != *len) {
+        free(data);
+        data = NULL;
+    }
+    fclose(file);
+    return data;
+}
+
+int main(int argc, char **argv) {
+    CHECK(argc == 2);
+    size_t rom_len = 0;
+    uint8_t *rom = read_file(argv[1], &rom_len);
+    CHECK(rom != NULL);
+
+    GbStatus status = GB_STATUS_OK;
+    const uint8_t too_short[16] = {0};
+    CHECK(gb_create(too_short, sizeof too_short, &status) == NULL);
+    CHECK(status == GB_STATUS_INVALID_ROM);
+    CHECK(gb_create(NULL, rom_len, NULL) == NULL);
+
+    GbHandle *gb = gb_create(rom, rom_len, &status);
+    /* The ROM is copied */
+    free(rom);
+    CHECK(gb != NULL);
+    CHECK(status == GB_STATUS_OK);
+
+    struct serial serial = {0, 0};
+    CHECK(gb_set_serial_callback(gb, on_serial, &serial) == GB_STATUS_OK);
+    CHECK(gb_set_buttons(gb, 0x09) == GB_STATUS_OK);
+
+    const uint8_t *frame = NULL;
+    for (int i = 0; i < 10; i++) {
+        frame = gb_run_frame(gb);
+        CHECK(frame != NULL);
+    }
+    for (size_t i = 3; i < GB_FRAME_BYTES; i += 4) {
+        CHECK(frame[i] == 0xFF);
+    }
+    CHECK(serial.count == 1);
+    CHECK(serial.last == 0x42);
+
+    size_t ram_len = gb_save_ram_size(gb);
+    CHECK(ram_len > 0);
+    uint8_t *ram = malloc(ram_len);
+    CHECK(ram != NULL);
+    CHECK(gb_save_ram_copy(gb, ram, ram_len - 1) == GB_STATUS_BUFFER_SIZE);
+    CHECK(gb_save_ram_copy(gb, ram, ram_len) == GB_STATUS_OK);
+    CHECK(gb_load_save_ram(gb, ram, ram_len) == GB_STATUS_OK);
+    free(ram);
+
+    size_t state_len = gb_save_state_size(gb);
+    CHECK(state_len > 0);
+    uint8_t *state = malloc(state_len);
+    CHECK(state != NULL);
+    CHECK(gb_save_state_copy(gb, state, state_len - 1) == GB_STATUS_BUFFER_SIZE);
+    CHECK(gb_save_state_copy(gb, state, state_len) == GB_STATUS_OK);
+    CHECK(gb_run_frame(gb) != NULL);
+    CHECK(gb_load_state(gb, state, state_len) == GB_STATUS_OK);
+    CHECK(gb_load_state(gb, state, 8) == GB_STATUS_INVALID_SAVE_DATA);
+    free(state);
+
+    CHECK(gb_set_serial_callback(gb, NULL, NULL) == GB_STATUS_OK);
+    gb_destroy(gb);
+
+    gb_destroy(NULL);
+    CHECK(gb_run_frame(NULL) == NULL);
+    CHECK(gb_set_buttons(NULL, 0) == GB_STATUS_NULL_POINTER);
+    return 0;
+}
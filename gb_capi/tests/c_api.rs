@@ -0,0 +1,143 @@
+use std::{fs, path::PathBuf, process::Command, ptr};
+
+use gb_capi::*;
+use gb_core::gameboy::cart::header;
+
+/// Sends $42 over the serial port, then loops forever
+#[rustfmt::skip]
+const SERIAL_PROGRAM: [u8; 10] = [
+    0x3E, 0x42, // LD A, $42
+    0xE0, 0x01, // LDH (SB), A
+    0x3E, 0x81, // LD A, $81
+    0xE0, 0x02, // LDH (SC), A
+    0x18, 0xFE, // JR -2
+];
+
+fn rom(program: &[u8]) -> Vec<u8> {
+    let mut rom = header::flat_rom(program, 0x0150, "CAPI").unwrap();
+    rom[0x147] = 0x03; // MBC1+RAM+BATTERY
+    header::update_checksums(&mut rom);
+    rom
+}
+
+/// Compile tests/c/smoke.c against the shared library, and run it
+#[test]
+#[cfg(unix)]
+fn c_program() {
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let out_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("c_api");
+    fs::create_dir_all(&out_dir).unwrap();
+    let rom_path = out_dir.join("serial.gb");
+    fs::write(&rom_path, rom(&SERIAL_PROGRAM)).unwrap();
+
+    // The test binary is in target/<profile>/deps, next to the libraries in target/<profile>. They
+    // have to be built separately, since `cargo test` only builds the rlib.
+    let exe = std::env::current_exe().unwrap();
+    let lib_dir = exe.parent().unwrap().parent().unwrap();
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let mut build = Command::new(cargo);
+    build
+        .arg("build")
+        .arg("--manifest-path")
+        .arg(manifest_dir.join("Cargo.toml"));
+    if lib_dir.ends_with("release") {
+        build.arg("--release");
+    }
+    assert!(build.status().unwrap().success());
+
+    let program = out_dir.join("smoke");
+    let compiled = cc::Build::new()
+        .target(env!("GB_CAPI_TARGET"))
+        .host(env!("GB_CAPI_TARGET"))
+        .opt_level(0)
+        .cargo_metadata(false)
+        .get_compiler()
+        .to_command()
+        .arg(manifest_dir.join("tests/c/smoke.c"))
+        .arg("-I")
+        .arg(manifest_dir.join("include"))
+        .arg("-o")
+        .arg(&program)
+        .arg("-L")
+        .arg(lib_dir)
+        .arg("-lgb_capi")
+        .arg(format!("-Wl,-rpath,{}", lib_dir.display()))
+        .status()
+        .unwrap();
+    assert!(compiled.success());
+
+    let output = Command::new(&program).arg(&rom_path).output().unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn errors() {
+    let mut status = GbStatus::Ok;
+    let rom = rom(&SERIAL_PROGRAM);
+    unsafe {
+        assert!(gb_create(ptr::null(), 0, &mut status).is_null());
+        assert_eq!(status, GbStatus::InvalidRom);
+
+        let mut unsupported = rom.clone();
+        unsupported[0x147] = 0xFD;
+        assert!(gb_create(unsupported.as_ptr(), unsupported.len(), &mut status).is_null());
+        assert_eq!(status, GbStatus::UnsupportedMapper);
+
+        let handle = gb_create(rom.as_ptr(), rom.len(), &mut status);
+        assert_eq!(status, GbStatus::Ok);
+        let mut buf = vec![0; gb_save_ram_size(handle)];
+        assert_eq!(
+            gb_load_save_ram(handle, buf.as_ptr(), buf.len() + 1),
+            GbStatus::InvalidSaveData
+        );
+        assert_eq!(
+            gb_save_ram_copy(handle, ptr::null_mut(), buf.len()),
+            GbStatus::NullPointer
+        );
+        assert_eq!(
+            gb_save_ram_copy(handle, buf.as_mut_ptr(), buf.len()),
+            GbStatus::Ok
+        );
+        gb_destroy(handle);
+    }
+}
+
+#[test]
+fn lock_up_keeps_running() {
+    // $D3 is an illegal opcode, which locks up the CPU like on hardware instead of panicking
+    let rom = rom(&[0xD3]);
+    let mut status = GbStatus::Ok;
+    unsafe {
+        let handle = gb_create(rom.as_ptr(), rom.len(), &mut status);
+        assert!(!handle.is_null());
+        assert!(!gb_run_frame(handle).is_null());
+        assert!(!gb_run_frame(handle).is_null());
+        assert_eq!(gb_set_buttons(handle, 0), GbStatus::Ok);
+        gb_destroy(handle);
+    }
+}
+
+/// The header is regenerated by hand, so check it hasn't fallen behind
+#[test]
+fn header_declares_every_function() {
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let source = fs::read_to_string(manifest_dir.join("src/lib.rs")).unwrap();
+    let header = fs::read_to_string(manifest_dir.join("include/gb.h")).unwrap();
+    let functions: Vec<&str> = source
+        .split("pub unsafe extern \"C\" fn ")
+        .skip(1)
+        .map(|rest| &rest[..rest.find('(').unwrap()])
+        .collect();
+    assert!(functions.contains(&"gb_create"));
+    for function in functions {
+        assert!(
+            header.contains(&format!("{}(", function)),
+            "{} is missing from gb.h",
+            function
+        );
+    }
+}
@@ -0,0 +1,29 @@
+//! The cost of always recording the PC history. `run_frames` is the hot path it is recorded on,
+//! and `crash_dump` is what collecting it costs when something goes wrong.
+#![feature(test)]
+
+extern crate test;
+
+use gb_core::gameboy::{test_pattern, Gameboy, ResetKind};
+use test::Bencher;
+
+const FRAMES: u32 = 10;
+
+fn gameboy() -> Gameboy {
+    let mut gameboy = Gameboy::new(test_pattern::rom()).unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    gameboy
+}
+
+#[bench]
+fn run_frames(b: &mut Bencher) {
+    let mut gameboy = gameboy();
+    b.iter(|| gameboy.run_frames(FRAMES).iter().count());
+}
+
+#[bench]
+fn crash_dump(b: &mut Bencher) {
+    let mut gameboy = gameboy();
+    gameboy.run_frames(1);
+    b.iter(|| gameboy.crash_dump());
+}
@@ -0,0 +1,37 @@
+#![feature(test)]
+
+extern crate test;
+
+use gb_core::gameboy::{events::EventMask, Gameboy, ResetKind};
+use test::Bencher;
+
+const FRAMES: u32 = 10;
+
+fn gameboy() -> Gameboy {
+    let mut gameboy = Gameboy::new(vec![0; 0x8000]).unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    gameboy
+}
+
+#[bench]
+fn no_subscribers(b: &mut Bencher) {
+    let mut gameboy = gameboy();
+    b.iter(|| gameboy.run_frames(FRAMES).iter().count());
+}
+
+#[bench]
+fn subscribed_to_nothing_emitted(b: &mut Bencher) {
+    let mut gameboy = gameboy();
+    gameboy.subscribe_callback(EventMask::ROM_BANK_SWITCH, |_| ());
+    b.iter(|| gameboy.run_frames(FRAMES).iter().count());
+}
+
+#[bench]
+fn subscribed_to_everything(b: &mut Bencher) {
+    let mut gameboy = gameboy();
+    let receiver = gameboy.subscribe(EventMask::all());
+    b.iter(|| {
+        gameboy.run_frames(FRAMES);
+        receiver.drain().count()
+    });
+}
@@ -0,0 +1,66 @@
+#![feature(test)]
+
+extern crate test;
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    collections::VecDeque,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use gb_core::gameboy::ppu::{consts::FRAME_T_CYCLES, Ppu};
+use test::Bencher;
+
+/// Counts every allocation made by the benchmark
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+/// Draw a frame and take it, keeping the last `held` frames alive, and print the number of
+/// allocations made per frame
+fn frames(b: &mut Bencher, held: usize) {
+    let mut ppu = Ppu::new();
+    let mut frames = VecDeque::new();
+    let mut count = 0;
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    b.iter(|| {
+        for _ in 0..FRAME_T_CYCLES {
+            ppu.clock_t_state();
+        }
+        frames.push_back(ppu.get_frame());
+        if frames.len() > held {
+            frames.pop_front();
+        }
+        count += 1;
+    });
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    eprintln!(
+        "holding {} frame(s): {:.2} allocations per frame",
+        held,
+        allocations as f64 / count as f64
+    );
+}
+
+#[bench]
+fn frames_released(b: &mut Bencher) {
+    frames(b, 0);
+}
+
+#[bench]
+fn frames_held(b: &mut Bencher) {
+    frames(b, 8);
+}
@@ -0,0 +1,30 @@
+#![feature(test)]
+
+extern crate test;
+
+use gb_core::gameboy::{Gameboy, ResetKind};
+use test::Bencher;
+
+const FRAMES: u32 = 10;
+
+fn gameboy() -> Gameboy {
+    let mut gameboy = Gameboy::new(vec![0; 0x8000]).unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    gameboy
+}
+
+#[bench]
+fn frames_drawn(b: &mut Bencher) {
+    let mut gameboy = gameboy();
+    b.iter(|| {
+        for _ in 0..FRAMES {
+            gameboy.run_frames(1);
+        }
+    });
+}
+
+#[bench]
+fn frames_skipped(b: &mut Bencher) {
+    let mut gameboy = gameboy();
+    b.iter(|| gameboy.run_frames(FRAMES).iter().count());
+}
@@ -0,0 +1,58 @@
+//! The cost of logging while no records are wanted. `no_logger` and `logger_at_info` should be
+//! the same unless the `trace-heavy` feature is enabled, and `logger_at_trace` shows the cost of
+//! the records that are always compiled in.
+#![feature(test)]
+
+extern crate test;
+
+use std::sync::Once;
+
+use gb_core::gameboy::{test_pattern, Gameboy, ResetKind};
+use log::{LevelFilter, Log, Metadata, Record};
+use test::Bencher;
+
+const FRAMES: u32 = 10;
+
+/// Accepts everything and throws it away
+struct Discard;
+
+impl Log for Discard {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        test::black_box(record);
+    }
+
+    fn flush(&self) {}
+}
+
+fn gameboy(level: LevelFilter) -> Gameboy {
+    static INSTALL: Once = Once::new();
+    if level != LevelFilter::Off {
+        INSTALL.call_once(|| log::set_logger(&Discard).unwrap());
+    }
+    log::set_max_level(level);
+    let mut gameboy = Gameboy::new(test_pattern::rom()).unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    gameboy
+}
+
+#[bench]
+fn no_logger(b: &mut Bencher) {
+    let mut gameboy = gameboy(LevelFilter::Off);
+    b.iter(|| gameboy.run_frames(FRAMES).iter().count());
+}
+
+#[bench]
+fn logger_at_info(b: &mut Bencher) {
+    let mut gameboy = gameboy(LevelFilter::Info);
+    b.iter(|| gameboy.run_frames(FRAMES).iter().count());
+}
+
+#[bench]
+fn logger_at_trace(b: &mut Bencher) {
+    let mut gameboy = gameboy(LevelFilter::Trace);
+    b.iter(|| gameboy.run_frames(FRAMES).iter().count());
+}
@@ -0,0 +1,18 @@
+#![feature(test)]
+
+//! Compare `cargo bench --bench perf_stats` with and without `--features perf-stats` to see the
+//! cost of the instrumentation
+
+extern crate test;
+
+use gb_core::gameboy::{Gameboy, ResetKind};
+use test::Bencher;
+
+const FRAMES: u32 = 10;
+
+#[bench]
+fn run_frames(b: &mut Bencher) {
+    let mut gameboy = Gameboy::new(vec![0; 0x8000]).unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    b.iter(|| gameboy.run_frames(FRAMES).iter().count());
+}
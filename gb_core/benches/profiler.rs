@@ -0,0 +1,27 @@
+#![feature(test)]
+
+extern crate test;
+
+use gb_core::gameboy::{Gameboy, ResetKind};
+use test::Bencher;
+
+const FRAMES: u32 = 10;
+
+fn gameboy(profiling: bool) -> Gameboy {
+    let mut gameboy = Gameboy::new(vec![0; 0x8000]).unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    gameboy.enable_profiling(profiling);
+    gameboy
+}
+
+#[bench]
+fn profiling_disabled(b: &mut Bencher) {
+    let mut gameboy = gameboy(false);
+    b.iter(|| gameboy.run_frames(FRAMES).iter().count());
+}
+
+#[bench]
+fn profiling_enabled(b: &mut Bencher) {
+    let mut gameboy = gameboy(true);
+    b.iter(|| gameboy.run_frames(FRAMES).iter().count());
+}
@@ -0,0 +1,59 @@
+#![feature(test)]
+
+extern crate test;
+
+use gb_core::gameboy::{
+    ppu::{consts::FRAME_T_CYCLES, registers::LCDC, Ppu},
+    PpuBackend,
+};
+use test::Bencher;
+
+/// A PPU with noise in VRAM and OAM, so that every line has tiles, the window and 10 sprites
+fn ppu() -> Ppu {
+    let mut seed = 1u32;
+    let mut next = || {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        (seed >> 16) as u8
+    };
+    let mut ppu = Ppu::new();
+    ppu.tile_data.iter_mut().for_each(|b| *b = next());
+    ppu.bg_map_1.iter_mut().for_each(|b| *b = next());
+    for sprite in ppu.oam.chunks_exact_mut(4) {
+        sprite[0] = next() % 160;
+        sprite[1] = next() % 168;
+        sprite[2] = next();
+        sprite[3] = next() & 0xF0;
+    }
+    ppu.lcdc = LCDC::LCD_ENABLE
+        | LCDC::BG_ENABLE
+        | LCDC::BG_TILE_DATA_AREA
+        | LCDC::WINDOW_ENABLE
+        | LCDC::OBJ_ENABLE;
+    ppu.wx = 87;
+    ppu.wy = 40;
+    ppu.bgp = 0b11_10_01_00;
+    ppu.obp0 = 0b11_01_10_00;
+    ppu.obp1 = 0b00_01_10_11;
+    ppu
+}
+
+#[bench]
+fn full_frame(b: &mut Bencher) {
+    let mut ppu = ppu();
+    b.iter(|| {
+        for _ in 0..FRAME_T_CYCLES {
+            ppu.clock_t_state();
+        }
+    });
+}
+
+#[bench]
+fn full_frame_scanline(b: &mut Bencher) {
+    let mut ppu = ppu();
+    ppu.backend = PpuBackend::Scanline;
+    b.iter(|| {
+        for _ in 0..FRAME_T_CYCLES {
+            ppu.clock_t_state();
+        }
+    });
+}
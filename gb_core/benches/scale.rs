@@ -0,0 +1,29 @@
+#![feature(test)]
+
+extern crate test;
+
+use gb_core::gameboy::ppu::frame::{scale, Frame};
+use test::Bencher;
+
+/// A frame with every shade in it
+fn frame() -> Frame {
+    let mut frame = Frame::new();
+    for (i, pixel) in frame.iter_mut().enumerate() {
+        *pixel = (i % 7 % 4) as u8;
+    }
+    frame
+}
+
+#[bench]
+fn nearest_4x(b: &mut Bencher) {
+    let frame = frame();
+    let mut out = vec![0; scale::buffer_len(4)];
+    b.iter(|| scale::scale_nearest(&frame, 4, &mut out));
+}
+
+#[bench]
+fn lcd_grid_4x(b: &mut Bencher) {
+    let frame = frame();
+    let mut out = vec![0; scale::buffer_len(4)];
+    b.iter(|| scale::scale_lcd_grid(&frame, 4, &mut out));
+}
@@ -0,0 +1,27 @@
+#![feature(test)]
+
+extern crate test;
+
+use gb_core::gameboy::{test_pattern, Gameboy, ResetKind};
+use test::Bencher;
+
+const FRAMES: u32 = 10;
+
+fn gameboy(scheduling: bool) -> Gameboy {
+    let mut gameboy = Gameboy::new(test_pattern::rom()).unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    gameboy.set_chip_scheduling(scheduling);
+    gameboy
+}
+
+#[bench]
+fn every_chip_every_cycle(b: &mut Bencher) {
+    let mut gameboy = gameboy(false);
+    b.iter(|| gameboy.run_frames(FRAMES).iter().count());
+}
+
+#[bench]
+fn scheduled(b: &mut Bencher) {
+    let mut gameboy = gameboy(true);
+    b.iter(|| gameboy.run_frames(FRAMES).iter().count());
+}
@@ -0,0 +1,131 @@
+//! Whole-system workloads for comparing the emulator's speed between branches, each run for
+//! [`FRAMES`] frames' worth of T-cycles per iteration.
+//!
+//! Each iteration sets `bytes` to the number of microseconds it emulates, so the MB/s that
+//! `cargo bench` prints is emulated seconds per host second. `examples/bench_headless.rs` prints
+//! the same number for any ROM.
+
+#![feature(test)]
+
+extern crate test;
+
+use gb_core::gameboy::{
+    cart::header::{flat_rom, update_checksums},
+    ppu::consts::FRAME_T_CYCLES,
+    Gameboy, GameboyBuilder, PpuBackend, ResetKind, T_CYCLES_PER_SECOND,
+};
+use test::Bencher;
+
+const FRAMES: u64 = 10;
+
+/// 40 sprites, the window, and a STAT interrupt on every line. See `ppu_scene.S`.
+const PPU_SCENE: &[u8] = include_bytes!("../tests/fixtures/ppu_scene.gb");
+
+fn run(b: &mut Bencher, mut gameboy: Gameboy) {
+    let t_cycles = FRAMES * FRAME_T_CYCLES as u64;
+    b.bytes = t_cycles * 1_000_000 / T_CYCLES_PER_SECOND;
+    b.iter(|| gameboy.run_cycles(t_cycles));
+}
+
+fn program(code: &[u8]) -> Gameboy {
+    Gameboy::with_program(code, 0x0150).unwrap()
+}
+
+/// Instructions and nothing else, with the LCD off
+#[bench]
+fn cpu_bound(b: &mut Bencher) {
+    #[rustfmt::skip]
+    let gameboy = program(&[
+        0xF0, 0x44,       // LDH A, (LY)
+        0xFE, 0x90,       // CP 144
+        0x38, 0xFA,       // JR C, -6
+        0xAF,             // XOR A
+        0xE0, 0x40,       // LDH (LCDC), A
+        0x21, 0x00, 0xC0, // LD HL, $C000
+        // loop:
+        0x34,             // INC (HL)
+        0x7E,             // LD A, (HL)
+        0x80,             // ADD A, B
+        0x47,             // LD B, A
+        0x0C,             // INC C
+        0x18, 0xF9,       // JR loop
+    ]);
+    run(b, gameboy);
+}
+
+#[bench]
+fn ppu_bound(b: &mut Bencher) {
+    let mut gameboy = Gameboy::new(PPU_SCENE.to_vec()).unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    run(b, gameboy);
+}
+
+/// The same scene as [`ppu_bound`], drawn a line at a time
+#[bench]
+fn ppu_bound_scanline(b: &mut Bencher) {
+    let mut gameboy = GameboyBuilder::new()
+        .rom(PPU_SCENE)
+        .ppu_backend(PpuBackend::Scanline)
+        .build()
+        .unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    run(b, gameboy);
+}
+
+/// An OAM DMA from $C000 at the start of every VBlank, from a routine copied to high RAM
+#[bench]
+fn dma_every_frame(b: &mut Bencher) {
+    #[rustfmt::skip]
+    let gameboy = program(&[
+        0x21, 0x6F, 0x01, // LD HL, routine
+        0x11, 0x80, 0xFF, // LD DE, $FF80
+        0x06, 0x0A,       // LD B, 10
+        // copy:
+        0x2A,             // LD A, (HL+)
+        0x12,             // LD (DE), A
+        0x13,             // INC DE
+        0x05,             // DEC B
+        0x20, 0xFA,       // JR NZ, copy
+        // loop:
+        0xF0, 0x44,       // LDH A, (LY)
+        0xFE, 0x90,       // CP 144
+        0x20, 0xFA,       // JR NZ, loop
+        0xCD, 0x80, 0xFF, // CALL $FF80
+        // wait:
+        0xF0, 0x44,       // LDH A, (LY)
+        0xFE, 0x90,       // CP 144
+        0x28, 0xFA,       // JR Z, wait
+        0x18, 0xEF,       // JR loop
+        // routine:
+        0x3E, 0xC0,       // LD A, $C0
+        0xE0, 0x46,       // LDH (DMA), A
+        0x3E, 0x28,       // LD A, 40
+        0x3D,             // DEC A
+        0x20, 0xFD,       // JR NZ, -3
+        0xC9,             // RET
+    ]);
+    run(b, gameboy);
+}
+
+/// Switches between two ROM banks of an MBC1 cartridge, reading each one
+#[bench]
+fn mapper_bound(b: &mut Bencher) {
+    #[rustfmt::skip]
+    let mut rom = flat_rom(&[
+        0x3E, 0x02,       // LD A, 2
+        0xEA, 0x00, 0x20, // LD ($2000), A
+        0xFA, 0x00, 0x40, // LD A, ($4000)
+        0x3E, 0x03,       // LD A, 3
+        0xEA, 0x00, 0x20, // LD ($2000), A
+        0xFA, 0x00, 0x40, // LD A, ($4000)
+        0x18, 0xEE,       // JR -18
+    ], 0x0150, "").unwrap();
+    rom.resize(0x10000, 0);
+    rom[0x147] = 0x01;
+    rom[0x148] = 0x01;
+    update_checksums(&mut rom);
+    let mut gameboy = Gameboy::new(rom).unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    gameboy.cpu.cpu.registers.pc = 0x0150;
+    run(b, gameboy);
+}
@@ -0,0 +1,51 @@
+//! Run a ROM headless as fast as possible, and print how many emulated seconds it ran per host
+//! second, the same number `benches/workloads.rs` reports. Meant for profiling with perf or
+//! flamegraph, e.g.
+//!
+//! ```text
+//! cargo build --release --example bench_headless
+//! perf record -g target/release/examples/bench_headless game.gb 3600
+//! ```
+
+use std::{process, time::Instant};
+
+use gb_core::{
+    gameboy::{ppu::consts::FRAME_T_CYCLES, T_CYCLES_PER_SECOND},
+    prelude::{Gameboy, ResetKind},
+};
+
+const USAGE: &str = "usage: bench_headless <rom> [frames]";
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let (path, frames) = match (args.next(), args.next().map(|n| n.parse::<u64>())) {
+        (Some(path), None) => (path, 600),
+        (Some(path), Some(Ok(frames))) => (path, frames),
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+    let rom = std::fs::read(&path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        process::exit(1);
+    });
+    let mut gameboy = Gameboy::new(rom).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        process::exit(1);
+    });
+    gameboy.reset(ResetKind::PowerCycle);
+
+    // Frames are counted in T-cycles, so that ROMs that turn the LCD off still finish
+    let start = Instant::now();
+    let t_cycles = gameboy.run_cycles(frames * FRAME_T_CYCLES as u64);
+    let host = start.elapsed().as_secs_f64();
+    let emulated = t_cycles as f64 / T_CYCLES_PER_SECOND as f64;
+    println!(
+        "{} frames: {:.2}s emulated in {:.2}s, {:.1} emulated seconds per host second",
+        frames,
+        emulated,
+        host,
+        emulated / host
+    );
+}
@@ -0,0 +1,388 @@
+//! Boot every `.gb` file in a directory headless, and report how far each one gets, e.g.
+//!
+//! ```text
+//! cargo run --release --example gb_compat -- roms/ --frames 600 --out compat
+//! ```
+//!
+//! Each ROM ends up in one of these categories:
+//!
+//! | Category             | Meaning                                                            |
+//! |----------------------|--------------------------------------------------------------------|
+//! | `header_error`       | The file couldn't be read, or its header couldn't be parsed        |
+//! | `unsupported_mapper` | The cartridge type isn't implemented                               |
+//! | `lock_up`            | The CPU hit an illegal opcode, or halted with no way to wake up    |
+//! | `panic`              | The emulator panicked                                              |
+//! | `blank`              | Every frame was identical, and a single shade                      |
+//! | `renders`            | Anything else. The final frame is hashed and saved as a thumbnail  |
+//!
+//! The output directory (`compat` by default) gets a `report.json` with one entry per ROM, and a
+//! PPM thumbnail of the final frame of each ROM that renders something. A summary table is printed
+//! to stdout.
+
+use std::{
+    fmt::Write as _,
+    fs, io,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+use gb_core::{
+    gameboy::cart::header::CartridgeHeader,
+    prelude::{Frame, Gameboy, GbError, ResetKind},
+};
+
+const USAGE: &str = "usage: gb_compat <rom directory> [--frames N] [--threads N] [--out DIR]";
+
+struct Options {
+    dir: PathBuf,
+    frames: u32,
+    threads: usize,
+    out: PathBuf,
+}
+
+impl Options {
+    fn from_args() -> Result<Self, String> {
+        let mut dir = None;
+        let mut options = Options {
+            dir: PathBuf::new(),
+            frames: 600,
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            out: PathBuf::from("compat"),
+        };
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| args.next().ok_or(format!("{} needs a value", name));
+            match arg.as_str() {
+                "--frames" => {
+                    options.frames = value("--frames")?
+                        .parse()
+                        .map_err(|_| "--frames must be a number")?
+                }
+                "--threads" => {
+                    options.threads = value("--threads")?
+                        .parse::<usize>()
+                        .map_err(|_| "--threads must be a number")?
+                        .max(1)
+                }
+                "--out" => options.out = PathBuf::from(value("--out")?),
+                _ if dir.is_none() && !arg.starts_with("--") => dir = Some(PathBuf::from(arg)),
+                _ => return Err(format!("unexpected argument {}", arg)),
+            }
+        }
+        options.dir = dir.ok_or("no ROM directory given")?;
+        Ok(options)
+    }
+}
+
+enum Outcome {
+    HeaderError(String),
+    UnsupportedMapper(u8),
+    LockUp(String),
+    Panic(String),
+    Blank,
+    /// The thumbnail is left out if it couldn't be written
+    Renders {
+        hash: u64,
+        thumbnail: Option<PathBuf>,
+    },
+}
+
+impl Outcome {
+    const CATEGORIES: [&'static str; 6] = [
+        "header_error",
+        "unsupported_mapper",
+        "lock_up",
+        "panic",
+        "blank",
+        "renders",
+    ];
+
+    fn category(&self) -> &'static str {
+        let i = match self {
+            Outcome::HeaderError(_) => 0,
+            Outcome::UnsupportedMapper(_) => 1,
+            Outcome::LockUp(_) => 2,
+            Outcome::Panic(_) => 3,
+            Outcome::Blank => 4,
+            Outcome::Renders { .. } => 5,
+        };
+        Self::CATEGORIES[i]
+    }
+
+    fn detail(&self) -> Option<String> {
+        match self {
+            Outcome::HeaderError(message) | Outcome::LockUp(message) | Outcome::Panic(message) => {
+                Some(message.clone())
+            }
+            Outcome::UnsupportedMapper(id) => Some(format!("cartridge type {:#04X}", id)),
+            Outcome::Blank | Outcome::Renders { .. } => None,
+        }
+    }
+}
+
+struct Report {
+    file: String,
+    header: Option<CartridgeHeader>,
+    frames_run: u32,
+    outcome: Outcome,
+}
+
+fn main() {
+    let options = Options::from_args().unwrap_or_else(|error| {
+        eprintln!("{}\n{}", error, USAGE);
+        process::exit(2);
+    });
+    if let Err(error) = run(&options) {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+}
+
+fn run(options: &Options) -> io::Result<()> {
+    let mut roms: Vec<PathBuf> = fs::read_dir(&options.dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<_>>()?;
+    roms.retain(|path| {
+        path.extension()
+            .map_or(false, |ext| ext.eq_ignore_ascii_case("gb"))
+    });
+    roms.sort();
+    fs::create_dir_all(options.out.join("thumbnails"))?;
+
+    // Panics are reported per ROM, so the default hook would only add noise
+    panic::set_hook(Box::new(|_| {}));
+
+    let next = AtomicUsize::new(0);
+    let reports = Mutex::new(Vec::with_capacity(roms.len()));
+    thread::scope(|scope| {
+        for _ in 0..options.threads.min(roms.len()) {
+            scope.spawn(|| {
+                while let Some(path) = roms.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    let report = test_rom(path, options);
+                    eprintln!("{}: {}", report.file, report.outcome.category());
+                    reports.lock().unwrap().push(report);
+                }
+            });
+        }
+    });
+    let mut reports = reports.into_inner().unwrap();
+    reports.sort_by(|a, b| a.file.cmp(&b.file));
+
+    fs::write(
+        options.out.join("report.json"),
+        json_report(&reports, options),
+    )?;
+    print_summary(&reports);
+    Ok(())
+}
+
+fn test_rom(path: &Path, options: &Options) -> Report {
+    let mut report = Report {
+        file: path.file_name().unwrap().to_string_lossy().into_owned(),
+        header: None,
+        frames_run: 0,
+        outcome: Outcome::Blank,
+    };
+    let rom = match fs::read(path) {
+        Ok(rom) => rom,
+        Err(error) => {
+            report.outcome = Outcome::HeaderError(error.to_string());
+            return report;
+        }
+    };
+    match CartridgeHeader::parse(&rom) {
+        Ok(header) => report.header = Some(header),
+        Err(error) => {
+            report.outcome = Outcome::HeaderError(error.to_string());
+            return report;
+        }
+    }
+    let mut gameboy = match Gameboy::new(rom) {
+        Ok(gameboy) => gameboy,
+        Err(GbError::UnsupportedMapper(id)) => {
+            report.outcome = Outcome::UnsupportedMapper(id);
+            return report;
+        }
+        Err(error) => {
+            report.outcome = Outcome::HeaderError(error.to_string());
+            return report;
+        }
+    };
+    gameboy.reset(ResetKind::PowerCycle);
+
+    let frames_run = &mut report.frames_run;
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        boot(&mut gameboy, options.frames, frames_run)
+    }));
+    report.outcome = match result {
+        Ok(Err(lock_up)) => Outcome::LockUp(lock_up),
+        Ok(Ok(changed)) => {
+            let frame = gameboy.get_frame();
+            if !changed && frame.iter().all(|&shade| shade == frame[0]) {
+                Outcome::Blank
+            } else {
+                let thumbnail =
+                    Path::new("thumbnails").join(path.with_extension("ppm").file_name().unwrap());
+                let written = fs::write(options.out.join(&thumbnail), ppm(&frame));
+                if let Err(error) = &written {
+                    eprintln!("{}: writing thumbnail: {}", report.file, error);
+                }
+                Outcome::Renders {
+                    hash: frame_hash(&frame),
+                    thumbnail: written.ok().map(|()| thumbnail),
+                }
+            }
+        }
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<String>()
+                .cloned()
+                .or_else(|| payload.downcast_ref::<&str>().map(|s| s.to_string()))
+                .unwrap_or_else(|| "unknown panic".to_string());
+            Outcome::Panic(message)
+        }
+    };
+    report
+}
+
+/// Run for `frames` frames, and return whether the picture ever changed. Stops early with an error
+/// if the CPU can never run again.
+fn boot(gameboy: &mut Gameboy, frames: u32, frames_run: &mut u32) -> Result<bool, String> {
+    let first = frame_hash(gameboy.run_frames(1));
+    *frames_run = 1;
+    let mut changed = false;
+    while *frames_run < frames {
+        changed |= frame_hash(gameboy.run_frames(1)) != first;
+        *frames_run += 1;
+
+        if let Some(crash) = gameboy.last_crash() {
+            let pc = crash.history.last().map_or(0, |record| record.pc);
+            return Err(format!("illegal opcode at {:#06X}", pc));
+        }
+        let cpu = &gameboy.cpu.cpu;
+        if cpu.halted && gameboy.peek(0xFFFF) & 0x1F == 0 {
+            return Err(format!(
+                "HALT at {:#06X} with no interrupts enabled",
+                cpu.registers.pc
+            ));
+        }
+    }
+    Ok(changed)
+}
+
+/// 64-bit FNV-1a of the shades of every pixel
+fn frame_hash(frame: &Frame) -> u64 {
+    frame.iter().fold(0xCBF2_9CE4_8422_2325, |hash, &shade| {
+        (hash ^ shade as u64).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
+
+/// `frame` as a binary PPM image
+fn ppm(frame: &Frame) -> Vec<u8> {
+    let mut image = b"P6\n160 144\n255\n".to_vec();
+    for color in frame.colors() {
+        image.extend_from_slice(&color.to_le_bytes()[..3]);
+    }
+    image
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn json_report(reports: &[Report], options: &Options) -> String {
+    let null = || "null".to_string();
+    let entries: Vec<String> = reports
+        .iter()
+        .map(|report| {
+            let header = report.header.as_ref();
+            let (hash, thumbnail) = match &report.outcome {
+                Outcome::Renders { hash, thumbnail } => (
+                    json_string(&format!("{:016x}", hash)),
+                    thumbnail
+                        .as_ref()
+                        .map_or_else(null, |path| json_string(&path.to_string_lossy())),
+                ),
+                _ => (null(), null()),
+            };
+            format!(
+                concat!(
+                    "    {{\"file\": {}, \"title\": {}, \"cart_type\": {}, \"outcome\": {}, ",
+                    "\"detail\": {}, \"frames_run\": {}, \"frame_hash\": {}, \"thumbnail\": {}}}"
+                ),
+                json_string(&report.file),
+                header.map_or_else(null, |header| json_string(&header.title)),
+                header.map_or_else(null, |header| header.cart_type.to_string()),
+                json_string(report.outcome.category()),
+                report
+                    .outcome
+                    .detail()
+                    .map_or_else(null, |detail| json_string(&detail)),
+                report.frames_run,
+                hash,
+                thumbnail,
+            )
+        })
+        .collect();
+    format!(
+        "{{\n  \"frames\": {},\n  \"roms\": [\n{}\n  ]\n}}\n",
+        options.frames,
+        entries.join(",\n")
+    )
+}
+
+fn print_summary(reports: &[Report]) {
+    let file_width = reports
+        .iter()
+        .map(|report| report.file.chars().count())
+        .chain([4])
+        .max()
+        .unwrap_or(4);
+    println!(
+        "{:<file_width$}  {:<15}  {:<4}  {:<18}  Detail",
+        "File",
+        "Title",
+        "Type",
+        "Outcome",
+        file_width = file_width
+    );
+    for report in reports {
+        let header = report.header.as_ref();
+        println!(
+            "{:<file_width$}  {:<15}  {:<4}  {:<18}  {}",
+            report.file,
+            header.map_or("", |header| &header.title),
+            header.map_or(String::new(), |header| format!("{:02X}", header.cart_type)),
+            report.outcome.category(),
+            report.outcome.detail().unwrap_or_default(),
+            file_width = file_width
+        );
+    }
+
+    println!();
+    for category in Outcome::CATEGORIES {
+        let count = reports
+            .iter()
+            .filter(|report| report.outcome.category() == category)
+            .count();
+        println!("{:<18}  {}", category, count);
+    }
+    println!("{:<18}  {}", "total", reports.len());
+}
@@ -0,0 +1,56 @@
+//! Run a ROM under GDB, e.g.
+//!
+//! ```text
+//! cargo run --example gdb_server --features gdb -- game.gb 1234
+//! ```
+//!
+//! and then, in GDB 11 or later, or `gdb-multiarch`:
+//!
+//! ```text
+//! (gdb) set architecture z80
+//! (gdb) target remote :1234
+//! (gdb) break *0x150
+//! (gdb) continue
+//! (gdb) info registers
+//! ```
+//!
+//! The game waits for the debugger before running at all, and nothing is shown on screen. When
+//! the debugger disconnects, the server waits for the next one.
+
+use std::{fs, net::TcpListener, process};
+
+use gb_core::gameboy::{gdb::GdbServer, Gameboy};
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let (rom, port) = match (args.next(), args.next().map(|port| port.parse::<u16>())) {
+        (Some(rom), None) => (rom, 1234),
+        (Some(rom), Some(Ok(port))) => (rom, port),
+        _ => {
+            eprintln!("usage: gdb_server <rom> [port]");
+            process::exit(2);
+        }
+    };
+
+    let rom = fs::read(&rom).unwrap_or_else(|e| {
+        eprintln!("{}: {}", rom, e);
+        process::exit(1);
+    });
+    let gameboy = Gameboy::new(rom).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+    let listener = TcpListener::bind(("127.0.0.1", port)).unwrap_or_else(|e| {
+        eprintln!("port {}: {}", port, e);
+        process::exit(1);
+    });
+    println!("waiting for GDB on port {}", port);
+
+    let mut server = GdbServer::new(gameboy, listener);
+    loop {
+        match server.serve() {
+            Ok(()) => println!("debugger disconnected"),
+            Err(e) => eprintln!("connection failed: {}", e),
+        }
+    }
+}
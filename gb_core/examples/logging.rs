@@ -0,0 +1,84 @@
+//! Print the core's log records to stderr while running the test pattern ROM, e.g.
+//!
+//! ```text
+//! GB_LOG=gb::ppu=debug,gb::dma=debug cargo run --example logging
+//! ```
+//!
+//! `GB_LOG` is a comma-separated list of `target=level` filters, where the target can be left out
+//! to set the level for every other target. Most frontends would use a crate like env_logger
+//! instead, as described in `gb_core::gameboy::logging`.
+
+use gb_core::{
+    gameboy::test_pattern,
+    prelude::{Gameboy, ResetKind},
+};
+use log::{LevelFilter, Log, Metadata, Record};
+
+/// Filters by the longest matching target prefix
+struct StderrLogger {
+    filters: Vec<(String, LevelFilter)>,
+    default: LevelFilter,
+}
+
+impl StderrLogger {
+    fn from_env() -> Self {
+        // Without `GB_LOG`, show everything but the trace records
+        let spec = std::env::var("GB_LOG").unwrap_or_else(|_| "debug".to_string());
+        let mut logger = StderrLogger {
+            filters: Vec::new(),
+            default: LevelFilter::Off,
+        };
+        for filter in spec.split(',').filter(|f| !f.is_empty()) {
+            match filter.split_once('=') {
+                Some((target, level)) => logger.filters.push((
+                    target.to_string(),
+                    level.parse().unwrap_or(LevelFilter::Off),
+                )),
+                None => logger.default = filter.parse().unwrap_or(LevelFilter::Off),
+            }
+        }
+        logger
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        self.filters
+            .iter()
+            .map(|&(_, level)| level)
+            .fold(self.default, Ord::max)
+    }
+}
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        let level = self
+            .filters
+            .iter()
+            .filter(|(target, _)| metadata.target().starts_with(target.as_str()))
+            .max_by_key(|(target, _)| target.len())
+            .map_or(self.default, |&(_, level)| level);
+        metadata.level() <= level
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!(
+                "{:5} {}: {}",
+                record.level(),
+                record.target(),
+                record.args()
+            );
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+fn main() {
+    let logger = StderrLogger::from_env();
+    log::set_max_level(logger.max_level());
+    log::set_logger(Box::leak(Box::new(logger))).unwrap();
+
+    let mut gameboy = Gameboy::new(test_pattern::rom()).unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    gameboy.run_frames(60);
+}
@@ -0,0 +1,462 @@
+//! A complete frontend without a window, showing how battery saves, savestates, input, frame
+//! pacing, the link port and lock-up handling fit together, e.g.
+//!
+//! ```text
+//! cargo run --release --example reference_frontend -- game.gb --screenshots shots --every 60
+//! ```
+//!
+//! Options:
+//!
+//! | Option              | Meaning                                                             |
+//! |---------------------|---------------------------------------------------------------------|
+//! | `--frames N`        | Stop after N frames. By default it runs until `quit`                |
+//! | `--input FILE`      | Run the commands in FILE, each line starting with the frame number  |
+//! | `--speed X`         | Run at X times real speed, or `inf` to run uncapped. Defaults to 1  |
+//! | `--save FILE`       | Where battery-backed RAM is kept. Defaults to the ROM's `.sav` file |
+//! | `--screenshots DIR` | Write a PPM screenshot to DIR every `--every` frames                |
+//! | `--every N`         | How often screenshots are taken. Defaults to 60                     |
+//! | `--ascii N`         | Print the screen as ASCII art every N frames                        |
+//!
+//! Commands are read from stdin, one per line, and take effect before the next frame:
+//!
+//! | Command          | Meaning                                                             |
+//! |------------------|---------------------------------------------------------------------|
+//! | `press BUTTON`   | Hold `A`, `B`, `Start`, `Select`, `Up`, `Down`, `Left` or `Right`   |
+//! | `release BUTTON` | Let go of a button                                                  |
+//! | `save SLOT`      | Save the state to the ROM's `.ssSLOT` file, for slots 0-9           |
+//! | `load SLOT`      | Load the state saved in a slot                                      |
+//! | `screenshot`     | Write a screenshot now, if `--screenshots` was given                |
+//! | `quit`           | Save and stop                                                       |
+//!
+//! An input file holds the same commands, each after the frame it is run before, so a run with
+//! one is deterministic:
+//!
+//! ```text
+//! # Comments start with '#'
+//! 120 press Start
+//! 125 release Start
+//! 600 save 1
+//! ```
+//!
+//! Bytes the game sends over the link cable are written to stdout, as test ROMs report their
+//! results that way. Everything else is written to stderr. If the CPU locks up, a crash dump is
+//! printed and the exit code is 3.
+
+use std::{
+    fs,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+    process,
+    str::FromStr,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+};
+
+use gb_core::{
+    frontend::Throttle,
+    gameboy::{crash_dump::CrashDump, serial::SerialConnection},
+    prelude::{Button, Frame, Gameboy, GameboyBuilder, ResetKind},
+};
+
+const USAGE: &str = "usage: reference_frontend <rom> [--frames N] [--input FILE] [--speed X] \
+                     [--save FILE] [--screenshots DIR] [--every N] [--ascii N]";
+
+/// Battery-backed RAM is written out at most this often, about every 5 seconds, as well as on
+/// exit
+const SAVE_INTERVAL_FRAMES: u32 = 300;
+
+/// Characters for each shade, from lightest to darkest
+const SHADES: [char; 4] = [' ', '.', '+', '#'];
+
+pub struct Options {
+    pub rom: PathBuf,
+    pub frames: Option<u64>,
+    pub input: Option<PathBuf>,
+    pub speed: f64,
+    pub save: Option<PathBuf>,
+    pub screenshots: Option<PathBuf>,
+    pub screenshot_every: u64,
+    pub ascii_every: Option<u64>,
+}
+
+impl Options {
+    pub fn new(rom: impl Into<PathBuf>) -> Self {
+        Options {
+            rom: rom.into(),
+            frames: None,
+            input: None,
+            speed: 1.0,
+            save: None,
+            screenshots: None,
+            screenshot_every: 60,
+            ascii_every: None,
+        }
+    }
+
+    fn from_args() -> Result<Self, String> {
+        let mut rom = None;
+        let mut options = Options::new("");
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| args.next().ok_or(format!("{} needs a value", name));
+            let number = |name: &str, value: String| {
+                value
+                    .parse::<u64>()
+                    .map_err(|_| format!("{} must be a number", name))
+            };
+            match arg.as_str() {
+                "--frames" => options.frames = Some(number("--frames", value("--frames")?)?),
+                "--input" => options.input = Some(PathBuf::from(value("--input")?)),
+                "--speed" => {
+                    options.speed = value("--speed")?
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|&speed| speed > 0.0)
+                        .ok_or("--speed must be a positive number")?
+                }
+                "--save" => options.save = Some(PathBuf::from(value("--save")?)),
+                "--screenshots" => {
+                    options.screenshots = Some(PathBuf::from(value("--screenshots")?))
+                }
+                "--every" => {
+                    options.screenshot_every = number("--every", value("--every")?)?.max(1)
+                }
+                "--ascii" => {
+                    options.ascii_every = Some(number("--ascii", value("--ascii")?)?.max(1))
+                }
+                _ if rom.is_none() && !arg.starts_with("--") => rom = Some(PathBuf::from(arg)),
+                _ => return Err(format!("unexpected argument {}", arg)),
+            }
+        }
+        options.rom = rom.ok_or("no ROM given")?;
+        Ok(options)
+    }
+
+    fn save_path(&self) -> PathBuf {
+        self.save
+            .clone()
+            .unwrap_or_else(|| self.rom.with_extension("sav"))
+    }
+
+    fn state_path(&self, slot: u8) -> PathBuf {
+        self.rom.with_extension(format!("ss{}", slot))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Press(Button),
+    Release(Button),
+    SaveState(u8),
+    LoadState(u8),
+    Screenshot,
+    Quit,
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let mut words = s.split_whitespace();
+        let command = words.next().ok_or("empty command")?;
+        let mut argument = || words.next().ok_or(format!("{} needs an argument", command));
+        let button = |name: &str| match name {
+            "A" => Ok(Button::A),
+            "B" => Ok(Button::B),
+            "Start" => Ok(Button::Start),
+            "Select" => Ok(Button::Select),
+            "Up" => Ok(Button::Up),
+            "Down" => Ok(Button::Down),
+            "Left" => Ok(Button::Left),
+            "Right" => Ok(Button::Right),
+            _ => Err(format!("unknown button {}", name)),
+        };
+        let slot = |slot: &str| match slot.parse::<u8>() {
+            Ok(slot) if slot < 10 => Ok(slot),
+            _ => Err(format!("slot must be 0-9, not {}", slot)),
+        };
+        let parsed = match command {
+            "press" => Command::Press(button(argument()?)?),
+            "release" => Command::Release(button(argument()?)?),
+            "save" => Command::SaveState(slot(argument()?)?),
+            "load" => Command::LoadState(slot(argument()?)?),
+            "screenshot" => Command::Screenshot,
+            "quit" => Command::Quit,
+            _ => return Err(format!("unknown command {}", command)),
+        };
+        match words.next() {
+            Some(extra) => Err(format!("unexpected {} after {}", extra, command)),
+            None => Ok(parsed),
+        }
+    }
+}
+
+/// Parse an input file into commands and the frames they are run before, in order
+pub fn parse_input(text: &str) -> Result<Vec<(u64, Command)>, String> {
+    let mut commands = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |message: String| format!("line {}: {}", number + 1, message);
+        let (frame, command) = line
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| error("expected a frame number and a command".to_string()))?;
+        let frame = frame
+            .parse()
+            .map_err(|_| error(format!("{} is not a frame number", frame)))?;
+        commands.push((frame, command.parse().map_err(error)?));
+    }
+    // The sort is stable, so commands for the same frame keep their order
+    commands.sort_by_key(|&(frame, _)| frame);
+    Ok(commands)
+}
+
+/// Passes every byte the game sends on to the main loop, and sends back 1s like an unplugged
+/// cable. It has to be `Send` to be plugged into the Gameboy, so it can't hold stdout itself.
+struct LinkToStdout(Sender<u8>);
+
+impl SerialConnection for LinkToStdout {
+    fn exchange(&mut self, byte: u8) -> u8 {
+        // The main loop only stops listening once it has finished with the Gameboy
+        let _ = self.0.send(byte);
+        0xFF
+    }
+}
+
+/// Why a run ended
+#[derive(Debug)]
+pub enum Exit {
+    /// `--frames` frames were run
+    Finished,
+    Quit,
+    LockedUp(Box<CrashDump>),
+}
+
+fn main() {
+    let options = Options::from_args().unwrap_or_else(|error| {
+        eprintln!("{}\n{}", error, USAGE);
+        process::exit(2);
+    });
+
+    // Reading stdin blocks, so it is done on its own thread, and the main loop picks up whatever
+    // has arrived before each frame
+    let (commands, interactive) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines().map_while(Result::ok) {
+            if commands.send(line).is_err() {
+                return;
+            }
+        }
+    });
+
+    match run(&options, &interactive, &mut io::stdout()) {
+        Ok(Exit::Finished | Exit::Quit) => (),
+        Ok(Exit::LockedUp(dump)) => {
+            eprintln!("{}", dump);
+            process::exit(3);
+        }
+        Err(error) => {
+            eprintln!("{}", error);
+            process::exit(1);
+        }
+    }
+}
+
+/// Run the ROM in `options`, taking commands from `interactive` as well as the input file, and
+/// writing what the game sends over the link cable and any ASCII art to `out`
+pub fn run(
+    options: &Options,
+    interactive: &Receiver<String>,
+    out: &mut dyn Write,
+) -> Result<Exit, String> {
+    let rom = fs::read(&options.rom).map_err(io_error(&options.rom))?;
+    let script = match &options.input {
+        Some(path) => parse_input(&fs::read_to_string(path).map_err(io_error(path))?)
+            .map_err(|error| format!("{}: {}", path.display(), error))?,
+        None => Vec::new(),
+    };
+    let mut script = script.into_iter().peekable();
+
+    let (link, serial) = mpsc::channel();
+    let mut gameboy = GameboyBuilder::new()
+        .rom(rom)
+        .serial(Box::new(LinkToStdout(link)))
+        .build()
+        .map_err(|error| format!("{}: {}", options.rom.display(), error))?;
+    gameboy.reset(ResetKind::PowerCycle);
+
+    // Battery saves are loaded before the first instruction runs, and written out whenever the
+    // game has changed them, at most every `SAVE_INTERVAL_FRAMES` frames
+    let save_path = options.save_path();
+    if gameboy.cart.has_battery() {
+        match fs::read(&save_path) {
+            Ok(save) => gameboy
+                .cart
+                .load_ram(&save)
+                .map_err(|error| format!("{}: {}", save_path.display(), error))?,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => (),
+            Err(error) => return Err(io_error(&save_path)(error)),
+        }
+        let path = save_path.clone();
+        gameboy.set_save_writer(SAVE_INTERVAL_FRAMES, move |save| write_save(&path, save));
+    }
+    if let Some(dir) = &options.screenshots {
+        fs::create_dir_all(dir).map_err(io_error(dir))?;
+    }
+
+    let mut throttle = Throttle::new(options.speed);
+    let mut frame = 0;
+    let exit = loop {
+        if options.frames.map_or(false, |frames| frame >= frames) {
+            break Exit::Finished;
+        }
+
+        // Scripted commands first, so that typing can't change when they happen
+        let mut commands = Vec::new();
+        while let Some((_, command)) = script.next_if(|&(at, _)| at <= frame) {
+            commands.push(command);
+        }
+        for line in interactive.try_iter() {
+            match line.parse() {
+                Ok(command) => commands.push(command),
+                Err(error) => eprintln!("{}", error),
+            }
+        }
+        if commands.contains(&Command::Quit) {
+            break Exit::Quit;
+        }
+        for command in commands {
+            run_command(&mut gameboy, command, options, frame);
+        }
+
+        gameboy.run_frames(1);
+        frame += 1;
+
+        let sent: Vec<u8> = serial.try_iter().collect();
+        if !sent.is_empty() {
+            out.write_all(&sent)
+                .and_then(|()| out.flush())
+                .map_err(|error| format!("writing serial output: {}", error))?;
+        }
+
+        if let Some(dump) = lock_up(&gameboy) {
+            break Exit::LockedUp(dump);
+        }
+        if frame % options.screenshot_every == 0 {
+            screenshot(&gameboy, options, frame);
+        }
+        if options
+            .ascii_every
+            .map_or(false, |every| frame % every == 0)
+        {
+            out.write_all(ascii_art(&gameboy.get_frame()).as_bytes())
+                .map_err(|error| format!("writing ASCII art: {}", error))?;
+        }
+
+        throttle.sync(gameboy.cycles());
+    };
+
+    // Whatever the game saved since the last write would be lost otherwise
+    if let Some(save) = gameboy.cart.take_save() {
+        write_save(&save_path, save);
+    }
+    Ok(exit)
+}
+
+fn run_command(gameboy: &mut Gameboy, command: Command, options: &Options, frame: u64) {
+    match command {
+        Command::Press(button) => gameboy.joypad.press(button),
+        Command::Release(button) => gameboy.joypad.release(button),
+        Command::SaveState(slot) => {
+            let path = options.state_path(slot);
+            match fs::write(&path, gameboy.save_state()) {
+                Ok(()) => eprintln!("saved state to {}", path.display()),
+                Err(error) => eprintln!("{}: {}", path.display(), error),
+            }
+        }
+        Command::LoadState(slot) => {
+            let path = options.state_path(slot);
+            let loaded = fs::read(&path)
+                .map_err(|error| error.to_string())
+                .and_then(|state| {
+                    gameboy
+                        .load_state(&state)
+                        .map_err(|error| error.to_string())
+                });
+            match loaded {
+                Ok(()) => eprintln!("loaded state from {}", path.display()),
+                Err(error) => eprintln!("{}: {}", path.display(), error),
+            }
+        }
+        Command::Screenshot => screenshot(gameboy, options, frame),
+        // Handled by the main loop
+        Command::Quit => (),
+    }
+}
+
+/// Turns an IO error into a message naming the file
+fn io_error(path: &Path) -> impl FnOnce(io::Error) -> String + '_ {
+    move |error| format!("{}: {}", path.display(), error)
+}
+
+fn write_save(path: &Path, save: &[u8]) {
+    if let Err(error) = fs::write(path, save) {
+        eprintln!("{}: {}", path.display(), error);
+    }
+}
+
+/// A crash dump if the CPU can never run again: it hit an illegal opcode, or halted with every
+/// interrupt disabled
+fn lock_up(gameboy: &Gameboy) -> Option<Box<CrashDump>> {
+    if let Some(dump) = gameboy.last_crash() {
+        return Some(Box::new(dump.clone()));
+    }
+    let halted = gameboy.cpu.cpu.halted && gameboy.peek(0xFFFF) & 0x1F == 0;
+    halted.then(|| Box::new(gameboy.crash_dump()))
+}
+
+/// Write the current frame to the screenshot directory, if there is one
+fn screenshot(gameboy: &Gameboy, options: &Options, frame: u64) {
+    let Some(dir) = &options.screenshots else {
+        return;
+    };
+    let path = dir.join(format!("frame_{:05}.ppm", frame));
+    let image = gameboy.get_frame();
+    match fs::write(&path, ppm(&image)) {
+        Ok(()) => eprintln!("{} {:016x}", path.display(), frame_hash(&image)),
+        Err(error) => eprintln!("{}: {}", path.display(), error),
+    }
+}
+
+/// 64-bit FNV-1a of the shades of every pixel
+fn frame_hash(frame: &Frame) -> u64 {
+    frame.iter().fold(0xCBF2_9CE4_8422_2325, |hash, &shade| {
+        (hash ^ shade as u64).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
+
+/// `frame` as a binary PPM image
+fn ppm(frame: &Frame) -> Vec<u8> {
+    let mut image = b"P6\n160 144\n255\n".to_vec();
+    for color in frame.colors() {
+        image.extend_from_slice(&color.to_le_bytes()[..3]);
+    }
+    image
+}
+
+/// `frame` with each character covering 2x4 pixels, so that the picture keeps its shape in a
+/// terminal
+fn ascii_art(frame: &Frame) -> String {
+    let mut art = String::new();
+    for row in frame.rows().step_by(4) {
+        art.extend(
+            row.iter()
+                .step_by(2)
+                .map(|&shade| SHADES[shade as usize & 3]),
+        );
+        art.push('\n');
+    }
+    art
+}
@@ -0,0 +1,42 @@
+//! Run a rhai script against a ROM, e.g.
+//!
+//! ```text
+//! cargo run --example run_script --features scripting -- \
+//!     tests/fixtures/start_counter.gb examples/scripts/press_start.rhai
+//! ```
+
+use gb_core::{
+    gameboy::script::{ScriptHost, ScriptOutcome},
+    prelude::{Gameboy, ResetKind},
+};
+
+/// Give up after a minute of emulated time
+const MAX_FRAMES: u64 = 60 * 60;
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let (rom, script) = match args.as_slice() {
+        [_, rom, script] => (rom, script),
+        _ => {
+            eprintln!("usage: run_script <rom> <script>");
+            std::process::exit(2);
+        }
+    };
+    let rom = std::fs::read(rom).expect("failed to read ROM");
+    let script = std::fs::read_to_string(script).expect("failed to read script");
+
+    let mut gameboy = Gameboy::new(rom).expect("failed to load ROM");
+    gameboy.reset(ResetKind::PowerCycle);
+    let mut host = ScriptHost::new(gameboy);
+    match host.run(&script, MAX_FRAMES) {
+        Ok(ScriptOutcome::Stopped(result)) => println!("stopped: {}", result),
+        Ok(ScriptOutcome::TimedOut) => {
+            println!("timed out after {} frames", MAX_FRAMES);
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,29 @@
+//! Run the built-in test pattern ROM and print the screen as ASCII art, e.g.
+//!
+//! ```text
+//! cargo run --example test_pattern
+//! ```
+
+use gb_core::{
+    gameboy::test_pattern,
+    prelude::{Gameboy, ResetKind},
+};
+
+/// Characters for each shade, from lightest to darkest
+const SHADES: [char; 4] = [' ', '.', '+', '#'];
+
+fn main() {
+    let mut gameboy = Gameboy::new(test_pattern::rom()).expect("failed to load ROM");
+    gameboy.reset(ResetKind::PowerCycle);
+    let frame = gameboy.run_frames(3);
+
+    // Each character covers 2x4 pixels, so that the picture keeps its shape in a terminal
+    for row in frame.rows().step_by(4) {
+        let line: String = row
+            .iter()
+            .step_by(2)
+            .map(|&shade| SHADES[shade as usize & 3])
+            .collect();
+        println!("{}", line);
+    }
+}
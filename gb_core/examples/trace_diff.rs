@@ -0,0 +1,81 @@
+//! Compare a CPU trace from this emulator with one from a reference emulator, both in the
+//! Gameboy Doctor format, and report where they first differ, e.g.
+//!
+//! ```text
+//! cargo run --release --example trace_diff -- ours.log reference.log --context 20 --lenient
+//! ```
+//!
+//! `--lenient` ignores PCMEM bytes read from IO registers, which often differ for harmless
+//! reasons. Exits with 0 if the traces match, 1 if they diverge, and 2 on errors.
+
+use std::{
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+    process,
+};
+
+use gb_core::gameboy::trace_diff::{diff, DiffOptions, Divergence, Tolerance};
+
+const USAGE: &str = "usage: trace_diff <our trace> <their trace> [--context N] [--lenient]";
+
+struct Options {
+    ours: PathBuf,
+    theirs: PathBuf,
+    diff: DiffOptions,
+}
+
+impl Options {
+    fn from_args() -> Result<Self, String> {
+        let mut paths = Vec::new();
+        let mut diff = DiffOptions::default();
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--context" => {
+                    diff.context = args
+                        .next()
+                        .ok_or("--context needs a value")?
+                        .parse()
+                        .map_err(|_| "--context must be a number")?
+                }
+                "--lenient" => diff.tolerance = Tolerance::Lenient,
+                _ if paths.len() < 2 && !arg.starts_with("--") => paths.push(PathBuf::from(arg)),
+                _ => return Err(format!("unexpected argument {}", arg)),
+            }
+        }
+        let mut paths = paths.into_iter();
+        Ok(Options {
+            ours: paths.next().ok_or("no traces given")?,
+            theirs: paths.next().ok_or("no reference trace given")?,
+            diff,
+        })
+    }
+}
+
+fn main() {
+    let options = Options::from_args().unwrap_or_else(|error| {
+        eprintln!("{}\n{}", error, USAGE);
+        process::exit(2);
+    });
+    match run(&options) {
+        Ok(None) => println!("Traces match"),
+        Ok(Some(divergence)) => {
+            print!("{}", divergence);
+            process::exit(1);
+        }
+        Err(error) => {
+            eprintln!("{}", error);
+            process::exit(2);
+        }
+    }
+}
+
+fn run(options: &Options) -> Result<Option<Divergence>, String> {
+    let open = |path: &Path| {
+        File::open(path)
+            .map(BufReader::new)
+            .map_err(|error| format!("couldn't open {}: {}", path.display(), error))
+    };
+    diff(open(&options.ours)?, open(&options.theirs)?, &options.diff).map_err(|e| e.to_string())
+}
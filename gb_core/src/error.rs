@@ -0,0 +1,30 @@
+//! Error types shared by the whole crate
+
+/// Errors that can be produced while constructing or running a [`Gameboy`](crate::gameboy::Gameboy).
+///
+/// Anything reachable from user-supplied data (ROM images, save files, addresses passed in by a
+/// frontend) should produce one of these rather than panicking.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum GbError {
+    /// The ROM image is malformed, e.g. too short to contain a header.
+    #[error("invalid ROM: {0}")]
+    InvalidRom(&'static str),
+    /// The cartridge header requests a mapper that is not implemented.
+    #[error("unsupported mapper: {0:#04X}")]
+    UnsupportedMapper(u8),
+    /// An address was accessed that is not handled by the component it was given to.
+    #[error("address out of range: {0:#06X}")]
+    AddressOutOfRange(u16),
+    /// More than one chip on the bus responds to this address.
+    #[error("multiple chips respond to address {0:#06X}")]
+    ChipConflict(u16),
+    /// A save file does not match the cartridge it was loaded into.
+    #[error("invalid save data: {0}")]
+    InvalidSaveData(&'static str),
+    /// A buffer passed in to be filled is the wrong size.
+    #[error("buffer is {actual} bytes long, but {expected} bytes are needed")]
+    BufferSize { expected: usize, actual: usize },
+    /// The emulator was asked to do something its current state does not allow.
+    #[error("invalid state: {0}")]
+    InvalidState(&'static str),
+}
@@ -0,0 +1,129 @@
+//! Helpers shared by the emulator frontends
+
+pub mod input;
+pub mod sync;
+
+use std::time::{Duration, Instant};
+
+use crate::gameboy::T_CYCLES_PER_SECOND;
+
+/// If emulation falls further behind real time than this, the [`Throttle`] gives up on catching up
+pub const MAX_LAG: Duration = Duration::from_millis(100);
+
+/// A monotonic clock that can be slept on. This is only a trait so that tests can mock time.
+pub trait HostClock {
+    /// Time elapsed since an arbitrary, fixed point
+    fn now(&mut self) -> Duration;
+    fn sleep(&mut self, duration: Duration);
+}
+
+/// The host system's monotonic clock
+#[derive(Debug, Clone, Copy)]
+pub struct StdClock {
+    epoch: Instant,
+}
+
+impl StdClock {
+    /// `thread::sleep` may overshoot by about a millisecond, so the end of a sleep is spun instead
+    const SPIN_TIME: Duration = Duration::from_millis(2);
+}
+
+impl Default for StdClock {
+    fn default() -> Self {
+        StdClock {
+            epoch: Instant::now(),
+        }
+    }
+}
+
+impl HostClock for StdClock {
+    fn now(&mut self) -> Duration {
+        self.epoch.elapsed()
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        let end = Instant::now() + duration;
+        if let Some(coarse) = duration.checked_sub(Self::SPIN_TIME) {
+            std::thread::sleep(coarse);
+        }
+        while Instant::now() < end {
+            std::hint::spin_loop();
+        }
+    }
+}
+
+/// Paces emulation to real time.
+///
+/// Call [`Throttle::sync`] with [`Gameboy::cycles`](crate::gameboy::Gameboy::cycles) after running
+/// the emulator for a while, and it will wait until that many cycles worth of real time has passed.
+#[derive(Debug, Clone)]
+pub struct Throttle<C: HostClock = StdClock> {
+    clock: C,
+    speed: f64,
+    /// Host time and emulated cycle count that pacing is measured from
+    anchor: Option<(Duration, u64)>,
+}
+
+impl Throttle {
+    /// Create a throttle that runs at `speed` times the speed of a real Gameboy. Use
+    /// `f64::INFINITY` to run uncapped.
+    ///
+    /// # Panics
+    /// Panics if `speed` is not positive
+    pub fn new(speed: f64) -> Self {
+        Throttle::with_clock(speed, StdClock::default())
+    }
+}
+
+impl<C: HostClock> Throttle<C> {
+    /// Like [`Throttle::new`], but measuring time with `clock`
+    pub fn with_clock(speed: f64, clock: C) -> Self {
+        assert!(speed > 0.0, "throttle speed must be positive");
+        Throttle {
+            clock,
+            speed,
+            anchor: None,
+        }
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    /// Change the speed multiplier. Pacing restarts from the next call to [`Throttle::sync`].
+    ///
+    /// # Panics
+    /// Panics if `speed` is not positive
+    pub fn set_speed(&mut self, speed: f64) {
+        assert!(speed > 0.0, "throttle speed must be positive");
+        self.speed = speed;
+        self.anchor = None;
+    }
+
+    /// Wait until real time has caught up with `emulated_cycles`, the total number of T-cycles
+    /// emulated so far.
+    ///
+    /// If emulation has fallen more than [`MAX_LAG`] behind (for example because the host was
+    /// suspended), pacing restarts from now instead of running fast to catch up.
+    pub fn sync(&mut self, emulated_cycles: u64) {
+        let now = self.clock.now();
+        let (anchor_time, anchor_cycles) = match self.anchor {
+            Some((time, cycles)) if cycles <= emulated_cycles && self.speed.is_finite() => {
+                (time, cycles)
+            }
+            _ => {
+                self.anchor = Some((now, emulated_cycles));
+                return;
+            }
+        };
+
+        let secs =
+            (emulated_cycles - anchor_cycles) as f64 / (T_CYCLES_PER_SECOND as f64 * self.speed);
+        let target = anchor_time + Duration::from_secs_f64(secs);
+        if target > now {
+            self.clock.sleep(target - now);
+        } else if now - target > MAX_LAG {
+            self.anchor = Some((now, emulated_cycles));
+        }
+    }
+}
@@ -0,0 +1,377 @@
+//! Maps host keyboard and gamepad input to joypad buttons
+
+use std::{fmt, str::FromStr};
+
+use crate::gameboy::joypad::{Button, Joypad};
+
+/// How far a gamepad axis has to be pushed before it counts as a button press
+pub const DEFAULT_AXIS_THRESHOLD: f32 = 0.5;
+
+const BUTTONS: [Button; 8] = [
+    Button::Start,
+    Button::Select,
+    Button::B,
+    Button::A,
+    Button::Left,
+    Button::Right,
+    Button::Up,
+    Button::Down,
+];
+
+/// An input event from the host. Key and gamepad codes are whatever the frontend uses, as long
+/// as they match the codes in the bindings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HostInput {
+    KeyDown(u32),
+    KeyUp(u32),
+    GamepadButton {
+        button: u32,
+        pressed: bool,
+    },
+    /// An axis position from -1.0 to 1.0
+    GamepadAxis {
+        axis: u32,
+        value: f32,
+    },
+}
+
+/// A host input that can be bound to a [`Button`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Binding {
+    Key(u32),
+    GamepadButton(u32),
+    /// An axis pushed past the threshold in the positive direction
+    AxisPositive(u32),
+    /// An axis pushed past the threshold in the negative direction
+    AxisNegative(u32),
+}
+
+/// What to do when both buttons of an opposing d-pad pair are held. A real d-pad can't press
+/// Left and Right, or Up and Down, together, and some games misbehave if they see it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OppositePolicy {
+    /// Pass both buttons through
+    Allow,
+    /// Press neither button
+    #[default]
+    Neutral,
+    /// Only press the button that was pressed most recently
+    LastWins,
+}
+
+/// Bindings, turbo periods and the opposite direction policy, which can be saved to and loaded
+/// from a simple text format with [`Display`](fmt::Display) and [`FromStr`]:
+///
+/// ```text
+/// # Comments start with '#'
+/// opposite = last-wins
+/// A = key 88
+/// A = button 0
+/// Left = axis- 0
+/// turbo B = 4
+/// ```
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct InputProfile {
+    pub bindings: Vec<(Binding, Button)>,
+    /// Turbo period in frames for each button, in the order of `BUTTONS`
+    turbo: [Option<u32>; 8],
+    pub opposite_policy: OppositePolicy,
+}
+
+impl InputProfile {
+    pub fn bind(&mut self, binding: Binding, button: Button) {
+        self.bindings.push((binding, button));
+    }
+
+    /// Remove every binding for `binding`
+    pub fn unbind(&mut self, binding: Binding) {
+        self.bindings.retain(|(b, _)| *b != binding);
+    }
+
+    /// Make `button` fire repeatedly while held: pressed for the first half of every `period`
+    /// frames (rounded up) and released for the rest. `None` turns turbo off.
+    ///
+    /// # Panics
+    /// Panics if `period` is less than 2
+    pub fn set_turbo(&mut self, button: Button, period: Option<u32>) {
+        assert!(
+            period.map_or(true, |p| p >= 2),
+            "turbo period must be at least 2 frames"
+        );
+        self.turbo[button_index(button)] = period;
+    }
+
+    pub fn turbo(&self, button: Button) -> Option<u32> {
+        self.turbo[button_index(button)]
+    }
+}
+
+/// Turns [`HostInput`] events into joypad button presses, according to an [`InputProfile`].
+///
+/// Events can arrive at any time, but only take effect when [`InputMapper::latch`] is called once
+/// per frame, so that turbo buttons and recorded input are deterministic.
+#[derive(Debug, Clone)]
+pub struct InputMapper {
+    profile: InputProfile,
+    axis_threshold: f32,
+    /// Bindings currently held on the host, in the order they were pressed
+    active: Vec<Binding>,
+    /// For each button, the frame it was first latched as held
+    held_since: [Option<u64>; 8],
+    frame: u64,
+}
+
+impl InputMapper {
+    pub fn new(profile: InputProfile) -> Self {
+        InputMapper {
+            profile,
+            axis_threshold: DEFAULT_AXIS_THRESHOLD,
+            active: Vec::new(),
+            held_since: [None; 8],
+            frame: 0,
+        }
+    }
+
+    pub fn profile(&self) -> &InputProfile {
+        &self.profile
+    }
+
+    /// Replace the profile. Held buttons stay held if they are still bound.
+    pub fn set_profile(&mut self, profile: InputProfile) {
+        self.profile = profile;
+    }
+
+    /// Set how far an axis must be pushed to count as a press, from 0.0 to 1.0
+    pub fn set_axis_threshold(&mut self, threshold: f32) {
+        self.axis_threshold = threshold;
+    }
+
+    /// Record an event from the host
+    pub fn handle(&mut self, input: HostInput) {
+        match input {
+            HostInput::KeyDown(code) => self.set_active(Binding::Key(code), true),
+            HostInput::KeyUp(code) => self.set_active(Binding::Key(code), false),
+            HostInput::GamepadButton { button, pressed } => {
+                self.set_active(Binding::GamepadButton(button), pressed)
+            }
+            HostInput::GamepadAxis { axis, value } => {
+                self.set_active(Binding::AxisPositive(axis), value >= self.axis_threshold);
+                self.set_active(Binding::AxisNegative(axis), value <= -self.axis_threshold);
+            }
+        }
+    }
+
+    fn set_active(&mut self, binding: Binding, active: bool) {
+        let index = self.active.iter().position(|b| *b == binding);
+        match (index, active) {
+            (None, true) => self.active.push(binding),
+            (Some(i), false) => {
+                self.active.remove(i);
+            }
+            _ => (),
+        }
+    }
+
+    /// Whether `button` is bound to anything the host is holding, before turbo and the opposite
+    /// direction policy are applied
+    pub fn is_held(&self, button: Button) -> bool {
+        self.profile
+            .bindings
+            .iter()
+            .any(|(binding, b)| *b == button && self.active.contains(binding))
+    }
+
+    /// Advance by one frame and update every button on `joypad`. Call this once per frame at the
+    /// same point, such as the start of VBlank when [`Gameboy::run_frames`] returns.
+    ///
+    /// [`Gameboy::run_frames`]: crate::gameboy::Gameboy::run_frames
+    pub fn latch(&mut self, joypad: &mut Joypad) {
+        for button in BUTTONS {
+            let i = button_index(button);
+            match (self.is_held(button), self.held_since[i]) {
+                (true, None) => self.held_since[i] = Some(self.frame),
+                (false, Some(_)) => self.held_since[i] = None,
+                _ => (),
+            }
+        }
+
+        let mut pressed = [false; 8];
+        for button in BUTTONS {
+            let i = button_index(button);
+            pressed[i] = match (self.held_since[i], self.profile.turbo(button)) {
+                (Some(start), Some(period)) => {
+                    let phase = (self.frame - start) % period as u64;
+                    phase < (period as u64 + 1) / 2
+                }
+                (held, None) => held.is_some(),
+                (None, _) => false,
+            };
+        }
+        self.resolve_opposites(&mut pressed, Button::Left, Button::Right);
+        self.resolve_opposites(&mut pressed, Button::Up, Button::Down);
+
+        for button in BUTTONS {
+            joypad.set_button(button, pressed[button_index(button)]);
+        }
+        self.frame += 1;
+    }
+
+    /// How recently any binding for `button` was pressed on the host, as a position in `active`
+    fn last_pressed(&self, button: Button) -> Option<usize> {
+        self.active.iter().rposition(|binding| {
+            self.profile
+                .bindings
+                .iter()
+                .any(|(b, bound)| b == binding && *bound == button)
+        })
+    }
+
+    fn resolve_opposites(&self, pressed: &mut [bool; 8], a: Button, b: Button) {
+        let (a, b) = (button_index(a), button_index(b));
+        if !(pressed[a] && pressed[b]) {
+            return;
+        }
+        match self.profile.opposite_policy {
+            OppositePolicy::Allow => (),
+            OppositePolicy::Neutral => {
+                pressed[a] = false;
+                pressed[b] = false;
+            }
+            OppositePolicy::LastWins => {
+                if self.last_pressed(BUTTONS[a]) > self.last_pressed(BUTTONS[b]) {
+                    pressed[b] = false;
+                } else {
+                    pressed[a] = false;
+                }
+            }
+        }
+    }
+}
+
+fn button_index(button: Button) -> usize {
+    BUTTONS.iter().position(|b| *b == button).unwrap()
+}
+
+fn button_name(button: Button) -> &'static str {
+    match button {
+        Button::Start => "Start",
+        Button::Select => "Select",
+        Button::B => "B",
+        Button::A => "A",
+        Button::Left => "Left",
+        Button::Right => "Right",
+        Button::Up => "Up",
+        Button::Down => "Down",
+    }
+}
+
+/// Errors produced while parsing an [`InputProfile`]
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ProfileParseError {
+    #[error("line {0}: expected `name = value`")]
+    MissingEquals(usize),
+    #[error("line {0}: unknown button {1:?}")]
+    UnknownButton(usize, String),
+    #[error("line {0}: invalid binding {1:?}")]
+    InvalidBinding(usize, String),
+    #[error("line {0}: invalid turbo period {1:?}")]
+    InvalidTurbo(usize, String),
+    #[error("line {0}: unknown opposite direction policy {1:?}")]
+    UnknownPolicy(usize, String),
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Binding::Key(code) => write!(f, "key {}", code),
+            Binding::GamepadButton(code) => write!(f, "button {}", code),
+            Binding::AxisPositive(axis) => write!(f, "axis+ {}", axis),
+            Binding::AxisNegative(axis) => write!(f, "axis- {}", axis),
+        }
+    }
+}
+
+impl fmt::Display for OppositePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OppositePolicy::Allow => "allow",
+            OppositePolicy::Neutral => "neutral",
+            OppositePolicy::LastWins => "last-wins",
+        })
+    }
+}
+
+impl fmt::Display for InputProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "opposite = {}", self.opposite_policy)?;
+        for (binding, button) in &self.bindings {
+            writeln!(f, "{} = {}", button_name(*button), binding)?;
+        }
+        for button in BUTTONS {
+            if let Some(period) = self.turbo(button) {
+                writeln!(f, "turbo {} = {}", button_name(button), period)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for InputProfile {
+    type Err = ProfileParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut profile = InputProfile::default();
+        for (i, line) in s.lines().enumerate() {
+            let line_no = i + 1;
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (name, value) = line
+                .split_once('=')
+                .ok_or(ProfileParseError::MissingEquals(line_no))?;
+            let (name, value) = (name.trim(), value.trim());
+            let button = |name: &str| {
+                BUTTONS
+                    .iter()
+                    .copied()
+                    .find(|b| button_name(*b) == name)
+                    .ok_or_else(|| ProfileParseError::UnknownButton(line_no, name.to_owned()))
+            };
+
+            if name == "opposite" {
+                profile.opposite_policy = match value {
+                    "allow" => OppositePolicy::Allow,
+                    "neutral" => OppositePolicy::Neutral,
+                    "last-wins" => OppositePolicy::LastWins,
+                    _ => return Err(ProfileParseError::UnknownPolicy(line_no, value.to_owned())),
+                };
+            } else if let Some(name) = name.strip_prefix("turbo ") {
+                let button = button(name.trim())?;
+                let period =
+                    value.parse().ok().filter(|p| *p >= 2).ok_or_else(|| {
+                        ProfileParseError::InvalidTurbo(line_no, value.to_owned())
+                    })?;
+                profile.set_turbo(button, Some(period));
+            } else {
+                let button = button(name)?;
+                let binding = parse_binding(value)
+                    .ok_or_else(|| ProfileParseError::InvalidBinding(line_no, value.to_owned()))?;
+                profile.bind(binding, button);
+            }
+        }
+        Ok(profile)
+    }
+}
+
+fn parse_binding(s: &str) -> Option<Binding> {
+    let (kind, code) = s.split_once(char::is_whitespace)?;
+    let code = code.trim().parse().ok()?;
+    match kind {
+        "key" => Some(Binding::Key(code)),
+        "button" => Some(Binding::GamepadButton(code)),
+        "axis+" => Some(Binding::AxisPositive(code)),
+        "axis-" => Some(Binding::AxisNegative(code)),
+        _ => None,
+    }
+}
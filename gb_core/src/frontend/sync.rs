@@ -0,0 +1,156 @@
+//! Keeping emulation in step with the host's display and audio device.
+//!
+//! A Gameboy frame is 70224 T-cycles at 4194304Hz, or about 59.73Hz, so a frontend that runs one
+//! frame per vsync of a 60Hz display slowly gets ahead of real time. [`VideoSync`] tells the
+//! frontend how far to run before each vsync under a [`SyncStrategy`], and [`AudioSync`] turns the
+//! same cycle counter into sample counts, so that audio can't drift away from the video.
+//!
+//! Both measure everything from where they started instead of adding up per-vsync amounts, so
+//! rounding never accumulates.
+//!
+//! ```no_run
+//! # fn present(_: &gb_core::gameboy::ppu::frame::Frame) {}
+//! # fn wait_for_vsync() {}
+//! use gb_core::{frontend::sync::{SyncStrategy, VideoSync}, gameboy::Gameboy};
+//!
+//! # fn frontend(mut gameboy: Gameboy) {
+//! let mut sync = VideoSync::new(60.0, SyncStrategy::RealTime, gameboy.cycles());
+//! loop {
+//!     gameboy.run_cycles(sync.vsync(gameboy.cycles()));
+//!     present(&gameboy.get_frame());
+//!     wait_for_vsync();
+//! }
+//! # }
+//! ```
+
+use crate::gameboy::{
+    ppu::consts::{FRAME_T_CYCLES, LINE_T_CYCLES},
+    T_CYCLES_PER_SECOND,
+};
+
+/// How emulated frames line up with the host's vsyncs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncStrategy {
+    /// Emulate as much time as passes on the host, in whole scanlines. At 60Hz that is usually
+    /// 153 lines per vsync and now and then one more, so emulation stays within a scanline of
+    /// real time. Every so often no new frame is finished by a vsync, and the last one is shown
+    /// again.
+    RealTime,
+    /// Emulate exactly one frame per vsync. Frames are never repeated or skipped, but the game
+    /// runs at the host's refresh rate, which is 0.46% fast at 60Hz.
+    FrameLocked,
+}
+
+/// Decides how many T-cycles to emulate before each host vsync
+#[derive(Debug, Clone)]
+pub struct VideoSync {
+    refresh_rate: f64,
+    strategy: SyncStrategy,
+    /// The cycle counter when syncing started
+    start: u64,
+    vsyncs: u64,
+}
+
+impl VideoSync {
+    /// Sync to a display refreshing `refresh_rate` times a second, starting from `cycles`, the
+    /// Gameboy's current cycle count.
+    ///
+    /// # Panics
+    /// Panics if `refresh_rate` is not positive and finite
+    pub fn new(refresh_rate: f64, strategy: SyncStrategy, cycles: u64) -> Self {
+        assert!(
+            refresh_rate > 0.0 && refresh_rate.is_finite(),
+            "refresh rate must be positive"
+        );
+        VideoSync {
+            refresh_rate,
+            strategy,
+            start: cycles,
+            vsyncs: 0,
+        }
+    }
+
+    pub fn strategy(&self) -> SyncStrategy {
+        self.strategy
+    }
+
+    /// Emulated T-cycles per second of host time
+    pub fn cycles_per_second(&self) -> f64 {
+        match self.strategy {
+            SyncStrategy::RealTime => T_CYCLES_PER_SECOND as f64,
+            SyncStrategy::FrameLocked => FRAME_T_CYCLES as f64 * self.refresh_rate,
+        }
+    }
+
+    /// The cycle count emulation should reach before vsync number `vsync`
+    fn target(&self, vsync: u64) -> u64 {
+        let cycles = match self.strategy {
+            SyncStrategy::RealTime => {
+                let exact = vsync as f64 * T_CYCLES_PER_SECOND as f64 / self.refresh_rate;
+                let lines = (exact / LINE_T_CYCLES as f64) as u64;
+                lines * LINE_T_CYCLES as u64
+            }
+            SyncStrategy::FrameLocked => vsync * FRAME_T_CYCLES as u64,
+        };
+        self.start + cycles
+    }
+
+    /// Call before each vsync with the Gameboy's current cycle count, and run it for the returned
+    /// number of T-cycles, such as with [`Gameboy::run_cycles`]. Whatever that overshoots by is
+    /// taken off the next vsync.
+    ///
+    /// [`Gameboy::run_cycles`]: crate::gameboy::Gameboy::run_cycles
+    pub fn vsync(&mut self, cycles: u64) -> u64 {
+        self.vsyncs += 1;
+        self.target(self.vsyncs).saturating_sub(cycles)
+    }
+
+    /// Start over from `cycles`, such as after a pause or when the refresh rate changes
+    pub fn restart(&mut self, refresh_rate: f64, cycles: u64) {
+        *self = VideoSync::new(refresh_rate, self.strategy, cycles);
+    }
+}
+
+/// Decides how many samples of audio to play for the emulated time, at the same speed as a
+/// [`VideoSync`]
+#[derive(Debug, Clone)]
+pub struct AudioSync {
+    sample_rate: u32,
+    cycles_per_second: f64,
+    /// The cycle counter when syncing started
+    start: u64,
+    samples: u64,
+}
+
+impl AudioSync {
+    /// Play `sample_rate` samples a second, at the speed `video` runs emulation at, starting from
+    /// `cycles`, the Gameboy's current cycle count
+    pub fn new(sample_rate: u32, video: &VideoSync, cycles: u64) -> Self {
+        AudioSync {
+            sample_rate,
+            cycles_per_second: video.cycles_per_second(),
+            start: cycles,
+            samples: 0,
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// How many emulated T-cycles each output sample covers. This is the ratio to resample the
+    /// APU's output by.
+    pub fn cycles_per_sample(&self) -> f64 {
+        self.cycles_per_second / self.sample_rate as f64
+    }
+
+    /// The number of samples to play for the emulated time up to `cycles`, the Gameboy's current
+    /// cycle count, that haven't been played yet
+    pub fn samples_until(&mut self, cycles: u64) -> u64 {
+        let elapsed = cycles.saturating_sub(self.start) as f64;
+        let total = (elapsed / self.cycles_per_sample()) as u64;
+        let new = total.saturating_sub(self.samples);
+        self.samples += new;
+        new
+    }
+}
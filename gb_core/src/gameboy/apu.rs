@@ -0,0 +1,102 @@
+use gb_cpu::CpuOutputPins;
+
+use super::{Chip, ClockContext};
+
+/// Bits of each register from NR10 to NR52 that always read as 1, because they are unused or
+/// write-only
+const READ_MASKS: [u8; 0x27 - 0x10] = [
+    0x80, 0x3F, 0x00, 0xFF, 0xBF, // NR10-NR14
+    0xFF, 0x3F, 0x00, 0xFF, 0xBF, // NR20-NR24
+    0x7F, 0xFF, 0x9F, 0xFF, 0xBF, // NR30-NR34
+    0xFF, 0xFF, 0x00, 0x00, 0xBF, // NR40-NR44
+    0x00, 0x00, 0x70, // NR50-NR52
+];
+
+/// The sound registers and wave RAM. No sound is produced; this only exists so that the registers
+/// read back like they do on hardware.
+#[derive(Debug, Clone)]
+pub struct Apu {
+    /// NR10 to NR51, indexed from $FF10
+    registers: [u8; 0x26 - 0x10],
+    powered: bool,
+    wave_ram: [u8; 0x10],
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Apu {
+            registers: [0; 0x26 - 0x10],
+            powered: true,
+            wave_ram: [0; 0x10],
+        }
+    }
+}
+
+impl Apu {
+    /// The registers as the DMG boot ROM leaves them, after playing the startup sound on
+    /// channel 1
+    pub fn after_boot() -> Self {
+        let mut apu = Apu::default();
+        apu.registers[0x01] = 0x80; // NR11
+        apu.registers[0x02] = 0xF3; // NR12
+        apu.registers[0x14] = 0x77; // NR50
+        apu.registers[0x15] = 0xF3; // NR51
+        apu
+    }
+}
+
+impl Chip for Apu {
+    fn clock(
+        &mut self,
+        input: CpuOutputPins,
+        data: &mut u8,
+        _interrupt_request: &mut u8,
+        _ctx: &ClockContext,
+    ) {
+        match input {
+            // NR52. Only the power bit is writable, and turning the power off clears every register.
+            CpuOutputPins::Write { addr: 0xFF26, data } => {
+                self.powered = data & 0x80 != 0;
+                if !self.powered {
+                    self.registers = [0; 0x26 - 0x10];
+                }
+            }
+            CpuOutputPins::Read { addr: 0xFF26 } => {
+                *data = READ_MASKS[0x16] | if self.powered { 0x80 } else { 0 }
+            }
+
+            // Writes are ignored while the power is off
+            CpuOutputPins::Write {
+                addr: addr @ 0xFF10..=0xFF25,
+                data,
+            } if self.powered => self.registers[addr as usize - 0xFF10] = data,
+            CpuOutputPins::Read {
+                addr: addr @ 0xFF10..=0xFF25,
+            } => {
+                let i = addr as usize - 0xFF10;
+                *data = self.registers[i] | READ_MASKS[i];
+            }
+
+            CpuOutputPins::Write {
+                addr: addr @ 0xFF30..=0xFF3F,
+                data,
+            } => self.wave_ram[addr as usize - 0xFF30] = data,
+            CpuOutputPins::Read {
+                addr: addr @ 0xFF30..=0xFF3F,
+            } => *data = self.wave_ram[addr as usize - 0xFF30],
+            _ => (),
+        }
+    }
+
+    fn chip_select(&self) -> Vec<std::ops::RangeInclusive<u16>> {
+        vec![0xFF10..=0xFF26, 0xFF30..=0xFF3F]
+    }
+
+    fn name(&self) -> &'static str {
+        "apu"
+    }
+
+    fn next_event(&self, _ctx: &ClockContext) -> u64 {
+        u64::MAX
+    }
+}
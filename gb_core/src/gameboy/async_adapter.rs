@@ -0,0 +1,218 @@
+//! Running a Gameboy on an async executor without blocking it. Nothing here depends on a
+//! particular runtime: [`AsyncGameboy::run_frame`] emulates in chunks and yields to the executor
+//! between them, and an [`AsyncSerialConnection`] lets link cable transfers wait on network I/O
+//! while emulation carries on.
+//!
+//! ```no_run
+//! # async fn frontend(gameboy: gb_core::gameboy::Gameboy) {
+//! use gb_core::gameboy::async_adapter::AsyncGameboy;
+//!
+//! let mut gameboy = AsyncGameboy::new(gameboy);
+//! loop {
+//!     let frame = gameboy.run_frame().await;
+//!     // Show `frame.frame`, and use `frame.info.cycle` to pace the audio
+//! }
+//! # }
+//! ```
+
+use std::{
+    future::Future,
+    ops::{Deref, DerefMut},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Wake, Waker},
+};
+
+use super::{
+    ppu::{frame_info::FrameInfo, frame_pool::SharedFrame},
+    Gameboy, SerialConnection,
+};
+
+/// T-cycles emulated between yields to the executor by default, which is about 2.4ms of emulated
+/// time
+pub const DEFAULT_CHUNK_CYCLES: u64 = 10_000;
+
+/// Resolves to the byte sent back by the other end of the link cable
+pub type ExchangeFuture = Pin<Box<dyn Future<Output = u8> + Send>>;
+
+/// A device on the other end of the link cable that may take a while to answer, such as a peer
+/// over the network
+pub trait AsyncSerialConnection {
+    /// Start exchanging one byte with the connected device.
+    ///
+    /// Called once a transfer driven by the internal clock has shifted out all 8 bits. The
+    /// transfer stays in progress until the future resolves, which is polled while the
+    /// [`AsyncGameboy`] runs.
+    fn exchange(&mut self, byte: u8) -> ExchangeFuture;
+}
+
+/// A completed frame, and what happened while it was drawn
+#[derive(Debug, Clone)]
+pub struct FrameHandle {
+    pub frame: SharedFrame,
+    pub info: FrameInfo,
+}
+
+/// Wraps a [`Gameboy`] to run it from async code. It derefs to the [`Gameboy`] for everything
+/// else.
+pub struct AsyncGameboy {
+    gameboy: Gameboy,
+    chunk_cycles: u64,
+    /// The waker of the task running the latest chunk, which serial exchanges are polled with
+    waker: Arc<Mutex<Option<Waker>>>,
+}
+
+impl AsyncGameboy {
+    pub fn new(gameboy: Gameboy) -> Self {
+        AsyncGameboy {
+            gameboy,
+            chunk_cycles: DEFAULT_CHUNK_CYCLES,
+            waker: Default::default(),
+        }
+    }
+
+    pub fn into_inner(self) -> Gameboy {
+        self.gameboy
+    }
+
+    /// Emulate at least `cycles` T-cycles between yields to the executor. Defaults to
+    /// [`DEFAULT_CHUNK_CYCLES`].
+    pub fn set_chunk_cycles(&mut self, cycles: u64) {
+        self.chunk_cycles = cycles.max(1);
+    }
+
+    /// Connect a device to the link port, replacing whatever was connected before
+    pub fn connect_serial(&mut self, connection: Box<dyn AsyncSerialConnection + Send>) {
+        self.gameboy.serial.connect(Box::new(AsyncSerialBridge {
+            connection,
+            pending: None,
+            waker: self.waker.clone(),
+        }));
+    }
+
+    /// Run until the next frame is completed, and return it. This is the async version of
+    /// `run_frames(1)`, and the frame is always drawn.
+    ///
+    /// Emulation runs in chunks of [`AsyncGameboy::set_chunk_cycles`] T-cycles, and yields to the
+    /// executor after each one, so other tasks on the same thread keep running.
+    pub async fn run_frame(&mut self) -> FrameHandle {
+        let target = self.gameboy.ppu.frame_count + 1;
+        loop {
+            register_waker(&self.waker).await;
+            self.gameboy.ppu.draw_next_frame = Some(true);
+            let start = self.gameboy.cycles();
+            while self.gameboy.ppu.frame_count < target
+                && self.gameboy.cycles() - start < self.chunk_cycles
+            {
+                self.gameboy.tick();
+            }
+            // Cleared before yielding, in case this future is dropped while it is suspended
+            self.gameboy.ppu.draw_next_frame = None;
+            if self.gameboy.ppu.frame_count >= target {
+                break;
+            }
+            YieldNow(false).await;
+        }
+        FrameHandle {
+            frame: self.gameboy.ppu.get_frame(),
+            info: self.gameboy.ppu.frame_info(),
+        }
+    }
+}
+
+impl Deref for AsyncGameboy {
+    type Target = Gameboy;
+
+    fn deref(&self) -> &Gameboy {
+        &self.gameboy
+    }
+}
+
+impl DerefMut for AsyncGameboy {
+    fn deref_mut(&mut self) -> &mut Gameboy {
+        &mut self.gameboy
+    }
+}
+
+/// Store the waker of the task this is awaited in, for serial exchanges to wake. This only
+/// borrows `slot`, so that [`AsyncGameboy::run_frame`] is `Send` without the [`Gameboy`] being
+/// `Sync`.
+async fn register_waker(slot: &Mutex<Option<Waker>>) {
+    std::future::poll_fn(|cx| {
+        let mut waker = slot.lock().unwrap();
+        if !waker.as_ref().map_or(false, |w| w.will_wake(cx.waker())) {
+            *waker = Some(cx.waker().clone());
+        }
+        Poll::Ready(())
+    })
+    .await
+}
+
+/// Returns `Pending` once, so that the executor can run other tasks
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            Poll::Ready(())
+        } else {
+            self.0 = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+/// Used to poll exchanges before any task has run the Gameboy
+struct NoopWake;
+
+impl Wake for NoopWake {
+    fn wake(self: Arc<Self>) {}
+}
+
+/// Lets the serial port poll an [`AsyncSerialConnection`] every M-cycle while it waits for a reply
+struct AsyncSerialBridge {
+    connection: Box<dyn AsyncSerialConnection + Send>,
+    pending: Option<ExchangeFuture>,
+    waker: Arc<Mutex<Option<Waker>>>,
+}
+
+impl AsyncSerialBridge {
+    fn poll_pending(&mut self) -> Option<u8> {
+        let future = self.pending.as_mut()?;
+        let waker = self
+            .waker
+            .lock()
+            .unwrap()
+            .clone()
+            .unwrap_or_else(|| Waker::from(Arc::new(NoopWake)));
+        match future.as_mut().poll(&mut Context::from_waker(&waker)) {
+            Poll::Ready(byte) => {
+                self.pending = None;
+                Some(byte)
+            }
+            Poll::Pending => None,
+        }
+    }
+}
+
+impl SerialConnection for AsyncSerialBridge {
+    /// The serial port never calls this, but anything else gets $FF if the reply isn't ready
+    /// straight away, as if the cable was unplugged
+    fn exchange(&mut self, byte: u8) -> u8 {
+        let reply = self.begin_exchange(byte);
+        self.pending = None;
+        reply.unwrap_or(0xFF)
+    }
+
+    fn begin_exchange(&mut self, byte: u8) -> Option<u8> {
+        self.pending = Some(self.connection.exchange(byte));
+        self.poll_pending()
+    }
+
+    fn poll_reply(&mut self) -> Option<u8> {
+        self.poll_pending()
+    }
+}
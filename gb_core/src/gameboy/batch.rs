@@ -0,0 +1,93 @@
+//! Run many Gameboys at once on a [rayon] thread pool, for fuzzing, compatibility scans and
+//! servers. Each instance runs on one thread at a time, and nothing is shared between instances
+//! except the ROM image.
+//!
+//! ```
+//! use gb_core::gameboy::{batch::BatchRunner, test_pattern};
+//!
+//! let roms = vec![test_pattern::rom(); 4];
+//! let frames = BatchRunner::new().run_roms(roms, |mut gameboy| gameboy.run_frames(3).clone());
+//! assert!(frames.iter().all(|frame| frame.as_ref().unwrap()[(8, 0)] == test_pattern::shade(8, 0)));
+//! ```
+
+use std::sync::Arc;
+
+use rayon::prelude::*;
+
+use super::{Gameboy, ResetKind};
+use crate::GbError;
+
+/// Runs a closure against each of a batch of Gameboys in parallel. Every Gameboy is built with the
+/// default settings and [`reset`](Gameboy::reset) before the closure gets it.
+#[derive(Default)]
+pub struct BatchRunner {
+    /// Uses the global rayon pool if `None`
+    pool: Option<rayon::ThreadPool>,
+}
+
+impl BatchRunner {
+    /// Run on the global rayon thread pool
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run on a thread pool of its own, with `threads` threads
+    pub fn with_threads(threads: usize) -> Result<Self, rayon::ThreadPoolBuildError> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()?;
+        Ok(BatchRunner { pool: Some(pool) })
+    }
+
+    fn install<R: Send>(&self, op: impl FnOnce() -> R + Send) -> R {
+        match &self.pool {
+            Some(pool) => pool.install(op),
+            None => op(),
+        }
+    }
+
+    /// Build a Gameboy for each ROM, and return what `f` returns for each of them, in the same
+    /// order. A ROM that can't be loaded gets an error without stopping the others.
+    pub fn run_roms<R, F>(
+        &self,
+        roms: Vec<impl Into<Arc<[u8]>> + Send>,
+        f: F,
+    ) -> Vec<Result<R, GbError>>
+    where
+        R: Send,
+        F: Fn(Gameboy) -> R + Sync + Send,
+    {
+        self.install(|| {
+            roms.into_par_iter()
+                .map(|rom| Ok(f(boot(rom.into())?)))
+                .collect()
+        })
+    }
+
+    /// Build a Gameboy from `rom` for each of `inputs`, and return what `f` returns for each of
+    /// them, in the same order. The ROM image is shared rather than copied.
+    pub fn run_inputs<I, R, F>(
+        &self,
+        rom: Arc<[u8]>,
+        inputs: Vec<I>,
+        f: F,
+    ) -> Result<Vec<R>, GbError>
+    where
+        I: Send,
+        R: Send,
+        F: Fn(Gameboy, I) -> R + Sync + Send,
+    {
+        self.install(|| {
+            inputs
+                .into_par_iter()
+                .map(|input| Ok(f(boot(rom.clone())?, input)))
+                .collect()
+        })
+    }
+}
+
+fn boot(rom: Arc<[u8]>) -> Result<Gameboy, GbError> {
+    let mut gameboy = Gameboy::from_shared_rom(rom)?;
+    gameboy.reset(ResetKind::PowerCycle);
+    Ok(gameboy)
+}
@@ -0,0 +1,112 @@
+//! Stopping the emulator when the CPU reaches an address, for debuggers
+
+use super::{
+    expr::{Expr, ExprError},
+    symbols::{self, SymError, SymbolTable},
+    Gameboy,
+};
+
+/// Identifies a breakpoint added with [`Gameboy::add_breakpoint`](super::Gameboy::add_breakpoint)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BreakpointId(u32);
+
+impl BreakpointId {
+    /// Returned by [`Gameboy::run_until_breakpoint`](super::Gameboy::run_until_breakpoint) when a
+    /// debug opcode handler asks to stop, rather than a breakpoint. See
+    /// [`debug_opcodes`](super::debug_opcodes).
+    pub const DEBUG_OPCODE: BreakpointId = BreakpointId(u32::MAX);
+}
+
+/// Stops [`Gameboy::run_until_breakpoint`](super::Gameboy::run_until_breakpoint) when an opcode is
+/// fetched from `addr`, before the instruction runs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub addr: u16,
+    /// Only stop while this bank is mapped at `addr`. Ignored outside $4000-$7FFF and
+    /// $A000-$BFFF.
+    pub bank: Option<u16>,
+    /// Only stop when this evaluates to non-zero
+    pub condition: Option<Expr>,
+}
+
+impl Breakpoint {
+    pub fn at(addr: u16) -> Self {
+        Breakpoint {
+            addr,
+            bank: None,
+            condition: None,
+        }
+    }
+
+    /// A breakpoint at a label from `symbols`, written `Name` or `Name+offset`. Labels in banked
+    /// memory only stop while their bank is mapped.
+    pub fn at_symbol(target: &str, symbols: &SymbolTable) -> Result<Self, SymError> {
+        let (bank, addr) = symbols.resolve(target)?;
+        Ok(Breakpoint {
+            bank: symbols::is_banked(addr).then_some(bank),
+            ..Breakpoint::at(addr)
+        })
+    }
+
+    /// Only stop when `expr` evaluates to non-zero, replacing any condition set before. See
+    /// [`expr`](super::expr) for the syntax.
+    pub fn with_condition(self, expr: &str) -> Result<Self, ExprError> {
+        Ok(Breakpoint {
+            condition: Some(expr.parse()?),
+            ..self
+        })
+    }
+
+    fn hit(&self, pc: u16, gameboy: &Gameboy) -> bool {
+        pc == self.addr
+            && self
+                .bank
+                .map_or(true, |bank| gameboy.mapped_bank(pc) == bank)
+            && self
+                .condition
+                .as_ref()
+                .map_or(true, |condition| condition.eval(gameboy) != 0)
+    }
+}
+
+/// Every breakpoint that has been added to a [`Gameboy`]
+#[derive(Debug, Default)]
+pub(crate) struct Breakpoints {
+    entries: Vec<(BreakpointId, Breakpoint)>,
+    next_id: u32,
+    /// The breakpoint that stopped the emulator, until `run_until_breakpoint` returns it
+    pub hit: Option<BreakpointId>,
+}
+
+impl Breakpoints {
+    pub fn add(&mut self, breakpoint: Breakpoint) -> BreakpointId {
+        let id = BreakpointId(self.next_id);
+        self.next_id += 1;
+        self.entries.push((id, breakpoint));
+        id
+    }
+
+    /// Returns false if there is no breakpoint with this id
+    pub fn remove(&mut self, id: BreakpointId) -> bool {
+        let len = self.entries.len();
+        self.entries.retain(|(entry, _)| *entry != id);
+        self.entries.len() != len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (BreakpointId, &Breakpoint)> {
+        self.entries
+            .iter()
+            .map(|(id, breakpoint)| (*id, breakpoint))
+    }
+
+    /// The first breakpoint added that stops at `pc`, with `gameboy` in its current state
+    pub fn find(&self, pc: u16, gameboy: &Gameboy) -> Option<BreakpointId> {
+        self.iter()
+            .find(|(_, breakpoint)| breakpoint.hit(pc, gameboy))
+            .map(|(id, _)| id)
+    }
+}
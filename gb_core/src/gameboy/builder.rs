@@ -0,0 +1,478 @@
+use std::{
+    collections::BTreeMap, convert::TryFrom, num::NonZeroU8, ops::RangeInclusive, sync::Arc,
+};
+
+use gb_cpu::{CpuInputPins, Registers};
+
+use super::{
+    cart::{Cart, Mbc1Wiring},
+    joypad::{self, JoypadLatchMode},
+    memory::{Memory, RamInit},
+    perf_stats::PerfStats,
+    ppu,
+    rtc::{RtcSource, RtcTimeSource},
+    scheduler::Scheduler,
+    serial,
+    timer::TimerClock,
+    Chip, Gameboy, SerialConnection,
+};
+use crate::threading::dyn_maybe_send;
+use crate::GbError;
+
+/// The hardware revision being emulated
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Model {
+    /// The original monochrome Gameboy
+    #[default]
+    Dmg,
+    /// A Super Game Boy, which receives command packets through the joypad port
+    Sgb,
+}
+
+impl Model {
+    /// The CPU registers left behind by this model's boot ROM. Games read A, B and C to tell
+    /// models apart.
+    pub fn boot_registers(self) -> Registers {
+        let (af, bc, de, hl) = match self {
+            Model::Dmg => (0x01B0, 0x0013, 0x00D8, 0x014D),
+            Model::Sgb => (0x0100, 0x0014, 0x0000, 0xC060),
+        };
+        let mut registers = Registers {
+            sp: 0xFFFE,
+            pc: 0x0100,
+            ..Default::default()
+        };
+        registers.set_af(af);
+        registers.set_bc(bc);
+        registers.set_de(de);
+        registers.set_hl(hl);
+        registers
+    }
+
+    /// The internal counter DIV is read from during the M-cycle in which the CPU fetches the
+    /// cartridge's first instruction at $0100, when the boot ROM is skipped. DIV reads $AB, and
+    /// only goes up to $AC 13 M-cycles later.
+    ///
+    /// The SGB boot ROM also sends the cartridge header to the SNES, so its DIV ends up elsewhere,
+    /// but that isn't modelled yet and it gets the DMG value.
+    pub fn boot_counter(self) -> u16 {
+        match self {
+            Model::Dmg | Model::Sgb => 0xABCC,
+        }
+    }
+
+    /// How many dots the PPU is into its frame during that same M-cycle, counting from the start
+    /// of line 0. The boot ROM leaves the LCD on, and hands over in line 153 after LY has wrapped
+    /// to 0, so STAT reads $85: VBlank, with LY=LYC. Line 0 of the next frame starts 14 M-cycles
+    /// later.
+    pub fn boot_ppu_dots(self) -> usize {
+        match self {
+            Model::Dmg | Model::Sgb => 153 * 456 + 400,
+        }
+    }
+
+    /// Whether writing STAT can raise a spurious STAT interrupt. Every monochrome model has this
+    /// bug, and only the CGB fixed it.
+    pub fn stat_write_bug(self) -> bool {
+        match self {
+            Model::Dmg | Model::Sgb => true,
+        }
+    }
+
+    /// Whether OPRI ($FF6C) exists, to let the CGB boot ROM choose how overlapping sprites are
+    /// ordered. Monochrome models don't have it, and always use
+    /// [`PriorityMode::Dmg`](ppu::priority::PriorityMode::Dmg).
+    pub fn has_opri(self) -> bool {
+        match self {
+            Model::Dmg | Model::Sgb => false,
+        }
+    }
+}
+
+/// How closely the emulator follows hardware in corner cases that games rarely depend on, where
+/// doing so costs speed or makes behavior harder to follow
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AccuracyLevel {
+    /// Take the simple route. VRAM reads while the PPU is drawing return $FF.
+    #[default]
+    Fast,
+    /// Follow hardware as far as it is known. VRAM reads while the PPU is drawing return the byte
+    /// its fetcher read last.
+    Accurate,
+}
+
+/// How the PPU turns VRAM and its registers into pixels. Either way, the modes, LY, STAT and the
+/// interrupts they raise are stepped one dot at a time by the same code.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PpuBackend {
+    /// Run the pixel FIFOs and fetchers dot by dot, as hardware does
+    #[default]
+    DotAccurate,
+    /// Draw each line all at once when mode 3 starts, from the registers, VRAM and OAM at that
+    /// moment, which makes the PPU itself about twice as fast on busy scenes. Writes that land
+    /// partway through mode 3 only take effect from the next line, and mode 3 lasts for an
+    /// estimate of the time the FIFOs would take. OAM is read all at once at the end of mode 2.
+    /// FIFO snapshots are not recorded, and the byte read from VRAM during mode 3 with
+    /// [`AccuracyLevel::Accurate`] is not updated.
+    Scanline,
+}
+
+/// Where within an M-cycle a CPU write to VRAM, OAM or the PPU's registers lands, relative to the
+/// 4 dots the PPU runs during it. Reads are answered before the dots either way.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum BusInterleave {
+    /// Handle the write, then run all 4 dots, so the PPU sees the new value for the whole
+    /// M-cycle. The STAT write bug and interrupts the write causes are latched straight away.
+    #[default]
+    MCycle,
+    /// Run 3 dots, handle the write, then run the last dot, as on hardware, where the write lands
+    /// on the last T-cycle of the M-cycle. The interrupt lines are latched at the start of the
+    /// M-cycle, so interrupts the write causes are latched on the next one.
+    TCycle,
+}
+
+/// Addresses handled directly by the [`Gameboy`] rather than by a chip
+const RESERVED_ADDRESSES: [RangeInclusive<u16>; 3] = [
+    // IF
+    0xFF0F..=0xFF0F,
+    // Boot ROM disable
+    0xFF50..=0xFF50,
+    // IE
+    0xFFFF..=0xFFFF,
+];
+
+enum CartSource {
+    Rom(Arc<[u8]>),
+    #[cfg(feature = "unstable-internals")]
+    Chip(Box<dyn_maybe_send!(Chip)>),
+}
+
+/// Assembles a [`Gameboy`] out of its component chips.
+///
+/// ```no_run
+/// # use gb_core::gameboy::{GameboyBuilder, Model};
+/// # let rom = vec![];
+/// let gameboy = GameboyBuilder::new()
+///     .rom(rom)
+///     .model(Model::Dmg)
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Default)]
+pub struct GameboyBuilder {
+    cart: Option<CartSource>,
+    boot_rom: Option<Vec<u8>>,
+    model: Model,
+    accuracy: AccuracyLevel,
+    ppu_backend: PpuBackend,
+    bus_interleave: BusInterleave,
+    ram_init: RamInit,
+    serial: Option<Box<dyn_maybe_send!(SerialConnection)>>,
+    rtc: Option<Box<dyn_maybe_send!(RtcSource)>>,
+    rtc_time_source: RtcTimeSource,
+    lenient_header: bool,
+    mbc1_wiring: Mbc1Wiring,
+    chips: Vec<Box<dyn_maybe_send!(Chip)>>,
+    allow_chip_conflicts: bool,
+    joypad_latch_mode: JoypadLatchMode,
+}
+
+impl GameboyBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load a ROM image into the cartridge slot, replacing any previously set cartridge. An
+    /// `Arc<[u8]>` is used as it is, so it can be shared with other Gameboys.
+    pub fn rom(mut self, rom: impl Into<Arc<[u8]>>) -> Self {
+        self.cart = Some(CartSource::Rom(rom.into()));
+        self
+    }
+
+    /// Plug an arbitrary chip into the cartridge slot, replacing any previously set cartridge
+    #[cfg(feature = "unstable-internals")]
+    pub fn cartridge(mut self, chip: Box<dyn_maybe_send!(Chip)>) -> Self {
+        self.cart = Some(CartSource::Chip(chip));
+        self
+    }
+
+    /// Map a 256 byte boot ROM over the start of the cartridge until it is disabled by a write to $FF50.
+    ///
+    /// Without a boot ROM, the CPU starts executing from the cartridge directly.
+    pub fn boot_rom(mut self, boot_rom: Vec<u8>) -> Self {
+        self.boot_rom = Some(boot_rom);
+        self
+    }
+
+    pub fn model(mut self, model: Model) -> Self {
+        self.model = model;
+        self
+    }
+
+    /// Choose between speed and accuracy in corner cases. Defaults to [`AccuracyLevel::Fast`].
+    pub fn accuracy(mut self, accuracy: AccuracyLevel) -> Self {
+        self.accuracy = accuracy;
+        self
+    }
+
+    /// Choose how the PPU draws lines. Defaults to [`PpuBackend::DotAccurate`], and can be changed
+    /// at any time through the `backend` field of [`Ppu`](super::ppu::Ppu), taking effect from
+    /// the next line.
+    pub fn ppu_backend(mut self, backend: PpuBackend) -> Self {
+        self.ppu_backend = backend;
+        self
+    }
+
+    /// Choose where CPU writes land among the PPU's dots. Defaults to [`BusInterleave::MCycle`],
+    /// and can be changed at any time through the `interleave` field of
+    /// [`Ppu`](super::ppu::Ppu).
+    pub fn bus_interleave(mut self, interleave: BusInterleave) -> Self {
+        self.bus_interleave = interleave;
+        self
+    }
+
+    /// Choose what work RAM, high RAM, VRAM and OAM contain at power on, before the boot ROM or
+    /// [`Gameboy::reset`] runs. Defaults to [`RamInit::Zero`].
+    pub fn ram_init(mut self, init: RamInit) -> Self {
+        self.ram_init = init;
+        self
+    }
+
+    /// Connect a device to the link port. Defaults to [`serial::Disconnected`].
+    pub fn serial(mut self, connection: Box<dyn_maybe_send!(SerialConnection)>) -> Self {
+        self.serial = Some(connection);
+        self
+    }
+
+    /// Set the time source used by cartridges with a real time clock. Defaults to one stuck at 0,
+    /// which together with [`RtcTimeSource::EmulatedCycles`] keeps runs reproducible. Frontends
+    /// that want the clock to keep real time pass [`SystemClock`](super::rtc::SystemClock) here
+    /// and [`RtcTimeSource::WallClock`] to [`GameboyBuilder::rtc_time_source`].
+    pub fn rtc(mut self, rtc: Box<dyn_maybe_send!(RtcSource)>) -> Self {
+        self.rtc = Some(rtc);
+        self
+    }
+
+    /// Choose what cartridges with a real time clock count. Defaults to
+    /// [`RtcTimeSource::EmulatedCycles`].
+    pub fn rtc_time_source(mut self, time_source: RtcTimeSource) -> Self {
+        self.rtc_time_source = time_source;
+        self
+    }
+
+    /// Work around a cartridge header that declares less hardware than the game uses, once the
+    /// game shows what it needs, instead of ignoring what it does. See
+    /// [`Cart::lenient`](super::cart::Cart::lenient) and [`Gameboy::cartridge_info`]. Off by
+    /// default.
+    pub fn lenient_header(mut self, lenient: bool) -> Self {
+        self.lenient_header = lenient;
+        self
+    }
+
+    /// Choose how an MBC1 cartridge's bank registers are wired. Defaults to
+    /// [`Mbc1Wiring::Detect`], which only wires multicarts as such, going by the games on them.
+    pub fn mbc1_wiring(mut self, wiring: Mbc1Wiring) -> Self {
+        self.mbc1_wiring = wiring;
+        self
+    }
+
+    /// Attach an extra chip to the bus. Extra chips are clocked after the built-in ones, in the
+    /// order they were added.
+    #[cfg(feature = "unstable-internals")]
+    pub fn chip(mut self, chip: Box<dyn_maybe_send!(Chip)>) -> Self {
+        self.chips.push(chip);
+        self
+    }
+
+    /// Choose when button presses reach the game. Defaults to [`JoypadLatchMode::Immediate`], and
+    /// can be changed between frames with [`Joypad::set_latch_mode`](joypad::Joypad::set_latch_mode).
+    pub fn joypad_latch_mode(mut self, mode: JoypadLatchMode) -> Self {
+        self.joypad_latch_mode = mode;
+        self
+    }
+
+    /// Let chips claim the same addresses, instead of failing to build. Reads of those addresses
+    /// AND together the bytes every claimant drives, and writes reach all of them. In debug builds,
+    /// or with the `strict-bus` feature, each such access is also logged and published as an
+    /// [`Event::BusConflict`](super::events::Event::BusConflict).
+    ///
+    /// Addresses the Gameboy handles itself, such as IF and IE, still can't be claimed.
+    #[doc(hidden)]
+    pub fn allow_chip_conflicts(mut self) -> Self {
+        self.allow_chip_conflicts = true;
+        self
+    }
+
+    /// Assemble the Gameboy.
+    ///
+    /// Fails if the cartridge is missing or invalid, the boot ROM is the wrong size, or if any two
+    /// chips respond to the same address without [`GameboyBuilder::allow_chip_conflicts`].
+    pub fn build(self) -> Result<Gameboy, GbError> {
+        let rtc = self.rtc.unwrap_or_else(super::rtc::default_source);
+        let mut cart = match self.cart {
+            Some(CartSource::Rom(rom)) => {
+                Cart::load(rom, rtc, self.lenient_header, self.mbc1_wiring)?
+            }
+            #[cfg(feature = "unstable-internals")]
+            Some(CartSource::Chip(chip)) => Cart::from_chip(chip, rtc),
+            None => return Err(GbError::InvalidState("no cartridge was provided")),
+        };
+        cart.set_rtc_time_source(self.rtc_time_source);
+
+        let boot_rom = match self.boot_rom {
+            Some(boot_rom) => Some(Box::new(
+                <[u8; 0x100]>::try_from(boot_rom.as_slice())
+                    .map_err(|_| GbError::InvalidRom("boot ROM must be 256 bytes long"))?,
+            )),
+            None => None,
+        };
+
+        let mut ppu = ppu::Ppu::new();
+        ppu.stat_write_bug = self.model.stat_write_bug();
+        ppu.has_opri = self.model.has_opri();
+        ppu.accuracy = self.accuracy;
+        ppu.backend = self.ppu_backend;
+        ppu.interleave = self.bus_interleave;
+
+        let mut gameboy = Gameboy {
+            cpu: gb_cpu::Cpu::default().runner(),
+            ppu,
+            cpu_input: CpuInputPins::default(),
+            fetched: false,
+            memory: Memory::new(),
+            cart,
+            timer: super::timer::Timer::default(),
+            counter: Default::default(),
+            apu: super::apu::Apu::default(),
+            joypad: match self.model {
+                Model::Dmg => joypad::Joypad::default(),
+                Model::Sgb => joypad::Joypad::with_sgb(),
+            },
+            serial: match self.serial {
+                Some(connection) => serial::Serial::new(connection),
+                None => serial::Serial::default(),
+            },
+            chips: self.chips,
+            io_hooks: Default::default(),
+            bus_conflicts: Default::default(),
+            scheduler: Scheduler::new(std::iter::empty()),
+            boot_rom_image: boot_rom.clone(),
+            boot_rom,
+            ram_init: self.ram_init,
+            model: self.model,
+            accuracy: self.accuracy,
+            scanline_callback: None,
+            debug_opcode_handler: None,
+            save_writer: None,
+            cheats: Default::default(),
+            breakpoints: Default::default(),
+            oam_bug: false,
+            violations: Default::default(),
+            events: Default::default(),
+            profiler: None,
+            profiling: false,
+            call_stack: None,
+            coverage: None,
+            bus_tracer: None,
+            perf: PerfStats::new(0, 0),
+            journal: Default::default(),
+            pc_history: Default::default(),
+            last_crash: None,
+            symbols: Default::default(),
+            #[cfg(feature = "capture")]
+            gif_recorder: None,
+            capture_triggers: Default::default(),
+            observers: None,
+            scanline_idle: None,
+            raced_lines: Vec::new(),
+            racing_beam: false,
+
+            interrupt_enable: 0,
+            interrupt_request: 0,
+            cycles: 0,
+            overclock: NonZeroU8::MIN,
+            timer_clock: TimerClock::Stock,
+            extra_cycles: 0,
+            extra_cycle: false,
+        };
+
+        gameboy.joypad.set_latch_mode(self.joypad_latch_mode);
+        if self.allow_chip_conflicts {
+            check_reserved_addresses(&gameboy)?;
+            gameboy.bus_conflicts = find_chip_conflicts(&gameboy);
+        } else {
+            check_chip_conflicts(&gameboy)?;
+        }
+        gameboy.scheduler = Scheduler::new(gameboy.other_chips());
+        gameboy.set_ram_init(self.ram_init);
+
+        Ok(gameboy)
+    }
+}
+
+/// Whether `addr` is handled by the [`Gameboy`] itself or by any chip on the bus
+pub(super) fn is_claimed(gameboy: &Gameboy, addr: u16) -> bool {
+    RESERVED_ADDRESSES
+        .iter()
+        .cloned()
+        .chain(gameboy.chips().flat_map(|chip| chip.chip_select()))
+        .any(|range| range.contains(&addr))
+}
+
+/// Returns an error containing the first address the Gameboy handles that a chip also claims
+fn check_reserved_addresses(gameboy: &Gameboy) -> Result<(), GbError> {
+    let conflict = gameboy
+        .chips()
+        .flat_map(|chip| chip.chip_select())
+        .flat_map(|range| {
+            RESERVED_ADDRESSES
+                .iter()
+                .map(move |reserved| (range.clone(), reserved))
+        })
+        .filter(|(range, reserved)| {
+            range.start() <= reserved.end() && reserved.start() <= range.end()
+        })
+        .map(|(range, reserved)| u16::max(*range.start(), *reserved.start()))
+        .min();
+    match conflict {
+        Some(addr) => Err(GbError::ChipConflict(addr)),
+        None => Ok(()),
+    }
+}
+
+/// Every address claimed by more than one chip, with the names of the first two to claim it
+fn find_chip_conflicts(gameboy: &Gameboy) -> BTreeMap<u16, [&'static str; 2]> {
+    let mut claimed: Vec<Option<&'static str>> = vec![None; 0x10000];
+    let mut conflicts = BTreeMap::new();
+    for chip in gameboy.chips() {
+        for addr in chip.chip_select().into_iter().flatten() {
+            match claimed[addr as usize] {
+                Some(first) => {
+                    conflicts.entry(addr).or_insert([first, chip.name()]);
+                }
+                None => claimed[addr as usize] = Some(chip.name()),
+            }
+        }
+    }
+    conflicts
+}
+
+/// Returns an error containing the first address claimed by more than one chip
+fn check_chip_conflicts(gameboy: &Gameboy) -> Result<(), GbError> {
+    let mut claimed: Vec<RangeInclusive<u16>> = RESERVED_ADDRESSES.to_vec();
+    for chip in gameboy.chips() {
+        let ranges = chip.chip_select();
+        for range in &ranges {
+            if let Some(conflict) = claimed
+                .iter()
+                .filter(|other| range.start() <= other.end() && other.start() <= range.end())
+                .map(|other| u16::max(*range.start(), *other.start()))
+                .min()
+            {
+                return Err(GbError::ChipConflict(conflict));
+            }
+        }
+        claimed.extend(ranges);
+    }
+    Ok(())
+}
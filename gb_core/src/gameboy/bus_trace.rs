@@ -0,0 +1,173 @@
+//! A record of every access made on the bus, like a logic analyzer would capture.
+//!
+//! Tracing is started with [`Gameboy::start_bus_trace`](super::Gameboy::start_bus_trace), which
+//! takes a [`BusTrace`] with a fixed capacity. Once it is full, each new access overwrites the
+//! oldest, so recording never allocates.
+
+use std::collections::VecDeque;
+
+use gb_cpu::CpuOutputPins;
+
+use super::{BusMaster, Chip};
+
+/// Whether an access read or wrote
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BusOp {
+    Read,
+    Write,
+}
+
+/// What answered an access
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChipId {
+    Ppu,
+    Memory,
+    Cart,
+    Timer,
+    Apu,
+    Joypad,
+    Serial,
+    /// A chip added with `GameboyBuilder::chip`, by the order they were added in
+    Extra(usize),
+    /// IE and IF, which aren't part of any chip
+    Interrupts,
+    /// A handler registered with [`Gameboy::register_io_hook`](super::Gameboy::register_io_hook)
+    IoHook,
+    /// The boot ROM, while it is mapped over the cartridge
+    BootRom,
+}
+
+impl ChipId {
+    /// The chip at position `i` in the order the chips are clocked
+    fn from_index(i: usize) -> Self {
+        match i {
+            0 => ChipId::Ppu,
+            1 => ChipId::Memory,
+            2 => ChipId::Cart,
+            3 => ChipId::Timer,
+            4 => ChipId::Apu,
+            5 => ChipId::Joypad,
+            6 => ChipId::Serial,
+            i => ChipId::Extra(i - 7),
+        }
+    }
+}
+
+/// One M-cycle of bus activity
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BusEvent {
+    /// T-cycles since power on, as of the start of the M-cycle
+    pub cycle: u64,
+    pub master: BusMaster,
+    pub op: BusOp,
+    pub addr: u16,
+    /// The byte written, or the byte on the data bus at the end of a read
+    pub data: u8,
+    /// `None` for open bus
+    pub chip: Option<ChipId>,
+}
+
+/// A ring buffer of the most recent [`BusEvent`]s
+#[derive(Debug, Clone)]
+pub struct BusTrace {
+    events: VecDeque<BusEvent>,
+    capacity: usize,
+}
+
+impl BusTrace {
+    /// A trace that holds up to `capacity` accesses
+    ///
+    /// # Panics
+    /// Panics if `capacity` is 0
+    pub fn with_capacity(capacity: usize) -> Self {
+        assert!(capacity > 0, "bus trace capacity must be positive");
+        BusTrace {
+            events: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Remove the recorded accesses, oldest first
+    pub fn drain(&mut self) -> impl Iterator<Item = BusEvent> + '_ {
+        self.events.drain(..)
+    }
+
+    #[inline]
+    fn push(&mut self, event: BusEvent) {
+        if self.events.len() == self.capacity {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+}
+
+/// Marks an address no chip claims in [`BusTracer::chip_map`]
+const OPEN_BUS: u8 = u8::MAX;
+
+/// A [`BusTrace`] being recorded into, and which chip claims each address
+pub(crate) struct BusTracer {
+    pub trace: BusTrace,
+    /// The index of the chip claiming each address, in clocking order, or [`OPEN_BUS`]. Chip
+    /// selects don't change after a Gameboy is built, so this is only worked out once.
+    chip_map: Box<[u8; 0x10000]>,
+}
+
+impl BusTracer {
+    pub fn new<'a>(trace: BusTrace, chips: impl Iterator<Item = &'a dyn Chip>) -> Self {
+        let mut chip_map = Box::new([OPEN_BUS; 0x10000]);
+        for (i, chip) in chips.enumerate() {
+            for range in chip.chip_select() {
+                for addr in range {
+                    chip_map[addr as usize] = i.min(OPEN_BUS as usize - 1) as u8;
+                }
+            }
+        }
+        BusTracer { trace, chip_map }
+    }
+
+    /// Record an access. `boot_rom` and `io_hook` are whether those answered instead of a chip.
+    #[inline]
+    pub fn record(
+        &mut self,
+        cycle: u64,
+        master: BusMaster,
+        pins: CpuOutputPins,
+        data: u8,
+        boot_rom: bool,
+        io_hook: bool,
+    ) {
+        let (op, addr, data) = match pins {
+            CpuOutputPins::Read { addr } => (BusOp::Read, addr, data),
+            CpuOutputPins::Write { addr, data } => (BusOp::Write, addr, data),
+        };
+        let chip = match addr {
+            0x0000..=0x00FF if boot_rom && op == BusOp::Read => Some(ChipId::BootRom),
+            0xFF0F | 0xFFFF => Some(ChipId::Interrupts),
+            _ if io_hook => Some(ChipId::IoHook),
+            _ => match self.chip_map[addr as usize] {
+                OPEN_BUS => None,
+                i => Some(ChipId::from_index(i as usize)),
+            },
+        };
+        self.trace.push(BusEvent {
+            cycle,
+            master,
+            op,
+            addr,
+            data,
+            chip,
+        });
+    }
+}
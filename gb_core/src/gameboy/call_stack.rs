@@ -0,0 +1,106 @@
+//! Reconstructs the call stack by watching the instructions the CPU executes
+
+/// Frames deeper than this are dropped from the bottom of the stack, in case a game leaves
+/// functions without returning from them in a way that can't be detected
+const MAX_DEPTH: usize = 256;
+
+/// A function call or interrupt that has not returned yet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StackFrame {
+    /// Address of the CALL or RST instruction, or the address that an interrupt returns to
+    pub call_site: u16,
+    /// Address of the function or interrupt handler
+    pub target: u16,
+    /// SP after the return address was pushed
+    pub sp_at_entry: u16,
+    /// The ROM bank mapped at $4000-$7FFF when the call was made
+    pub bank: u8,
+    /// Whether this frame was created by an interrupt rather than an instruction
+    pub interrupt: bool,
+}
+
+/// A call instruction that was fetched, which may or may not jump
+#[derive(Debug, Clone, Copy)]
+struct PendingCall {
+    call_site: u16,
+    sp: u16,
+    bank: u8,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct CallStack {
+    frames: Vec<StackFrame>,
+    pending: Option<PendingCall>,
+}
+
+fn is_call(opcode: u8) -> bool {
+    matches!(
+        opcode,
+        // CALL nn, CALL cc, nn
+        0xCD | 0xC4 | 0xCC | 0xD4 | 0xDC
+        // RST
+        | 0xC7 | 0xCF | 0xD7 | 0xDF | 0xE7 | 0xEF | 0xF7 | 0xFF
+    )
+}
+
+impl CallStack {
+    pub fn frames(&self) -> &[StackFrame] {
+        &self.frames
+    }
+
+    fn push(&mut self, frame: StackFrame) {
+        if self.frames.len() == MAX_DEPTH {
+            self.frames.remove(0);
+        }
+        self.frames.push(frame);
+    }
+
+    /// Finish tracking the previous instruction, now that execution has reached `pc` with the
+    /// stack pointer at `sp`
+    fn sync(&mut self, pc: u16, sp: u16) {
+        // Once SP is above a frame's return address, that address has been popped, whether by
+        // RET, RETI, POP or by moving SP directly
+        while self.frames.last().map_or(false, |f| f.sp_at_entry < sp) {
+            self.frames.pop();
+        }
+
+        if let Some(call) = self.pending.take() {
+            // Untaken conditional calls leave SP alone
+            if sp == call.sp.wrapping_sub(2) {
+                self.push(StackFrame {
+                    call_site: call.call_site,
+                    target: pc,
+                    sp_at_entry: sp,
+                    bank: call.bank,
+                    interrupt: false,
+                });
+            }
+        }
+    }
+
+    /// Called on every opcode fetch. `sp` is the stack pointer once the previous instruction has
+    /// completed, and `bank` is the ROM bank mapped at $4000-$7FFF.
+    pub fn fetch(&mut self, addr: u16, opcode: u8, sp: u16, bank: u8) {
+        self.sync(addr, sp);
+        if is_call(opcode) {
+            self.pending = Some(PendingCall {
+                call_site: addr,
+                sp,
+                bank,
+            });
+        }
+    }
+
+    /// Called when the CPU jumps to an interrupt handler. `sp` is the stack pointer after the
+    /// return address was pushed.
+    pub fn interrupt(&mut self, vector: u16, return_addr: u16, sp: u16, bank: u8) {
+        self.sync(return_addr, sp.wrapping_add(2));
+        self.push(StackFrame {
+            call_site: return_addr,
+            target: vector,
+            sp_at_entry: sp,
+            bank,
+            interrupt: true,
+        });
+    }
+}
@@ -0,0 +1,195 @@
+//! Saving what the screen shows, for bug reports and sharing. [`Gameboy::screenshot`] encodes the
+//! front frame as a PNG, and [`Gameboy::start_gif_recording`] records the frames that follow into
+//! an animated GIF.
+//!
+//! ```no_run
+//! # fn share(gameboy: &mut gb_core::gameboy::Gameboy) {
+//! use gb_core::gameboy::capture::GifOptions;
+//!
+//! gameboy.start_gif_recording(GifOptions::default());
+//! gameboy.run_frames(60);
+//! let gif = gameboy.stop_recording().unwrap();
+//! # }
+//! ```
+
+use std::borrow::Cow;
+
+use super::{
+    ppu::{color::RgbaColor, consts::FRAME_T_CYCLES, frame::Frame},
+    Gameboy, T_CYCLES_PER_SECOND,
+};
+
+/// Encode `frame` as an 8-bit RGBA PNG, with the colors it is displayed in
+pub fn encode_png(frame: &Frame) -> Vec<u8> {
+    let pixels: Vec<u8> = frame.colors().flat_map(|c| c.to_le_bytes()).collect();
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, 160, 144);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    // Writing into a `Vec` can't fail, and the size of the image always matches
+    let mut writer = encoder.write_header().expect("PNG header");
+    writer.write_image_data(&pixels).expect("PNG image data");
+    writer.finish().expect("PNG end");
+    png
+}
+
+/// How a GIF is recorded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GifOptions {
+    /// Record one out of every `every_nth` frames. Many viewers slow down frames shorter than
+    /// 2/100s, which is most of them when every frame is recorded, so 2 plays back at the right
+    /// speed in more places.
+    pub every_nth: u32,
+    /// Loop forever when played back, instead of stopping at the last frame
+    pub repeat: bool,
+}
+
+impl Default for GifOptions {
+    fn default() -> Self {
+        GifOptions {
+            every_nth: 1,
+            repeat: true,
+        }
+    }
+}
+
+/// Encodes frames into a GIF as they are recorded, so that only the compressed result is kept.
+///
+/// Each frame is stored as its shades, with the palette as its 4 colors. Colors that
+/// post-processing gave individual pixels are not kept.
+pub struct GifRecorder {
+    encoder: gif::Encoder<Vec<u8>>,
+    options: GifOptions,
+    /// The palette written at the start of the GIF. Frames with another palette get their own.
+    palette: [RgbaColor; 4],
+    /// The frame count when recording started
+    start_frame: u64,
+    frames: u64,
+    /// Delay of every frame written so far, in 1/100s
+    elapsed: u64,
+    /// The shades of the frame being written, to save allocating one for every frame
+    indices: Vec<u8>,
+}
+
+impl GifRecorder {
+    /// Start recording the frames completed after `start_frame`, which is the PPU's frame count
+    /// when recording starts. The GIF's global palette is `palette`.
+    pub fn new(options: GifOptions, palette: [RgbaColor; 4], start_frame: u64) -> Self {
+        let options = GifOptions {
+            every_nth: options.every_nth.max(1),
+            ..options
+        };
+        let mut encoder =
+            gif::Encoder::new(Vec::new(), 160, 144, &palette_rgb(&palette)).expect("GIF header");
+        if options.repeat {
+            encoder
+                .set_repeat(gif::Repeat::Infinite)
+                .expect("GIF loop extension");
+        }
+        GifRecorder {
+            encoder,
+            options,
+            palette,
+            start_frame,
+            frames: 0,
+            elapsed: 0,
+            indices: Vec::with_capacity(160 * 144),
+        }
+    }
+
+    /// Whether the frame completed as the PPU's frame count reaches `number` is recorded
+    pub fn wants(&self, number: u64) -> bool {
+        number > self.start_frame
+            && (number - self.start_frame - 1) % self.options.every_nth as u64 == 0
+    }
+
+    /// Number of frames recorded
+    pub fn frame_count(&self) -> u64 {
+        self.frames
+    }
+
+    /// Encode `frame` as the next frame of the GIF.
+    ///
+    /// Delays are whole hundredths of a second, so they are rounded such that the total matches
+    /// the real frame rate of about 59.73Hz: recording every frame gives delays of 2 and 1
+    /// alternating to average 1.67.
+    pub fn push(&mut self, frame: &Frame) {
+        self.frames += 1;
+        let cycles = self.frames * self.options.every_nth as u64 * FRAME_T_CYCLES as u64;
+        let end = (cycles * 100 + T_CYCLES_PER_SECOND / 2) / T_CYCLES_PER_SECOND;
+        let delay = end - self.elapsed;
+        self.elapsed = end;
+
+        self.indices.clear();
+        self.indices.extend(frame.iter().map(|&shade| shade & 3));
+        let palette = (*frame.palette() != self.palette).then(|| palette_rgb(frame.palette()));
+        let gif_frame = gif::Frame {
+            delay: delay as u16,
+            width: 160,
+            height: 144,
+            palette,
+            buffer: Cow::Borrowed(&self.indices),
+            ..Default::default()
+        };
+        self.encoder.write_frame(&gif_frame).expect("GIF frame");
+    }
+
+    /// Finish the GIF and return it
+    pub fn finish(self) -> Vec<u8> {
+        self.encoder.into_inner().expect("GIF trailer")
+    }
+}
+
+/// The RGB bytes of each color, as a GIF color table
+fn palette_rgb(palette: &[RgbaColor; 4]) -> Vec<u8> {
+    palette
+        .iter()
+        .flat_map(|c| c.to_le_bytes()[..3].to_vec())
+        .collect()
+}
+
+impl Gameboy {
+    /// The front frame as a PNG, in the colors it is displayed in
+    pub fn screenshot(&self) -> Vec<u8> {
+        encode_png(&self.get_frame())
+    }
+
+    /// Record the frames completed from now on into a GIF, replacing any recording in progress.
+    ///
+    /// Recorded frames are drawn even if [`Gameboy::run_frames`] would have skipped them, but
+    /// frames skipped by [`Gameboy::set_frame_skip`] are recorded as the last frame drawn.
+    pub fn start_gif_recording(&mut self, options: GifOptions) {
+        let palette = *self.get_frame().palette();
+        self.gif_recorder = Some(Box::new(GifRecorder::new(
+            options,
+            palette,
+            self.ppu.frame_count,
+        )));
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.gif_recorder.is_some()
+    }
+
+    /// Stop recording, and return the GIF. Returns `None` if nothing was being recorded.
+    pub fn stop_recording(&mut self) -> Option<Vec<u8>> {
+        self.gif_recorder.take().map(|recorder| recorder.finish())
+    }
+
+    /// Whether frame `number` is about to be recorded, so must be drawn
+    pub(super) fn capture_wants(&self, number: u64) -> bool {
+        self.gif_recorder
+            .as_ref()
+            .map_or(false, |recorder| recorder.wants(number))
+    }
+
+    /// Called when a frame is completed
+    pub(super) fn capture_frame(&mut self) {
+        if self.capture_wants(self.ppu.frame_count) {
+            let frame = self.ppu.get_frame();
+            if let Some(recorder) = &mut self.gif_recorder {
+                recorder.push(&frame);
+            }
+        }
+    }
+}
@@ -0,0 +1,209 @@
+//! Keeping the frames on which something happened, for automated visual testing.
+//!
+//! A [`Trigger`] is checked each time a frame is completed, and when it fires, that frame is
+//! copied into a queue to be collected with [`Gameboy::take_captures`]. Captures are only taken
+//! at that point, so they are always whole frames, never one half drawn. While any trigger is
+//! waiting, every frame is drawn, whatever [`Gameboy::set_frame_skip`] and
+//! [`Gameboy::run_frames`] would otherwise skip.
+//!
+//! ```no_run
+//! # fn test(mut gameboy: gb_core::gameboy::Gameboy) {
+//! use gb_core::gameboy::capture_triggers::Trigger;
+//!
+//! let title_screen = gameboy.add_capture_trigger(Trigger::OnMemoryEquals {
+//!     addr: 0xC0A0,
+//!     value: 0x01,
+//! });
+//! gameboy.run_frames(600);
+//! for (id, frame_number, frame) in gameboy.take_captures() {
+//!     assert_eq!(id, title_screen);
+//! }
+//! # }
+//! ```
+
+use std::collections::VecDeque;
+
+use super::{
+    breakpoints::BreakpointId,
+    ppu::{frame::Frame, registers::LCDC},
+    Gameboy,
+};
+
+/// How many captures are kept until they are taken, unless changed with
+/// [`Gameboy::set_capture_limit`]. A frame is about 23KiB.
+pub const DEFAULT_CAPTURE_LIMIT: usize = 64;
+
+/// Identifies a trigger added with [`Gameboy::add_capture_trigger`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TriggerId(u32);
+
+/// When a frame is captured. Every trigger is checked as a frame is completed, which is the
+/// start of VBlank, or every 70224 T-cycles while the LCD is off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+    /// The frame that takes the PPU's `frame_count` to this number
+    OnFrameNumber(u64),
+    /// The frame during which this breakpoint was hit. The breakpoint does this whether or not
+    /// the emulator is run with [`Gameboy::run_until_breakpoint`].
+    OnBreakpointHit(BreakpointId),
+    /// Any frame at the end of which `addr` holds `value`, as read by [`Gameboy::peek`]
+    OnMemoryEquals { addr: u16, value: u8 },
+    /// The first frame drawn after the LCD is turned on
+    OnLcdEnable,
+}
+
+struct Entry {
+    id: TriggerId,
+    trigger: Trigger,
+    /// Kept after it fires, to fire again on any later frame it matches
+    repeat: bool,
+    /// An `OnBreakpointHit` whose breakpoint was hit during the frame being drawn
+    hit: bool,
+}
+
+/// The triggers that have been added to a [`Gameboy`], and the frames they captured
+pub(crate) struct CaptureTriggers {
+    entries: Vec<Entry>,
+    next_id: u32,
+    captures: VecDeque<(TriggerId, u64, Frame)>,
+    limit: usize,
+    /// Captures dropped from the front of the queue to stay within `limit`
+    dropped: u64,
+}
+
+impl Default for CaptureTriggers {
+    fn default() -> Self {
+        CaptureTriggers {
+            entries: Vec::new(),
+            next_id: 0,
+            captures: VecDeque::new(),
+            limit: DEFAULT_CAPTURE_LIMIT,
+            dropped: 0,
+        }
+    }
+}
+
+impl CaptureTriggers {
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Remember that `id` was hit, for the triggers waiting on it
+    pub fn breakpoint_hit(&mut self, id: BreakpointId) {
+        for entry in &mut self.entries {
+            if entry.trigger == Trigger::OnBreakpointHit(id) {
+                entry.hit = true;
+            }
+        }
+    }
+
+    fn push(&mut self, capture: (TriggerId, u64, Frame)) {
+        while self.captures.len() >= self.limit.max(1) {
+            self.captures.pop_front();
+            self.dropped += 1;
+        }
+        self.captures.push_back(capture);
+    }
+}
+
+impl Gameboy {
+    /// Capture the next frame `trigger` matches, then remove it
+    pub fn add_capture_trigger(&mut self, trigger: Trigger) -> TriggerId {
+        self.insert_capture_trigger(trigger, false)
+    }
+
+    /// Capture every frame `trigger` matches, until it is removed. Only the latest captures are
+    /// kept if they aren't taken often enough, see [`Gameboy::set_capture_limit`].
+    pub fn add_repeating_capture_trigger(&mut self, trigger: Trigger) -> TriggerId {
+        self.insert_capture_trigger(trigger, true)
+    }
+
+    fn insert_capture_trigger(&mut self, trigger: Trigger, repeat: bool) -> TriggerId {
+        let triggers = &mut self.capture_triggers;
+        let id = TriggerId(triggers.next_id);
+        triggers.next_id += 1;
+        triggers.entries.push(Entry {
+            id,
+            trigger,
+            repeat,
+            hit: false,
+        });
+        self.update_capture_drawing();
+        id
+    }
+
+    /// Returns false if there is no trigger with this id, which includes one-shot triggers that
+    /// have fired. Frames it already captured are kept.
+    pub fn remove_capture_trigger(&mut self, id: TriggerId) -> bool {
+        let entries = &mut self.capture_triggers.entries;
+        let len = entries.len();
+        entries.retain(|entry| entry.id != id);
+        let found = entries.len() != len;
+        self.update_capture_drawing();
+        found
+    }
+
+    /// Every trigger waiting to fire, and whether it repeats
+    pub fn capture_triggers(&self) -> impl Iterator<Item = (TriggerId, Trigger, bool)> + '_ {
+        self.capture_triggers
+            .entries
+            .iter()
+            .map(|entry| (entry.id, entry.trigger, entry.repeat))
+    }
+
+    /// Take the frames captured so far, oldest first, with the trigger that fired and the
+    /// number of the frame. A frame that more than one trigger matched is captured once for each.
+    pub fn take_captures(&mut self) -> Vec<(TriggerId, u64, Frame)> {
+        self.capture_triggers.captures.drain(..).collect()
+    }
+
+    /// Keep at most `limit` captures, dropping the oldest to make room for new ones. At least
+    /// one is always kept.
+    pub fn set_capture_limit(&mut self, limit: usize) {
+        let triggers = &mut self.capture_triggers;
+        triggers.limit = limit;
+        let excess = triggers.captures.len().saturating_sub(limit.max(1));
+        triggers.captures.drain(..excess);
+        triggers.dropped += excess as u64;
+    }
+
+    pub fn capture_limit(&self) -> usize {
+        self.capture_triggers.limit
+    }
+
+    /// Number of captures dropped to stay within the limit since the Gameboy was built
+    pub fn dropped_captures(&self) -> u64 {
+        self.capture_triggers.dropped
+    }
+
+    /// Skipped frames would leave a stale front frame to be captured, so every frame is drawn
+    /// while there are triggers waiting
+    fn update_capture_drawing(&mut self) {
+        self.ppu.draw_every_frame = !self.capture_triggers.is_empty();
+    }
+
+    /// Called when a frame is completed
+    pub(super) fn check_capture_triggers(&mut self) {
+        let number = self.ppu.frame_count;
+        let info = self.ppu.frame_info();
+        let lcd_enabled = info.number == number
+            && info.lcd_was_disabled
+            && self.ppu.lcdc.contains(LCDC::LCD_ENABLE);
+        let mut entries = std::mem::take(&mut self.capture_triggers.entries);
+        entries.retain_mut(|entry| {
+            let fired = match entry.trigger {
+                Trigger::OnFrameNumber(n) => n == number,
+                Trigger::OnBreakpointHit(_) => std::mem::take(&mut entry.hit),
+                Trigger::OnMemoryEquals { addr, value } => self.peek(addr) == value,
+                Trigger::OnLcdEnable => lcd_enabled,
+            };
+            if fired {
+                let frame = Frame::clone(&self.ppu.get_frame());
+                self.capture_triggers.push((entry.id, number, frame));
+            }
+            !fired || entry.repeat
+        });
+        self.capture_triggers.entries = entries;
+        self.update_capture_drawing();
+    }
+}
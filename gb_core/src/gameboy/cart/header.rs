@@ -0,0 +1,151 @@
+//! Reading and synthesising cartridge headers.
+//!
+//! The header lives at $0100-$014F of every ROM image. The boot ROM refuses to start a cartridge
+//! whose logo or header checksum is wrong, so hand-built ROMs need these filled in to run on real
+//! hardware.
+
+use super::{rom_size_from_id, HEADER_END};
+use crate::GbError;
+
+/// The logo the boot ROM scrolls down the screen, which must be present at $0104-$0133
+pub const NINTENDO_LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+const LOGO: usize = 0x104;
+const TITLE: usize = 0x134;
+/// Titles are 16 bytes long on the DMG, but the last byte became the CGB flag
+const TITLE_LEN: usize = 15;
+const CART_TYPE: usize = 0x147;
+const ROM_SIZE: usize = 0x148;
+const RAM_SIZE: usize = 0x149;
+const DESTINATION: usize = 0x14A;
+const OLD_LICENSEE: usize = 0x14B;
+const HEADER_CHECKSUM: usize = 0x14D;
+const GLOBAL_CHECKSUM: usize = 0x14E;
+
+/// The parts of a header that describe the cartridge, read with [`CartridgeHeader::parse`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CartridgeHeader {
+    /// The title without its trailing NULs. Anything that isn't printable ASCII becomes `?`.
+    pub title: String,
+    /// The mapper and any extra hardware on the cartridge
+    pub cart_type: u8,
+    /// Size of the ROM in bytes
+    pub rom_size: usize,
+    pub ram_size_id: u8,
+    pub logo_valid: bool,
+    pub header_checksum_valid: bool,
+    pub global_checksum_valid: bool,
+}
+
+impl CartridgeHeader {
+    /// Read the header of `rom`. The checksums and logo are reported rather than checked, since
+    /// plenty of homebrew gets them wrong.
+    ///
+    /// Returns [`GbError::InvalidRom`] if `rom` is too short to contain a header, or its ROM size
+    /// byte is unknown.
+    pub fn parse(rom: &[u8]) -> Result<Self, GbError> {
+        if rom.len() < HEADER_END {
+            return Err(GbError::InvalidRom("ROM is too short to contain a header"));
+        }
+        let title = rom[TITLE..TITLE + TITLE_LEN]
+            .iter()
+            .take_while(|&&b| b != 0)
+            .map(|&b| match b {
+                b' ' | b'!'..=b'~' => b as char,
+                _ => '?',
+            })
+            .collect();
+        let global = u16::from_be_bytes([rom[GLOBAL_CHECKSUM], rom[GLOBAL_CHECKSUM + 1]]);
+        Ok(CartridgeHeader {
+            title,
+            cart_type: rom[CART_TYPE],
+            rom_size: rom_size_from_id(rom[ROM_SIZE])?,
+            ram_size_id: rom[RAM_SIZE],
+            logo_valid: rom[LOGO..LOGO + NINTENDO_LOGO.len()] == NINTENDO_LOGO,
+            header_checksum_valid: rom[HEADER_CHECKSUM] == header_checksum(rom),
+            global_checksum_valid: global == global_checksum(rom),
+        })
+    }
+}
+
+/// The checksum of $0134-$014C that the boot ROM checks against $014D
+///
+/// # Panics
+/// Panics if `rom` is too short to contain a header
+pub fn header_checksum(rom: &[u8]) -> u8 {
+    rom[TITLE..HEADER_CHECKSUM]
+        .iter()
+        .fold(0u8, |x, &b| x.wrapping_sub(b).wrapping_sub(1))
+}
+
+/// The sum of every byte of the ROM except the global checksum itself, which is stored big endian
+/// at $014E-$014F. Nothing checks this, but it is part of a well-formed header.
+pub fn global_checksum(rom: &[u8]) -> u16 {
+    rom.iter()
+        .enumerate()
+        .filter(|(i, _)| !(GLOBAL_CHECKSUM..GLOBAL_CHECKSUM + 2).contains(i))
+        .fold(0u16, |sum, (_, &b)| sum.wrapping_add(b as u16))
+}
+
+/// The ROM size byte for a ROM image of `len` bytes, or `None` if no cartridge is that size
+pub fn rom_size_id(len: usize) -> Option<u8> {
+    (0..=8u8).find(|&id| 0x8000 << id == len)
+}
+
+/// Fill in the header of a ROM with no mapper and no RAM, including both checksums. Only the
+/// first 15 bytes of `title` are used.
+///
+/// Returns [`GbError::InvalidRom`] if `rom` is not a size that a cartridge can be.
+pub fn write_header(rom: &mut [u8], title: &str) -> Result<(), GbError> {
+    let size_id = rom_size_id(rom.len()).ok_or(GbError::InvalidRom("unsupported ROM size"))?;
+    rom[LOGO..LOGO + NINTENDO_LOGO.len()].copy_from_slice(&NINTENDO_LOGO);
+    let title = &title.as_bytes()[..title.len().min(TITLE_LEN)];
+    rom[TITLE..TITLE + TITLE_LEN].fill(0);
+    rom[TITLE..TITLE + title.len()].copy_from_slice(title);
+    rom[CART_TYPE] = 0x00;
+    rom[ROM_SIZE] = size_id;
+    rom[RAM_SIZE] = 0x00;
+    // Non-Japanese
+    rom[DESTINATION] = 0x01;
+    rom[OLD_LICENSEE] = 0x00;
+    update_checksums(rom);
+    Ok(())
+}
+
+/// Recalculate both checksums after the ROM has been changed
+///
+/// # Panics
+/// Panics if `rom` is too short to contain a header
+pub fn update_checksums(rom: &mut [u8]) {
+    assert!(
+        rom.len() >= HEADER_END,
+        "ROM is too short to contain a header"
+    );
+    rom[HEADER_CHECKSUM] = header_checksum(rom);
+    let global = global_checksum(rom);
+    rom[GLOBAL_CHECKSUM..GLOBAL_CHECKSUM + 2].copy_from_slice(&global.to_be_bytes());
+}
+
+/// Build a 32KiB ROM with no mapper that jumps from the entry point to `code`, which is copied to
+/// `load_addr`. If `code` overlaps the header, the code wins, and the checksums are calculated
+/// afterwards so that they still match.
+///
+/// Returns [`GbError::InvalidRom`] if `code` runs past the end of the ROM at $7FFF.
+pub fn flat_rom(code: &[u8], load_addr: u16, title: &str) -> Result<Vec<u8>, GbError> {
+    let start = load_addr as usize;
+    let end = start + code.len();
+    if end > 0x8000 {
+        return Err(GbError::InvalidRom("program does not fit in a 32KiB ROM"));
+    }
+    let mut rom = vec![0; 0x8000];
+    let [lo, hi] = load_addr.to_le_bytes();
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, lo, hi]); // NOP; JP load_addr
+    write_header(&mut rom, title)?;
+    rom[start..end].copy_from_slice(code);
+    update_checksums(&mut rom);
+    Ok(rom)
+}
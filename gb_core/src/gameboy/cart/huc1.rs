@@ -0,0 +1,190 @@
+//! Hudson's HuC-1, and the infrared transceiver on it.
+//!
+//! The cartridge (type $FF) banks ROM like MBC1, but with a plain 6-bit bank register and nothing
+//! at $6000-$7FFF, and banks up to 32KiB of battery-backed RAM in 4 banks. There is no RAM enable:
+//! writing $0E to $0000-$1FFF maps the transceiver over $A000-$BFFF instead of RAM, and writing
+//! anything else maps RAM back. While the transceiver is mapped in, reads return $C1 if it sees
+//! light and $C0 if it doesn't, and bit 0 of a write turns the LED on or off.
+//!
+//! The other end of the link is an [`IrPort`], set with
+//! [`Cart::set_ir_port`](super::Cart::set_ir_port). Until one is set, no light is ever seen.
+
+use crate::gameboy::{Chip, ClockContext};
+use crate::threading::dyn_maybe_send;
+use gb_cpu::CpuOutputPins;
+
+use super::{Mapper, RomImage};
+
+/// HuC-1 can address at most 64 ROM banks
+pub const MAX_SIZE: usize = 0x40 * 0x4000;
+
+/// 4 banks of 8KiB
+pub const MAX_RAM_SIZE: usize = 0x8000;
+
+/// Writing this to $0000-$1FFF maps the transceiver in
+pub const IR_MODE: u8 = 0x0E;
+
+/// What the infrared transceiver sees, and where its LED shines, such as another emulator's
+/// cartridge or a scripted test. Both methods default to a link with nothing at the other end.
+pub trait IrPort {
+    /// Whether the transceiver sees light, asked on each read while it is mapped in
+    fn light_seen(&mut self) -> bool {
+        false
+    }
+
+    /// The game turned the LED on or off
+    fn set_led(&mut self, _on: bool) {}
+}
+
+/// Nothing at the other end of the link, which is what a HuC-1 starts out with
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Unconnected;
+
+impl IrPort for Unconnected {}
+
+/// The mapper and transceiver. The battery is handled by `Cart`.
+pub(super) struct Huc1 {
+    data: RomImage,
+    ram: Vec<u8>,
+    ir_port: Box<dyn_maybe_send!(IrPort)>,
+
+    ir_mode: bool,
+    rom_bank: u8,
+    ram_bank: u8,
+    led: bool,
+}
+
+impl Huc1 {
+    pub(super) fn new(data: RomImage, ram_size: usize) -> Self {
+        Huc1 {
+            data,
+            ram: vec![0; ram_size.min(MAX_RAM_SIZE)],
+            ir_port: Box::new(Unconnected),
+            ir_mode: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            led: false,
+        }
+    }
+
+    /// See [`Cart::set_ir_port`](super::Cart::set_ir_port)
+    pub(super) fn set_ir_port(&mut self, port: Box<dyn_maybe_send!(IrPort)>) {
+        self.ir_port = port;
+        self.ir_port.set_led(self.led);
+    }
+}
+
+impl Chip for Huc1 {
+    fn clock(
+        &mut self,
+        input: CpuOutputPins,
+        data: &mut u8,
+        _interrupt_request: &mut u8,
+        _ctx: &ClockContext,
+    ) {
+        match input {
+            CpuOutputPins::Read { addr } => match addr {
+                0x0000..=0x3FFF => *data = self.data.read(0, addr),
+                0x4000..=0x7FFF => *data = self.data.read(self.rom_bank as usize, addr),
+
+                0xA000..=0xBFFF if self.ir_mode => {
+                    *data = 0xC0 | self.ir_port.light_seen() as u8;
+                }
+                0xA000..=0xBFFF => {
+                    if let Some(offset) = self.ram_offset(addr) {
+                        *data = self.ram[offset];
+                    }
+                }
+                0x8000..=0x9FFF | 0xC000..=0xFFFF => (),
+            },
+            CpuOutputPins::Write { addr, data } => match addr {
+                0x0000..=0x1FFF => self.ir_mode = data == IR_MODE,
+                0x2000..=0x3FFF => {
+                    self.rom_bank = match data & 0x3F {
+                        0 => 1,
+                        bank => bank,
+                    }
+                }
+                0x4000..=0x5FFF => self.ram_bank = data & 0x03,
+                0x6000..=0x7FFF => (),
+                0xA000..=0xBFFF if self.ir_mode => {
+                    let led = data & 0x01 != 0;
+                    if led != self.led {
+                        self.led = led;
+                        self.ir_port.set_led(led);
+                    }
+                }
+                0xA000..=0xBFFF => {
+                    if let Some(offset) = self.ram_offset(addr) {
+                        self.ram[offset] = data;
+                    }
+                }
+                0x8000..=0x9FFF | 0xC000..=0xFFFF => (),
+            },
+        }
+    }
+
+    fn chip_select(&self) -> Vec<std::ops::RangeInclusive<u16>> {
+        vec![0x0000..=0x7FFF, 0xA000..=0xBFFF]
+    }
+
+    fn next_event(&self, _ctx: &ClockContext) -> u64 {
+        u64::MAX
+    }
+}
+
+impl Mapper for Huc1 {
+    fn rom_bank(&self) -> u16 {
+        self.rom_bank as u16
+    }
+
+    fn ram_bank(&self) -> u8 {
+        self.ram_bank
+    }
+
+    fn has_register(&self, addr: u16) -> bool {
+        addr < 0x6000
+    }
+
+    fn ram(&self) -> Option<&[u8]> {
+        Some(&self.ram)
+    }
+
+    fn ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.ram)
+    }
+
+    /// Nothing lands in RAM while the transceiver is mapped in
+    fn ram_offset(&self, addr: u16) -> Option<usize> {
+        let offset = self.ram_bank as usize * 0x2000 + (addr - 0xA000) as usize;
+        (!self.ir_mode && offset < self.ram.len()).then_some(offset)
+    }
+
+    fn huc1_mut(&mut self) -> Option<&mut Huc1> {
+        Some(self)
+    }
+
+    fn peek_rom(&self, addr: u16) -> Option<u8> {
+        let bank = if addr < 0x4000 { 0 } else { self.rom_bank };
+        Some(self.data.read(bank as usize, addr))
+    }
+
+    fn registers(&self) -> [u8; 4] {
+        [
+            self.ir_mode as u8,
+            self.rom_bank,
+            self.ram_bank,
+            self.led as u8,
+        ]
+    }
+
+    fn set_registers(&mut self, [ir_mode, rom_bank, ram_bank, led]: [u8; 4]) {
+        self.ir_mode = ir_mode != 0;
+        self.rom_bank = rom_bank;
+        self.ram_bank = ram_bank;
+        if self.led != (led != 0) {
+            self.led = led != 0;
+            self.ir_port.set_led(self.led);
+        }
+    }
+}
@@ -0,0 +1,106 @@
+//! The hardware [`Cart::lenient`](super::Cart::lenient) adds when a header turns out to be wrong
+
+use std::ops::RangeInclusive;
+
+use gb_cpu::CpuOutputPins;
+
+use super::Mapper;
+use crate::gameboy::{Chip, ClockContext};
+use crate::threading::dyn_maybe_send;
+
+/// A wrong header that [`Cart::lenient`](super::Cart::lenient) worked around. Plenty of homebrew
+/// and bootleg ROMs declare less hardware than they use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderWorkaround {
+    /// The header declares no cartridge RAM, but the game wrote to $A000-$BFFF, so 8KiB of RAM was
+    /// added there. It is always enabled and never banked, and has no battery, so it isn't saved.
+    AddedRam,
+    /// The header declares no mapper for a ROM larger than 32KiB, but the game wrote to
+    /// $2000-$3FFF, so the cartridge became an MBC1, keeping any RAM added before
+    PromotedToMbc1,
+}
+
+impl std::fmt::Display for HeaderWorkaround {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            HeaderWorkaround::AddedRam => {
+                "cartridge RAM written, but the header declares none: added 8KiB"
+            }
+            HeaderWorkaround::PromotedToMbc1 => {
+                "ROM bank selected, but the header declares no mapper: using MBC1"
+            }
+        })
+    }
+}
+
+/// The size of the RAM added by [`HeaderWorkaround::AddedRam`]
+pub const ADDED_RAM_SIZE: usize = 0x2000;
+
+/// A mapper with [`ADDED_RAM_SIZE`] bytes of RAM added at $A000-$BFFF
+pub struct AddedRam {
+    pub mapper: Box<dyn_maybe_send!(Mapper)>,
+    pub ram: Box<[u8]>,
+}
+
+impl Chip for AddedRam {
+    fn clock(
+        &mut self,
+        input: CpuOutputPins,
+        data: &mut u8,
+        interrupt_request: &mut u8,
+        ctx: &ClockContext,
+    ) {
+        match input {
+            CpuOutputPins::Read {
+                addr: addr @ 0xA000..=0xBFFF,
+            } => *data = self.ram[(addr - 0xA000) as usize],
+            CpuOutputPins::Write {
+                addr: addr @ 0xA000..=0xBFFF,
+                data,
+            } => self.ram[(addr - 0xA000) as usize] = data,
+            _ => self.mapper.clock(input, data, interrupt_request, ctx),
+        }
+    }
+
+    fn chip_select(&self) -> Vec<RangeInclusive<u16>> {
+        self.mapper.chip_select()
+    }
+
+    fn name(&self) -> &'static str {
+        self.mapper.name()
+    }
+
+    fn next_event(&self, ctx: &ClockContext) -> u64 {
+        self.mapper.next_event(ctx)
+    }
+}
+
+impl Mapper for AddedRam {
+    fn rom_bank(&self) -> u16 {
+        self.mapper.rom_bank()
+    }
+
+    fn ram(&self) -> Option<&[u8]> {
+        Some(&self.ram)
+    }
+
+    fn ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.ram)
+    }
+
+    fn peek_rom(&self, addr: u16) -> Option<u8> {
+        self.mapper.peek_rom(addr)
+    }
+
+    fn has_register(&self, addr: u16) -> bool {
+        self.mapper.has_register(addr)
+    }
+
+    fn registers(&self) -> [u8; 4] {
+        self.mapper.registers()
+    }
+
+    fn set_registers(&mut self, registers: [u8; 4]) {
+        self.mapper.set_registers(registers)
+    }
+}
@@ -1,47 +1,46 @@
-use crate::gameboy::Chip;
+use crate::gameboy::{Chip, ClockContext};
 use gb_cpu::CpuOutputPins;
 
-use super::Mapper;
+use super::{header::NINTENDO_LOGO, Mapper, RomImage};
 
-type Bank = [u8; 0x4000];
+/// MBC1 can address at most 128 ROM banks
+pub const MAX_SIZE: usize = 0x80 * 0x4000;
+
+/// Every MBC1 multicart is 1MiB, made of four games of 256KiB
+const MULTICART_SIZE: usize = 0x100000;
+const MULTICART_GAME_SIZE: usize = 0x40000;
+
+/// Whether `rom` looks like it is wired as an MBC1 multicart (MBC1M), which the header doesn't
+/// say. Each game on a multicart has a header of its own, so this looks for the Nintendo logo at
+/// the start of any game after the first, which is where Gambatte and mooneye-gb look.
+pub fn is_multicart(rom: &[u8]) -> bool {
+    rom.len() == MULTICART_SIZE
+        && (1..4).any(|game| {
+            let logo = game * MULTICART_GAME_SIZE + 0x104;
+            rom[logo..logo + NINTENDO_LOGO.len()] == NINTENDO_LOGO
+        })
+}
 
 pub type Mbc1 = Mbc1Generic<ram::NullRam>;
 pub type Mbc1WithRam = Mbc1Generic<ram::BasicRam>;
-// TODO: Implement save files
+// The battery is handled by `Cart`, which tracks changes to the RAM for saving
 pub type Mbc1WithBatteryRam = Mbc1Generic<ram::BasicRam>;
 
-// TODO: ROM Bank mirroring
 pub struct Mbc1Generic<R: ram::Ram> {
-    data: Vec<Bank>,
+    data: RomImage,
     ram: R,
 
     ram_enable: bool,
     rom_bank_lower: u8,
     rom_bank_upper: u8,
     mode_select: bool,
+    /// Wired as a multicart, where bit 4 of the lower bank register isn't connected, and the upper
+    /// bank register selects the game instead
+    multicart: bool,
 }
 
 impl<R: ram::Ram> Mbc1Generic<R> {
-    pub fn new(data: Vec<u8>) -> Self {
-        let mut banks = data.array_chunks::<0x4000>();
-        let mut data = vec![];
-        for bank in banks.by_ref() {
-            data.push(*bank);
-        }
-        let remainder = {
-            let mut buf = [0; 0x4000];
-            buf[..banks.remainder().len()].copy_from_slice(banks.remainder());
-            buf
-        };
-
-        data.push(remainder);
-
-        while data.len() < 0x80 {
-            data.push([0; 0x4000]);
-        }
-
-        assert_eq!(data.len(), 0x80);
-
+    pub fn new(data: RomImage, multicart: bool) -> Self {
         Mbc1Generic {
             data,
             ram: Default::default(),
@@ -49,43 +48,58 @@ impl<R: ram::Ram> Mbc1Generic<R> {
             rom_bank_lower: 1,
             rom_bank_upper: 0,
             mode_select: false,
+            multicart,
         }
     }
 
-    fn bank_0(&mut self) -> &mut [u8; 0x4000] {
-        let bank_idx = if self.mode_select {
-            self.rom_bank_upper << 5
+    /// Where the upper bank register lands in the bank number
+    fn upper_shift(&self) -> u8 {
+        if self.multicart {
+            4
+        } else {
+            5
+        }
+    }
+
+    /// Index of the bank mapped at $0000-$3FFF
+    fn bank_0_idx(&self) -> u8 {
+        if self.mode_select {
+            self.rom_bank_upper << self.upper_shift()
         } else {
             0
-        };
-        &mut self.data[bank_idx as usize]
+        }
     }
 
-    fn bank_1(&mut self) -> &mut [u8; 0x4000] {
+    /// Index of the bank mapped at $4000-$7FFF
+    fn bank_1_idx(&self) -> u8 {
+        // Bank 0 is replaced with bank 1 before bit 4 is dropped on a multicart, so bank $10
+        // of a game is its bank 0
         let lower = if self.rom_bank_lower == 0 {
             1
         } else {
             self.rom_bank_lower
         };
-        let bank_idx = (self.rom_bank_upper << 5) + lower;
-        &mut self.data[bank_idx as usize]
+        let lower = if self.multicart { lower & 0x0F } else { lower };
+        (self.rom_bank_upper << self.upper_shift()) | lower
     }
 }
 
 impl<R: ram::Ram> Chip for Mbc1Generic<R> {
-    fn clock(&mut self, input: CpuOutputPins, data: &mut u8, _interrupt_request: &mut u8) {
+    fn clock(
+        &mut self,
+        input: CpuOutputPins,
+        data: &mut u8,
+        _interrupt_request: &mut u8,
+        _ctx: &ClockContext,
+    ) {
         match input {
             CpuOutputPins::Read { addr } => match addr {
-                0x0000..=0x3FFF => *data = self.bank_0()[addr as usize],
-                0x4000..=0x7FFF => *data = self.bank_1()[(addr - 0x4000) as usize],
-
-                0xA000..=0xBFFF => {
-                    *data = if self.ram_enable {
-                        self.ram[addr - 0xA000]
-                    } else {
-                        0
-                    }
-                }
+                0x0000..=0x3FFF => *data = self.data.read(self.bank_0_idx() as usize, addr),
+                0x4000..=0x7FFF => *data = self.data.read(self.bank_1_idx() as usize, addr),
+
+                // Disabled RAM doesn't drive the bus at all
+                0xA000..=0xBFFF if self.ram_enable => *data = self.ram[addr - 0xA000],
+                0xA000..=0xBFFF => (),
                 0x8000..=0x9FFF | 0xC000..=0xFFFF => (),
             },
             CpuOutputPins::Write { addr, data } => {
@@ -107,19 +121,69 @@ impl<R: ram::Ram> Chip for Mbc1Generic<R> {
             }
         }
     }
+
+    fn chip_select(&self) -> Vec<std::ops::RangeInclusive<u16>> {
+        vec![0x0000..=0x7FFF, 0xA000..=0xBFFF]
+    }
+
+    fn next_event(&self, _ctx: &ClockContext) -> u64 {
+        u64::MAX
+    }
 }
 
-impl<R: ram::Ram> Mapper for Mbc1Generic<R> {}
+impl<R: ram::Ram> Mapper for Mbc1Generic<R> {
+    fn rom_bank(&self) -> u16 {
+        self.bank_1_idx() as u16
+    }
+
+    fn peek_rom(&self, addr: u16) -> Option<u8> {
+        let bank = if addr < 0x4000 {
+            self.bank_0_idx()
+        } else {
+            self.bank_1_idx()
+        };
+        Some(self.data.read(bank as usize, addr))
+    }
+
+    fn ram(&self) -> Option<&[u8]> {
+        self.ram.as_slice()
+    }
+
+    fn ram_mut(&mut self) -> Option<&mut [u8]> {
+        self.ram.as_mut_slice()
+    }
+
+    fn registers(&self) -> [u8; 4] {
+        [
+            self.ram_enable as u8,
+            self.rom_bank_lower,
+            self.rom_bank_upper,
+            self.mode_select as u8,
+        ]
+    }
+
+    fn set_registers(&mut self, [ram_enable, lower, upper, mode_select]: [u8; 4]) {
+        self.ram_enable = ram_enable != 0;
+        self.rom_bank_lower = lower;
+        self.rom_bank_upper = upper;
+        self.mode_select = mode_select != 0;
+    }
+}
 
 mod ram {
-    pub trait Ram: std::ops::IndexMut<u16, Output = u8> + Default {}
+    pub trait Ram: std::ops::IndexMut<u16, Output = u8> + Default {
+        /// The contents of the RAM, or `None` if there is no RAM
+        fn as_slice(&self) -> Option<&[u8]>;
+        fn as_mut_slice(&mut self) -> Option<&mut [u8]>;
+    }
 
+    /// Reads as open bus, and throws away writes
     #[derive(Default)]
     pub struct NullRam(u8);
     impl std::ops::Index<u16> for NullRam {
         type Output = u8;
         fn index(&self, _index: u16) -> &u8 {
-            &0
+            &0xFF
         }
     }
     impl std::ops::IndexMut<u16> for NullRam {
@@ -128,7 +192,15 @@ mod ram {
         }
     }
 
-    impl Ram for NullRam {}
+    impl Ram for NullRam {
+        fn as_slice(&self) -> Option<&[u8]> {
+            None
+        }
+
+        fn as_mut_slice(&mut self) -> Option<&mut [u8]> {
+            None
+        }
+    }
 
     pub struct BasicRam([u8; 0x2000]);
     impl Default for BasicRam {
@@ -148,5 +220,13 @@ mod ram {
         }
     }
 
-    impl Ram for BasicRam {}
+    impl Ram for BasicRam {
+        fn as_slice(&self) -> Option<&[u8]> {
+            Some(&self.0)
+        }
+
+        fn as_mut_slice(&mut self) -> Option<&mut [u8]> {
+            Some(&mut self.0)
+        }
+    }
 }
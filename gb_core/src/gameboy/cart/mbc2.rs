@@ -0,0 +1,132 @@
+use crate::gameboy::{Chip, ClockContext};
+use crate::GbError;
+use gb_cpu::CpuOutputPins;
+
+use super::{Mapper, RomImage};
+
+/// MBC2 can address at most 16 ROM banks
+pub const MAX_SIZE: usize = 0x10 * 0x4000;
+
+/// The built-in RAM holds 512 half-bytes
+const RAM_SIZE: usize = 0x200;
+
+/// MBC2 has no RAM bank register, and its built-in RAM only stores the lower 4 bits of each byte.
+/// The battery is handled by `Cart`, so both cartridge types use this.
+pub struct Mbc2 {
+    data: RomImage,
+    /// Only the lower nibble of each byte is used, and the upper nibble is always 0
+    ram: [u8; RAM_SIZE],
+
+    ram_enable: bool,
+    rom_bank: u8,
+}
+
+impl Mbc2 {
+    pub fn new(data: RomImage) -> Self {
+        Mbc2 {
+            data,
+            ram: [0; RAM_SIZE],
+            ram_enable: false,
+            rom_bank: 1,
+        }
+    }
+}
+
+impl Chip for Mbc2 {
+    fn clock(
+        &mut self,
+        input: CpuOutputPins,
+        data: &mut u8,
+        _interrupt_request: &mut u8,
+        _ctx: &ClockContext,
+    ) {
+        match input {
+            CpuOutputPins::Read { addr } => match addr {
+                0x0000..=0x3FFF => *data = self.data.read(0, addr),
+                0x4000..=0x7FFF => *data = self.data.read(self.rom_bank as usize, addr),
+
+                // Only 9 address lines are connected, so the RAM repeats through $A200-$BFFF. The
+                // upper 4 data lines aren't driven, and read as 1s.
+                0xA000..=0xBFFF if self.ram_enable => {
+                    *data = 0xF0 | self.ram[addr as usize % RAM_SIZE]
+                }
+                0xA000..=0xBFFF => (),
+                0x8000..=0x9FFF | 0xC000..=0xFFFF => (),
+            },
+            CpuOutputPins::Write { addr, data } => match addr {
+                // Both registers are mapped over the whole of $0000-$3FFF, and address bit 8
+                // selects between them
+                0x0000..=0x3FFF if addr & 0x0100 == 0 => self.ram_enable = data & 0x0F == 0xA,
+                0x0000..=0x3FFF => {
+                    self.rom_bank = match data & 0x0F {
+                        0 => 1,
+                        bank => bank,
+                    }
+                }
+                0x4000..=0x7FFF => (),
+                0xA000..=0xBFFF if self.ram_enable => {
+                    self.ram[addr as usize % RAM_SIZE] = data & 0x0F
+                }
+                0xA000..=0xBFFF => (),
+                0x8000..=0x9FFF | 0xC000..=0xFFFF => (),
+            },
+        }
+    }
+
+    fn chip_select(&self) -> Vec<std::ops::RangeInclusive<u16>> {
+        vec![0x0000..=0x7FFF, 0xA000..=0xBFFF]
+    }
+
+    fn next_event(&self, _ctx: &ClockContext) -> u64 {
+        u64::MAX
+    }
+}
+
+impl Mapper for Mbc2 {
+    fn rom_bank(&self) -> u16 {
+        self.rom_bank as u16
+    }
+
+    fn has_register(&self, addr: u16) -> bool {
+        addr < 0x4000
+    }
+
+    fn peek_rom(&self, addr: u16) -> Option<u8> {
+        let bank = if addr < 0x4000 { 0 } else { self.rom_bank };
+        Some(self.data.read(bank as usize, addr))
+    }
+
+    fn ram(&self) -> Option<&[u8]> {
+        Some(&self.ram)
+    }
+
+    fn ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.ram)
+    }
+
+    fn ram_offset(&self, addr: u16) -> Option<usize> {
+        Some(addr as usize % RAM_SIZE)
+    }
+
+    fn registers(&self) -> [u8; 4] {
+        [self.ram_enable as u8, self.rom_bank, 0, 0]
+    }
+
+    fn set_registers(&mut self, [ram_enable, rom_bank, ..]: [u8; 4]) {
+        self.ram_enable = ram_enable != 0;
+        self.rom_bank = rom_bank;
+    }
+
+    /// The upper nibble of each byte is thrown away, as if it had been written over the bus
+    fn load_ram(&mut self, save: &[u8]) -> Result<(), GbError> {
+        if save.len() != RAM_SIZE {
+            return Err(GbError::InvalidSaveData(
+                "save file size does not match cartridge RAM",
+            ));
+        }
+        for (byte, saved) in self.ram.iter_mut().zip(save) {
+            *byte = saved & 0x0F;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,190 @@
+use crate::gameboy::{rtc::Rtc, rtc::SAVE_FOOTER_LEN, Chip, ClockContext};
+use crate::GbError;
+use gb_cpu::CpuOutputPins;
+
+use super::{Mapper, RomImage};
+
+/// MBC3 can address at most 128 ROM banks
+pub const MAX_SIZE: usize = 0x80 * 0x4000;
+
+/// Every MBC3 cartridge type uses this, with or without RAM and a clock. The battery is handled by
+/// `Cart`.
+pub struct Mbc3 {
+    data: RomImage,
+    /// Empty if the cartridge has no RAM
+    ram: Vec<u8>,
+    rtc: Option<Rtc>,
+
+    ram_enable: bool,
+    rom_bank: u8,
+    /// A RAM bank in $00-$03, or a clock register in $08-$0C
+    ram_select: u8,
+    /// The last byte written to the latch register. The clock is latched when it goes from 0 to 1.
+    latch: u8,
+}
+
+impl Mbc3 {
+    pub fn new(data: RomImage, ram_size: usize, rtc: Option<Rtc>) -> Self {
+        Mbc3 {
+            data,
+            ram: vec![0; ram_size],
+            rtc,
+            ram_enable: false,
+            rom_bank: 1,
+            ram_select: 0,
+            latch: 0xFF,
+        }
+    }
+}
+
+impl Chip for Mbc3 {
+    fn clock(
+        &mut self,
+        input: CpuOutputPins,
+        data: &mut u8,
+        _interrupt_request: &mut u8,
+        ctx: &ClockContext,
+    ) {
+        if let Some(rtc) = self.rtc.as_mut().filter(|_| !ctx.extra_cycle) {
+            rtc.clock();
+        }
+        match input {
+            CpuOutputPins::Read { addr } => match addr {
+                0x0000..=0x3FFF => *data = self.data.read(0, addr),
+                0x4000..=0x7FFF => *data = self.data.read(self.rom_bank as usize, addr),
+
+                // Disabled RAM doesn't drive the bus at all
+                0xA000..=0xBFFF if self.ram_enable => match (self.ram_select, &self.rtc) {
+                    (0x08..=0x0C, Some(rtc)) => *data = rtc.state.latched.read(self.ram_select),
+                    _ => {
+                        if let Some(offset) = self.ram_offset(addr) {
+                            *data = self.ram[offset];
+                        }
+                    }
+                },
+                0xA000..=0xBFFF => (),
+                0x8000..=0x9FFF | 0xC000..=0xFFFF => (),
+            },
+            CpuOutputPins::Write { addr, data } => match addr {
+                0x0000..=0x1FFF => self.ram_enable = data & 0x0F == 0xA,
+                0x2000..=0x3FFF => {
+                    self.rom_bank = match data & 0x7F {
+                        0 => 1,
+                        bank => bank,
+                    }
+                }
+                0x4000..=0x5FFF => self.ram_select = data & 0x0F,
+                0x6000..=0x7FFF => {
+                    if let Some(rtc) = &mut self.rtc {
+                        if self.latch == 0 && data == 1 {
+                            rtc.latch();
+                        }
+                    }
+                    self.latch = data;
+                }
+                0xA000..=0xBFFF if self.ram_enable => match (self.ram_select, &mut self.rtc) {
+                    (0x08..=0x0C, Some(rtc)) => rtc.write(self.ram_select, data),
+                    _ => {
+                        if let Some(offset) = self.ram_offset(addr) {
+                            self.ram[offset] = data;
+                        }
+                    }
+                },
+                0xA000..=0xBFFF => (),
+                0x8000..=0x9FFF | 0xC000..=0xFFFF => (),
+            },
+        }
+    }
+
+    fn chip_select(&self) -> Vec<std::ops::RangeInclusive<u16>> {
+        vec![0x0000..=0x7FFF, 0xA000..=0xBFFF]
+    }
+
+    /// The clock can be switched over to counting emulated cycles through [`Cart`](super::Cart)
+    /// at any time, so a cartridge with one is clocked on every M-cycle
+    fn next_event(&self, ctx: &ClockContext) -> u64 {
+        match self.rtc {
+            Some(_) => ctx.cycles + 4,
+            None => u64::MAX,
+        }
+    }
+}
+
+impl Mapper for Mbc3 {
+    fn rom_bank(&self) -> u16 {
+        self.rom_bank as u16
+    }
+
+    fn ram_bank(&self) -> u8 {
+        self.ram_select
+    }
+
+    fn peek_rom(&self, addr: u16) -> Option<u8> {
+        let bank = if addr < 0x4000 { 0 } else { self.rom_bank };
+        Some(self.data.read(bank as usize, addr))
+    }
+
+    /// A cartridge with a clock but no RAM has empty RAM, so that the clock is still saved
+    fn ram(&self) -> Option<&[u8]> {
+        (!self.ram.is_empty() || self.rtc.is_some()).then_some(&self.ram[..])
+    }
+
+    fn ram_mut(&mut self) -> Option<&mut [u8]> {
+        (!self.ram.is_empty() || self.rtc.is_some()).then_some(&mut self.ram[..])
+    }
+
+    fn rtc(&self) -> Option<&Rtc> {
+        self.rtc.as_ref()
+    }
+
+    fn rtc_mut(&mut self) -> Option<&mut Rtc> {
+        self.rtc.as_mut()
+    }
+
+    fn registers(&self) -> [u8; 4] {
+        [
+            self.ram_enable as u8,
+            self.rom_bank,
+            self.ram_select,
+            self.latch,
+        ]
+    }
+
+    fn set_registers(&mut self, [ram_enable, rom_bank, ram_select, latch]: [u8; 4]) {
+        self.ram_enable = ram_enable != 0;
+        self.rom_bank = rom_bank;
+        self.ram_select = ram_select;
+        self.latch = latch;
+    }
+
+    /// Saves from cartridges with a clock may have the clock's footer on the end, which is used
+    /// to restore it
+    fn load_ram(&mut self, save: &[u8]) -> Result<(), GbError> {
+        if self.ram().is_none() {
+            return Err(GbError::InvalidSaveData("cartridge has no RAM"));
+        }
+        let (ram, footer) = save.split_at(save.len().min(self.ram.len()));
+        if ram.len() != self.ram.len() {
+            return Err(GbError::InvalidSaveData(
+                "save file size does not match cartridge RAM",
+            ));
+        }
+        match &mut self.rtc {
+            _ if footer.is_empty() => (),
+            Some(rtc) if footer.len() <= SAVE_FOOTER_LEN => {
+                if !rtc.load_save_footer(footer) {
+                    return Err(GbError::InvalidSaveData(
+                        "save file has an unknown real time clock format",
+                    ));
+                }
+            }
+            _ => {
+                return Err(GbError::InvalidSaveData(
+                    "save file size does not match cartridge RAM",
+                ))
+            }
+        }
+        self.ram.copy_from_slice(ram);
+        Ok(())
+    }
+}
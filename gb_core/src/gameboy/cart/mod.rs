@@ -1,36 +1,869 @@
+pub mod header;
+pub mod huc1;
+mod lenient;
 mod mbc1;
+mod mbc2;
+mod mbc3;
+pub mod pocket_camera;
 mod rom;
 
-use super::Chip;
+use super::{
+    cheats::RomPatch,
+    events::{Event, EventLog, EventMask},
+    logging,
+    rtc::{Rtc, RtcRegisters, RtcSource, RtcState, RtcTimeSource},
+    Chip, ClockContext,
+};
+use crate::threading::dyn_maybe_send;
+use crate::GbError;
 use gb_cpu::CpuOutputPins;
+use header::CartridgeHeader;
+use huc1::{Huc1, IrPort};
+pub use lenient::HeaderWorkaround;
+use lenient::{AddedRam, ADDED_RAM_SIZE};
 use mbc1::{Mbc1, Mbc1WithBatteryRam, Mbc1WithRam};
+use pocket_camera::PocketCamera;
+use std::{
+    ops::{Range, RangeInclusive},
+    sync::Arc,
+};
 
-trait Mapper: Chip {}
+/// Length of the ROM area that must be present for the cartridge header to be readable
+const HEADER_END: usize = 0x150;
+
+/// Battery-backed cartridge RAM is tracked for changes in blocks of this many bytes
+pub const SAVE_BLOCK_SIZE: usize = 0x100;
+
+/// A block of cartridge RAM that has changed, and its offset into [`Cart::ram`]
+pub type SaveBlock = (usize, [u8; SAVE_BLOCK_SIZE]);
+
+/// A ROM image, which may be shared with other cartridges, read 16KiB bank at a time
+struct RomImage {
+    data: Arc<[u8]>,
+    /// Bank numbers are masked with this, as a cartridge only connects as many bank lines as it
+    /// needs
+    bank_mask: usize,
+}
+
+impl RomImage {
+    fn new(data: Arc<[u8]>) -> Self {
+        let banks = (data.len() + 0x3FFF) / 0x4000;
+        RomImage {
+            bank_mask: banks.next_power_of_two() - 1,
+            data,
+        }
+    }
+
+    /// Read the byte at `offset` into 16KiB bank `bank`. Bank numbers past the end of the image
+    /// wrap around at the next power of two, and anything else missing from a truncated image
+    /// reads as $FF.
+    fn read(&self, bank: usize, offset: u16) -> u8 {
+        let addr = (bank & self.bank_mask) * 0x4000 + (offset & 0x3FFF) as usize;
+        self.data.get(addr).copied().unwrap_or(0xFF)
+    }
+}
+
+trait Mapper: Chip {
+    /// The ROM bank mapped at $4000-$7FFF
+    fn rom_bank(&self) -> u16 {
+        1
+    }
+
+    /// The RAM bank mapped at $A000-$BFFF
+    fn ram_bank(&self) -> u8 {
+        0
+    }
+
+    /// The contents of the cartridge RAM, if the cartridge has any
+    fn ram(&self) -> Option<&[u8]> {
+        None
+    }
+
+    fn ram_mut(&mut self) -> Option<&mut [u8]> {
+        None
+    }
+
+    /// The cartridge's real time clock, if it has one
+    fn rtc(&self) -> Option<&Rtc> {
+        None
+    }
+
+    fn rtc_mut(&mut self) -> Option<&mut Rtc> {
+        None
+    }
+
+    /// The part of [`Mapper::ram`] the mapper has written to on its own since the last call, such
+    /// as a picture from the camera
+    fn take_ram_writes(&mut self) -> Option<Range<usize>> {
+        None
+    }
+
+    /// The Game Boy Camera's sensor, if this is one
+    fn camera_mut(&mut self) -> Option<&mut PocketCamera> {
+        None
+    }
+
+    /// The HuC-1 and its infrared transceiver, if this is one
+    fn huc1_mut(&mut self) -> Option<&mut Huc1> {
+        None
+    }
+
+    /// Where a write to `addr` (in $A000-$BFFF) lands in [`Mapper::ram`], whether or not RAM is
+    /// enabled
+    fn ram_offset(&self, addr: u16) -> Option<usize> {
+        let offset = self.ram_bank() as usize * 0x2000 + (addr - 0xA000) as usize;
+        (offset < self.ram()?.len()).then_some(offset)
+    }
+
+    /// The byte at `addr` (in $0000-$7FFF) with the current banks, or `None` if it can't be read
+    /// without clocking the mapper
+    fn peek_rom(&self, _addr: u16) -> Option<u8> {
+        None
+    }
+
+    /// Whether writing to `addr` (in $0000-$7FFF) sets one of the mapper's registers
+    fn has_register(&self, _addr: u16) -> bool {
+        true
+    }
+
+    /// The mapper's registers, packed into as many bytes as it needs
+    fn registers(&self) -> [u8; 4] {
+        [0; 4]
+    }
+
+    /// Restore registers saved by [`Mapper::registers`]
+    fn set_registers(&mut self, _registers: [u8; 4]) {}
+
+    /// Restore the cartridge RAM from a save file created from [`Mapper::ram`]
+    fn load_ram(&mut self, save: &[u8]) -> Result<(), GbError> {
+        let ram = self
+            .ram_mut()
+            .ok_or(GbError::InvalidSaveData("cartridge has no RAM"))?;
+        if ram.len() != save.len() {
+            return Err(GbError::InvalidSaveData(
+                "save file size does not match cartridge RAM",
+            ));
+        }
+        ram.copy_from_slice(save);
+        Ok(())
+    }
+}
+
+/// Lets an arbitrary chip be plugged into the cartridge slot
+#[cfg(feature = "unstable-internals")]
+struct ExternalCart(Box<dyn_maybe_send!(Chip)>);
+
+#[cfg(feature = "unstable-internals")]
+impl Chip for ExternalCart {
+    fn clock(
+        &mut self,
+        input: CpuOutputPins,
+        data: &mut u8,
+        interrupt_request: &mut u8,
+        ctx: &ClockContext,
+    ) {
+        self.0.clock(input, data, interrupt_request, ctx)
+    }
+
+    fn chip_select(&self) -> Vec<RangeInclusive<u16>> {
+        self.0.chip_select()
+    }
+
+    fn name(&self) -> &'static str {
+        self.0.name()
+    }
+
+    fn next_event(&self, ctx: &ClockContext) -> u64 {
+        self.0.next_event(ctx)
+    }
+}
+
+#[cfg(feature = "unstable-internals")]
+impl Mapper for ExternalCart {}
 
 pub struct Cart {
-    mapper: Box<dyn Mapper + Send>,
+    mapper: Box<dyn_maybe_send!(Mapper)>,
+    /// Game Genie codes, which are applied on top of ROM reads without touching the ROM itself
+    rom_patches: Vec<RomPatch>,
+    /// One flag for each block of battery-backed RAM, set when it changes. Empty if the cartridge
+    /// has no battery.
+    dirty_blocks: Vec<bool>,
+    /// The mapper's [`Mapper::rom_bank`], which is read on every instruction fetch, so it is only
+    /// asked for again after the mapper's registers are written
+    rom_bank: u16,
+    /// The RAM followed by the clock's footer, for cartridges with a real time clock, whose saves
+    /// don't fit in [`Cart::ram`]
+    save: Vec<u8>,
+    /// The mapper's registers when the cartridge was loaded
+    power_on_registers: [u8; 4],
+    /// `None` for cartridges plugged in as chips
+    header: Option<CartridgeHeader>,
+    /// Set for cartridges loaded with [`Cart::lenient`]
+    lenient: Option<Box<Leniency>>,
+    /// How an MBC1 was to be wired, kept for [`HeaderWorkaround::PromotedToMbc1`]
+    mbc1_wiring: Mbc1Wiring,
+    /// The mapper is an MBC1 wired as a multicart
+    multicart: bool,
+    /// The time source the cartridge was loaded with, if the mapper has no clock to give it to
+    rtc_source: Option<Box<dyn_maybe_send!(RtcSource)>>,
+    /// What a clock counts, kept even without a clock so that the next cartridge gets it
+    rtc_time_source: RtcTimeSource,
+    pub(crate) events: EventLog,
+}
+
+/// How an MBC1's bank registers are wired to the ROM. Multicarts (MBC1M), which hold several
+/// games of 256KiB each, leave bit 4 of the lower bank register unconnected and move the upper
+/// bank register down a bit to select the game, but say nothing about it in the header.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Mbc1Wiring {
+    /// Multicart wiring for 1MiB ROMs with a Nintendo logo at the start of another game
+    #[default]
+    Detect,
+    Standard,
+    Multicart,
+}
+
+impl Mbc1Wiring {
+    fn is_multicart(self, rom: &[u8]) -> bool {
+        match self {
+            Mbc1Wiring::Detect => mbc1::is_multicart(rom),
+            Mbc1Wiring::Standard => false,
+            Mbc1Wiring::Multicart => true,
+        }
+    }
+}
+
+/// What [`Cart::lenient`] needs to work around a wrong header
+struct Leniency {
+    /// The ROM image, to make an MBC1 from
+    rom: Arc<[u8]>,
+    /// The workarounds applied so far, in order
+    workarounds: Vec<HeaderWorkaround>,
+}
+
+/// What a cartridge is, going by its header and anything [`Cart::lenient`] has found out since
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CartridgeInfo {
+    /// `None` for cartridges plugged in with `GameboyBuilder::cartridge`
+    pub header: Option<CartridgeHeader>,
+    /// The size of the cartridge RAM in bytes, including any added by
+    /// [`HeaderWorkaround::AddedRam`]
+    pub ram_size: usize,
+    pub has_battery: bool,
+    pub has_rtc: bool,
+    /// The workarounds applied so far, in order
+    pub workarounds: Vec<HeaderWorkaround>,
+    /// The cartridge is an MBC1 wired as a multicart, see [`Mbc1Wiring`]
+    pub multicart: bool,
 }
 
 impl Chip for Cart {
-    fn clock(&mut self, input: CpuOutputPins, data: &mut u8, interrupt_request: &mut u8) {
-        self.mapper.clock(input, data, interrupt_request)
+    fn clock(
+        &mut self,
+        input: CpuOutputPins,
+        data: &mut u8,
+        interrupt_request: &mut u8,
+        ctx: &ClockContext,
+    ) {
+        if self.lenient.is_some() {
+            if let CpuOutputPins::Write { addr, .. } = input {
+                self.work_around_header(addr);
+            }
+        }
+        let ram_write = match input {
+            CpuOutputPins::Write {
+                addr: addr @ 0xA000..=0xBFFF,
+                ..
+            } if !self.dirty_blocks.is_empty() => self.ram_byte(addr),
+            _ => None,
+        };
+        let rtc = self.rtc_registers();
+        self.mapper.clock(input, data, interrupt_request, ctx);
+        // Setting the clock changes the save as much as writing RAM does
+        if let CpuOutputPins::Write {
+            addr: 0xA000..=0xBFFF,
+            ..
+        } = input
+        {
+            if rtc.is_some() && self.rtc_registers() != rtc {
+                self.mark_dirty(0);
+            }
+        }
+        // Whether the write reached RAM depends on the mapper, so look at what it left there
+        if let Some((offset, old)) = ram_write {
+            if self.mapper.ram().map(|ram| ram[offset]) != Some(old) {
+                self.mark_dirty(offset);
+            }
+        }
+        if !self.dirty_blocks.is_empty() {
+            if let Some(written) = self.mapper.take_ram_writes() {
+                for offset in written.step_by(SAVE_BLOCK_SIZE) {
+                    self.mark_dirty(offset);
+                }
+            }
+        }
+        if let CpuOutputPins::Write {
+            addr: 0x0000..=0x7FFF,
+            ..
+        } = input
+        {
+            let (from, to) = (self.rom_bank, self.mapper.rom_bank());
+            if from != to {
+                self.rom_bank = to;
+                log::debug!(target: logging::MAPPER, "ROM bank {} -> {}", from, to);
+                self.events
+                    .emit(EventMask::ROM_BANK_SWITCH, || Event::RomBankSwitch {
+                        from,
+                        to,
+                    });
+            }
+        }
+        if let CpuOutputPins::Read {
+            addr: addr @ 0x0000..=0x7FFF,
+        } = input
+        {
+            for patch in &self.rom_patches {
+                patch.apply(addr, data);
+            }
+        }
+    }
+
+    fn chip_select(&self) -> Vec<RangeInclusive<u16>> {
+        let mut ranges = self.mapper.chip_select();
+        // Listen for writes to RAM that isn't there yet
+        if self.lenient.is_some() && !ranges.iter().any(|range| range.contains(&0xA000)) {
+            ranges.push(0xA000..=0xBFFF);
+        }
+        ranges
+    }
+
+    fn name(&self) -> &'static str {
+        "cart"
+    }
+
+    fn next_event(&self, ctx: &ClockContext) -> u64 {
+        self.mapper.next_event(ctx)
     }
 }
 
 impl Cart {
-    pub fn new(data: Vec<u8>) -> Result<Self, &'static str> {
-        let id = data.get(0x147).ok_or("Invalid ROM file")?;
-        let mapper = mapper_from_id(*id, data);
-        Ok(Cart { mapper })
+    /// Load a ROM image. A real time clock only counts emulated time, from a time source stuck at
+    /// 0.
+    pub fn new(data: impl Into<Arc<[u8]>>) -> Result<Self, GbError> {
+        Self::with_rtc(data, super::rtc::default_source())
+    }
+
+    /// Load a ROM image, using `rtc` as the time source if the cartridge has a real time clock.
+    /// Passing an `Arc<[u8]>` lets any number of cartridges share the same image without copying
+    /// it.
+    pub fn with_rtc(
+        data: impl Into<Arc<[u8]>>,
+        rtc: Box<dyn_maybe_send!(RtcSource)>,
+    ) -> Result<Self, GbError> {
+        Self::load(data.into(), rtc, false, Mbc1Wiring::Detect)
+    }
+
+    /// Load a ROM image like [`Cart::with_rtc`], but work around a header that declares less
+    /// hardware than the game uses, once the game shows what it needs. See [`HeaderWorkaround`]
+    /// for what is worked around, and [`Cart::info`] for what has been so far. Each workaround is
+    /// made before the write that called for it reaches the cartridge, so the write isn't lost.
+    ///
+    /// A ROM larger than 32KiB that declares no mapper loads rather than failing.
+    pub fn lenient(
+        data: impl Into<Arc<[u8]>>,
+        rtc: Box<dyn_maybe_send!(RtcSource)>,
+    ) -> Result<Self, GbError> {
+        Self::load(data.into(), rtc, true, Mbc1Wiring::Detect)
+    }
+
+    /// Load a ROM image like [`Cart::with_rtc`], or like [`Cart::lenient`] if `lenient` is set,
+    /// wiring an MBC1 as `mbc1_wiring` says
+    pub fn load(
+        data: Arc<[u8]>,
+        rtc: Box<dyn_maybe_send!(RtcSource)>,
+        lenient: bool,
+        mbc1_wiring: Mbc1Wiring,
+    ) -> Result<Self, GbError> {
+        Self::load_with(data, &mut Some(rtc), lenient, mbc1_wiring)
+    }
+
+    /// Load a ROM image like [`Cart::load`], but only take `rtc` if the cartridge loads
+    fn load_with(
+        data: Arc<[u8]>,
+        rtc: &mut Option<Box<dyn_maybe_send!(RtcSource)>>,
+        lenient: bool,
+        mbc1_wiring: Mbc1Wiring,
+    ) -> Result<Self, GbError> {
+        if data.len() < HEADER_END {
+            return Err(GbError::InvalidRom("ROM is too short to contain a header"));
+        }
+        let id = data[0x147];
+        let rom_size = rom_size_from_id(data[0x148])?;
+        let ram_size = ram_size_from_id(data[0x149]);
+        let header = CartridgeHeader::parse(&data).ok();
+        let multicart = matches!(id, 1..=3) && mbc1_wiring.is_multicart(&data);
+        if multicart {
+            log::info!(target: logging::MAPPER, "MBC1 is wired as a multicart");
+        }
+        let lenient = lenient.then(|| {
+            Box::new(Leniency {
+                rom: data.clone(),
+                workarounds: Vec::new(),
+            })
+        });
+        let mapper: Box<dyn_maybe_send!(Mapper)> = match id {
+            // Until it turns out which mapper it needs
+            0x00 if lenient.is_some() => Box::new(rom::Rom::new(RomImage::new(data))),
+            _ => mapper_from_id(id, rom_size, ram_size, data, rtc, multicart)?,
+        };
+        let dirty_blocks = match mapper.ram() {
+            // A clock without RAM still needs saving, so gets a block of its own
+            Some(ram) if has_battery(id) => {
+                vec![false; ((ram.len() + SAVE_BLOCK_SIZE - 1) / SAVE_BLOCK_SIZE).max(1)]
+            }
+            _ => Vec::new(),
+        };
+        Ok(Cart {
+            rom_bank: mapper.rom_bank(),
+            save: Vec::new(),
+            power_on_registers: mapper.registers(),
+            mapper,
+            rom_patches: Vec::new(),
+            dirty_blocks,
+            header,
+            lenient,
+            mbc1_wiring,
+            multicart,
+            rtc_source: rtc.take(),
+            rtc_time_source: RtcTimeSource::default(),
+            events: EventLog::default(),
+        })
+    }
+
+    /// Load another ROM image the way this cartridge was loaded: as leniently, with the same MBC1
+    /// wiring, and with the same clock time source and [`RtcTimeSource`], whether or not this
+    /// cartridge has a clock. The time source moves to the new cartridge.
+    ///
+    /// Fails without changing anything if `data` is not a valid cartridge.
+    pub(crate) fn load_another(&mut self, data: Arc<[u8]>) -> Result<Self, GbError> {
+        // This cartridge is replaced if the new one loads, and gets the time source back if not
+        let mut rtc = Some(std::mem::replace(
+            self.rtc_source_mut(),
+            super::rtc::default_source(),
+        ));
+        match Self::load_with(data, &mut rtc, self.is_lenient(), self.mbc1_wiring) {
+            Ok(mut cart) => {
+                cart.set_rtc_time_source(self.rtc_time_source);
+                Ok(cart)
+            }
+            Err(error) => {
+                if let Some(rtc) = rtc {
+                    *self.rtc_source_mut() = rtc;
+                }
+                Err(error)
+            }
+        }
+    }
+
+    /// Where the clock gets the time from, or where the next cartridge's clock would if this one
+    /// has none
+    pub(crate) fn rtc_source_mut(&mut self) -> &mut Box<dyn_maybe_send!(RtcSource)> {
+        match (self.mapper.rtc_mut(), &mut self.rtc_source) {
+            (Some(rtc), _) => rtc.source_mut(),
+            (None, Some(source)) => source,
+            (None, None) => unreachable!("the time source is kept by the cart if not the mapper"),
+        }
+    }
+
+    /// What the cartridge is, and what [`Cart::lenient`] has worked around so far
+    pub fn info(&self) -> CartridgeInfo {
+        CartridgeInfo {
+            header: self.header.clone(),
+            ram_size: self.mapper.ram().map_or(0, <[u8]>::len),
+            has_battery: self.has_battery(),
+            has_rtc: self.has_rtc(),
+            workarounds: self
+                .lenient
+                .as_ref()
+                .map(|lenient| lenient.workarounds.clone())
+                .unwrap_or_default(),
+            multicart: self.multicart,
+        }
+    }
+
+    /// Whether the cartridge was loaded with [`Cart::lenient`]
+    pub fn is_lenient(&self) -> bool {
+        self.lenient.is_some()
+    }
+
+    /// How an MBC1 was to be wired when the cartridge was loaded
+    pub fn mbc1_wiring(&self) -> Mbc1Wiring {
+        self.mbc1_wiring
+    }
+
+    /// Change the hardware to suit a write to `addr`, if it shows the header is wrong. Called
+    /// before the write reaches the mapper, so that it lands in whatever was added.
+    #[cold]
+    fn work_around_header(&mut self, addr: u16) {
+        let lenient = match &mut self.lenient {
+            Some(lenient) => lenient,
+            None => return,
+        };
+        let workaround = match addr {
+            0xA000..=0xBFFF if self.mapper.ram().is_none() => {
+                let placeholder = rom::Rom::new(RomImage::new(lenient.rom.clone()));
+                let mapper = std::mem::replace(&mut self.mapper, Box::new(placeholder));
+                self.mapper = Box::new(AddedRam {
+                    mapper,
+                    ram: vec![0; ADDED_RAM_SIZE].into(),
+                });
+                HeaderWorkaround::AddedRam
+            }
+            0x2000..=0x3FFF
+                if lenient.rom[0x147] == 0x00
+                    && lenient.rom.len() > rom::Rom::MAX_SIZE
+                    && !lenient
+                        .workarounds
+                        .contains(&HeaderWorkaround::PromotedToMbc1) =>
+            {
+                self.multicart = self.mbc1_wiring.is_multicart(&lenient.rom);
+                let mbc1 = Box::new(Mbc1::new(
+                    RomImage::new(lenient.rom.clone()),
+                    self.multicart,
+                ));
+                self.power_on_registers = mbc1.registers();
+                self.mapper = match self.mapper.ram() {
+                    Some(ram) => Box::new(AddedRam {
+                        mapper: mbc1,
+                        ram: ram.into(),
+                    }),
+                    None => mbc1,
+                };
+                HeaderWorkaround::PromotedToMbc1
+            }
+            _ => return,
+        };
+        log::warn!(target: logging::MAPPER, "{}", workaround);
+        lenient.workarounds.push(workaround);
+        self.events.emit(EventMask::HEADER_WORKAROUND, || {
+            Event::HeaderWorkaround(workaround)
+        });
+    }
+
+    /// The contents of the cartridge RAM, or `None` if the cartridge has no RAM
+    pub fn ram(&self) -> Option<&[u8]> {
+        self.mapper.ram()
+    }
+
+    /// Restore the cartridge RAM from a save file created from [`Cart::ram`] or
+    /// [`Cart::take_save`]. The RAM then matches the save, so it is no longer dirty.
+    ///
+    /// For cartridges with a real time clock, the save may end with the clock's footer. If the
+    /// clock follows [`RtcTimeSource::WallClock`], it then catches up on the time since the save
+    /// was made.
+    pub fn load_ram(&mut self, save: &[u8]) -> Result<(), GbError> {
+        self.mapper.load_ram(save)?;
+        self.clear_dirty();
+        Ok(())
+    }
+
+    /// Whether the cartridge RAM is kept by a battery, and so should be saved
+    pub fn has_battery(&self) -> bool {
+        !self.dirty_blocks.is_empty()
+    }
+
+    /// Whether battery-backed RAM has changed since it was last loaded, or taken with
+    /// [`Cart::take_dirty_blocks`] or [`Cart::take_save`]
+    pub fn is_save_dirty(&self) -> bool {
+        self.dirty_blocks.contains(&true)
+    }
+
+    /// The blocks of battery-backed RAM that have changed since the last call, in order, or `None`
+    /// if nothing has. Writing them into the previous save at their offsets brings it up to date.
+    pub fn take_dirty_blocks(&mut self) -> Option<Vec<SaveBlock>> {
+        if !self.is_save_dirty() {
+            return None;
+        }
+        let ram = self.mapper.ram()?;
+        let blocks = self
+            .dirty_blocks
+            .iter()
+            .enumerate()
+            .filter(|(_, &dirty)| dirty)
+            .map(|(i, _)| {
+                let offset = i * SAVE_BLOCK_SIZE;
+                let mut block = [0; SAVE_BLOCK_SIZE];
+                let end = ram.len().min(offset + SAVE_BLOCK_SIZE);
+                block[..end - offset].copy_from_slice(&ram[offset..end]);
+                (offset, block)
+            })
+            .collect();
+        self.clear_dirty();
+        Some(blocks)
+    }
+
+    /// The whole of battery-backed RAM if any of it has changed since it was last taken, or
+    /// `None` if it hasn't. For cartridges with a real time clock, the clock's
+    /// [footer](RtcState::save_footer) follows the RAM.
+    pub fn take_save(&mut self) -> Option<&[u8]> {
+        if !self.is_save_dirty() {
+            return None;
+        }
+        self.clear_dirty();
+        match self.mapper.rtc_mut().map(Rtc::save_footer) {
+            Some(footer) => {
+                self.save.clear();
+                self.save.extend_from_slice(self.mapper.ram()?);
+                self.save.extend_from_slice(&footer);
+                Some(&self.save)
+            }
+            None => self.mapper.ram(),
+        }
+    }
+
+    /// Whether the cartridge has a real time clock
+    pub fn has_rtc(&self) -> bool {
+        self.mapper.rtc().is_some()
+    }
+
+    /// The real time clock's counters, both live and latched, brought up to date. Returns `None`
+    /// if the cartridge has no clock.
+    pub fn rtc_state(&mut self) -> Option<RtcState> {
+        let rtc = self.mapper.rtc_mut()?;
+        rtc.sync();
+        Some(rtc.state)
+    }
+
+    /// Restore a state taken with [`Cart::rtc_state`], as it was when taken. Does nothing if the
+    /// cartridge has no clock.
+    pub fn set_rtc_state(&mut self, state: RtcState) {
+        if let Some(rtc) = self.mapper.rtc_mut() {
+            rtc.restore(state);
+        }
+    }
+
+    /// The real time clock's time source, or `None` if the cartridge has no clock
+    pub fn rtc_time_source(&self) -> Option<RtcTimeSource> {
+        self.mapper.rtc().map(Rtc::time_source)
+    }
+
+    /// Choose what the real time clock counts. This can be changed at any time, such as while
+    /// fast-forwarding. Does nothing if the cartridge has no clock.
+    pub fn set_rtc_time_source(&mut self, time_source: RtcTimeSource) {
+        self.rtc_time_source = time_source;
+        if let Some(rtc) = self.mapper.rtc_mut() {
+            rtc.set_time_source(time_source);
+        }
+    }
+
+    /// Give the Game Boy Camera's sensor a 128x112 grayscale image to take pictures of, from 0 for
+    /// black to 255 for white, row by row. See [`pocket_camera`].
+    pub fn set_camera_image(&mut self, image: &[u8]) -> Result<(), GbError> {
+        self.mapper
+            .camera_mut()
+            .ok_or(GbError::InvalidState("cartridge has no camera"))?
+            .set_image(image)
+    }
+
+    /// Connect the HuC-1's infrared transceiver to `port`, which is told straight away whether the
+    /// LED is on. See [`huc1`].
+    pub fn set_ir_port(&mut self, port: Box<dyn_maybe_send!(IrPort)>) -> Result<(), GbError> {
+        self.mapper
+            .huc1_mut()
+            .ok_or(GbError::InvalidState("cartridge has no infrared port"))?
+            .set_ir_port(port);
+        Ok(())
+    }
+
+    fn rtc_registers(&self) -> Option<[RtcRegisters; 2]> {
+        self.mapper
+            .rtc()
+            .map(|rtc| [rtc.state.live, rtc.state.latched])
+    }
+
+    fn mark_dirty(&mut self, offset: usize) {
+        if !self.is_save_dirty() {
+            log::debug!(target: logging::MAPPER, "battery-backed RAM is dirty");
+        }
+        if let Some(dirty) = self.dirty_blocks.get_mut(offset / SAVE_BLOCK_SIZE) {
+            *dirty = true;
+        }
+    }
+
+    fn clear_dirty(&mut self) {
+        if self.is_save_dirty() {
+            log::debug!(target: logging::MAPPER, "battery-backed RAM is clean");
+        }
+        self.dirty_blocks.fill(false);
+    }
+
+    /// Use `chip` as the cartridge instead of a ROM image, keeping `rtc` for a cartridge swapped
+    /// in later
+    #[cfg(feature = "unstable-internals")]
+    pub fn from_chip(
+        chip: Box<dyn_maybe_send!(Chip)>,
+        rtc: Box<dyn_maybe_send!(RtcSource)>,
+    ) -> Self {
+        let mapper = ExternalCart(chip);
+        Cart {
+            rom_bank: mapper.rom_bank(),
+            save: Vec::new(),
+            power_on_registers: mapper.registers(),
+            mapper: Box::new(mapper),
+            rom_patches: Vec::new(),
+            dirty_blocks: Vec::new(),
+            header: None,
+            lenient: None,
+            mbc1_wiring: Mbc1Wiring::default(),
+            multicart: false,
+            rtc_source: Some(rtc),
+            rtc_time_source: RtcTimeSource::default(),
+            events: EventLog::default(),
+        }
+    }
+
+    /// The ROM bank mapped at $4000-$7FFF. Cartridges without a mapper always have bank 1 there.
+    pub fn rom_bank(&self) -> u16 {
+        self.rom_bank
+    }
+
+    /// The RAM bank mapped at $A000-$BFFF
+    pub fn ram_bank(&self) -> u8 {
+        self.mapper.ram_bank()
+    }
+
+    /// Where a write to `addr` (in $A000-$BFFF) lands in [`Cart::ram`], and the byte it replaces
+    pub(crate) fn ram_byte(&self, addr: u16) -> Option<(usize, u8)> {
+        let offset = self.mapper.ram_offset(addr)?;
+        Some((offset, self.mapper.ram()?[offset]))
+    }
+
+    pub(crate) fn set_ram_byte(&mut self, offset: usize, data: u8) {
+        if let Some(byte) = self.mapper.ram_mut().and_then(|ram| ram.get_mut(offset)) {
+            if *byte != data {
+                *byte = data;
+                self.mark_dirty(offset);
+            }
+        }
+    }
+
+    pub(crate) fn mapper_registers(&self) -> [u8; 4] {
+        self.mapper.registers()
+    }
+
+    pub(crate) fn set_mapper_registers(&mut self, registers: [u8; 4]) {
+        self.mapper.set_registers(registers);
+        self.rom_bank = self.mapper.rom_bank();
+    }
+
+    /// Put the mapper's registers back as they were when the cartridge was loaded. RAM and the
+    /// real time clock run from the cartridge's battery, so they are kept.
+    pub(crate) fn power_cycle(&mut self) {
+        self.set_mapper_registers(self.power_on_registers);
+        self.events.drain().for_each(drop);
+    }
+
+    /// Read a byte of ROM at `addr` (in $0000-$7FFF) as the CPU would see it, without
+    /// disturbing the mapper. Reads as $FF if the cartridge can't be read this way.
+    pub fn peek_rom(&self, addr: u16) -> u8 {
+        let mut data = self.mapper.peek_rom(addr).unwrap_or(0xFF);
+        for patch in &self.rom_patches {
+            patch.apply(addr, &mut data);
+        }
+        data
+    }
+
+    /// Whether writing to `addr` (in $0000-$7FFF) sets one of the mapper's registers, rather than
+    /// being ignored. Cartridges plugged in with `GameboyBuilder::cartridge` are assumed to have
+    /// registers everywhere.
+    pub fn is_mapper_register(&self, addr: u16) -> bool {
+        self.mapper.has_register(addr)
+    }
+
+    pub(crate) fn set_rom_patches(&mut self, patches: Vec<RomPatch>) {
+        self.rom_patches = patches;
+    }
+
+    /// Write to cartridge RAM at `addr` (in $A000-$BFFF). If `bank` is `None`, this is an ordinary
+    /// bus write to the currently mapped bank, which is ignored if RAM is disabled. Otherwise it
+    /// writes straight into that bank, and is ignored if the cartridge has no such bank.
+    pub(crate) fn poke_ram(&mut self, bank: Option<u8>, addr: u16, data: u8) {
+        match bank {
+            None => self.clock(
+                CpuOutputPins::Write { addr, data },
+                &mut 0xFF,
+                &mut 0,
+                &ClockContext::default(),
+            ),
+            Some(bank) => {
+                let offset = bank as usize * 0x2000 + (addr - 0xA000) as usize;
+                self.set_ram_byte(offset, data);
+            }
+        }
+    }
+}
+
+/// Whether the cartridge type in the header has a battery to keep its RAM
+fn has_battery(id: u8) -> bool {
+    matches!(id, 0x03 | 0x06 | 0x0F | 0x10 | 0x13 | 0xFC | 0xFF)
+}
+
+/// Decode the RAM size byte of the cartridge header into a size in bytes. Only MBC3 and HuC-1 use
+/// this; the other mappers have a fixed amount of RAM.
+fn ram_size_from_id(id: u8) -> usize {
+    match id {
+        0x02 => 0x2000,
+        0x03 => 0x8000,
+        0x04 => 0x20000,
+        0x05 => 0x10000,
+        _ => 0,
     }
 }
 
-fn mapper_from_id(id: u8, data: Vec<u8>) -> Box<dyn Mapper + Send> {
+/// Decode the ROM size byte of the cartridge header into a size in bytes
+fn rom_size_from_id(id: u8) -> Result<usize, GbError> {
     match id {
-        0 => Box::new(rom::Rom::new(data)),
-        1 => Box::new(Mbc1::new(data)),
-        2 => Box::new(Mbc1WithRam::new(data)),
-        3 => Box::new(Mbc1WithBatteryRam::new(data)),
-        _ => panic!("Mapper unimplemented: {:#02X}", id),
+        0x00..=0x08 => Ok(0x8000 << id),
+        _ => Err(GbError::InvalidRom("unknown ROM size in header")),
     }
 }
+
+fn mapper_from_id(
+    id: u8,
+    rom_size: usize,
+    ram_size: usize,
+    data: Arc<[u8]>,
+    rtc: &mut Option<Box<dyn_maybe_send!(RtcSource)>>,
+    multicart: bool,
+) -> Result<Box<dyn_maybe_send!(Mapper)>, GbError> {
+    let max_size = match id {
+        0 => rom::Rom::MAX_SIZE,
+        1..=3 => mbc1::MAX_SIZE,
+        5 | 6 => mbc2::MAX_SIZE,
+        0x0F..=0x13 => mbc3::MAX_SIZE,
+        0xFC => pocket_camera::MAX_SIZE,
+        0xFF => huc1::MAX_SIZE,
+        _ => return Err(GbError::UnsupportedMapper(id)),
+    };
+    if rom_size > max_size || data.len() > max_size {
+        return Err(GbError::InvalidRom(
+            "ROM is larger than its mapper can address",
+        ));
+    }
+
+    let rom = RomImage::new(data);
+    Ok(match id {
+        0 => Box::new(rom::Rom::new(rom)),
+        1 => Box::new(Mbc1::new(rom, multicart)),
+        2 => Box::new(Mbc1WithRam::new(rom, multicart)),
+        3 => Box::new(Mbc1WithBatteryRam::new(rom, multicart)),
+        5 | 6 => Box::new(mbc2::Mbc2::new(rom)),
+        0x0F => Box::new(mbc3::Mbc3::new(rom, 0, rtc.take().map(Rtc::new))),
+        0x10 => Box::new(mbc3::Mbc3::new(rom, ram_size, rtc.take().map(Rtc::new))),
+        0x11 => Box::new(mbc3::Mbc3::new(rom, 0, None)),
+        0x12 | 0x13 => Box::new(mbc3::Mbc3::new(rom, ram_size, None)),
+        0xFC => Box::new(PocketCamera::new(rom)),
+        0xFF => Box::new(Huc1::new(rom, ram_size)),
+        _ => unreachable!(),
+    })
+}
@@ -0,0 +1,362 @@
+//! The Game Boy Camera (Pocket Camera in Japan), and the photos it saves.
+//!
+//! The cartridge (type $FC) banks its 1MiB ROM and 128KiB of battery-backed RAM much like MBC3.
+//! Selecting RAM bank $10 or above maps the image sensor's registers over $A000-$BFFF instead.
+//! Setting bit 0 of $A000 takes a picture, which is turned into 2-bit shades using the dithering
+//! thresholds at $A006-$A035, and written to RAM bank 0 at $A100-$AEFF as tiles. Bit 0 reads as 1
+//! until it is done. The sensor sees the image given to
+//! [`Cart::set_camera_image`](super::Cart::set_camera_image), or a gradient if there isn't one.
+//! The gain, edge and voltage registers are stored but don't change the picture, and the exposure
+//! only changes how long it takes.
+//!
+//! [`extract_photos`] decodes the photos saved in the album from a save file or
+//! [`Cart::ram`](super::Cart::ram), without running anything.
+//!
+//! ```
+//! use gb_core::gameboy::cart::pocket_camera::{extract_photos, RAM_SIZE};
+//!
+//! // A camera that has never saved a photo
+//! let mut sram = vec![0; RAM_SIZE];
+//! sram[0x11B2..0x11B2 + 30].fill(0xFF);
+//! assert!(extract_photos(&sram).is_empty());
+//! ```
+
+use std::ops::Range;
+
+use crate::gameboy::{
+    ppu::{color::RgbaColor, frame::Frame, frame::Shade},
+    Chip, ClockContext,
+};
+use crate::GbError;
+use gb_cpu::CpuOutputPins;
+
+use super::{Mapper, RomImage};
+
+/// The camera can address at most 64 ROM banks
+pub const MAX_SIZE: usize = 0x40 * 0x4000;
+
+/// 16 banks of 8KiB
+pub const RAM_SIZE: usize = 0x20000;
+
+pub const PHOTO_WIDTH: usize = 128;
+pub const PHOTO_HEIGHT: usize = 112;
+
+/// Number of photos the album holds
+pub const PHOTO_SLOTS: usize = 30;
+
+/// Where the picture is written when the sensor takes one, in RAM bank 0
+const CAPTURE_OFFSET: usize = 0x0100;
+
+/// One byte for each slot, holding the photo's place in the album, or $FF if the slot is empty
+const SLOT_TABLE_OFFSET: usize = 0x11B2;
+
+/// Each slot takes 4KiB from bank 1 onwards, starting with the photo's tiles
+const SLOT_OFFSET: usize = 0x2000;
+const SLOT_SIZE: usize = 0x1000;
+
+/// 16 by 14 tiles, of 16 bytes each
+const PHOTO_TILE_BYTES: usize = PHOTO_WIDTH * PHOTO_HEIGHT / 4;
+
+/// The trigger, gain, exposure, edge and voltage registers, followed by a 4x4 matrix of 3
+/// thresholds each
+const SENSOR_REGISTERS: usize = 0x36;
+
+/// A photo decoded from the camera's RAM
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Photo {
+    /// The album slot the photo is saved in, or `None` for the picture last taken by the sensor
+    pub slot: Option<u8>,
+    pixels: Box<[Shade]>,
+}
+
+impl Photo {
+    /// Decode the 16 by 14 tiles at the start of `tiles`
+    fn from_tiles(slot: Option<u8>, tiles: &[u8]) -> Self {
+        let mut pixels = vec![0; PHOTO_WIDTH * PHOTO_HEIGHT].into_boxed_slice();
+        for (i, pixel) in pixels.iter_mut().enumerate() {
+            let (offset, bit) = tile_position(i % PHOTO_WIDTH, i / PHOTO_WIDTH);
+            let (low, high) = (tiles[offset], tiles[offset + 1]);
+            *pixel = (low >> bit & 1) | (high >> bit & 1) << 1;
+        }
+        Photo { slot, pixels }
+    }
+
+    /// The shade of the pixel at `(x, y)`
+    ///
+    /// # Panics
+    /// Panics if the coordinates are outside of the photo
+    pub fn shade(&self, x: usize, y: usize) -> Shade {
+        assert!(
+            x < PHOTO_WIDTH && y < PHOTO_HEIGHT,
+            "({}, {}) is outside of the photo",
+            x,
+            y
+        );
+        self.pixels[y * PHOTO_WIDTH + x]
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[Shade; PHOTO_WIDTH]> {
+        self.pixels.array_chunks()
+    }
+
+    /// The color of every pixel, row by row, with each shade shown as its color in `palette`
+    pub fn colors<'a>(
+        &'a self,
+        palette: &'a [RgbaColor; 4],
+    ) -> impl Iterator<Item = RgbaColor> + 'a {
+        self.pixels
+            .iter()
+            .map(move |&shade| palette[shade as usize])
+    }
+
+    /// The photo in the middle of a frame with a border of shade 0, like the camera shows it.
+    /// This can be shown like any other frame, or saved with `capture::encode_png` when the
+    /// `capture` feature is enabled.
+    pub fn to_frame(&self) -> Frame {
+        let (left, top) = ((160 - PHOTO_WIDTH) / 2, (144 - PHOTO_HEIGHT) / 2);
+        let mut frame = Frame::new();
+        for (y, row) in self.rows().enumerate() {
+            frame.row_mut(top + y)[left..left + PHOTO_WIDTH].copy_from_slice(row);
+        }
+        frame
+    }
+}
+
+/// The photos saved in the album, in the order the camera shows them. `sram` is the whole of the
+/// camera's RAM, as in a save file. Slots that don't fit in `sram` are left out.
+pub fn extract_photos(sram: &[u8]) -> Vec<Photo> {
+    let table = match sram.get(SLOT_TABLE_OFFSET..SLOT_TABLE_OFFSET + PHOTO_SLOTS) {
+        Some(table) => table,
+        None => return Vec::new(),
+    };
+    let mut photos: Vec<(u8, Photo)> = table
+        .iter()
+        .enumerate()
+        .filter(|(_, &place)| place != 0xFF)
+        .filter_map(|(slot, &place)| {
+            let offset = SLOT_OFFSET + slot * SLOT_SIZE;
+            let tiles = sram.get(offset..offset + PHOTO_TILE_BYTES)?;
+            Some((place, Photo::from_tiles(Some(slot as u8), tiles)))
+        })
+        .collect();
+    // Sorting is stable, so slots claiming the same place stay in slot order
+    photos.sort_by_key(|(place, _)| *place);
+    photos.into_iter().map(|(_, photo)| photo).collect()
+}
+
+/// The picture the sensor took last, from the whole of the camera's RAM, or `None` if `sram` is
+/// too short to hold it
+pub fn last_capture(sram: &[u8]) -> Option<Photo> {
+    let tiles = sram.get(CAPTURE_OFFSET..CAPTURE_OFFSET + PHOTO_TILE_BYTES)?;
+    Some(Photo::from_tiles(None, tiles))
+}
+
+/// The offset of the pair of bytes holding the pixel at `(x, y)` in a photo's tiles, and its bit
+/// in each of them
+fn tile_position(x: usize, y: usize) -> (usize, usize) {
+    let tile = (y / 8) * (PHOTO_WIDTH / 8) + x / 8;
+    (tile * 16 + (y % 8) * 2, 7 - x % 8)
+}
+
+/// The mapper and image sensor
+pub(super) struct PocketCamera {
+    data: RomImage,
+    ram: Vec<u8>,
+
+    ram_enable: bool,
+    rom_bank: u8,
+    /// A RAM bank in $00-$0F, or the sensor's registers from $10
+    ram_select: u8,
+    sensor: [u8; SENSOR_REGISTERS],
+    /// What the sensor sees, 0 for black to 255 for white
+    image: Box<[u8]>,
+    /// When the picture being taken will be ready, in T-cycles like [`ClockContext::cycles`]
+    capture_end: Option<u64>,
+    /// A picture has been written to RAM since [`Mapper::take_ram_writes`] was last called
+    captured: bool,
+}
+
+impl PocketCamera {
+    pub(super) fn new(data: RomImage) -> Self {
+        let image = (0..PHOTO_WIDTH * PHOTO_HEIGHT)
+            .map(|i| (i % PHOTO_WIDTH * 2) as u8)
+            .collect();
+        PocketCamera {
+            data,
+            ram: vec![0; RAM_SIZE],
+            ram_enable: false,
+            rom_bank: 1,
+            ram_select: 0,
+            sensor: [0; SENSOR_REGISTERS],
+            image,
+            capture_end: None,
+            captured: false,
+        }
+    }
+
+    fn sensor_selected(&self) -> bool {
+        self.ram_select & 0x10 != 0
+    }
+
+    /// M-cycles taken to take a picture, which depends on the exposure time in registers 2 and 3,
+    /// and bit 7 of register 1
+    fn capture_m_cycles(&self) -> u64 {
+        let exposure = u16::from_be_bytes([self.sensor[2], self.sensor[3]]) as u64;
+        let extra = if self.sensor[1] & 0x80 != 0 { 0 } else { 512 };
+        32446 + extra + 16 * exposure
+    }
+
+    /// Dither the image into shades and write it to RAM as tiles
+    fn finish_capture(&mut self) {
+        let tiles = &mut self.ram[CAPTURE_OFFSET..CAPTURE_OFFSET + PHOTO_TILE_BYTES];
+        tiles.fill(0);
+        for (i, &brightness) in self.image.iter().enumerate() {
+            let (x, y) = (i % PHOTO_WIDTH, i / PHOTO_WIDTH);
+            let matrix = 6 + ((y % 4) * 4 + x % 4) * 3;
+            let thresholds = &self.sensor[matrix..matrix + 3];
+            // Darker than more thresholds is a darker shade
+            let shade = thresholds.iter().filter(|&&t| brightness < t).count() as u8;
+            let (offset, bit) = tile_position(x, y);
+            tiles[offset] |= (shade & 1) << bit;
+            tiles[offset + 1] |= (shade >> 1) << bit;
+        }
+        self.capture_end = None;
+        self.captured = true;
+    }
+
+    /// See [`Cart::set_camera_image`](super::Cart::set_camera_image)
+    pub(super) fn set_image(&mut self, image: &[u8]) -> Result<(), GbError> {
+        if image.len() != self.image.len() {
+            return Err(GbError::BufferSize {
+                expected: self.image.len(),
+                actual: image.len(),
+            });
+        }
+        self.image.copy_from_slice(image);
+        Ok(())
+    }
+}
+
+impl Chip for PocketCamera {
+    fn clock(
+        &mut self,
+        input: CpuOutputPins,
+        data: &mut u8,
+        _interrupt_request: &mut u8,
+        ctx: &ClockContext,
+    ) {
+        if matches!(self.capture_end, Some(end) if ctx.cycles >= end) {
+            self.finish_capture();
+        }
+        match input {
+            CpuOutputPins::Read { addr } => match addr {
+                0x0000..=0x3FFF => *data = self.data.read(0, addr),
+                0x4000..=0x7FFF => *data = self.data.read(self.rom_bank as usize, addr),
+
+                // The registers repeat every $80 bytes, and only the first can be read
+                0xA000..=0xBFFF if self.sensor_selected() => {
+                    *data = match (addr - 0xA000) % 0x80 {
+                        0 => self.sensor[0] | self.capture_end.is_some() as u8,
+                        _ => 0x00,
+                    }
+                }
+                // RAM can always be read, and only writing needs enabling
+                0xA000..=0xBFFF => {
+                    if let Some(offset) = self.ram_offset(addr) {
+                        *data = self.ram[offset];
+                    }
+                }
+                0x8000..=0x9FFF | 0xC000..=0xFFFF => (),
+            },
+            CpuOutputPins::Write { addr, data } => match addr {
+                0x0000..=0x1FFF => self.ram_enable = data & 0x0F == 0xA,
+                // Unlike MBC1 and MBC3, bank 0 can be mapped here too
+                0x2000..=0x3FFF => self.rom_bank = data & 0x3F,
+                0x4000..=0x5FFF => self.ram_select = data & 0x1F,
+                0x6000..=0x7FFF => (),
+                0xA000..=0xBFFF if self.sensor_selected() => {
+                    let register = (addr - 0xA000) as usize % 0x80;
+                    match register {
+                        0 => {
+                            self.sensor[0] = data & 0x06;
+                            if data & 0x01 != 0 && self.capture_end.is_none() {
+                                self.capture_end = Some(ctx.cycles + 4 * self.capture_m_cycles());
+                            }
+                        }
+                        1..=0x35 => self.sensor[register] = data,
+                        _ => (),
+                    }
+                }
+                0xA000..=0xBFFF if self.ram_enable => {
+                    if let Some(offset) = self.ram_offset(addr) {
+                        self.ram[offset] = data;
+                    }
+                }
+                0xA000..=0xBFFF => (),
+                0x8000..=0x9FFF | 0xC000..=0xFFFF => (),
+            },
+        }
+    }
+
+    fn chip_select(&self) -> Vec<std::ops::RangeInclusive<u16>> {
+        vec![0x0000..=0x7FFF, 0xA000..=0xBFFF]
+    }
+
+    /// The picture being taken is written to RAM when it is ready, whether or not the game is
+    /// looking at it
+    fn next_event(&self, _ctx: &ClockContext) -> u64 {
+        self.capture_end.unwrap_or(u64::MAX)
+    }
+}
+
+impl Mapper for PocketCamera {
+    fn rom_bank(&self) -> u16 {
+        self.rom_bank as u16
+    }
+
+    fn has_register(&self, addr: u16) -> bool {
+        addr < 0x6000
+    }
+
+    fn ram_bank(&self) -> u8 {
+        self.ram_select
+    }
+
+    fn ram(&self) -> Option<&[u8]> {
+        Some(&self.ram)
+    }
+
+    fn ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.ram)
+    }
+
+    /// Nothing lands in RAM while the sensor's registers are selected
+    fn ram_offset(&self, addr: u16) -> Option<usize> {
+        (!self.sensor_selected())
+            .then(|| self.ram_select as usize * 0x2000 + (addr - 0xA000) as usize)
+    }
+
+    fn take_ram_writes(&mut self) -> Option<Range<usize>> {
+        std::mem::take(&mut self.captured)
+            .then_some(CAPTURE_OFFSET..CAPTURE_OFFSET + PHOTO_TILE_BYTES)
+    }
+
+    fn camera_mut(&mut self) -> Option<&mut PocketCamera> {
+        Some(self)
+    }
+
+    fn peek_rom(&self, addr: u16) -> Option<u8> {
+        let bank = if addr < 0x4000 { 0 } else { self.rom_bank };
+        Some(self.data.read(bank as usize, addr))
+    }
+
+    fn registers(&self) -> [u8; 4] {
+        [self.ram_enable as u8, self.rom_bank, self.ram_select, 0]
+    }
+
+    fn set_registers(&mut self, [ram_enable, rom_bank, ram_select, _]: [u8; 4]) {
+        self.ram_enable = ram_enable != 0;
+        self.rom_bank = rom_bank;
+        self.ram_select = ram_select;
+    }
+}
@@ -1,26 +1,48 @@
 use super::*;
 
 pub struct Rom {
-    pub data: [u8; 0x8000],
+    data: RomImage,
 }
 
 impl Rom {
-    pub fn new(data: Vec<u8>) -> Self {
-        let mut buf = [0; 0x8000];
-        let len = usize::max(data.len(), 0x8000);
-        buf[..len].copy_from_slice(&data[..len]);
-        Self { data: buf }
+    /// The largest ROM image that fits in the unbanked address space
+    pub const MAX_SIZE: usize = 0x8000;
+
+    pub fn new(data: RomImage) -> Self {
+        Self { data }
     }
 }
 
 impl Chip for Rom {
-    fn clock(&mut self, input: CpuOutputPins, data: &mut u8, _interrupt_request: &mut u8) {
+    fn clock(
+        &mut self,
+        input: CpuOutputPins,
+        data: &mut u8,
+        _interrupt_request: &mut u8,
+        _ctx: &ClockContext,
+    ) {
         if let CpuOutputPins::Read {
             addr: addr @ (0x0000..=0x7FFF),
         } = input
         {
-            *data = self.data[addr as usize]
+            *data = self.data.read(addr as usize / 0x4000, addr)
         }
     }
+
+    fn chip_select(&self) -> Vec<std::ops::RangeInclusive<u16>> {
+        vec![0x0000..=0x7FFF]
+    }
+
+    fn next_event(&self, _ctx: &ClockContext) -> u64 {
+        u64::MAX
+    }
+}
+impl Mapper for Rom {
+    fn peek_rom(&self, addr: u16) -> Option<u8> {
+        Some(self.data.read(addr as usize / 0x4000, addr))
+    }
+
+    fn has_register(&self, _addr: u16) -> bool {
+        false
+    }
 }
-impl Mapper for Rom {}
@@ -0,0 +1,238 @@
+//! Game Genie and GameShark cheat codes, and frozen addresses
+
+use std::str::FromStr;
+
+/// Identifies a cheat added with [`Gameboy::add_cheat`](super::Gameboy::add_cheat)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CheatId(u32);
+
+/// Identifies an address frozen with [`Gameboy::freeze`](super::Gameboy::freeze)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FreezeId(u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cheat {
+    /// Replaces reads from cartridge ROM at `address` with `data`. If there is a `compare` byte,
+    /// the read is only replaced when the ROM contains that byte, which picks out one ROM bank.
+    GameGenie {
+        address: u16,
+        data: u8,
+        compare: Option<u8>,
+    },
+    /// Writes `data` to WRAM or cartridge RAM at `address` once per frame. If `bank` is set, the
+    /// write goes to that cartridge RAM bank instead of the one that is currently mapped in.
+    GameShark {
+        bank: Option<u8>,
+        address: u16,
+        data: u8,
+    },
+}
+
+/// Errors produced while parsing a cheat code
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum CheatParseError {
+    #[error("expected 6 or 9 digits for a Game Genie code or 8 for GameShark, found {0}")]
+    InvalidLength(usize),
+    #[error("invalid digit {0:?}")]
+    InvalidDigit(char),
+    /// Game Genie codes can only patch cartridge ROM
+    #[error("Game Genie address is not in ROM: {0:#06X}")]
+    NotRomAddress(u16),
+    /// GameShark codes can only write to WRAM or cartridge RAM
+    #[error("GameShark address is not in RAM: {0:#06X}")]
+    NotRamAddress(u16),
+    #[error("unsupported GameShark bank: {0:#04X}")]
+    UnsupportedBank(u8),
+}
+
+impl FromStr for Cheat {
+    type Err = CheatParseError;
+
+    /// Parse a Game Genie code like `ABC-DEF-GHI` or `ABC-DEF`, or a GameShark code like
+    /// `ABCDEFGH`. Dashes and spaces are ignored.
+    fn from_str(code: &str) -> Result<Self, Self::Err> {
+        let digits = code
+            .chars()
+            .filter(|c| *c != '-' && !c.is_whitespace())
+            .map(|c| {
+                c.to_digit(16)
+                    .map(|d| d as u8)
+                    .ok_or(CheatParseError::InvalidDigit(c))
+            })
+            .collect::<Result<Vec<u8>, _>>()?;
+
+        match digits.len() {
+            6 | 9 => parse_game_genie(&digits),
+            8 => parse_game_shark(&digits),
+            len => Err(CheatParseError::InvalidLength(len)),
+        }
+    }
+}
+
+/// `AB` is the new data, `FCDE` is the address with `F` inverted, and `GI` is the compare byte,
+/// rotated and XORed with $BA. `H` is not used.
+fn parse_game_genie(d: &[u8]) -> Result<Cheat, CheatParseError> {
+    let data = d[0] << 4 | d[1];
+    let address =
+        ((d[5] ^ 0xF) as u16) << 12 | (d[2] as u16) << 8 | (d[3] as u16) << 4 | d[4] as u16;
+    if address >= 0x8000 {
+        return Err(CheatParseError::NotRomAddress(address));
+    }
+    let compare = (d.len() == 9).then(|| (d[6] << 4 | d[8]).rotate_right(2) ^ 0xBA);
+    Ok(Cheat::GameGenie {
+        address,
+        data,
+        compare,
+    })
+}
+
+/// `AB` is the bank, `CD` is the new data, and `GHEF` is the address
+fn parse_game_shark(d: &[u8]) -> Result<Cheat, CheatParseError> {
+    let byte = |i: usize| d[i] << 4 | d[i + 1];
+    let data = byte(2);
+    let address = u16::from_le_bytes([byte(4), byte(6)]);
+    if !(0xA000..=0xDFFF).contains(&address) {
+        return Err(CheatParseError::NotRamAddress(address));
+    }
+    // $00 and $01 write to whichever bank is mapped in, and $8X writes to cartridge RAM bank X
+    let bank = match byte(0) {
+        0x00 | 0x01 => None,
+        b @ 0x80..=0x8F if address <= 0xBFFF => Some(b & 0x0F),
+        b => return Err(CheatParseError::UnsupportedBank(b)),
+    };
+    Ok(Cheat::GameShark {
+        bank,
+        address,
+        data,
+    })
+}
+
+/// A Game Genie code, as seen by the cartridge
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RomPatch {
+    pub address: u16,
+    pub data: u8,
+    pub compare: Option<u8>,
+}
+
+impl RomPatch {
+    /// Apply the patch to `data`, which was read from `address`
+    pub fn apply(&self, address: u16, data: &mut u8) {
+        if address == self.address && self.compare.map_or(true, |c| c == *data) {
+            *data = self.data;
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    id: CheatId,
+    cheat: Cheat,
+    enabled: bool,
+}
+
+/// A value written to `address` once per frame, like a GameShark code without the bank
+#[derive(Debug)]
+struct Freeze {
+    id: FreezeId,
+    address: u16,
+    value: u8,
+}
+
+/// Every cheat that has been added to a [`Gameboy`](super::Gameboy), and every frozen address
+#[derive(Debug, Default)]
+pub(crate) struct Cheats {
+    entries: Vec<Entry>,
+    freezes: Vec<Freeze>,
+    next_id: u32,
+    /// The PPU frame count when GameShark codes were last applied
+    pub applied_frame: u64,
+}
+
+impl Cheats {
+    pub fn add(&mut self, cheat: Cheat) -> CheatId {
+        let id = CheatId(self.next_id);
+        self.next_id += 1;
+        self.entries.push(Entry {
+            id,
+            cheat,
+            enabled: true,
+        });
+        id
+    }
+
+    /// Returns false if there is no cheat with this id
+    pub fn set_enabled(&mut self, id: CheatId, enabled: bool) -> bool {
+        match self.entries.iter_mut().find(|e| e.id == id) {
+            Some(entry) => {
+                entry.enabled = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns false if there is no cheat with this id
+    pub fn remove(&mut self, id: CheatId) -> bool {
+        let len = self.entries.len();
+        self.entries.retain(|e| e.id != id);
+        self.entries.len() != len
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (CheatId, &Cheat, bool)> {
+        self.entries.iter().map(|e| (e.id, &e.cheat, e.enabled))
+    }
+
+    fn enabled(&self) -> impl Iterator<Item = &Cheat> {
+        self.entries.iter().filter(|e| e.enabled).map(|e| &e.cheat)
+    }
+
+    pub fn rom_patches(&self) -> Vec<RomPatch> {
+        self.enabled()
+            .filter_map(|cheat| match *cheat {
+                Cheat::GameGenie {
+                    address,
+                    data,
+                    compare,
+                } => Some(RomPatch {
+                    address,
+                    data,
+                    compare,
+                }),
+                Cheat::GameShark { .. } => None,
+            })
+            .collect()
+    }
+
+    /// `address` must be in WRAM, cartridge RAM or HRAM
+    pub fn freeze(&mut self, address: u16, value: u8) -> FreezeId {
+        let id = FreezeId(self.next_id);
+        self.next_id += 1;
+        self.freezes.push(Freeze { id, address, value });
+        id
+    }
+
+    /// Returns false if there is no frozen address with this id
+    pub fn unfreeze(&mut self, id: FreezeId) -> bool {
+        let len = self.freezes.len();
+        self.freezes.retain(|f| f.id != id);
+        self.freezes.len() != len
+    }
+
+    pub fn frozen(&self) -> impl Iterator<Item = (FreezeId, u16, u8)> + '_ {
+        self.freezes.iter().map(|f| (f.id, f.address, f.value))
+    }
+
+    /// The bank, address and data of each enabled GameShark code, then of each frozen address
+    pub fn ram_writes(&self) -> impl Iterator<Item = (Option<u8>, u16, u8)> + '_ {
+        let codes = self.enabled().filter_map(|cheat| match *cheat {
+            Cheat::GameShark {
+                bank,
+                address,
+                data,
+            } => Some((bank, address, data)),
+            Cheat::GameGenie { .. } => None,
+        });
+        codes.chain(self.freezes.iter().map(|f| (None, f.address, f.value)))
+    }
+}
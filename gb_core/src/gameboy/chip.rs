@@ -0,0 +1,40 @@
+use std::ops::RangeInclusive;
+
+use gb_cpu::CpuOutputPins;
+
+use super::ClockContext;
+
+/// Using this trait makes it easy to clock every chip on the Gameboy independently.
+///
+/// This follows how the bus is emulated, and can change whenever that does, so it is only public
+/// with the `unstable-internals` feature. Frontends shouldn't need it; see
+/// [`prelude`](crate::prelude) instead.
+pub trait Chip {
+    /// Clock by one M-cycle
+    fn clock(
+        &mut self,
+        input: CpuOutputPins,
+        data: &mut u8,
+        interrupt_request: &mut u8,
+        ctx: &ClockContext,
+    );
+
+    /// The addresses this chip responds to. Two chips only claim the same address if the
+    /// Gameboy was built with [`GameboyBuilder::allow_chip_conflicts`](super::GameboyBuilder::allow_chip_conflicts).
+    fn chip_select(&self) -> Vec<RangeInclusive<u16>>;
+
+    /// A short name to tell the chip apart in bus conflict reports
+    fn name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+
+    /// Called after the chip is clocked with `ctx`. Returns the start of the next M-cycle, in
+    /// T-cycles like [`ClockContext::cycles`], that the chip has to be clocked on even if the bus
+    /// doesn't access it, or `u64::MAX` if it only changes when accessed. Until then, the
+    /// [`Gameboy`](super::Gameboy) only clocks it for accesses to the addresses in [`Chip::chip_select`].
+    ///
+    /// By default, the chip is clocked on every M-cycle.
+    fn next_event(&self, ctx: &ClockContext) -> u64 {
+        ctx.cycles + 4
+    }
+}
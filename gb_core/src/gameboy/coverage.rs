@@ -0,0 +1,201 @@
+//! Records which addresses are executed, read and written
+
+use std::{collections::BTreeMap, ops::RangeInclusive};
+
+use gb_cpu::CpuOutputPins;
+
+/// Magic number at the start of [`CoverageSnapshot::to_bytes`]
+pub const EXPORT_MAGIC: &[u8; 8] = b"GBCOVER1";
+
+/// Which bank an address was in when it was accessed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Bank {
+    /// Any address outside of the switchable ROM and cartridge RAM areas
+    Fixed,
+    /// A ROM bank mapped at $4000-$7FFF
+    Rom(u16),
+    /// A cartridge RAM bank mapped at $A000-$BFFF
+    Ram(u8),
+}
+
+impl Bank {
+    fn of(addr: u16, rom_bank: u16, ram_bank: u8) -> Self {
+        match addr {
+            0x4000..=0x7FFF => Bank::Rom(rom_bank),
+            0xA000..=0xBFFF => Bank::Ram(ram_bank),
+            _ => Bank::Fixed,
+        }
+    }
+}
+
+/// One bit for every address
+#[derive(Clone)]
+struct Bitmap(Box<[u64; 0x10000 / 64]>);
+
+impl Bitmap {
+    fn new() -> Self {
+        Bitmap(Box::new([0; 0x10000 / 64]))
+    }
+
+    #[inline]
+    fn set(&mut self, addr: u16) {
+        self.0[addr as usize / 64] |= 1 << (addr % 64);
+    }
+
+    fn get(&self, addr: u16) -> bool {
+        self.0[addr as usize / 64] & 1 << (addr % 64) != 0
+    }
+
+    /// Runs of consecutive set bits
+    fn ranges(&self) -> impl Iterator<Item = RangeInclusive<u16>> + '_ {
+        let mut addr = 0u32;
+        std::iter::from_fn(move || {
+            while addr <= 0xFFFF && !self.get(addr as u16) {
+                addr += 1;
+            }
+            let start = addr;
+            while addr <= 0xFFFF && self.get(addr as u16) {
+                addr += 1;
+            }
+            (start <= 0xFFFF).then(|| start as u16..=(addr - 1) as u16)
+        })
+    }
+
+    fn bytes(&self) -> impl Iterator<Item = u8> + '_ {
+        self.0.iter().flat_map(|word| word.to_le_bytes())
+    }
+}
+
+#[derive(Clone)]
+struct Bitmaps {
+    executed: Bitmap,
+    read: Bitmap,
+    written: Bitmap,
+}
+
+impl Bitmaps {
+    fn new() -> Self {
+        Bitmaps {
+            executed: Bitmap::new(),
+            read: Bitmap::new(),
+            written: Bitmap::new(),
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct CoverageTracker {
+    banks: BTreeMap<Bank, Bitmaps>,
+}
+
+impl CoverageTracker {
+    /// Record the bus activity of one M-cycle. `executing` is true if the CPU is fetching part of
+    /// an instruction.
+    pub fn record(&mut self, pins: CpuOutputPins, executing: bool, rom_bank: u16, ram_bank: u8) {
+        let addr = pins.addr();
+        let maps = self
+            .banks
+            .entry(Bank::of(addr, rom_bank, ram_bank))
+            .or_insert_with(Bitmaps::new);
+        match pins {
+            CpuOutputPins::Read { .. } if executing => {
+                maps.executed.set(addr);
+                maps.read.set(addr);
+            }
+            CpuOutputPins::Read { .. } => maps.read.set(addr),
+            CpuOutputPins::Write { .. } => maps.written.set(addr),
+        }
+    }
+
+    pub fn snapshot(&self) -> CoverageSnapshot {
+        CoverageSnapshot {
+            banks: self.banks.clone(),
+        }
+    }
+}
+
+/// The addresses accessed since coverage tracking was enabled, from
+/// [`Gameboy::coverage`](super::Gameboy::coverage)
+#[derive(Clone, Default)]
+pub struct CoverageSnapshot {
+    banks: BTreeMap<Bank, Bitmaps>,
+}
+
+impl std::fmt::Debug for CoverageSnapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CoverageSnapshot")
+            .field("banks", &self.banks.keys())
+            .finish_non_exhaustive()
+    }
+}
+
+impl CoverageSnapshot {
+    fn ranges<'a>(
+        &'a self,
+        bitmap: impl Fn(&Bitmaps) -> &Bitmap + 'a,
+    ) -> impl Iterator<Item = (Bank, RangeInclusive<u16>)> + 'a {
+        self.banks
+            .iter()
+            .flat_map(move |(&bank, maps)| bitmap(maps).ranges().map(move |range| (bank, range)))
+    }
+
+    fn contains(&self, bank: Bank, addr: u16, bitmap: impl Fn(&Bitmaps) -> &Bitmap) -> bool {
+        self.banks
+            .get(&bank)
+            .map_or(false, |maps| bitmap(maps).get(addr))
+    }
+
+    /// Runs of consecutive addresses that were executed as part of an instruction, sorted by bank
+    /// and then by address
+    pub fn executed_ranges(&self) -> impl Iterator<Item = (Bank, RangeInclusive<u16>)> + '_ {
+        self.ranges(|maps| &maps.executed)
+    }
+
+    /// Runs of consecutive addresses that were read, including instruction fetches. The CPU puts
+    /// $0000 on the bus during internal cycles, so it always shows up as read.
+    pub fn read_ranges(&self) -> impl Iterator<Item = (Bank, RangeInclusive<u16>)> + '_ {
+        self.ranges(|maps| &maps.read)
+    }
+
+    /// Runs of consecutive addresses that were written
+    pub fn written_ranges(&self) -> impl Iterator<Item = (Bank, RangeInclusive<u16>)> + '_ {
+        self.ranges(|maps| &maps.written)
+    }
+
+    pub fn is_executed(&self, bank: Bank, addr: u16) -> bool {
+        self.contains(bank, addr, |maps| &maps.executed)
+    }
+
+    pub fn is_read(&self, bank: Bank, addr: u16) -> bool {
+        self.contains(bank, addr, |maps| &maps.read)
+    }
+
+    pub fn is_written(&self, bank: Bank, addr: u16) -> bool {
+        self.contains(bank, addr, |maps| &maps.written)
+    }
+
+    /// Export the snapshot for use in other tools. The format is [`EXPORT_MAGIC`], then a
+    /// little-endian `u16` count of banks, then for each bank:
+    ///
+    /// * a kind byte: 0 for [`Bank::Fixed`], 1 for [`Bank::Rom`] or 2 for [`Bank::Ram`]
+    /// * the bank number as a little-endian `u16`, which is 0 for [`Bank::Fixed`]
+    /// * the executed, read and written bitmaps, 8KiB each, covering $0000-$FFFF. Bit `n % 8` of
+    ///   byte `n / 8` is set if address `n` was accessed.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = EXPORT_MAGIC.to_vec();
+        bytes.extend((self.banks.len() as u16).to_le_bytes());
+        for (bank, maps) in &self.banks {
+            let (kind, number) = match *bank {
+                Bank::Fixed => (0, 0),
+                Bank::Rom(n) => (1, n),
+                Bank::Ram(n) => (2, n as u16),
+            };
+            bytes.push(kind);
+            bytes.extend(number.to_le_bytes());
+            for bitmap in [&maps.executed, &maps.read, &maps.written] {
+                bytes.extend(bitmap.bytes());
+            }
+        }
+        bytes
+    }
+}
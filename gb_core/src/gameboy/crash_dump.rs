@@ -0,0 +1,193 @@
+//! Post-mortems for games that lock up. The addresses of the last [`PC_HISTORY_LEN`] instructions
+//! are always recorded, and [`Gameboy::crash_dump`](super::Gameboy::crash_dump) collects them
+//! with the rest of the machine state into a [`CrashDump`], which prints as a readable report.
+//!
+//! A dump is taken automatically when the CPU locks up on an illegal opcode, and kept in
+//! [`Gameboy::last_crash`](super::Gameboy::last_crash).
+
+use std::{fmt, sync::Arc};
+
+use gb_cpu::Registers;
+
+use super::symbols::SymbolTable;
+
+/// How many instructions are kept in the PC history
+pub const PC_HISTORY_LEN: usize = 256;
+
+/// How many bytes of memory are shown around SP and PC
+pub const MEMORY_WINDOW_LEN: usize = 64;
+
+/// An instruction that was executed
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PcRecord {
+    /// The address of its opcode
+    pub pc: u16,
+    /// The ROM bank mapped at $4000-$7FFF when it was fetched
+    pub bank: u16,
+}
+
+impl fmt::Display for PcRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The bank only matters for the switchable area
+        if (0x4000..0x8000).contains(&self.pc) {
+            write!(f, "{:02X}:{:04X}", self.bank, self.pc)
+        } else {
+            write!(f, "   {:04X}", self.pc)
+        }
+    }
+}
+
+/// The last [`PC_HISTORY_LEN`] instructions fetched. Recording one is a store and an increment.
+#[derive(Clone)]
+pub(crate) struct PcHistory {
+    records: [PcRecord; PC_HISTORY_LEN],
+    /// Instructions recorded since power on. The next record goes at this index, wrapped.
+    count: u64,
+}
+
+impl PcHistory {
+    #[inline(always)]
+    pub fn record(&mut self, pc: u16, bank: u16) {
+        self.records[self.count as u8 as usize] = PcRecord { pc, bank };
+        self.count += 1;
+    }
+
+    /// The instruction fetched last
+    pub fn last(&self) -> Option<PcRecord> {
+        let index = self.count.checked_sub(1)?;
+        Some(self.records[index as u8 as usize])
+    }
+
+    /// The recorded instructions, oldest first
+    pub fn iter(&self) -> impl Iterator<Item = PcRecord> + '_ {
+        let len = self.count.min(PC_HISTORY_LEN as u64) as usize;
+        let start = self.count as usize - len;
+        (start..start + len).map(move |i| self.records[i % PC_HISTORY_LEN])
+    }
+}
+
+impl Default for PcHistory {
+    fn default() -> Self {
+        PcHistory {
+            records: [PcRecord::default(); PC_HISTORY_LEN],
+            count: 0,
+        }
+    }
+}
+
+/// [`MEMORY_WINDOW_LEN`] bytes centred on an address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryWindow {
+    /// The address the window is centred on
+    pub center: u16,
+    /// The address of `bytes[0]`. The window wraps around at the ends of memory.
+    pub start: u16,
+    pub bytes: [u8; MEMORY_WINDOW_LEN],
+}
+
+impl MemoryWindow {
+    pub(crate) fn around(center: u16, peek: impl Fn(u16) -> u8) -> Self {
+        let start = center.wrapping_sub(MEMORY_WINDOW_LEN as u16 / 2);
+        let mut bytes = [0; MEMORY_WINDOW_LEN];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = peek(start.wrapping_add(i as u16));
+        }
+        MemoryWindow {
+            center,
+            start,
+            bytes,
+        }
+    }
+}
+
+/// Rows of 16 bytes, with the centre byte in brackets
+impl fmt::Display for MemoryWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (row, chunk) in self.bytes.chunks(16).enumerate() {
+            let addr = self.start.wrapping_add(row as u16 * 16);
+            write!(f, "  {:04X}:", addr)?;
+            for (i, byte) in chunk.iter().enumerate() {
+                if addr.wrapping_add(i as u16) == self.center {
+                    write!(f, "[{:02X}]", byte)?;
+                } else {
+                    write!(f, " {:02X} ", byte)?;
+                }
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// The state of the machine when it crashed, or when the dump was asked for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrashDump {
+    /// T-cycles since power on
+    pub cycle: u64,
+    /// The last instructions executed, oldest first
+    pub history: Vec<PcRecord>,
+    pub registers: Registers,
+    pub ime: bool,
+    pub stack: MemoryWindow,
+    /// Centred on the opcode fetched last
+    pub code: MemoryWindow,
+    pub interrupt_enable: u8,
+    pub interrupt_request: u8,
+    /// The mode in the lower 2 bits of STAT
+    pub ppu_mode: u8,
+    pub ly: u8,
+    /// The ROM bank mapped at $4000-$7FFF
+    pub rom_bank: u16,
+    /// The RAM bank mapped at $A000-$BFFF
+    pub ram_bank: u8,
+    /// The symbols loaded when the dump was taken, which name the instructions in the report
+    pub symbols: Arc<SymbolTable>,
+}
+
+impl fmt::Display for CrashDump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let r = &self.registers;
+        writeln!(f, "Crash dump at cycle {}", self.cycle)?;
+        writeln!(
+            f,
+            "AF={:04X} BC={:04X} DE={:04X} HL={:04X} SP={:04X} PC={:04X} IME={}",
+            r.get_af(),
+            r.get_bc(),
+            r.get_de(),
+            r.get_hl(),
+            r.sp,
+            r.pc,
+            self.ime as u8
+        )?;
+        writeln!(
+            f,
+            "IE={:02X} IF={:02X} LY={} mode {} ROM bank {:02X} RAM bank {:02X}",
+            self.interrupt_enable,
+            self.interrupt_request,
+            self.ly,
+            self.ppu_mode,
+            self.rom_bank,
+            self.ram_bank
+        )?;
+        writeln!(f, "\nCode around the last instruction:")?;
+        write!(f, "{}", self.code)?;
+        writeln!(f, "\nStack around SP:")?;
+        write!(f, "{}", self.stack)?;
+        writeln!(
+            f,
+            "\nLast {} instructions, newest last:",
+            self.history.len()
+        )?;
+        if !self.symbols.is_empty() {
+            for record in &self.history {
+                writeln!(f, "  {}", self.symbols.location(record.bank, record.pc))?;
+            }
+            return Ok(());
+        }
+        for row in self.history.chunks(8) {
+            let row: Vec<String> = row.iter().map(|record| record.to_string()).collect();
+            writeln!(f, "  {}", row.join("  "))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,78 @@
+//! The `LD B,B` and `LD D,D` conventions that test ROMs and emulator-aware homebrew use to talk to
+//! the emulator.
+//!
+//! Neither instruction does anything, so games are free to use them for their own reasons, and
+//! they are only treated as signals once a handler is set with
+//! [`Gameboy::set_debug_opcode_handler`](super::Gameboy::set_debug_opcode_handler):
+//!
+//! - `LD B,B` is a software breakpoint, which mooneye-style test ROMs execute when they finish,
+//!   with the result in the registers.
+//! - `LD D,D` prints a debug message, a NUL-terminated string that HL points to.
+
+use gb_cpu::Registers;
+
+use crate::threading::dyn_maybe_send;
+
+/// `LD B,B`
+pub const BREAKPOINT_OPCODE: u8 = 0x40;
+/// `LD D,D`
+pub const MESSAGE_OPCODE: u8 = 0x52;
+
+/// The longest message read for `LD D,D`, in bytes. Longer messages are cut short.
+pub const MAX_MESSAGE_LEN: usize = 256;
+
+/// Which convention an instruction follows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugOpcode {
+    /// `LD B,B`
+    Breakpoint,
+    /// `LD D,D`
+    Message,
+}
+
+/// A debug opcode that has just been fetched, before it runs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DebugOpcodeEvent {
+    pub opcode: DebugOpcode,
+    /// Where the opcode was fetched from
+    pub pc: u16,
+    /// The registers as the instruction found them
+    pub registers: Registers,
+    /// For `LD D,D`, the string HL points to, up to the NUL or [`MAX_MESSAGE_LEN`] bytes, whichever
+    /// comes first. Bytes that aren't UTF-8 are replaced with U+FFFD.
+    pub message: Option<String>,
+}
+
+/// What the emulator does after a handler has seen a [`DebugOpcodeEvent`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugOpcodeAction {
+    /// Carry on. This is also the answer for instructions that weren't meant as a signal.
+    Continue,
+    /// Stop [`Gameboy::run_until_breakpoint`](super::Gameboy::run_until_breakpoint), which
+    /// returns [`BreakpointId::DEBUG_OPCODE`](super::breakpoints::BreakpointId::DEBUG_OPCODE)
+    Break,
+}
+
+/// Called with each debug opcode fetched, once set with
+/// [`Gameboy::set_debug_opcode_handler`](super::Gameboy::set_debug_opcode_handler)
+// The bound is in brackets so that the `Send` added by `dyn_maybe_send!` isn't taken as part of the
+// return type
+pub type DebugOpcodeHandler = Box<dyn_maybe_send!((FnMut(DebugOpcodeEvent) -> DebugOpcodeAction))>;
+
+/// The convention `opcode` follows, if it is one of the debug opcodes
+pub(crate) fn decode(opcode: u8) -> Option<DebugOpcode> {
+    match opcode {
+        BREAKPOINT_OPCODE => Some(DebugOpcode::Breakpoint),
+        MESSAGE_OPCODE => Some(DebugOpcode::Message),
+        _ => None,
+    }
+}
+
+/// Read the NUL-terminated string at `addr` with `peek`
+pub(crate) fn read_message(addr: u16, peek: impl Fn(u16) -> u8) -> String {
+    let bytes: Vec<u8> = (0..MAX_MESSAGE_LEN as u16)
+        .map(|i| peek(addr.wrapping_add(i)))
+        .take_while(|&byte| byte != 0)
+        .collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
@@ -0,0 +1,334 @@
+//! A stream of emulator events, for tooling such as debuggers, profilers and trace viewers.
+//!
+//! Subscribe with [`Gameboy::subscribe`](super::Gameboy::subscribe) or
+//! [`Gameboy::subscribe_callback`](super::Gameboy::subscribe_callback). When nothing is subscribed,
+//! each place that could emit an event costs a single branch.
+
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    mpsc::{self, Receiver, SyncSender, TryRecvError, TrySendError},
+    Arc,
+};
+
+use bitflags::bitflags;
+
+use super::{cart::HeaderWorkaround, violations::Violation};
+use crate::threading::dyn_maybe_send;
+
+bitflags! {
+    /// The kinds of [`Event`] a subscriber wants to receive
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct EventMask: u16 {
+        const INTERRUPT_RAISED = 0x01;
+        const INTERRUPT_SERVICED = 0x02;
+        const PPU_MODE_CHANGE = 0x04;
+        const ROM_BANK_SWITCH = 0x08;
+        const OAM_DMA_START = 0x10;
+        const SERIAL_BYTE = 0x20;
+        const DISPLAY = 0x40;
+        const BUS_CONFLICT = 0x80;
+        const VIOLATION = 0x100;
+        const HEADER_WORKAROUND = 0x200;
+        const ORDERING_HAZARD = 0x400;
+    }
+}
+
+/// The interrupt sources, in order of priority
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interrupt {
+    VBlank,
+    Stat,
+    Timer,
+    Serial,
+    Joypad,
+}
+
+impl Interrupt {
+    const ALL: [Interrupt; 5] = [
+        Interrupt::VBlank,
+        Interrupt::Stat,
+        Interrupt::Timer,
+        Interrupt::Serial,
+        Interrupt::Joypad,
+    ];
+
+    /// The bit of IE and IF for this interrupt
+    pub fn bit(self) -> u8 {
+        1 << self as u8
+    }
+
+    /// Address of the interrupt handler
+    pub fn vector(self) -> u16 {
+        0x40 + 8 * self as u16
+    }
+
+    /// Every interrupt whose bit is set in `flags`
+    pub(crate) fn from_flags(flags: u8) -> impl Iterator<Item = Interrupt> {
+        IntoIterator::into_iter(Self::ALL).filter(move |i| flags & i.bit() != 0)
+    }
+}
+
+/// Changes to what the screen shows, for frontends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisplayEvent {
+    /// LCDC bit 7 was cleared. The front frame has been replaced with a blank one, which stays
+    /// until the LCD is turned back on.
+    LcdDisabled,
+    /// LCDC bit 7 was set again. The next frame is drawn as usual.
+    LcdEnabled,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// A chip set a bit in IF
+    InterruptRaised(Interrupt),
+    /// The CPU jumped to an interrupt handler at `vector`, interrupting the instruction at `pc`
+    InterruptServiced {
+        vector: u16,
+        pc: u16,
+    },
+    /// The PPU entered `mode` on line `ly`, `dot` T-cycles into the line
+    PpuModeChange {
+        ly: u8,
+        mode: u8,
+        dot: u16,
+    },
+    /// The ROM bank mapped at $4000-$7FFF changed
+    RomBankSwitch {
+        from: u16,
+        to: u16,
+    },
+    /// An OAM DMA transfer from `source` started
+    OamDmaStart {
+        source: u16,
+    },
+    /// A byte was shifted out of the serial port
+    SerialByte(u8),
+    Display(DisplayEvent),
+    /// More than one chip answered an access to `addr`. `chips` are the first two to claim it, in
+    /// the order chips are clocked. Only reported in debug builds, or with the `strict-bus`
+    /// feature.
+    BusConflict {
+        addr: u16,
+        chips: [&'static str; 2],
+    },
+    /// Code did something that is almost always a bug. Only reported once enabled, see
+    /// [`violations`](super::violations).
+    Violation(Violation),
+    /// The cartridge's header turned out to be wrong, and was worked around. Only happens with
+    /// [`GameboyBuilder::lenient_header`](super::GameboyBuilder::lenient_header).
+    HeaderWorkaround(HeaderWorkaround),
+    /// The CPU wrote `value` to the PPU register at `addr` in the same M-cycle as the PPU used it,
+    /// at `dot` of line `ly`, so the order the two happen in decides what is drawn. Only reported
+    /// with [`Gameboy::set_ordering_check`](super::Gameboy::set_ordering_check).
+    OrderingHazard {
+        addr: u16,
+        value: u8,
+        ly: u8,
+        dot: u16,
+    },
+}
+
+impl Event {
+    pub fn kind(&self) -> EventMask {
+        match self {
+            Event::InterruptRaised(_) => EventMask::INTERRUPT_RAISED,
+            Event::InterruptServiced { .. } => EventMask::INTERRUPT_SERVICED,
+            Event::PpuModeChange { .. } => EventMask::PPU_MODE_CHANGE,
+            Event::RomBankSwitch { .. } => EventMask::ROM_BANK_SWITCH,
+            Event::OamDmaStart { .. } => EventMask::OAM_DMA_START,
+            Event::SerialByte(_) => EventMask::SERIAL_BYTE,
+            Event::Display(_) => EventMask::DISPLAY,
+            Event::BusConflict { .. } => EventMask::BUS_CONFLICT,
+            Event::Violation(_) => EventMask::VIOLATION,
+            Event::HeaderWorkaround(_) => EventMask::HEADER_WORKAROUND,
+            Event::OrderingHazard { .. } => EventMask::ORDERING_HAZARD,
+        }
+    }
+}
+
+/// An [`Event`], and the value of [`Gameboy::cycles`](super::Gameboy::cycles) at the start of the
+/// M-cycle it happened in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EventRecord {
+    pub cycle: u64,
+    pub event: Event,
+}
+
+/// Identifies a subscription, so that it can be cancelled with
+/// [`Gameboy::unsubscribe`](super::Gameboy::unsubscribe)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SubscriptionId(u32);
+
+/// Receives events from a bounded queue created by [`Gameboy::subscribe`](super::Gameboy::subscribe).
+/// Dropping it cancels the subscription.
+#[derive(Debug)]
+pub struct EventReceiver {
+    id: SubscriptionId,
+    receiver: Receiver<EventRecord>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl EventReceiver {
+    pub fn id(&self) -> SubscriptionId {
+        self.id
+    }
+
+    /// Take the oldest queued event, if there is one
+    pub fn try_recv(&self) -> Option<EventRecord> {
+        match self.receiver.try_recv() {
+            Ok(record) => Some(record),
+            Err(TryRecvError::Empty | TryRecvError::Disconnected) => None,
+        }
+    }
+
+    /// Take every queued event
+    pub fn drain(&self) -> impl Iterator<Item = EventRecord> + '_ {
+        self.receiver.try_iter()
+    }
+
+    /// Number of events that were thrown away because the queue was full
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+pub type EventCallback = Box<dyn_maybe_send!(FnMut(&EventRecord))>;
+
+enum Sink {
+    Callback(EventCallback),
+    Queue {
+        sender: SyncSender<EventRecord>,
+        dropped: Arc<AtomicU64>,
+    },
+}
+
+struct Subscriber {
+    id: SubscriptionId,
+    mask: EventMask,
+    sink: Sink,
+}
+
+/// Every subscriber attached to a [`Gameboy`](super::Gameboy)
+#[derive(Default)]
+pub(crate) struct EventBus {
+    subscribers: Vec<Subscriber>,
+    next_id: u32,
+    /// Every kind of event that somebody is subscribed to
+    mask: EventMask,
+}
+
+impl EventBus {
+    fn add(&mut self, mask: EventMask, sink: Sink) -> SubscriptionId {
+        let id = SubscriptionId(self.next_id);
+        self.next_id += 1;
+        self.subscribers.push(Subscriber { id, mask, sink });
+        self.update_mask();
+        id
+    }
+
+    fn update_mask(&mut self) {
+        self.mask = self
+            .subscribers
+            .iter()
+            .fold(EventMask::empty(), |mask, s| mask | s.mask);
+    }
+
+    pub fn subscribe(&mut self, mask: EventMask, capacity: usize) -> EventReceiver {
+        let (sender, receiver) = mpsc::sync_channel(capacity);
+        let dropped = Arc::new(AtomicU64::new(0));
+        let id = self.add(
+            mask,
+            Sink::Queue {
+                sender,
+                dropped: dropped.clone(),
+            },
+        );
+        EventReceiver {
+            id,
+            receiver,
+            dropped,
+        }
+    }
+
+    pub fn subscribe_callback(
+        &mut self,
+        mask: EventMask,
+        callback: EventCallback,
+    ) -> SubscriptionId {
+        self.add(mask, Sink::Callback(callback))
+    }
+
+    /// Returns false if there is no subscription with this id
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        let len = self.subscribers.len();
+        self.subscribers.retain(|s| s.id != id);
+        self.update_mask();
+        self.subscribers.len() != len
+    }
+
+    /// Every kind of event that somebody is subscribed to
+    #[inline(always)]
+    pub fn mask(&self) -> EventMask {
+        self.mask
+    }
+
+    /// Send `record` to everyone who is subscribed to it. Subscriptions whose receiver has been
+    /// dropped are removed, which may change [`EventBus::mask`].
+    pub fn publish(&mut self, record: EventRecord) {
+        let kind = record.event.kind();
+        let mut connected = true;
+        self.subscribers.retain_mut(|s| {
+            if !s.mask.contains(kind) {
+                return true;
+            }
+            match &mut s.sink {
+                Sink::Callback(callback) => callback(&record),
+                Sink::Queue { sender, dropped } => match sender.try_send(record) {
+                    Ok(()) => (),
+                    Err(TrySendError::Full(_)) => {
+                        dropped.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Err(TrySendError::Disconnected(_)) => {
+                        connected = false;
+                        return false;
+                    }
+                },
+            }
+            true
+        });
+        if !connected {
+            self.update_mask();
+        }
+    }
+}
+
+/// Collects events inside a chip until the [`Gameboy`](super::Gameboy) publishes them
+#[derive(Debug, Default)]
+pub(crate) struct EventLog {
+    mask: EventMask,
+    pending: Vec<Event>,
+}
+
+impl EventLog {
+    pub fn set_mask(&mut self, mask: EventMask) {
+        self.mask = mask;
+    }
+
+    #[inline(always)]
+    pub fn enabled(&self, kind: EventMask) -> bool {
+        self.mask.intersects(kind)
+    }
+
+    /// Record the event made by `event` if anyone is subscribed to `kind`
+    #[inline(always)]
+    pub fn emit(&mut self, kind: EventMask, event: impl FnOnce() -> Event) {
+        if self.enabled(kind) {
+            self.pending.push(event());
+        }
+    }
+
+    pub fn drain(&mut self) -> impl Iterator<Item = Event> + '_ {
+        self.pending.drain(..)
+    }
+}
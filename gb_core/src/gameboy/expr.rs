@@ -0,0 +1,446 @@
+//! Small expressions over the registers and memory, for watch windows and conditional
+//! breakpoints.
+//!
+//! ```text
+//! [$C345] > 8 && A == 0
+//! word[HL + 2] & 0x8000 != 0
+//! ```
+//!
+//! Values are 16 bits wide, and arithmetic wraps. Numbers are decimal, or hex with a `0x` or `$`
+//! prefix. Registers are `A`, `F`, `B`, `C`, `D`, `E`, `H`, `L`, `AF`, `BC`, `DE`, `HL`, `SP` and
+//! `PC`, in any case, where `PC` is the address of the instruction being executed. `[addr]` reads a
+//! byte and `word[addr]` reads a little-endian word, through [`Gameboy::peek`], so banked memory is
+//! read from the bank that is mapped in.
+//!
+//! From lowest to highest precedence, the operators are `||`, `&&`, the comparisons (`==` `!=` `<`
+//! `<=` `>` `>=`), `|`, `^`, `&`, `<<` `>>`, `+` `-`, and the unary `!` `~` `-`. Like Rust and unlike
+//! C, the bitwise operators bind tighter than comparisons, so `F & 0x80 != 0` tests the zero flag.
+//! Comparisons and `!`, `&&` and `||` produce 1 for true and 0 for false, and treat any
+//! non-zero value as true.
+
+use std::str::FromStr;
+
+use super::Gameboy;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    A,
+    F,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    AF,
+    BC,
+    DE,
+    HL,
+    SP,
+    PC,
+}
+
+impl Register {
+    fn from_name(name: &str) -> Option<Self> {
+        use Register::*;
+        let register = match name.to_ascii_uppercase().as_str() {
+            "A" => A,
+            "F" => F,
+            "B" => B,
+            "C" => C,
+            "D" => D,
+            "E" => E,
+            "H" => H,
+            "L" => L,
+            "AF" => AF,
+            "BC" => BC,
+            "DE" => DE,
+            "HL" => HL,
+            "SP" => SP,
+            "PC" => PC,
+            _ => return None,
+        };
+        Some(register)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    /// `!`
+    Not,
+    /// `~`
+    Complement,
+    /// `-`
+    Negate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Shl,
+    Shr,
+    Add,
+    Sub,
+}
+
+impl BinaryOp {
+    /// Higher binds tighter
+    fn precedence(self) -> u8 {
+        use BinaryOp::*;
+        match self {
+            Or => 1,
+            And => 2,
+            Eq | Ne | Lt | Le | Gt | Ge => 3,
+            BitOr => 4,
+            BitXor => 5,
+            BitAnd => 6,
+            Shl | Shr => 7,
+            Add | Sub => 8,
+        }
+    }
+
+    fn apply(self, l: u16, r: u16) -> u16 {
+        use BinaryOp::*;
+        match self {
+            Or => (l != 0 || r != 0) as u16,
+            And => (l != 0 && r != 0) as u16,
+            Eq => (l == r) as u16,
+            Ne => (l != r) as u16,
+            Lt => (l < r) as u16,
+            Le => (l <= r) as u16,
+            Gt => (l > r) as u16,
+            Ge => (l >= r) as u16,
+            BitOr => l | r,
+            BitXor => l ^ r,
+            BitAnd => l & r,
+            Shl => l.checked_shl(r.into()).unwrap_or(0),
+            Shr => l.checked_shr(r.into()).unwrap_or(0),
+            Add => l.wrapping_add(r),
+            Sub => l.wrapping_sub(r),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Literal(u16),
+    Register(Register),
+    /// `[addr]`
+    Byte(Box<Expr>),
+    /// `word[addr]`
+    Word(Box<Expr>),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    /// Evaluate the expression without disturbing the emulation
+    pub fn eval(&self, gameboy: &Gameboy) -> u16 {
+        match self {
+            Expr::Literal(value) => *value,
+            Expr::Register(register) => {
+                let registers = &gameboy.cpu.cpu.registers;
+                match register {
+                    Register::A => registers.a.into(),
+                    Register::F => u8::from(registers.f).into(),
+                    Register::B => registers.b.into(),
+                    Register::C => registers.c.into(),
+                    Register::D => registers.d.into(),
+                    Register::E => registers.e.into(),
+                    Register::H => registers.h.into(),
+                    Register::L => registers.l.into(),
+                    Register::AF => registers.get_af(),
+                    Register::BC => registers.get_bc(),
+                    Register::DE => registers.get_de(),
+                    Register::HL => registers.get_hl(),
+                    Register::SP => registers.sp,
+                    Register::PC => gameboy.instruction_pc(),
+                }
+            }
+            Expr::Byte(addr) => gameboy.peek(addr.eval(gameboy)).into(),
+            Expr::Word(addr) => {
+                let addr = addr.eval(gameboy);
+                u16::from_le_bytes([gameboy.peek(addr), gameboy.peek(addr.wrapping_add(1))])
+            }
+            Expr::Unary(op, operand) => {
+                let value = operand.eval(gameboy);
+                match op {
+                    UnaryOp::Not => (value == 0) as u16,
+                    UnaryOp::Complement => !value,
+                    UnaryOp::Negate => value.wrapping_neg(),
+                }
+            }
+            // Both sides are always evaluated, since reading memory has no side effects
+            Expr::Binary(op, l, r) => op.apply(l.eval(gameboy), r.eval(gameboy)),
+        }
+    }
+}
+
+impl FromStr for Expr {
+    type Err = ExprError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            next: 0,
+            end: s.len(),
+        };
+        let expr = parser.expr(0)?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some(token) => Err(token.unexpected("an operator")),
+        }
+    }
+}
+
+/// Errors produced while parsing an expression. Each one has the byte offset in the expression
+/// where the problem was found.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ExprError {
+    #[error("expected {expected} at offset {offset}, found {found:?}")]
+    UnexpectedToken {
+        offset: usize,
+        found: String,
+        expected: &'static str,
+    },
+    #[error("expected {expected} at offset {offset}, found the end of the expression")]
+    UnexpectedEnd {
+        offset: usize,
+        expected: &'static str,
+    },
+    #[error("invalid number {text:?} at offset {offset}")]
+    InvalidNumber { offset: usize, text: String },
+    #[error("unknown register {name:?} at offset {offset}")]
+    UnknownName { offset: usize, name: String },
+}
+
+impl ExprError {
+    pub fn offset(&self) -> usize {
+        match *self {
+            ExprError::UnexpectedToken { offset, .. }
+            | ExprError::UnexpectedEnd { offset, .. }
+            | ExprError::InvalidNumber { offset, .. }
+            | ExprError::UnknownName { offset, .. } => offset,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TokenKind {
+    Number(u16),
+    Register(Register),
+    /// `word`, which must be followed by `[`
+    Word,
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Not,
+    Complement,
+    Minus,
+    Binary(BinaryOp),
+}
+
+#[derive(Debug)]
+struct Token<'a> {
+    kind: TokenKind,
+    offset: usize,
+    text: &'a str,
+}
+
+impl Token<'_> {
+    fn unexpected(&self, expected: &'static str) -> ExprError {
+        ExprError::UnexpectedToken {
+            offset: self.offset,
+            found: self.text.to_owned(),
+            expected,
+        }
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token<'_>>, ExprError> {
+    use BinaryOp::*;
+    let mut tokens = Vec::new();
+    let mut chars = s.char_indices().peekable();
+    while let Some((offset, c)) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+        let next = chars.peek().map(|&(_, c)| c);
+        // Words and numbers run until the next character that can't be part of one
+        let word_end = |start: usize| {
+            s[start..]
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .map_or(s.len(), |len| start + len)
+        };
+        let (kind, len) = match (c, next) {
+            ('0'..='9', _) | ('$', _) => {
+                let end = word_end(offset + 1);
+                let text = &s[offset..end];
+                let value = match text.strip_prefix('$') {
+                    Some(hex) => u16::from_str_radix(hex, 16),
+                    None => match text.strip_prefix("0x").or(text.strip_prefix("0X")) {
+                        Some(hex) => u16::from_str_radix(hex, 16),
+                        None => text.parse(),
+                    },
+                };
+                let value = value.map_err(|_| ExprError::InvalidNumber {
+                    offset,
+                    text: text.to_owned(),
+                })?;
+                (TokenKind::Number(value), text.len())
+            }
+            (c, _) if c.is_ascii_alphabetic() || c == '_' => {
+                let name = &s[offset..word_end(offset)];
+                let kind = if name.eq_ignore_ascii_case("word") {
+                    TokenKind::Word
+                } else {
+                    let register =
+                        Register::from_name(name).ok_or_else(|| ExprError::UnknownName {
+                            offset,
+                            name: name.to_owned(),
+                        })?;
+                    TokenKind::Register(register)
+                };
+                (kind, name.len())
+            }
+            ('|', Some('|')) => (TokenKind::Binary(Or), 2),
+            ('&', Some('&')) => (TokenKind::Binary(And), 2),
+            ('=', Some('=')) => (TokenKind::Binary(Eq), 2),
+            ('!', Some('=')) => (TokenKind::Binary(Ne), 2),
+            ('<', Some('=')) => (TokenKind::Binary(Le), 2),
+            ('>', Some('=')) => (TokenKind::Binary(Ge), 2),
+            ('<', Some('<')) => (TokenKind::Binary(Shl), 2),
+            ('>', Some('>')) => (TokenKind::Binary(Shr), 2),
+            ('<', _) => (TokenKind::Binary(Lt), 1),
+            ('>', _) => (TokenKind::Binary(Gt), 1),
+            ('|', _) => (TokenKind::Binary(BitOr), 1),
+            ('^', _) => (TokenKind::Binary(BitXor), 1),
+            ('&', _) => (TokenKind::Binary(BitAnd), 1),
+            ('+', _) => (TokenKind::Binary(Add), 1),
+            ('-', _) => (TokenKind::Minus, 1),
+            ('!', _) => (TokenKind::Not, 1),
+            ('~', _) => (TokenKind::Complement, 1),
+            ('(', _) => (TokenKind::LParen, 1),
+            (')', _) => (TokenKind::RParen, 1),
+            ('[', _) => (TokenKind::LBracket, 1),
+            (']', _) => (TokenKind::RBracket, 1),
+            (c, _) => {
+                return Err(ExprError::UnexpectedToken {
+                    offset,
+                    found: c.to_string(),
+                    expected: "a number, register or operator",
+                })
+            }
+        };
+        tokens.push(Token {
+            kind,
+            offset,
+            text: &s[offset..offset + len],
+        });
+        // Skip the rest of the token, which is all ASCII
+        for _ in 1..len {
+            chars.next();
+        }
+    }
+    Ok(tokens)
+}
+
+/// A recursive descent parser, which handles binary operators by precedence climbing
+struct Parser<'a> {
+    tokens: Vec<Token<'a>>,
+    next: usize,
+    /// The length of the expression, where running out of tokens is reported
+    end: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Token<'a>> {
+        self.tokens.get(self.next)
+    }
+
+    fn advance(&mut self, expected: &'static str) -> Result<&Token<'a>, ExprError> {
+        let token = self.tokens.get(self.next).ok_or(ExprError::UnexpectedEnd {
+            offset: self.end,
+            expected,
+        })?;
+        self.next += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, kind: TokenKind, expected: &'static str) -> Result<(), ExprError> {
+        let token = self.advance(expected)?;
+        if token.kind == kind {
+            Ok(())
+        } else {
+            Err(token.unexpected(expected))
+        }
+    }
+
+    /// Parse operators that bind tighter than `min_precedence`
+    fn expr(&mut self, min_precedence: u8) -> Result<Expr, ExprError> {
+        let mut lhs = self.unary()?;
+        loop {
+            let op = match self.peek().map(|token| token.kind) {
+                Some(TokenKind::Binary(op)) => op,
+                Some(TokenKind::Minus) => BinaryOp::Sub,
+                _ => return Ok(lhs),
+            };
+            if op.precedence() <= min_precedence {
+                return Ok(lhs);
+            }
+            self.next += 1;
+            // Parsing the right hand side above this operator's precedence makes them left
+            // associative
+            let rhs = self.expr(op.precedence())?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, ExprError> {
+        let op = match self.peek().map(|token| token.kind) {
+            Some(TokenKind::Not) => UnaryOp::Not,
+            Some(TokenKind::Complement) => UnaryOp::Complement,
+            Some(TokenKind::Minus) => UnaryOp::Negate,
+            _ => return self.primary(),
+        };
+        self.next += 1;
+        Ok(Expr::Unary(op, Box::new(self.unary()?)))
+    }
+
+    fn primary(&mut self) -> Result<Expr, ExprError> {
+        const OPERAND: &str = "a number, register or memory read";
+        match self.advance(OPERAND)?.kind {
+            TokenKind::Number(value) => Ok(Expr::Literal(value)),
+            TokenKind::Register(register) => Ok(Expr::Register(register)),
+            TokenKind::LParen => {
+                let expr = self.expr(0)?;
+                self.expect(TokenKind::RParen, "\")\"")?;
+                Ok(expr)
+            }
+            TokenKind::LBracket => Ok(Expr::Byte(Box::new(self.address()?))),
+            TokenKind::Word => {
+                self.expect(TokenKind::LBracket, "\"[\"")?;
+                Ok(Expr::Word(Box::new(self.address()?)))
+            }
+            _ => Err(self.tokens[self.next - 1].unexpected(OPERAND)),
+        }
+    }
+
+    /// The address in a memory read, after the opening bracket
+    fn address(&mut self) -> Result<Expr, ExprError> {
+        let addr = self.expr(0)?;
+        self.expect(TokenKind::RBracket, "\"]\"")?;
+        Ok(addr)
+    }
+}
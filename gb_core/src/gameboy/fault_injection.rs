@@ -0,0 +1,190 @@
+//! Making a chip misbehave on purpose, to check that the rest of the Gameboy copes with whatever
+//! ends up on the bus. Wrap a cartridge or an extra chip in a [`FaultInjector`], plug it in with
+//! [`GameboyBuilder::cartridge`](super::GameboyBuilder::cartridge) or
+//! [`GameboyBuilder::chip`](super::GameboyBuilder::chip), and add [`Fault`]s through its
+//! [`FaultControl`] while the Gameboy runs.
+//!
+//! Garbage on the bus should at worst make the game crash the way it would on hardware. It must
+//! never make the emulator panic.
+
+use std::{
+    ops::{Range, RangeInclusive},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use gb_cpu::CpuOutputPins;
+
+use super::{memory::SplitMix64, Chip, ClockContext};
+
+/// What a chip does wrong
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultKind {
+    /// Reads of addresses the chip claims return random data, like a flashcart with a bad
+    /// connection
+    CorruptReads,
+    /// Writes to addresses the chip claims never reach it
+    SwallowWrites,
+    /// The chip doesn't answer for addresses it claims, so reads are left as open bus
+    Deny,
+    /// The chip drives random data onto the bus for reads of addresses it doesn't claim,
+    /// overriding whatever chip does
+    Claim,
+}
+
+/// A [`FaultKind`] that applies to accesses to `addresses` made during `cycles`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fault {
+    pub kind: FaultKind,
+    pub addresses: RangeInclusive<u16>,
+    /// T-cycles since power on
+    pub cycles: Range<u64>,
+}
+
+impl Fault {
+    /// A fault that applies to `addresses` from now on
+    pub fn always(kind: FaultKind, addresses: RangeInclusive<u16>) -> Self {
+        Fault {
+            kind,
+            addresses,
+            cycles: 0..u64::MAX,
+        }
+    }
+
+    fn applies(&self, kind: FaultKind, addr: u16, cycle: u64) -> bool {
+        self.kind == kind && self.addresses.contains(&addr) && self.cycles.contains(&cycle)
+    }
+}
+
+/// Changes the faults of a [`FaultInjector`] after it has been plugged in. Clones share the same
+/// faults.
+#[derive(Debug, Clone, Default)]
+pub struct FaultControl {
+    faults: Arc<Mutex<Vec<Fault>>>,
+    swallowed_writes: Arc<AtomicU64>,
+}
+
+impl FaultControl {
+    pub fn add(&self, fault: Fault) {
+        self.faults.lock().unwrap().push(fault);
+    }
+
+    /// Remove every fault, so that the chip behaves normally again
+    pub fn clear(&self) {
+        self.faults.lock().unwrap().clear();
+    }
+
+    pub fn faults(&self) -> Vec<Fault> {
+        self.faults.lock().unwrap().clone()
+    }
+
+    /// How many writes [`FaultKind::SwallowWrites`] and [`FaultKind::Deny`] have kept from
+    /// reaching the chip
+    pub fn swallowed_writes(&self) -> u64 {
+        self.swallowed_writes.load(Ordering::Relaxed)
+    }
+}
+
+/// Wraps a [`Chip`] and makes it misbehave as its [`FaultControl`] says. Without any faults, it
+/// behaves exactly like the chip it wraps.
+pub struct FaultInjector<C> {
+    chip: C,
+    control: FaultControl,
+    rng: SplitMix64,
+    /// What the chip claimed when it was wrapped
+    claims: Vec<RangeInclusive<u16>>,
+    /// An address the chip doesn't claim. Accesses that are denied or swallowed are replaced
+    /// with a read of it, so that the chip is still clocked.
+    idle_addr: u16,
+}
+
+impl<C: Chip> FaultInjector<C> {
+    /// Wrap `chip`. Random data comes from `seed`, so runs can be repeated.
+    pub fn new(chip: C, seed: u64) -> Self {
+        let claims = chip.chip_select();
+        let idle_addr = (0xFF00..=0xFFFF)
+            .rev()
+            .find(|addr| !claims.iter().any(|range| range.contains(addr)))
+            .unwrap_or(0xFFFF);
+        FaultInjector {
+            chip,
+            control: FaultControl::default(),
+            rng: SplitMix64(seed),
+            claims,
+            idle_addr,
+        }
+    }
+
+    pub fn control(&self) -> FaultControl {
+        self.control.clone()
+    }
+
+    pub fn into_inner(self) -> C {
+        self.chip
+    }
+
+    fn claims(&self, addr: u16) -> bool {
+        self.claims.iter().any(|range| range.contains(&addr))
+    }
+
+    fn random_byte(&mut self) -> u8 {
+        self.rng.next() as u8
+    }
+}
+
+impl<C: Chip> Chip for FaultInjector<C> {
+    fn clock(
+        &mut self,
+        input: CpuOutputPins,
+        data: &mut u8,
+        interrupt_request: &mut u8,
+        ctx: &ClockContext,
+    ) {
+        let addr = input.addr();
+        let (deny, swallow, corrupt, claim) = {
+            let faults = self.control.faults.lock().unwrap();
+            let active = |kind| faults.iter().any(|f| f.applies(kind, addr, ctx.cycles));
+            (
+                active(FaultKind::Deny),
+                active(FaultKind::SwallowWrites),
+                active(FaultKind::CorruptReads),
+                active(FaultKind::Claim),
+            )
+        };
+        let claimed = self.claims(addr);
+        let ignored = match input {
+            CpuOutputPins::Read { .. } => claimed && deny,
+            CpuOutputPins::Write { .. } => claimed && (deny || swallow),
+        };
+
+        if ignored {
+            if let CpuOutputPins::Write { .. } = input {
+                self.control
+                    .swallowed_writes
+                    .fetch_add(1, Ordering::Relaxed);
+            }
+            let idle = CpuOutputPins::Read {
+                addr: self.idle_addr,
+            };
+            self.chip.clock(idle, &mut 0xFF, interrupt_request, ctx);
+        } else {
+            self.chip.clock(input, data, interrupt_request, ctx);
+        }
+
+        if let CpuOutputPins::Read { .. } = input {
+            if (claimed && corrupt && !deny) || (!claimed && claim) {
+                *data = self.random_byte();
+            }
+        }
+    }
+
+    fn chip_select(&self) -> Vec<RangeInclusive<u16>> {
+        self.chip.chip_select()
+    }
+
+    fn name(&self) -> &'static str {
+        self.chip.name()
+    }
+}
@@ -0,0 +1,491 @@
+//! A server for the GDB remote serial protocol, so that GDB, or any other debugger that speaks it,
+//! can stop, step and inspect a running game.
+//!
+//! ```no_run
+//! use std::net::TcpListener;
+//!
+//! use gb_core::gameboy::{gdb::GdbServer, Gameboy};
+//!
+//! # let rom = vec![];
+//! let gameboy = Gameboy::new(rom).unwrap();
+//! let listener = TcpListener::bind("127.0.0.1:1234").unwrap();
+//! // Connect with `target remote :1234`
+//! GdbServer::new(gameboy, listener).serve().unwrap();
+//! ```
+//!
+//! GDB has no SM83 target, so the registers are numbered like the first six of its z80 target,
+//! and GDB 11 or later can show them after `set architecture z80`. Each is sent as 16 bits, little
+//! endian:
+//!
+//! | Number | Register |
+//! |--------|----------|
+//! | 0      | AF       |
+//! | 1      | BC       |
+//! | 2      | DE       |
+//! | 3      | HL       |
+//! | 4      | SP       |
+//! | 5      | PC       |
+//!
+//! The CPU only ever stops between instructions, and PC is the address of the next one. Memory is
+//! read with [`Gameboy::peek`] and written with [`Gameboy::poke_silent`], or [`Gameboy::poke`] for
+//! the IO registers, so the IO registers other than IF and IE read as $FF, and ROM can't be
+//! written. Software and hardware breakpoints are both
+//! [`Breakpoint`]s, which are kept by the emulator rather than written into memory. Watchpoints,
+//! threads and the `vCont` packets aren't supported, and debuggers fall back on the basic
+//! packets without them.
+//!
+//! While the game runs, the connection is checked for an interrupt (Ctrl-C in GDB) after every
+//! [`CHUNK_T_CYCLES`]. While it is stopped, the server sleeps until the next packet arrives.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    io::{self, Read, Write},
+    net::{TcpListener, TcpStream},
+};
+
+use gb_cpu::{CpuInputPins, CpuRunner, Registers};
+
+use super::{
+    breakpoints::{Breakpoint, BreakpointId},
+    ppu::consts::FRAME_T_CYCLES,
+    Gameboy,
+};
+
+/// T-cycles emulated between checks for an interrupt from the debugger, which is one frame
+pub const CHUNK_T_CYCLES: u64 = FRAME_T_CYCLES as u64;
+
+/// The number of registers, see the [module documentation](self)
+pub const REGISTER_COUNT: usize = 6;
+
+/// Reported when the debugger interrupts the game
+const SIGINT: u8 = 2;
+/// Reported when the game stops at a breakpoint or after a step
+const SIGTRAP: u8 = 5;
+
+/// The largest packet the debugger may send, which is sent to it in hex
+const PACKET_SIZE: usize = 0x1000;
+
+/// Serves a debugger connected over TCP, controlling a [`Gameboy`]
+pub struct GdbServer {
+    gameboy: Gameboy,
+    listener: TcpListener,
+    /// The breakpoints the debugger has set, by address
+    breakpoints: BTreeMap<u16, BreakpointId>,
+    /// The reason the game last stopped
+    signal: u8,
+}
+
+/// What to do after a packet
+enum Action {
+    Reply(String),
+    Continue,
+    Step,
+    /// Reply, if there is anything to reply, then close the connection
+    End(Option<String>),
+}
+
+impl GdbServer {
+    pub fn new(gameboy: Gameboy, listener: TcpListener) -> Self {
+        GdbServer {
+            gameboy,
+            listener,
+            breakpoints: BTreeMap::new(),
+            signal: SIGTRAP,
+        }
+    }
+
+    pub fn gameboy(&self) -> &Gameboy {
+        &self.gameboy
+    }
+
+    pub fn gameboy_mut(&mut self) -> &mut Gameboy {
+        &mut self.gameboy
+    }
+
+    /// Stop serving, and return the Gameboy without the debugger's breakpoints
+    pub fn into_inner(mut self) -> Gameboy {
+        self.remove_breakpoints();
+        self.gameboy
+    }
+
+    /// Wait for a debugger to connect, and serve it until it detaches, kills the game, or
+    /// disconnects. The game only runs when the debugger continues or steps it. This can be
+    /// called again to wait for the next debugger.
+    pub fn serve(&mut self) -> io::Result<()> {
+        let (stream, _) = self.listener.accept()?;
+        stream.set_nodelay(true)?;
+        let mut connection = Connection { stream, ack: true };
+        self.finish_instruction();
+        let result = self.serve_connection(&mut connection);
+        self.remove_breakpoints();
+        result
+    }
+
+    fn serve_connection(&mut self, connection: &mut Connection) -> io::Result<()> {
+        while let Some(packet) = connection.receive()? {
+            match self.handle(&packet) {
+                Action::Reply(reply) => connection.send(&reply)?,
+                Action::Continue => {
+                    self.signal = self.run(connection)?;
+                    connection.send(&format!("S{:02x}", self.signal))?;
+                }
+                Action::Step => {
+                    self.step();
+                    self.signal = SIGTRAP;
+                    connection.send(&format!("S{:02x}", self.signal))?;
+                }
+                Action::End(reply) => {
+                    if let Some(reply) = reply {
+                        connection.send(&reply)?;
+                    }
+                    return Ok(());
+                }
+            }
+            // Acknowledgments stop after the reply to the packet that turns them off
+            if packet == b"QStartNoAckMode" {
+                connection.ack = false;
+            }
+        }
+        Ok(())
+    }
+
+    fn handle(&mut self, packet: &[u8]) -> Action {
+        let packet = String::from_utf8_lossy(packet);
+        let reply = |reply: &str| Action::Reply(reply.to_string());
+        let (command, args) = packet.split_at(packet.len().min(1));
+        match command {
+            "?" => Action::Reply(format!("S{:02x}", self.signal)),
+            "g" => {
+                let registers = registers(&self.cpu_registers());
+                Action::Reply(hex(registers.iter().flat_map(|value| value.to_le_bytes())))
+            }
+            "G" => match hex_bytes(args) {
+                Some(bytes) if bytes.len() == REGISTER_COUNT * 2 => {
+                    let mut registers = self.cpu_registers();
+                    for (i, value) in bytes.chunks(2).enumerate() {
+                        set_register(&mut registers, i, u16::from_le_bytes([value[0], value[1]]));
+                    }
+                    self.set_cpu_registers(registers);
+                    reply("OK")
+                }
+                _ => reply("E01"),
+            },
+            "p" => match hex_number(args).map(|n| n as usize) {
+                Some(n) if n < REGISTER_COUNT => {
+                    let [low, high] = registers(&self.cpu_registers())[n].to_le_bytes();
+                    Action::Reply(format!("{:02x}{:02x}", low, high))
+                }
+                _ => reply("E01"),
+            },
+            "P" => {
+                let parsed = args.split_once('=').and_then(|(n, value)| {
+                    let n = hex_number(n)? as usize;
+                    match hex_bytes(value)?.as_slice() {
+                        &[low, high] if n < REGISTER_COUNT => {
+                            Some((n, u16::from_le_bytes([low, high])))
+                        }
+                        _ => None,
+                    }
+                });
+                match parsed {
+                    Some((n, value)) => {
+                        let mut registers = self.cpu_registers();
+                        set_register(&mut registers, n, value);
+                        self.set_cpu_registers(registers);
+                        reply("OK")
+                    }
+                    None => reply("E01"),
+                }
+            }
+            "m" => match address_and_length(args) {
+                Some((addr, len)) => {
+                    let gameboy = &self.gameboy;
+                    Action::Reply(hex((0..len).map(|i| gameboy.peek(addr.wrapping_add(i)))))
+                }
+                None => reply("E01"),
+            },
+            "M" => {
+                let parsed = args.split_once(':').and_then(|(range, data)| {
+                    let (addr, len) = address_and_length(range)?;
+                    let data = hex_bytes(data)?;
+                    (data.len() == len as usize).then_some((addr, data))
+                });
+                match parsed {
+                    Some((addr, data)) => {
+                        for (i, &byte) in data.iter().enumerate() {
+                            let addr = addr.wrapping_add(i as u16);
+                            // ROM is left alone, so that the mapper's registers can't be set by
+                            // accident
+                            if addr >= 0x8000 && self.gameboy.poke_silent(addr, byte).is_err() {
+                                self.gameboy.poke(addr, byte);
+                            }
+                        }
+                        reply("OK")
+                    }
+                    None => reply("E01"),
+                }
+            }
+            "c" | "s" => {
+                // Resume from a different address if one is given
+                if !args.is_empty() {
+                    match hex_number(args) {
+                        Some(pc) => {
+                            let mut registers = self.cpu_registers();
+                            registers.pc = pc as u16;
+                            self.set_cpu_registers(registers);
+                        }
+                        None => return reply("E01"),
+                    }
+                }
+                if command == "c" {
+                    Action::Continue
+                } else {
+                    Action::Step
+                }
+            }
+            "Z" | "z" => {
+                let mut fields = args.split(',');
+                let kind = fields.next();
+                let addr = fields.next().and_then(hex_number);
+                match (kind, addr) {
+                    (Some("0" | "1"), Some(addr)) => {
+                        let addr = addr as u16;
+                        if command == "Z" {
+                            let gameboy = &mut self.gameboy;
+                            self.breakpoints
+                                .entry(addr)
+                                .or_insert_with(|| gameboy.add_breakpoint(Breakpoint::at(addr)));
+                        } else if let Some(id) = self.breakpoints.remove(&addr) {
+                            self.gameboy.remove_breakpoint(id);
+                        }
+                        reply("OK")
+                    }
+                    // Watchpoints
+                    _ => reply(""),
+                }
+            }
+            "H" | "T" => reply("OK"),
+            "D" => Action::End(Some("OK".to_string())),
+            "k" => Action::End(None),
+            _ if packet.starts_with("qSupported") => {
+                Action::Reply(format!("PacketSize={:x};QStartNoAckMode+", PACKET_SIZE))
+            }
+            _ => match &*packet {
+                "QStartNoAckMode" => reply("OK"),
+                "qAttached" => reply("1"),
+                "qC" => reply("QC1"),
+                "qfThreadInfo" => reply("m1"),
+                "qsThreadInfo" => reply("l"),
+                _ => reply(""),
+            },
+        }
+    }
+
+    /// Run until a breakpoint is hit or the debugger interrupts, and return the signal to report
+    fn run(&mut self, connection: &mut Connection) -> io::Result<u8> {
+        loop {
+            if self.gameboy.run_until_breakpoint(CHUNK_T_CYCLES).is_some() {
+                return Ok(SIGTRAP);
+            }
+            if connection.interrupted()? {
+                self.finish_instruction();
+                return Ok(SIGINT);
+            }
+        }
+    }
+
+    /// Run one instruction, or a frame's worth of T-cycles if the CPU is halted
+    fn step(&mut self) {
+        let start = self.gameboy.cycles;
+        while !self.gameboy.tick().is_fetch_cycle && self.gameboy.cycles - start < CHUNK_T_CYCLES {}
+    }
+
+    /// Run to the end of the instruction being executed, so that the next one has been fetched
+    fn finish_instruction(&mut self) {
+        let gameboy = &mut self.gameboy;
+        while !(gameboy.fetched || gameboy.cpu.cpu.halted || gameboy.cpu.cpu.locked) {
+            gameboy.tick();
+        }
+    }
+
+    /// The registers, with PC at the next instruction rather than past its opcode
+    fn cpu_registers(&self) -> Registers {
+        let mut registers = self.gameboy.cpu.cpu.registers;
+        if self.gameboy.fetched {
+            registers.pc = registers.pc.wrapping_sub(1);
+        }
+        registers
+    }
+
+    fn set_cpu_registers(&mut self, registers: Registers) {
+        let unchanged_pc = registers.pc == self.cpu_registers().pc;
+        let gameboy = &mut self.gameboy;
+        if unchanged_pc {
+            let pc = gameboy.cpu.cpu.registers.pc;
+            gameboy.cpu.cpu.registers = Registers { pc, ..registers };
+            return;
+        }
+        // The opcode at the old PC has already been fetched, so start a new runner at the new PC
+        // and fetch its opcode instead, like loading a savestate
+        let mut cpu = gameboy.cpu.cpu;
+        cpu.registers = registers;
+        let flags = gameboy.cpu.state_flags();
+        gameboy.cpu = CpuRunner::from_state(cpu, flags);
+        if gameboy.fetched {
+            gameboy.cpu.clock(CpuInputPins::default());
+            gameboy.cpu_input.data = gameboy.peek(registers.pc);
+        }
+    }
+
+    fn remove_breakpoints(&mut self) {
+        for (_, id) in std::mem::take(&mut self.breakpoints) {
+            self.gameboy.remove_breakpoint(id);
+        }
+    }
+}
+
+impl std::fmt::Debug for GdbServer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GdbServer")
+            .field("listener", &self.listener)
+            .field("breakpoints", &self.breakpoints)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The registers in the order they are numbered in
+fn registers(registers: &Registers) -> [u16; REGISTER_COUNT] {
+    [
+        registers.get_af(),
+        registers.get_bc(),
+        registers.get_de(),
+        registers.get_hl(),
+        registers.sp,
+        registers.pc,
+    ]
+}
+
+fn set_register(registers: &mut Registers, n: usize, value: u16) {
+    match n {
+        0 => registers.set_af(value),
+        1 => registers.set_bc(value),
+        2 => registers.set_de(value),
+        3 => registers.set_hl(value),
+        4 => registers.sp = value,
+        5 => registers.pc = value,
+        _ => unreachable!(),
+    }
+}
+
+fn hex(bytes: impl Iterator<Item = u8>) -> String {
+    bytes.fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{:02x}", byte);
+        hex
+    })
+}
+
+fn hex_number(s: &str) -> Option<u32> {
+    u32::from_str_radix(s, 16).ok()
+}
+
+fn hex_bytes(s: &str) -> Option<Vec<u8>> {
+    if s.len() % 2 != 0 {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Parse `addr,length`
+fn address_and_length(s: &str) -> Option<(u16, u16)> {
+    let (addr, len) = s.split_once(',')?;
+    let len = hex_number(len)?;
+    (len as usize <= PACKET_SIZE / 2).then_some((hex_number(addr)? as u16, len as u16))
+}
+
+/// The framing of packets on the connection to the debugger
+struct Connection {
+    stream: TcpStream,
+    /// Whether each packet is acknowledged with `+`, which the debugger can turn off
+    ack: bool,
+}
+
+impl Connection {
+    fn byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = 0;
+        match self.stream.read(std::slice::from_mut(&mut byte))? {
+            0 => Ok(None),
+            _ => Ok(Some(byte)),
+        }
+    }
+
+    /// Wait for the next packet, and return its contents, or `None` if the debugger disconnected
+    fn receive(&mut self) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            // Skip acknowledgments, and interrupts that arrive after the game has stopped anyway
+            match self.byte()? {
+                None => return Ok(None),
+                Some(b'$') => (),
+                Some(_) => continue,
+            }
+            let mut packet = Vec::new();
+            let mut sum = 0u8;
+            loop {
+                match self.byte()? {
+                    None => return Ok(None),
+                    Some(b'#') => break,
+                    Some(byte) => {
+                        sum = sum.wrapping_add(byte);
+                        packet.push(byte);
+                    }
+                }
+            }
+            let mut checksum = [0; 2];
+            self.stream.read_exact(&mut checksum)?;
+            let checksum = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|checksum| u8::from_str_radix(checksum, 16).ok());
+            if !self.ack {
+                return Ok(Some(packet));
+            }
+            if checksum == Some(sum) {
+                self.stream.write_all(b"+")?;
+                return Ok(Some(packet));
+            }
+            self.stream.write_all(b"-")?;
+        }
+    }
+
+    /// Send a packet, resending it until the debugger acknowledges it
+    fn send(&mut self, packet: &str) -> io::Result<()> {
+        let sum = packet.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        let framed = format!("${}#{:02x}", packet, sum);
+        loop {
+            self.stream.write_all(framed.as_bytes())?;
+            if !self.ack {
+                return Ok(());
+            }
+            match self.byte()? {
+                Some(b'-') => continue,
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /// Whether the debugger has sent an interrupt or disconnected, without waiting
+    fn interrupted(&mut self) -> io::Result<bool> {
+        self.stream.set_nonblocking(true)?;
+        let mut buf = [0; 64];
+        let read = self.stream.read(&mut buf);
+        self.stream.set_nonblocking(false)?;
+        match read {
+            Ok(0) => Ok(true),
+            Ok(len) => Ok(buf[..len].contains(&0x03)),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
@@ -0,0 +1,13 @@
+//! User-supplied handlers for IO registers that no chip implements
+
+use super::ClockContext;
+
+/// Handles reads and writes to a single unmapped IO register, for prototyping peripherals without
+/// writing a whole `Chip`. Registered with [`Gameboy::register_io_hook`](super::Gameboy::register_io_hook).
+pub trait IoHook {
+    /// Called when the CPU reads the register. The result replaces the $FF that unmapped
+    /// registers normally read as.
+    fn read(&mut self, ctx: &ClockContext) -> u8;
+
+    fn write(&mut self, value: u8, ctx: &ClockContext);
+}
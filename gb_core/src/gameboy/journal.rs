@@ -0,0 +1,317 @@
+//! Undoing instructions one at a time, for stepping backwards in a debugger
+//!
+//! While the journal is enabled, an entry is pushed every time the CPU fetches an opcode. It holds
+//! the CPU's registers, IE, IF and the small registers and counters of the other chips, which are
+//! cheap enough to copy every instruction. Anything larger is only saved when it is written: each
+//! write on the bus adds the byte it is about to replace to the newest entry. Stepping back undoes
+//! those writes in reverse, then restores the registers.
+//!
+//! Not everything can be rewound:
+//! - The PPU runs as a coroutine, so it carries on from where it is in the frame. LY, the mode
+//!   bits of STAT, the frame count and the frames already drawn are not restored, which leaves
+//!   the PPU ahead of the rest of the machine by the length of the undone instructions. VRAM, OAM
+//!   and every other PPU register are restored.
+//! - Bytes exchanged over the link cable, Super Game Boy packets, IO hooks and chips attached
+//!   with `GameboyBuilder::chip` are not journaled, and neither
+//!   are the RAM writes made by GameShark cheats.
+//! - A cartridge's real time clock keeps the time it was set to, and keeps counting.
+//! - Events that have been published stay published, and profiling, coverage and call stack
+//!   tracking are not taken back.
+
+use std::collections::VecDeque;
+
+use gb_cpu::{Cpu, CpuInputPins};
+
+use super::{
+    apu::Apu,
+    ppu::{
+        registers::{LCDC, STAT},
+        DmaState,
+    },
+    serial::SerialState,
+    system_counter::SystemCounter,
+    timer::Timer,
+    Gameboy,
+};
+
+/// Number of instructions that can be stepped back, unless changed with
+/// [`Gameboy::set_journal_capacity`](super::Gameboy::set_journal_capacity)
+pub const DEFAULT_CAPACITY: usize = 1024;
+
+/// An instruction undone by [`Gameboy::step_back_instruction`](super::Gameboy::step_back_instruction)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstructionRecord {
+    /// The address the opcode was fetched from
+    pub pc: u16,
+    pub opcode: u8,
+    /// T-cycles from the opcode fetch to the next one, including any interrupt dispatch, HALT or
+    /// OAM DMA in between
+    pub t_cycles: u64,
+    /// Every address written on the bus during the instruction, in order
+    pub writes: Vec<u16>,
+}
+
+#[derive(Debug)]
+pub(crate) struct Journal {
+    enabled: bool,
+    capacity: usize,
+    /// The state at each of the last few instruction boundaries, oldest first. The newest entry is
+    /// the one being executed, so there is one more entry than instructions that can be undone.
+    entries: VecDeque<Entry>,
+    /// The last M-cycle fetched an opcode, so the newest entry is the current state
+    at_boundary: bool,
+}
+
+impl Default for Journal {
+    fn default() -> Self {
+        Journal {
+            enabled: false,
+            capacity: DEFAULT_CAPACITY,
+            entries: VecDeque::new(),
+            at_boundary: false,
+        }
+    }
+}
+
+impl Journal {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Starting or stopping the journal forgets every entry
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.entries = VecDeque::new();
+        self.at_boundary = false;
+    }
+
+    pub fn set_capacity(&mut self, instructions: usize) {
+        self.capacity = instructions;
+        self.trim();
+    }
+
+    fn trim(&mut self) {
+        while self.entries.len() > self.capacity + 1 {
+            self.entries.pop_front();
+        }
+    }
+
+    /// The entry that writes are currently being saved to
+    fn newest(&mut self) -> Option<&mut Entry> {
+        self.entries.back_mut()
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    state: State,
+    /// How to undo the writes made since `state` was saved, oldest first
+    undo: Vec<Undo>,
+    writes: Vec<u16>,
+    /// OAM has been saved to `undo` in full, so further DMA or OAM bug corruption can be ignored
+    oam_saved: bool,
+}
+
+/// Everything that is saved at every instruction boundary
+#[derive(Debug, Clone, Copy)]
+struct State {
+    /// Includes the 12 bytes of registers, IME and the HALT state
+    cpu: Cpu,
+    /// Holds the opcode that was just fetched
+    cpu_input: CpuInputPins,
+    interrupt_enable: u8,
+    interrupt_request: u8,
+    cycles: u64,
+    extra_cycles: u8,
+    counter: SystemCounter,
+    timer: Timer,
+    serial: SerialState,
+    p1: u8,
+    ppu: PpuRegisters,
+}
+
+/// The PPU's registers, except for LY and the read-only bits of STAT
+#[derive(Debug, Clone, Copy)]
+struct PpuRegisters {
+    lcdc: LCDC,
+    stat: STAT,
+    scy: u8,
+    scx: u8,
+    lyc: u8,
+    wy: u8,
+    wx: u8,
+    bgp: u8,
+    obp0: u8,
+    obp1: u8,
+    dma: u8,
+    dma_transfer: DmaState,
+}
+
+/// What a write replaced
+#[derive(Debug)]
+enum Undo {
+    /// A byte that [`Gameboy::poke`] can write back
+    Byte {
+        addr: u16,
+        old: u8,
+    },
+    CartRam {
+        offset: usize,
+        old: u8,
+    },
+    Mapper([u8; 4]),
+    Apu(Box<Apu>),
+    Oam(Box<[u8; 0xA0]>),
+    BootRom(Box<[u8; 0x100]>),
+}
+
+impl Gameboy {
+    /// Called at the end of every M-cycle while journaling
+    pub(super) fn journal_tick(&mut self, is_fetch_cycle: bool) {
+        self.journal.at_boundary = is_fetch_cycle;
+        if is_fetch_cycle {
+            let entry = Entry {
+                state: self.journal_state(),
+                undo: Vec::new(),
+                writes: Vec::new(),
+                oam_saved: false,
+            };
+            self.journal.entries.push_back(entry);
+            self.journal.trim();
+        }
+    }
+
+    /// Called while journaling before the chips see a write of `data` to `addr`
+    pub(super) fn journal_write(&mut self, addr: u16, data: u8) {
+        let undo = match addr {
+            0x0000..=0x7FFF => Some(Undo::Mapper(self.cart.mapper_registers())),
+            0xA000..=0xBFFF => self
+                .cart
+                .ram_byte(addr)
+                .map(|(offset, old)| Undo::CartRam { offset, old }),
+            0x8000..=0x9FFF | 0xC000..=0xFE9F | 0xFF80..=0xFFFE => Some(Undo::Byte {
+                addr,
+                old: self.peek(addr),
+            }),
+            0xFF10..=0xFF3F => Some(Undo::Apu(Box::new(self.apu.clone()))),
+            0xFF50 if data != 0 => self.boot_rom.clone().map(Undo::BootRom),
+            // Every other register is part of the state saved at each boundary
+            _ => None,
+        };
+        if let Some(entry) = self.journal.newest() {
+            entry.writes.push(addr);
+            entry.undo.extend(undo);
+        }
+    }
+
+    /// Called while journaling before OAM is changed other than by a write on the bus
+    pub(super) fn journal_oam(&mut self) {
+        let oam = self.ppu.oam;
+        if let Some(entry) = self.journal.newest() {
+            if !entry.oam_saved {
+                entry.undo.push(Undo::Oam(Box::new(oam)));
+                entry.oam_saved = true;
+            }
+        }
+    }
+
+    pub(super) fn journal_step_back(&mut self) -> Option<InstructionRecord> {
+        if !self.journal.at_boundary || self.journal.entries.len() < 2 {
+            return None;
+        }
+        let current = self.journal.entries.pop_back()?;
+        let previous = self.journal.newest()?;
+        let state = previous.state;
+        let undo = std::mem::take(&mut previous.undo);
+        let writes = std::mem::take(&mut previous.writes);
+        previous.oam_saved = false;
+
+        // Nothing can have been written since the fetch, so only the previous entry has writes
+        self.undo(undo);
+        self.restore_journal_state(&state);
+        self.perf.reset(self.ppu.frame_count, self.cycles);
+
+        Some(InstructionRecord {
+            pc: state.cpu.registers.pc.wrapping_sub(1),
+            opcode: state.cpu_input.data,
+            t_cycles: current.state.cycles - state.cycles,
+            writes,
+        })
+    }
+
+    fn undo(&mut self, undo: Vec<Undo>) {
+        for undo in undo.into_iter().rev() {
+            match undo {
+                // Only writes to RAM are recorded as bytes, so this can't fail
+                Undo::Byte { addr, old } => self.poke_silent(addr, old).unwrap(),
+                Undo::CartRam { offset, old } => self.cart.set_ram_byte(offset, old),
+                Undo::Mapper(registers) => self.cart.set_mapper_registers(registers),
+                Undo::Apu(apu) => self.apu = *apu,
+                Undo::Oam(oam) => self.ppu.oam = *oam,
+                Undo::BootRom(boot_rom) => self.boot_rom = Some(boot_rom),
+            }
+        }
+    }
+
+    fn journal_state(&self) -> State {
+        let ppu = &self.ppu;
+        State {
+            cpu: self.cpu.cpu,
+            cpu_input: self.cpu_input,
+            interrupt_enable: self.interrupt_enable,
+            interrupt_request: self.interrupt_request,
+            cycles: self.cycles,
+            extra_cycles: self.extra_cycles,
+            counter: self.counter,
+            timer: self.timer,
+            serial: self.serial.registers(),
+            p1: self.joypad.p1(),
+            ppu: PpuRegisters {
+                lcdc: ppu.lcdc,
+                stat: ppu.stat,
+                scy: ppu.scy,
+                scx: ppu.scx,
+                lyc: ppu.lyc,
+                wy: ppu.wy,
+                wx: ppu.wx,
+                bgp: ppu.bgp,
+                obp0: ppu.obp0,
+                obp1: ppu.obp1,
+                dma: ppu.dma,
+                dma_transfer: ppu.dma_transfer,
+            },
+        }
+    }
+
+    fn restore_journal_state(&mut self, state: &State) {
+        self.cpu.cpu = state.cpu;
+        self.cpu_input = state.cpu_input;
+        self.interrupt_enable = state.interrupt_enable;
+        self.interrupt_request = state.interrupt_request;
+        self.cycles = state.cycles;
+        self.extra_cycles = state.extra_cycles;
+        self.counter = state.counter;
+        self.timer = state.timer;
+        self.serial.set_registers(state.serial);
+        self.joypad.set_p1(state.p1);
+        self.scheduler.wake_all();
+
+        let registers = &state.ppu;
+        let ppu = &mut self.ppu;
+        ppu.lcdc = registers.lcdc;
+        // The mode and LYC=LY bits follow the PPU, which isn't rewound
+        ppu.stat =
+            STAT::from_bits_truncate((registers.stat.bits() & 0x78) | (ppu.stat.bits() & 0x07));
+        ppu.scy = registers.scy;
+        ppu.scx = registers.scx;
+        ppu.lyc = registers.lyc;
+        ppu.wy = registers.wy;
+        ppu.wx = registers.wx;
+        ppu.bgp = registers.bgp;
+        ppu.obp0 = registers.obp0;
+        ppu.obp1 = registers.obp1;
+        ppu.dma = registers.dma;
+        ppu.dma_transfer = registers.dma_transfer;
+        ppu.refresh_registers();
+    }
+}
@@ -1,6 +1,6 @@
-use super::Chip;
+use super::{sgb::Sgb, Chip, ClockContext};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Button {
     Start,
     Select,
@@ -12,6 +12,34 @@ pub enum Button {
     Down,
 }
 
+/// Every button, in the order they are declared
+const BUTTONS: [Button; 8] = [
+    Button::Start,
+    Button::Select,
+    Button::B,
+    Button::A,
+    Button::Left,
+    Button::Right,
+    Button::Up,
+    Button::Down,
+];
+
+/// When the button changes made with [`Joypad::press`], [`Joypad::release`] and
+/// [`Joypad::set_button`] reach the game. Setting the button fields directly always takes effect
+/// immediately.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum JoypadLatchMode {
+    /// On the next M-cycle, for the least input latency
+    #[default]
+    Immediate,
+    /// At the start of the next VBlank, so the game sees the same input however the frontend's
+    /// calls line up with the frame, which replaying recorded input relies on. While the LCD is
+    /// off, this is every 70224 T-cycles instead, when the PPU counts a frame anyway. Only the
+    /// last change to each button before then is seen, so a button pressed and released within a
+    /// frame is never pressed as far as the game can tell.
+    VBlank,
+}
+
 #[derive(Debug, Default)]
 pub struct Joypad {
     pub start: bool,
@@ -24,49 +52,132 @@ pub struct Joypad {
     pub right: bool,
 
     p1: u8,
+    latch_mode: JoypadLatchMode,
+    /// The last change to each button waiting for the next VBlank, indexed by `Button as usize`
+    pending: [Option<bool>; 8],
+    /// Present when emulating a Super Game Boy
+    pub(crate) sgb: Option<Sgb>,
 }
 
 impl Joypad {
+    /// A joypad port that also passes command packets to a Super Game Boy
+    pub(crate) fn with_sgb() -> Self {
+        Joypad {
+            sgb: Some(Sgb::default()),
+            ..Default::default()
+        }
+    }
+
+    pub fn latch_mode(&self) -> JoypadLatchMode {
+        self.latch_mode
+    }
+
+    /// Change when button changes take effect. Changes still waiting for VBlank are applied
+    /// straight away when switching to [`JoypadLatchMode::Immediate`].
+    pub fn set_latch_mode(&mut self, mode: JoypadLatchMode) {
+        self.latch_mode = mode;
+        if mode == JoypadLatchMode::Immediate {
+            self.latch();
+        }
+    }
+
+    /// Apply the changes waiting for VBlank
+    pub(crate) fn latch(&mut self) {
+        let pending = std::mem::take(&mut self.pending);
+        for (&button, &pressed) in BUTTONS.iter().zip(&pending) {
+            if let Some(pressed) = pressed {
+                self.set_live(button, pressed);
+            }
+        }
+    }
+
     pub fn press(&mut self, button: Button) {
+        self.set_button(button, true)
+    }
+
+    pub fn release(&mut self, button: Button) {
+        self.set_button(button, false)
+    }
+
+    /// Press or release `button`
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        match self.latch_mode {
+            JoypadLatchMode::Immediate => self.set_live(button, pressed),
+            JoypadLatchMode::VBlank => self.pending[button as usize] = Some(pressed),
+        }
+    }
+
+    fn set_live(&mut self, button: Button, pressed: bool) {
         use Button::*;
-        match button {
-            Start => self.start = true,
-            Select => self.select = true,
-            B => self.b = true,
-            A => self.a = true,
-            Left => self.left = true,
-            Right => self.right = true,
-            Up => self.up = true,
-            Down => self.down = true,
+        let field = match button {
+            Start => &mut self.start,
+            Select => &mut self.select,
+            B => &mut self.b,
+            A => &mut self.a,
+            Left => &mut self.left,
+            Right => &mut self.right,
+            Up => &mut self.up,
+            Down => &mut self.down,
+        };
+        *field = pressed;
+    }
+
+    /// Clear P1 and the Super Game Boy's state. Buttons held down, and changes waiting for VBlank,
+    /// are kept, since they belong to the player rather than the console.
+    pub(crate) fn power_cycle(&mut self) {
+        self.p1 = 0;
+        if let Some(sgb) = &mut self.sgb {
+            sgb.power_cycle();
         }
     }
 
-    pub fn release(&mut self, button: Button) {
+    /// P1, without the unused upper 2 bits
+    pub(crate) fn p1(&self) -> u8 {
+        self.p1
+    }
+
+    pub(crate) fn set_p1(&mut self, p1: u8) {
+        self.p1 = p1;
+    }
+
+    /// Whether `button` is pressed as far as the game can see, not counting changes waiting for
+    /// VBlank
+    pub fn is_pressed(&self, button: Button) -> bool {
         use Button::*;
         match button {
-            Start => self.start = false,
-            Select => self.select = false,
-            B => self.b = false,
-            A => self.a = false,
-            Left => self.left = false,
-            Right => self.right = false,
-            Up => self.up = false,
-            Down => self.down = false,
+            Start => self.start,
+            Select => self.select,
+            B => self.b,
+            A => self.a,
+            Left => self.left,
+            Right => self.right,
+            Up => self.up,
+            Down => self.down,
         }
     }
 }
 
 impl Chip for Joypad {
-    fn clock(&mut self, input: gb_cpu::CpuOutputPins, data: &mut u8, interrupt_request: &mut u8) {
+    fn clock(
+        &mut self,
+        input: gb_cpu::CpuOutputPins,
+        data: &mut u8,
+        interrupt_request: &mut u8,
+        _ctx: &ClockContext,
+    ) {
         match input {
             gb_cpu::CpuOutputPins::Write {
                 addr: 0xFF00,
                 data: v,
             } => {
                 self.p1 = v & 0b00110000;
+                if let Some(sgb) = &mut self.sgb {
+                    sgb.write(v);
+                }
             }
+            // The upper 2 bits are unused
             gb_cpu::CpuOutputPins::Read { addr: 0xFF00 } => {
-                *data = self.p1;
+                *data = self.p1 | 0xC0;
             }
             _ => (),
         };
@@ -93,14 +204,32 @@ impl Chip for Joypad {
             0x0F
         };
 
+        // In SGB multiplayer mode, only the first joypad is connected, and deselecting both
+        // groups of buttons reads the ID of the current joypad instead
+        let buttons = match self.sgb.as_ref().and_then(Sgb::player) {
+            Some(player) if self.p1 & 0b00110000 == 0b00110000 => 0x0F - player,
+            Some(player) if player != 0 => 0x0F,
+            _ => action_buttons & direction_buttons,
+        };
+
         let old_p1 = self.p1;
-        self.p1 = (old_p1 & 0xF0) | (action_buttons & direction_buttons);
+        self.p1 = (old_p1 & 0xF0) | buttons;
 
-        let interrupt = old_p1 & 0x0F == 0x0F && self.p1 & 0x0F != 0x0F;
+        // Any line falling requests the interrupt, on the same M-cycle the game can first read
+        // the change
+        let interrupt = old_p1 & !self.p1 & 0x0F != 0;
         if interrupt {
             *interrupt_request |= 1 << 4;
         }
     }
+
+    fn chip_select(&self) -> Vec<std::ops::RangeInclusive<u16>> {
+        vec![0xFF00..=0xFF00]
+    }
+
+    fn name(&self) -> &'static str {
+        "joypad"
+    }
 }
 
 fn bool_to_bit(b: bool, bit: usize) -> u8 {
@@ -0,0 +1,49 @@
+//! The core logs what it is doing through the [`log`] crate, so any logger installed by the
+//! frontend will show it. Records are split between these targets:
+//!
+//! | Target          | Level | Records                                                      |
+//! |-----------------|-------|--------------------------------------------------------------|
+//! | `gb::cpu`       | warn  | Illegal opcodes, which lock up the CPU                       |
+//! | `gb::cpu`       | trace | Every instruction fetched, named by any [`symbols`](super::symbols) loaded, with `trace-heavy` |
+//! | `gb::ppu`       | warn  | Writes to registers the PPU used in the same M-cycle, once enabled with [`Gameboy::set_ordering_check`](super::Gameboy::set_ordering_check) |
+//! | `gb::ppu`       | debug | The LCD being turned on or off                               |
+//! | `gb::ppu`       | trace | STAT interrupt line edges, and mode changes with `trace-heavy` |
+//! | `gb::dma`       | debug | OAM DMA transfers starting and finishing                     |
+//! | `gb::mapper`    | debug | ROM bank switches, and battery-backed RAM becoming dirty or clean |
+//! | `gb::interrupt` | debug | Interrupts being dispatched, with their vector and return address |
+//! | `gb::interrupt` | trace | Interrupts being requested                                   |
+//! | `gb::serial`    | debug | Bytes exchanged over the link port                           |
+//! | `gb::bus`       | warn  | Accesses answered by more than one chip, in debug builds or with `strict-bus` |
+//! | `gb::violation` | warn  | Writes to ROM and code run from VRAM or IO, once enabled, see [`violations`](super::violations) |
+//!
+//! Records made for every instruction or dot are only compiled in with the `trace-heavy` feature,
+//! so that the rest can be left on without slowing the emulator down. For example, with
+//! [env_logger](https://docs.rs/env_logger):
+//!
+//! ```ignore
+//! env_logger::Builder::new()
+//!     .parse_filters("gb::dma=debug,gb::interrupt=trace")
+//!     .init();
+//! ```
+//!
+//! See `examples/logging.rs` for a frontend that needs no extra crates.
+
+pub const CPU: &str = "gb::cpu";
+pub const PPU: &str = "gb::ppu";
+pub const DMA: &str = "gb::dma";
+pub const MAPPER: &str = "gb::mapper";
+pub const INTERRUPT: &str = "gb::interrupt";
+pub const SERIAL: &str = "gb::serial";
+pub const BUS: &str = "gb::bus";
+pub const VIOLATION: &str = "gb::violation";
+
+/// Like [`log::trace!`], but compiled out entirely unless the `trace-heavy` feature is enabled.
+/// For records made on every instruction or dot.
+macro_rules! trace_heavy {
+    ($($arg:tt)+) => {
+        if cfg!(feature = "trace-heavy") {
+            log::trace!($($arg)+);
+        }
+    };
+}
+pub(crate) use trace_heavy;
@@ -1,5 +1,76 @@
 use gb_cpu::CpuOutputPins;
 
+use crate::GbError;
+
+/// What work RAM, high RAM, VRAM and OAM contain at power on. Real hardware powers up with
+/// unpredictable contents, so emulating that needs a seed to stay reproducible.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RamInit {
+    /// Every byte is $00
+    #[default]
+    Zero,
+    /// Alternating runs of 8 bytes of $00 and 8 bytes of $FF, starting again at the start of each
+    /// memory, which is similar to what many DMG units power up with. The exact pattern varies
+    /// between units.
+    Nintendo,
+    /// Bytes from a pseudorandom generator with this seed. The same seed gives the same contents
+    /// on every platform. Work RAM is filled first, then high RAM, VRAM and OAM.
+    Random(u64),
+}
+
+impl RamInit {
+    /// Start filling memories with this pattern, in the order they are passed to
+    /// [`RamFiller::fill`]
+    pub fn filler(self) -> RamFiller {
+        let seed = match self {
+            RamInit::Random(seed) => seed,
+            _ => 0,
+        };
+        RamFiller {
+            init: self,
+            rng: SplitMix64(seed),
+        }
+    }
+}
+
+/// Fills one memory after another with a [`RamInit`] pattern. The random generator carries on
+/// from one memory to the next, so they don't all get the same bytes.
+pub struct RamFiller {
+    init: RamInit,
+    rng: SplitMix64,
+}
+
+impl RamFiller {
+    pub fn fill(&mut self, ram: &mut [u8]) {
+        match self.init {
+            RamInit::Zero => ram.fill(0),
+            RamInit::Nintendo => ram
+                .iter_mut()
+                .enumerate()
+                .for_each(|(i, byte)| *byte = if i & 8 == 0 { 0x00 } else { 0xFF }),
+            RamInit::Random(_) => {
+                for chunk in ram.chunks_mut(8) {
+                    let len = chunk.len();
+                    chunk.copy_from_slice(&self.rng.next().to_le_bytes()[..len]);
+                }
+            }
+        }
+    }
+}
+
+/// SplitMix64, which is small and gives the same sequence everywhere
+pub(crate) struct SplitMix64(pub u64);
+
+impl SplitMix64 {
+    pub fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
 pub struct Memory {
     work_ram_1: [u8; 0x1000],
     work_ram_2: [u8; 0x1000],
@@ -15,8 +86,49 @@ impl Memory {
         }
     }
 
-    fn address_is_in_range(addr: u16) -> bool {
-        matches!(addr, 0xC000..=0xDFFF | 0xFF80..=0xFFFE)
+    /// Overwrite work RAM and high RAM with their power on contents. Work RAM is treated as one
+    /// memory, even though it is split in two.
+    pub fn fill(&mut self, filler: &mut RamFiller) {
+        let mut work_ram = [0; 0x2000];
+        filler.fill(&mut work_ram);
+        self.work_ram_1.copy_from_slice(&work_ram[..0x1000]);
+        self.work_ram_2.copy_from_slice(&work_ram[0x1000..]);
+        filler.fill(&mut self.high_ram);
+    }
+
+    /// The byte at `addr`, if it is in work RAM or high RAM
+    fn byte(&self, addr: u16) -> Option<&u8> {
+        match addr {
+            0xC000..=0xCFFF => Some(&self.work_ram_1[(addr - 0xC000) as usize]),
+            0xD000..=0xDFFF => Some(&self.work_ram_2[(addr - 0xD000) as usize]),
+            0xFF80..=0xFFFE => Some(&self.high_ram[(addr - 0xFF80) as usize]),
+            _ => None,
+        }
+    }
+
+    fn byte_mut(&mut self, addr: u16) -> Option<&mut u8> {
+        match addr {
+            0xC000..=0xCFFF => Some(&mut self.work_ram_1[(addr - 0xC000) as usize]),
+            0xD000..=0xDFFF => Some(&mut self.work_ram_2[(addr - 0xD000) as usize]),
+            0xFF80..=0xFFFE => Some(&mut self.high_ram[(addr - 0xFF80) as usize]),
+            _ => None,
+        }
+    }
+
+    /// Read a byte from work RAM or high RAM, returning an error if `addr` is not backed by this chip
+    pub fn read(&self, addr: u16) -> Result<u8, GbError> {
+        self.byte(addr)
+            .copied()
+            .ok_or(GbError::AddressOutOfRange(addr))
+    }
+
+    /// Write a byte to work RAM or high RAM, returning an error if `addr` is not backed by this chip
+    pub fn write(&mut self, addr: u16, data: u8) -> Result<(), GbError> {
+        let byte = self
+            .byte_mut(addr)
+            .ok_or(GbError::AddressOutOfRange(addr))?;
+        *byte = data;
+        Ok(())
     }
 }
 
@@ -26,40 +138,58 @@ impl Default for Memory {
     }
 }
 
+/// # Panics
+/// Panics if `index` is not in work RAM or high RAM. Use [`Memory::read`] for unchecked addresses.
 impl std::ops::Index<u16> for Memory {
     type Output = u8;
     fn index(&self, index: u16) -> &Self::Output {
-        match index {
-            0xC000..=0xCFFF => &self.work_ram_1[(index - 0xC000) as usize],
-            0xD000..=0xDFFF => &self.work_ram_2[(index - 0xD000) as usize],
-            0xFF80..=0xFFFE => &self.high_ram[(index - 0xFF80) as usize],
-            _ => panic!("Out of bounds: {}", index),
-        }
+        self.byte(index)
+            .unwrap_or_else(|| panic!("Out of bounds: {}", index))
     }
 }
 
+/// # Panics
+/// Panics if `index` is not in work RAM or high RAM. Use [`Memory::write`] for unchecked addresses.
 impl std::ops::IndexMut<u16> for Memory {
     fn index_mut(&mut self, index: u16) -> &mut Self::Output {
-        match index {
-            0xC000..=0xCFFF => &mut self.work_ram_1[(index - 0xC000) as usize],
-            0xD000..=0xDFFF => &mut self.work_ram_2[(index - 0xD000) as usize],
-            0xFF80..=0xFFFE => &mut self.high_ram[(index - 0xFF80) as usize],
-            _ => panic!("Out of bounds: {}", index),
-        }
+        self.byte_mut(index)
+            .unwrap_or_else(|| panic!("Out of bounds: {}", index))
     }
 }
 
 impl super::Chip for Memory {
-    fn clock(&mut self, input: CpuOutputPins, data: &mut u8, _interrupt_request: &mut u8) {
-        if Self::address_is_in_range(input.addr()) {
-            match input {
-                CpuOutputPins::Read { addr } => {
-                    *data = self[addr];
+    fn clock(
+        &mut self,
+        input: CpuOutputPins,
+        data: &mut u8,
+        _interrupt_request: &mut u8,
+        _ctx: &super::ClockContext,
+    ) {
+        // Every chip sees every access, including stack pushes to wherever SP points, so anything
+        // outside work RAM and high RAM is ignored rather than indexed
+        match input {
+            CpuOutputPins::Read { addr } => {
+                if let Some(&byte) = self.byte(addr) {
+                    *data = byte;
                 }
-                CpuOutputPins::Write { addr, data } => {
-                    self[addr] = data;
+            }
+            CpuOutputPins::Write { addr, data } => {
+                if let Some(byte) = self.byte_mut(addr) {
+                    *byte = data;
                 }
             }
         }
     }
+
+    fn chip_select(&self) -> Vec<std::ops::RangeInclusive<u16>> {
+        vec![0xC000..=0xDFFF, 0xFF80..=0xFFFE]
+    }
+
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    fn next_event(&self, _ctx: &super::ClockContext) -> u64 {
+        u64::MAX
+    }
 }
@@ -0,0 +1,28 @@
+//! Changing memory from tools like memory editors and debuggers.
+//!
+//! There are three ways to write a byte, depending on what the tool wants to happen:
+//!
+//! - [`Gameboy::poke`] writes it the way the CPU would, so that writes to IO registers have their
+//!   usual effects, and reports what became of it as a [`PokeOutcome`].
+//! - [`Gameboy::poke_silent`] changes RAM directly, even where the CPU would be locked out, and
+//!   refuses anything else.
+//! - [`Gameboy::freeze`] writes it again at the start of every VBlank, the same way GameShark codes
+//!   are applied, so the game can't change it for long.
+//!
+//! [`Gameboy::poke`]: super::Gameboy::poke
+//! [`Gameboy::poke_silent`]: super::Gameboy::poke_silent
+//! [`Gameboy::freeze`]: super::Gameboy::freeze
+
+/// What became of a write made with [`Gameboy::poke`](super::Gameboy::poke)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PokeOutcome {
+    /// VRAM, cartridge RAM, WRAM, OAM or HRAM now holds the value
+    WroteRam,
+    /// An IO register, IF or IE was written, with whatever effect that has
+    WroteRegister,
+    /// The write to ROM set one of the cartridge's mapper registers, which may have switched banks
+    MapperRegister,
+    /// Nothing took the write: it went to ROM with no register behind it, an unused address, or
+    /// memory that is locked out, like VRAM while the PPU is drawing or disabled cartridge RAM
+    Ignored,
+}
@@ -0,0 +1,127 @@
+//! Finding where a game keeps a value, the way cheat devices search for codes.
+//!
+//! A [`MemorySearch`] starts with every address in WRAM, cartridge RAM and HRAM as a candidate.
+//! Each filter reads memory again with [`Gameboy::peek`], keeps the candidates whose value passes
+//! compared to the previous read, and remembers the new values for the next filter. Once the
+//! candidates are narrowed down, a value can be frozen with [`Gameboy::freeze`] or changed with
+//! [`Gameboy::poke`].
+//!
+//! ```
+//! use gb_core::gameboy::{memory_search::MemorySearch, Gameboy};
+//!
+//! # let mut gameboy = Gameboy::with_program(&[0x18, 0xFE], 0x0150).unwrap();
+//! let mut search = MemorySearch::<u8>::new(&gameboy);
+//! gameboy.run_frames(1);
+//! search.unchanged(&gameboy);
+//! for (addr, value) in search.candidates() {
+//!     println!("${:04X} = {}", addr, value);
+//! }
+//! ```
+
+use std::ops::RangeInclusive;
+
+use super::Gameboy;
+
+/// A width of value that can be searched for. Values wider than a byte are little-endian.
+pub trait SearchValue: Copy + Ord + Into<i64> {
+    /// Size in bytes
+    const SIZE: u16;
+
+    fn peek(gameboy: &Gameboy, addr: u16) -> Self;
+}
+
+impl SearchValue for u8 {
+    const SIZE: u16 = 1;
+
+    fn peek(gameboy: &Gameboy, addr: u16) -> Self {
+        gameboy.peek(addr)
+    }
+}
+
+impl SearchValue for u16 {
+    const SIZE: u16 = 2;
+
+    fn peek(gameboy: &Gameboy, addr: u16) -> Self {
+        u16::from_le_bytes([gameboy.peek(addr), gameboy.peek(addr + 1)])
+    }
+}
+
+/// The addresses searched: WRAM, as much cartridge RAM as is mapped at once, and HRAM
+fn searched_ranges(gameboy: &Gameboy) -> Vec<RangeInclusive<u16>> {
+    let mut ranges = vec![0xC000..=0xDFFF, 0xFF80..=0xFFFE];
+    if let Some(ram) = gameboy.cart.ram().filter(|ram| !ram.is_empty()) {
+        ranges.insert(0, 0xA000..=0xA000 + ram.len().min(0x2000) as u16 - 1);
+    }
+    ranges
+}
+
+/// A set of addresses that might hold a value, narrowed down by each filter
+#[derive(Debug, Clone)]
+pub struct MemorySearch<V: SearchValue = u8> {
+    /// Each candidate and its value as of the last filter
+    candidates: Vec<(u16, V)>,
+}
+
+impl<V: SearchValue> MemorySearch<V> {
+    /// Start a search with every address as a candidate, and remember their values for the first
+    /// filter
+    pub fn new(gameboy: &Gameboy) -> Self {
+        let candidates = searched_ranges(gameboy)
+            .into_iter()
+            // A value must fit in the same area of memory
+            .flat_map(|range| *range.start()..=*range.end() + 1 - V::SIZE)
+            .map(|addr| (addr, V::peek(gameboy, addr)))
+            .collect();
+        MemorySearch { candidates }
+    }
+
+    /// The addresses still in the running, and their values as of the last filter
+    pub fn candidates(&self) -> Vec<(u16, V)> {
+        self.candidates.clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.candidates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.candidates.is_empty()
+    }
+
+    /// Read each candidate again, keep those for which `keep(old, new)` is true, and return how
+    /// many are left
+    pub fn filter(&mut self, gameboy: &Gameboy, mut keep: impl FnMut(V, V) -> bool) -> usize {
+        self.candidates.retain_mut(|(addr, value)| {
+            let new = V::peek(gameboy, *addr);
+            let old = std::mem::replace(value, new);
+            keep(old, new)
+        });
+        self.candidates.len()
+    }
+
+    /// Keep the candidates that hold `value` now
+    pub fn equal_to(&mut self, gameboy: &Gameboy, value: V) -> usize {
+        self.filter(gameboy, |_, new| new == value)
+    }
+
+    pub fn changed(&mut self, gameboy: &Gameboy) -> usize {
+        self.filter(gameboy, |old, new| new != old)
+    }
+
+    pub fn unchanged(&mut self, gameboy: &Gameboy) -> usize {
+        self.filter(gameboy, |old, new| new == old)
+    }
+
+    pub fn increased(&mut self, gameboy: &Gameboy) -> usize {
+        self.filter(gameboy, |old, new| new > old)
+    }
+
+    pub fn decreased(&mut self, gameboy: &Gameboy) -> usize {
+        self.filter(gameboy, |old, new| new < old)
+    }
+
+    /// Keep the candidates that have changed by exactly `delta`, without wrapping around
+    pub fn changed_by(&mut self, gameboy: &Gameboy, delta: i64) -> usize {
+        self.filter(gameboy, |old, new| new.into() - old.into() == delta)
+    }
+}
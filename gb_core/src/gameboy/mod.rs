@@ -1,13 +1,112 @@
+pub mod apu;
+#[cfg(feature = "async")]
+pub mod async_adapter;
+#[cfg(feature = "batch")]
+pub mod batch;
+pub mod breakpoints;
+mod builder;
+pub mod bus_trace;
+pub mod call_stack;
+#[cfg(feature = "capture")]
+pub mod capture;
+pub mod capture_triggers;
 pub mod cart;
+pub mod cheats;
+mod chip;
+pub mod coverage;
+pub mod crash_dump;
+pub mod debug_opcodes;
+pub mod events;
+pub mod expr;
+#[cfg(feature = "unstable-internals")]
+pub mod fault_injection;
+#[cfg(feature = "gdb")]
+pub mod gdb;
+pub mod io_hook;
+pub mod journal;
 pub mod joypad;
+pub mod logging;
 pub mod memory;
+pub mod memory_editor;
+pub mod memory_search;
+pub mod observer;
+pub mod perf_stats;
 pub mod ppu;
+pub mod profiler;
+pub mod rtc;
+pub mod savestate;
+mod scheduler;
+#[cfg(feature = "scripting")]
+pub mod script;
+pub mod serial;
+pub mod sgb;
+pub mod symbols;
+pub mod system_counter;
+pub mod test_pattern;
 pub mod timer;
+pub mod trace_diff;
+pub mod violations;
 
-use gb_cpu::{CpuInputPins, CpuOutputPins, CpuRunner, CpuRunnerYield};
-use memory::Memory;
+use std::{collections::BTreeMap, num::NonZeroU8, sync::Arc};
 
-use self::{cart::Cart, ppu::Ppu};
+use breakpoints::{Breakpoint, BreakpointId, Breakpoints};
+use bus_trace::{BusEvent, BusTrace, BusTracer};
+use call_stack::{CallStack, StackFrame};
+use cheats::{Cheat, CheatId, CheatParseError, FreezeId};
+use coverage::{CoverageSnapshot, CoverageTracker};
+use crash_dump::{CrashDump, MemoryWindow, PcHistory, PcRecord};
+use debug_opcodes::{DebugOpcodeAction, DebugOpcodeEvent, DebugOpcodeHandler};
+use events::{Event, EventMask, EventReceiver, EventRecord, Interrupt, SubscriptionId};
+use expr::{Expr, ExprError};
+use gb_cpu::{CpuInputPins, CpuOutputPins, CpuRunner, CpuRunnerYield, InterruptDispatch};
+use io_hook::IoHook;
+use journal::{InstructionRecord, Journal};
+use logging::trace_heavy;
+use memory::{Memory, RamInit};
+use memory_editor::PokeOutcome;
+use perf_stats::{PerfStats, PerfStatsSnapshot, Subsystem};
+use profiler::{ProfileEntry, Profiler};
+use scheduler::Scheduler;
+use symbols::{SymError, SymbolRef, SymbolTable};
+use system_counter::SystemCounter;
+use timer::TimerClock;
+use violations::{RomWritePolicy, Violation, Violations};
+
+pub use self::builder::{AccuracyLevel, BusInterleave, GameboyBuilder, Model, PpuBackend};
+#[cfg(feature = "unstable-internals")]
+pub use self::chip::Chip;
+#[cfg(not(feature = "unstable-internals"))]
+pub(crate) use self::chip::Chip;
+use self::ppu::{color::RgbaColor, frame::post_process::PostProcess, Ppu};
+pub use self::serial::SerialConnection;
+use crate::threading::{dyn_maybe_send, MaybeSend};
+use crate::GbError;
+
+/// Frequency of the base clock, in T-cycles per second
+pub const T_CYCLES_PER_SECOND: u64 = 4_194_304;
+
+/// Number of events an [`EventReceiver`] can hold before further events are dropped
+pub const EVENT_QUEUE_CAPACITY: usize = 4096;
+
+/// Opcodes that lock up the CPU instead of executing
+const ILLEGAL_OPCODES: [u8; 11] = [
+    0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB, 0xEC, 0xED, 0xF4, 0xFC, 0xFD,
+];
+
+/// Called with LY and the finished row of pixels each time a scanline is drawn
+pub type ScanlineCallback = Box<dyn_maybe_send!(FnMut(u8, &[RgbaColor; 160]))>;
+
+/// Called with the whole of battery-backed cartridge RAM when it needs saving
+pub type SaveCallback = Box<dyn_maybe_send!(FnMut(&[u8]))>;
+
+/// The callback registered by [`Gameboy::set_save_writer`]
+struct SaveWriter {
+    callback: SaveCallback,
+    /// Frames between saves
+    interval: u64,
+    /// The frame count when the RAM was last saved, or when the writer was set
+    last_frame: u64,
+}
 
 pub struct Gameboy {
     pub cpu: CpuRunner,
@@ -15,165 +114,1743 @@ pub struct Gameboy {
     pub memory: Memory,
     pub cart: cart::Cart,
     timer: timer::Timer,
+    counter: SystemCounter,
+    apu: apu::Apu,
     pub joypad: joypad::Joypad,
+    pub serial: serial::Serial,
+    /// Extra chips attached through [`GameboyBuilder::chip`]
+    chips: Vec<Box<dyn_maybe_send!(Chip)>>,
+    /// Handlers for unmapped IO registers, by address
+    io_hooks: BTreeMap<u16, Box<dyn_maybe_send!(IoHook)>>,
+    /// Addresses claimed by more than one chip, and the first two to claim them. Empty unless
+    /// built with [`GameboyBuilder::allow_chip_conflicts`].
+    bus_conflicts: BTreeMap<u16, [&'static str; 2]>,
+    /// Which of the chips after the PPU have something to do on each M-cycle
+    scheduler: Scheduler,
+    /// Mapped over $0000-$00FF until disabled by writing to $FF50
+    boot_rom: Option<Box<[u8; 0x100]>>,
+    /// The boot ROM the Gameboy was built with, which is mapped again by [`Gameboy::reset`]
+    boot_rom_image: Option<Box<[u8; 0x100]>>,
+    /// What memory is filled with by [`Gameboy::reset`] and [`Gameboy::set_ram_init`]
+    ram_init: RamInit,
+    model: Model,
+    accuracy: AccuracyLevel,
+    scanline_callback: Option<ScanlineCallback>,
+    save_writer: Option<SaveWriter>,
+    cheats: cheats::Cheats,
+    breakpoints: Breakpoints,
+    debug_opcode_handler: Option<DebugOpcodeHandler>,
+    oam_bug: bool,
+    violations: Violations,
+    events: events::EventBus,
+    /// Created the first time profiling is enabled
+    profiler: Option<Box<Profiler>>,
+    profiling: bool,
+    /// Only present while call stack tracking is enabled
+    call_stack: Option<CallStack>,
+    /// Only present while coverage tracking is enabled
+    coverage: Option<Box<CoverageTracker>>,
+    /// Only present while the bus is being traced
+    bus_tracer: Option<Box<BusTracer>>,
+    perf: PerfStats,
+    journal: Journal,
+    pc_history: PcHistory,
+    /// Taken when the CPU locked up
+    last_crash: Option<Box<CrashDump>>,
+    /// Shared with the crash dumps taken while they were loaded
+    symbols: Arc<SymbolTable>,
+    #[cfg(feature = "capture")]
+    gif_recorder: Option<Box<capture::GifRecorder>>,
+    capture_triggers: capture_triggers::CaptureTriggers,
+    /// Created by the first call to [`Gameboy::observer`]
+    observers: Option<Box<observer::Observers>>,
+    /// Set by [`Gameboy::set_scanline_idle_tracking`]
+    scanline_idle: Option<Box<ppu::frame_info::ScanlineIdle>>,
+    /// The scanlines completed during [`Gameboy::run_until_line`], which collects them while
+    /// `racing_beam` is set
+    raced_lines: Vec<RgbaColor>,
+    racing_beam: bool,
 
     cpu_input: CpuInputPins,
+    /// The last M-cycle fetched an opcode, so the CPU is between two instructions
+    fetched: bool,
     interrupt_enable: u8,
     interrupt_request: u8,
+    cycles: u64,
+    /// CPU M-cycles for every M-cycle of the rest of the system
+    overclock: NonZeroU8,
+    timer_clock: TimerClock,
+    /// The extra M-cycles the CPU has left before the rest of the system runs again
+    extra_cycles: u8,
+    /// The M-cycle being run is one of the CPU's extra ones
+    extra_cycle: bool,
 }
 
+// Frontends and `batch` run Gameboys on other threads, so nothing they hold can be tied to one
+#[cfg(not(feature = "single-thread"))]
+static_assertions::assert_impl_all!(Gameboy: Send);
+static_assertions::assert_impl_all!(EventReceiver: Send);
+static_assertions::assert_impl_all!(ppu::frame_sink::FrameReceiver: Send);
+#[cfg(not(feature = "single-thread"))]
+static_assertions::assert_impl_all!(ppu::frame_pool::SharedFrame: Send, Sync);
+#[cfg(not(feature = "single-thread"))]
+static_assertions::assert_impl_all!(observer::GbObserver: Send, Sync);
+// Unless everything stays on one thread, and frames are counted without atomics
+#[cfg(feature = "single-thread")]
+static_assertions::assert_not_impl_any!(Gameboy: Send);
+#[cfg(feature = "single-thread")]
+static_assertions::assert_not_impl_any!(ppu::frame_pool::SharedFrame: Send, Sync);
+
 impl Gameboy {
-    pub fn new(rom: Vec<u8>) -> Result<Self, &'static str> {
-        Ok(Gameboy {
-            cpu: gb_cpu::Cpu::default().runner(),
-            ppu: ppu::Ppu::new(),
-            cpu_input: CpuInputPins::default(),
-            memory: Memory::new(),
-            cart: Cart::new(rom)?,
-            timer: timer::Timer::default(),
-            joypad: joypad::Joypad::default(),
-
-            interrupt_enable: 0,
-            interrupt_request: 0,
-        })
+    /// Shorthand for building a DMG with `rom` in the cartridge slot and nothing else attached
+    pub fn new(rom: Vec<u8>) -> Result<Self, GbError> {
+        GameboyBuilder::new().rom(rom).build()
+    }
+
+    /// Like [`Gameboy::new`], but the ROM image is shared rather than copied, so that any number
+    /// of Gameboys can run the same ROM from one buffer
+    pub fn from_shared_rom(rom: Arc<[u8]>) -> Result<Self, GbError> {
+        GameboyBuilder::new().rom(rom).build()
+    }
+
+    /// Run `code` without a cartridge, by building a 32KiB ROM with a valid header around it. The
+    /// code is copied to `load_addr`, which must leave room for it below $8000, and the CPU starts
+    /// there with the registers the boot ROM leaves behind.
+    ///
+    /// ```
+    /// use gb_core::gameboy::Gameboy;
+    ///
+    /// #[rustfmt::skip]
+    /// let mut gameboy = Gameboy::with_program(&[
+    ///     0x3E, 0x42,       // LD A, $42
+    ///     0xEA, 0x00, 0xC0, // LD ($C000), A
+    ///     0x18, 0xFE,       // JR -2
+    /// ], 0x0150).unwrap();
+    /// gameboy.run_frames(1);
+    /// assert_eq!(gameboy.peek(0xC000), 0x42);
+    /// ```
+    pub fn with_program(code: &[u8], load_addr: u16) -> Result<Self, GbError> {
+        let mut gameboy = Self::new(cart::header::flat_rom(code, load_addr, "")?)?;
+        gameboy.reset(ResetKind::PowerCycle);
+        gameboy.cpu.cpu.registers.pc = load_addr;
+        Ok(gameboy)
+    }
+
+    /// Fill work RAM, high RAM, VRAM and OAM with `init`, as if the console had just been powered
+    /// on, and again on every [`ResetKind::PowerCycle`]. Nothing else is reset, so this is
+    /// normally called straight after creating the Gameboy, or left to
+    /// [`GameboyBuilder::ram_init`].
+    pub fn set_ram_init(&mut self, init: RamInit) {
+        self.ram_init = init;
+        let mut filler = init.filler();
+        self.memory.fill(&mut filler);
+        self.ppu.fill_ram(&mut filler);
+    }
+
+    pub fn builder() -> GameboyBuilder {
+        GameboyBuilder::new()
+    }
+
+    pub fn model(&self) -> Model {
+        self.model
+    }
+
+    pub fn accuracy(&self) -> AccuracyLevel {
+        self.accuracy
+    }
+
+    /// Number of T-cycles elapsed since power on. This keeps counting while the LCD is off.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Register a callback to run whenever the PPU finishes drawing a scanline (at the transition
+    /// from mode 3 to mode 0), replacing any previous callback.
+    ///
+    /// The row passed to the callback is taken from the frame currently being drawn, so it is
+    /// available before the rest of the frame is finished.
+    pub fn on_scanline(
+        &mut self,
+        callback: impl FnMut(u8, &[RgbaColor; 160]) + MaybeSend + 'static,
+    ) {
+        self.scanline_callback = Some(Box::new(callback));
+    }
+
+    /// Emulate the DMG bug where accessing OAM, or incrementing or decrementing a 16-bit register
+    /// that points into OAM, corrupts OAM while the PPU is scanning it. This is off by default.
+    ///
+    /// Of the instructions that increment or decrement registers, only INC rr, DEC rr, PUSH, POP
+    /// and LD (HL+/-) trigger it. CALL, RET, RST and interrupts do not.
+    pub fn set_oam_bug_emulation(&mut self, enabled: bool) {
+        self.oam_bug = enabled;
+    }
+
+    pub fn oam_bug_emulation(&self) -> bool {
+        self.oam_bug
+    }
+
+    /// Decide what happens to writes to $0000-$7FFF. By default they go to the cartridge, which
+    /// ignores the ones that don't land on a mapper register, hiding bugs in homebrew on
+    /// cartridges without a mapper. See [`violations`] for how violations are reported.
+    pub fn set_rom_write_policy(&mut self, policy: RomWritePolicy) {
+        self.violations.rom_write_policy = policy;
+    }
+
+    pub fn rom_write_policy(&self) -> RomWritePolicy {
+        self.violations.rom_write_policy
+    }
+
+    /// Report every opcode fetched from VRAM, OAM or the IO registers as a
+    /// [`Violation::Execution`]. This is off by default.
+    pub fn set_execution_check(&mut self, enabled: bool) {
+        self.violations.check_execution = enabled;
+    }
+
+    pub fn execution_check(&self) -> bool {
+        self.violations.check_execution
+    }
+
+    /// Report every CPU write to a PPU register that lands in an M-cycle where the PPU also used
+    /// that register, as an [`Event::OrderingHazard`](events::Event::OrderingHazard), which is
+    /// also logged to `gb::ppu`. What is drawn then depends on where the write lands among the
+    /// PPU's dots, which [`BusInterleave`] decides, so these are the places to look for ordering
+    /// bugs. Writes while the LCD is off are left out, since the PPU only starts running once
+    /// LCDC is written. This is off by default.
+    pub fn set_ordering_check(&mut self, enabled: bool) {
+        self.ppu.ordering_check = enabled;
+    }
+
+    pub fn ordering_check(&self) -> bool {
+        self.ppu.ordering_check
+    }
+
+    /// Only clock the chips that are accessed, or have something to do, on each M-cycle. This is
+    /// on by default, and behaves exactly the same as clocking every chip on every M-cycle, which
+    /// turning it off does instead. It doesn't skip any M-cycles: the CPU and the PPU still run on
+    /// every one, and are most of the time spent, so this is only a little faster.
+    pub fn set_chip_scheduling(&mut self, enabled: bool) {
+        self.scheduler.set_enabled(enabled);
+    }
+
+    pub fn chip_scheduling(&self) -> bool {
+        self.scheduler.is_enabled()
+    }
+
+    /// Give the CPU `factor` M-cycles for every M-cycle the rest of the system runs, so that games
+    /// that slow down when there is too much to do in a frame keep up. 1 is stock speed. The PPU,
+    /// and with it the frame rate, OAM DMA, serial and the interrupts the chips raise stay on the
+    /// stock clock, and so do DIV and TIMA unless [`Gameboy::set_timer_clock`] says otherwise.
+    ///
+    /// Each of the CPU's extra M-cycles is a [`Gameboy::tick`] of its own. The CPU's access goes
+    /// to the chips as usual, but no time passes for them, and [`Gameboy::cycles`] doesn't count
+    /// it. The CPU only gets extra M-cycles while it is running: while it is halted, or an OAM DMA
+    /// transfer is copying, it waits on the stock clock, so it wakes for an interrupt as many
+    /// stock M-cycles after it is requested as it would without overclocking.
+    pub fn set_cpu_overclock(&mut self, factor: NonZeroU8) {
+        self.overclock = factor;
+        self.extra_cycles = self.extra_cycles.min(factor.get() - 1);
+        self.scheduler.wake_all();
+    }
+
+    pub fn cpu_overclock(&self) -> NonZeroU8 {
+        self.overclock
+    }
+
+    /// Choose whether DIV and TIMA speed up along with a CPU overclocked with
+    /// [`Gameboy::set_cpu_overclock`]. They stay on the stock clock by default.
+    pub fn set_timer_clock(&mut self, clock: TimerClock) {
+        self.timer_clock = clock;
+        self.scheduler.wake_all();
+    }
+
+    pub fn timer_clock(&self) -> TimerClock {
+        self.timer_clock
+    }
+
+    /// Start or stop counting how many times each instruction is executed, and how many M-cycles
+    /// it takes. Stopping keeps the counts collected so far.
+    ///
+    /// An instruction's M-cycles are the time from its opcode fetch to the next one, so they
+    /// include any interrupt dispatch, HALT or OAM DMA that happens in between.
+    pub fn enable_profiling(&mut self, enabled: bool) {
+        let profiler = self
+            .profiler
+            .get_or_insert_with(|| Box::new(Profiler::new()));
+        if !enabled {
+            profiler.pause();
+        }
+        self.profiling = enabled;
+    }
+
+    /// The `top_n` instructions that took the most M-cycles while profiling was enabled, sorted
+    /// from most to least
+    pub fn profile_report(&self, top_n: usize) -> Vec<ProfileEntry> {
+        self.profiler
+            .as_ref()
+            .map_or_else(Vec::new, |profiler| profiler.report(top_n))
+    }
+
+    /// Clear the counts collected by the profiler
+    pub fn reset_profile(&mut self) {
+        if let Some(profiler) = &mut self.profiler {
+            profiler.reset();
+        }
+    }
+
+    /// Start or stop reconstructing the call stack from the CALL, RST and RET instructions the CPU
+    /// executes, and from interrupts. Stopping clears the call stack.
+    ///
+    /// Frames are popped once SP moves above their return address, so games that adjust SP
+    /// directly or return to an address they pushed themselves don't leave stale frames behind.
+    pub fn track_call_stack(&mut self, enabled: bool) {
+        self.call_stack = enabled.then(CallStack::default);
+    }
+
+    /// The functions and interrupt handlers that have not returned yet, innermost last. This is
+    /// empty unless enabled with [`Gameboy::track_call_stack`].
+    pub fn call_stack(&self) -> &[StackFrame] {
+        self.call_stack.as_ref().map_or(&[], |c| c.frames())
+    }
+
+    /// The call stack as text, innermost first with one frame to a line, naming addresses with
+    /// the symbols loaded by [`Gameboy::load_symbols`]
+    pub fn call_stack_report(&self) -> String {
+        let mut report = String::new();
+        for frame in self.call_stack().iter().rev() {
+            let bank = frame.bank as u16;
+            let target = self.symbols.location(bank, frame.target).to_string();
+            let call_site = self.symbols.location(bank, frame.call_site).to_string();
+            let how = if frame.interrupt {
+                "interrupted"
+            } else {
+                "called from"
+            };
+            report += &format!("{} {} {}\n", target, how, call_site.trim_start());
+        }
+        report
+    }
+
+    /// Load labels from the text of a `.sym` file, replacing any loaded before, and return how
+    /// many there are. See [`symbols`] for the format.
+    pub fn load_symbols(&mut self, sym_text: &str) -> Result<usize, SymError> {
+        let symbols = SymbolTable::parse(sym_text)?;
+        let len = symbols.len();
+        self.symbols = Arc::new(symbols);
+        Ok(len)
+    }
+
+    /// The labels loaded with [`Gameboy::load_symbols`]
+    pub fn symbols(&self) -> &SymbolTable {
+        &self.symbols
+    }
+
+    /// Names `addr` by the nearest label at or before it, in whichever bank is mapped there now
+    pub fn symbolize(&self, addr: u16) -> Option<SymbolRef<'_>> {
+        self.symbols.symbolize(self.mapped_bank(addr), addr)
+    }
+
+    /// The ROM or cartridge RAM bank mapped at `addr`, or 0 for memory that isn't banked
+    pub(crate) fn mapped_bank(&self, addr: u16) -> u16 {
+        match addr {
+            0x4000..=0x7FFF => self.cart.rom_bank(),
+            0xA000..=0xBFFF => self.cart.ram_bank() as u16,
+            _ => 0,
+        }
+    }
+
+    /// The addresses of the last instructions executed, oldest first. Up to
+    /// [`PC_HISTORY_LEN`](crash_dump::PC_HISTORY_LEN) are always kept.
+    pub fn pc_history(&self) -> impl Iterator<Item = PcRecord> + '_ {
+        self.pc_history.iter()
+    }
+
+    /// Collect the machine state into a report for working out how a game crashed
+    pub fn crash_dump(&self) -> CrashDump {
+        let cpu = &self.cpu.cpu;
+        let peek = |addr| self.peek(addr);
+        let last_pc = self.instruction_pc();
+        CrashDump {
+            cycle: self.cycles,
+            history: self.pc_history.iter().collect(),
+            registers: cpu.registers,
+            ime: cpu.ime,
+            stack: MemoryWindow::around(cpu.registers.sp, peek),
+            code: MemoryWindow::around(last_pc, peek),
+            interrupt_enable: self.interrupt_enable,
+            interrupt_request: self.interrupt_request,
+            ppu_mode: self.ppu.stat.bits() & 3,
+            ly: self.ppu.ly,
+            rom_bank: self.cart.rom_bank(),
+            ram_bank: self.cart.ram_bank(),
+            symbols: self.symbols.clone(),
+        }
+    }
+
+    /// The address of the instruction being executed. PC has already moved past its opcode.
+    pub(crate) fn instruction_pc(&self) -> u16 {
+        self.pc_history
+            .last()
+            .map_or(self.cpu.cpu.registers.pc, |record| record.pc)
+    }
+
+    /// The crash dump taken when the CPU last locked up on an illegal opcode
+    pub fn last_crash(&self) -> Option<&CrashDump> {
+        self.last_crash.as_deref()
+    }
+
+    /// Start or stop recording every address the CPU executes, reads and writes, separately for
+    /// each ROM and cartridge RAM bank. Stopping discards everything recorded so far.
+    pub fn track_coverage(&mut self, enabled: bool) {
+        self.coverage = enabled.then(Default::default);
+    }
+
+    /// A copy of the addresses recorded since [`Gameboy::track_coverage`] was enabled, which is
+    /// empty if it isn't
+    pub fn coverage(&self) -> CoverageSnapshot {
+        self.coverage
+            .as_ref()
+            .map_or_else(Default::default, |c| c.snapshot())
+    }
+
+    /// Record every access on the bus into `trace` from now on, replacing any trace already being
+    /// recorded
+    pub fn start_bus_trace(&mut self, trace: BusTrace) {
+        self.bus_tracer = Some(Box::new(BusTracer::new(trace, self.chips())));
+    }
+
+    /// Stop tracing the bus, and return the trace with anything that hasn't been drained
+    pub fn stop_bus_trace(&mut self) -> Option<BusTrace> {
+        self.bus_tracer.take().map(|tracer| tracer.trace)
+    }
+
+    /// Remove the accesses traced since the last call, oldest first. This is empty if the bus
+    /// isn't being traced.
+    pub fn bus_trace(&mut self) -> impl Iterator<Item = BusEvent> + '_ {
+        self.bus_tracer
+            .as_mut()
+            .into_iter()
+            .flat_map(|tracer| tracer.trace.drain())
+    }
+
+    /// Start or stop recording what each instruction changes, so that it can be undone with
+    /// [`Gameboy::step_back_instruction`]. Either way, everything recorded so far is discarded.
+    ///
+    /// Recording starts from the next opcode fetch. See the [`journal`] module for what is and
+    /// isn't restored.
+    pub fn debug_journal(&mut self, enabled: bool) {
+        self.journal.set_enabled(enabled);
+    }
+
+    /// Set the number of instructions that can be stepped back, which defaults to
+    /// [`journal::DEFAULT_CAPACITY`]. Older instructions are forgotten.
+    pub fn set_journal_capacity(&mut self, instructions: usize) {
+        self.journal.set_capacity(instructions);
+    }
+
+    /// Undo the last instruction recorded by [`Gameboy::debug_journal`], and return what it was.
+    ///
+    /// Returns `None` if the journal is disabled or empty, or if the CPU is partway through an
+    /// instruction, which happens after [`Gameboy::tick`] or during OAM DMA.
+    pub fn step_back_instruction(&mut self) -> Option<InstructionRecord> {
+        self.journal_step_back()
+    }
+
+    /// The number of frames and cycles run since [`Gameboy::reset_perf_stats`] was last called,
+    /// and, with the `perf-stats` feature, the emulation speed and where host time is being spent
+    pub fn perf_stats(&self) -> PerfStatsSnapshot {
+        self.perf.snapshot(self.ppu.frame_count, self.cycles)
+    }
+
+    /// Start counting frames and cycles from zero, and forget all timings
+    pub fn reset_perf_stats(&mut self) {
+        self.perf.reset(self.ppu.frame_count, self.cycles);
+    }
+
+    /// Set the number of frames the timings in [`Gameboy::perf_stats`] are averaged over, which
+    /// defaults to [`perf_stats::DEFAULT_WINDOW`]
+    pub fn set_perf_stats_window(&mut self, frames: usize) {
+        self.perf.set_window(frames);
+    }
+
+    /// Count the M-cycles the CPU spends halted on each of the 144 visible lines, for finding the
+    /// lines where a game's raster code leaves no time to spare. How the CPU spent each frame as a
+    /// whole is always counted, in [`FrameInfo::cpu`](ppu::frame_info::FrameInfo::cpu). Off by
+    /// default, and kept across power cycles.
+    pub fn set_scanline_idle_tracking(&mut self, enabled: bool) {
+        if enabled != self.scanline_idle.is_some() {
+            self.scanline_idle = enabled.then(Default::default);
+        }
+    }
+
+    pub fn scanline_idle_tracking(&self) -> bool {
+        self.scanline_idle.is_some()
+    }
+
+    /// The M-cycles the CPU spent halted on each visible line of the last completed frame, out of
+    /// the 114 in a line, or `None` if [`Gameboy::set_scanline_idle_tracking`] is off
+    pub fn scanline_idle_cycles(&self) -> Option<&[u16; 144]> {
+        self.scanline_idle
+            .as_ref()
+            .map(|scanline_idle| scanline_idle.last())
+    }
+
+    /// Call `writer` with the whole of battery-backed cartridge RAM whenever it has changed,
+    /// replacing any previous writer. The RAM is saved at the end of a frame, at most once every
+    /// `frames` frames, so a game that takes a few frames to write its save is normally saved in
+    /// one go once it has finished.
+    ///
+    /// This shares its record of what has changed with [`Gameboy::take_dirty_save_blocks`], so
+    /// only one of them should be used.
+    pub fn set_save_writer(
+        &mut self,
+        frames: u32,
+        writer: impl FnMut(&[u8]) + MaybeSend + 'static,
+    ) {
+        self.save_writer = Some(SaveWriter {
+            callback: Box::new(writer),
+            interval: frames as u64,
+            last_frame: self.ppu.frame_count,
+        });
+    }
+
+    /// Remove the writer registered by [`Gameboy::set_save_writer`]
+    pub fn clear_save_writer(&mut self) {
+        self.save_writer = None;
+    }
+
+    /// The 256 byte blocks of battery-backed cartridge RAM that have been written since the last
+    /// call, or since the RAM was loaded, with their offsets. Returns `None` if nothing has
+    /// changed or the cartridge has no battery.
+    pub fn take_dirty_save_blocks(&mut self) -> Option<Vec<cart::SaveBlock>> {
+        self.cart.take_dirty_blocks()
+    }
+
+    /// Call the save writer if it is due and the RAM has changed
+    fn write_save(&mut self) {
+        if let Some(writer) = &mut self.save_writer {
+            if self.ppu.frame_count >= writer.last_frame + writer.interval {
+                if let Some(save) = self.cart.take_save() {
+                    writer.last_frame = self.ppu.frame_count;
+                    (writer.callback)(save);
+                }
+            }
+        }
+    }
+
+    /// Remove the callback registered by [`Gameboy::on_scanline`]
+    pub fn clear_scanline_callback(&mut self) {
+        self.scanline_callback = None;
+    }
+
+    /// Register a callback to run with each command packet the game sends to the Super Game Boy,
+    /// replacing any previous callback. This does nothing unless the model is [`Model::Sgb`].
+    pub fn on_sgb_packet(&mut self, callback: impl FnMut(&[u8; 16]) + MaybeSend + 'static) {
+        if let Some(sgb) = &mut self.joypad.sgb {
+            sgb.set_callback(Some(Box::new(callback)));
+        }
+    }
+
+    /// Get the Gameboy ready to run the cartridge, which should be done once after building it,
+    /// and can be done again at any time to start the game over. Callbacks, breakpoints, cheats,
+    /// event subscriptions and the other settings made on the Gameboy are kept, as is
+    /// cartridge RAM, which is backed by the cartridge's battery. Extra chips attached with
+    /// `GameboyBuilder::chip` are left as they are.
+    ///
+    /// With a boot ROM, this leaves everything in its power-on state, starting from $0000, and the
+    /// boot ROM brings the registers and counters to where they are at $0100 on its own. Without
+    /// one, the CPU starts at $0100 with what the boot ROM would have left behind: the registers
+    /// from [`Model::boot_registers`], the IO registers it writes, DIV at
+    /// [`Model::boot_counter`] and the PPU [`Model::boot_ppu_dots`] into a frame. Those are all
+    /// as of the M-cycle that fetches the opcode at $0100.
+    pub fn reset(&mut self, kind: ResetKind) {
+        self.restart_hardware();
+        if kind == ResetKind::PowerCycle {
+            self.set_ram_init(self.ram_init);
+        }
+        if self.boot_rom.is_some() {
+            return;
+        }
+        self.cpu.cpu.registers = self.model.boot_registers();
+        self.counter = SystemCounter::starting_at(self.model.boot_counter());
+        // The timer's next edge moves with the counter
+        self.scheduler.wake_all();
+        self.apu = apu::Apu::after_boot();
+        self.ppu.opri_locked = true;
+        self.ppu.bgp = 0xFC;
+        self.ppu.skip_dots(self.model.boot_ppu_dots());
+        // The VBlank interrupt from the last frame of the boot ROM is never handled
+        self.interrupt_request = 0x01;
+    }
+
+    /// Put the CPU, the built-in chips and the state between them back as they are at power on,
+    /// apart from the contents of memory. Anything recorded about the code that ran before, like
+    /// the journal and the call stack, is forgotten, since it can't be undone or returned to.
+    fn restart_hardware(&mut self) {
+        self.cpu = gb_cpu::Cpu::default().runner();
+        self.cpu_input = CpuInputPins::default();
+        self.fetched = false;
+        self.extra_cycles = 0;
+        self.ppu.power_cycle();
+        self.cart.power_cycle();
+        self.timer = timer::Timer::default();
+        self.counter = SystemCounter::default();
+        self.apu = apu::Apu::default();
+        self.joypad.power_cycle();
+        self.serial.power_cycle();
+        self.scheduler.wake_all();
+        self.boot_rom = self.boot_rom_image.clone();
+        self.interrupt_enable = 0;
+        self.interrupt_request = 0;
+
+        // Everything counting time starts again from 0
+        self.cycles = 0;
+        self.cheats.applied_frame = 0;
+        self.violations.restart();
+        if let Some(writer) = &mut self.save_writer {
+            writer.last_frame = 0;
+        }
+
+        if self.call_stack.is_some() {
+            self.call_stack = Some(CallStack::default());
+        }
+        self.journal.set_enabled(self.journal.is_enabled());
+        self.pc_history = PcHistory::default();
+        self.last_crash = None;
+        self.breakpoints.hit = None;
+        if self.scanline_idle.is_some() {
+            self.scanline_idle = Some(Default::default());
+        }
+        self.update_event_masks();
+        self.perf.reset(self.ppu.frame_count, self.cycles);
+    }
+
+    /// Save the whole machine, in the format described in [`savestate`]. The CPU finishes the
+    /// instruction it is in first, so this can run a few cycles.
+    pub fn save_state(&mut self) -> Vec<u8> {
+        self.write_state()
     }
 
-    /// temporary
-    pub fn reset(&mut self) {
-        self.cpu.cpu.registers.pc = 0x100;
-        self.cpu.cpu.registers.sp = 0xFFFE;
+    /// Load a state saved by [`Gameboy::save_state`], by this or an earlier version. Anything
+    /// recorded about the code that ran before is forgotten, as with [`Gameboy::reset`].
+    ///
+    /// Fails without changing anything if the state can't be read or is for a different type of
+    /// cartridge. Use [`savestate::state_info`] to check that it is for the same game.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), savestate::StateError> {
+        self.read_state(state)
+    }
+
+    /// What the cartridge is, including anything about its header that was worked around because
+    /// of [`GameboyBuilder::lenient_header`], so that frontends can tell the user
+    pub fn cartridge_info(&self) -> cart::CartridgeInfo {
+        self.cart.info()
+    }
+
+    /// Replace the cartridge with one loaded from `rom`, and power cycle with it, like a frontend's
+    /// "load another ROM". If the old cartridge's RAM has changed since it was last saved, the
+    /// save writer is called with it one last time, and then removed, since it saves the old
+    /// game. Nothing else about the old cartridge is kept, though a wrong header is worked around
+    /// if the old one was built with [`GameboyBuilder::lenient_header`], an MBC1 is wired as
+    /// [`GameboyBuilder::mbc1_wiring`] said, and a clock in the new cartridge gets its time from
+    /// [`GameboyBuilder::rtc`] and counts what [`GameboyBuilder::rtc_time_source`] said, or what
+    /// was last set with [`Cart::set_rtc_time_source`](cart::Cart::set_rtc_time_source).
+    ///
+    /// Fails without changing anything if `rom` is not a valid cartridge.
+    pub fn swap_cartridge(&mut self, rom: &[u8]) -> Result<(), GbError> {
+        let cart = self.cart.load_another(rom.into())?;
+        if let Some(mut writer) = self.save_writer.take() {
+            if let Some(save) = self.cart.take_save() {
+                (writer.callback)(save);
+            }
+        }
+        self.cart = cart;
+        self.symbols = Default::default();
+        self.update_rom_patches();
+        // The new cartridge may not claim the same addresses
+        let scheduling = self.scheduler.is_enabled();
+        self.scheduler = Scheduler::new(self.other_chips());
+        self.scheduler.set_enabled(scheduling);
+        self.reset(ResetKind::PowerCycle);
+        Ok(())
     }
 }
 
+/// How much [`Gameboy::reset`] starts over
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResetKind {
+    /// Switch the console off and on again. Work RAM, high RAM, VRAM and OAM are filled again
+    /// following [`RamInit`].
+    PowerCycle,
+    /// Reset the CPU and every chip without cutting the power, so work RAM, high RAM, VRAM and OAM
+    /// keep what the game left in them
+    Soft,
+}
+
 /// Contains information about a clock cycle for use by debugging methods
 pub struct ClockDebug {
     pub is_fetch_cycle: bool,
     pub opcode_fetched: Option<u16>,
+    /// Number of T-cycles that passed during this clock
+    pub t_cycles: u64,
+}
+
+/// What happened during one M-cycle, from [`Gameboy::tick`]
+#[derive(Debug, Clone, Copy)]
+pub struct TickInfo {
+    /// The address and direction of the bus access
+    pub pins: CpuOutputPins,
+    /// The value on the data bus at the end of the M-cycle, which is what the CPU reads
+    pub data: u8,
+    /// The CPU fetched an opcode
+    pub is_fetch_cycle: bool,
+    /// OAM DMA copied a byte. Unless the CPU was running from HRAM, it was paused, and `pins` are
+    /// the DMA's read.
+    pub dma: bool,
+    /// Bits of IF that the chips raised during this M-cycle
+    pub interrupts_raised: u8,
+    /// The PPU finished a frame and entered VBlank, or the LCD has been off for another 70224
+    /// T-cycles
+    pub frame_completed: bool,
+    /// This was one of the extra M-cycles an overclocked CPU gets, during which no time passed
+    /// for the rest of the system
+    pub extra_cycle: bool,
 }
 
 impl Gameboy {
-    /// Clock the entire gameboy by M-cycle
-    pub fn clock(&mut self) -> ClockDebug {
-        if self.ppu.dma_active() {
-            // If there is a DMA operation, we'll just pause the CPU during it since most games won't care.
-            return self.clock_dma();
+    /// Advance the whole machine by exactly one M-cycle (4 T-cycles), which every other way of
+    /// running the emulator is built on. In order, a tick:
+    ///
+    /// 1. Gets the pins the bus is driven with. If an OAM DMA transfer is copying a byte and the
+    ///    CPU is running from anywhere but HRAM, the DMA drives the bus and the CPU is paused.
+    ///    Otherwise the CPU is clocked with the data and interrupt lines from the end of the
+    ///    previous tick, and interrupt dispatch is handled. A CPU running from HRAM during a
+    ///    transfer shares the bus with it: IO registers, HRAM, IE and IF work as usual, OAM reads
+    ///    $FF, and everything else reads the byte being copied.
+    /// 2. Clocks every chip with those pins: the PPU, then memory, the cartridge, the timer, the
+    ///    APU, the joypad, serial, and finally any extra chips, in the order they were added.
+    ///    Chips only respond to the addresses they claim, so a chip with nothing to do this
+    ///    M-cycle is skipped unless the access is to one of them (see
+    ///    [`Gameboy::set_chip_scheduling`]). The PPU handles the access before advancing by its 4
+    ///    dots, except that with [`BusInterleave::TCycle`] it runs 3 dots before a write lands
+    ///    and the last one after, which is where writes land on hardware.
+    /// 3. Updates IF with the interrupts the chips raised, applies cheats, calls the scanline
+    ///    callback, and overlays the boot ROM onto the data bus.
+    /// 4. Handles IE and IF, which are not part of any chip, and latches the data and interrupt
+    ///    lines for the CPU to read on the next tick.
+    ///
+    /// While the CPU is overclocked (see [`Gameboy::set_cpu_overclock`]), its extra M-cycles are
+    /// ticks of their own, where the chips only handle the CPU's access.
+    pub fn tick(&mut self) -> TickInfo {
+        self.perf.begin_cycle();
+        let frame_count = self.ppu.frame_count;
+        let interrupt_request = self.interrupt_request;
+        self.extra_cycle = self.extra_cycles > 0
+            && !self.cpu.cpu.halted
+            && self.ppu.dma_transfer.transfer.is_none();
+        let dma_source = if self.extra_cycle {
+            self.extra_cycles -= 1;
+            None
+        } else {
+            self.extra_cycles = self.overclock.get() - 1;
+            self.ppu.begin_dma_cycle()
+        };
+        let (pins, is_fetch_cycle) = match dma_source {
+            Some(source) if !self.cpu_in_hram() => (self.tick_dma(source), false),
+            _ => self.tick_cpu(dma_source),
         };
+        let dma = dma_source.is_some();
+        self.fetched = is_fetch_cycle;
+        if self.journal.is_enabled() {
+            self.journal_tick(is_fetch_cycle);
+        }
+        let frame_completed = self.ppu.frame_count != frame_count;
+        for interrupt in Interrupt::from_flags(self.interrupt_request & !interrupt_request) {
+            log::trace!(target: logging::INTERRUPT, "{:?} interrupt requested", interrupt);
+        }
+        self.perf.lap(Subsystem::Bus);
+        if frame_completed {
+            self.joypad.latch();
+            if let Some(scanline_idle) = &mut self.scanline_idle {
+                scanline_idle.finish_frame();
+            }
+            self.perf.frame_completed();
+            self.write_save();
+            #[cfg(feature = "capture")]
+            self.capture_frame();
+            if !self.capture_triggers.is_empty() {
+                self.check_capture_triggers();
+            }
+            if self.observers.is_some() {
+                self.publish_due_observation();
+            }
+        }
+        TickInfo {
+            pins,
+            data: self.cpu_input.data,
+            is_fetch_cycle,
+            dma,
+            interrupts_raised: self.interrupt_request & !interrupt_request,
+            frame_completed,
+            extra_cycle: self.extra_cycle,
+        }
+    }
 
+    /// Clock the entire gameboy by one M-cycle, like [`Gameboy::tick`]
+    pub fn clock(&mut self) -> ClockDebug {
+        let info = self.tick();
+        ClockDebug {
+            is_fetch_cycle: info.is_fetch_cycle,
+            opcode_fetched: info.is_fetch_cycle.then(|| info.pins.addr()),
+            t_cycles: if info.extra_cycle { 0 } else { 4 },
+        }
+    }
+
+    /// Run for at least `t_cycles` T-cycles, and return the number actually run, which is rounded
+    /// up to a whole number of M-cycles
+    pub fn run_cycles(&mut self, t_cycles: u64) -> u64 {
+        let start = self.cycles;
+        while self.cycles - start < t_cycles {
+            self.tick();
+        }
+        self.cycles - start
+    }
+
+    /// Let the CPU drive the bus for one M-cycle, alongside OAM DMA reading from `dma_source` if
+    /// it is set, and return the pins and whether an opcode was fetched
+    fn tick_cpu(&mut self, dma_source: Option<u16>) -> (CpuOutputPins, bool) {
+        let start = self.cycles;
         let CpuRunnerYield {
             pins: cpu_pins_out,
             is_fetch_cycle,
+            is_halted_cycle,
+            is_dispatch_cycle,
+            inc_dec,
+            interrupt,
+            acknowledge,
         } = self.cpu.clock(self.cpu_input);
-
-        let opcode_fetched = if is_fetch_cycle {
-            Some(cpu_pins_out.addr())
-        } else {
-            None
-        };
-
-        let chips: &mut [&mut dyn Chip] = &mut [
-            &mut self.ppu,
-            &mut self.memory,
-            &mut self.cart,
-            &mut self.timer,
-            &mut self.joypad,
-        ];
-
-        let bus_output = {
-            let mut data = 0xFF;
-            let mut ir = self.interrupt_request;
-
-            for chip in chips {
-                chip.clock(cpu_pins_out, &mut data, &mut ir);
+        self.ppu
+            .cpu_usage
+            .add_cycle(is_halted_cycle, is_dispatch_cycle);
+        if let Some(bit) = acknowledge {
+            self.interrupt_request &= !(1 << bit);
+        }
+        if self.oam_bug {
+            if self.journal.is_enabled() {
+                self.journal_oam();
+            }
+            self.ppu.oam_bug(cpu_pins_out, inc_dec);
+        }
+        if self.profiling && is_fetch_cycle {
+            if let Some(profiler) = &mut self.profiler {
+                profiler.fetch(cpu_pins_out.addr(), self.cart.rom_bank(), start);
             }
+        }
+        if let Some(InterruptDispatch {
+            vector,
+            return_addr,
+        }) = interrupt
+        {
+            log::debug!(
+                target: logging::INTERRUPT,
+                "dispatching interrupt to ${:04X}, returning to ${:04X}",
+                vector,
+                return_addr
+            );
+            if let Some(call_stack) = &mut self.call_stack {
+                let sp = self.cpu.cpu.registers.sp;
+                call_stack.interrupt(vector, return_addr, sp, self.cart.rom_bank() as u8);
+            }
+            self.events.publish(EventRecord {
+                cycle: start,
+                event: Event::InterruptServiced {
+                    vector,
+                    pc: return_addr,
+                },
+            });
+        }
 
-            self.interrupt_request = ir;
-            data
+        self.perf.lap(Subsystem::Cpu);
+        let bus_output = match dma_source {
+            Some(source) => self.bus_cycle_beside_dma(cpu_pins_out, source),
+            None => self.bus_cycle(cpu_pins_out, BusMaster::Cpu),
         };
+        if is_halted_cycle && self.ppu.lcdc.contains(ppu::registers::LCDC::LCD_ENABLE) {
+            if let Some(scanline_idle) = &mut self.scanline_idle {
+                // Going by the last dot the PPU ran, since LY already reads 0 for most of line 153
+                let dot = self.ppu.frame_position().dots.saturating_sub(1);
+                scanline_idle.add_halted_cycle(dot as usize / ppu::consts::LINE_T_CYCLES);
+            }
+        }
+        if is_fetch_cycle {
+            let pc = cpu_pins_out.addr();
+            trace_heavy!(
+                target: logging::CPU,
+                "${:04X}: {:02X}{}",
+                pc,
+                bus_output,
+                self.symbolize(pc)
+                    .map_or(String::new(), |symbol| format!(" {}", symbol))
+            );
+            self.pc_history.record(pc, self.cart.rom_bank());
+            if ILLEGAL_OPCODES.contains(&bus_output) {
+                log::warn!(
+                    target: logging::CPU,
+                    "illegal opcode ${:02X} at ${:04X} locks up the CPU",
+                    bus_output,
+                    pc
+                );
+                self.last_crash = Some(Box::new(self.crash_dump()));
+            }
+            if let Some(call_stack) = &mut self.call_stack {
+                let sp = self.cpu.cpu.registers.sp;
+                let bank = self.cart.rom_bank() as u8;
+                call_stack.fetch(cpu_pins_out.addr(), bus_output, sp, bank);
+            }
+            if self.violations.check_execution && violations::is_bad_pc(pc) {
+                self.report_violation(start, Violation::Execution { pc });
+            }
+            if !self.breakpoints.is_empty() {
+                if let Some(id) = self.breakpoints.find(pc, self) {
+                    self.breakpoints.hit = Some(id);
+                    self.capture_triggers.breakpoint_hit(id);
+                }
+            }
+            if self.debug_opcode_handler.is_some() {
+                self.debug_opcode(pc, bus_output);
+            }
+        }
 
         // Handle changes to IE & IF (handled independently from chips)
         match cpu_pins_out {
             CpuOutputPins::Write { addr: 0xFF0F, data } => self.interrupt_request = data & 0x1F,
-            CpuOutputPins::Write { addr: 0xFFFF, data } => self.interrupt_enable = data & 0x1F,
+            // All 8 bits of IE can be read back, even though only the lower 5 are used
+            CpuOutputPins::Write { addr: 0xFFFF, data } => self.interrupt_enable = data,
             _ => (),
         };
 
+        // IE & IF are not part of any chip, so they must be handled separately
+        let data = match cpu_pins_out {
+            CpuOutputPins::Read { addr: 0xFF0F } => self.interrupt_request | 0xE0,
+            CpuOutputPins::Read { addr: 0xFFFF } => self.interrupt_enable,
+            _ => bus_output,
+        };
+        self.cpu_input = self.cpu_input_pins(data);
+
+        (cpu_pins_out, is_fetch_cycle)
+    }
+
+    /// The pins the CPU sees on its next cycle. The interrupt lines follow IE & IF as they are at
+    /// the end of this cycle, so a write to either is visible to the CPU straight away.
+    fn cpu_input_pins(&self, data: u8) -> CpuInputPins {
         let interrupt_requests = self.interrupt_enable & self.interrupt_request;
-        self.cpu_input = CpuInputPins {
+        CpuInputPins {
+            data,
             interrupt_40h: interrupt_requests & (1 << 0) != 0,
             interrupt_48h: interrupt_requests & (1 << 1) != 0,
             interrupt_50h: interrupt_requests & (1 << 2) != 0,
             interrupt_58h: interrupt_requests & (1 << 3) != 0,
             interrupt_60h: interrupt_requests & (1 << 4) != 0,
+        }
+    }
 
-            // IE & IF are not part of any chip, so they must be handled separately
-            data: match cpu_pins_out {
-                CpuOutputPins::Read { addr: 0xFF0F } => self.interrupt_request,
-                CpuOutputPins::Read { addr: 0xFFFF } => self.interrupt_enable,
-                _ => bus_output,
-            },
-        };
+    /// Whether the CPU is running from HRAM, where it can keep going during OAM DMA. Anywhere
+    /// else it would only fetch the bytes being copied, so it is paused instead, which games
+    /// won't notice since they wait for DMA in HRAM.
+    fn cpu_in_hram(&self) -> bool {
+        (0xFF80..=0xFFFE).contains(&self.cpu.cpu.registers.pc)
+    }
 
-        ClockDebug {
-            is_fetch_cycle,
-            opcode_fetched,
+    /// Let the OAM DMA drive the bus for one M-cycle, reading from `source`, while the CPU is
+    /// paused
+    fn tick_dma(&mut self, source: u16) -> CpuOutputPins {
+        if self.journal.is_enabled() {
+            self.journal_oam();
         }
+        let pins = CpuOutputPins::Read { addr: source };
+        let data = self.bus_cycle(pins, BusMaster::Dma);
+        self.ppu.finish_dma_cycle(data);
+        // The CPU sees the byte it last read when it carries on
+        self.cpu_input = self.cpu_input_pins(self.cpu_input.data);
+        pins
     }
 
-    fn clock_dma(&mut self) -> ClockDebug {
-        for _ in 0..4 {
-            if !self.ppu.dma_active() {
-                break;
+    /// Let the CPU access the bus with `pins` while OAM DMA copies a byte from `source`, and
+    /// return what the CPU reads.
+    ///
+    /// Only one access can be clocked through the chips. An access to an IO register is, and the
+    /// DMA's byte is read without disturbing anything. Otherwise the DMA's read is, and the CPU
+    /// reaches HRAM, IE and IF as usual, reads $FF from OAM, and everywhere else reads the byte
+    /// being copied, since the DMA is using the bus. Writes to those are lost.
+    fn bus_cycle_beside_dma(&mut self, pins: CpuOutputPins, source: u16) -> u8 {
+        if self.journal.is_enabled() {
+            self.journal_oam();
+        }
+        if matches!(pins.addr(), 0xFF00..=0xFF7F if pins.addr() != 0xFF0F) {
+            let data = self.peek(source);
+            let bus_output = self.bus_cycle(pins, BusMaster::Cpu);
+            self.ppu.finish_dma_cycle(data);
+            return bus_output;
+        }
+        let data = self.bus_cycle(CpuOutputPins::Read { addr: source }, BusMaster::Dma);
+        self.ppu.finish_dma_cycle(data);
+        match pins {
+            CpuOutputPins::Read {
+                addr: addr @ 0xFF80..=0xFFFE,
+            } => self.memory[addr],
+            CpuOutputPins::Write {
+                addr: addr @ 0xFF80..=0xFFFE,
+                data,
+            } => {
+                if self.journal.is_enabled() {
+                    self.journal_write(addr, data);
+                }
+                self.memory[addr] = data;
+                data
+            }
+            CpuOutputPins::Read {
+                addr: 0xFE00..=0xFEFF,
+            } => 0xFF,
+            _ => data,
+        }
+    }
+
+    /// Every chip on the bus, in the order they are clocked
+    fn chips(&self) -> impl Iterator<Item = &dyn Chip> {
+        std::iter::once(&self.ppu as &dyn Chip).chain(self.other_chips())
+    }
+
+    /// Every chip but the PPU, which is clocked on its own so that it can be timed separately
+    fn other_chips(&self) -> impl Iterator<Item = &dyn Chip> {
+        // Edition 2018 arrays iterate by reference through `.into_iter()`
+        IntoIterator::into_iter([
+            &self.memory as &dyn Chip,
+            &self.cart,
+            &self.timer,
+            &self.apu,
+            &self.joypad,
+            &self.serial,
+        ])
+        .chain(self.chips.iter().map(|chip| chip.as_ref() as &dyn Chip))
+    }
+
+    fn other_chips_mut(&mut self) -> impl Iterator<Item = &mut dyn Chip> {
+        IntoIterator::into_iter([
+            &mut self.memory as &mut dyn Chip,
+            &mut self.cart,
+            &mut self.timer,
+            &mut self.apu,
+            &mut self.joypad,
+            &mut self.serial,
+        ])
+        .chain(
+            self.chips
+                .iter_mut()
+                .map(|chip| chip.as_mut() as &mut dyn Chip),
+        )
+    }
+
+    /// Clock every chip by one M-cycle with `pins` on the bus, and return the resulting value of the data bus.
+    fn bus_cycle(&mut self, pins: CpuOutputPins, master: BusMaster) -> u8 {
+        if let Some(coverage) = &mut self.coverage {
+            // Whether the CPU is fetching an instruction. Operand fetches read the byte just
+            // before the incremented PC, like opcode fetches do.
+            let pc = self.cpu.cpu.registers.pc;
+            let executing = master == BusMaster::Cpu
+                && matches!(pins, CpuOutputPins::Read { addr } if addr.wrapping_add(1) == pc);
+            coverage.record(pins, executing, self.cart.rom_bank(), self.cart.ram_bank());
+        }
+
+        if self.journal.is_enabled() {
+            if let CpuOutputPins::Write { addr, data } = pins {
+                self.journal_write(addr, data);
+            }
+        }
+
+        // What the chips see, which only differs for writes to ROM with a RomWritePolicy set
+        let chip_pins = match pins {
+            CpuOutputPins::Write { addr, data }
+                if addr < 0x8000
+                    && self.violations.rom_write_policy != RomWritePolicy::MapperRegisters =>
+            {
+                self.rom_write(addr, data)
+            }
+            _ => pins,
+        };
+
+        let mut data = 0xFF;
+        let mut ir = self.interrupt_request;
+
+        let counting = !self.extra_cycle || self.timer_clock == TimerClock::Cpu;
+        self.counter.begin_cycle(chip_pins, counting);
+        let ctx = ClockContext {
+            counter: self.counter,
+            cycles: self.cycles,
+            master,
+            extra_cycle: self.extra_cycle,
+        };
+        // The PPU is clocked on its own so that it can be timed separately
+        self.ppu.clock(chip_pins, &mut data, &mut ir, &ctx);
+        self.perf.lap(Subsystem::Ppu);
+        if self.bus_conflicts.is_empty() || !self.bus_conflicts.contains_key(&chip_pins.addr()) {
+            self.clock_chips(chip_pins, &mut data, &mut ir, &ctx);
+        } else {
+            data &= self.clock_conflicting_chips(chip_pins, &mut ir, &ctx);
+        }
+        if let Some(hook) = self.io_hooks.get_mut(&chip_pins.addr()) {
+            match chip_pins {
+                CpuOutputPins::Read { .. } => data = hook.read(&ctx),
+                CpuOutputPins::Write { data, .. } => hook.write(data, &ctx),
             }
+        }
+        self.counter.end_cycle();
+
+        if !self.events.mask().is_empty() {
+            self.publish_events(ir & !self.interrupt_request);
+        }
+
+        self.interrupt_request = ir;
+        if !self.extra_cycle {
+            self.cycles += 4;
+        }
 
-            let pins = self.ppu.clock_dma(self.cpu_input);
+        if self.ppu.frame_count != self.cheats.applied_frame {
+            self.cheats.applied_frame = self.ppu.frame_count;
+            self.apply_ram_cheats();
+        }
+
+        if let Some(ly) = self.ppu.last_completed_line.take() {
+            if self.racing_beam || self.scanline_callback.is_some() {
+                let row = self.ppu.back_frame_row(ly);
+                if self.racing_beam {
+                    self.raced_lines.extend_from_slice(&row);
+                }
+                if let Some(callback) = &mut self.scanline_callback {
+                    callback(ly, &row);
+                }
+            }
+        }
 
-            let chips = [
-                &mut self.memory as &mut dyn Chip,
-                &mut self.cart,
-                &mut self.timer,
-                &mut self.joypad,
-                &mut self.ppu,
-            ];
-            let mut data = 0;
-            let mut ir = self.interrupt_request;
-            for chip in chips {
-                chip.clock(pins, &mut data, &mut ir);
+        // The boot ROM overlays the cartridge, so it is handled after the chips
+        if let Some(boot_rom) = &self.boot_rom {
+            match pins {
+                CpuOutputPins::Read {
+                    addr: addr @ 0x0000..=0x00FF,
+                } => data = boot_rom[addr as usize],
+                CpuOutputPins::Write { addr: 0xFF50, data } if data != 0 => {
+                    self.boot_rom = None;
+                    self.ppu.opri_locked = true;
+                }
+                _ => (),
             }
+        }
 
-            self.cpu_input = CpuInputPins {
+        if let Some(tracer) = &mut self.bus_tracer {
+            let data = match pins {
+                CpuOutputPins::Read { addr: 0xFF0F } => self.interrupt_request | 0xE0,
+                CpuOutputPins::Read { addr: 0xFFFF } => self.interrupt_enable,
+                _ => data,
+            };
+            tracer.record(
+                ctx.cycles,
+                master,
+                pins,
                 data,
-                ..Default::default()
+                self.boot_rom.is_some(),
+                self.io_hooks.contains_key(&pins.addr()),
+            );
+        }
+
+        data
+    }
+
+    /// Clock the chips after the PPU that claim the address on the bus, or have something to do
+    /// this M-cycle, or all of them if chip scheduling is off
+    fn clock_chips(&mut self, pins: CpuOutputPins, data: &mut u8, ir: &mut u8, ctx: &ClockContext) {
+        // The timer's next edge can't be worked out in stock T-cycles when it counts faster
+        let timer_overclocked = self.timer_clock == TimerClock::Cpu && self.overclock.get() > 1;
+        if !self.scheduler.is_enabled() || timer_overclocked {
+            for chip in self.other_chips_mut() {
+                chip.clock(pins, data, ir, ctx);
+            }
+            return;
+        }
+        let owner = self.scheduler.owner(pins.addr());
+        for i in 0..self.scheduler.wake.len() {
+            if self.scheduler.wake[i] <= ctx.cycles || i == owner {
+                let chip = self.scheduled_chip_mut(i);
+                chip.clock(pins, data, ir, ctx);
+                self.scheduler.wake[i] = chip.next_event(ctx);
             }
         }
+    }
 
-        ClockDebug {
-            is_fetch_cycle: false,
-            opcode_fetched: None,
+    /// The chip at `index` in [`Gameboy::other_chips_mut`]. This is quicker than
+    /// going through the iterator when only a few chips are clocked.
+    fn scheduled_chip_mut(&mut self, index: usize) -> &mut dyn Chip {
+        match index {
+            0 => &mut self.memory,
+            1 => &mut self.cart,
+            2 => &mut self.timer,
+            3 => &mut self.apu,
+            4 => &mut self.joypad,
+            5 => &mut self.serial,
+            _ => self.chips[index - 6].as_mut(),
         }
     }
 
-    /// Clock the gameboy by the time it takes to complete one instruction
-    pub fn step_instruction(&mut self) {
-        loop {
-            if let ClockDebug {
-                is_fetch_cycle: true,
-                ..
-            } = self.clock()
-            {
-                break;
+    /// Clock every chip but the PPU for an access to an address more than one of them claims.
+    /// Each chip drives its own copy of the data bus, and like the open-drain bus of the real
+    /// hardware, a bit reads as 0 if any chip pulls it low.
+    #[cold]
+    fn clock_conflicting_chips(
+        &mut self,
+        pins: CpuOutputPins,
+        ir: &mut u8,
+        ctx: &ClockContext,
+    ) -> u8 {
+        #[cfg(any(debug_assertions, feature = "strict-bus"))]
+        {
+            let (addr, chips) = (pins.addr(), self.bus_conflicts[&pins.addr()]);
+            log::warn!(
+                target: logging::BUS,
+                "{} and {} both answered ${:04X}",
+                chips[0],
+                chips[1],
+                addr
+            );
+            if self.events.mask().contains(EventMask::BUS_CONFLICT) {
+                self.events.publish(EventRecord {
+                    cycle: self.cycles,
+                    event: Event::BusConflict { addr, chips },
+                });
+            }
+        }
+        let mut data = 0xFF;
+        let mut wake = std::mem::take(&mut self.scheduler.wake);
+        for (chip, wake) in self.other_chips_mut().zip(&mut wake) {
+            let mut driven = 0xFF;
+            chip.clock(pins, &mut driven, ir, ctx);
+            data &= driven;
+            *wake = chip.next_event(ctx);
+        }
+        self.scheduler.wake = wake;
+        data
+    }
+
+    /// Apply the [`RomWritePolicy`] to a write of `data` to `addr` in ROM, and return what the
+    /// chips see instead
+    #[cold]
+    fn rom_write(&mut self, addr: u16, data: u8) -> CpuOutputPins {
+        match self.violations.rom_write_policy {
+            // The cartridge only answers reads of ROM, so turning the write into one leaves it
+            // untouched
+            RomWritePolicy::Ignore => CpuOutputPins::Read { addr },
+            RomWritePolicy::ReportViolation if !self.cart.is_mapper_register(addr) => {
+                let pc = self.pc_history.last().map_or(0, |record| record.pc);
+                let violation = Violation::RomWrite {
+                    pc,
+                    addr,
+                    value: data,
+                };
+                self.report_violation(self.cycles, violation);
+                CpuOutputPins::Write { addr, data }
             }
+            _ => CpuOutputPins::Write { addr, data },
+        }
+    }
+
+    /// Log `violation`, which happened in the M-cycle starting at `cycle`, and publish it as an
+    /// event, unless too many have been reported recently
+    #[cold]
+    fn report_violation(&mut self, cycle: u64, violation: Violation) {
+        if !self.violations.allow(cycle) {
+            return;
         }
+        log::warn!(target: logging::VIOLATION, "{}", violation);
+        if self.events.mask().contains(EventMask::VIOLATION) {
+            self.events.publish(EventRecord {
+                cycle,
+                event: Event::Violation(violation),
+            });
+        }
+    }
+
+    /// Publish the events collected by the chips during the current M-cycle. `raised` holds the
+    /// bits of IF that were set during it.
+    fn publish_events(&mut self, raised: u8) {
+        let cycle = self.cycles;
+        let events = Interrupt::from_flags(raised)
+            .map(Event::InterruptRaised)
+            .chain(self.ppu.events.drain())
+            .chain(self.cart.events.drain())
+            .chain(self.serial.events.drain());
+        for event in events {
+            self.events.publish(EventRecord { cycle, event });
+        }
+        // Dropping a receiver cancels its subscription
+        self.update_event_masks();
+    }
+
+    fn update_event_masks(&mut self) {
+        let mask = self.events.mask();
+        self.ppu.events.set_mask(mask);
+        self.cart.events.set_mask(mask);
+        self.serial.events.set_mask(mask);
+    }
+
+    /// Subscribe to the events in `mask`, which are placed into a queue holding up to
+    /// [`EVENT_QUEUE_CAPACITY`] events. Events that arrive while the queue is full are dropped.
+    pub fn subscribe(&mut self, mask: EventMask) -> EventReceiver {
+        let receiver = self.events.subscribe(mask, EVENT_QUEUE_CAPACITY);
+        self.update_event_masks();
+        receiver
+    }
+
+    /// Call `callback` with each event in `mask` as soon as it happens
+    pub fn subscribe_callback(
+        &mut self,
+        mask: EventMask,
+        callback: impl FnMut(&EventRecord) + MaybeSend + 'static,
+    ) -> SubscriptionId {
+        let id = self.events.subscribe_callback(mask, Box::new(callback));
+        self.update_event_masks();
+        id
+    }
+
+    /// Returns false if there is no subscription with this id
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        let found = self.events.unsubscribe(id);
+        self.update_event_masks();
+        found
+    }
+
+    /// Clock the gameboy by the time it takes to complete one instruction, and return the number
+    /// of T-cycles that took
+    pub fn step_instruction(&mut self) -> u64 {
+        let start = self.cycles;
+        while !self.tick().is_fetch_cycle {}
+        self.cycles - start
     }
 }
 impl Gameboy {
+    /// Only draw one out of every `n + 1` frames, for fast-forwarding. Skipped frames still run
+    /// with exact timing, but produce no pixels and do not trigger the scanline callback.
+    pub fn set_frame_skip(&mut self, n: u32) {
+        self.ppu.set_frame_skip(n);
+    }
+
+    /// Record where each pixel of every drawn frame came from: which layer, tile and OAM entry,
+    /// its color ID and the palette applied. The record is returned by
+    /// [`Frame::attribution`](ppu::frame::Frame::attribution). Off by default, since it costs time
+    /// on every pixel.
+    pub fn set_pixel_attribution(&mut self, enabled: bool) {
+        self.ppu.set_pixel_attribution(enabled);
+    }
+
+    /// Run `stages` in order on every drawn frame, just before it is shown, replacing any stages
+    /// set before. This affects every way of getting frames, including [`Gameboy::get_frame`] and
+    /// [`Gameboy::frame_receiver`], but not the rows passed to the scanline callback.
+    ///
+    /// There are no stages by default. Pass an empty `Vec` to get the frames exactly as the PPU
+    /// drew them, as accuracy tests need.
+    pub fn set_post_processing(&mut self, stages: Vec<Box<dyn_maybe_send!(PostProcess)>>) {
+        self.ppu.set_post_processing(stages);
+    }
+
+    /// Run until `n` more frames have been completed, and return the last one.
+    ///
+    /// Only the final frame is drawn, regardless of the frame skip setting. If the final frame had
+    /// already started when this was called, it is drawn according to the frame skip setting instead.
+    ///
+    /// While the LCD is off, a frame still completes every 70224 T-cycles, but nothing is drawn and
+    /// the blank frame shown when it was turned off is returned.
+    pub fn run_frames(&mut self, n: u32) -> &ppu::frame::Frame {
+        let target = self.ppu.frame_count + n as u64;
+        while self.ppu.frame_count < target {
+            let next = self.ppu.frame_count + 1;
+            #[cfg(feature = "capture")]
+            let draw = next == target || self.capture_wants(next);
+            #[cfg(not(feature = "capture"))]
+            let draw = next == target;
+            self.ppu.draw_next_frame = Some(draw);
+            self.tick();
+        }
+        self.ppu.draw_next_frame = None;
+        &self.ppu.frame
+    }
+
+    /// Run until the PPU starts line `ly`, and return the scanlines it completed on the way, 160
+    /// colors each, in the order they were drawn. This is for frontends that race the beam,
+    /// presenting each part of a frame as soon as it has been drawn. The lines of skipped frames
+    /// aren't drawn, so they aren't returned.
+    ///
+    /// If the PPU is already on line `ly`, this runs until it comes round again. Lines are
+    /// counted like `self.ppu.current_line()`, so line 0 starts at the end of line 153, not when
+    /// LY wraps to 0. While the LCD is off the PPU never gets
+    /// anywhere, so this gives up after two frames' worth of T-cycles.
+    ///
+    /// # Panics
+    /// Panics if `ly` is over 153
+    pub fn run_until_line(&mut self, ly: u8) -> &[RgbaColor] {
+        assert!(ly <= 153, "there is no line {}", ly);
+        self.raced_lines.clear();
+        self.racing_beam = true;
+        let give_up = self.cycles + 2 * ppu::consts::FRAME_T_CYCLES as u64;
+        let mut line = self.ppu.current_line();
+        while self.cycles < give_up {
+            self.tick();
+            let now = self.ppu.current_line();
+            if now != line && now == ly {
+                break;
+            }
+            line = now;
+        }
+        self.racing_beam = false;
+        &self.raced_lines
+    }
+
+    /// Handle reads and writes to the IO register at `addr` with `hook`, replacing any hook
+    /// already registered there.
+    ///
+    /// Returns [`GbError::AddressOutOfRange`] if `addr` is not in $FF00-$FF7F, or
+    /// [`GbError::ChipConflict`] if a chip already responds to it.
+    pub fn register_io_hook(
+        &mut self,
+        addr: u16,
+        hook: Box<dyn_maybe_send!(IoHook)>,
+    ) -> Result<(), GbError> {
+        if !(0xFF00..=0xFF7F).contains(&addr) {
+            return Err(GbError::AddressOutOfRange(addr));
+        }
+        if builder::is_claimed(self, addr) {
+            return Err(GbError::ChipConflict(addr));
+        }
+        self.io_hooks.insert(addr, hook);
+        Ok(())
+    }
+
+    /// Remove the hook at `addr`, which then reads as $FF again
+    pub fn remove_io_hook(&mut self, addr: u16) -> Option<Box<dyn_maybe_send!(IoHook)>> {
+        self.io_hooks.remove(&addr)
+    }
+
+    /// Add a Game Genie (`ABC-DEF-GHI` or `ABC-DEF`) or GameShark (`ABCDEFGH`) cheat code. The
+    /// cheat starts out enabled.
+    ///
+    /// Game Genie codes patch reads from cartridge ROM. GameShark codes write to RAM at the start
+    /// of every VBlank.
+    pub fn add_cheat(&mut self, code: &str) -> Result<CheatId, CheatParseError> {
+        let cheat: Cheat = code.parse()?;
+        let id = self.cheats.add(cheat);
+        self.update_rom_patches();
+        Ok(id)
+    }
+
+    /// Returns false if there is no cheat with this id
+    pub fn enable_cheat(&mut self, id: CheatId) -> bool {
+        let found = self.cheats.set_enabled(id, true);
+        self.update_rom_patches();
+        found
+    }
+
+    /// Returns false if there is no cheat with this id
+    pub fn disable_cheat(&mut self, id: CheatId) -> bool {
+        let found = self.cheats.set_enabled(id, false);
+        self.update_rom_patches();
+        found
+    }
+
+    /// Returns false if there is no cheat with this id
+    pub fn remove_cheat(&mut self, id: CheatId) -> bool {
+        let found = self.cheats.remove(id);
+        self.update_rom_patches();
+        found
+    }
+
+    /// Every cheat that has been added, and whether it is enabled
+    pub fn cheats(&self) -> impl Iterator<Item = (CheatId, &Cheat, bool)> {
+        self.cheats.iter()
+    }
+
+    /// Stop [`Gameboy::run_until_breakpoint`] at `breakpoint`
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> BreakpointId {
+        self.breakpoints.add(breakpoint)
+    }
+
+    /// Returns false if there is no breakpoint with this id
+    pub fn remove_breakpoint(&mut self, id: BreakpointId) -> bool {
+        self.breakpoints.remove(id)
+    }
+
+    /// Every breakpoint that has been added
+    pub fn breakpoints(&self) -> impl Iterator<Item = (BreakpointId, &Breakpoint)> {
+        self.breakpoints.iter()
+    }
+
+    /// Register a handler for the `LD B,B` and `LD D,D` debug opcodes, replacing any previous
+    /// handler. See [`debug_opcodes`] for the conventions. Without a handler, they run like any
+    /// other instruction.
+    pub fn set_debug_opcode_handler(
+        &mut self,
+        handler: impl FnMut(DebugOpcodeEvent) -> DebugOpcodeAction + MaybeSend + 'static,
+    ) {
+        self.debug_opcode_handler = Some(Box::new(handler));
+    }
+
+    /// Remove the handler registered by [`Gameboy::set_debug_opcode_handler`]
+    pub fn clear_debug_opcode_handler(&mut self) {
+        self.debug_opcode_handler = None;
+    }
+
+    /// Pass `opcode`, just fetched from `pc`, to the debug opcode handler if it is one
+    #[cold]
+    fn debug_opcode(&mut self, pc: u16, opcode: u8) {
+        let opcode = match debug_opcodes::decode(opcode) {
+            Some(opcode) => opcode,
+            None => return,
+        };
+        let registers = self.cpu.cpu.registers;
+        let message = (opcode == debug_opcodes::DebugOpcode::Message)
+            .then(|| debug_opcodes::read_message(registers.get_hl(), |addr| self.peek(addr)));
+        let event = DebugOpcodeEvent {
+            opcode,
+            pc,
+            registers,
+            message,
+        };
+        let handler = self.debug_opcode_handler.as_mut().unwrap();
+        if handler(event) == DebugOpcodeAction::Break {
+            self.breakpoints.hit = Some(BreakpointId::DEBUG_OPCODE);
+            self.capture_triggers
+                .breakpoint_hit(BreakpointId::DEBUG_OPCODE);
+        }
+    }
+
+    /// Run for at least `t_cycles` T-cycles, or until an opcode is fetched at a breakpoint whose
+    /// condition holds. Returns the breakpoint that was hit, with the instruction there not run
+    /// yet, so calling this again continues from it. A debug opcode handler that answers
+    /// [`DebugOpcodeAction::Break`] stops it the same way, returning
+    /// [`BreakpointId::DEBUG_OPCODE`].
+    pub fn run_until_breakpoint(&mut self, t_cycles: u64) -> Option<BreakpointId> {
+        self.breakpoints.hit = None;
+        let start = self.cycles;
+        while self.cycles - start < t_cycles {
+            self.tick();
+            if let Some(id) = self.breakpoints.hit.take() {
+                return Some(id);
+            }
+        }
+        None
+    }
+
+    /// Evaluate an expression over the registers and memory, for watch windows. See
+    /// [`expr`] for the syntax.
+    pub fn eval(&self, expr: &str) -> Result<u16, ExprError> {
+        Ok(expr.parse::<Expr>()?.eval(self))
+    }
+
+    fn update_rom_patches(&mut self) {
+        self.cart.set_rom_patches(self.cheats.rom_patches());
+    }
+
+    fn apply_ram_cheats(&mut self) {
+        for (bank, addr, data) in self.cheats.ram_writes() {
+            match addr {
+                0xA000..=0xBFFF => self.cart.poke_ram(bank, addr, data),
+                // GameShark codes and frozen addresses are only ever in WRAM, cartridge RAM or HRAM,
+                // so this can't fail
+                _ => self.memory.write(addr, data).unwrap(),
+            }
+        }
+    }
+
+    /// Read a byte from ROM, VRAM, cartridge RAM, WRAM, OAM, HRAM, IF or IE without disturbing
+    /// the emulation. Cartridge RAM is read from the bank that is mapped in, even while it is
+    /// disabled. Everything else reads as $FF.
+    pub fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x00FF if self.boot_rom.is_some() => {
+                self.boot_rom.as_ref().unwrap()[addr as usize]
+            }
+            0x0000..=0x7FFF => self.cart.peek_rom(addr),
+            0x8000..=0x97FF => self.ppu.tile_data[addr as usize - 0x8000],
+            0x9800..=0x9BFF => self.ppu.bg_map_1[addr as usize - 0x9800],
+            0x9C00..=0x9FFF => self.ppu.bg_map_2[addr as usize - 0x9C00],
+            0xA000..=0xBFFF => self.cart.ram_byte(addr).map_or(0xFF, |(_, byte)| byte),
+            0xC000..=0xDFFF | 0xFF80..=0xFFFE => self.memory[addr],
+            // Echo RAM
+            0xE000..=0xFDFF => self.memory[addr - 0x2000],
+            0xFE00..=0xFE9F => self.ppu.oam[addr as usize - 0xFE00],
+            0xFF0F => self.interrupt_request | 0xE0,
+            0xFFFF => self.interrupt_enable,
+            _ => 0xFF,
+        }
+    }
+
+    /// Write a byte the way the CPU would, through the bus, so that writes to IO registers have
+    /// their usual effects: writing $FF46 starts an OAM DMA transfer, for example. Like the CPU,
+    /// it is locked out of VRAM while the PPU is drawing. No time passes, so this can be called
+    /// between any two M-cycles.
+    ///
+    /// Returns what became of the write, for memory editors to point out the surprising ones.
+    pub fn poke(&mut self, addr: u16, data: u8) -> PokeOutcome {
+        match addr {
+            // Without a register behind it, the write wouldn't do anything but report a violation
+            0x0000..=0x7FFF
+                if !self.cart.is_mapper_register(addr)
+                    || self.violations.rom_write_policy == RomWritePolicy::Ignore =>
+            {
+                return PokeOutcome::Ignored
+            }
+            0xFEA0..=0xFEFF => return PokeOutcome::Ignored,
+            _ => (),
+        }
+        let register = match addr {
+            0x0000..=0x7FFF => Some(PokeOutcome::MapperRegister),
+            0xFF0F | 0xFFFF => Some(PokeOutcome::WroteRegister),
+            0xFF50 if self.boot_rom.is_some() => Some(PokeOutcome::WroteRegister),
+            0xFF00..=0xFF7F => Some(
+                if self.io_hooks.contains_key(&addr)
+                    || self
+                        .chips()
+                        .any(|chip| chip.chip_select().iter().any(|r| r.contains(&addr)))
+                {
+                    PokeOutcome::WroteRegister
+                } else {
+                    PokeOutcome::Ignored
+                },
+            ),
+            _ => None,
+        };
+
+        // An extra M-cycle, like an overclocked CPU gets, is an access during which no time passes
+        let extra_cycle = std::mem::replace(&mut self.extra_cycle, true);
+        let timer_clock = std::mem::replace(&mut self.timer_clock, TimerClock::Stock);
+        self.bus_cycle(CpuOutputPins::Write { addr, data }, BusMaster::Cpu);
+        self.extra_cycle = extra_cycle;
+        self.timer_clock = timer_clock;
+        // The chips that were clocked think they are done with the next M-cycle, which hasn't
+        // started yet
+        self.scheduler.wake_all();
+        match addr {
+            0xFF0F => self.interrupt_request = data & 0x1F,
+            0xFFFF => self.interrupt_enable = data,
+            _ => (),
+        }
+        self.cpu_input = self.cpu_input_pins(self.cpu_input.data);
+
+        register.unwrap_or(if self.peek(addr) == data {
+            PokeOutcome::WroteRam
+        } else {
+            PokeOutcome::Ignored
+        })
+    }
+
+    /// Write a byte straight into VRAM, cartridge RAM, WRAM, OAM or HRAM, without disturbing the
+    /// emulation, even where the CPU would be locked out. Cartridge RAM is written to the bank
+    /// that is mapped in, even while it is disabled.
+    ///
+    /// Returns [`GbError::AddressOutOfRange`] for ROM, the IO registers, IF and IE, which can't be
+    /// written without side effects.
+    pub fn poke_silent(&mut self, addr: u16, data: u8) -> Result<(), GbError> {
+        match addr {
+            0x8000..=0x97FF => self.ppu.tile_data[addr as usize - 0x8000] = data,
+            0x9800..=0x9BFF => self.ppu.bg_map_1[addr as usize - 0x9800] = data,
+            0x9C00..=0x9FFF => self.ppu.bg_map_2[addr as usize - 0x9C00] = data,
+            0xA000..=0xBFFF => {
+                if let Some((offset, _)) = self.cart.ram_byte(addr) {
+                    self.cart.set_ram_byte(offset, data);
+                }
+            }
+            0xC000..=0xDFFF | 0xFF80..=0xFFFE => self.memory[addr] = data,
+            0xE000..=0xFDFF => self.memory[addr - 0x2000] = data,
+            0xFE00..=0xFE9F => self.ppu.oam[addr as usize - 0xFE00] = data,
+            _ => return Err(GbError::AddressOutOfRange(addr)),
+        }
+        Ok(())
+    }
+
+    /// Write `value` to `addr` now, and again at the start of every VBlank, along with GameShark
+    /// codes, until it is [unfrozen](Gameboy::unfreeze). Cartridge RAM is written to the bank
+    /// that is mapped in, and only while it is enabled.
+    ///
+    /// Returns [`GbError::AddressOutOfRange`] unless `addr` is in WRAM, cartridge RAM or HRAM.
+    pub fn freeze(&mut self, addr: u16, value: u8) -> Result<FreezeId, GbError> {
+        match addr {
+            0xA000..=0xBFFF => self.cart.poke_ram(None, addr, value),
+            0xC000..=0xDFFF | 0xFF80..=0xFFFE => self.memory[addr] = value,
+            _ => return Err(GbError::AddressOutOfRange(addr)),
+        }
+        Ok(self.cheats.freeze(addr, value))
+    }
+
+    /// Returns false if there is no frozen address with this id
+    pub fn unfreeze(&mut self, id: FreezeId) -> bool {
+        self.cheats.unfreeze(id)
+    }
+
+    /// Every address frozen with [`Gameboy::freeze`], and the value it is frozen at
+    pub fn frozen(&self) -> impl Iterator<Item = (FreezeId, u16, u8)> + '_ {
+        self.cheats.frozen()
+    }
+
     /// Fetches a frame from the PPU
-    pub fn get_frame(&self) -> Box<ppu::frame::Frame> {
+    pub fn get_frame(&self) -> ppu::frame_pool::SharedFrame {
         self.ppu.get_frame()
     }
+
+    /// Get a handle to the latest completed frame, which can be cloned and sent to other threads.
+    /// Reading frames through it never blocks emulation.
+    pub fn frame_receiver(&mut self) -> ppu::frame_sink::FrameReceiver {
+        self.ppu.frame_receiver()
+    }
+
+    /// Choose when completed frames are copied to [`Gameboy::frame_receiver`]s. Every frame is by
+    /// default, while [`PresentationMode::LatestOnly`](ppu::frame_sink::PresentationMode::LatestOnly) only
+    /// copies the frames taken with [`Gameboy::take_frame`], for running uncapped.
+    pub fn set_presentation_mode(&mut self, mode: ppu::frame_sink::PresentationMode) {
+        self.ppu.set_presentation_mode(mode);
+    }
+
+    pub fn presentation_mode(&self) -> ppu::frame_sink::PresentationMode {
+        self.ppu.presentation_mode()
+    }
+
+    /// The latest completed frame, or `None` if it has already been taken. Frames that are never
+    /// taken are dropped without being copied, and counted by [`Gameboy::frames_produced`] but not
+    /// [`Gameboy::frames_presented`].
+    pub fn take_frame(&mut self) -> Option<ppu::frame_pool::SharedFrame> {
+        self.ppu.take_frame()
+    }
+
+    /// Number of frames drawn since power on
+    pub fn frames_produced(&self) -> u64 {
+        self.ppu.frames_produced()
+    }
+
+    /// Number of frames taken with [`Gameboy::take_frame`] since power on
+    pub fn frames_presented(&self) -> u64 {
+        self.ppu.frames_presented()
+    }
+}
+
+/// State shared by every chip on the bus during an M-cycle
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ClockContext {
+    pub counter: SystemCounter,
+    /// T-cycles since power on, as of the start of the M-cycle
+    pub cycles: u64,
+    /// Whoever is driving the bus this M-cycle
+    pub master: BusMaster,
+    /// This is one of the extra M-cycles an overclocked CPU gets. Chips should handle the access,
+    /// but no time passes, and `cycles` is the same as on the next stock M-cycle.
+    pub extra_cycle: bool,
 }
 
-/// Using this trait makes it easy to clock every chip on the Gameboy independently
-trait Chip {
-    /// Clock by one M-cycle
-    fn clock(&mut self, input: CpuOutputPins, data: &mut u8, interrupt_request: &mut u8);
+/// What drives the bus during an M-cycle. Some chips answer OAM DMA differently to the CPU.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum BusMaster {
+    #[default]
+    Cpu,
+    /// OAM DMA, while the CPU is paused. The PPU doesn't block its reads from VRAM during mode 3.
+    Dma,
 }
@@ -0,0 +1,236 @@
+//! Read-only views of a running Gameboy for tools on other threads.
+//!
+//! A debugger's VRAM viewer, memory editor and trace log can each hold a [`GbObserver`] instead
+//! of sharing the `&mut Gameboy` with the thread running it. The emulator publishes an
+//! [`Observation`] at each sync point (the end of every frame by default, see
+//! [`Gameboy::set_observer_sync`]), and every part of an observation was taken at the same
+//! T-cycle, so the views agree with one another.
+//!
+//! The latest observation is held in an [`ArcSwap`], which the emulator swaps a new one into.
+//! Readers only ever load an [`Arc`] from it, without taking a lock, so neither side waits for the
+//! other.
+//!
+//! ```no_run
+//! # #[cfg(not(feature = "single-thread"))]
+//! # fn tools(gameboy: &mut gb_core::gameboy::Gameboy) {
+//! let observer = gameboy.observer();
+//! std::thread::spawn(move || loop {
+//!     observer.request_memory();
+//!     let observation = observer.latest();
+//!     if let Some(memory) = &observation.memory {
+//!         println!("{:02X?}", &memory.wram[..16]);
+//!     }
+//! });
+//! # }
+//! ```
+//!
+//! With the `single-thread` feature, frames can't be sent to other threads, so neither can
+//! observers, though they still work on the thread running the Gameboy.
+
+// Observations hold frames, which aren't `Send` with the `single-thread` feature
+#![cfg_attr(feature = "single-thread", allow(clippy::arc_with_non_send_sync))]
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use arc_swap::ArcSwap;
+use gb_cpu::Registers;
+
+use super::{
+    ppu::{debug::PpuDebugSnapshot, frame_info::FrameInfo, frame_pool::SharedFrame},
+    Gameboy,
+};
+
+/// When the emulator publishes an [`Observation`] to its observers
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SyncPoint {
+    /// Each time a frame is completed
+    #[default]
+    EveryFrame,
+    /// Each time the frame count reaches a multiple of this. 0 is treated as 1.
+    EveryNthFrame(u32),
+    /// Only when [`Gameboy::publish_observation`] is called
+    Manual,
+}
+
+/// The CPU's registers, along with IE and IF
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RegistersSnapshot {
+    pub registers: Registers,
+    pub ime: bool,
+    pub halted: bool,
+    pub interrupt_enable: u8,
+    pub interrupt_request: u8,
+}
+
+/// Copies of video RAM and work RAM, only taken when an observer asks for them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemorySnapshot {
+    /// $8000-$9FFF
+    pub vram: Box<[u8]>,
+    /// $C000-$DFFF
+    pub wram: Box<[u8]>,
+}
+
+/// Everything published at one sync point, all taken at the same T-cycle
+#[derive(Debug, Clone)]
+pub struct Observation {
+    /// T-cycles since power on
+    pub cycle: u64,
+    /// The PPU's frame count
+    pub frame_count: u64,
+    pub registers: RegistersSnapshot,
+    pub ppu: PpuDebugSnapshot,
+    /// The latest completed frame, and what happened while it was drawn. Frames that were
+    /// skipped leave this as the last one that was drawn.
+    pub frame: SharedFrame,
+    pub frame_info: FrameInfo,
+    /// Only taken if an observer called [`GbObserver::request_memory`] since the last
+    /// observation was published
+    pub memory: Option<MemorySnapshot>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    latest: ArcSwap<Observation>,
+    /// An observer wants memory in the next observation
+    memory_requested: AtomicBool,
+    /// An observer has loaded the latest observation, and it had memory
+    memory_loaded: AtomicBool,
+}
+
+/// The publishing half, which is owned by the [`Gameboy`]
+pub(crate) struct Observers {
+    shared: Arc<Shared>,
+    pub sync: SyncPoint,
+}
+
+impl Observers {
+    fn new(first: Observation) -> Self {
+        Observers {
+            shared: Arc::new(Shared {
+                latest: ArcSwap::from_pointee(first),
+                memory_requested: AtomicBool::new(false),
+                memory_loaded: AtomicBool::new(false),
+            }),
+            sync: SyncPoint::default(),
+        }
+    }
+
+    /// Whether the observation published on completing frame `frame_count` is due
+    pub fn due(&self, frame_count: u64) -> bool {
+        match self.sync {
+            SyncPoint::EveryFrame => true,
+            SyncPoint::EveryNthFrame(n) => frame_count % n.max(1) as u64 == 0,
+            SyncPoint::Manual => false,
+        }
+    }
+
+    /// Whether the next observation should have memory, because an observer asked for it, or
+    /// because the latest one has memory that no observer has loaded yet
+    fn take_memory_request(&self) -> bool {
+        let requested = self.shared.memory_requested.swap(false, Ordering::SeqCst);
+        let loaded = self.shared.memory_loaded.swap(false, Ordering::SeqCst);
+        requested || (self.shared.latest.load().memory.is_some() && !loaded)
+    }
+
+    /// Publish `observation` as the latest
+    fn publish(&self, observation: Observation) {
+        self.shared.latest.store(Arc::new(observation));
+    }
+}
+
+/// A read-only view of a [`Gameboy`], from [`Gameboy::observer`]. Cloning it is cheap, and it
+/// can be sent to any thread.
+#[derive(Debug, Clone)]
+pub struct GbObserver {
+    shared: Arc<Shared>,
+}
+
+impl GbObserver {
+    /// The latest observation. It is kept for as long as it is held, without holding up the
+    /// emulator.
+    pub fn latest(&self) -> Arc<Observation> {
+        let observation = self.shared.latest.load_full();
+        if observation.memory.is_some() {
+            self.shared.memory_loaded.store(true, Ordering::SeqCst);
+        }
+        observation
+    }
+
+    /// Ask for copies of video RAM and work RAM in the next observation. Copying them costs the
+    /// emulator time, so they are only taken when asked for, and are taken again at each sync
+    /// point until an observer has loaded an observation with them.
+    pub fn request_memory(&self) {
+        self.shared.memory_requested.store(true, Ordering::SeqCst);
+    }
+}
+
+impl Gameboy {
+    /// Get a read-only view of the Gameboy for another thread. The first call starts publishing
+    /// observations, starting with one of the Gameboy as it is now.
+    pub fn observer(&mut self) -> GbObserver {
+        if self.observers.is_none() {
+            self.observers = Some(Box::new(Observers::new(self.observe(false))));
+        }
+        let observers = self.observers.as_ref().unwrap();
+        GbObserver {
+            shared: observers.shared.clone(),
+        }
+    }
+
+    /// Choose when observations are published. Does nothing until [`Gameboy::observer`] has
+    /// been called.
+    pub fn set_observer_sync(&mut self, sync: SyncPoint) {
+        if let Some(observers) = &mut self.observers {
+            observers.sync = sync;
+        }
+    }
+
+    pub fn observer_sync(&self) -> Option<SyncPoint> {
+        self.observers.as_ref().map(|observers| observers.sync)
+    }
+
+    /// Publish an observation of the Gameboy as it is now, whatever the sync point is
+    pub fn publish_observation(&mut self) {
+        if let Some(observers) = &self.observers {
+            let memory = observers.take_memory_request();
+            let observation = self.observe(memory);
+            self.observers.as_ref().unwrap().publish(observation);
+        }
+    }
+
+    /// Called when a frame is completed
+    pub(super) fn publish_due_observation(&mut self) {
+        if let Some(observers) = &self.observers {
+            if observers.due(self.ppu.frame_count) {
+                self.publish_observation();
+            }
+        }
+    }
+
+    fn observe(&self, memory: bool) -> Observation {
+        let cpu = &self.cpu.cpu;
+        let copy = |range: std::ops::Range<u16>| range.map(|addr| self.peek(addr)).collect();
+        Observation {
+            cycle: self.cycles,
+            frame_count: self.ppu.frame_count,
+            registers: RegistersSnapshot {
+                registers: cpu.registers,
+                ime: cpu.ime,
+                halted: cpu.halted,
+                interrupt_enable: self.interrupt_enable,
+                interrupt_request: self.interrupt_request,
+            },
+            ppu: self.ppu.debug_snapshot(),
+            frame: self.ppu.get_frame(),
+            frame_info: self.ppu.frame_info(),
+            memory: memory.then(|| MemorySnapshot {
+                vram: copy(0x8000..0xA000),
+                wram: copy(0xC000..0xE000),
+            }),
+        }
+    }
+}
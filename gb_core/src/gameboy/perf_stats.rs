@@ -0,0 +1,215 @@
+//! Emulation speed and the host time spent in each part of the emulator
+//!
+//! Frame and cycle counts are always kept. Host time is only measured with the `perf-stats`
+//! feature, since reading the clock is not free (and not possible on every target). Even then,
+//! only one M-cycle in every [`SAMPLE_INTERVAL`] is timed, and the result is scaled up.
+
+use std::time::Duration;
+#[cfg(feature = "perf-stats")]
+use std::{collections::VecDeque, time::Instant};
+
+/// Number of frames the averages are taken over, unless changed with
+/// [`Gameboy::set_perf_stats_window`](super::Gameboy::set_perf_stats_window)
+pub const DEFAULT_WINDOW: usize = 60;
+
+/// One M-cycle in this many is timed
+pub const SAMPLE_INTERVAL: u32 = 64;
+
+/// The parts of an M-cycle that are timed separately
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subsystem {
+    /// Stepping the CPU, and everything that watches it execute (profiling, call stack tracking)
+    Cpu,
+    /// Advancing the PPU by 4 dots
+    Ppu,
+    /// Clocking every other chip, and the rest of the bus cycle
+    Bus,
+}
+
+/// Host time, by subsystem
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SubsystemTimes {
+    pub cpu: Duration,
+    pub ppu: Duration,
+    pub bus: Duration,
+}
+
+impl SubsystemTimes {
+    pub fn total(&self) -> Duration {
+        self.cpu + self.ppu + self.bus
+    }
+
+    #[cfg(feature = "perf-stats")]
+    fn get_mut(&mut self, subsystem: Subsystem) -> &mut Duration {
+        match subsystem {
+            Subsystem::Cpu => &mut self.cpu,
+            Subsystem::Ppu => &mut self.ppu,
+            Subsystem::Bus => &mut self.bus,
+        }
+    }
+}
+
+/// Averages over the last few frames, measured with the host clock
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PerfTiming {
+    /// Emulated frames completed per second of host time
+    pub frames_per_second: f64,
+    /// Emulation speed relative to a real Gameboy, where 1.0 is full speed
+    pub speed: f64,
+    /// Host time spent emulating each frame
+    pub frame_time: SubsystemTimes,
+}
+
+/// Returned by [`Gameboy::perf_stats`](super::Gameboy::perf_stats)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PerfStatsSnapshot {
+    /// Frames completed since the stats were last reset
+    pub frames: u64,
+    /// T-cycles run since the stats were last reset
+    pub cycles: u64,
+    /// `None` without the `perf-stats` feature, or until two frames have been completed
+    pub timing: Option<PerfTiming>,
+}
+
+#[derive(Debug)]
+pub(crate) struct PerfStats {
+    window: usize,
+    /// Frame and cycle counts when the stats were reset
+    start: (u64, u64),
+    #[cfg(feature = "perf-stats")]
+    timer: FrameTimer,
+}
+
+impl PerfStats {
+    pub fn new(frame_count: u64, cycles: u64) -> Self {
+        PerfStats {
+            window: DEFAULT_WINDOW,
+            start: (frame_count, cycles),
+            #[cfg(feature = "perf-stats")]
+            timer: FrameTimer::default(),
+        }
+    }
+
+    pub fn set_window(&mut self, frames: usize) {
+        self.window = frames.max(1);
+    }
+
+    pub fn reset(&mut self, frame_count: u64, cycles: u64) {
+        *self = PerfStats {
+            window: self.window,
+            ..PerfStats::new(frame_count, cycles)
+        };
+    }
+
+    pub fn snapshot(&self, frame_count: u64, cycles: u64) -> PerfStatsSnapshot {
+        PerfStatsSnapshot {
+            frames: frame_count.saturating_sub(self.start.0),
+            cycles: cycles.saturating_sub(self.start.1),
+            #[cfg(feature = "perf-stats")]
+            timing: self.timer.timing(),
+            #[cfg(not(feature = "perf-stats"))]
+            timing: None,
+        }
+    }
+
+    /// Called at the start of every M-cycle
+    #[inline(always)]
+    pub fn begin_cycle(&mut self) {
+        #[cfg(feature = "perf-stats")]
+        self.timer.begin_cycle();
+    }
+
+    /// Attribute the host time since the last lap to `subsystem`, if this M-cycle is being timed
+    #[inline(always)]
+    pub fn lap(&mut self, _subsystem: Subsystem) {
+        #[cfg(feature = "perf-stats")]
+        self.timer.lap(_subsystem);
+    }
+
+    /// Called at the end of the M-cycle in which a frame was completed
+    #[inline]
+    pub fn frame_completed(&mut self) {
+        #[cfg(feature = "perf-stats")]
+        self.timer.frame_completed(self.window);
+    }
+}
+
+#[cfg(feature = "perf-stats")]
+#[derive(Debug, Default)]
+struct FrameTimer {
+    /// M-cycles until the next one to be timed
+    countdown: u32,
+    /// Set during a timed M-cycle
+    last_lap: Option<Instant>,
+    /// Scaled time spent on the frame currently being emulated
+    current: SubsystemTimes,
+    /// When each of the last few frames was completed, and the time spent on it
+    frames: VecDeque<(Instant, SubsystemTimes)>,
+}
+
+#[cfg(feature = "perf-stats")]
+impl FrameTimer {
+    #[inline(always)]
+    fn begin_cycle(&mut self) {
+        if self.countdown == 0 {
+            self.countdown = SAMPLE_INTERVAL - 1;
+            self.last_lap = Some(Instant::now());
+        } else {
+            self.countdown -= 1;
+            self.last_lap = None;
+        }
+    }
+
+    #[inline(always)]
+    fn lap(&mut self, subsystem: Subsystem) {
+        if let Some(last) = &mut self.last_lap {
+            let now = Instant::now();
+            *self.current.get_mut(subsystem) += (now - *last) * SAMPLE_INTERVAL;
+            *last = now;
+        }
+    }
+
+    fn frame_completed(&mut self, window: usize) {
+        // One more timestamp than the window, so that there are `window` intervals between them
+        while self.frames.len() > window {
+            self.frames.pop_front();
+        }
+        let times = std::mem::take(&mut self.current);
+        self.frames.push_back((Instant::now(), times));
+    }
+
+    fn timing(&self) -> Option<PerfTiming> {
+        let (first, _) = self.frames.front()?;
+        let (last, _) = self.frames.back()?;
+        let intervals = self.frames.len() as u32 - 1;
+        let elapsed = (*last - *first).as_secs_f64();
+        if intervals == 0 || elapsed == 0.0 {
+            return None;
+        }
+
+        // The first frame's time was spent before the window started
+        let total = self.frames.iter().skip(1).map(|(_, times)| times).fold(
+            SubsystemTimes::default(),
+            |sum, times| SubsystemTimes {
+                cpu: sum.cpu + times.cpu,
+                ppu: sum.ppu + times.ppu,
+                bus: sum.bus + times.bus,
+            },
+        );
+        let frames_per_second = intervals as f64 / elapsed;
+        Some(PerfTiming {
+            frames_per_second,
+            speed: frames_per_second / FRAMES_PER_SECOND,
+            frame_time: SubsystemTimes {
+                cpu: total.cpu / intervals,
+                ppu: total.ppu / intervals,
+                bus: total.bus / intervals,
+            },
+        })
+    }
+}
+
+/// The frame rate of a real Gameboy
+#[cfg(feature = "perf-stats")]
+const FRAMES_PER_SECOND: f64 =
+    super::T_CYCLES_PER_SECOND as f64 / super::ppu::consts::FRAME_T_CYCLES as f64;
@@ -1,3 +1,5 @@
+use super::frame::Shade;
+
 pub type RgbaColor = u32;
 
 pub const COLOR_BLACK: RgbaColor = 0xFF000000;
@@ -11,3 +13,8 @@ pub fn calculate_monochrome_color_id(palette: u8, pix: u8) -> usize {
     assert!(pix < 4);
     ((palette >> (pix * 2)) & 0x03) as usize
 }
+
+/// The shade of each of the 4 colors of a palette register
+pub fn palette_shades(palette: u8) -> [Shade; 4] {
+    [0, 1, 2, 3].map(|pix| calculate_monochrome_color_id(palette, pix) as Shade)
+}
@@ -1 +1,10 @@
 pub const FRAME_T_CYCLES: usize = 70224;
+
+/// Number of T-cycles in each of the 154 lines of a frame
+pub const LINE_T_CYCLES: usize = 456;
+
+/// Number of dots of line 153 during which LY reads 153. It reads 0 for the rest of the line.
+pub const LINE_153_DOTS: usize = 4;
+
+/// Number of dots in the first line after the LCD is turned on, which is shorter than the usual 456
+pub const FIRST_LINE_DOTS: usize = 452;
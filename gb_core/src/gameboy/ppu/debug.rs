@@ -0,0 +1,272 @@
+//! Annotated copies of the PPU's output, for debugging views in frontends
+
+use super::{
+    color::{self, RgbaColor},
+    execute::PpuState,
+    frame::Frame,
+    registers::{OamEntry, OamEntryFlags},
+};
+
+/// Width and height of the background map in pixels
+pub const BACKGROUND_SIZE: usize = 256;
+
+/// Outline color of sprites using OBP0
+pub const COLOR_SPRITE_OBP0: RgbaColor = 0xFF0000FF;
+/// Outline color of sprites using OBP1
+pub const COLOR_SPRITE_OBP1: RgbaColor = 0xFF00FF00;
+pub const COLOR_WINDOW: RgbaColor = 0xFFFF0000;
+pub const COLOR_VIEWPORT: RgbaColor = 0xFFFF00FF;
+
+/// Which annotations to draw
+#[derive(Debug, Clone, Copy)]
+pub struct OverlayOptions {
+    /// Outline every sprite that was selected during OAM search
+    pub sprites: bool,
+    /// Outline the area covered by the window
+    pub window: bool,
+    /// Outline the visible area on a background render
+    pub viewport: bool,
+}
+
+impl Default for OverlayOptions {
+    fn default() -> Self {
+        OverlayOptions {
+            sprites: true,
+            window: true,
+            viewport: true,
+        }
+    }
+}
+
+/// A sprite that was selected during OAM search on at least one line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SelectedSprite {
+    /// Index into OAM
+    pub index: u8,
+    /// The OAM entry as it was on the first line the sprite was selected
+    pub entry: OamEntry,
+    /// 8 or 16
+    pub height: u8,
+}
+
+/// The lines of the frame that contain window pixels, and where the window starts on them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowArea {
+    /// The leftmost pixel of the window on any line
+    pub left: u8,
+    pub top: u8,
+    pub bottom: u8,
+}
+
+/// The PPU's hidden window state, as returned by [`PpuState::debug_snapshot`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PpuDebugSnapshot {
+    pub ly: u8,
+    /// Whether LY has matched WY yet this frame
+    pub wy_latch: bool,
+    /// The line of the window that will be drawn next, which lags behind LY - WY if the window
+    /// was hidden on some lines
+    pub window_line: u8,
+}
+
+/// One of the four steps of a pixel fetcher, each of which takes 2 dots
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FetcherStep {
+    #[default]
+    ReadTileNumber,
+    ReadDataLow,
+    ReadDataHigh,
+    /// Push the 8 pixels of the tile into the FIFO. The BG fetcher repeats this step until the
+    /// FIFO has room.
+    Push,
+}
+
+/// An entry of the BG or window tile map, in tiles
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TileMapPosition {
+    pub x: u8,
+    pub y: u8,
+    /// Whether this is in the window's tile map rather than the background's
+    pub window: bool,
+}
+
+/// A pixel waiting in one of the pixel FIFOs
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FifoPixel {
+    /// Color index, before the palette is applied
+    pub color: u8,
+    /// 0 for OBP0 and 1 for OBP1. Always 0 for BG pixels.
+    pub palette: u8,
+    /// The tile the pixel was fetched from
+    pub tile: u8,
+    /// The sprite is drawn behind BG colors 1-3
+    pub bg_priority: bool,
+}
+
+/// The state of the pixel FIFOs and fetchers at the end of a dot of mode 3, as returned by
+/// [`PpuState::fifo_snapshot`]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct FifoSnapshot {
+    pub ly: u8,
+    /// The X coordinate of the next pixel to be shifted out. This starts at -(SCX % 8), since those
+    /// pixels are discarded.
+    pub lx: i16,
+    /// BG or window pixels, from the next one to be shifted out
+    pub bg_fifo: Vec<FifoPixel>,
+    /// Sprite pixels, from the next one to be shifted out
+    pub sprite_fifo: Vec<FifoPixel>,
+    /// The step the BG fetcher performed last. The fetcher is paused while a sprite is fetched.
+    pub bg_fetcher: FetcherStep,
+    /// The step the sprite fetcher performed last, if it is fetching a sprite
+    pub sprite_fetcher: Option<FetcherStep>,
+    /// The tile map entry the BG fetcher reads next
+    pub tile_map: TileMapPosition,
+}
+
+/// What the PPU drew during one frame, beyond the pixels themselves
+#[derive(Debug, Clone)]
+pub(crate) struct FrameRecord {
+    sprites: [Option<SelectedSprite>; 40],
+    window: Option<WindowArea>,
+}
+
+impl Default for FrameRecord {
+    fn default() -> Self {
+        FrameRecord {
+            sprites: [None; 40],
+            window: None,
+        }
+    }
+}
+
+impl FrameRecord {
+    pub fn select_sprite(&mut self, index: usize, entry: OamEntry, height: u8) {
+        self.sprites[index].get_or_insert(SelectedSprite {
+            index: index as u8,
+            entry,
+            height,
+        });
+    }
+
+    /// Record that the window starts at pixel `x` of line `ly`
+    pub fn window_line(&mut self, x: u8, ly: u8) {
+        let area = self.window.get_or_insert(WindowArea {
+            left: x,
+            top: ly,
+            bottom: ly,
+        });
+        area.left = area.left.min(x);
+        area.bottom = ly;
+    }
+
+    pub fn sprites(&self) -> impl Iterator<Item = &SelectedSprite> {
+        self.sprites.iter().flatten()
+    }
+
+    pub fn window(&self) -> Option<WindowArea> {
+        self.window
+    }
+}
+
+/// Call `put` with the coordinates of every pixel on the outline of a rectangle
+fn outline(
+    mut put: impl FnMut(isize, isize),
+    (x, y): (isize, isize),
+    (width, height): (isize, isize),
+) {
+    for px in x..x + width {
+        put(px, y);
+        put(px, y + height - 1);
+    }
+    for py in y..y + height {
+        put(x, py);
+        put(x + width - 1, py);
+    }
+}
+
+/// Set pixels of a 160x144 image to `color`, ignoring any outside of the screen
+fn draw(image: &mut [RgbaColor], color: RgbaColor) -> impl FnMut(isize, isize) + '_ {
+    move |x, y| {
+        if (0..160).contains(&x) && (0..144).contains(&y) {
+            image[y as usize * 160 + x as usize] = color;
+        }
+    }
+}
+
+/// Return the colors of `base`, 160x144 pixels row by row, with outlines around the sprites and
+/// the window drawn during the last completed frame, as chosen by `options`. Sprites are outlined
+/// in [`COLOR_SPRITE_OBP0`] or [`COLOR_SPRITE_OBP1`] depending on their palette, and the window in
+/// [`COLOR_WINDOW`].
+pub fn render_overlay(state: &PpuState, base: &Frame, options: OverlayOptions) -> Vec<RgbaColor> {
+    let mut frame: Vec<RgbaColor> = base.colors().collect();
+
+    if options.window {
+        if let Some(area) = state.window_area() {
+            outline(
+                draw(&mut frame, COLOR_WINDOW),
+                (area.left as isize, area.top as isize),
+                (
+                    160 - area.left as isize,
+                    (area.bottom - area.top) as isize + 1,
+                ),
+            );
+        }
+    }
+
+    if options.sprites {
+        for sprite in state.selected_sprites() {
+            let color = if sprite.entry.flags.contains(OamEntryFlags::PALETTE_OBP1) {
+                COLOR_SPRITE_OBP1
+            } else {
+                COLOR_SPRITE_OBP0
+            };
+            outline(
+                draw(&mut frame, color),
+                (
+                    sprite.entry.xpos as isize - 8,
+                    sprite.entry.ypos as isize - 16,
+                ),
+                (8, sprite.height as isize),
+            );
+        }
+    }
+
+    frame
+}
+
+/// Render the entire current background map, using the current tile data and BGP. The result is
+/// [`BACKGROUND_SIZE`] pixels wide and high.
+pub fn render_background(state: &PpuState) -> Vec<RgbaColor> {
+    let mut image = vec![0; BACKGROUND_SIZE * BACKGROUND_SIZE];
+    for (i, pixel) in image.iter_mut().enumerate() {
+        let (x, y) = (i % BACKGROUND_SIZE, i / BACKGROUND_SIZE);
+        let tile_no = state.get_bg_tile_number((y / 8 * 32 + x / 8) as u16);
+        let row = state.bg_tile_data_address(tile_no) + y % 8 * 2;
+        let (lo, hi) = (state.tile_data[row], state.tile_data[row + 1]);
+        let bit = 7 - x % 8;
+        let pix = (hi >> bit & 1) << 1 | (lo >> bit & 1);
+        *pixel = color::COLORS[color::calculate_monochrome_color_id(state.bgp, pix)];
+    }
+    image
+}
+
+/// Return a copy of `background`, a render of the whole background map from
+/// [`render_background`], with the area currently scrolled onto the screen by SCX and SCY outlined
+/// in [`COLOR_VIEWPORT`]. The outline wraps around the edges like the background does.
+pub fn render_background_overlay(
+    state: &PpuState,
+    background: &[RgbaColor],
+    options: OverlayOptions,
+) -> Vec<RgbaColor> {
+    assert_eq!(background.len(), BACKGROUND_SIZE * BACKGROUND_SIZE);
+    let mut image = background.to_vec();
+    if options.viewport {
+        let size = BACKGROUND_SIZE as isize;
+        outline(
+            |x, y| image[(y % size * size + x % size) as usize] = COLOR_VIEWPORT,
+            (state.scx as isize, state.scy as isize),
+            (160, 144),
+        );
+    }
+    image
+}
@@ -1,16 +1,57 @@
+mod oam_bug;
 mod pixel_fifo;
 
-use crate::gameboy::ppu::color;
-use gb_cpu::{CpuInputPins, CpuOutputPins};
+use crate::gameboy::{
+    events::{DisplayEvent, Event, EventLog, EventMask},
+    logging::{self, trace_heavy},
+    memory::RamFiller,
+    ppu::color,
+    AccuracyLevel, BusInterleave, BusMaster, PpuBackend,
+};
+use crate::threading::dyn_maybe_send;
+use crate::GbError;
+use gb_cpu::CpuOutputPins;
 
-use self::pixel_fifo::Pixel;
+pub use self::pixel_fifo::Pixel;
+use self::pixel_fifo::{BgPixelFifo, SpritePixelFifo};
 
+#[cfg(feature = "differential")]
+use super::simple_renderer::{Divergence, LineState};
 use super::{
-    frame::Frame,
+    color::RgbaColor,
+    consts,
+    debug::{FifoPixel, FifoSnapshot, FrameRecord, PpuDebugSnapshot, SelectedSprite, WindowArea},
+    frame::{
+        attribution::{PaletteRegister, PixelSource},
+        post_process::PostProcess,
+        Shade,
+    },
+    frame_info::{CpuUsage, FrameInfo},
+    frame_pool::{FramePool, FrameRc, SharedFrame},
+    frame_sink::{FrameReceiver, FrameSink, PresentationMode},
+    priority::{mix_pixel, resolve_sprite_priority, MixResult, PriorityMode},
     registers::{OamEntry, OamEntryFlags, LCDC, STAT},
+    simple_renderer::LineView,
 };
 use std::{ops::Coroutine, pin::Pin};
 
+bitflags::bitflags! {
+    /// Registers at $FF40-$FF4B, one bit each in address order, that the PPU has used
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+    pub(super) struct UsedRegisters: u16 {
+        const LCDC = 1 << 0;
+        const STAT = 1 << 1;
+        const SCY = 1 << 2;
+        const SCX = 1 << 3;
+        const LYC = 1 << 5;
+        const BGP = 1 << 7;
+        const OBP0 = 1 << 8;
+        const OBP1 = 1 << 9;
+        const WY = 1 << 10;
+        const WX = 1 << 11;
+    }
+}
+
 pub struct PpuState {
     pub tile_data: [u8; 0x9800 - 0x8000],
 
@@ -27,19 +68,139 @@ pub struct PpuState {
     pub lyc: u8,
     pub wy: u8,
     pub wx: u8,
+    /// Writing the palette registers directly, rather than over the bus, only takes effect from the
+    /// next scanline
     pub bgp: u8,
     pub obp0: u8,
     pub obp1: u8,
+    /// The shades of BGP, OBP0 and OBP1, in that order
+    palette_shades: [[Shade; 4]; 3],
+    /// Set when LY is compared equal to WY, which happens whenever either of them changes, and
+    /// cleared at the start of every frame. The window can only be drawn on lines that start with
+    /// this set.
+    pub(super) wy_latch: bool,
+    /// The line of the window to draw next. Only lines that actually contain window pixels count.
+    pub(super) window_line: u8,
 
     vblank_irq: bool,
     stat_irq: bool,
+    /// Emulate the DMG bug where writing STAT acts as if the HBlank, VBlank and LYC interrupt
+    /// sources were all enabled for a moment, which raises a STAT interrupt during HBlank, VBlank
+    /// or while LY=LYC, whatever value is written. Set from
+    /// [`Model::stat_write_bug`](crate::gameboy::Model::stat_write_bug) when the Gameboy is built.
+    pub stat_write_bug: bool,
+    /// How overlapping sprites are ordered
+    pub priority_mode: PriorityMode,
+    /// Whether OPRI ($FF6C) exists. Without it, `priority_mode` can only be changed directly. Set
+    /// from [`Model::has_opri`](crate::gameboy::Model::has_opri) when the Gameboy is built.
+    pub has_opri: bool,
+    /// OPRI can only be written while the boot ROM is mapped
+    pub(crate) opri_locked: bool,
+    /// Decides what the CPU reads from VRAM during mode 3. Set from the
+    /// [`GameboyBuilder`](crate::gameboy::GameboyBuilder) when the Gameboy is built.
+    pub accuracy: AccuracyLevel,
+    /// How lines are drawn, checked at the start of each line's mode 3. Set from the
+    /// [`GameboyBuilder`](crate::gameboy::GameboyBuilder) when the Gameboy is built.
+    pub backend: PpuBackend,
+    /// Where CPU writes land among the dots of an M-cycle. Set from the
+    /// [`GameboyBuilder`](crate::gameboy::GameboyBuilder) when the Gameboy is built.
+    pub interleave: BusInterleave,
+    /// Report CPU writes to the registers at $FF40-$FF4B that land in an M-cycle where the PPU
+    /// also used the register, as [`Event::OrderingHazard`]
+    pub ordering_check: bool,
+    /// The registers the PPU has used during the current M-cycle
+    pub(super) consumed: UsedRegisters,
+    /// The byte of VRAM the BG or sprite fetcher read last, which is what the CPU reads from VRAM
+    /// during mode 3 with [`AccuracyLevel::Accurate`]
+    pub last_fetcher_read: u8,
+    /// The VBlank and STAT interrupt lines as of the last bus cycle, in IF bit order
+    irq_lines: u8,
+    /// A pulse from the STAT write bug that is latched on the next bus cycle, because the write
+    /// landed after the lines were latched with [`BusInterleave::TCycle`]
+    pub(super) stat_pulse: bool,
 
-    pub frame: Box<Frame>,
+    pub frame: SharedFrame,
     // Double-buffer the frames to prevent tearing
-    back_frame: Box<Frame>,
+    back_frame: FrameRc,
+    /// Where `back_frame` is replaced from when it is finished
+    frame_pool: FramePool,
+    /// Created when the first [`FrameReceiver`] is requested
+    frame_sink: Option<FrameSink>,
+    presentation_mode: PresentationMode,
+    /// Whether `frame` has been taken by [`PpuState::take_frame`] yet
+    frame_taken: bool,
+    /// Frames completed, and frames taken by [`PpuState::take_frame`], since power on
+    frames_produced: u64,
+    frames_presented: u64,
+    /// Run on `back_frame` when it is finished
+    post_processing: Vec<Box<dyn_maybe_send!(PostProcess)>>,
 
-    /// Indicates a DMA transfer in progress, and the next address to read.
+    /// The sprites and window drawn in the frame being drawn, and in `frame`
+    back_record: FrameRecord,
+    record: FrameRecord,
+    /// Counted while drawing the frame being drawn, and handed off with `frame`
+    back_info: FrameInfo,
+    info: FrameInfo,
+    /// Counted by the Gameboy since the last frame was completed, drawn or not. Unlike
+    /// `back_info`, this isn't cleared when a frame starts, so it covers VBlank too.
+    pub(crate) cpu_usage: CpuUsage,
+    /// `cpu_usage` as it was when the last frame was completed, to be handed off with it
+    frame_cpu_usage: CpuUsage,
+    /// Whether the LCD has been turned off since the last frame was handed off
+    pub(super) lcd_was_disabled: bool,
+
+    /// Only present while FIFO snapshots are enabled
+    fifo_snapshot: Option<Box<FifoSnapshot>>,
+    /// The state at the start of the line being drawn, and whether anything it holds has been
+    /// written since
+    #[cfg(feature = "differential")]
+    differential_line: Option<(Box<LineState>, bool)>,
+    #[cfg(feature = "differential")]
+    divergences: Vec<Divergence>,
+
+    pub(crate) events: EventLog,
+
+    /// The OAM DMA transfer in progress, and any that is about to start
     pub dma_transfer: DmaState,
+    /// The last value written to DMA, which is what reading it returns
+    pub dma: u8,
+
+    /// Set to the value of LY when a scanline finishes drawing. The driver is expected to `take()` this.
+    pub last_completed_line: Option<u8>,
+
+    /// Dots left before the coroutine needs to run again, unless the LCD is turned off. The
+    /// coroutine sets this instead of yielding on every dot when it is only waiting.
+    pub(super) idle_dots: u16,
+    /// Dots since line 0 of the frame being drawn began, counting the short first line after the
+    /// LCD is turned on as a full one, so that the same count is the same place in every frame.
+    /// While the LCD is off, dots since the last blank frame was counted.
+    pub(super) frame_dots: u32,
+    /// Set from the LCD being turned on until the end of the first line, which is only
+    /// [`FIRST_LINE_DOTS`](consts::FIRST_LINE_DOTS) long
+    pub(super) first_line: bool,
+    /// The dot of the line on which mode 3 ends. The dot-accurate backend only knows once the last
+    /// pixel has been pushed, so until then this is the earliest mode 3 could end.
+    pub(super) mode_3_end: u16,
+
+    /// Number of frames skipped between each drawn frame
+    frame_skip: u32,
+    /// Frames left to skip before the next drawn frame
+    frames_until_drawn: u32,
+    /// If set, overrides `frame_skip` when deciding whether to draw the next frame
+    pub(crate) draw_next_frame: Option<bool>,
+    /// Overrides both of the above while capture triggers are waiting to fire
+    pub(crate) draw_every_frame: bool,
+    /// Whether pixels are being produced for the current frame
+    drawing: bool,
+    /// Whether drawn frames record where each pixel came from
+    pixel_attribution: bool,
+    /// Number of frames completed since power on. While the LCD is off, this still goes up every
+    /// 70224 dots, so that frontends keep their pacing.
+    pub frame_count: u64,
+    /// T-cycles since power on, kept in step with [`ClockContext::cycles`] while on the bus
+    ///
+    /// [`ClockContext::cycles`]: crate::gameboy::ClockContext::cycles
+    pub(crate) cycles: u64,
 }
 
 impl std::fmt::Debug for PpuState {
@@ -62,6 +223,8 @@ impl std::fmt::Debug for PpuState {
 
 impl PpuState {
     pub fn new() -> Self {
+        let mut frame_pool = FramePool::new();
+        let frame = frame_pool.take();
         PpuState {
             tile_data: [0u8; 0x9800 - 0x8000],
 
@@ -81,23 +244,290 @@ impl PpuState {
             bgp: 0u8,
             obp0: 0u8,
             obp1: 0u8,
+            palette_shades: [[0; 4]; 3],
+            wy_latch: false,
+            window_line: 0,
 
             vblank_irq: false,
             stat_irq: false,
+            stat_write_bug: true,
+            priority_mode: PriorityMode::Dmg,
+            has_opri: false,
+            opri_locked: false,
+            accuracy: AccuracyLevel::Fast,
+            backend: PpuBackend::DotAccurate,
+            interleave: BusInterleave::MCycle,
+            ordering_check: false,
+            consumed: UsedRegisters::empty(),
+            last_fetcher_read: 0,
+            irq_lines: 0,
+            stat_pulse: false,
+
+            frame: frame_pool.share(frame),
+            back_frame: frame_pool.take(),
+            frame_pool,
+            frame_sink: None,
+            presentation_mode: PresentationMode::EveryFrame,
+            // The blank frame at power on was never drawn
+            frame_taken: true,
+            frames_produced: 0,
+            frames_presented: 0,
+            post_processing: Vec::new(),
+
+            back_record: FrameRecord::default(),
+            record: FrameRecord::default(),
+            back_info: FrameInfo::default(),
+            info: FrameInfo::default(),
+            cpu_usage: CpuUsage::default(),
+            frame_cpu_usage: CpuUsage::default(),
+            lcd_was_disabled: false,
+
+            fifo_snapshot: None,
+            #[cfg(feature = "differential")]
+            differential_line: None,
+            #[cfg(feature = "differential")]
+            divergences: Vec::new(),
+
+            events: EventLog::default(),
+
+            dma_transfer: DmaState::default(),
+            dma: 0xFF,
+
+            last_completed_line: None,
+
+            idle_dots: 0,
+            frame_dots: 0,
+            first_line: false,
+            mode_3_end: 0,
+
+            frame_skip: 0,
+            frames_until_drawn: 0,
+            pixel_attribution: false,
+            draw_next_frame: None,
+            draw_every_frame: false,
+            drawing: true,
+            frame_count: 0,
+            cycles: 0,
+        }
+    }
+
+    /// Return to the state at power on, except for VRAM and OAM, which keep their contents. The
+    /// settings that aren't part of the hardware are kept too: those from the model and the
+    /// builder, frame skip, pixel attribution, post-processing, FIFO snapshots, and the frames and
+    /// the receivers they are sent to.
+    pub(crate) fn power_cycle(&mut self) {
+        let old = std::mem::replace(self, PpuState::new());
+        self.tile_data = old.tile_data;
+        self.bg_map_1 = old.bg_map_1;
+        self.bg_map_2 = old.bg_map_2;
+        self.oam = old.oam;
+        self.stat_write_bug = old.stat_write_bug;
+        self.has_opri = old.has_opri;
+        self.accuracy = old.accuracy;
+        self.backend = old.backend;
+        self.interleave = old.interleave;
+        self.ordering_check = old.ordering_check;
+        self.frame = old.frame;
+        self.back_frame = old.back_frame;
+        self.frame_pool = old.frame_pool;
+        self.frame_sink = old.frame_sink;
+        self.presentation_mode = old.presentation_mode;
+        self.post_processing = old.post_processing;
+        self.fifo_snapshot = old.fifo_snapshot.map(|_| Default::default());
+        self.frame_skip = old.frame_skip;
+        self.draw_every_frame = old.draw_every_frame;
+        self.pixel_attribution = old.pixel_attribution;
+    }
+
+    /// Overwrite VRAM and then OAM with their power on contents
+    pub fn fill_ram(&mut self, filler: &mut RamFiller) {
+        // Each part of VRAM is a multiple of 16 bytes long, so filling them one after another is
+        // the same as filling VRAM as a whole
+        filler.fill(&mut self.tile_data);
+        filler.fill(&mut self.bg_map_1);
+        filler.fill(&mut self.bg_map_2);
+        filler.fill(&mut self.oam);
+    }
+
+    /// Only draw one out of every `n + 1` frames. Skipped frames still run with exact timing, but
+    /// no pixels are produced and the front frame keeps the last drawn image.
+    pub fn set_frame_skip(&mut self, n: u32) {
+        self.frame_skip = n;
+        self.frames_until_drawn = self.frames_until_drawn.min(n);
+    }
+
+    pub fn frame_skip(&self) -> u32 {
+        self.frame_skip
+    }
+
+    /// Record where each pixel of the drawn frames came from, in
+    /// [`Frame::attribution`](super::frame::Frame::attribution). This takes effect from the next
+    /// line drawn.
+    pub fn set_pixel_attribution(&mut self, enabled: bool) {
+        self.pixel_attribution = enabled;
+    }
+
+    pub fn pixel_attribution(&self) -> bool {
+        self.pixel_attribution
+    }
+
+    /// Decide whether the frame that is about to start will be drawn
+    fn begin_frame(&mut self) {
+        let draw =
+            self.draw_every_frame || self.draw_next_frame.unwrap_or(self.frames_until_drawn == 0);
+        if draw {
+            // Drawing a frame restarts the skip count
+            self.frames_until_drawn = self.frame_skip;
+        } else {
+            self.frames_until_drawn = self.frames_until_drawn.saturating_sub(1);
+        }
+        self.drawing = draw;
+        self.back_record = FrameRecord::default();
+        self.back_info = FrameInfo::default();
+        self.wy_latch = false;
+        self.window_line = 0;
+        self.frame_dots = 0;
+    }
+
+    /// During the OAM scan, the OAM row (two entries) that the PPU reads during the next M-cycle.
+    /// Each row takes 4 dots.
+    fn oam_scan_row(&self) -> Option<usize> {
+        let dot = self.current_dot_in_line() as usize;
+        (self.current_line() < 144 && (1..=80).contains(&dot)).then_some((dot + 1) / 4)
+    }
+
+    /// `frame_dots`, but with the first line after the LCD is turned on ending 4 dots early
+    fn line_start_dots(&self) -> u32 {
+        let short = (consts::LINE_T_CYCLES - consts::FIRST_LINE_DOTS) as u32;
+        if self.first_line && self.frame_dots >= consts::FIRST_LINE_DOTS as u32 {
+            self.frame_dots + short
+        } else {
+            self.frame_dots
+        }
+    }
+
+    /// The line the PPU is on, from 0 to 153. This is what LY reads, except near the end of
+    /// line 153, where LY already reads 0. While the LCD is off, the PPU stays at the start of
+    /// line 0.
+    pub fn current_line(&self) -> u8 {
+        if !self.lcdc.contains(LCDC::LCD_ENABLE) {
+            return 0;
+        }
+        (self.line_start_dots() / consts::LINE_T_CYCLES as u32 % 154) as u8
+    }
+
+    /// How many dots of the current line the PPU has run, from 0 to 455. The first line after
+    /// the LCD is turned on is 4 dots short, so it ends after dot 451.
+    pub fn current_dot_in_line(&self) -> u16 {
+        if !self.lcdc.contains(LCDC::LCD_ENABLE) {
+            return 0;
+        }
+        (self.line_start_dots() % consts::LINE_T_CYCLES as u32) as u16
+    }
+
+    /// How many more dots the PPU has to run before STAT shows the next mode. This is at least 1.
+    /// With the dot-accurate backend, the end of mode 3 isn't known until it is reached, so this
+    /// counts down to the earliest it could end, and is 1 from then until it does.
+    ///
+    /// Nothing changes while the LCD is off, so this is `u16::MAX` then.
+    pub fn dots_until_mode_change(&self) -> u16 {
+        if !self.lcdc.contains(LCDC::LCD_ENABLE) {
+            return u16::MAX;
+        }
+        let (line, dot) = (self.current_line(), self.current_dot_in_line());
+        if line >= 144 {
+            // Mode 2 starts on the first dot of the next frame, unless this is the first dot of
+            // VBlank
+            return match self.dots_until_vblank() {
+                1 => 1,
+                _ => ((154 - line as u32) * consts::LINE_T_CYCLES as u32 - dot as u32 + 1) as u16,
+            };
+        }
+        if self.stat.mode() == STAT::MODE_3 && dot >= self.mode_3_end {
+            return 1;
+        }
+        // Once the first line is over, the coroutine only notices on the first dot of the next
+        let first_line = self.first_line && line == 0;
+        let line_length = if first_line {
+            consts::FIRST_LINE_DOTS as u16
+        } else {
+            consts::LINE_T_CYCLES as u16
+        };
+        // The dots of the line on which each mode starts. On the first line after the LCD is
+        // turned on, STAT stays in mode 0 through the OAM scan.
+        let changes = [
+            (!first_line).then_some(0),
+            Some(80),
+            Some(self.mode_3_end),
+            Some(line_length),
+        ];
+        let next = changes
+            .iter()
+            .flatten()
+            .copied()
+            .find(|&start| start >= dot);
+        next.unwrap_or(line_length) - dot + 1
+    }
 
-            frame: Box::new(Frame::new()),
-            back_frame: Box::new(Frame::new()),
+    /// How many more dots the PPU has to run before VBlank starts, at least 1. This is `u32::MAX`
+    /// while the LCD is off, when there is no VBlank.
+    pub fn dots_until_vblank(&self) -> u32 {
+        if !self.lcdc.contains(LCDC::LCD_ENABLE) {
+            return u32::MAX;
+        }
+        let vblank = 144 * consts::LINE_T_CYCLES as u32;
+        let short = (consts::LINE_T_CYCLES - consts::FIRST_LINE_DOTS) as u32;
+        match self.line_start_dots() {
+            dots if dots > vblank => consts::FRAME_T_CYCLES as u32 - dots + vblank + 1,
+            _ if self.first_line => vblank - short - self.frame_dots + 1,
+            dots => vblank - dots + 1,
+        }
+    }
 
-            dma_transfer: DmaState::Inactive,
+    /// Where the PPU is in its frame, along with what it remembers from earlier in the frame
+    pub(crate) fn frame_position(&self) -> FramePosition {
+        FramePosition {
+            dots: self.frame_dots,
+            wy_latch: self.wy_latch,
+            window_line: self.window_line,
+            lcd_was_disabled: self.lcd_was_disabled,
+        }
+    }
+
+    /// What happened while the frame in [`PpuState::frame`] was being drawn
+    pub fn frame_info(&self) -> FrameInfo {
+        self.info
+    }
+
+    /// The sprites selected during OAM search in the frame in [`PpuState::frame`], by OAM index
+    pub fn selected_sprites(&self) -> impl Iterator<Item = &SelectedSprite> {
+        self.record.sprites()
+    }
+
+    /// The part of the screen covered by the window in the frame in [`PpuState::frame`], if it
+    /// was drawn at all
+    pub fn window_area(&self) -> Option<WindowArea> {
+        self.record.window()
+    }
+
+    /// Returns the nth OAM entry, or an error if `index` >= 40
+    pub fn oam(&self, index: usize) -> Result<OamEntry, GbError> {
+        if index < 40 {
+            Ok(self.oam_entry(index))
+        } else {
+            let addr = 0xFE00usize.saturating_add(index.saturating_mul(4));
+            Err(GbError::AddressOutOfRange(
+                addr.min(u16::MAX as usize) as u16
+            ))
         }
     }
 
     /// Returns the nth OAM entry
     ///
     /// # Panics
-    /// Panics if `index` > 40
-    pub fn oam(&self, index: usize) -> OamEntry {
-        assert!(index <= 40);
+    /// Panics if `index` >= 40
+    fn oam_entry(&self, index: usize) -> OamEntry {
+        assert!(index < 40);
         OamEntry {
             ypos: self.oam[index * 4],
             xpos: self.oam[index * 4 + 1],
@@ -118,7 +548,7 @@ impl PpuState {
     ///
     /// # Panics
     /// Panics if `offset` >= 0x400
-    fn get_bg_tile_number(&self, offset: u16) -> u8 {
+    pub(super) fn get_bg_tile_number(&self, offset: u16) -> u8 {
         if self.lcdc.contains(LCDC::BG_TILEMAP_AREA) {
             self.bg_map_2[offset as usize]
         } else {
@@ -139,11 +569,11 @@ impl PpuState {
     }
 
     /// Return the index of the first byte of the tile data for tile `n`, using the appropriate BG tile data addressing mode
-    fn bg_tile_data_address(&self, tile_no: u8) -> usize {
+    pub(super) fn bg_tile_data_address(&self, tile_no: u8) -> usize {
         if self.lcdc.contains(LCDC::BG_TILE_DATA_AREA) {
             tile_no as usize * 16
         } else {
-            0x1000 + (tile_no as i8 as i16 * 16) as usize
+            (0x1000 + tile_no as i8 as isize * 16) as usize
         }
     }
 
@@ -152,27 +582,196 @@ impl PpuState {
         tile_no as usize * 16
     }
 
-    fn put_pixel(&mut self, bg_pix: Pixel, sprite_pix: Pixel, x: usize, y: usize) {
-        assert!(x < 160);
-        assert!(y < 144);
-        let color_id = if sprite_pix.color == 0 || (sprite_pix.bg_priority && bg_pix.color != 0) {
-            // If the sprite pixel is transparent, draw the BG pixel
-            // If the sprite has BG priority and the background color is not 0, draw the BG pixel
-            color::calculate_monochrome_color_id(self.bgp, bg_pix.color)
-        } else {
-            // Otherwise, draw the sprite pixel
-            let palette = if sprite_pix.palette == 0 {
-                self.obp0
-            } else {
-                self.obp1
-            };
-            color::calculate_monochrome_color_id(palette, sprite_pix.color)
-        };
-        self.back_frame[(x, y)] = color::COLORS[color_id];
+    fn update_palette_shades(&mut self) {
+        self.palette_shades = [self.bgp, self.obp0, self.obp1].map(color::palette_shades);
+    }
+
+    /// The pixel that is drawn out of `bg_pix` and `sprite_pix`, and the index of its palette in
+    /// `palette_shades`
+    fn mix_pixels(&self, bg_pix: Pixel, sprite_pix: Pixel) -> (Pixel, usize) {
+        // The background is still fetched as usual while disabled, so the timing of mode 3 is
+        // unaffected
+        match mix_pixel(bg_pix, sprite_pix, self.lcdc) {
+            MixResult::Bg(pixel) => (pixel, 0),
+            MixResult::Sprite(pixel) => (pixel, 1 + (pixel.palette & 1) as usize),
+        }
+    }
+
+    /// Mark the registers used to draw a pixel out of `bg_pix` and `sprite_pix`
+    fn use_pixel_registers(&mut self, bg_pix: Pixel, sprite_pix: Pixel) {
+        let palettes = [UsedRegisters::BGP, UsedRegisters::OBP0, UsedRegisters::OBP1];
+        let (_, palette) = self.mix_pixels(bg_pix, sprite_pix);
+        self.consumed |= UsedRegisters::LCDC | palettes[palette];
+    }
+
+    /// Report a CPU write of `value` to `addr` at $FF40-$FF4B if the PPU used that register
+    /// during the same M-cycle, where the order of the two decides what is drawn
+    pub(super) fn check_ordering(&mut self, addr: u16, value: u8) {
+        if !self
+            .consumed
+            .contains(UsedRegisters::from_bits_retain(1 << (addr - 0xFF40)))
+        {
+            return;
+        }
+        let (ly, dot) = (
+            self.ly,
+            (self.frame_dots % consts::LINE_T_CYCLES as u32) as u16,
+        );
+        log::warn!(
+            target: logging::PPU,
+            "${:02X} written to ${:04X} in the M-cycle the PPU used it, at LY={} dot {}",
+            value,
+            addr,
+            ly,
+            dot
+        );
+        self.events
+            .emit(EventMask::ORDERING_HAZARD, || Event::OrderingHazard {
+                addr,
+                value,
+                ly,
+                dot,
+            });
+    }
+
+    fn pixel_shade(&self, bg_pix: Pixel, sprite_pix: Pixel) -> Shade {
+        let (pixel, palette) = self.mix_pixels(bg_pix, sprite_pix);
+        self.palette_shades[palette][pixel.color as usize & 3]
+    }
+
+    fn pixel_source(&self, bg_pix: Pixel, sprite_pix: Pixel) -> PixelSource {
+        let (pixel, palette) = self.mix_pixels(bg_pix, sprite_pix);
+        PixelSource {
+            layer: pixel.layer,
+            color: pixel.color,
+            palette: match palette {
+                0 => PaletteRegister::Bgp,
+                1 => PaletteRegister::Obp0,
+                _ => PaletteRegister::Obp1,
+            },
+        }
+    }
+
+    /// Returns a row of the frame currently being drawn
+    ///
+    /// # Panics
+    /// Panics if `ly` >= 144
+    pub fn back_frame_row(&self, ly: u8) -> [RgbaColor; 160] {
+        self.back_frame.row_colors(ly as usize)
+    }
+
+    /// Replace the stages run on each finished frame before it is shown. An empty chain skips
+    /// post-processing entirely.
+    pub fn set_post_processing(&mut self, stages: Vec<Box<dyn_maybe_send!(PostProcess)>>) {
+        self.post_processing = stages;
     }
 
     fn swap_frames(&mut self) {
-        std::mem::swap(&mut self.back_frame, &mut self.frame);
+        let mut next = self.frame_pool.take();
+        let frame = FrameRc::get_mut(&mut next).expect("frame pool buffer is shared");
+        frame.clear_pixel_colors();
+        frame.clear_attribution();
+        let mut finished = std::mem::replace(&mut self.back_frame, next);
+        if !self.post_processing.is_empty() {
+            let frame = FrameRc::get_mut(&mut finished).expect("back frame is shared");
+            for stage in &mut self.post_processing {
+                stage.process(frame);
+            }
+        }
+        self.frame = self.frame_pool.share(finished);
+        std::mem::swap(&mut self.back_record, &mut self.record);
+        self.info = FrameInfo {
+            cycle: self.cycles,
+            number: self.frame_count,
+            lcd_was_disabled: std::mem::take(&mut self.lcd_was_disabled),
+            cpu: self.frame_cpu_usage,
+            ..std::mem::take(&mut self.back_info)
+        };
+        self.frames_produced += 1;
+        self.frame_taken = false;
+        if self.presentation_mode == PresentationMode::EveryFrame {
+            if let Some(sink) = &self.frame_sink {
+                sink.push(self.info, &self.frame);
+            }
+        }
+    }
+
+    /// Start counting what the CPU does in the next frame
+    fn complete_frame_cpu_usage(&mut self) {
+        self.frame_cpu_usage = std::mem::take(&mut self.cpu_usage);
+    }
+
+    /// Stop the PPU when the LCD is turned off, and replace the front frame with color 0 of BGP
+    fn disable_lcd(&mut self) {
+        self.ly = 0;
+        self.frame_dots = 0;
+        self.stat.set_mode(STAT::MODE_0);
+        self.vblank_irq = false;
+        self.stat_irq = false;
+
+        self.update_palette_shades();
+        let blank = self.palette_shades[0][0];
+        let back_frame = FrameRc::get_mut(&mut self.back_frame).expect("back frame is shared");
+        back_frame.iter_mut().for_each(|pixel| *pixel = blank);
+        back_frame.clear_attribution();
+        self.back_record = FrameRecord::default();
+        self.back_info = FrameInfo::default();
+        self.lcd_was_disabled = true;
+        self.complete_frame_cpu_usage();
+        self.swap_frames();
+        log::debug!(target: logging::PPU, "LCD off");
+        self.events.emit(EventMask::DISPLAY, || {
+            Event::Display(DisplayEvent::LcdDisabled)
+        });
+    }
+
+    /// Get a handle that can read the latest completed frame from another thread
+    pub fn frame_receiver(&mut self) -> FrameReceiver {
+        let (info, frame) = (self.info, &self.frame);
+        self.frame_sink
+            .get_or_insert_with(|| {
+                let sink = FrameSink::new();
+                sink.push(info, frame);
+                sink
+            })
+            .receiver()
+    }
+
+    /// Choose when completed frames are copied to [`FrameReceiver`]s
+    pub fn set_presentation_mode(&mut self, mode: PresentationMode) {
+        self.presentation_mode = mode;
+    }
+
+    pub fn presentation_mode(&self) -> PresentationMode {
+        self.presentation_mode
+    }
+
+    /// The latest completed frame, if it hasn't been taken already. With
+    /// [`PresentationMode::LatestOnly`], this is also when it is copied to [`FrameReceiver`]s.
+    pub fn take_frame(&mut self) -> Option<SharedFrame> {
+        if self.frame_taken {
+            return None;
+        }
+        self.frame_taken = true;
+        self.frames_presented += 1;
+        if self.presentation_mode == PresentationMode::LatestOnly {
+            if let Some(sink) = &self.frame_sink {
+                sink.push(self.info, &self.frame);
+            }
+        }
+        Some(self.frame.clone())
+    }
+
+    /// Number of frames drawn since power on. Frames skipped by frame skip are not counted, and
+    /// turning the LCD off completes one blank frame.
+    pub fn frames_produced(&self) -> u64 {
+        self.frames_produced
+    }
+
+    /// Number of frames returned by [`PpuState::take_frame`] since power on. The rest of
+    /// [`PpuState::frames_produced`] were dropped.
+    pub fn frames_presented(&self) -> u64 {
+        self.frames_presented
     }
 }
 
@@ -187,15 +786,115 @@ impl PpuState {
     fn set_ly(&mut self, ly: u8) {
         debug_assert!(ly <= 153);
         self.ly = ly;
+        self.consumed |= UsedRegisters::STAT | UsedRegisters::LYC | UsedRegisters::WY;
         self.stat.set(STAT::LYC_EQUALS_LY, self.ly == self.lyc);
+        self.check_wy();
 
         self.update_stat_interrupt();
     }
 
+    /// Bring the palette shades, the LYC=LY flag and the STAT interrupt line up to date, after
+    /// the registers have been changed without going through the bus
+    pub(crate) fn refresh_registers(&mut self) {
+        self.stat.set(STAT::LYC_EQUALS_LY, self.ly == self.lyc);
+        self.update_palette_shades();
+        self.update_stat_interrupt();
+    }
+
+    /// Latch a match between LY and WY. Once latched, changing WY again has no effect until the
+    /// next frame.
+    fn check_wy(&mut self) {
+        self.wy_latch |= self.ly == self.wy;
+    }
+
+    /// The internal state of the PPU that can't be read through its registers
+    pub fn debug_snapshot(&self) -> PpuDebugSnapshot {
+        PpuDebugSnapshot {
+            ly: self.ly,
+            wy_latch: self.wy_latch,
+            window_line: self.window_line,
+        }
+    }
+
+    /// Lines where the pixel FIFOs drew something different to
+    /// [`simple_renderer`](super::simple_renderer), with the first pixel that differs on each.
+    /// Lines where the PPU's registers, VRAM or OAM were written while they were being drawn are
+    /// not checked.
+    #[cfg(feature = "differential")]
+    pub fn divergences(&self) -> &[Divergence] {
+        &self.divergences
+    }
+
+    /// Start checking a line against the simple renderer
+    #[cfg(feature = "differential")]
+    fn begin_differential_line(&mut self) {
+        self.differential_line = Some((Box::new(LineState::capture(self)), false));
+    }
+
+    /// Compare the line drawn by the FIFOs against the simple renderer
+    #[cfg(feature = "differential")]
+    fn check_differential_line(&mut self, line: &[Shade; 160]) {
+        if let Some((state, false)) = self.differential_line.take() {
+            self.divergences.extend(Divergence::check(&state, line));
+        }
+    }
+
+    /// Note that something the line being drawn depends on has changed, so that the differential
+    /// check skips it
+    fn line_written(&mut self) {
+        #[cfg(feature = "differential")]
+        if let Some((_, written)) = &mut self.differential_line {
+            *written = true;
+        }
+    }
+
+    /// Start or stop recording the contents of the pixel FIFOs after every dot of mode 3, for
+    /// [`PpuState::fifo_snapshot`]
+    pub fn enable_fifo_snapshots(&mut self, enabled: bool) {
+        self.fifo_snapshot = enabled.then(Default::default);
+    }
+
+    /// The pixel FIFOs and fetchers as of the last dot of mode 3, or `None` if snapshots are not
+    /// enabled. This is empty until mode 3 is first reached after enabling them.
+    pub fn fifo_snapshot(&self) -> Option<&FifoSnapshot> {
+        self.fifo_snapshot.as_deref()
+    }
+
+    fn record_fifos(&mut self, bg_fifo: &BgPixelFifo, sprite_fifo: &SpritePixelFifo, lx: isize) {
+        let tile_map = bg_fifo.tile_map_position(self);
+        let ly = self.ly;
+        if let Some(snapshot) = &mut self.fifo_snapshot {
+            let pixel = |pixel: Pixel| FifoPixel {
+                color: pixel.color,
+                palette: pixel.palette,
+                tile: pixel.tile,
+                bg_priority: pixel.bg_priority,
+            };
+            snapshot.ly = ly;
+            snapshot.lx = lx as i16;
+            snapshot.bg_fifo.clear();
+            snapshot.bg_fifo.extend(bg_fifo.pixels().map(pixel));
+            snapshot.sprite_fifo.clear();
+            snapshot.sprite_fifo.extend(sprite_fifo.pixels().map(pixel));
+            snapshot.bg_fetcher = bg_fifo.step();
+            snapshot.sprite_fetcher = sprite_fifo.step();
+            snapshot.tile_map = tile_map;
+        }
+    }
+
     #[inline(always)]
-    fn set_mode(&mut self, mode: u8) {
+    fn set_mode(&mut self, mode: u8, dot: u16) {
         debug_assert!(mode <= 3);
         self.stat.set_mode(STAT::from_bits_truncate(mode));
+        self.consumed |= UsedRegisters::STAT;
+        let ly = self.ly;
+        trace_heavy!(target: logging::PPU, "mode {} at LY={} dot {}", mode, ly, dot);
+        self.events
+            .emit(EventMask::PPU_MODE_CHANGE, || Event::PpuModeChange {
+                ly,
+                mode,
+                dot,
+            });
 
         self.update_stat_interrupt();
     }
@@ -218,10 +917,47 @@ impl PpuState {
         self.stat_irq = mode_int | lyc_int;
     }
 
+    /// Handle an access by the CPU
     #[inline]
     pub fn perform_io(&mut self, input: CpuOutputPins, data: &mut u8, interrupt_request: &mut u8) {
+        self.perform_io_from(BusMaster::Cpu, input, data, interrupt_request)
+    }
+
+    /// Handle an access by `master`, and latch the interrupt lines
+    #[inline]
+    pub fn perform_io_from(
+        &mut self,
+        master: BusMaster,
+        input: CpuOutputPins,
+        data: &mut u8,
+        interrupt_request: &mut u8,
+    ) {
+        let stat_pulse = self.access(master, input, data);
+        self.latch_interrupts(interrupt_request, stat_pulse);
+    }
+
+    /// Handle an access by `master` without latching the interrupt lines, and return whether the
+    /// STAT line went high for a moment because of the STAT write bug
+    #[inline]
+    pub(crate) fn access(
+        &mut self,
+        master: BusMaster,
+        input: CpuOutputPins,
+        data: &mut u8,
+    ) -> bool {
+        // Set if the STAT line goes high for a moment because of the STAT write bug
+        let mut stat_pulse = false;
+        if let CpuOutputPins::Write {
+            addr: 0x8000..=0x9FFF | 0xFE00..=0xFE9F | 0xFF40..=0xFF4B | 0xFF6C,
+            ..
+        } = input
+        {
+            self.line_written();
+        }
         match input {
             CpuOutputPins::Write { addr, data: v } => match addr {
+                // VRAM is busy while the PPU is drawing, so the CPU's writes are lost
+                0x8000..=0x9FFF if master == BusMaster::Cpu && self.stat.bits() & 0x03 == 3 => (),
                 0x8000..=0x97FF => self.tile_data[addr as usize - 0x8000] = v,
                 0x9800..=0x9BFF => self.bg_map_1[addr as usize - 0x9800] = v,
                 0x9C00..=0x9FFF => self.bg_map_2[addr as usize - 0x9C00] = v,
@@ -229,28 +965,65 @@ impl PpuState {
                 0xFE00..=0xFE9F => self.oam[addr as usize - 0xFE00] = v,
 
                 0xFF40 => self.lcdc = LCDC::from_bits_truncate(v),
+                // The mode and LYC=LY bits are read-only
                 0xFF41 => {
-                    self.stat = STAT::from_bits_truncate(v);
+                    if self.stat_write_bug {
+                        let enabled = STAT::HBLANK_INTERRUPT_ENABLE
+                            | STAT::VBLANK_INTERRUPT_ENABLE
+                            | STAT::LYC_INTERRUPT_ENABLE;
+                        self.stat.insert(enabled);
+                        self.update_stat_interrupt();
+                        stat_pulse = self.stat_irq;
+                    }
+                    self.stat = STAT::from_bits_truncate((v & 0x78) | (self.stat.bits() & 0x07));
                     self.update_stat_interrupt();
                 }
                 0xFF42 => self.scy = v,
                 0xFF43 => self.scx = v,
-                0xFF44 => self.ly = v,
+                // LY is read-only
+                0xFF44 => (),
                 0xFF45 => self.lyc = v,
                 // Begin an OAM DMA transfer
                 0xFF46 => {
-                    self.dma_transfer = DmaState::ActiveFirstRead {
-                        addr: v as u16 * 0x100,
-                    }
+                    let source = v as u16 * 0x100;
+                    self.dma = v;
+                    // Any transfer already running carries on through the setup cycle
+                    self.dma_transfer.starting = Some((source, 1));
+                    log::debug!(target: logging::DMA, "OAM DMA started from ${:04X}", source);
+                    self.events
+                        .emit(EventMask::OAM_DMA_START, || Event::OamDmaStart { source });
+                }
+                0xFF47 => {
+                    self.bgp = v;
+                    self.update_palette_shades();
+                }
+                0xFF48 => {
+                    self.obp0 = v;
+                    self.update_palette_shades();
+                }
+                0xFF49 => {
+                    self.obp1 = v;
+                    self.update_palette_shades();
+                }
+                0xFF4A => {
+                    self.wy = v;
+                    self.check_wy();
                 }
-                0xFF47 => self.bgp = v,
-                0xFF48 => self.obp0 = v,
-                0xFF49 => self.obp1 = v,
-                0xFF4A => self.wy = v,
                 0xFF4B => self.wx = v,
+                0xFF6C if self.has_opri && !self.opri_locked => {
+                    self.priority_mode = PriorityMode::from_opri(v)
+                }
                 _ => (),
             },
             CpuOutputPins::Read { addr } => match addr {
+                // VRAM is busy while the PPU is drawing, but OAM DMA reads it over the video bus
+                // regardless
+                0x8000..=0x9FFF if master == BusMaster::Cpu && self.stat.bits() & 0x03 == 3 => {
+                    *data = match self.accuracy {
+                        AccuracyLevel::Fast => 0xFF,
+                        AccuracyLevel::Accurate => self.last_fetcher_read,
+                    }
+                }
                 0x8000..=0x97FF => *data = self.tile_data[addr as usize - 0x8000],
                 0x9800..=0x9BFF => *data = self.bg_map_1[addr as usize - 0x9800],
                 0x9C00..=0x9FFF => *data = self.bg_map_2[addr as usize - 0x9C00],
@@ -258,205 +1031,423 @@ impl PpuState {
                 0xFE00..=0xFE9F => *data = self.oam[addr as usize - 0xFE00],
 
                 0xFF40 => *data = self.lcdc.bits(),
-                0xFF41 => *data = self.stat.bits(),
+                // Bit 7 is unused
+                0xFF41 => *data = self.stat.bits() | 0x80,
                 0xFF42 => *data = self.scy,
                 0xFF43 => *data = self.scx,
                 0xFF44 => *data = self.ly,
                 0xFF45 => *data = self.lyc,
-                0xFF46 => {
-                    *data = match self.dma_transfer {
-                        DmaState::Active { addr } | DmaState::ActiveFirstRead { addr } => {
-                            (addr / 0x100) as u8
-                        }
-                        DmaState::Inactive => 0,
-                    }
-                }
+                0xFF46 => *data = self.dma,
                 0xFF47 => *data = self.bgp,
                 0xFF48 => *data = self.obp0,
                 0xFF49 => *data = self.obp1,
                 0xFF4A => *data = self.wy,
                 0xFF4B => *data = self.wx,
+                0xFF6C if self.has_opri => *data = self.priority_mode.opri(),
 
                 _ => (),
             },
         };
+        stat_pulse
+    }
 
-        let mut irq = *interrupt_request;
-        if self.vblank_irq {
-            irq |= 1 << 0;
-        } else {
-            irq &= !(1 << 0);
+    /// Set the bits of `interrupt_request` for the interrupt lines that have risen since they were
+    /// last latched
+    #[inline]
+    pub(crate) fn latch_interrupts(&mut self, interrupt_request: &mut u8, stat_pulse: bool) {
+        // IF latches the rising edge of each line, so a request stays set until the CPU services
+        // it or IF is written, even if the line has gone low again by then. A pulse from the STAT
+        // write bug is only seen if the line was low before the write.
+        let stat_pulse = stat_pulse | std::mem::take(&mut self.stat_pulse);
+        let lines = self.vblank_irq as u8 | (self.stat_irq as u8) << 1;
+        if (lines ^ self.irq_lines) & 0x02 != 0 {
+            log::trace!(
+                target: logging::PPU,
+                "STAT interrupt line {} at LY={}",
+                if self.stat_irq { "rose" } else { "fell" },
+                self.ly
+            );
         }
+        *interrupt_request |= (lines | (stat_pulse as u8) << 1) & !self.irq_lines;
+        self.irq_lines = lines;
+    }
 
-        if self.stat_irq {
-            irq |= 1 << 1;
-        } else {
-            irq &= !(1 << 1);
+    /// Called at the start of every M-cycle. Starts a transfer whose setup cycle has passed, and
+    /// returns the address the transfer in progress reads this M-cycle, if there is one. Sources
+    /// from $E000 up read work RAM $2000 lower down, like the echo of work RAM.
+    pub fn begin_dma_cycle(&mut self) -> Option<u16> {
+        match self.dma_transfer.starting {
+            Some((source, 0)) => {
+                self.dma_transfer.transfer = Some(source);
+                self.dma_transfer.starting = None;
+            }
+            Some((source, delay)) => self.dma_transfer.starting = Some((source, delay - 1)),
+            None => (),
         }
-
-        *interrupt_request = irq;
+        self.dma_transfer.transfer.map(dma_source)
     }
 
-    /// During a DMA transfer, read in the next byte from memory.
-    ///
-    /// # Panics
-    /// Panics if there is not an active DMA transfer
-    pub fn clock_dma(&mut self, input: CpuInputPins) -> CpuOutputPins {
-        match self.dma_transfer {
-            DmaState::Inactive => unreachable!(),
-            DmaState::ActiveFirstRead { addr } => {
-                self.dma_transfer = DmaState::Active { addr };
-                CpuOutputPins::Read { addr }
-            }
-            DmaState::Active { addr } => {
-                let i = (addr % 0x100) as usize;
-                self.oam[i] = input.data;
-                if i == 0x9F {
-                    self.dma_transfer = DmaState::Inactive;
-                    CpuOutputPins::Read { addr: 0 }
-                } else {
-                    self.dma_transfer = DmaState::Active { addr: addr + 1 };
-                    CpuOutputPins::Read { addr: addr + 1 }
-                }
+    /// Copy `data`, the byte read from the address [`PpuState::begin_dma_cycle`] returned, into
+    /// OAM. Does nothing if no transfer is in progress.
+    pub fn finish_dma_cycle(&mut self, data: u8) {
+        if let Some(addr) = self.dma_transfer.transfer {
+            let i = (addr % 0x100) as usize;
+            self.oam[i] = data;
+            self.line_written();
+            if i == 0x9F {
+                self.dma_transfer.transfer = None;
+                log::debug!(target: logging::DMA, "OAM DMA finished");
+            } else {
+                self.dma_transfer.transfer = Some(addr + 1);
             }
         }
     }
 }
 
-#[derive(Debug)]
-pub enum DmaState {
-    Inactive,
-    ActiveFirstRead { addr: u16 },
-    Active { addr: u16 },
+/// The address OAM DMA reads to copy from `addr`
+fn dma_source(addr: u16) -> u16 {
+    match addr {
+        0xE000..=0xFFFF => addr - 0x2000,
+        _ => addr,
+    }
 }
 
-pub type PpuGenerator =
-    Pin<Box<dyn Coroutine<Box<PpuState>, Yield = Box<PpuState>, Return = !> + Send + Sync>>;
+/// The parts of the PPU's state that come from running through the frame so far, returned by
+/// [`PpuState::frame_position`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct FramePosition {
+    /// Dots since line 0 began, as if the frame's first line was full length
+    pub dots: u32,
+    pub wy_latch: bool,
+    pub window_line: u8,
+    /// The frame started with the LCD being turned on
+    pub lcd_was_disabled: bool,
+}
 
-pub fn gen() -> PpuGenerator {
-    Box::pin(|mut state: Box<PpuState>| {
-        macro_rules! ppu_yield {
-            () => {
-                state = yield state
-            };
+/// OAM DMA copies a byte from `source` * $100 + i to OAM[i] on each of 160 M-cycles. Writing to
+/// DMA starts a transfer after a setup cycle, during which OAM can still be accessed, unless a
+/// transfer that was already running carries on through it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DmaState {
+    /// The address of the next byte the running transfer copies
+    pub transfer: Option<u16>,
+    /// The source of a transfer that has been started, and the M-cycles left until it takes over
+    pub starting: Option<(u16, u8)>,
+}
+
+/// An estimate of how many dots the pixel FIFOs take to draw a line, for
+/// [`PpuBackend::Scanline`]. `sprites` are those selected for the line, and `wx` is WX if the
+/// window is drawn on the line.
+fn mode_3_dots(scx: u8, wx: Option<u8>, sprites: &[OamEntry]) -> u16 {
+    // Discarding the first SCX % 8 pixels takes a dot each
+    let mut dots = 174 + (scx % 8) as u16;
+    // Switching to the window partway through the line restarts the BG fetcher
+    if let Some(wx) = wx.filter(|&wx| wx + scx % 8 > 7) {
+        dots += 15 - (wx.wrapping_add(scx) % 2) as u16;
+    }
+    // Each sprite pauses the BG fetcher for 6 dots, and the first sprite over a tile also waits
+    // for the fetcher to get far enough through that tile
+    let mut waited = [false; 22];
+    for sprite in sprites.iter().filter(|sprite| sprite.xpos < 168) {
+        dots += 6;
+        let Some(pixel) = (sprite.xpos as usize + (scx % 8) as usize).checked_sub(8) else {
+            continue;
+        };
+        // The fetcher moves in steps of two dots
+        if !std::mem::replace(&mut waited[pixel / 8], true) {
+            dots += ((pixel % 8) & !1) as u16;
         }
+    }
+    dots
+}
+
+/// Only ever resumed through `&mut`, so it never needs to be `Sync`
+pub(crate) type PpuGenerator =
+    Pin<Box<dyn_maybe_send!(Coroutine<Box<PpuState>, Yield = Box<PpuState>, Return = !>)>>;
+
+pub(crate) fn gen() -> PpuGenerator {
+    Box::pin(|mut state: Box<PpuState>| {
+        'frame: loop {
+            // Turning the LCD off stops the PPU wherever it is, and it starts from the top of a
+            // new frame once turned back on
+            macro_rules! ppu_yield {
+                () => {
+                    state = yield state;
+                    if !state.lcdc.contains(LCDC::LCD_ENABLE) {
+                        continue 'frame;
+                    }
+                };
+            }
+            // Let `dots` dots go by without running the coroutine on each of them
+            macro_rules! ppu_wait {
+                ($dots:expr) => {
+                    state.idle_dots = $dots as u16 - 1;
+                    ppu_yield!();
+                };
+            }
+
+            state.first_line = false;
+            if !state.lcdc.contains(LCDC::LCD_ENABLE) {
+                state.disable_lcd();
+                // Nothing is drawn, but frames keep being counted as if the PPU was running
+                while !state.lcdc.contains(LCDC::LCD_ENABLE) {
+                    state = yield state;
+                    if state.frame_dots == consts::FRAME_T_CYCLES as u32 {
+                        state.frame_dots = 0;
+                        state.frame_count += 1;
+                        state.complete_frame_cpu_usage();
+                    }
+                }
+                log::debug!(target: logging::PPU, "LCD on");
+                state.events.emit(EventMask::DISPLAY, || {
+                    Event::Display(DisplayEvent::LcdEnabled)
+                });
+                state.first_line = true;
+                state.lcd_was_disabled = true;
+            }
+
+            state.begin_frame();
 
-        loop {
-            // The window is rendered if ly==wy at any point during the frame
-            let mut wy_passed = false;
-            // Number of completed scanlines containing any window pixels
-            let mut window_lines = 0;
             for scanline in 0..144 {
                 state.set_ly(scanline);
-                if state.ly == state.wy {
-                    wy_passed = true;
-                }
+                // A match later in the line only shows the window from the next line
+                let wy_passed = state.wy_latch;
 
-                // OAM Search
-                state.set_mode(2);
+                // OAM Search. On the first line after the LCD is turned on, STAT stays in mode 0
+                // and the mode 2 interrupt isn't raised, although the scan still happens.
+                if !state.first_line {
+                    state.set_mode(2, 0);
+                }
+                #[cfg(feature = "differential")]
+                if state.drawing && state.backend == PpuBackend::DotAccurate {
+                    state.begin_differential_line();
+                }
                 let mut sprite_buffer = [OamEntry {
                     xpos: 255,
                     ..Default::default()
                 }; 10];
+                // The OAM index of each sprite in `sprite_buffer`
+                let mut sprite_indices = [0; 10];
                 let mut sprite_buffer_len = 0;
-                for entry in 0..40 {
-                    if sprite_buffer_len < 10 {
-                        let entry = state.oam(entry);
-                        if entry.xpos > 0
-                            && scanline + 16 >= entry.ypos
+                let mut selected = 0;
+                // The scanline backend draws the line from the state at the start of mode 3, so
+                // it reads all of OAM there instead of an entry every 2 dots
+                let scan_at_once = state.backend == PpuBackend::Scanline;
+                if scan_at_once {
+                    ppu_wait!(80);
+                }
+                for entry_index in 0..40 {
+                    if selected < 10 {
+                        state.consumed |= UsedRegisters::LCDC;
+                        let entry = state.oam_entry(entry_index);
+                        if scanline + 16 >= entry.ypos
                             && scanline + 16 < entry.ypos + state.sprite_height()
                         {
-                            sprite_buffer[sprite_buffer_len] = entry;
-                            sprite_buffer_len += 1;
+                            // Sprites at X=0 are never drawn, but still count towards the limit
+                            selected += 1;
+                            if entry.xpos > 0 {
+                                sprite_buffer[sprite_buffer_len] = entry;
+                                sprite_indices[sprite_buffer_len] = entry_index as u8;
+                                sprite_buffer_len += 1;
+                                let height = state.sprite_height();
+                                state.back_record.select_sprite(entry_index, entry, height);
+                            }
                         }
                     }
-                    ppu_yield!();
-                    ppu_yield!();
+                    if !scan_at_once {
+                        ppu_wait!(2);
+                    }
+                }
+                // The rank of each selected sprite, with 0 drawn on top
+                let mut sprite_ranks = [0; 10];
+                let candidates = &sprite_buffer[..sprite_buffer_len];
+                for (rank, &i) in resolve_sprite_priority(candidates, state.priority_mode)
+                    .iter()
+                    .enumerate()
+                {
+                    sprite_ranks[i as usize] = rank as u8;
                 }
 
                 // Drawing
-                state.set_mode(3);
+                state.set_mode(3, 80);
+                // Mode 3 is at least this long, with nothing to pause the fetcher
+                state.mode_3_end = 80 + 174 + (state.scx % 8) as u16;
+                state.update_palette_shades();
                 // 80 cycles have passed already
                 let mut cycles = 80;
-                let mut bg_fifo = pixel_fifo::BgPixelFifo::new();
-                bg_fifo.set_tile_map_offset(pixel_fifo::TileCounter::Bg { x_counter: 0 });
-                let mut sprite_fifo = pixel_fifo::SpritePixelFifo::new();
-                // Discard the first SCX % 8 pixels
-                let mut x = -(state.scx as isize % 8);
                 let mut inside_window = false;
-                while x < 160 {
-                    if cycles % 2 == 0 {
-                        bg_fifo.clock(&state);
-                    }
-
-                    if let Some(bg_pixel) = bg_fifo.pop_pixel() {
-                        // Check if any sprites are about to be drawn
-                        if let Some(sprite) = sprite_buffer
-                            .iter_mut()
-                            .find(|sprite| sprite.xpos as isize <= x + 8)
-                        {
-                            // Pause and reset the BG fetcher, and load the sprite into the sprite fetcher
-                            bg_fifo.reset_fetcher();
-                            sprite_fifo.load_sprite(*sprite);
-                            let xpos = sprite.xpos;
-                            // Move the sprite offscreen to prevent it from being redrawn
-                            sprite.xpos = 255;
-                            for _ in 0..6 {
-                                sprite_fifo.clock(&mut state);
-                                ppu_yield!()
+                let mut line_sprites = 0;
+                let mut line = [0; 160];
+                // Checked once per line, so that pixels cost nothing extra without attribution
+                let attributing = state.drawing && state.pixel_attribution;
+                let mut line_sources = [PixelSource::default(); 160];
+                match state.backend {
+                    PpuBackend::DotAccurate => {
+                        let mut bg_fifo = pixel_fifo::BgPixelFifo::new();
+                        bg_fifo.set_tile_map_offset(pixel_fifo::TileCounter::Bg { x_counter: 0 });
+                        let mut sprite_fifo = pixel_fifo::SpritePixelFifo::new();
+                        // Discard the first SCX % 8 pixels
+                        let mut x = -(state.scx as isize % 8);
+                        while x < 160 {
+                            state.consumed |= UsedRegisters::LCDC;
+                            if state.lcdc.contains(LCDC::WINDOW_ENABLE) && wy_passed {
+                                state.consumed |= UsedRegisters::WX;
+                            }
+                            // Check if the next pixel is inside the window
+                            if state.lcdc.contains(LCDC::WINDOW_ENABLE)
+                                && wy_passed
+                                && x >= state.wx as isize - 7
+                                && !inside_window
+                            {
+                                bg_fifo.clear();
+                                bg_fifo.set_tile_map_offset(pixel_fifo::TileCounter::Window {
+                                    x_counter: 0,
+                                    window_line: state.window_line as u16,
+                                });
+                                inside_window = true;
+                                state.back_record.window_line(x.max(0) as u8, scanline);
+                                state.back_info.window_lines += 1;
                             }
 
-                            // If necessary, discard pixels that are off-screen to the left
-                            let discard = 8 - xpos as isize;
-                            for _ in 0..discard {
-                                sprite_fifo.pop_pixel();
+                            if cycles % 2 == 0 {
+                                bg_fifo.clock(&mut state);
                             }
-                        }
 
-                        let sprite_pixel = sprite_fifo.pop_pixel();
-                        if x >= 0 {
-                            state.put_pixel(bg_pixel, sprite_pixel, x as usize, scanline as usize);
+                            if let Some(bg_pixel) = bg_fifo.pop_pixel() {
+                                // Check if any sprites are about to be drawn. Several can start at the
+                                // same pixel, and are fetched in priority order.
+                                while let Some(((sprite, &rank), &oam_index)) = sprite_buffer
+                                    .iter_mut()
+                                    .zip(&sprite_ranks)
+                                    .zip(&sprite_indices)
+                                    .filter(|((sprite, _), _)| sprite.xpos as isize <= x + 8)
+                                    .min_by_key(|((_, &rank), _)| rank)
+                                {
+                                    // Pause and reset the BG fetcher, and load the sprite into the sprite fetcher
+                                    bg_fifo.reset_fetcher();
+                                    // Sprites that start left of the first pixel are fetched there, so
+                                    // the columns that have already gone by are skipped
+                                    let skip = (x - (sprite.xpos as isize - 8)) as u8;
+                                    sprite_fifo.load_sprite(*sprite, oam_index, rank, skip);
+                                    // Move the sprite offscreen to prevent it from being redrawn
+                                    sprite.xpos = 255;
+                                    line_sprites += 1;
+                                    for _ in 0..6 {
+                                        sprite_fifo.clock(&mut state);
+                                        if state.fifo_snapshot.is_some() {
+                                            state.record_fifos(&bg_fifo, &sprite_fifo, x);
+                                        }
+                                        ppu_yield!();
+                                        cycles += 1;
+                                    }
+                                }
+
+                                let sprite_pixel = sprite_fifo.pop_pixel();
+                                // The FIFO keeps running on skipped frames since it determines the length of mode 3
+                                if x >= 0 {
+                                    line[x as usize] = state.pixel_shade(bg_pixel, sprite_pixel);
+                                    if state.ordering_check {
+                                        state.use_pixel_registers(bg_pixel, sprite_pixel);
+                                    }
+                                    if attributing {
+                                        line_sources[x as usize] =
+                                            state.pixel_source(bg_pixel, sprite_pixel);
+                                    }
+                                }
+                                x += 1;
+                            }
+                            if state.fifo_snapshot.is_some() {
+                                state.record_fifos(&bg_fifo, &sprite_fifo, x);
+                            }
+                            ppu_yield!();
+                            cycles += 1;
                         }
-                        // Check if we're about to enter the window
-                        if state.lcdc.contains(LCDC::WINDOW_ENABLE)
-                            && wy_passed
-                            && x >= state.wx as isize - 7
-                            && !inside_window
-                        {
-                            bg_fifo.clear();
-                            bg_fifo.set_tile_map_offset(pixel_fifo::TileCounter::Window {
-                                x_counter: 0,
-                                window_line: window_lines,
-                            });
+                    }
+                    PpuBackend::Scanline => {
+                        let window =
+                            state.lcdc.contains(LCDC::WINDOW_ENABLE) && wy_passed && state.wx < 167;
+                        let sprites = &sprite_buffer[..sprite_buffer_len];
+                        let mode_3_dots =
+                            mode_3_dots(state.scx, window.then_some(state.wx), sprites);
+                        state.mode_3_end = 80 + mode_3_dots;
+                        // The whole line is drawn from the registers as they are now
+                        state.consumed |= UsedRegisters::LCDC
+                            | UsedRegisters::SCY
+                            | UsedRegisters::SCX
+                            | UsedRegisters::BGP
+                            | UsedRegisters::OBP0
+                            | UsedRegisters::OBP1
+                            | UsedRegisters::WX;
+                        if window {
                             inside_window = true;
+                            state
+                                .back_record
+                                .window_line(state.wx.saturating_sub(7), scanline);
+                            state.back_info.window_lines += 1;
+                        }
+                        // Sprites past the right edge are never fetched
+                        line_sprites =
+                            sprites.iter().filter(|sprite| sprite.xpos < 168).count() as u8;
+                        if state.drawing {
+                            let sources = attributing.then_some(&mut line_sources);
+                            line = LineView::new(&state, wy_passed).render(sources);
                         }
-                        x += 1;
+                        ppu_wait!(mode_3_dots);
+                        cycles += mode_3_dots;
                     }
-                    ppu_yield!();
-                    cycles += 1;
                 }
-                if wy_passed {
-                    window_lines += 1;
+                if inside_window {
+                    state.window_line += 1;
                 }
+                state.back_info.add_line_sprites(line_sprites);
 
                 // HBlank
-                state.set_mode(0);
-                while cycles < 456 {
-                    ppu_yield!();
-                    cycles += 1;
+                state.mode_3_end = cycles;
+                state.set_mode(0, cycles);
+                if state.drawing {
+                    let back_frame =
+                        FrameRc::get_mut(&mut state.back_frame).expect("back frame is shared");
+                    *back_frame.row_mut(scanline as usize) = line;
+                    if attributing {
+                        *back_frame.attribution_mut().row_mut(scanline as usize) = line_sources;
+                    }
+                    state.last_completed_line = Some(scanline);
+                    #[cfg(feature = "differential")]
+                    state.check_differential_line(&line);
+                }
+                let line_dots = if state.first_line {
+                    consts::FIRST_LINE_DOTS as u16
+                } else {
+                    456
+                };
+                if cycles < line_dots {
+                    ppu_wait!(line_dots - cycles);
                 }
+                if state.first_line {
+                    state.frame_dots += (consts::LINE_T_CYCLES - consts::FIRST_LINE_DOTS) as u32;
+                }
+                state.first_line = false;
             }
 
             // VBlank
-            state.set_mode(1);
-            state.swap_frames();
+            state.set_ly(144);
+            state.set_mode(1, 0);
+            state.frame_count += 1;
+            state.complete_frame_cpu_usage();
+            if state.drawing {
+                state.swap_frames();
+            }
             state.vblank_irq = true;
-            for scanline in 144..154 {
+            for scanline in 144..153 {
                 state.set_ly(scanline);
-                for _dot in 0..456 {
-                    ppu_yield!()
-                }
+                ppu_wait!(consts::LINE_T_CYCLES);
             }
+            // LY only reads 153 briefly before it wraps to 0, and line 0 proper begins once the
+            // line is over
+            state.set_ly(153);
+            ppu_wait!(consts::LINE_153_DOTS);
+            state.set_ly(0);
+            ppu_wait!(consts::LINE_T_CYCLES - consts::LINE_153_DOTS);
             state.vblank_irq = false;
         }
     })
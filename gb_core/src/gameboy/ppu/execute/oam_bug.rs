@@ -0,0 +1,91 @@
+//! The DMG OAM corruption bug.
+//!
+//! If the CPU puts an address in $FE00-$FEFF on the bus while the PPU is scanning OAM, the row
+//! (8 bytes) the PPU is reading gets mixed with the row before it. Each row is treated as four
+//! 16-bit words. See <https://gbdev.io/pandocs/OAM_Corruption_Bug.html>.
+
+use gb_cpu::CpuOutputPins;
+
+use super::PpuState;
+use crate::gameboy::ppu::registers::LCDC;
+
+const ROWS: usize = 20;
+
+fn in_oam(addr: u16) -> bool {
+    (0xFE00..=0xFEFF).contains(&addr)
+}
+
+impl PpuState {
+    /// Corrupt OAM if the CPU's bus activity this cycle triggers the OAM bug. `inc_dec` is the
+    /// value being worked on by the CPU's 16-bit increment/decrement unit.
+    pub fn oam_bug(&mut self, pins: CpuOutputPins, inc_dec: Option<u16>) {
+        let row = match self.oam_scan_row() {
+            Some(row) if row < ROWS && self.lcdc.contains(LCDC::LCD_ENABLE) => row,
+            _ => return,
+        };
+        let inc_dec = inc_dec.map_or(false, in_oam);
+
+        match pins {
+            CpuOutputPins::Read { addr } if in_oam(addr) && inc_dec => {
+                self.read_inc_dec_corruption(row)
+            }
+            CpuOutputPins::Read { addr } if in_oam(addr) => self.read_corruption(row),
+            // A write while incrementing acts like a single write
+            CpuOutputPins::Write { addr, .. } if in_oam(addr) => self.write_corruption(row),
+            _ if inc_dec => self.write_corruption(row),
+            _ => (),
+        }
+    }
+
+    fn oam_word(&self, row: usize, word: usize) -> u16 {
+        let i = row * 8 + word * 2;
+        u16::from_le_bytes([self.oam[i], self.oam[i + 1]])
+    }
+
+    fn set_oam_word(&mut self, row: usize, word: usize, v: u16) {
+        let i = row * 8 + word * 2;
+        self.oam[i..i + 2].copy_from_slice(&v.to_le_bytes());
+        self.line_written();
+    }
+
+    /// Replace the first word of `row` with `f(a, b, c)`, where `a` is that word, `b` is the first
+    /// word of the preceding row and `c` is its third word. The other three words are copied from
+    /// the preceding row. The first row is never corrupted.
+    fn corrupt_row(&mut self, row: usize, f: impl FnOnce(u16, u16, u16) -> u16) {
+        if row == 0 {
+            return;
+        }
+        let a = self.oam_word(row, 0);
+        let b = self.oam_word(row - 1, 0);
+        let c = self.oam_word(row - 1, 2);
+        self.set_oam_word(row, 0, f(a, b, c));
+        self.oam
+            .copy_within((row - 1) * 8 + 2..row * 8, row * 8 + 2);
+    }
+
+    fn write_corruption(&mut self, row: usize) {
+        self.corrupt_row(row, |a, b, c| ((a ^ c) & (b ^ c)) ^ c)
+    }
+
+    fn read_corruption(&mut self, row: usize) {
+        self.corrupt_row(row, |a, b, c| b | (a & c))
+    }
+
+    /// A read and an increment in the same cycle corrupt the two rows before `row` as well, unless
+    /// `row` is one of the first four or the last one. A normal read corruption follows.
+    fn read_inc_dec_corruption(&mut self, row: usize) {
+        if (4..ROWS - 1).contains(&row) {
+            let a = self.oam_word(row - 2, 0);
+            let b = self.oam_word(row - 1, 0);
+            let c = self.oam_word(row, 0);
+            let d = self.oam_word(row - 1, 2);
+            self.set_oam_word(row - 1, 0, (b & (a | c | d)) | (a & c & d));
+
+            let preceding = (row - 1) * 8;
+            self.oam.copy_within(preceding..preceding + 8, row * 8);
+            self.oam
+                .copy_within(preceding..preceding + 8, (row - 2) * 8);
+        }
+        self.read_corruption(row);
+    }
+}
@@ -1,11 +1,17 @@
-use super::super::registers::{OamEntry, OamEntryFlags, LCDC};
+use super::super::{
+    debug::{FetcherStep, TileMapPosition},
+    frame::attribution::PixelLayer,
+    registers::{OamEntry, OamEntryFlags, LCDC},
+};
 
-use super::PpuState;
+use super::{PpuState, UsedRegisters};
 
 pub struct BgPixelFifo {
     pixels: ShiftRegister<Pixel, 16>,
     tile_map_offset: TileCounter,
     state: FifoState,
+    /// The step performed by the last clock
+    step: FetcherStep,
 }
 
 impl BgPixelFifo {
@@ -14,6 +20,7 @@ impl BgPixelFifo {
             pixels: ShiftRegister::new(),
             tile_map_offset: TileCounter::Bg { x_counter: 0 },
             state: FifoState::FetchTile,
+            step: FetcherStep::ReadTileNumber,
         }
     }
 
@@ -31,15 +38,27 @@ impl BgPixelFifo {
     }
 
     /// Each FIFO cycle takes 2 PPU cycles
-    pub fn clock(&mut self, state: &PpuState) {
+    pub fn clock(&mut self, state: &mut PpuState) {
+        self.step = self.state.step();
         match self.state {
             FifoState::FetchTile => {
+                state.consumed |= match self.tile_map_offset {
+                    TileCounter::Bg { .. } => {
+                        UsedRegisters::LCDC | UsedRegisters::SCX | UsedRegisters::SCY
+                    }
+                    TileCounter::Window { .. } => UsedRegisters::LCDC,
+                };
+                let tile_no = self.tile_map_offset.get_tile_number(state);
+                state.last_fetcher_read = tile_no;
                 self.state = FifoState::FetchTileDataLow {
+                    tile: tile_no,
+                    layer: self.tile_map_offset.layer(state, tile_no),
                     tile_data_index: {
-                        let tile_no = self.tile_map_offset.get_tile_number(state);
                         let tile_addr = state.bg_tile_data_address(tile_no);
                         let tile_line_offset = match self.tile_map_offset {
-                            TileCounter::Bg { .. } => 2 * ((state.ly + state.scy) % 8) as usize,
+                            TileCounter::Bg { .. } => {
+                                2 * (state.ly.wrapping_add(state.scy) % 8) as usize
+                            }
                             TileCounter::Window { window_line, .. } => {
                                 2 * (window_line % 8) as usize
                             }
@@ -49,24 +68,38 @@ impl BgPixelFifo {
                 }
             }
 
-            FifoState::FetchTileDataLow { tile_data_index } => {
+            FifoState::FetchTileDataLow {
+                tile,
+                layer,
+                tile_data_index,
+            } => {
+                state.last_fetcher_read = state.tile_data[tile_data_index];
                 self.state = FifoState::FetchTileDataHigh {
+                    tile,
+                    layer,
                     tile_data_index,
-                    tile_data_low: state.tile_data[tile_data_index],
+                    tile_data_low: state.last_fetcher_read,
                 }
             }
 
             FifoState::FetchTileDataHigh {
+                tile,
+                layer,
                 tile_data_index,
                 tile_data_low,
             } => {
+                state.last_fetcher_read = state.tile_data[tile_data_index + 1];
                 self.state = FifoState::ReadyToPush {
+                    tile,
+                    layer,
                     tile_data_low,
-                    tile_data_high: state.tile_data[tile_data_index + 1],
+                    tile_data_high: state.last_fetcher_read,
                 }
             }
 
             FifoState::ReadyToPush {
+                tile,
+                layer,
                 tile_data_low,
                 tile_data_high,
             } => {
@@ -77,6 +110,8 @@ impl BgPixelFifo {
                         self.pixels
                             .push(Pixel {
                                 color: (pix_high << 1) | pix_low,
+                                tile,
+                                layer,
                                 ..Default::default()
                             })
                             .unwrap();
@@ -95,6 +130,21 @@ impl BgPixelFifo {
             None
         }
     }
+
+    /// The queued pixels, from the next one to be popped to the last one pushed
+    pub fn pixels(&self) -> impl Iterator<Item = Pixel> + '_ {
+        self.pixels.iter()
+    }
+
+    /// The step the fetcher performed on its last clock
+    pub fn step(&self) -> FetcherStep {
+        self.step
+    }
+
+    /// The tile map entry the fetcher reads next
+    pub fn tile_map_position(&self, state: &PpuState) -> TileMapPosition {
+        self.tile_map_offset.map_position(state)
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -104,17 +154,53 @@ pub enum TileCounter {
 }
 
 impl TileCounter {
+    /// Where the pixels of `tile`, at the fetcher's position, come from
+    fn layer(&self, state: &PpuState, tile: u8) -> PixelLayer {
+        match *self {
+            TileCounter::Bg { .. } => {
+                let position = self.map_position(state);
+                PixelLayer::Background {
+                    tile,
+                    map_x: position.x,
+                    map_y: position.y,
+                }
+            }
+            TileCounter::Window {
+                x_counter,
+                window_line,
+            } => PixelLayer::Window {
+                tile,
+                map_x: x_counter as u8,
+                window_line: window_line as u8,
+            },
+        }
+    }
+
     fn get_tile_number(&self, state: &PpuState) -> u8 {
-        match self {
-            TileCounter::Bg { x_counter } => state.get_bg_tile_number(
-                (state.ly.wrapping_add(state.scy) as u16 / 8 * 32
-                    + ((state.scx as u16 / 8 + x_counter) & 0x1F))
-                    & 0x3FF,
-            ),
+        let position = self.map_position(state);
+        let offset = position.y as u16 * 32 + position.x as u16;
+        if position.window {
+            state.get_window_tile_number(offset)
+        } else {
+            state.get_bg_tile_number(offset)
+        }
+    }
+
+    fn map_position(&self, state: &PpuState) -> TileMapPosition {
+        match *self {
+            TileCounter::Bg { x_counter } => TileMapPosition {
+                x: ((state.scx as u16 / 8 + x_counter) & 0x1F) as u8,
+                y: state.ly.wrapping_add(state.scy) / 8,
+                window: false,
+            },
             TileCounter::Window {
                 x_counter,
                 window_line,
-            } => state.get_window_tile_number(window_line / 8 * 32 + x_counter),
+            } => TileMapPosition {
+                x: x_counter as u8,
+                y: (window_line / 8) as u8,
+                window: true,
+            },
         }
     }
 
@@ -135,7 +221,15 @@ impl TileCounter {
 pub struct SpritePixelFifo {
     pixels: ShiftRegister<Pixel, 8>,
     sprite: Option<super::OamEntry>,
+    /// The rank of `sprite`, from [`resolve_sprite_priority`](super::super::priority::resolve_sprite_priority)
+    rank: u8,
+    /// The index of `sprite` in OAM
+    oam_index: u8,
+    /// The number of columns of `sprite` that are off screen to the left
+    skip: usize,
     state: FifoState,
+    /// The step performed by the last clock, while a sprite is being fetched
+    step: Option<FetcherStep>,
 }
 
 impl SpritePixelFifo {
@@ -143,70 +237,105 @@ impl SpritePixelFifo {
         SpritePixelFifo {
             pixels: ShiftRegister::new(),
             sprite: None,
+            rank: 0,
+            oam_index: 0,
+            skip: 0,
             state: FifoState::FetchTile,
+            step: None,
         }
     }
 
-    pub fn load_sprite(&mut self, sprite: OamEntry) {
+    /// Fetch `sprite`, entry `oam_index` of OAM, next, leaving out its first `skip` columns. Its
+    /// pixels are drawn over those of sprites with a higher `rank`.
+    pub fn load_sprite(&mut self, sprite: OamEntry, oam_index: u8, rank: u8, skip: u8) {
         self.sprite = Some(sprite);
+        self.oam_index = oam_index;
+        self.rank = rank;
+        self.skip = skip as usize;
     }
 
     pub fn clock(&mut self, state: &mut PpuState) {
+        self.step = self.sprite.map(|_| self.state.step());
         match self.state {
             FifoState::FetchTile => match self.sprite {
                 None => (),
                 Some(sprite) => {
+                    state.consumed |= UsedRegisters::LCDC;
+                    // 8x16 sprites ignore bit 0 of the tile number
+                    let tile = if state.lcdc.contains(LCDC::OBJ_SIZE) {
+                        sprite.tile & 0xFE
+                    } else {
+                        sprite.tile
+                    };
                     self.state = FifoState::FetchTileDataLow {
+                        tile,
+                        layer: PixelLayer::Sprite {
+                            tile,
+                            oam_index: self.oam_index,
+                        },
                         tile_data_index: {
-                            let sprite_line = state.ly - self.sprite.unwrap().ypos + 16;
+                            let sprite_line = state.ly + 16 - self.sprite.unwrap().ypos;
                             if sprite.flags.contains(OamEntryFlags::Y_FLIP) {
                                 if state.lcdc.contains(LCDC::OBJ_SIZE) {
                                     // For y-flipped 8x16 sprites, we want to draw the second tile's
                                     // data first
                                     if sprite_line < 8 {
-                                        state.sprite_tile_data_address(sprite.tile + 1)
+                                        state.sprite_tile_data_address(tile + 1)
                                             + 2 * (7 - sprite_line) as usize
                                     } else {
-                                        state.sprite_tile_data_address(sprite.tile)
-                                            + 2 * (7 - sprite_line + 8) as usize
+                                        state.sprite_tile_data_address(tile)
+                                            + 2 * (15 - sprite_line) as usize
                                     }
                                 } else {
-                                    state.sprite_tile_data_address(sprite.tile)
+                                    state.sprite_tile_data_address(tile)
                                         + 2 * (7 - sprite_line) as usize
                                 }
                             } else {
                                 // For non-y-flipped sprites, the line offset will naturally roll
                                 // over into the next tile.
-                                state.sprite_tile_data_address(sprite.tile)
-                                    + 2 * sprite_line as usize
+                                state.sprite_tile_data_address(tile) + 2 * sprite_line as usize
                             }
                         },
                     }
                 }
             },
 
-            FifoState::FetchTileDataLow { tile_data_index } => {
+            FifoState::FetchTileDataLow {
+                tile,
+                layer,
+                tile_data_index,
+            } => {
+                state.last_fetcher_read = state.tile_data[tile_data_index];
                 self.state = FifoState::FetchTileDataHigh {
+                    tile,
+                    layer,
                     tile_data_index,
-                    tile_data_low: state.tile_data[tile_data_index],
+                    tile_data_low: state.last_fetcher_read,
                 };
             }
 
             FifoState::FetchTileDataHigh {
+                tile,
+                layer,
                 tile_data_index,
                 tile_data_low,
             } => {
+                state.last_fetcher_read = state.tile_data[tile_data_index + 1];
                 self.state = FifoState::ReadyToPush {
+                    tile,
+                    layer,
                     tile_data_low,
-                    tile_data_high: state.tile_data[tile_data_index + 1],
+                    tile_data_high: state.last_fetcher_read,
                 };
             }
 
             FifoState::ReadyToPush {
+                tile,
+                layer,
                 tile_data_low,
                 tile_data_high,
             } => {
-                for i in 0..8 {
+                for i in self.skip..8 {
                     let (pix_low, pix_high) =
                         if self.sprite.unwrap().flags.contains(OamEntryFlags::X_FLIP) {
                             let pix_low = (tile_data_low >> i) & 1;
@@ -219,6 +348,7 @@ impl SpritePixelFifo {
                         };
                     let prepared_pixel = Pixel {
                         color: (pix_high << 1) | pix_low,
+                        tile,
                         palette: if self
                             .sprite
                             .unwrap()
@@ -234,15 +364,20 @@ impl SpritePixelFifo {
                             .unwrap()
                             .flags
                             .contains(super::OamEntryFlags::BG_PRIORITY),
-                        sprite_priority: false,
+                        sprite_rank: self.rank,
+                        layer,
                     };
 
-                    // Avoid drawing on top of already visible sprite pixels
-                    if let Some(pix) = self.pixels.get_mut(i) {
-                        if pix.color != 0b00 {
-                            continue;
-                        } else {
-                            // If the pixel is transparent, we can still overwrite it
+                    // The sprite fetched first keeps its visible pixels, and later sprites only
+                    // fill in the transparent ones. Sprites are fetched in priority order, so a
+                    // later sprite can only outrank one already in the FIFO with CGB ordering,
+                    // and sprites at the same X are fetched in OAM order, earliest on top.
+                    // Transparent pixels never replace anything, so a pixel keeps the flags of
+                    // the sprite it came from.
+                    if let Some(pix) = self.pixels.get_mut(i - self.skip) {
+                        if prepared_pixel.color != 0b00
+                            && (pix.color == 0b00 || self.rank < pix.sprite_rank)
+                        {
                             *pix = prepared_pixel;
                         }
                     } else {
@@ -263,33 +398,66 @@ impl SpritePixelFifo {
             ..Default::default()
         })
     }
+
+    /// The queued pixels, from the next one to be popped to the last one pushed
+    pub fn pixels(&self) -> impl Iterator<Item = Pixel> + '_ {
+        self.pixels.iter()
+    }
+
+    /// The step the fetcher performed on its last clock, or `None` if it wasn't fetching a sprite
+    pub fn step(&self) -> Option<FetcherStep> {
+        self.step
+    }
 }
 
 enum FifoState {
     FetchTile,
     FetchTileDataLow {
+        tile: u8,
+        layer: PixelLayer,
         tile_data_index: usize,
     },
     FetchTileDataHigh {
+        tile: u8,
+        layer: PixelLayer,
         tile_data_index: usize,
         tile_data_low: u8,
     },
     ReadyToPush {
+        tile: u8,
+        layer: PixelLayer,
         tile_data_low: u8,
         tile_data_high: u8,
     },
 }
 
-#[derive(Debug, Default, Clone, Copy)]
+impl FifoState {
+    /// The step the fetcher performs when it is next clocked
+    fn step(&self) -> FetcherStep {
+        match self {
+            FifoState::FetchTile => FetcherStep::ReadTileNumber,
+            FifoState::FetchTileDataLow { .. } => FetcherStep::ReadDataLow,
+            FifoState::FetchTileDataHigh { .. } => FetcherStep::ReadDataHigh,
+            FifoState::ReadyToPush { .. } => FetcherStep::Push,
+        }
+    }
+}
+
+/// A pixel in one of the FIFOs
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub struct Pixel {
     /// Pixel color (palette index)
     pub color: u8,
     /// Palette (0-1 on DMG, 0-7 on CGB), only applies to sprites on DMG
     pub palette: u8,
-    /// Sprite priority (only relevant on CGB)
-    pub sprite_priority: bool,
+    /// The rank of the sprite the pixel came from, with lower ranks drawn on top
+    pub sprite_rank: u8,
     /// BG Priority (flag bit 7 of sprites)
     pub bg_priority: bool,
+    /// The tile the pixel was fetched from
+    pub tile: u8,
+    /// Where the pixel was fetched from, for pixel attribution
+    pub layer: PixelLayer,
 }
 
 struct ShiftRegister<T: Default + Clone + Copy, const N: usize> {
@@ -343,6 +511,10 @@ impl<T: Default + Clone + Copy, const N: usize> ShiftRegister<T, N> {
         Some(r)
     }
 
+    fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        (0..self.len).map(move |index| self.data[(self.i + index) % N])
+    }
+
     fn get_mut(&mut self, index: usize) -> Option<&mut T> {
         if index >= self.len {
             return None;
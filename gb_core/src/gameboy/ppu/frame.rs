@@ -1,30 +1,189 @@
-use std::ops::{Index, IndexMut};
+pub mod attribution;
+pub mod post_process;
+pub mod scale;
 
-use super::color::RgbaColor;
+use std::{
+    convert::TryInto,
+    ops::{Index, IndexMut},
+};
 
-#[derive(Clone, Copy, Debug)]
+use self::attribution::AttributionBuffer;
+use super::color::{RgbaColor, COLORS};
+
+/// A shade from 0 (lightest) to 3 (darkest), after the BG or OBJ palette has been applied
+pub type Shade = u8;
+
+/// A screen of pixels, stored as shades along with the colors they are displayed as.
+///
+/// Post-processing can give every pixel a color of its own with [`Frame::colors_mut`], which is
+/// then displayed in place of the shades and palette.
+#[derive(Debug)]
 pub struct Frame {
-    pixels: [RgbaColor; 144 * 160],
+    pixels: [Shade; 144 * 160],
+    palette: [RgbaColor; 4],
+    /// The color of every pixel, which is only used while `pixel_colors` is set. The buffer is
+    /// kept when the frame is reused, so that post-processing doesn't allocate every frame.
+    colors: Option<Box<[RgbaColor]>>,
+    pixel_colors: bool,
+    /// Where each pixel came from, which is only used while `attributed` is set. Kept when the
+    /// frame is reused, like `colors`.
+    attribution: Option<Box<AttributionBuffer>>,
+    attributed: bool,
 }
 
 impl Frame {
     pub fn new() -> Self {
         Self {
             pixels: [0; 144 * 160],
+            palette: COLORS,
+            colors: None,
+            pixel_colors: false,
+            attribution: None,
+            attributed: false,
+        }
+    }
+
+    fn pixel_color(&self, i: usize) -> RgbaColor {
+        match self.pixel_colors() {
+            Some(colors) => colors[i],
+            None => self.palette[self.pixels[i] as usize & 3],
+        }
+    }
+
+    /// The color each shade is displayed as
+    pub fn palette(&self) -> &[RgbaColor; 4] {
+        &self.palette
+    }
+
+    /// Change the color of each shade. This has no effect on the colors of a frame that has been
+    /// given colors of its own with [`Frame::colors_mut`].
+    pub fn set_palette(&mut self, palette: [RgbaColor; 4]) {
+        self.palette = palette;
+    }
+
+    /// The color of every pixel, row by row, if post-processing has given them colors of their
+    /// own. Otherwise the colors come from the shades and the palette.
+    pub fn pixel_colors(&self) -> Option<&[RgbaColor]> {
+        self.colors.as_deref().filter(|_| self.pixel_colors)
+    }
+
+    /// The color of every pixel, row by row, to be changed by post-processing. The first call
+    /// fills them in from the shades and the palette, and from then on they are displayed
+    /// instead.
+    pub fn colors_mut(&mut self) -> &mut [RgbaColor] {
+        if !self.pixel_colors {
+            let (pixels, palette) = (&self.pixels, &self.palette);
+            let colors = self
+                .colors
+                .get_or_insert_with(|| vec![0; 144 * 160].into_boxed_slice());
+            for (color, &shade) in colors.iter_mut().zip(pixels.iter()) {
+                *color = palette[shade as usize & 3];
+            }
+            self.pixel_colors = true;
         }
+        self.colors.as_deref_mut().unwrap()
+    }
+
+    /// Go back to displaying the shades with the palette, before drawing into a reused frame
+    pub(crate) fn clear_pixel_colors(&mut self) {
+        self.pixel_colors = false;
+    }
+
+    /// Where each pixel came from, if the frame was drawn with pixel attribution enabled
+    pub fn attribution(&self) -> Option<&AttributionBuffer> {
+        self.attribution.as_deref().filter(|_| self.attributed)
+    }
+
+    /// The attribution buffer, to be filled in as the frame is drawn
+    pub(crate) fn attribution_mut(&mut self) -> &mut AttributionBuffer {
+        self.attributed = true;
+        self.attribution
+            .get_or_insert_with(|| Box::new(AttributionBuffer::new()))
     }
 
-    pub fn rows(&self) -> impl Iterator<Item = &[RgbaColor; 160]> {
+    /// Forget the attribution, before drawing into a reused frame
+    pub(crate) fn clear_attribution(&mut self) {
+        self.attributed = false;
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[Shade; 160]> {
         self.pixels.array_chunks::<160>()
     }
 
-    pub fn iter(&self) -> impl Iterator<Item = &RgbaColor> {
+    /// Returns row `y` of the frame
+    ///
+    /// # Panics
+    /// Panics if `y` >= 144
+    pub fn row(&self, y: usize) -> &[Shade; 160] {
+        assert_coords_in_range(0, y);
+        self.pixels[y * 160..(y + 1) * 160].try_into().unwrap()
+    }
+
+    /// Returns row `y` of the frame, to be written to
+    ///
+    /// # Panics
+    /// Panics if `y` >= 144
+    pub fn row_mut(&mut self, y: usize) -> &mut [Shade; 160] {
+        assert_coords_in_range(0, y);
+        (&mut self.pixels[y * 160..(y + 1) * 160])
+            .try_into()
+            .unwrap()
+    }
+
+    /// Returns row `y` of the frame as colors
+    ///
+    /// # Panics
+    /// Panics if `y` >= 144
+    pub fn row_colors(&self, y: usize) -> [RgbaColor; 160] {
+        assert_coords_in_range(0, y);
+        std::array::from_fn(|x| self.pixel_color(y * 160 + x))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Shade> {
         self.pixels.iter()
     }
 
-    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut RgbaColor> {
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Shade> {
         self.pixels.iter_mut()
     }
+
+    /// The color of the pixel at `(x, y)`
+    ///
+    /// # Panics
+    /// Panics if the coordinates are outside of the frame
+    pub fn color(&self, x: usize, y: usize) -> RgbaColor {
+        assert_coords_in_range(x, y);
+        self.pixel_color(y * 160 + x)
+    }
+
+    /// The color of every pixel, row by row
+    pub fn colors(&self) -> impl Iterator<Item = RgbaColor> + '_ {
+        (0..self.pixels.len()).map(move |i| self.pixel_color(i))
+    }
+}
+
+impl Clone for Frame {
+    fn clone(&self) -> Self {
+        Self {
+            pixels: self.pixels,
+            palette: self.palette,
+            colors: self.colors.clone(),
+            pixel_colors: self.pixel_colors,
+            attribution: self.attribution.clone(),
+            attributed: self.attributed,
+        }
+    }
+
+    /// Reuses the color buffer, so that copying frames into a [`FrameSink`](super::frame_sink::FrameSink)
+    /// doesn't allocate
+    fn clone_from(&mut self, source: &Self) {
+        self.pixels = source.pixels;
+        self.palette = source.palette;
+        self.colors.clone_from(&source.colors);
+        self.pixel_colors = source.pixel_colors;
+        self.attribution.clone_from(&source.attribution);
+        self.attributed = source.attributed;
+    }
 }
 
 impl Default for Frame {
@@ -39,7 +198,7 @@ fn assert_coords_in_range(x: usize, y: usize) {
 }
 
 impl Index<(usize, usize)> for Frame {
-    type Output = RgbaColor;
+    type Output = Shade;
     fn index(&self, (x, y): (usize, usize)) -> &Self::Output {
         assert_coords_in_range(x, y);
         &self.pixels[y * 160 + x]
@@ -54,7 +213,7 @@ impl IndexMut<(usize, usize)> for Frame {
 }
 
 impl Index<usize> for Frame {
-    type Output = RgbaColor;
+    type Output = Shade;
     fn index(&self, i: usize) -> &Self::Output {
         &self.pixels[i]
     }
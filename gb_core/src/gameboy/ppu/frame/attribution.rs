@@ -0,0 +1,113 @@
+//! Where each pixel of a frame came from, for tracking down rendering bugs.
+//!
+//! While [`Gameboy::set_pixel_attribution`](crate::gameboy::Gameboy::set_pixel_attribution) is
+//! enabled, the PPU records a [`PixelSource`] for every pixel it draws, and each drawn frame
+//! carries them in an [`AttributionBuffer`] returned by
+//! [`Frame::attribution`](super::Frame::attribution).
+
+use std::convert::TryInto;
+
+use super::assert_coords_in_range;
+
+/// The layer a pixel was fetched from, and the tile it belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelLayer {
+    /// `map_x` and `map_y` are the position of the tile in the background map
+    Background {
+        tile: u8,
+        map_x: u8,
+        map_y: u8,
+    },
+    /// `map_x` is the column of the tile in the window map, and `window_line` is the line of the
+    /// window being drawn, which is what the window's row is taken from
+    Window {
+        tile: u8,
+        map_x: u8,
+        window_line: u8,
+    },
+    Sprite {
+        tile: u8,
+        oam_index: u8,
+    },
+}
+
+impl PixelLayer {
+    /// The tile number, as stored in the tile map or OAM
+    pub fn tile(&self) -> u8 {
+        match *self {
+            PixelLayer::Background { tile, .. }
+            | PixelLayer::Window { tile, .. }
+            | PixelLayer::Sprite { tile, .. } => tile,
+        }
+    }
+}
+
+impl Default for PixelLayer {
+    fn default() -> Self {
+        PixelLayer::Background {
+            tile: 0,
+            map_x: 0,
+            map_y: 0,
+        }
+    }
+}
+
+/// The palette register that turned a pixel's color ID into a shade
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PaletteRegister {
+    #[default]
+    Bgp,
+    Obp0,
+    Obp1,
+}
+
+/// What produced a pixel
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PixelSource {
+    pub layer: PixelLayer,
+    /// The 2-bit color ID, before the palette was applied. Background and window pixels drawn
+    /// while they are disabled have color 0.
+    pub color: u8,
+    pub palette: PaletteRegister,
+}
+
+/// A [`PixelSource`] for every pixel of a frame
+#[derive(Debug, Clone)]
+pub struct AttributionBuffer {
+    sources: Box<[PixelSource; 144 * 160]>,
+}
+
+impl AttributionBuffer {
+    pub(crate) fn new() -> Self {
+        AttributionBuffer {
+            sources: vec![PixelSource::default(); 144 * 160]
+                .into_boxed_slice()
+                .try_into()
+                .unwrap(),
+        }
+    }
+
+    /// The source of the pixel at `(x, y)`
+    ///
+    /// # Panics
+    /// Panics if the coordinates are outside of the frame
+    pub fn get(&self, x: usize, y: usize) -> PixelSource {
+        assert_coords_in_range(x, y);
+        self.sources[y * 160 + x]
+    }
+
+    /// Returns row `y` of the buffer
+    ///
+    /// # Panics
+    /// Panics if `y` >= 144
+    pub fn row(&self, y: usize) -> &[PixelSource; 160] {
+        assert_coords_in_range(0, y);
+        self.sources[y * 160..(y + 1) * 160].try_into().unwrap()
+    }
+
+    pub(crate) fn row_mut(&mut self, y: usize) -> &mut [PixelSource; 160] {
+        (&mut self.sources[y * 160..(y + 1) * 160])
+            .try_into()
+            .unwrap()
+    }
+}
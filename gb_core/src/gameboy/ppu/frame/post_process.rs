@@ -0,0 +1,138 @@
+//! Filters applied to each finished frame before it is shown, set up with
+//! [`Gameboy::set_post_processing`](crate::gameboy::Gameboy::set_post_processing).
+//!
+//! The stages run in order on the back buffer, just before it becomes the front frame, so
+//! everything that reads frames afterwards sees the processed result. With no stages, which is
+//! the default, frames are left exactly as the PPU drew them.
+
+use super::Frame;
+use crate::gameboy::ppu::color::RgbaColor;
+
+/// One stage of the post-processing chain
+pub trait PostProcess {
+    /// Change `frame` in place. Called once for every frame that is drawn.
+    fn process(&mut self, frame: &mut Frame);
+}
+
+/// Maps each color through a gamma curve and a channel mixing matrix, to imitate how an LCD
+/// displays colors.
+///
+/// Each channel is decoded from the LCD's gamma into linear light, mixed with the matrix, then
+/// encoded with the display's gamma. A frame that still uses its palette only has its 4 palette
+/// colors corrected.
+#[derive(Debug, Clone)]
+pub struct ColorCorrection {
+    /// Linear light for each 8-bit channel value
+    decode: [f32; 256],
+    /// Each row gives the amount of red, green and blue mixed into that output channel
+    matrix: [[f32; 3]; 3],
+    /// 8-bit channel values for linear light, in steps of 1/[`ENCODE_STEPS`]
+    encode: Box<[u8; ENCODE_STEPS + 1]>,
+}
+
+const ENCODE_STEPS: usize = 1024;
+
+impl ColorCorrection {
+    /// The matrix that leaves colors unchanged
+    pub const IDENTITY: [[f32; 3]; 3] = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+    /// Correct colors shown on an LCD with gamma `lcd_gamma` for a display with gamma
+    /// `display_gamma`, mixing the channels with `matrix`
+    pub fn new(lcd_gamma: f32, display_gamma: f32, matrix: [[f32; 3]; 3]) -> Self {
+        let mut encode = Box::new([0; ENCODE_STEPS + 1]);
+        for (i, c) in encode.iter_mut().enumerate() {
+            let linear = i as f32 / ENCODE_STEPS as f32;
+            *c = (linear.powf(display_gamma.recip()) * 255.0).round() as u8;
+        }
+        ColorCorrection {
+            decode: std::array::from_fn(|c| (c as f32 / 255.0).powf(lcd_gamma)),
+            matrix,
+            encode,
+        }
+    }
+
+    pub fn correct(&self, color: RgbaColor) -> RgbaColor {
+        let [r, g, b, a] = color.to_le_bytes();
+        let linear = [r, g, b].map(|c| self.decode[c as usize]);
+        let [r, g, b] = self.matrix.map(|row| {
+            let mixed: f32 = row.iter().zip(linear).map(|(m, c)| m * c).sum();
+            let step = (mixed.clamp(0.0, 1.0) * ENCODE_STEPS as f32).round();
+            self.encode[step as usize]
+        });
+        u32::from_le_bytes([r, g, b, a])
+    }
+}
+
+impl Default for ColorCorrection {
+    /// Leaves colors unchanged, apart from rounding
+    fn default() -> Self {
+        Self::new(2.2, 2.2, Self::IDENTITY)
+    }
+}
+
+impl PostProcess for ColorCorrection {
+    fn process(&mut self, frame: &mut Frame) {
+        if frame.pixel_colors().is_some() {
+            for color in frame.colors_mut() {
+                *color = self.correct(*color);
+            }
+        } else {
+            let palette = frame.palette().map(|color| self.correct(color));
+            frame.set_palette(palette);
+        }
+    }
+}
+
+/// Mixes each frame with the one shown before it, like the slow response of the DMG's LCD, which
+/// games rely on to make flickering sprites look transparent.
+///
+/// The mix is taken with the previous output of this stage rather than the previous frame drawn,
+/// so that an image fades out over several frames.
+#[derive(Debug, Clone)]
+pub struct FrameBlend {
+    /// How much of the previous frame is kept, in 256ths
+    previous_weight: u16,
+    previous: Option<Box<[RgbaColor]>>,
+}
+
+impl FrameBlend {
+    /// Blend in `ratio` of the previous frame, from 0.0 (no blending) to 1.0 (the first frame is
+    /// never replaced)
+    pub fn new(ratio: f32) -> Self {
+        FrameBlend {
+            previous_weight: (ratio.clamp(0.0, 1.0) * 256.0).round() as u16,
+            previous: None,
+        }
+    }
+
+    /// Forget the previous frame, so that the next one is shown without blending
+    pub fn reset(&mut self) {
+        self.previous = None;
+    }
+}
+
+/// Mix `weight`/256 of `previous` into `current`, keeping the alpha of `current`
+fn blend(current: RgbaColor, previous: RgbaColor, weight: u16) -> RgbaColor {
+    let current = current.to_le_bytes();
+    let previous = previous.to_le_bytes();
+    let mix = |i: usize| {
+        let mixed = current[i] as u16 * (256 - weight) + previous[i] as u16 * weight;
+        ((mixed + 128) / 256) as u8
+    };
+    u32::from_le_bytes([mix(0), mix(1), mix(2), current[3]])
+}
+
+impl PostProcess for FrameBlend {
+    fn process(&mut self, frame: &mut Frame) {
+        let colors = frame.colors_mut();
+        match &mut self.previous {
+            Some(previous) => {
+                for (color, previous) in colors.iter_mut().zip(previous.iter_mut()) {
+                    *color = blend(*color, *previous, self.previous_weight);
+                    *previous = *color;
+                }
+            }
+            None => self.previous = Some(Box::from(&*colors)),
+        }
+    }
+}
@@ -0,0 +1,99 @@
+//! Integer scaling of frames into RGBA8 buffers, with optional filters.
+//!
+//! Every function writes `160 * factor` by `144 * factor` pixels of 4 bytes each into `out`, row
+//! by row, and returns [`GbError::BufferSize`] without writing anything if `out` has a different
+//! length.
+
+use super::Frame;
+use crate::GbError;
+
+/// How bright the darkened rows of [`scale_scanlines`] are, in 256ths
+const SCANLINE_BRIGHTNESS: u16 = 192;
+/// How bright the grid lines of [`scale_lcd_grid`] are, in 256ths
+const GRID_BRIGHTNESS: u16 = 224;
+
+/// The number of bytes needed to hold a frame scaled by `factor`
+pub fn buffer_len(factor: usize) -> usize {
+    160 * factor * 144 * factor * 4
+}
+
+/// Scale each pixel up to a `factor` by `factor` square
+pub fn scale_nearest(frame: &Frame, factor: usize, out: &mut [u8]) -> Result<(), GbError> {
+    scale(frame, factor, out, 256, |_, _, _| false)
+}
+
+/// Like [`scale_nearest`], but every other row of the output is darkened like the scanlines of a
+/// CRT
+pub fn scale_scanlines(frame: &Frame, factor: usize, out: &mut [u8]) -> Result<(), GbError> {
+    scale(frame, factor, out, SCANLINE_BRIGHTNESS, |_, _, out_y| {
+        out_y % 2 == 1
+    })
+}
+
+/// Like [`scale_nearest`], but the gaps between the DMG's LCD pixels are drawn as a faint grid
+/// along the right and bottom edges of each square. A `factor` of 1 leaves no room for the grid,
+/// so it is the same as [`scale_nearest`].
+pub fn scale_lcd_grid(frame: &Frame, factor: usize, out: &mut [u8]) -> Result<(), GbError> {
+    scale(frame, factor, out, GRID_BRIGHTNESS, |sub_x, sub_y, _| {
+        factor > 1 && (sub_x == factor - 1 || sub_y == factor - 1)
+    })
+}
+
+fn darken([r, g, b, a]: [u8; 4], brightness: u16) -> [u8; 4] {
+    let darken = |c: u8| (c as u16 * brightness / 256) as u8;
+    [darken(r), darken(g), darken(b), a]
+}
+
+/// Scale `frame` into `out`. `dim` is called with the position of an output pixel inside its
+/// square, and the output row, and returns whether to scale its brightness by `brightness`/256.
+fn scale(
+    frame: &Frame,
+    factor: usize,
+    out: &mut [u8],
+    brightness: u16,
+    dim: impl Fn(usize, usize, usize) -> bool,
+) -> Result<(), GbError> {
+    let expected = buffer_len(factor);
+    if out.len() != expected {
+        return Err(GbError::BufferSize {
+            expected,
+            actual: out.len(),
+        });
+    }
+    if factor == 0 {
+        return Ok(());
+    }
+
+    let palette = frame.palette().map(u32::to_le_bytes);
+    let palette_dimmed = palette.map(|color| darken(color, brightness));
+    let mut out_rows = out.chunks_exact_mut(160 * factor * 4).enumerate();
+    for (y, row) in frame.rows().enumerate() {
+        // Frames with colors of their own from post-processing don't use the palette
+        let row_colors = frame
+            .pixel_colors()
+            .map(|colors| &colors[y * 160..(y + 1) * 160]);
+        for (sub_y, (out_y, out_row)) in (0..factor).zip(out_rows.by_ref()) {
+            let mut pixels = out_row.chunks_exact_mut(4);
+            for (x, &shade) in row.iter().enumerate() {
+                let (color, dimmed) = match row_colors {
+                    Some(colors) => {
+                        let color = colors[x].to_le_bytes();
+                        (color, darken(color, brightness))
+                    }
+                    None => (
+                        palette[shade as usize & 3],
+                        palette_dimmed[shade as usize & 3],
+                    ),
+                };
+                for (sub_x, pixel) in (0..factor).zip(pixels.by_ref()) {
+                    if dim(sub_x, sub_y, out_y) {
+                        pixel.copy_from_slice(&dimmed);
+                    } else {
+                        pixel.copy_from_slice(&color);
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,111 @@
+//! Timing and content details handed off alongside each completed frame, for A/V sync and
+//! statistics in frontends
+
+/// What happened while a frame was being drawn. Get it with
+/// [`PpuState::frame_info`](super::PpuState::frame_info) or
+/// [`FrameGuard::info`](super::frame_sink::FrameGuard::info).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FrameInfo {
+    /// T-cycles since power on when the frame was finished
+    pub cycle: u64,
+    /// The number of frames the PPU had completed when this one was finished, as in
+    /// [`PpuState::frame_count`](super::PpuState::frame_count)
+    pub number: u64,
+    /// How many lines showed the window
+    pub window_lines: u8,
+    /// How many sprites were drawn, counting a sprite again on each line it covers
+    pub sprites_drawn: u16,
+    /// The most sprites drawn on any one line, which is never more than 10
+    pub peak_sprites_per_line: u8,
+    /// Whether the LCD was turned off since the previous frame. This is set for the blank frame
+    /// shown when it is turned off, and for the first frame drawn after it is turned back on.
+    pub lcd_was_disabled: bool,
+    /// What the CPU did from the end of the frame before, whether or not that one was drawn, to
+    /// the end of this one. For the blank frame shown when the LCD is turned off, this ends there.
+    pub cpu: CpuUsage,
+}
+
+/// How the CPU spent its M-cycles, for seeing how much headroom a game has each frame. Counted
+/// by the [`Gameboy`](crate::gameboy::Gameboy) as it runs, so always zero for a PPU on its own.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CpuUsage {
+    /// M-cycles the CPU was clocked for. This counts the extra M-cycles of an overclocked CPU,
+    /// but not the ones it was paused for by OAM DMA.
+    pub cycles: u32,
+    /// M-cycles spent halted, waiting for an interrupt
+    pub halted_cycles: u32,
+    /// M-cycles spent dispatching interrupts, which is 5 for each one
+    pub dispatch_cycles: u32,
+}
+
+impl CpuUsage {
+    /// Count one M-cycle
+    pub(crate) fn add_cycle(&mut self, halted: bool, dispatch: bool) {
+        self.cycles += 1;
+        self.halted_cycles += halted as u32;
+        self.dispatch_cycles += dispatch as u32;
+    }
+
+    /// The percentage of M-cycles spent halted. A game that waits for VBlank with HALT has this
+    /// much of each frame to spare.
+    pub fn idle_percent(&self) -> f64 {
+        self.percent(self.halted_cycles)
+    }
+
+    /// The percentage of M-cycles spent dispatching interrupts
+    pub fn dispatch_percent(&self) -> f64 {
+        self.percent(self.dispatch_cycles)
+    }
+
+    fn percent(&self, cycles: u32) -> f64 {
+        if self.cycles == 0 {
+            0.0
+        } else {
+            cycles as f64 * 100.0 / self.cycles as f64
+        }
+    }
+}
+
+/// The M-cycles the CPU spent halted on each visible line, see
+/// [`Gameboy::set_scanline_idle_tracking`](crate::gameboy::Gameboy::set_scanline_idle_tracking)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ScanlineIdle {
+    /// The frame being drawn
+    current: [u16; 144],
+    /// The last frame completed
+    last: [u16; 144],
+}
+
+impl Default for ScanlineIdle {
+    fn default() -> Self {
+        ScanlineIdle {
+            current: [0; 144],
+            last: [0; 144],
+        }
+    }
+}
+
+impl ScanlineIdle {
+    /// Count a halted M-cycle on `line`, unless it is in VBlank
+    pub fn add_halted_cycle(&mut self, line: usize) {
+        if let Some(count) = self.current.get_mut(line) {
+            *count = count.saturating_add(1);
+        }
+    }
+
+    pub fn finish_frame(&mut self) {
+        self.last = std::mem::replace(&mut self.current, [0; 144]);
+    }
+
+    pub fn last(&self) -> &[u16; 144] {
+        &self.last
+    }
+}
+
+impl FrameInfo {
+    /// Count the sprites drawn on one line
+    pub(crate) fn add_line_sprites(&mut self, sprites: u8) {
+        self.sprites_drawn += sprites as u16;
+        self.peak_sprites_per_line = self.peak_sprites_per_line.max(sprites);
+    }
+}
@@ -0,0 +1,80 @@
+//! Recycling frame buffers.
+//!
+//! Completed frames are handed out as [`SharedFrame`]s, which can be held for as long as needed
+//! without copying them, while the PPU draws into other buffers. Once every handle to a frame has
+//! been dropped, its buffer is free to have a later frame drawn into it.
+//!
+//! Buffers are counted with `Arc`, or with `Rc` when built with the `single-thread` feature, which
+//! saves the atomic operations where frames are never sent to another thread.
+
+use std::ops::Deref;
+
+use super::frame::Frame;
+
+/// A counted reference to a frame buffer
+#[cfg(not(feature = "single-thread"))]
+pub type FrameRc = std::sync::Arc<Frame>;
+
+/// A counted reference to a frame buffer
+#[cfg(feature = "single-thread")]
+pub type FrameRc = std::rc::Rc<Frame>;
+
+/// The most buffers a pool keeps track of. The PPU needs three to keep drawing, so this leaves
+/// room for a frontend to hold on to one frame without causing any allocations.
+pub const POOL_CAPACITY: usize = 4;
+
+/// A bounded set of frame buffers, each of which is free whenever the pool holds the only
+/// reference to it
+#[derive(Debug, Default)]
+pub struct FramePool {
+    buffers: Vec<FrameRc>,
+}
+
+impl FramePool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take a free buffer out of the pool, or allocate a new one if they are all being held, so
+    /// this never waits for a frame to be released. The buffer is not shared with anything, and a
+    /// recycled buffer still holds whatever was last drawn in it.
+    pub fn take(&mut self) -> FrameRc {
+        match self
+            .buffers
+            .iter()
+            .position(|buffer| FrameRc::strong_count(buffer) == 1)
+        {
+            Some(i) => self.buffers.swap_remove(i),
+            None => FrameRc::new(Frame::new()),
+        }
+    }
+
+    /// Share a finished frame, keeping track of its buffer so that it can be reused once every
+    /// handle to it is dropped. If the pool is full, the buffer is freed then instead.
+    pub fn share(&mut self, frame: FrameRc) -> SharedFrame {
+        if self.buffers.len() < POOL_CAPACITY {
+            self.buffers.push(frame.clone());
+        }
+        SharedFrame(frame)
+    }
+
+    /// Number of buffers ready to be reused
+    pub fn free_len(&self) -> usize {
+        self.buffers
+            .iter()
+            .filter(|buffer| FrameRc::strong_count(buffer) == 1)
+            .count()
+    }
+}
+
+/// A completed frame, which never changes while it is held. Cloning it is cheap.
+#[derive(Debug, Clone)]
+pub struct SharedFrame(FrameRc);
+
+impl Deref for SharedFrame {
+    type Target = Frame;
+
+    fn deref(&self) -> &Frame {
+        &self.0
+    }
+}
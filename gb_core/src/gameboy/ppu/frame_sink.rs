@@ -0,0 +1,153 @@
+//! Sharing completed frames with other threads.
+//!
+//! The PPU pushes every frame it draws into a [`FrameSink`], and any number of [`FrameReceiver`]s
+//! can look at the most recent one. Frames are triple buffered: the emulator writes into a slot
+//! that nobody is reading, and then publishes it as the latest frame, so it never has to wait for a
+//! reader and readers never see a half-written frame.
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, PoisonError, RwLock, RwLockReadGuard,
+};
+
+use super::{frame::Frame, frame_info::FrameInfo};
+
+/// When completed frames are copied into the [`FrameSink`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PresentationMode {
+    /// Every frame is copied in as soon as it is completed
+    #[default]
+    EveryFrame,
+    /// Only frames the frontend takes with [`Gameboy::take_frame`] are copied in. Frames completed
+    /// in between are dropped without being copied anywhere, and their buffers go back to the
+    /// frame pool, which suits running uncapped. The PPU still draws every frame in full.
+    ///
+    /// [`Gameboy::take_frame`]: crate::gameboy::Gameboy::take_frame
+    LatestOnly,
+}
+
+/// One for the latest frame, one for a reader to hold on to, and one to write the next frame into
+const SLOTS: usize = 3;
+
+#[derive(Debug)]
+struct Slot {
+    info: FrameInfo,
+    frame: Frame,
+}
+
+#[derive(Debug)]
+struct Shared {
+    slots: [RwLock<Slot>; SLOTS],
+    /// Index of the slot holding the latest frame. This slot is never written to.
+    latest: AtomicUsize,
+}
+
+/// The sending half, which is owned by the PPU
+#[derive(Debug)]
+pub struct FrameSink {
+    shared: Arc<Shared>,
+}
+
+impl FrameSink {
+    pub fn new() -> Self {
+        let slot = || {
+            RwLock::new(Slot {
+                info: FrameInfo::default(),
+                frame: Frame::new(),
+            })
+        };
+        FrameSink {
+            shared: Arc::new(Shared {
+                slots: [slot(), slot(), slot()],
+                latest: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    pub fn receiver(&self) -> FrameReceiver {
+        FrameReceiver {
+            shared: self.shared.clone(),
+        }
+    }
+
+    /// Publish `frame` as the latest frame, along with `info`. This never blocks: if every other
+    /// slot is being read, the frame is dropped instead.
+    pub fn push(&self, info: FrameInfo, frame: &Frame) {
+        let latest = self.shared.latest.load(Ordering::SeqCst);
+        let free = self
+            .shared
+            .slots
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| i != latest)
+            .find_map(|(i, slot)| slot.try_write().ok().map(|guard| (i, guard)));
+
+        if let Some((i, mut slot)) = free {
+            slot.info = info;
+            slot.frame.clone_from(frame);
+            drop(slot);
+            self.shared.latest.store(i, Ordering::SeqCst);
+        }
+    }
+}
+
+impl Default for FrameSink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A handle to the latest frame drawn by a [`FrameSink`]. Cloning it is cheap.
+#[derive(Debug, Clone)]
+pub struct FrameReceiver {
+    shared: Arc<Shared>,
+}
+
+impl FrameReceiver {
+    /// Borrow the latest completed frame.
+    ///
+    /// This only waits if the emulator is in the middle of publishing a frame. Holding on to the
+    /// guard does not block the emulator, but if several receivers hold guards to different frames
+    /// at once, new frames may be dropped until they are released.
+    pub fn latest(&self) -> FrameGuard<'_> {
+        loop {
+            let i = self.shared.latest.load(Ordering::SeqCst);
+            let slot = self.shared.slots[i]
+                .read()
+                .unwrap_or_else(PoisonError::into_inner);
+            // The slot may have been reused for a newer frame before we locked it. In that case
+            // `latest` will have moved on, so try again.
+            if self.shared.latest.load(Ordering::SeqCst) == i {
+                return FrameGuard { slot };
+            }
+        }
+    }
+}
+
+/// A completed frame borrowed from a [`FrameReceiver`]
+#[derive(Debug)]
+pub struct FrameGuard<'a> {
+    slot: RwLockReadGuard<'a, Slot>,
+}
+
+impl FrameGuard<'_> {
+    /// The number of frames the PPU had completed when this one was finished, which increases by
+    /// at least one with each new frame. Frames skipped by frame skip or dropped by the sink are
+    /// not seen, so the difference may be larger.
+    pub fn number(&self) -> u64 {
+        self.slot.info.number
+    }
+
+    /// What happened while this frame was being drawn
+    pub fn info(&self) -> FrameInfo {
+        self.slot.info
+    }
+}
+
+impl std::ops::Deref for FrameGuard<'_> {
+    type Target = Frame;
+
+    fn deref(&self) -> &Frame {
+        &self.slot.frame
+    }
+}
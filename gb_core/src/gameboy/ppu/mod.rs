@@ -1,18 +1,44 @@
 //! An implementation of the Gameboy monochrome PPU
+//!
+//! Every register reads back what was last written to it, except for these:
+//!
+//! | Address     | Register               | Reads                                                |
+//! |-------------|------------------------|------------------------------------------------------|
+//! | $FF41       | STAT                   | Bit 7 set, and the mode and LYC=LY bits as they are  |
+//! | $FF44       | LY                     | The line being drawn, and writes are ignored         |
+//! | $FF46       | DMA                    | The last value written, even once the transfer ends  |
+//! | $FF4F       | VBK                    | $FF, as it is only on the CGB                        |
+//! | $FF51-$FF55 | HDMA1-HDMA5            | $FF, as above                                        |
+//! | $FF68-$FF6B | BCPS, BCPD, OCPS, OCPD | $FF, as above                                        |
+//! | $FF6C       | OPRI                   | $FF, unless the [`Model`](super::Model) has it       |
+//!
+//! Nothing is worked out from other state when it is read, so a savestate only needs the stored
+//! values.
 pub mod color;
 pub mod consts;
+pub mod debug;
 mod execute;
 pub mod frame;
+pub mod frame_info;
+pub mod frame_pool;
+pub mod frame_sink;
+pub mod priority;
 pub mod registers;
+pub mod simple_renderer;
 
-use frame::Frame;
+use frame_pool::SharedFrame;
 use std::ops::{CoroutineState, Deref, DerefMut};
 
 use gb_cpu::CpuOutputPins;
 
-use self::execute::PpuState;
+pub(crate) use self::execute::FramePosition;
+pub use self::execute::{DmaState, Pixel};
+use self::{
+    execute::{PpuState, UsedRegisters},
+    registers::LCDC,
+};
 
-use super::Chip;
+use super::{BusInterleave, Chip, ClockContext};
 
 pub struct Ppu {
     state: Option<Box<PpuState>>,
@@ -98,32 +124,155 @@ impl Ppu {
         (image, IMAGE_WIDTH * scale, IMAGE_HEIGHT * scale)
     }
 
+    /// Whether an OAM DMA transfer is running or about to start
     pub fn dma_active(&self) -> bool {
-        match self.dma_transfer {
-            execute::DmaState::Inactive => false,
-            execute::DmaState::Active { .. } | execute::DmaState::ActiveFirstRead { .. } => true,
-        }
+        self.dma_transfer.transfer.is_some() || self.dma_transfer.starting.is_some()
     }
 }
 
 impl Ppu {
+    #[inline]
     pub fn clock_t_state(&mut self) {
+        let state = self.state.as_mut().unwrap();
+        // Dots the coroutine is only waiting through are counted off here, which is much cheaper
+        // than resuming it
+        if state.idle_dots > 0 && state.lcdc.contains(LCDC::LCD_ENABLE) {
+            state.idle_dots -= 1;
+            state.cycles += 1;
+            state.frame_dots += 1;
+        } else {
+            state.idle_dots = 0;
+            self.resume();
+        }
+    }
+
+    /// Run for `dots` T-cycles, counting off any the coroutine is waiting through all at once
+    fn clock_dots(&mut self, mut dots: u16) {
+        while dots > 0 {
+            let state = self.state.as_mut().unwrap();
+            if state.lcdc.contains(LCDC::LCD_ENABLE) {
+                let idle = state.idle_dots.min(dots);
+                state.idle_dots -= idle;
+                state.cycles += idle as u64;
+                state.frame_dots += idle as u32;
+                dots -= idle;
+            }
+            if dots > 0 {
+                state.idle_dots = 0;
+                self.resume();
+                dots -= 1;
+            }
+        }
+    }
+
+    fn resume(&mut self) {
         match self.gen.as_mut().resume(self.state.take().unwrap()) {
             CoroutineState::Yielded(state) => self.state = Some(state),
             CoroutineState::Complete(_) => unreachable!(),
         }
+        self.cycles += 1;
+        self.frame_dots += 1;
+    }
+
+    /// Return to the state at power on, restarting the PPU's coroutine. VRAM, OAM and the
+    /// settings that aren't part of the hardware are kept.
+    pub(crate) fn power_cycle(&mut self) {
+        self.state.as_mut().unwrap().power_cycle();
+        self.gen = execute::gen();
+    }
+
+    /// Run for `dots` T-cycles on its own, to move the PPU to a different point in its frame. The
+    /// frames, lines, events and interrupts it produces along the way are discarded, and
+    /// `frame_count` is left as it was.
+    pub fn skip_dots(&mut self, dots: usize) {
+        self.run_dots_quietly(dots);
+        // Catch up with the interrupt lines, so that the next bus cycle doesn't see them rise
+        self.perform_io(CpuOutputPins::Read { addr: 0 }, &mut 0xFF, &mut 0);
+    }
+
+    /// Run for `dots` T-cycles, discarding the frames, lines and events they produce
+    fn run_dots_quietly(&mut self, dots: usize) {
+        let frame_count = self.frame_count;
+        for _ in 0..dots {
+            self.clock_t_state();
+        }
+        self.frame_count = frame_count;
+        self.last_completed_line = None;
+        self.events.drain().for_each(drop);
+    }
+
+    /// Move a PPU that has just been power cycled to `position`, by running through the frame up
+    /// to there with the registers and memory it has now. Does nothing while the LCD is off.
+    pub(crate) fn restore_position(&mut self, position: FramePosition) {
+        if self.lcdc.contains(LCDC::LCD_ENABLE) {
+            // The interrupt lines are latched at the start of each M-cycle, so one that rose
+            // during the last M-cycle is still to be latched on the next
+            let dots = position.dots as usize;
+            self.skip_dots(dots.saturating_sub(4));
+            self.run_dots_quietly(dots.min(4));
+        }
+        let state = self.state.as_mut().unwrap();
+        state.frame_dots = position.dots;
+        state.wy_latch = position.wy_latch;
+        state.window_line = position.window_line;
+        state.lcd_was_disabled = position.lcd_was_disabled;
     }
 
-    pub fn get_frame(&self) -> Box<Frame> {
+    /// The latest completed frame. This doesn't copy the frame, and the PPU draws the following
+    /// frames elsewhere, so it can be held on to for as long as needed.
+    pub fn get_frame(&self) -> SharedFrame {
         self.frame.clone()
     }
 }
 
 impl Chip for Ppu {
-    fn clock(&mut self, input: CpuOutputPins, data: &mut u8, interrupt_request: &mut u8) {
-        self.perform_io(input, data, interrupt_request);
-        for _ in 0..4 {
-            self.clock_t_state();
+    fn clock(
+        &mut self,
+        input: CpuOutputPins,
+        data: &mut u8,
+        interrupt_request: &mut u8,
+        ctx: &ClockContext,
+    ) {
+        self.cycles = ctx.cycles;
+        self.consumed = UsedRegisters::empty();
+        // Turning the LCD on starts the PPU, so nothing it does this M-cycle can see the old value
+        let lcd_on = self.lcdc.contains(LCDC::LCD_ENABLE);
+        match input {
+            // No time passes, so a pulse from the write is latched on the next stock M-cycle
+            _ if ctx.extra_cycle => self.stat_pulse |= self.access(ctx.master, input, data),
+            CpuOutputPins::Write { .. } if self.interleave == BusInterleave::TCycle => {
+                // The interrupt lines are latched at the start of the M-cycle, as for any other
+                // access, so a pulse from the write is latched on the next one
+                self.latch_interrupts(interrupt_request, false);
+                self.clock_dots(3);
+                self.stat_pulse = self.access(ctx.master, input, data);
+                self.clock_dots(1);
+            }
+            _ => {
+                self.perform_io_from(ctx.master, input, data, interrupt_request);
+                self.clock_dots(4);
+            }
         }
+        if let CpuOutputPins::Write {
+            addr: addr @ 0xFF40..=0xFF4B,
+            data,
+        } = input
+        {
+            if self.ordering_check && lcd_on {
+                self.check_ordering(addr, data);
+            }
+        }
+    }
+
+    fn chip_select(&self) -> Vec<std::ops::RangeInclusive<u16>> {
+        let mut addresses = vec![0x8000..=0x9FFF, 0xFE00..=0xFE9F, 0xFF40..=0xFF4B];
+        if self.has_opri {
+            addresses.push(0xFF6C..=0xFF6C);
+        }
+        addresses
+    }
+
+    fn name(&self) -> &'static str {
+        "ppu"
     }
 }
@@ -0,0 +1,112 @@
+//! Which of several overlapping sprites is drawn on top, and whether the sprite on top or the
+//! background ends up on screen. See <https://gbdev.io/pandocs/OAM.html#drawing-priority>.
+
+use std::ops::Deref;
+
+use super::{
+    registers::{OamEntry, LCDC},
+    Pixel,
+};
+
+/// The most sprites the PPU selects for one line
+pub const SPRITES_PER_LINE: usize = 10;
+
+/// How overlapping sprites are ordered, selected by OPRI ($FF6C) on models that have it
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PriorityMode {
+    /// The sprite with the lower X coordinate is on top, and the one earlier in OAM breaks ties.
+    /// Monochrome models always use this.
+    #[default]
+    Dmg,
+    /// The sprite earlier in OAM is on top, wherever it is
+    Cgb,
+}
+
+impl PriorityMode {
+    /// Decode a value written to OPRI. Only bit 0 is used, and it is set for DMG ordering.
+    pub fn from_opri(value: u8) -> Self {
+        if value & 1 != 0 {
+            PriorityMode::Dmg
+        } else {
+            PriorityMode::Cgb
+        }
+    }
+
+    /// The value read from OPRI
+    pub fn opri(self) -> u8 {
+        match self {
+            PriorityMode::Dmg => 0xFF,
+            PriorityMode::Cgb => 0xFE,
+        }
+    }
+}
+
+/// The order of a line's sprites, returned by [`resolve_sprite_priority`]. It derefs to their
+/// indices in the sprites it was given, and is kept on the stack since it is worked out for
+/// every line.
+#[derive(Debug, Clone, Copy)]
+pub struct SpriteOrder {
+    indices: [u8; SPRITES_PER_LINE],
+    len: usize,
+}
+
+impl Deref for SpriteOrder {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.indices[..self.len]
+    }
+}
+
+/// Order the sprites selected for a line by priority. `candidates` must be in OAM order, and the
+/// result holds their indices in `candidates`, from the sprite drawn on top to the one drawn
+/// underneath.
+///
+/// # Panics
+/// If there are more than [`SPRITES_PER_LINE`] candidates.
+pub fn resolve_sprite_priority(candidates: &[OamEntry], mode: PriorityMode) -> SpriteOrder {
+    let mut indices = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
+    let order = &mut indices[..candidates.len()];
+    if mode == PriorityMode::Dmg {
+        // OAM order is kept between sprites at the same X. Unlike a stable sort, an unstable one
+        // never allocates.
+        order.sort_unstable_by_key(|&i| (candidates[i as usize].xpos, i));
+    }
+    SpriteOrder {
+        indices,
+        len: candidates.len(),
+    }
+}
+
+/// The pixel [`mix_pixel`] puts on screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MixResult {
+    /// The BG or window pixel, with color 0 if the background is disabled
+    Bg(Pixel),
+    Sprite(Pixel),
+}
+
+impl MixResult {
+    pub fn pixel(self) -> Pixel {
+        match self {
+            MixResult::Bg(pixel) | MixResult::Sprite(pixel) => pixel,
+        }
+    }
+}
+
+/// Mix a BG pixel with the sprite pixel on top of it, which is whatever the sprite FIFO holds
+/// there after overlapping sprites have been merged. Only that sprite's BG priority flag
+/// matters, even if a sprite underneath without the flag covers the pixel too.
+///
+/// On DMG, clearing [`LCDC::BG_ENABLE`] blanks both the background and the window to color 0, so
+/// any visible sprite pixel is drawn over them.
+pub fn mix_pixel(mut bg: Pixel, sprite: Pixel, lcdc: LCDC) -> MixResult {
+    if !lcdc.contains(LCDC::BG_ENABLE) {
+        bg.color = 0;
+    }
+    if sprite.color == 0 || (sprite.bg_priority && bg.color != 0) {
+        MixResult::Bg(bg)
+    } else {
+        MixResult::Sprite(sprite)
+    }
+}
@@ -0,0 +1,400 @@
+//! A scanline renderer with none of the timing of the pixel FIFOs, to check them against. It draws
+//! a whole line at once from the state of the PPU at the start of the line, so it can't show the
+//! effect of registers written partway through a line.
+//!
+//! With the `differential` feature, every line the PPU draws is compared to this renderer's, and
+//! the differences are collected in [`PpuState::divergences`](super::PpuState::divergences). It
+//! also draws every line with [`PpuBackend::Scanline`](crate::gameboy::PpuBackend::Scanline).
+
+use super::{
+    color::palette_shades,
+    frame::{
+        attribution::{PaletteRegister, PixelLayer, PixelSource},
+        Shade,
+    },
+    priority::{resolve_sprite_priority, PriorityMode, SPRITES_PER_LINE},
+    registers::{OamEntry, OamEntryFlags, LCDC},
+    PpuState,
+};
+
+/// Everything that decides what a line looks like, as of the start of the line
+#[derive(Clone)]
+pub struct LineState {
+    pub tile_data: [u8; 0x9800 - 0x8000],
+    pub bg_map_1: [u8; 0x9C00 - 0x9800],
+    pub bg_map_2: [u8; 0xA000 - 0x9C00],
+    pub oam: [u8; 0xFEA0 - 0xFE00],
+    pub lcdc: LCDC,
+    pub scy: u8,
+    pub scx: u8,
+    pub ly: u8,
+    pub wy: u8,
+    pub wx: u8,
+    pub bgp: u8,
+    pub obp0: u8,
+    pub obp1: u8,
+    pub priority_mode: PriorityMode,
+    /// Whether LY has matched WY yet this frame, which the window needs to be shown
+    pub wy_latch: bool,
+    /// The line of the window drawn if it is shown on this line
+    pub window_line: u8,
+}
+
+impl LineState {
+    pub fn capture(state: &PpuState) -> Self {
+        let view = LineView::new(state, state.debug_snapshot().wy_latch);
+        LineState {
+            tile_data: *view.tile_data,
+            bg_map_1: *view.bg_map_1,
+            bg_map_2: *view.bg_map_2,
+            oam: *view.oam,
+            lcdc: view.lcdc,
+            scy: view.scy,
+            scx: view.scx,
+            ly: view.ly,
+            wy: view.wy,
+            wx: view.wx,
+            bgp: view.bgp,
+            obp0: view.obp0,
+            obp1: view.obp1,
+            priority_mode: view.priority_mode,
+            wy_latch: view.wy_latch,
+            window_line: view.window_line,
+        }
+    }
+
+    fn view(&self) -> LineView<'_> {
+        LineView {
+            tile_data: &self.tile_data,
+            bg_map_1: &self.bg_map_1,
+            bg_map_2: &self.bg_map_2,
+            oam: &self.oam,
+            lcdc: self.lcdc,
+            scy: self.scy,
+            scx: self.scx,
+            ly: self.ly,
+            wy: self.wy,
+            wx: self.wx,
+            bgp: self.bgp,
+            obp0: self.obp0,
+            obp1: self.obp1,
+            priority_mode: self.priority_mode,
+            wy_latch: self.wy_latch,
+            window_line: self.window_line,
+        }
+    }
+}
+
+/// A [`LineState`] that borrows VRAM and OAM instead of copying them, which is what
+/// [`PpuBackend::Scanline`](crate::gameboy::PpuBackend::Scanline) draws from
+#[derive(Clone, Copy)]
+pub(super) struct LineView<'a> {
+    tile_data: &'a [u8; 0x9800 - 0x8000],
+    bg_map_1: &'a [u8; 0x9C00 - 0x9800],
+    bg_map_2: &'a [u8; 0xA000 - 0x9C00],
+    oam: &'a [u8; 0xFEA0 - 0xFE00],
+    lcdc: LCDC,
+    scy: u8,
+    scx: u8,
+    ly: u8,
+    wy: u8,
+    wx: u8,
+    bgp: u8,
+    obp0: u8,
+    obp1: u8,
+    priority_mode: PriorityMode,
+    wy_latch: bool,
+    window_line: u8,
+}
+
+impl<'a> LineView<'a> {
+    /// The PPU as it is now, with `wy_latch` as of the start of the line
+    pub fn new(state: &'a PpuState, wy_latch: bool) -> Self {
+        LineView {
+            tile_data: &state.tile_data,
+            bg_map_1: &state.bg_map_1,
+            bg_map_2: &state.bg_map_2,
+            oam: &state.oam,
+            lcdc: state.lcdc,
+            scy: state.scy,
+            scx: state.scx,
+            ly: state.ly,
+            wy: state.wy,
+            wx: state.wx,
+            bgp: state.bgp,
+            obp0: state.obp0,
+            obp1: state.obp1,
+            priority_mode: state.priority_mode,
+            wy_latch,
+            window_line: state.debug_snapshot().window_line,
+        }
+    }
+
+    /// Row `y` of the data of BG or window tile `tile`
+    fn tile_row(&self, tile: u8, y: u8) -> [u8; 2] {
+        let base = if self.lcdc.contains(LCDC::BG_TILE_DATA_AREA) {
+            tile as usize * 16
+        } else {
+            (0x1000 + tile as i8 as isize * 16) as usize
+        } + y as usize * 2;
+        [self.tile_data[base], self.tile_data[base + 1]]
+    }
+
+    /// Where the window starts, if it is shown on this line
+    fn window_start(&self) -> Option<u8> {
+        (self.lcdc.contains(LCDC::WINDOW_ENABLE) && self.wy_latch)
+            .then(|| self.wx.saturating_sub(7))
+    }
+
+    /// The row of the BG or window tile under pixel `x`, which column of it `x` is, and where it
+    /// came from
+    fn bg_tile_row(&self, x: u8) -> ([u8; 2], u8, PixelLayer) {
+        if self.window_start().map_or(false, |start| x >= start) {
+            let map = if self.lcdc.contains(LCDC::WINDOW_TILEMAP_AREA) {
+                self.bg_map_2
+            } else {
+                self.bg_map_1
+            };
+            let (map_x, map_y) = (x + 7 - self.wx, self.window_line);
+            let tile = map[map_y as usize / 8 * 32 + map_x as usize / 8];
+            let layer = PixelLayer::Window {
+                tile,
+                map_x: map_x / 8,
+                window_line: map_y,
+            };
+            (self.tile_row(tile, map_y % 8), map_x % 8, layer)
+        } else {
+            let map = if self.lcdc.contains(LCDC::BG_TILEMAP_AREA) {
+                self.bg_map_2
+            } else {
+                self.bg_map_1
+            };
+            let map_x = x.wrapping_add(self.scx);
+            let map_y = self.ly.wrapping_add(self.scy);
+            let tile = map[map_y as usize / 8 * 32 + map_x as usize / 8];
+            let layer = PixelLayer::Background {
+                tile,
+                map_x: map_x / 8,
+                map_y: map_y / 8,
+            };
+            (self.tile_row(tile, map_y % 8), map_x % 8, layer)
+        }
+    }
+
+    fn sprite_height(&self) -> u8 {
+        if self.lcdc.contains(LCDC::OBJ_SIZE) {
+            16
+        } else {
+            8
+        }
+    }
+
+    /// The first 10 sprites in OAM that cover this line, in OAM order, with their OAM indices and
+    /// how many there are
+    fn selected_sprites(&self) -> ([OamEntry; SPRITES_PER_LINE], [u8; SPRITES_PER_LINE], usize) {
+        let height = self.sprite_height() as usize;
+        let line = self.ly as usize + 16;
+        let mut sprites = [OamEntry::default(); SPRITES_PER_LINE];
+        let mut oam_indices = [0; SPRITES_PER_LINE];
+        let mut len = 0;
+        for (oam_index, entry) in (0..).zip(self.oam.chunks_exact(4)) {
+            let top = entry[0] as usize;
+            // Sprites count towards the limit even if they are off screen horizontally
+            if line >= top && line < top + height {
+                sprites[len] = OamEntry {
+                    ypos: entry[0],
+                    xpos: entry[1],
+                    tile: entry[2],
+                    flags: OamEntryFlags::from_bits_truncate(entry[3]),
+                };
+                oam_indices[len] = oam_index;
+                len += 1;
+                if len == SPRITES_PER_LINE {
+                    break;
+                }
+            }
+        }
+        (sprites, oam_indices, len)
+    }
+
+    /// The color index of each column of `sprite`, from left to right on screen, and the tile they
+    /// come from
+    fn sprite_row(&self, sprite: &OamEntry) -> ([u8; 8], u8) {
+        let height = self.sprite_height();
+        let mut row = self.ly + 16 - sprite.ypos;
+        if sprite.flags.contains(OamEntryFlags::Y_FLIP) {
+            row = height - 1 - row;
+        }
+        // 8x16 sprites ignore bit 0 of the tile number, and their row carries on into the next
+        // tile for the bottom half
+        let tile = if height == 16 {
+            sprite.tile & 0xFE
+        } else {
+            sprite.tile
+        };
+        let data = &self.tile_data[tile as usize * 16 + row as usize * 2..];
+        let mut colors = [0; 8];
+        for (column, color) in colors.iter_mut().enumerate() {
+            let column = if sprite.flags.contains(OamEntryFlags::X_FLIP) {
+                7 - column
+            } else {
+                column
+            };
+            *color = color_index(data, column as u8);
+        }
+        (colors, tile)
+    }
+
+    /// Draw line `ly`, and record where each pixel came from in `sources` if it is given
+    pub fn render(&self, mut sources: Option<&mut [PixelSource; 160]>) -> [Shade; 160] {
+        let bg_shades = palette_shades(self.bgp);
+        let obj_shades = [palette_shades(self.obp0), palette_shades(self.obp1)];
+        let (sprites, oam_indices, len) = self.selected_sprites();
+        let sprites = &sprites[..len];
+        let order = resolve_sprite_priority(sprites, self.priority_mode);
+
+        // The highest priority sprite with a visible pixel in a column is the only one that can be
+        // drawn there, so they are laid down from the lowest priority up
+        let mut sprite_pixels: [Option<(u8, u8, u8)>; 160] = [None; 160];
+        for &i in order.iter().rev() {
+            let sprite = &sprites[i as usize];
+            let (colors, tile) = self.sprite_row(sprite);
+            for (column, &color) in colors.iter().enumerate() {
+                match (sprite.xpos as usize + column).checked_sub(8) {
+                    Some(x) if x < 160 && color != 0 => sprite_pixels[x] = Some((i, color, tile)),
+                    _ => (),
+                }
+            }
+        }
+
+        // The BG and window are looked up a tile at a time
+        let window_start = self.window_start().map_or(160, |start| start as usize);
+        let mut bg_colors = [0; 160];
+        let mut bg_layers = [PixelLayer::default(); 160];
+        let mut x = 0;
+        while x < 160 {
+            let (data, column, layer) = self.bg_tile_row(x as u8);
+            let mut end = (x + 8 - column as usize).min(160);
+            if x < window_start {
+                end = end.min(window_start);
+            }
+            // The row's pixels from `column` on, leftmost in the top bit
+            let (low, high) = (data[0] << column, data[1] << column);
+            for (i, color) in bg_colors[x..end].iter_mut().enumerate() {
+                *color = (low << i) >> 7 | (high << i) >> 7 << 1;
+            }
+            // Only needed for attribution, which most lines are drawn without
+            if sources.is_some() {
+                bg_layers[x..end].fill(layer);
+            }
+            x = end;
+        }
+        // Clearing BG_ENABLE blanks both the background and the window on DMG
+        if !self.lcdc.contains(LCDC::BG_ENABLE) {
+            bg_colors = [0; 160];
+        }
+
+        let mut line = bg_colors.map(|color| bg_shades[color as usize]);
+        if let Some(sources) = &mut sources {
+            for (x, source) in sources.iter_mut().enumerate() {
+                *source = PixelSource {
+                    layer: bg_layers[x],
+                    color: bg_colors[x],
+                    palette: PaletteRegister::Bgp,
+                };
+            }
+        }
+        for (x, pixel) in line.iter_mut().enumerate() {
+            let Some((i, color, tile)) = sprite_pixels[x] else {
+                continue;
+            };
+            let i = i as usize;
+            let flags = sprites[i].flags;
+            if flags.contains(OamEntryFlags::BG_PRIORITY) && bg_colors[x] != 0 {
+                continue;
+            }
+            let obp1 = flags.contains(OamEntryFlags::PALETTE_OBP1);
+            *pixel = obj_shades[obp1 as usize][color as usize];
+            if let Some(sources) = &mut sources {
+                sources[x] = PixelSource {
+                    layer: PixelLayer::Sprite {
+                        tile,
+                        oam_index: oam_indices[i],
+                    },
+                    color,
+                    palette: if obp1 {
+                        PaletteRegister::Obp1
+                    } else {
+                        PaletteRegister::Obp0
+                    },
+                };
+            }
+        }
+        line
+    }
+}
+
+/// The color index of pixel `x` of a row of tile data, starting from its low byte
+fn color_index(row: &[u8], x: u8) -> u8 {
+    let bit = 7 - x;
+    (row[0] >> bit) & 1 | ((row[1] >> bit) & 1) << 1
+}
+
+/// Draw line `state.ly`
+pub fn render_line(state: &LineState) -> [Shade; 160] {
+    state.view().render(None)
+}
+
+/// A pixel where the PPU and [`render_line`] disagree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Divergence {
+    pub ly: u8,
+    pub x: u8,
+    /// The shade the PPU drew
+    pub ppu: Shade,
+    /// The shade [`render_line`] drew
+    pub expected: Shade,
+    pub lcdc: LCDC,
+    pub scx: u8,
+    pub scy: u8,
+    pub wx: u8,
+    pub wy: u8,
+}
+
+impl Divergence {
+    /// Compare a line drawn by the PPU to the one [`render_line`] draws from `state`, and return
+    /// the first pixel that differs
+    pub fn check(state: &LineState, ppu_line: &[Shade; 160]) -> Option<Self> {
+        let expected = render_line(state);
+        let x = (0..160).find(|&x| ppu_line[x] != expected[x])?;
+        Some(Divergence {
+            ly: state.ly,
+            x: x as u8,
+            ppu: ppu_line[x],
+            expected: expected[x],
+            lcdc: state.lcdc,
+            scx: state.scx,
+            scy: state.scy,
+            wx: state.wx,
+            wy: state.wy,
+        })
+    }
+}
+
+impl std::fmt::Display for Divergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "line {} pixel {}: PPU drew shade {}, expected {} (LCDC={:02X} SCX={} SCY={} WX={} WY={})",
+            self.ly,
+            self.x,
+            self.ppu,
+            self.expected,
+            self.lcdc.bits(),
+            self.scx,
+            self.scy,
+            self.wx,
+            self.wy
+        )
+    }
+}
@@ -0,0 +1,135 @@
+//! Counts how often each instruction is executed and how long it takes
+
+use std::{collections::BTreeMap, convert::TryInto};
+
+const BANK_SIZE: usize = 0x4000;
+
+/// The profile of one instruction, as returned by
+/// [`Gameboy::profile_report`](super::Gameboy::profile_report)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProfileEntry {
+    pub address: u16,
+    /// The ROM bank the instruction was in, or `None` if it isn't in ROM
+    pub bank: Option<u16>,
+    /// Number of times the instruction was fetched
+    pub hits: u32,
+    /// Total M-cycles spent executing the instruction
+    pub cycles: u64,
+    /// Fraction of all profiled M-cycles spent executing the instruction
+    pub cycle_share: f64,
+}
+
+/// The counters for one switchable ROM bank
+struct BankCounters {
+    hits: [u32; BANK_SIZE],
+    cycles: [u64; BANK_SIZE],
+}
+
+pub(crate) struct Profiler {
+    /// Indexed by address. $4000-$7FFF only counts bank 1, which is always mapped there if the
+    /// cartridge has no mapper.
+    hits: Box<[u32; 0x10000]>,
+    cycles: Box<[u64; 0x10000]>,
+    /// Counters for $4000-$7FFF while any bank other than 1 is mapped there
+    /// Ordered, so that the report breaks ties the same way every time
+    banks: BTreeMap<u16, Box<BankCounters>>,
+    /// The instruction that is executing, its bank, and the cycle it was fetched on
+    current: Option<(u16, u16, u64)>,
+}
+
+fn zeroed<T: Copy + Default, const N: usize>() -> Box<[T; N]> {
+    // Built on the heap, since the arrays are too big for the stack
+    vec![T::default(); N]
+        .into_boxed_slice()
+        .try_into()
+        .unwrap_or_else(|_| unreachable!())
+}
+
+fn is_banked(address: u16) -> bool {
+    (0x4000..0x8000).contains(&address)
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler {
+            hits: zeroed(),
+            cycles: zeroed(),
+            banks: BTreeMap::new(),
+            current: None,
+        }
+    }
+
+    fn counters(&mut self, address: u16, bank: u16) -> (&mut u32, &mut u64) {
+        if is_banked(address) && bank != 1 {
+            let counters = self.banks.entry(bank).or_insert_with(|| {
+                Box::new(BankCounters {
+                    hits: [0; BANK_SIZE],
+                    cycles: [0; BANK_SIZE],
+                })
+            });
+            let i = address as usize - 0x4000;
+            (&mut counters.hits[i], &mut counters.cycles[i])
+        } else {
+            let i = address as usize;
+            (&mut self.hits[i], &mut self.cycles[i])
+        }
+    }
+
+    /// Called on every opcode fetch. `cycle` is the T-cycle count at the start of the fetch, and
+    /// `bank` is the ROM bank mapped at $4000-$7FFF.
+    #[inline]
+    pub fn fetch(&mut self, address: u16, bank: u16, cycle: u64) {
+        // The previous instruction ran until this one was fetched
+        if let Some((address, bank, start)) = self.current.replace((address, bank, cycle)) {
+            *self.counters(address, bank).1 += (cycle - start) / 4;
+        }
+        *self.counters(address, bank).0 += 1;
+    }
+
+    /// Forget the instruction that is executing, so that it isn't counted when profiling resumes
+    pub fn pause(&mut self) {
+        self.current = None;
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    /// The `top_n` instructions that took the most M-cycles, in descending order
+    pub fn report(&self, top_n: usize) -> Vec<ProfileEntry> {
+        let flat = (0..=u16::MAX).map(|address| {
+            let bank = match address {
+                0x0000..=0x3FFF => Some(0),
+                0x4000..=0x7FFF => Some(1),
+                _ => None,
+            };
+            let i = address as usize;
+            (address, bank, self.hits[i], self.cycles[i])
+        });
+        let banked = self.banks.iter().flat_map(|(&bank, counters)| {
+            (0..BANK_SIZE).map(move |i| {
+                let address = 0x4000 + i as u16;
+                (address, Some(bank), counters.hits[i], counters.cycles[i])
+            })
+        });
+
+        let mut entries: Vec<_> = flat.chain(banked).filter(|e| e.2 > 0).collect();
+        let total: u64 = entries.iter().map(|e| e.3).sum();
+        entries.sort_by(|a, b| b.3.cmp(&a.3).then(b.2.cmp(&a.2)));
+        entries
+            .into_iter()
+            .take(top_n)
+            .map(|(address, bank, hits, cycles)| ProfileEntry {
+                address,
+                bank,
+                hits,
+                cycles,
+                cycle_share: if total == 0 {
+                    0.0
+                } else {
+                    cycles as f64 / total as f64
+                },
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,313 @@
+//! Time sources for cartridges with a real time clock
+
+use std::convert::TryInto;
+
+use crate::threading::dyn_maybe_send;
+
+/// Provides the current time to a cartridge's real time clock
+pub trait RtcSource {
+    /// Seconds elapsed since an arbitrary, fixed epoch
+    fn now(&mut self) -> u64;
+}
+
+/// A clock that is stuck at one time, for runs that must be reproducible
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FixedClock(pub u64);
+
+impl RtcSource for FixedClock {
+    fn now(&mut self) -> u64 {
+        self.0
+    }
+}
+
+/// The time source cartridges get when none is given. It is stuck at 0, so a run never depends
+/// on when it happens.
+pub(crate) fn default_source() -> Box<dyn_maybe_send!(RtcSource)> {
+    Box::<FixedClock>::default()
+}
+
+/// Reads the time from the host system clock. Only used if given to
+/// [`GameboyBuilder::rtc`](super::GameboyBuilder::rtc), together with
+/// [`RtcTimeSource::WallClock`] for the clock to follow it.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl RtcSource for SystemClock {
+    #[cfg(not(target_arch = "wasm32"))]
+    fn now(&mut self) -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0)
+    }
+
+    /// `SystemTime::now` panics on wasm32, so the clock never advances there. Web frontends
+    /// should provide their own [`RtcSource`] instead.
+    #[cfg(target_arch = "wasm32")]
+    fn now(&mut self) -> u64 {
+        0
+    }
+}
+
+/// T-cycles in one second of emulated time
+const CYCLES_PER_SECOND: u32 = 4_194_304;
+
+/// Length of the real time clock data some emulators append to save files: the live and latched
+/// registers as 32-bit words, then the time they were saved at as a 64-bit word
+pub const SAVE_FOOTER_LEN: usize = 48;
+
+/// An older version of the footer, with a 32-bit timestamp
+const SHORT_SAVE_FOOTER_LEN: usize = 44;
+
+/// What a cartridge's real time clock counts
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RtcTimeSource {
+    /// Follow the [`RtcSource`], so the clock keeps real time whatever speed the emulator runs at,
+    /// and catches up on the time spent between sessions when a save is loaded
+    WallClock,
+    /// Count emulated T-cycles, so the clock runs faster while fast-forwarding, stops while the
+    /// emulator is paused, and doesn't move between sessions. This is the default, since the
+    /// same inputs then always give the same run.
+    #[default]
+    EmulatedCycles,
+}
+
+/// The time held by an MBC3 real time clock, as the game sees it
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RtcRegisters {
+    pub seconds: u8,
+    pub minutes: u8,
+    pub hours: u8,
+    /// 9 bits
+    pub days: u16,
+    /// The clock is stopped
+    pub halted: bool,
+    /// The day counter has overflowed since the game last cleared this
+    pub carry: bool,
+}
+
+impl RtcRegisters {
+    /// Read register `select`, in $08-$0C
+    pub(crate) fn read(&self, select: u8) -> u8 {
+        match select {
+            0x08 => self.seconds,
+            0x09 => self.minutes,
+            0x0A => self.hours,
+            0x0B => self.days as u8,
+            _ => (self.days >> 8) as u8 | (self.halted as u8) << 6 | (self.carry as u8) << 7,
+        }
+    }
+
+    /// Write register `select`, in $08-$0C. Only as many bits as the counter has are kept.
+    pub(crate) fn write(&mut self, select: u8, data: u8) {
+        match select {
+            0x08 => self.seconds = data & 0x3F,
+            0x09 => self.minutes = data & 0x3F,
+            0x0A => self.hours = data & 0x1F,
+            0x0B => self.days = (self.days & 0x100) | data as u16,
+            _ => {
+                self.days = (self.days & 0xFF) | (data as u16 & 1) << 8;
+                self.halted = data & 0x40 != 0;
+                self.carry = data & 0x80 != 0;
+            }
+        }
+    }
+
+    /// Count `seconds` forward, unless the clock is halted
+    pub fn advance(&mut self, mut seconds: u64) {
+        if self.halted {
+            return;
+        }
+        // Counters set past their range count up to the top of their bits and wrap to 0 without
+        // carrying, which is done a second at a time until they're back in range
+        while seconds > 0 && (self.seconds > 59 || self.minutes > 59 || self.hours > 23) {
+            self.tick();
+            seconds -= 1;
+        }
+        let total = self.seconds as u64
+            + self.minutes as u64 * 60
+            + self.hours as u64 * 3600
+            + self.days as u64 * 86400
+            + seconds;
+        let days = total / 86400;
+        self.seconds = (total % 60) as u8;
+        self.minutes = (total / 60 % 60) as u8;
+        self.hours = (total / 3600 % 24) as u8;
+        self.days = (days % 512) as u16;
+        self.carry |= days >= 512;
+    }
+
+    fn tick(&mut self) {
+        self.seconds = (self.seconds + 1) & 0x3F;
+        if self.seconds != 60 {
+            return;
+        }
+        self.seconds = 0;
+        self.minutes = (self.minutes + 1) & 0x3F;
+        if self.minutes != 60 {
+            return;
+        }
+        self.minutes = 0;
+        self.hours = (self.hours + 1) & 0x1F;
+        if self.hours != 24 {
+            return;
+        }
+        self.hours = 0;
+        self.days = (self.days + 1) % 512;
+        self.carry |= self.days == 0;
+    }
+}
+
+/// Everything needed to save and restore a real time clock
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RtcState {
+    /// The counters, which keep running
+    pub live: RtcRegisters,
+    /// The copy of the counters the game reads, taken when it last latched them
+    pub latched: RtcRegisters,
+    /// The [`RtcSource`] time the live counters were last brought up to date
+    pub timestamp: u64,
+    /// T-cycles counted towards the next second with [`RtcTimeSource::EmulatedCycles`]
+    pub subsecond_cycles: u32,
+}
+
+impl RtcState {
+    /// The footer appended to save files, in the format shared by most emulators
+    pub fn save_footer(&self) -> [u8; SAVE_FOOTER_LEN] {
+        let mut footer = [0; SAVE_FOOTER_LEN];
+        // Edition 2018 arrays iterate by reference through `.into_iter()`
+        let registers = IntoIterator::into_iter([self.live, self.latched])
+            .flat_map(|registers| (0x08..=0x0C).map(move |select| registers.read(select)));
+        for (word, register) in footer.chunks_exact_mut(4).zip(registers) {
+            word[0] = register;
+        }
+        footer[40..].copy_from_slice(&self.timestamp.to_le_bytes());
+        footer
+    }
+
+    /// Read a footer made by [`RtcState::save_footer`], or its older 44 byte version. Returns
+    /// `None` if `footer` is neither length.
+    pub fn from_save_footer(footer: &[u8]) -> Option<Self> {
+        let timestamp = match footer.len() {
+            SAVE_FOOTER_LEN => u64::from_le_bytes(footer[40..].try_into().unwrap()),
+            SHORT_SAVE_FOOTER_LEN => u32::from_le_bytes(footer[40..].try_into().unwrap()) as u64,
+            _ => return None,
+        };
+        let mut registers = [RtcRegisters::default(); 2];
+        for (i, word) in footer[..40].chunks_exact(4).enumerate() {
+            registers[i / 5].write(0x08 + (i % 5) as u8, word[0]);
+        }
+        Some(RtcState {
+            live: registers[0],
+            latched: registers[1],
+            timestamp,
+            subsecond_cycles: 0,
+        })
+    }
+}
+
+/// The real time clock of a cartridge, and where it gets the time from
+pub(crate) struct Rtc {
+    source: Box<dyn_maybe_send!(RtcSource)>,
+    time_source: RtcTimeSource,
+    pub state: RtcState,
+}
+
+impl Rtc {
+    pub fn new(mut source: Box<dyn_maybe_send!(RtcSource)>) -> Self {
+        let timestamp = source.now();
+        Rtc {
+            source,
+            time_source: RtcTimeSource::default(),
+            state: RtcState {
+                timestamp,
+                ..Default::default()
+            },
+        }
+    }
+
+    pub fn time_source(&self) -> RtcTimeSource {
+        self.time_source
+    }
+
+    pub fn source_mut(&mut self) -> &mut Box<dyn_maybe_send!(RtcSource)> {
+        &mut self.source
+    }
+
+    /// Switching to [`RtcTimeSource::WallClock`] starts counting from now, so time that passed
+    /// while counting cycles isn't counted again
+    pub fn set_time_source(&mut self, time_source: RtcTimeSource) {
+        self.sync();
+        self.time_source = time_source;
+        self.state.timestamp = self.source.now();
+    }
+
+    /// Go back to `state`, counting from now rather than from when it was taken
+    pub fn restore(&mut self, state: RtcState) {
+        self.state = RtcState {
+            timestamp: self.source.now(),
+            ..state
+        };
+    }
+
+    /// Count one M-cycle of emulated time
+    #[inline]
+    pub fn clock(&mut self) {
+        if self.time_source == RtcTimeSource::EmulatedCycles && !self.state.live.halted {
+            self.state.subsecond_cycles += 4;
+            if self.state.subsecond_cycles >= CYCLES_PER_SECOND {
+                self.state.subsecond_cycles -= CYCLES_PER_SECOND;
+                self.state.live.advance(1);
+            }
+        }
+    }
+
+    /// Bring the live counters up to date with the wall clock
+    pub fn sync(&mut self) {
+        if self.time_source == RtcTimeSource::WallClock {
+            let now = self.source.now();
+            // A clock that has gone backwards doesn't take time off
+            self.state
+                .live
+                .advance(now.saturating_sub(self.state.timestamp));
+            self.state.timestamp = now;
+        }
+    }
+
+    pub fn latch(&mut self) {
+        self.sync();
+        self.state.latched = self.state.live;
+    }
+
+    pub fn write(&mut self, select: u8, data: u8) {
+        self.sync();
+        self.state.live.write(select, data);
+        // Writing the seconds resets the divider that counts them
+        if select == 0x08 {
+            self.state.subsecond_cycles = 0;
+        }
+    }
+
+    /// The footer for a save file made now
+    pub fn save_footer(&mut self) -> [u8; SAVE_FOOTER_LEN] {
+        self.sync();
+        self.state.timestamp = self.source.now();
+        self.state.save_footer()
+    }
+
+    /// Restore the clock from a save file's footer, counting the time since it was saved if
+    /// following the wall clock
+    pub fn load_save_footer(&mut self, footer: &[u8]) -> bool {
+        match RtcState::from_save_footer(footer) {
+            Some(state) => {
+                self.state = state;
+                if self.time_source == RtcTimeSource::EmulatedCycles {
+                    self.state.timestamp = self.source.now();
+                }
+                self.sync();
+                true
+            }
+            None => false,
+        }
+    }
+}
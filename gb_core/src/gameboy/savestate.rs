@@ -0,0 +1,804 @@
+//! Savestates: the whole machine as bytes, which can be loaded back later, including by later
+//! versions of the emulator.
+//!
+//! A state is [`MAGIC`], the [`FORMAT_VERSION`] byte, and then a series of chunks. Each chunk is
+//! a four byte ID, a version byte, the length of its contents as a 32-bit number, and then the
+//! contents. Every number is little endian.
+//!
+//! | ID     | Contents |
+//! |--------|----------|
+//! | `INFO` | When the state was taken, and the global checksum and title of the ROM |
+//! | `CPU ` | The CPU's registers and flags, its data and interrupt pins, and the T-cycle count |
+//! | `INT ` | IE and IF |
+//! | `TIMR` | The system counter that DIV is the top of, TIMA, TMA and TAC |
+//! | `PPU ` | The PPU's registers, OAM DMA, where the PPU is in its frame, and the frame count |
+//! | `VRAM` | Video RAM |
+//! | `OAM ` | Sprite attribute memory |
+//! | `WRAM` | Work RAM, then high RAM |
+//! | `MBC ` | The cartridge type byte, the mapper's registers and cartridge RAM |
+//! | `RTC ` | The cartridge's real time clock, for cartridges that have one |
+//! | `IO  ` | SB, SC, the rest of the serial transfer including the byte being shifted in, P1, and whether the boot ROM is mapped |
+//! | `CLK ` | How far the CPU is overclocked, the clock the timer follows, and the CPU's extra M-cycles left |
+//!
+//! Only `INFO` and `CPU ` have to be there. Any other chunk that is missing leaves its part of the
+//! machine as a power cycle does, with VRAM, OAM and work RAM cleared, and the overclocking
+//! settings as they are. Chunks with an ID that
+//! isn't known are skipped, so later versions can add chunks without breaking earlier ones.
+//!
+//! Each chunk has its own version. Changing what a chunk holds means raising its version by
+//! adding a `migrate_vN_to_vN+1` function to its list of migrations, which turns the layout before
+//! the change into the one after. A chunk from an older state goes through each migration from
+//! its version up before it is read, so only the current layout is ever read. A chunk with a
+//! version newer than this emulator knows can't be read, and the state is refused.
+//!
+//! `tests/fixtures/states` holds a state for each type of cartridge, written when each was added.
+//! They must keep loading, so none of them should ever be written again.
+//!
+//! States are only taken between two instructions, so [`Gameboy::save_state`] lets the CPU finish
+//! the instruction it is in first. A few things aren't saved:
+//! - The PPU is put back where it was in its frame by running it through the frame up to there
+//!   with the registers and memory from the state. Changes the game made partway through the
+//!   frame aren't repeated, so the rest of that frame may be drawn differently, and a state taken
+//!   on the first line after the LCD is turned on resumes on an ordinary line.
+//! - Sound isn't saved. The APU comes back as the boot ROM leaves it, silent until the game plays
+//!   something.
+//! - The Game Boy Camera's sensor, extra chips on the bus, IO hooks, and anything on the other end
+//!   of the link cable are left as they are.
+//! - A real time clock comes back with the time it had when the state was taken, and counts on
+//!   from there.
+
+use std::{collections::BTreeMap, convert::TryInto, num::NonZeroU8};
+
+use gb_cpu::{Cpu, CpuInputPins, CpuResumeFlags, CpuRunner, Registers};
+
+use super::{
+    apu::Apu,
+    cart::header::CartridgeHeader,
+    ppu::{
+        registers::{LCDC, STAT},
+        DmaState, FramePosition,
+    },
+    rtc::{RtcRegisters, RtcSource, RtcState, SystemClock},
+    serial::{self, SerialState},
+    system_counter::SystemCounter,
+    timer::TimerClock,
+    Gameboy,
+};
+
+/// The first bytes of every state
+pub const MAGIC: [u8; 8] = *b"GBSTATE\0";
+
+/// The version of the layout of the header and chunks, which doesn't change when a chunk does
+pub const FORMAT_VERSION: u8 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum StateError {
+    #[error("not a savestate")]
+    NotAState,
+    #[error("savestate format version {0} is newer than this version supports")]
+    UnsupportedVersion(u8),
+    #[error("savestate ends partway through a chunk")]
+    Truncated,
+    #[error("savestate has no {0} chunk")]
+    MissingChunk(String),
+    #[error("{chunk} chunk version {version} is newer than this version supports")]
+    NewerChunk { chunk: String, version: u8 },
+    #[error("invalid {chunk} chunk: {reason}")]
+    InvalidChunk { chunk: String, reason: &'static str },
+}
+
+/// What [`state_info`] reads from a state without loading it, so that frontends can list states
+/// and warn before loading one taken with a different game
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateInfo {
+    /// The [`FORMAT_VERSION`] the state was written with
+    pub version: u8,
+    /// The title in the header of the ROM, as [`CartridgeHeader::title`] reads it
+    pub title: String,
+    /// The global checksum in the header of the ROM
+    pub checksum: u16,
+    /// When the state was taken, in seconds since the Unix epoch, or 0 if the time wasn't known
+    pub timestamp: u64,
+}
+
+impl StateInfo {
+    /// Whether the state was taken with the ROM `gameboy` is running, going by the title and
+    /// checksum in its header
+    pub fn matches(&self, gameboy: &Gameboy) -> bool {
+        let (title, checksum) = rom_identity(gameboy);
+        self.title == title && self.checksum == checksum
+    }
+}
+
+/// Read the `INFO` chunk of a state
+pub fn state_info(bytes: &[u8]) -> Result<StateInfo, StateError> {
+    let (version, chunks) = read_chunks(bytes)?;
+    let mut info = required(&chunks, &INFO)?;
+    Ok(StateInfo {
+        version,
+        timestamp: info.u64()?,
+        checksum: info.u16()?,
+        title: String::from_utf8_lossy(info.rest()).into_owned(),
+    })
+}
+
+/// Turns the contents of a chunk from one version into the layout of the next
+type Migration = fn(&[u8]) -> Result<Vec<u8>, StateError>;
+
+/// A kind of chunk
+struct Chunk {
+    id: [u8; 4],
+    /// `migrations[0]` turns version 1 into version 2, and so on
+    migrations: &'static [Migration],
+}
+
+impl Chunk {
+    /// The version this version of the emulator writes
+    fn version(&self) -> u8 {
+        self.migrations.len() as u8 + 1
+    }
+
+    fn name(&self) -> String {
+        chunk_name(self.id)
+    }
+
+    fn invalid(&self, reason: &'static str) -> StateError {
+        StateError::InvalidChunk {
+            chunk: self.name(),
+            reason,
+        }
+    }
+
+    /// Bring the contents of a chunk written as `version` up to the current version
+    fn migrate(&self, version: u8, contents: &[u8]) -> Result<Vec<u8>, StateError> {
+        if version == 0 {
+            return Err(self.invalid("there is no version 0"));
+        }
+        if version > self.version() {
+            return Err(StateError::NewerChunk {
+                chunk: self.name(),
+                version,
+            });
+        }
+        let mut contents = contents.to_vec();
+        for migrate in &self.migrations[version as usize - 1..] {
+            contents = migrate(&contents)?;
+        }
+        Ok(contents)
+    }
+}
+
+const INFO: Chunk = Chunk {
+    id: *b"INFO",
+    migrations: &[],
+};
+const CPU: Chunk = Chunk {
+    id: *b"CPU ",
+    migrations: &[],
+};
+const INTERRUPTS: Chunk = Chunk {
+    id: *b"INT ",
+    migrations: &[],
+};
+const TIMER: Chunk = Chunk {
+    id: *b"TIMR",
+    migrations: &[],
+};
+const PPU: Chunk = Chunk {
+    id: *b"PPU ",
+    migrations: &[],
+};
+const VRAM: Chunk = Chunk {
+    id: *b"VRAM",
+    migrations: &[],
+};
+const OAM: Chunk = Chunk {
+    id: *b"OAM ",
+    migrations: &[],
+};
+const WRAM: Chunk = Chunk {
+    id: *b"WRAM",
+    migrations: &[],
+};
+const MAPPER: Chunk = Chunk {
+    id: *b"MBC ",
+    migrations: &[],
+};
+const RTC: Chunk = Chunk {
+    id: *b"RTC ",
+    migrations: &[],
+};
+const IO: Chunk = Chunk {
+    id: *b"IO  ",
+    migrations: &[migrate_io_v1_to_v2],
+};
+const CLOCK: Chunk = Chunk {
+    id: *b"CLK ",
+    migrations: &[],
+};
+
+const CHUNKS: [&Chunk; 12] = [
+    &INFO,
+    &CPU,
+    &INTERRUPTS,
+    &TIMER,
+    &PPU,
+    &VRAM,
+    &OAM,
+    &WRAM,
+    &MAPPER,
+    &RTC,
+    &IO,
+    &CLOCK,
+];
+
+/// Version 1 only had SB, SC and the M-cycles left in the transfer, with SB only changing once
+/// the byte sent back had arrived. Which bits were already shifted isn't known, so a transfer in
+/// progress is started again from the beginning. SC's fast clock bit wasn't kept, and read as set.
+fn migrate_io_v1_to_v2(contents: &[u8]) -> Result<Vec<u8>, StateError> {
+    let [sb, sc, cycles @ .., p1, boot_rom]: [u8; 6] = contents
+        .try_into()
+        .map_err(|_| IO.invalid("wrong length for version 1"))?;
+    let cycles = if sc & 0x81 == 0x81 {
+        serial::TRANSFER_CYCLES.to_le_bytes()
+    } else {
+        cycles
+    };
+    let mut contents = vec![sb, sc | 0x02];
+    contents.extend_from_slice(&cycles);
+    contents.extend_from_slice(&[sb, 0, 0, p1, boot_rom]);
+    Ok(contents)
+}
+
+fn chunk_name(id: [u8; 4]) -> String {
+    String::from_utf8_lossy(&id).trim_end().to_owned()
+}
+
+/// The contents of each chunk with a known ID, brought up to date
+type Chunks = BTreeMap<[u8; 4], Vec<u8>>;
+
+/// Split a state into its chunks, and return them with the format version
+fn read_chunks(bytes: &[u8]) -> Result<(u8, Chunks), StateError> {
+    if !bytes.starts_with(&MAGIC) {
+        return Err(StateError::NotAState);
+    }
+    let mut reader = Reader {
+        bytes: &bytes[MAGIC.len()..],
+        chunk: None,
+    };
+    let version = reader.u8()?;
+    if version > FORMAT_VERSION {
+        return Err(StateError::UnsupportedVersion(version));
+    }
+    let mut chunks = Chunks::new();
+    while !reader.bytes.is_empty() {
+        let id = reader.array()?;
+        let version = reader.u8()?;
+        let len = reader.u32()? as usize;
+        let contents = reader.take(len)?;
+        if let Some(chunk) = CHUNKS.iter().find(|chunk| chunk.id == id) {
+            chunks.insert(id, chunk.migrate(version, contents)?);
+        }
+    }
+    Ok((version, chunks))
+}
+
+fn optional<'a>(chunks: &'a Chunks, chunk: &'static Chunk) -> Option<Reader<'a>> {
+    chunks.get(&chunk.id).map(|bytes| Reader {
+        bytes,
+        chunk: Some(chunk),
+    })
+}
+
+fn required<'a>(chunks: &'a Chunks, chunk: &'static Chunk) -> Result<Reader<'a>, StateError> {
+    optional(chunks, chunk).ok_or_else(|| StateError::MissingChunk(chunk.name()))
+}
+
+/// Takes values off the front of a state, or of the contents of one of its chunks
+struct Reader<'a> {
+    bytes: &'a [u8],
+    chunk: Option<&'static Chunk>,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        if self.bytes.len() < len {
+            return Err(match self.chunk {
+                Some(chunk) => chunk.invalid("too short"),
+                None => StateError::Truncated,
+            });
+        }
+        let (front, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(front)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], StateError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, StateError> {
+        self.array().map(u16::from_le_bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, StateError> {
+        self.array().map(u32::from_le_bytes)
+    }
+
+    fn u64(&mut self) -> Result<u64, StateError> {
+        self.array().map(u64::from_le_bytes)
+    }
+
+    /// Eight flags packed into a byte, lowest bit first
+    fn flags(&mut self) -> Result<[bool; 8], StateError> {
+        let byte = self.u8()?;
+        Ok(std::array::from_fn(|bit| byte & (1 << bit) != 0))
+    }
+
+    fn rest(&mut self) -> &'a [u8] {
+        std::mem::take(&mut self.bytes)
+    }
+
+    /// Check that the contents of the chunk have all been read
+    fn finish(self) -> Result<(), StateError> {
+        match self.chunk {
+            Some(chunk) if !self.bytes.is_empty() => Err(chunk.invalid("too long")),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Pack up to eight flags into a byte, lowest bit first
+fn flags(flags: &[bool]) -> u8 {
+    flags
+        .iter()
+        .enumerate()
+        .fold(0, |byte, (bit, &flag)| byte | (flag as u8) << bit)
+}
+
+fn write_chunk(state: &mut Vec<u8>, chunk: &Chunk, contents: &[u8]) {
+    state.extend_from_slice(&chunk.id);
+    state.push(chunk.version());
+    state.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+    state.extend_from_slice(contents);
+}
+
+/// The title and global checksum in the header of the cartridge's ROM
+fn rom_identity(gameboy: &Gameboy) -> (String, u16) {
+    let header: Vec<u8> = (0..0x150).map(|addr| gameboy.cart.peek_rom(addr)).collect();
+    let title = CartridgeHeader::parse(&header)
+        .map(|header| header.title)
+        .unwrap_or_default();
+    (title, u16::from_be_bytes([header[0x14E], header[0x14F]]))
+}
+
+/// A state read from its chunks, and checked against the Gameboy it is being loaded into
+struct State<'a> {
+    cpu: Cpu,
+    /// The CPU has fetched the opcode on its data pins, and PC has moved past it
+    fetched: bool,
+    cpu_input: CpuInputPins,
+    cycles: u64,
+    interrupts: [u8; 2],
+    counter: u16,
+    timer: [u8; 3],
+    ppu: Option<PpuRegisters>,
+    vram: Option<&'a [u8]>,
+    oam: Option<&'a [u8]>,
+    wram: Option<&'a [u8]>,
+    mapper: Option<([u8; 4], &'a [u8])>,
+    rtc: Option<RtcState>,
+    io: Option<Io>,
+    /// The overclock factor, the timer's clock and the CPU's extra M-cycles left
+    clock: Option<(NonZeroU8, TimerClock, u8)>,
+}
+
+struct PpuRegisters {
+    lcdc: u8,
+    stat: u8,
+    /// SCY, SCX, LYC, WY, WX, BGP, OBP0, OBP1 and DMA
+    registers: [u8; 9],
+    dma_transfer: DmaState,
+    position: FramePosition,
+    frame_count: u64,
+}
+
+struct Io {
+    serial: SerialState,
+    p1: u8,
+    boot_rom: bool,
+}
+
+impl Gameboy {
+    pub(super) fn write_state(&mut self) -> Vec<u8> {
+        // A runner can only be made to pick up again between instructions
+        while !(self.fetched || self.cpu.cpu.halted || self.cpu.cpu.locked) {
+            self.tick();
+        }
+        let mut state = MAGIC.to_vec();
+        state.push(FORMAT_VERSION);
+
+        let (title, checksum) = rom_identity(self);
+        let mut info = SystemClock.now().to_le_bytes().to_vec();
+        info.extend_from_slice(&checksum.to_le_bytes());
+        info.extend_from_slice(title.as_bytes());
+        write_chunk(&mut state, &INFO, &info);
+
+        let cpu = &self.cpu.cpu;
+        let r = &cpu.registers;
+        let mut contents = vec![r.a, r.f.into(), r.b, r.c, r.d, r.e, r.h, r.l];
+        contents.extend_from_slice(&r.sp.to_le_bytes());
+        contents.extend_from_slice(&r.pc.to_le_bytes());
+        contents.push(flags(&[
+            cpu.ime,
+            cpu.halted,
+            cpu.ei_pending,
+            cpu.locked,
+            self.fetched,
+        ]));
+        let pins = &self.cpu_input;
+        contents.push(pins.data);
+        contents.push(flags(&[
+            pins.interrupt_40h,
+            pins.interrupt_48h,
+            pins.interrupt_50h,
+            pins.interrupt_58h,
+            pins.interrupt_60h,
+        ]));
+        contents.extend_from_slice(&self.cycles.to_le_bytes());
+        write_chunk(&mut state, &CPU, &contents);
+
+        let interrupts = [self.interrupt_enable, self.interrupt_request];
+        write_chunk(&mut state, &INTERRUPTS, &interrupts);
+
+        let mut contents = self.counter.value().to_le_bytes().to_vec();
+        contents.extend_from_slice(&self.timer.registers());
+        write_chunk(&mut state, &TIMER, &contents);
+
+        let ppu = &self.ppu;
+        let mut contents = vec![
+            ppu.lcdc.bits(),
+            ppu.stat.bits(),
+            ppu.ly,
+            ppu.scy,
+            ppu.scx,
+            ppu.lyc,
+            ppu.wy,
+            ppu.wx,
+            ppu.bgp,
+            ppu.obp0,
+            ppu.obp1,
+            ppu.dma,
+        ];
+        let DmaState { transfer, starting } = ppu.dma_transfer;
+        let (source, delay) = starting.unwrap_or_default();
+        contents.push(flags(&[transfer.is_some(), starting.is_some()]));
+        contents.extend_from_slice(&transfer.unwrap_or_default().to_le_bytes());
+        contents.extend_from_slice(&source.to_le_bytes());
+        contents.push(delay);
+        let position = ppu.frame_position();
+        contents.extend_from_slice(&position.dots.to_le_bytes());
+        contents.push(position.window_line);
+        contents.push(flags(&[position.wy_latch, position.lcd_was_disabled]));
+        contents.extend_from_slice(&ppu.frame_count.to_le_bytes());
+        write_chunk(&mut state, &PPU, &contents);
+
+        let vram = [&ppu.tile_data[..], &ppu.bg_map_1, &ppu.bg_map_2].concat();
+        write_chunk(&mut state, &VRAM, &vram);
+        write_chunk(&mut state, &OAM, &ppu.oam);
+        let wram: Vec<u8> = (0xC000..=0xDFFF)
+            .chain(0xFF80..=0xFFFE)
+            .map(|addr| self.memory[addr])
+            .collect();
+        write_chunk(&mut state, &WRAM, &wram);
+
+        let mut contents = vec![self.cart.peek_rom(0x147)];
+        contents.extend_from_slice(&self.cart.mapper_registers());
+        contents.extend_from_slice(self.cart.ram().unwrap_or_default());
+        write_chunk(&mut state, &MAPPER, &contents);
+
+        if let Some(rtc) = self.cart.rtc_state() {
+            let mut contents: Vec<u8> = [rtc.live, rtc.latched]
+                .iter()
+                .flat_map(|registers| (0x08..=0x0C).map(move |select| registers.read(select)))
+                .collect();
+            contents.extend_from_slice(&rtc.subsecond_cycles.to_le_bytes());
+            contents.extend_from_slice(&rtc.timestamp.to_le_bytes());
+            write_chunk(&mut state, &RTC, &contents);
+        }
+
+        let serial = self.serial.registers();
+        let mut contents = vec![serial.sb, serial.sc];
+        contents.extend_from_slice(&serial.cycles_remaining.to_le_bytes());
+        contents.extend_from_slice(&[
+            serial.sent,
+            serial.incoming.is_some() as u8,
+            serial.incoming.unwrap_or(0),
+        ]);
+        contents.push(self.joypad.p1());
+        contents.push(self.boot_rom.is_some() as u8);
+        write_chunk(&mut state, &IO, &contents);
+
+        let clock = [
+            self.overclock.get(),
+            (self.timer_clock == TimerClock::Cpu) as u8,
+            self.extra_cycles,
+        ];
+        write_chunk(&mut state, &CLOCK, &clock);
+
+        state
+    }
+
+    pub(super) fn read_state(&mut self, bytes: &[u8]) -> Result<(), StateError> {
+        let (_, chunks) = read_chunks(bytes)?;
+        let state = self.check_state(&chunks)?;
+        self.restart_hardware();
+        self.apply_state(state);
+        Ok(())
+    }
+
+    /// Read every chunk, making sure the whole state can be loaded before anything is changed
+    fn check_state<'a>(&self, chunks: &'a Chunks) -> Result<State<'a>, StateError> {
+        let mut cpu = required(chunks, &CPU)?;
+        let registers = Registers {
+            a: cpu.u8()?,
+            f: cpu.u8()?.into(),
+            b: cpu.u8()?,
+            c: cpu.u8()?,
+            d: cpu.u8()?,
+            e: cpu.u8()?,
+            h: cpu.u8()?,
+            l: cpu.u8()?,
+            sp: cpu.u16()?,
+            pc: cpu.u16()?,
+        };
+        let [ime, halted, ei_pending, locked, fetched, ..] = cpu.flags()?;
+        let data = cpu.u8()?;
+        let [interrupt_40h, interrupt_48h, interrupt_50h, interrupt_58h, interrupt_60h, ..] =
+            cpu.flags()?;
+        let mut state = State {
+            cpu: Cpu {
+                registers,
+                ime,
+                halted,
+                ei_pending,
+                locked,
+            },
+            fetched,
+            cpu_input: CpuInputPins {
+                data,
+                interrupt_40h,
+                interrupt_48h,
+                interrupt_50h,
+                interrupt_58h,
+                interrupt_60h,
+            },
+            cycles: cpu.u64()?,
+            interrupts: [0; 2],
+            counter: 0,
+            timer: [0; 3],
+            ppu: None,
+            vram: None,
+            oam: None,
+            wram: None,
+            mapper: None,
+            rtc: None,
+            io: None,
+            clock: None,
+        };
+        cpu.finish()?;
+        if fetched && (halted || locked) {
+            return Err(CPU.invalid("an opcode can't have been fetched while halted"));
+        }
+
+        if let Some(mut interrupts) = optional(chunks, &INTERRUPTS) {
+            state.interrupts = interrupts.array()?;
+            interrupts.finish()?;
+        }
+        if let Some(mut timer) = optional(chunks, &TIMER) {
+            state.counter = timer.u16()?;
+            state.timer = timer.array()?;
+            timer.finish()?;
+        }
+
+        if let Some(mut ppu) = optional(chunks, &PPU) {
+            let [lcdc, stat, _ly] = ppu.array()?;
+            let registers = ppu.array()?;
+            let [transfer, starting, ..] = ppu.flags()?;
+            let transfer = Some(ppu.u16()?).filter(|_| transfer);
+            let starting = Some((ppu.u16()?, ppu.u8()?)).filter(|_| starting);
+            let dots = ppu.u32()?;
+            let window_line = ppu.u8()?;
+            let [wy_latch, lcd_was_disabled, ..] = ppu.flags()?;
+            state.ppu = Some(PpuRegisters {
+                lcdc,
+                stat,
+                registers,
+                dma_transfer: DmaState { transfer, starting },
+                position: FramePosition {
+                    dots,
+                    wy_latch,
+                    window_line,
+                    lcd_was_disabled,
+                },
+                frame_count: ppu.u64()?,
+            });
+            ppu.finish()?;
+            if dots as usize >= super::ppu::consts::FRAME_T_CYCLES {
+                return Err(PPU.invalid("position is past the end of the frame"));
+            }
+        }
+
+        for (chunk, memory, len) in [
+            (&VRAM, &mut state.vram, 0x2000),
+            (&OAM, &mut state.oam, 0xA0),
+            (&WRAM, &mut state.wram, 0x207F),
+        ] {
+            if let Some(mut reader) = optional(chunks, chunk) {
+                *memory = Some(reader.take(len)?);
+                reader.finish()?;
+            }
+        }
+
+        if let Some(mut mapper) = optional(chunks, &MAPPER) {
+            if mapper.u8()? != self.cart.peek_rom(0x147) {
+                return Err(MAPPER.invalid("the state is for a different type of cartridge"));
+            }
+            let registers = mapper.array()?;
+            let ram = mapper.rest();
+            if ram.len() != self.cart.ram().map_or(0, <[u8]>::len) {
+                return Err(MAPPER.invalid("cartridge RAM is a different size"));
+            }
+            state.mapper = Some((registers, ram));
+        }
+
+        if let Some(mut rtc) = optional(chunks, &RTC) {
+            let mut registers = [RtcRegisters::default(); 2];
+            for registers in &mut registers {
+                for select in 0x08..=0x0C {
+                    registers.write(select, rtc.u8()?);
+                }
+            }
+            state.rtc = Some(RtcState {
+                live: registers[0],
+                latched: registers[1],
+                subsecond_cycles: rtc.u32()?,
+                timestamp: rtc.u64()?,
+            });
+            rtc.finish()?;
+        }
+
+        if let Some(mut io) = optional(chunks, &IO) {
+            let [sb, sc] = io.array()?;
+            let cycles_remaining = io.u16()?;
+            let [sent, has_incoming, incoming] = io.array()?;
+            state.io = Some(Io {
+                serial: SerialState {
+                    sb,
+                    sc,
+                    cycles_remaining,
+                    sent,
+                    incoming: (has_incoming != 0).then_some(incoming),
+                },
+                p1: io.u8()?,
+                boot_rom: io.u8()? != 0,
+            });
+            io.finish()?;
+            if state.io.as_ref().unwrap().boot_rom && self.boot_rom_image.is_none() {
+                return Err(IO.invalid("the boot ROM is mapped, but there isn't one"));
+            }
+        }
+
+        if let Some(mut clock) = optional(chunks, &CLOCK) {
+            let [factor, timer_clock, extra_cycles] = clock.array()?;
+            clock.finish()?;
+            let factor =
+                NonZeroU8::new(factor).ok_or_else(|| CLOCK.invalid("the overclock factor is 0"))?;
+            let timer_clock = match timer_clock {
+                0 => TimerClock::Stock,
+                1 => TimerClock::Cpu,
+                _ => return Err(CLOCK.invalid("unknown timer clock")),
+            };
+            if extra_cycles >= factor.get() {
+                return Err(CLOCK.invalid("more extra M-cycles left than the CPU gets"));
+            }
+            state.clock = Some((factor, timer_clock, extra_cycles));
+        }
+        Ok(state)
+    }
+
+    /// Load a state that has been checked, onto hardware that has just been restarted
+    fn apply_state(&mut self, state: State) {
+        let mut cpu = state.cpu;
+        if state.fetched {
+            cpu.registers.pc = cpu.registers.pc.wrapping_sub(1);
+        }
+        let flags = CpuResumeFlags {
+            halted: cpu.halted,
+            locked: cpu.locked,
+            ime_scheduled: cpu.ei_pending,
+        };
+        self.cpu = CpuRunner::from_state(cpu, flags);
+        if state.fetched {
+            // Fetch the opcode again, which leaves the new runner where the old one was: waiting
+            // for the opcode on its data pins. Nothing is on the bus, so no time passes.
+            self.cpu.clock(CpuInputPins::default());
+        }
+        self.fetched = state.fetched;
+        self.cpu_input = state.cpu_input;
+        self.cycles = state.cycles;
+        [self.interrupt_enable, self.interrupt_request] = state.interrupts;
+        self.counter = SystemCounter::starting_at(state.counter);
+        self.timer.set_registers(state.timer);
+
+        let vram = state.vram.unwrap_or(&[0; 0x2000]);
+        let ppu = &mut self.ppu;
+        let (tile_data, bg_maps) = vram.split_at(ppu.tile_data.len());
+        let (bg_map_1, bg_map_2) = bg_maps.split_at(ppu.bg_map_1.len());
+        ppu.tile_data.copy_from_slice(tile_data);
+        ppu.bg_map_1.copy_from_slice(bg_map_1);
+        ppu.bg_map_2.copy_from_slice(bg_map_2);
+        ppu.oam.copy_from_slice(state.oam.unwrap_or(&[0; 0xA0]));
+        if let Some(registers) = state.ppu {
+            let [scy, scx, lyc, wy, wx, bgp, obp0, obp1, dma] = registers.registers;
+            ppu.lcdc = LCDC::from_bits_retain(registers.lcdc);
+            // LY, the mode and the LYC=LY flag follow from the position
+            ppu.stat = STAT::from_bits_truncate(registers.stat & 0x78);
+            ppu.scy = scy;
+            ppu.scx = scx;
+            ppu.lyc = lyc;
+            ppu.wy = wy;
+            ppu.wx = wx;
+            ppu.bgp = bgp;
+            ppu.obp0 = obp0;
+            ppu.obp1 = obp1;
+            ppu.dma = dma;
+            ppu.dma_transfer = registers.dma_transfer;
+            ppu.refresh_registers();
+            ppu.restore_position(registers.position);
+            ppu.frame_count = registers.frame_count;
+        }
+
+        let wram = state.wram.unwrap_or(&[0; 0x207F]);
+        let addrs = (0xC000..=0xDFFF).chain(0xFF80..=0xFFFE);
+        for (addr, &byte) in addrs.zip(wram) {
+            self.memory[addr] = byte;
+        }
+
+        if let Some((registers, ram)) = state.mapper {
+            self.cart.set_mapper_registers(registers);
+            for (offset, &byte) in ram.iter().enumerate() {
+                self.cart.set_ram_byte(offset, byte);
+            }
+        }
+        if let Some(rtc) = state.rtc {
+            self.cart.set_rtc_state(rtc);
+        }
+
+        let boot_rom = match state.io {
+            Some(io) => {
+                self.serial.set_registers(io.serial);
+                self.joypad.set_p1(io.p1);
+                io.boot_rom
+            }
+            None => false,
+        };
+        if !boot_rom {
+            self.boot_rom = None;
+            self.ppu.opri_locked = true;
+            self.apu = Apu::after_boot();
+        }
+        if let Some((factor, timer_clock, extra_cycles)) = state.clock {
+            self.overclock = factor;
+            self.timer_clock = timer_clock;
+            self.extra_cycles = extra_cycles;
+        }
+        self.scheduler.wake_all();
+        // The frame and cycle counts have gone back to those in the state
+        self.perf.reset(self.ppu.frame_count, self.cycles);
+    }
+}
@@ -0,0 +1,66 @@
+//! Decides which chips to clock on each M-cycle.
+//!
+//! Most chips only do anything when the bus accesses them, or at times they can work out in
+//! advance, like the timer on the edges of the system counter. After clocking a chip, the
+//! [`Gameboy`](super::Gameboy) asks it for [`Chip::next_event`], and until that cycle comes it
+//! only clocks the chip for accesses to the addresses it claims. The PPU isn't scheduled, since it
+//! has something to do on every M-cycle.
+//!
+//! This only saves clocking chips with nothing to do. The Gameboy is still stepped one M-cycle at
+//! a time, as the CPU runs on every M-cycle that OAM DMA doesn't pause it. A skipped chip needs no
+//! catching up afterwards, since it promised that nothing would happen until its next event.
+
+use super::Chip;
+
+/// No chip claims the address
+const UNCLAIMED: u16 = u16::MAX;
+
+pub(crate) struct Scheduler {
+    enabled: bool,
+    /// For each chip after the PPU, the cycle it has to be clocked on next, as a count of
+    /// T-cycles like [`ClockContext::cycles`](super::ClockContext::cycles)
+    pub(crate) wake: Vec<u64>,
+    /// The index into `wake` of the chip claiming each address. Addresses claimed by several
+    /// chips clock all of them anyway, so this only has to hold one.
+    owners: Box<[u16]>,
+}
+
+impl Scheduler {
+    /// A scheduler for `chips`, which are every chip on the bus after the PPU, in the order they
+    /// are clocked
+    pub(crate) fn new<'a>(chips: impl Iterator<Item = &'a dyn Chip>) -> Self {
+        let mut owners = vec![UNCLAIMED; 0x10000].into_boxed_slice();
+        let mut count = 0;
+        for (i, chip) in chips.enumerate() {
+            for addr in chip.chip_select().into_iter().flatten() {
+                owners[addr as usize] = i as u16;
+            }
+            count += 1;
+        }
+        Scheduler {
+            enabled: true,
+            wake: vec![0; count],
+            owners,
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub(crate) fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.wake_all();
+    }
+
+    /// The index of the chip that claims `addr`, which is clocked whether or not it is due
+    pub(crate) fn owner(&self, addr: u16) -> usize {
+        self.owners[addr as usize] as usize
+    }
+
+    /// Clock every chip on the next M-cycle, for when something has changed their state behind
+    /// the scheduler's back, like stepping back through the journal
+    pub(crate) fn wake_all(&mut self) {
+        self.wake.fill(0);
+    }
+}
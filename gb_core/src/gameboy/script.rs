@@ -0,0 +1,181 @@
+//! Drive the emulator from [rhai](https://rhai.rs) scripts, for automated gameplay and tests.
+//!
+//! A script runs once to set itself up, registering closures with `on_frame` that are called
+//! after every frame. The functions available to scripts are:
+//!
+//! | Function | Description |
+//! |---|---|
+//! | `on_frame(f)` | Call `f` after every frame |
+//! | `peek(addr)`, `poke(addr, value)` | See [`Gameboy::peek`] and [`Gameboy::poke`] |
+//! | `press(button)`, `release(button)` | Press or release `"A"`, `"B"`, `"Start"`, `"Select"`, `"Up"`, `"Down"`, `"Left"` or `"Right"` |
+//! | `frame_count()` | Number of frames completed since power on |
+//! | `screenshot_hash()` | A hash of the last completed frame, to compare against known screens |
+//! | `stop(result)` | Stop running immediately, and return `result` from [`ScriptHost::run`] |
+
+use std::{cell::RefCell, convert::TryFrom, rc::Rc};
+
+use rhai::{Dynamic, Engine, EvalAltResult, FnPtr, ParseError, Position};
+
+use super::{joypad::Button, Gameboy};
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// Why [`ScriptHost::run`] returned
+#[derive(Debug, Clone)]
+pub enum ScriptOutcome {
+    /// The script called `stop` with this value
+    Stopped(Dynamic),
+    /// The frame limit was reached first
+    TimedOut,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ScriptError {
+    #[error("failed to compile script: {0}")]
+    Parse(#[from] ParseError),
+    #[error("script error: {0}")]
+    Runtime(#[from] Box<EvalAltResult>),
+}
+
+/// Owns a [`Gameboy`], and runs scripts against it
+pub struct ScriptHost {
+    engine: Engine,
+    gameboy: Rc<RefCell<Gameboy>>,
+    callbacks: Rc<RefCell<Vec<FnPtr>>>,
+}
+
+fn button(name: &str) -> ScriptResult<Button> {
+    Ok(match name {
+        "A" => Button::A,
+        "B" => Button::B,
+        "Start" => Button::Start,
+        "Select" => Button::Select,
+        "Up" => Button::Up,
+        "Down" => Button::Down,
+        "Left" => Button::Left,
+        "Right" => Button::Right,
+        _ => return Err(format!("unknown button {:?}", name).into()),
+    })
+}
+
+fn address(addr: i64) -> ScriptResult<u16> {
+    u16::try_from(addr).map_err(|_| format!("address out of range: {}", addr).into())
+}
+
+/// 64-bit FNV-1a, which unlike the standard library's hasher is guaranteed to stay the same
+/// between Rust versions
+fn fnv1a(bytes: impl Iterator<Item = u8>) -> u64 {
+    bytes.fold(0xCBF2_9CE4_8422_2325, |hash, b| {
+        (hash ^ b as u64).wrapping_mul(0x0100_0000_01B3)
+    })
+}
+
+impl ScriptHost {
+    pub fn new(gameboy: Gameboy) -> Self {
+        let gameboy = Rc::new(RefCell::new(gameboy));
+        let callbacks = Rc::new(RefCell::new(Vec::new()));
+        let mut engine = Engine::new();
+
+        let cb = callbacks.clone();
+        engine.register_fn("on_frame", move |f: FnPtr| cb.borrow_mut().push(f));
+
+        let gb = gameboy.clone();
+        engine.register_fn("peek", move |addr: i64| -> ScriptResult<i64> {
+            Ok(gb.borrow().peek(address(addr)?) as i64)
+        });
+        let gb = gameboy.clone();
+        engine.register_fn("poke", move |addr: i64, value: i64| -> ScriptResult<()> {
+            let value = u8::try_from(value).map_err(|_| format!("not a byte: {}", value))?;
+            gb.borrow_mut().poke(address(addr)?, value);
+            Ok(())
+        });
+
+        let gb = gameboy.clone();
+        engine.register_fn("press", move |name: &str| -> ScriptResult<()> {
+            gb.borrow_mut().joypad.press(button(name)?);
+            Ok(())
+        });
+        let gb = gameboy.clone();
+        engine.register_fn("release", move |name: &str| -> ScriptResult<()> {
+            gb.borrow_mut().joypad.release(button(name)?);
+            Ok(())
+        });
+
+        let gb = gameboy.clone();
+        engine.register_fn("frame_count", move || gb.borrow().ppu.frame_count as i64);
+        let gb = gameboy.clone();
+        engine.register_fn("screenshot_hash", move || {
+            let gameboy = gb.borrow();
+            let pixels = gameboy.ppu.frame.colors().flat_map(|p| p.to_le_bytes());
+            fnv1a(pixels) as i64
+        });
+
+        // Terminating the script is the only way to unwind out of a callback immediately
+        engine.register_fn("stop", |result: Dynamic| -> ScriptResult<()> {
+            Err(EvalAltResult::ErrorTerminated(result, Position::NONE).into())
+        });
+
+        ScriptHost {
+            engine,
+            gameboy,
+            callbacks,
+        }
+    }
+
+    pub fn gameboy(&self) -> std::cell::RefMut<'_, Gameboy> {
+        self.gameboy.borrow_mut()
+    }
+
+    pub fn into_gameboy(self) -> Gameboy {
+        let ScriptHost {
+            engine, gameboy, ..
+        } = self;
+        // The engine's functions hold the other references
+        drop(engine);
+        match Rc::try_unwrap(gameboy) {
+            Ok(gameboy) => gameboy.into_inner(),
+            Err(_) => unreachable!("a script function outlived the engine"),
+        }
+    }
+
+    /// Run `script`, then run frames and call the script's `on_frame` callbacks until it calls
+    /// `stop` or `max_frames` frames have passed. Callbacks from previous runs are discarded.
+    pub fn run(&mut self, script: &str, max_frames: u64) -> Result<ScriptOutcome, ScriptError> {
+        self.callbacks.borrow_mut().clear();
+        let ast = self.engine.compile(script)?;
+        if let Some(outcome) = stopped(self.engine.run_ast(&ast))? {
+            return Ok(outcome);
+        }
+
+        for _ in 0..max_frames {
+            self.gameboy.borrow_mut().run_frames(1);
+            let callbacks = self.callbacks.borrow().clone();
+            for callback in callbacks {
+                let result = callback.call::<Dynamic>(&self.engine, &ast, ()).map(drop);
+                if let Some(outcome) = stopped(result)? {
+                    return Ok(outcome);
+                }
+            }
+        }
+        Ok(ScriptOutcome::TimedOut)
+    }
+}
+
+/// Turn a call to `stop` into a [`ScriptOutcome`]
+fn stopped(result: ScriptResult<()>) -> Result<Option<ScriptOutcome>, ScriptError> {
+    let error = match result {
+        Ok(()) => return Ok(None),
+        Err(error) => error,
+    };
+    // Errors raised inside script functions and closures are wrapped once per call
+    let mut inner = &*error;
+    while let EvalAltResult::ErrorInFunctionCall(_, _, e, _) = inner {
+        inner = e;
+    }
+    match inner {
+        EvalAltResult::ErrorTerminated(result, _) => {
+            Ok(Some(ScriptOutcome::Stopped(result.clone())))
+        }
+        _ => Err(error.into()),
+    }
+}
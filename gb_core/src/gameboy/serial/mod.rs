@@ -0,0 +1,267 @@
+pub mod printer;
+
+use gb_cpu::CpuOutputPins;
+
+use super::{
+    events::{Event, EventLog, EventMask},
+    logging, Chip, ClockContext,
+};
+use crate::threading::dyn_maybe_send;
+
+/// A device on the other end of the link cable
+pub trait SerialConnection {
+    /// Exchange one byte with the connected device, returning the byte it sends back.
+    ///
+    /// Called once a transfer driven by the internal clock has shifted out all 8 bits.
+    fn exchange(&mut self, byte: u8) -> u8;
+
+    /// Start exchanging one byte, returning the byte sent back if it is already known. If not,
+    /// the transfer stays in progress, and [`SerialConnection::poll_reply`] is called every
+    /// M-cycle until it returns the byte, like a link cable with some latency.
+    ///
+    /// This is what the serial port calls. By default, it calls [`SerialConnection::exchange`].
+    fn begin_exchange(&mut self, byte: u8) -> Option<u8> {
+        Some(self.exchange(byte))
+    }
+
+    /// The byte sent back for the exchange started by the last call to
+    /// [`SerialConnection::begin_exchange`], if it has arrived
+    fn poll_reply(&mut self) -> Option<u8> {
+        None
+    }
+
+    /// Called every M-cycle while a transfer waits on the external clock, which this device
+    /// drives. Once it has clocked all 8 bits, it returns the byte it sent, and receives `byte`,
+    /// the contents of SB, in exchange.
+    ///
+    /// By default the device never drives the clock, like an unplugged cable, so the transfer
+    /// stays in progress for as long as the game is willing to wait.
+    fn drive_exchange(&mut self, _byte: u8) -> Option<u8> {
+        None
+    }
+}
+
+/// Behaves like an unplugged link cable. Transfers using the internal clock shift in 1s, and
+/// transfers using the external clock never complete.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Disconnected;
+
+impl SerialConnection for Disconnected {
+    fn exchange(&mut self, _byte: u8) -> u8 {
+        0xFF
+    }
+}
+
+/// Number of M-cycles taken to shift out one bit at 8192Hz
+const BIT_CYCLES: u16 = 128;
+/// Number of M-cycles taken to shift out a whole byte at 8192Hz
+pub(crate) const TRANSFER_CYCLES: u16 = 8 * BIT_CYCLES;
+
+/// SC bits: a transfer is in progress, the CGB's fast clock, and the internal clock. The fast
+/// clock is only kept to be read back, as there is no CGB to run at its speed.
+const SC_TRANSFER: u8 = 0x80;
+const SC_FAST: u8 = 0x02;
+const SC_INTERNAL: u8 = 0x01;
+
+pub struct Serial {
+    sb: u8,
+    sc: u8,
+    /// M-cycles left until a transfer using the internal clock has shifted every bit, or 0 if
+    /// there is none
+    cycles_remaining: u16,
+    /// What SB held when the transfer started
+    sent: u8,
+    /// The byte the connection sent back, with the bits still to be shifted into SB at the top,
+    /// or `None` while the connection hasn't sent it yet
+    incoming: Option<u8>,
+    connection: Box<dyn_maybe_send!(SerialConnection)>,
+    pub(crate) events: EventLog,
+}
+
+/// Everything about the serial port apart from the connection, see [`Serial::registers`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct SerialState {
+    pub sb: u8,
+    pub sc: u8,
+    pub cycles_remaining: u16,
+    pub sent: u8,
+    pub incoming: Option<u8>,
+}
+
+impl Serial {
+    pub fn new(connection: Box<dyn_maybe_send!(SerialConnection)>) -> Self {
+        Serial {
+            sb: 0,
+            // Reads back as set until written, like every unused bit on the DMG
+            sc: SC_FAST,
+            cycles_remaining: 0,
+            sent: 0,
+            incoming: None,
+            connection,
+            events: EventLog::default(),
+        }
+    }
+
+    /// Replace the device on the other end of the link cable. A transfer waiting for a reply from
+    /// the old device is started again with the new one.
+    pub fn connect(&mut self, connection: Box<dyn_maybe_send!(SerialConnection)>) {
+        self.connection = connection;
+        self.resend();
+    }
+
+    /// Start the exchange again if the transfer in progress is waiting for the byte sent back
+    fn resend(&mut self) {
+        if self.cycles_remaining > 0 && self.incoming.is_none() {
+            self.incoming = self.connection.begin_exchange(self.sent);
+        }
+    }
+
+    /// Clear SB and SC and abandon any transfer, keeping the connection
+    pub(crate) fn power_cycle(&mut self) {
+        let connection = std::mem::replace(&mut self.connection, Box::new(Disconnected));
+        *self = Serial::new(connection);
+    }
+
+    /// SB, SC and the transfer in progress
+    pub(crate) fn registers(&self) -> SerialState {
+        SerialState {
+            sb: self.sb,
+            sc: self.sc,
+            cycles_remaining: self.cycles_remaining,
+            sent: self.sent,
+            incoming: self.incoming,
+        }
+    }
+
+    /// Put back what [`Serial::registers`] returned. The connection isn't part of it, so a
+    /// transfer that was waiting for the byte sent back is started again with the connection.
+    pub(crate) fn set_registers(&mut self, state: SerialState) {
+        self.sb = state.sb;
+        self.sc = state.sc;
+        self.cycles_remaining = state.cycles_remaining;
+        self.sent = state.sent;
+        self.incoming = state.incoming;
+        self.resend();
+    }
+
+    /// Shift the next bit of the byte sent back into SB, and the top bit of SB out
+    fn shift(&mut self, incoming: u8) {
+        self.sb = (self.sb << 1) | (incoming >> 7);
+        self.incoming = Some(incoming << 1);
+    }
+
+    /// Finish a transfer, once all 8 bits have been shifted
+    fn complete(&mut self, interrupt_request: &mut u8) {
+        let (sent, received) = (self.sent, self.sb);
+        self.events
+            .emit(EventMask::SERIAL_BYTE, || Event::SerialByte(sent));
+        log::debug!(
+            target: logging::SERIAL,
+            "sent ${:02X}, received ${:02X}",
+            sent,
+            received
+        );
+        self.incoming = None;
+        self.sc &= !SC_TRANSFER;
+        // Set interrupt 58h
+        *interrupt_request |= 1 << 3;
+    }
+}
+
+impl Default for Serial {
+    fn default() -> Self {
+        Self::new(Box::new(Disconnected))
+    }
+}
+
+impl std::fmt::Debug for Serial {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Serial")
+            .field("SB", &self.sb)
+            .field("SC", &self.sc)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Chip for Serial {
+    fn clock(
+        &mut self,
+        input: CpuOutputPins,
+        data: &mut u8,
+        interrupt_request: &mut u8,
+        ctx: &ClockContext,
+    ) {
+        match input {
+            CpuOutputPins::Write {
+                addr: 0xFF01,
+                data: v,
+            } => self.sb = v,
+            CpuOutputPins::Read { addr: 0xFF01 } => *data = self.sb,
+
+            CpuOutputPins::Write {
+                addr: 0xFF02,
+                data: v,
+            } => {
+                self.sc = v & (SC_TRANSFER | SC_FAST | SC_INTERNAL);
+                self.cycles_remaining = 0;
+                self.incoming = None;
+                // Only a transfer using the internal clock starts on its own. One using the
+                // external clock waits for the other end to drive it.
+                if self.sc & (SC_TRANSFER | SC_INTERNAL) == SC_TRANSFER | SC_INTERNAL {
+                    self.cycles_remaining = TRANSFER_CYCLES;
+                    self.sent = self.sb;
+                    self.incoming = self.connection.begin_exchange(self.sb);
+                }
+            }
+            CpuOutputPins::Read { addr: 0xFF02 } => *data = self.sc | 0x7C,
+            _ => (),
+        };
+
+        // Transfers keep to the stock clock
+        if ctx.extra_cycle {
+            return;
+        }
+
+        if self.cycles_remaining > 0 {
+            // The byte sent back may take a while to arrive. Until it does, the clock is held
+            // before the next bit is shifted.
+            if self.incoming.is_none() {
+                self.incoming = self.connection.poll_reply();
+            }
+            let next = self.cycles_remaining - 1;
+            if next % BIT_CYCLES != 0 {
+                self.cycles_remaining = next;
+            } else if let Some(incoming) = self.incoming {
+                self.cycles_remaining = next;
+                self.shift(incoming);
+                if next == 0 {
+                    self.complete(interrupt_request);
+                }
+            }
+        } else if self.sc & (SC_TRANSFER | SC_INTERNAL) == SC_TRANSFER {
+            if let Some(received) = self.connection.drive_exchange(self.sb) {
+                self.sent = self.sb;
+                self.sb = received;
+                self.complete(interrupt_request);
+            }
+        }
+    }
+
+    fn chip_select(&self) -> Vec<std::ops::RangeInclusive<u16>> {
+        vec![0xFF01..=0xFF02]
+    }
+
+    fn name(&self) -> &'static str {
+        "serial"
+    }
+
+    /// Transfers are clocked through every M-cycle, so that the M-cycles left stay up to date,
+    /// and so that the other end can drive a transfer using the external clock at any time
+    fn next_event(&self, ctx: &ClockContext) -> u64 {
+        if self.sc & SC_TRANSFER != 0 {
+            ctx.cycles + 4
+        } else {
+            u64::MAX
+        }
+    }
+}
@@ -0,0 +1,371 @@
+//! The Game Boy Printer, which prints what games send it over the link cable.
+//!
+//! Games talk to it in packets, each made of:
+//!
+//! | Bytes | Contents |
+//! |-------|----------|
+//! | 2 | $88 $33 |
+//! | 1 | The command |
+//! | 1 | 1 if the data is compressed, otherwise 0 |
+//! | 2 | The length of the data, little endian |
+//! | length | The data |
+//! | 2 | The sum of the command, compression flag, length and data, little endian |
+//! | 2 | $00 $00, to which the printer sends back $81 and its status |
+//!
+//! The printer sends back $00 for every other byte. The commands are:
+//! - $01 clears the image and the status.
+//! - $04 adds the data to the image. It holds tiles, 20 to a row, so 640 bytes make a band 16
+//!   pixels high. Compressed data is a series of runs: a byte below $80 is followed by that many
+//!   plus 1 bytes to copy, and a byte of $80 or above by one byte to repeat that many minus $7E
+//!   times. Games send an empty one after the last band.
+//! - $02 prints the image, and clears it. The data is the number of sheets, the lines to feed
+//!   before and after in the upper and lower nibble, the palette, and the exposure.
+//! - $0F does nothing, so games send it to ask for the status.
+//!
+//! Printing is reported as taking [`PRINTING_PACKETS`] packets, so that games waiting for it to
+//! finish carry on straight away. The image is passed to the callback given to
+//! [`GbPrinter::new`] as soon as the print command arrives.
+
+use crate::gameboy::{
+    logging,
+    ppu::{color::RgbaColor, frame::Shade},
+};
+
+use super::SerialConnection;
+use crate::threading::{dyn_maybe_send, MaybeSend};
+
+/// Printed images are always 20 tiles wide
+pub const PRINT_WIDTH: usize = 160;
+
+/// The printer can hold 9 bands of 20 by 2 tiles
+pub const BUFFER_SIZE: usize = 9 * 640;
+
+/// The number of packets after a print command that the status says the printer is busy for
+pub const PRINTING_PACKETS: u32 = 8;
+
+const MAGIC: [u8; 2] = [0x88, 0x33];
+
+/// Sent back in place of the first byte of zeros at the end of each packet
+const DEVICE_ID: u8 = 0x81;
+
+const INIT: u8 = 0x01;
+const PRINT: u8 = 0x02;
+const DATA: u8 = 0x04;
+const STATUS: u8 = 0x0F;
+
+bitflags::bitflags! {
+    /// The printer's status, sent back as the last byte of each packet
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct PrinterStatus: u8 {
+        const CHECKSUM_ERROR = 0x01;
+        const PRINTING = 0x02;
+        /// Set once an image has been printed, until the next is sent
+        const IMAGE_DATA_FULL = 0x04;
+        /// Set once data has been received, until it is printed
+        const UNPROCESSED_DATA = 0x08;
+        const PACKET_ERROR = 0x10;
+        const PAPER_JAM = 0x20;
+        const OTHER_ERROR = 0x40;
+        const LOW_BATTERY = 0x80;
+    }
+}
+
+/// An image printed by a [`GbPrinter`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrintedImage {
+    /// The shade of every pixel, row by row, with the palette applied
+    pixels: Box<[Shade]>,
+    /// How many copies to print. 0 only feeds the paper.
+    pub sheets: u8,
+    /// Lines of paper to feed before printing in the upper nibble, and after in the lower nibble
+    pub margins: u8,
+    /// Maps each 2-bit color in the tiles to a shade, like BGP. A palette of 0 is taken to be $E4.
+    pub palette: u8,
+    /// How dark to print, from $00 for 25% lighter to $7F for 25% darker
+    pub exposure: u8,
+}
+
+impl PrintedImage {
+    /// Decode `tiles`, 20 to a row, into an image, mapping each color through `palette`
+    fn from_tiles(tiles: &[u8], [sheets, margins, palette, exposure]: [u8; 4]) -> Self {
+        let palette = if palette == 0 { 0xE4 } else { palette };
+        let tile_rows = tiles.len() / (PRINT_WIDTH / 8 * 16);
+        let mut pixels = vec![0; PRINT_WIDTH * tile_rows * 8].into_boxed_slice();
+        for (i, pixel) in pixels.iter_mut().enumerate() {
+            let (x, y) = (i % PRINT_WIDTH, i / PRINT_WIDTH);
+            let offset = ((y / 8) * (PRINT_WIDTH / 8) + x / 8) * 16 + (y % 8) * 2;
+            let bit = 7 - x % 8;
+            let color = (tiles[offset] >> bit & 1) | (tiles[offset + 1] >> bit & 1) << 1;
+            *pixel = palette >> (color * 2) & 3;
+        }
+        PrintedImage {
+            pixels,
+            sheets,
+            margins,
+            palette,
+            exposure,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        PRINT_WIDTH
+    }
+
+    /// Height in pixels, which is a multiple of 16 for every game that sends whole bands
+    pub fn height(&self) -> usize {
+        self.pixels.len() / PRINT_WIDTH
+    }
+
+    /// The shade of the pixel at `(x, y)`
+    ///
+    /// # Panics
+    /// Panics if the coordinates are outside of the image
+    pub fn shade(&self, x: usize, y: usize) -> Shade {
+        assert!(
+            x < PRINT_WIDTH && y < self.height(),
+            "({}, {}) is outside of the image",
+            x,
+            y
+        );
+        self.pixels[y * PRINT_WIDTH + x]
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[Shade; PRINT_WIDTH]> {
+        self.pixels.array_chunks()
+    }
+
+    /// The color of every pixel, row by row, with each shade shown as its color in `palette`
+    pub fn colors<'a>(
+        &'a self,
+        palette: &'a [RgbaColor; 4],
+    ) -> impl Iterator<Item = RgbaColor> + 'a {
+        self.pixels
+            .iter()
+            .map(move |&shade| palette[shade as usize])
+    }
+}
+
+/// Called with each image the printer prints
+pub type PrintCallback = Box<dyn_maybe_send!(FnMut(PrintedImage))>;
+
+/// Where the printer is in the packet it is receiving
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Waiting for byte `n` of [`MAGIC`]
+    Magic(usize),
+    Command,
+    Compression,
+    Length(usize),
+    /// Waiting for this many more bytes of data
+    Data(usize),
+    Checksum(usize),
+    DeviceId,
+    Status,
+}
+
+/// A Game Boy Printer, to connect to the link port with
+/// [`GameboyBuilder::serial`](crate::gameboy::GameboyBuilder::serial)
+pub struct GbPrinter {
+    on_print: PrintCallback,
+    state: State,
+    command: u8,
+    compressed: bool,
+    length: u16,
+    data: Vec<u8>,
+    /// The checksum sent at the end of the packet
+    checksum: u16,
+    status: PrinterStatus,
+    /// Packets left until printing finishes
+    printing_packets: u32,
+    /// The decompressed tiles received since the last print
+    image: Vec<u8>,
+}
+
+impl GbPrinter {
+    pub fn new(on_print: impl FnMut(PrintedImage) + MaybeSend + 'static) -> Self {
+        GbPrinter {
+            on_print: Box::new(on_print),
+            state: State::Magic(0),
+            command: 0,
+            compressed: false,
+            length: 0,
+            data: Vec::new(),
+            checksum: 0,
+            status: PrinterStatus::empty(),
+            printing_packets: 0,
+            image: Vec::new(),
+        }
+    }
+
+    pub fn status(&self) -> PrinterStatus {
+        self.status
+    }
+
+    /// The sum the game should have sent at the end of the packet
+    fn expected_checksum(&self) -> u16 {
+        let header = [
+            self.command,
+            self.compressed as u8,
+            self.length as u8,
+            (self.length >> 8) as u8,
+        ];
+        header
+            .iter()
+            .chain(&self.data)
+            .fold(0u16, |sum, &byte| sum.wrapping_add(byte as u16))
+    }
+
+    /// The status to send back at the end of a packet, which the packet itself hasn't changed
+    /// yet apart from a wrong checksum
+    fn reply_status(&mut self) -> u8 {
+        let status = self.status;
+        if self.status.contains(PrinterStatus::PRINTING) {
+            self.printing_packets = self.printing_packets.saturating_sub(1);
+            if self.printing_packets == 0 {
+                self.status.remove(PrinterStatus::PRINTING);
+            }
+        }
+        status.bits()
+    }
+
+    /// Carry out the packet that has just been received
+    fn run_command(&mut self) {
+        if self.status.contains(PrinterStatus::CHECKSUM_ERROR) {
+            log::warn!(
+                target: logging::SERIAL,
+                "printer: wrong checksum for command ${:02X}",
+                self.command
+            );
+            return;
+        }
+        match self.command {
+            INIT => {
+                self.image.clear();
+                self.status = PrinterStatus::empty();
+            }
+            DATA => {
+                let data = std::mem::take(&mut self.data);
+                if self.compressed {
+                    decompress(&data, &mut self.image);
+                } else {
+                    self.image.extend_from_slice(&data);
+                }
+                self.image.truncate(BUFFER_SIZE);
+                self.data = data;
+                if !self.image.is_empty() {
+                    self.status.remove(PrinterStatus::IMAGE_DATA_FULL);
+                    self.status.insert(PrinterStatus::UNPROCESSED_DATA);
+                }
+            }
+            PRINT if self.data.len() == 4 => {
+                let settings = [self.data[0], self.data[1], self.data[2], self.data[3]];
+                let image = PrintedImage::from_tiles(&self.image, settings);
+                log::debug!(
+                    target: logging::SERIAL,
+                    "printer: printing {}x{}",
+                    image.width(),
+                    image.height()
+                );
+                self.image.clear();
+                self.status.remove(PrinterStatus::UNPROCESSED_DATA);
+                self.status
+                    .insert(PrinterStatus::PRINTING | PrinterStatus::IMAGE_DATA_FULL);
+                self.printing_packets = PRINTING_PACKETS;
+                (self.on_print)(image);
+            }
+            STATUS => (),
+            command => {
+                log::warn!(
+                    target: logging::SERIAL,
+                    "printer: unknown command ${:02X}",
+                    command
+                );
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for GbPrinter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GbPrinter")
+            .field("state", &self.state)
+            .field("status", &self.status)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SerialConnection for GbPrinter {
+    fn exchange(&mut self, byte: u8) -> u8 {
+        let (next, reply) = match self.state {
+            State::Magic(i) if byte == MAGIC[i] && i + 1 < MAGIC.len() => (State::Magic(i + 1), 0),
+            State::Magic(i) if byte == MAGIC[i] => (State::Command, 0),
+            // Start again, looking for the start of a packet
+            State::Magic(_) => (State::Magic((byte == MAGIC[0]) as usize), 0),
+            State::Command => {
+                self.command = byte;
+                (State::Compression, 0)
+            }
+            State::Compression => {
+                self.compressed = byte & 1 != 0;
+                self.length = 0;
+                (State::Length(0), 0)
+            }
+            State::Length(i) => {
+                self.length |= (byte as u16) << (i * 8);
+                if i == 0 {
+                    (State::Length(1), 0)
+                } else {
+                    self.data.clear();
+                    self.checksum = 0;
+                    match self.length {
+                        0 => (State::Checksum(0), 0),
+                        length => (State::Data(length as usize), 0),
+                    }
+                }
+            }
+            State::Data(remaining) => {
+                self.data.push(byte);
+                match remaining - 1 {
+                    0 => (State::Checksum(0), 0),
+                    remaining => (State::Data(remaining), 0),
+                }
+            }
+            State::Checksum(i) => {
+                self.checksum |= (byte as u16) << (i * 8);
+                if i == 0 {
+                    (State::Checksum(1), 0)
+                } else {
+                    let wrong = self.checksum != self.expected_checksum();
+                    self.status.set(PrinterStatus::CHECKSUM_ERROR, wrong);
+                    (State::DeviceId, 0)
+                }
+            }
+            State::DeviceId => (State::Status, DEVICE_ID),
+            State::Status => {
+                let status = self.reply_status();
+                self.run_command();
+                (State::Magic(0), status)
+            }
+        };
+        self.state = next;
+        reply
+    }
+}
+
+/// Append the data of a compressed packet to `out`. A run cut short by the end of the data is
+/// copied as far as it goes.
+fn decompress(mut data: &[u8], out: &mut Vec<u8>) {
+    while let Some((&control, rest)) = data.split_first() {
+        if control & 0x80 == 0 {
+            let len = (control as usize + 1).min(rest.len());
+            out.extend_from_slice(&rest[..len]);
+            data = &rest[len..];
+        } else {
+            let Some((&byte, rest)) = rest.split_first() else {
+                break;
+            };
+            out.resize(out.len() + (control & 0x7F) as usize + 2, byte);
+            data = rest;
+        }
+    }
+}
@@ -0,0 +1,151 @@
+//! The Super Game Boy's side of the joypad port.
+//!
+//! Games send 16 byte command packets to the SGB one bit at a time through P1. Only MLT_REQ is
+//! acted on here, since games use it to detect the SGB. The rest are passed on to a callback, so
+//! that a frontend can implement borders and palettes itself.
+//! See <https://gbdev.io/pandocs/SGB_Command_Packet.html>.
+
+use crate::threading::dyn_maybe_send;
+
+/// Called with each packet sent to the SGB
+pub type SgbPacketCallback = Box<dyn_maybe_send!(FnMut(&[u8; 16]))>;
+
+const PACKET_BITS: u8 = 128;
+
+/// The MLT_REQ command, which enables multiplayer mode
+const MLT_REQ: u8 = 0x11;
+
+/// P14 and P15, the bits of P1 that carry the packets
+const SELECT_MASK: u8 = 0x30;
+/// P14 and P15 are both low
+const RESET_PULSE: u8 = 0x00;
+/// Only P14 is low
+const ZERO_BIT: u8 = 0x20;
+/// Only P15 is low
+const ONE_BIT: u8 = 0x10;
+/// P14 and P15 are both high, which separates the bits of a packet
+const IDLE: u8 = 0x30;
+
+#[derive(Debug, Clone, Copy)]
+enum Transfer {
+    Idle,
+    Receiving {
+        packet: [u8; 16],
+        /// Number of bits received so far, not counting the stop bit
+        bits: u8,
+        /// Whether P14 and P15 have both been high since the last bit, so that the next pulse
+        /// is a new bit
+        ready: bool,
+    },
+}
+
+pub(crate) struct Sgb {
+    transfer: Transfer,
+    /// The last value of P14 and P15
+    select: u8,
+    /// Number of joypads enabled by MLT_REQ, minus one (0, 1 or 3)
+    player_mask: u8,
+    /// The joypad whose ID is returned from P1 when neither button group is selected
+    player: u8,
+    callback: Option<SgbPacketCallback>,
+}
+
+impl std::fmt::Debug for Sgb {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Sgb")
+            .field("transfer", &self.transfer)
+            .field("player_mask", &self.player_mask)
+            .field("player", &self.player)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for Sgb {
+    fn default() -> Self {
+        Sgb {
+            transfer: Transfer::Idle,
+            select: IDLE,
+            player_mask: 0,
+            player: 0,
+            callback: None,
+        }
+    }
+}
+
+impl Sgb {
+    /// Forget any packet being received and leave multiplayer mode, keeping the callback
+    pub fn power_cycle(&mut self) {
+        *self = Sgb {
+            callback: self.callback.take(),
+            ..Default::default()
+        };
+    }
+
+    pub fn set_callback(&mut self, callback: Option<SgbPacketCallback>) {
+        self.callback = callback;
+    }
+
+    /// The joypad currently being read, if multiplayer mode is enabled
+    pub fn player(&self) -> Option<u8> {
+        (self.player_mask != 0).then_some(self.player)
+    }
+
+    /// Handle a write to P1
+    pub fn write(&mut self, p1: u8) {
+        let select = p1 & SELECT_MASK;
+        let previous = std::mem::replace(&mut self.select, select);
+
+        match (select, &mut self.transfer) {
+            (RESET_PULSE, _) => {
+                self.transfer = Transfer::Receiving {
+                    packet: [0; 16],
+                    bits: 0,
+                    ready: false,
+                }
+            }
+            (IDLE, Transfer::Receiving { ready, .. }) => *ready = true,
+            (IDLE, Transfer::Idle) => {
+                // Deselecting the buttons after reading them moves on to the next joypad
+                if previous == ONE_BIT {
+                    self.player = (self.player + 1) & self.player_mask;
+                }
+            }
+            (
+                ZERO_BIT | ONE_BIT,
+                Transfer::Receiving {
+                    packet,
+                    bits,
+                    ready: ready @ true,
+                },
+            ) => {
+                *ready = false;
+                let bit = select == ONE_BIT;
+                if *bits < PACKET_BITS {
+                    packet[*bits as usize / 8] |= (bit as u8) << (*bits % 8);
+                    *bits += 1;
+                } else {
+                    // The stop bit must be a 0, otherwise the packet is thrown away
+                    let packet = *packet;
+                    self.transfer = Transfer::Idle;
+                    if !bit {
+                        self.receive(&packet);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn receive(&mut self, packet: &[u8; 16]) {
+        if packet[0] >> 3 == MLT_REQ {
+            self.player_mask = match packet[1] & 0x03 {
+                2 => 1,
+                mask => mask,
+            };
+            self.player = 0;
+        }
+        if let Some(callback) = &mut self.callback {
+            callback(packet);
+        }
+    }
+}
@@ -0,0 +1,193 @@
+//! Names for addresses, read from the `.sym` files that RGBDS and most other assemblers write.
+//!
+//! Each line of a symbol file is a bank and an address in hex, then a label:
+//!
+//! ```text
+//! ; File generated by rgblink
+//! 00:0150 Main
+//! 00:0158 Main.loop
+//! 01:4abc UpdatePlayer
+//! 02:4abc LoadLevel
+//! ```
+//!
+//! Labels starting with `.` belong to the label before them, so `.loop` after `Main` is stored as
+//! `Main.loop`. Once loaded with [`Gameboy::load_symbols`](super::Gameboy::load_symbols), the
+//! names show up in the CPU trace log, crash dumps and
+//! [`Gameboy::call_stack_report`](super::Gameboy::call_stack_report), and breakpoints can be set
+//! with [`Breakpoint::at_symbol`](super::breakpoints::Breakpoint::at_symbol).
+//!
+//! Only the switchable ROM bank at $4000-$7FFF and cartridge RAM at $A000-$BFFF are looked up by
+//! bank. Elsewhere a label names its address whatever bank the file gives it.
+
+use std::{collections::BTreeMap, fmt};
+
+/// The start of each area that a label can't reach past
+const REGIONS: [u16; 10] = [
+    0x0000, 0x4000, 0x8000, 0xA000, 0xC000, 0xE000, 0xFE00, 0xFF00, 0xFF80, 0xFFFF,
+];
+
+fn region(addr: u16) -> u16 {
+    let start = REGIONS.iter().rev().find(|&&start| start <= addr);
+    *start.unwrap()
+}
+
+/// Whether the bank of a label at `addr` depends on the mapper
+pub(crate) fn is_banked(addr: u16) -> bool {
+    matches!(addr, 0x4000..=0x7FFF | 0xA000..=0xBFFF)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum SymError {
+    #[error("line {line}: expected a bank:address and a label, found {text:?}")]
+    InvalidLine { line: usize, text: String },
+    #[error("unknown symbol {0:?}")]
+    UnknownSymbol(String),
+    #[error("invalid offset {0:?}")]
+    InvalidOffset(String),
+}
+
+/// The nearest label at or before an address, and how far past it the address is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SymbolRef<'a> {
+    pub name: &'a str,
+    pub offset: u16,
+}
+
+/// `Name`, or `Name+0x12`
+impl fmt::Display for SymbolRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.offset {
+            0 => f.write_str(self.name),
+            offset => write!(f, "{}+{:#X}", self.name, offset),
+        }
+    }
+}
+
+/// Labels by bank and address
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SymbolTable {
+    /// By (bank, address), with the bank 0 for addresses that aren't banked. Where several labels
+    /// share an address, the first one that isn't local is kept.
+    labels: BTreeMap<(u16, u16), String>,
+    /// Every label, with the bank it was given
+    names: BTreeMap<String, (u16, u16)>,
+}
+
+impl SymbolTable {
+    /// Parses a symbol file. Blank lines and comments starting with `;` are skipped.
+    pub fn parse(text: &str) -> Result<Self, SymError> {
+        let mut table = SymbolTable::default();
+        let mut scope = "";
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split(';').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let invalid = || SymError::InvalidLine {
+                line: i + 1,
+                text: line.to_owned(),
+            };
+            let (location, name) = line.split_once(char::is_whitespace).ok_or_else(invalid)?;
+            let (bank, addr) = location.split_once(':').ok_or_else(invalid)?;
+            let bank = u16::from_str_radix(bank, 16).map_err(|_| invalid())?;
+            let addr = u16::from_str_radix(addr, 16).map_err(|_| invalid())?;
+            let name = name.trim();
+            let name = if name.starts_with('.') {
+                format!("{}{}", scope, name)
+            } else {
+                scope = name.split('.').next().unwrap();
+                name.to_owned()
+            };
+            table.insert(bank, addr, name);
+        }
+        Ok(table)
+    }
+
+    fn insert(&mut self, bank: u16, addr: u16, name: String) {
+        let key = (if is_banked(addr) { bank } else { 0 }, addr);
+        let is_local = |name: &str| name.contains('.');
+        match self.labels.get(&key) {
+            Some(existing) if !is_local(existing) || is_local(&name) => {}
+            _ => {
+                self.labels.insert(key, name.clone());
+            }
+        }
+        self.names.insert(name, (bank, addr));
+    }
+
+    /// The number of labels
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Names `addr`, with `bank` mapped in if it is a banked address, by the nearest label at or
+    /// before it in the same area of memory
+    pub fn symbolize(&self, bank: u16, addr: u16) -> Option<SymbolRef<'_>> {
+        let bank = if is_banked(addr) { bank } else { 0 };
+        let (&(_, label_addr), name) = self
+            .labels
+            .range((bank, region(addr))..=(bank, addr))
+            .next_back()?;
+        Some(SymbolRef {
+            name,
+            offset: addr - label_addr,
+        })
+    }
+
+    /// The bank and address of a label, written `Name` or `Name+offset` with the offset in decimal
+    /// or hex
+    pub fn resolve(&self, target: &str) -> Result<(u16, u16), SymError> {
+        let (name, offset) = match target.split_once('+') {
+            Some((name, offset)) => (name.trim(), parse_offset(offset.trim())?),
+            None => (target.trim(), 0),
+        };
+        let &(bank, addr) = self
+            .names
+            .get(name)
+            .ok_or_else(|| SymError::UnknownSymbol(name.to_owned()))?;
+        Ok((bank, addr.wrapping_add(offset)))
+    }
+
+    /// Displays `addr` as `01:4ABC UpdatePlayer+0x12`
+    pub(crate) fn location(&self, bank: u16, addr: u16) -> Location<'_> {
+        Location {
+            symbols: self,
+            bank,
+            addr,
+        }
+    }
+}
+
+/// An address with its label. The bank is left out where it isn't banked, and the label if there
+/// isn't one.
+pub(crate) struct Location<'a> {
+    symbols: &'a SymbolTable,
+    bank: u16,
+    addr: u16,
+}
+
+impl fmt::Display for Location<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if is_banked(self.addr) {
+            write!(f, "{:02X}:{:04X}", self.bank, self.addr)?;
+        } else {
+            write!(f, "   {:04X}", self.addr)?;
+        }
+        match self.symbols.symbolize(self.bank, self.addr) {
+            Some(symbol) => write!(f, " {}", symbol),
+            None => Ok(()),
+        }
+    }
+}
+
+fn parse_offset(text: &str) -> Result<u16, SymError> {
+    let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix('$')) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    parsed.map_err(|_| SymError::InvalidOffset(text.to_owned()))
+}
@@ -0,0 +1,73 @@
+//! The internal 16-bit divider that DIV, the timer and the APU frame sequencer are all clocked
+//! from. See <https://gbdev.io/pandocs/Timer_Obscure_Behaviour.html>.
+
+/// The address of DIV, the upper 8 bits of the counter. Writing any value to it resets the whole
+/// counter.
+const DIV: u16 = 0xFF04;
+
+/// Counts T-cycles, so it goes up by 4 every M-cycle.
+///
+/// Chips see the counter as it was at the start of the M-cycle they are being clocked for, and can
+/// use [`SystemCounter::fell`] to detect the edges their own counters are driven by. Because every
+/// chip sees the same edges, a write to DIV affects all of them at once.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SystemCounter {
+    value: u16,
+    /// DIV is written during the current M-cycle
+    reset: bool,
+    /// The counter doesn't count during the current M-cycle, which is one of the extra ones an
+    /// overclocked CPU gets
+    held: bool,
+}
+
+impl SystemCounter {
+    /// A counter that starts from `value` rather than 0
+    pub fn starting_at(value: u16) -> Self {
+        SystemCounter {
+            value,
+            reset: false,
+            held: false,
+        }
+    }
+
+    /// The value of the counter at the start of this M-cycle
+    pub fn value(&self) -> u16 {
+        self.value
+    }
+
+    /// The value read from DIV during this M-cycle
+    pub fn div(&self) -> u8 {
+        (self.value >> 8) as u8
+    }
+
+    /// The value of the counter at the end of this M-cycle
+    pub fn next(&self) -> u16 {
+        let start = if self.reset { 0 } else { self.value };
+        if self.held {
+            start
+        } else {
+            start.wrapping_add(4)
+        }
+    }
+
+    /// Whether `bit` of the counter goes from 1 to 0 during this M-cycle, either by counting or
+    /// because DIV was written
+    pub fn fell(&self, bit: u8) -> bool {
+        let mask = 1 << bit;
+        self.value & mask != 0 && self.next() & mask == 0
+    }
+
+    /// Called before the chips are clocked, with the pins on the bus for this M-cycle, and
+    /// whether the counter counts during it
+    pub(crate) fn begin_cycle(&mut self, pins: gb_cpu::CpuOutputPins, counting: bool) {
+        self.reset = matches!(pins, gb_cpu::CpuOutputPins::Write { addr: DIV, .. });
+        self.held = !counting;
+    }
+
+    /// Called after the chips are clocked
+    pub(crate) fn end_cycle(&mut self) {
+        self.value = self.next();
+        self.reset = false;
+        self.held = false;
+    }
+}
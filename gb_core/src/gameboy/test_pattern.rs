@@ -0,0 +1,39 @@
+//! A tiny homebrew ROM that draws a test pattern, so examples and doctests have something to run
+//! without needing a commercial ROM.
+//!
+//! The screen is filled with diagonal stripes, 8 pixels wide, that cycle through shades 0-3: the
+//! pixel at `(x, y)` has [`shade`]`(x, y)`. The program is assembled from `test_pattern.S`.
+//!
+//! ```
+//! use gb_core::gameboy::{test_pattern, Gameboy, ResetKind};
+//!
+//! let mut gameboy = Gameboy::new(test_pattern::rom()).unwrap();
+//! gameboy.reset(ResetKind::PowerCycle);
+//! let frame = gameboy.run_frames(3);
+//! assert_eq!(frame[(8, 0)], test_pattern::shade(8, 0));
+//! ```
+
+use super::{cart::header, ppu::frame::Shade};
+
+/// Where [`PROGRAM`] is loaded
+pub const LOAD_ADDR: u16 = 0x0150;
+
+/// The assembled program
+#[rustfmt::skip]
+pub const PROGRAM: [u8; 72] = [
+    0xF0, 0x44, 0xFE, 0x90, 0x38, 0xFA, 0xAF, 0xE0, 0x40, 0x11, 0x90, 0x01, 0x21, 0x00, 0x80, 0x0E,
+    0x04, 0x06, 0x08, 0x1A, 0x22, 0x13, 0x1A, 0x22, 0x1B, 0x05, 0x20, 0xF7, 0x13, 0x13, 0x0D, 0x20,
+    0xF0, 0x21, 0x00, 0x98, 0x16, 0x00, 0x42, 0x1E, 0x20, 0x78, 0xE6, 0x03, 0x22, 0x04, 0x1D, 0x20,
+    0xF8, 0x14, 0xCB, 0x6A, 0x28, 0xF0, 0x3E, 0xE4, 0xE0, 0x47, 0x3E, 0x91, 0xE0, 0x40, 0x18, 0xFE,
+    0x00, 0x00, 0xFF, 0x00, 0x00, 0xFF, 0xFF, 0xFF,
+];
+
+/// The complete ROM image, with a valid header
+pub fn rom() -> Vec<u8> {
+    header::flat_rom(&PROGRAM, LOAD_ADDR, "TEST PATTERN").unwrap()
+}
+
+/// The shade the test pattern has at `(x, y)`
+pub fn shade(x: usize, y: usize) -> Shade {
+    ((x / 8 + y / 8) % 4) as Shade
+}
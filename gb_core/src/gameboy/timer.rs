@@ -1,23 +1,69 @@
 use gb_cpu::CpuOutputPins;
 
-use super::Chip;
+use super::{Chip, ClockContext};
 
-#[derive(Default, Debug)]
+/// TIMA and its registers. DIV is the upper byte of the [`SystemCounter`](super::system_counter::SystemCounter),
+/// which is owned by the [`Gameboy`](super::Gameboy).
+#[derive(Default, Debug, Clone, Copy)]
 pub struct Timer {
-    div: u16,
     tima: u8,
     tma: u8,
     tac: u8,
 }
 
+/// Which clock DIV and TIMA count with while the CPU is overclocked, see
+/// [`Gameboy::set_cpu_overclock`](super::Gameboy::set_cpu_overclock)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TimerClock {
+    /// Count at the stock rate, like the PPU, so that music and anything else a game times with
+    /// the timer keeps its speed
+    #[default]
+    Stock,
+    /// Count on every M-cycle the CPU gets, so that the timer speeds up along with the CPU
+    Cpu,
+}
+
+impl Timer {
+    fn enabled(&self) -> bool {
+        self.tac & 0b100 != 0
+    }
+
+    /// The bit of the system counter that TIMA counts on the falling edge of
+    fn counter_bit(&self) -> u8 {
+        match self.tac & 0b11 {
+            0b00 => 9,
+            0b01 => 3,
+            0b10 => 5,
+            0b11 => 7,
+            _ => unreachable!(),
+        }
+    }
+
+    /// TIMA, TMA and TAC
+    pub(crate) fn registers(&self) -> [u8; 3] {
+        [self.tima, self.tma, self.tac]
+    }
+
+    pub(crate) fn set_registers(&mut self, [tima, tma, tac]: [u8; 3]) {
+        self.tima = tima;
+        self.tma = tma;
+        self.tac = tac;
+    }
+}
+
 impl Chip for Timer {
-    fn clock(&mut self, input: gb_cpu::CpuOutputPins, data: &mut u8, interrupt_request: &mut u8) {
+    fn clock(
+        &mut self,
+        input: gb_cpu::CpuOutputPins,
+        data: &mut u8,
+        interrupt_request: &mut u8,
+        ctx: &ClockContext,
+    ) {
         let mut tima_write = false;
 
         match input {
-            // DIV
-            CpuOutputPins::Write { addr: 0xFF04, .. } => self.div = 0,
-            CpuOutputPins::Read { addr: 0xFF04 } => *data = (self.div >> 8) as u8,
+            // DIV. Writes are handled by the system counter.
+            CpuOutputPins::Read { addr: 0xFF04 } => *data = ctx.counter.div(),
 
             // TIMA
             CpuOutputPins::Write {
@@ -40,24 +86,15 @@ impl Chip for Timer {
             CpuOutputPins::Write {
                 addr: 0xFF07,
                 data: v,
-            } => self.tac = v,
-            CpuOutputPins::Read { addr: 0xFF07 } => *data = self.tac,
+            } => self.tac = v & 0x07,
+            // The upper 5 bits are unused
+            CpuOutputPins::Read { addr: 0xFF07 } => *data = self.tac | 0xF8,
             _ => (),
         };
 
-        self.div = self.div.wrapping_add(4);
-
-        let div_compare = match self.tac & 0b11 {
-            0b00 => 1024,
-            0b01 => 16,
-            0b10 => 64,
-            0b11 => 256,
-            _ => unreachable!(),
-        };
-
-        let timer_enable = self.tac & 0b100 != 0;
-
-        let timer_inc = timer_enable && self.div % div_compare == 0;
+        // TIMA counts on the falling edge of one bit of the system counter, so resetting the
+        // counter while that bit is set causes an extra increment
+        let timer_inc = self.enabled() && ctx.counter.fell(self.counter_bit());
 
         if !tima_write && timer_inc {
             let (tima, carry) = self.tima.overflowing_add(1);
@@ -70,4 +107,26 @@ impl Chip for Timer {
             }
         }
     }
+
+    fn chip_select(&self) -> Vec<std::ops::RangeInclusive<u16>> {
+        vec![0xFF04..=0xFF07]
+    }
+
+    fn name(&self) -> &'static str {
+        "timer"
+    }
+
+    /// The next falling edge of the counter bit, unless DIV or the timer's registers are written
+    /// first
+    fn next_event(&self, ctx: &ClockContext) -> u64 {
+        if !self.enabled() {
+            return u64::MAX;
+        }
+        // The bit falls during the M-cycle that takes the counter up to a multiple of the bit's
+        // period, which is this far from the start of the next one
+        let period = 2u16 << self.counter_bit();
+        let until_wrap = period - (ctx.counter.next() & (period - 1));
+        let m_cycles = (until_wrap.saturating_sub(4) + 3) / 4;
+        ctx.cycles + 4 + 4 * m_cycles as u64
+    }
 }
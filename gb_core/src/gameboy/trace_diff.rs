@@ -0,0 +1,587 @@
+//! Finding where two CPU traces part ways, such as this emulator's and a reference emulator's.
+//!
+//! Both traces are in the format of [Gameboy Doctor](https://github.com/robert/gameboy-doctor),
+//! one line for each instruction, with the state before it runs and the 4 bytes of memory from PC:
+//!
+//! ```text
+//! A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02
+//! ```
+//!
+//! [`diff`] reads the two traces a line at a time, so logs of millions of instructions don't have
+//! to fit in memory, and stops at the first instruction where any field differs. The
+//! [`Divergence`] it returns prints as a report with the instructions leading up to it,
+//! disassembled, and each field that differs:
+//!
+//! ```
+//! use gb_core::gameboy::trace_diff::{diff, DiffOptions};
+//!
+//! let ours = "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02\n\
+//!             A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0101 PCMEM:C3,13,02,CE\n";
+//! let theirs = "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02\n\
+//!               A:01 F:80 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0101 PCMEM:C3,13,02,CE\n";
+//! let divergence = diff(ours.as_bytes(), theirs.as_bytes(), &DiffOptions::default())
+//!     .unwrap()
+//!     .unwrap();
+//! assert_eq!(divergence.index, 1);
+//! println!("{}", divergence);
+//! ```
+//!
+//! Some fields differ between emulators for reasons that don't matter, like the IO registers that
+//! PCMEM reads when the CPU runs near them. [`Tolerance::Lenient`] ignores those.
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    io::{self, BufRead},
+    str::FromStr,
+};
+
+use gb_cpu::disassembler::disassemble;
+
+/// The CPU state before an instruction, as one line of a trace
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TraceLine {
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub sp: u16,
+    pub pc: u16,
+    /// The bytes at PC to PC+3
+    pub pcmem: [u8; 4],
+}
+
+/// The names of the fields of a line, in order
+const FIELDS: [&str; 11] = ["A", "F", "B", "C", "D", "E", "H", "L", "SP", "PC", "PCMEM"];
+
+/// Errors produced while parsing a line of a trace
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum TraceParseError {
+    #[error("expected a field like \"A:01\", found {0:?}")]
+    MalformedField(String),
+    #[error("unknown field {0:?}")]
+    UnknownField(String),
+    #[error("{0} appears more than once")]
+    DuplicateField(&'static str),
+    #[error("missing {0}")]
+    MissingField(&'static str),
+    #[error("{field} should be {digits} hex digits, found {value:?}")]
+    InvalidValue {
+        field: &'static str,
+        digits: usize,
+        value: String,
+    },
+}
+
+impl FromStr for TraceLine {
+    type Err = TraceParseError;
+
+    /// Parse a line like `A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100
+    /// PCMEM:00,C3,13,02`. The fields can be in any order, but each one has to be there.
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let mut values: [Option<&str>; FIELDS.len()] = [None; FIELDS.len()];
+        for token in line.split_whitespace() {
+            let (name, value) = token
+                .split_once(':')
+                .ok_or_else(|| TraceParseError::MalformedField(token.to_owned()))?;
+            let index = FIELDS
+                .iter()
+                .position(|&field| field == name)
+                .ok_or_else(|| TraceParseError::UnknownField(name.to_owned()))?;
+            if values[index].replace(value).is_some() {
+                return Err(TraceParseError::DuplicateField(FIELDS[index]));
+            }
+        }
+        let value =
+            |index: usize| values[index].ok_or(TraceParseError::MissingField(FIELDS[index]));
+        let byte = |index: usize| hex(FIELDS[index], value(index)?, 2).map(|v| v as u8);
+        let word = |index: usize| hex(FIELDS[index], value(index)?, 4);
+
+        let mut line = TraceLine {
+            a: byte(0)?,
+            f: byte(1)?,
+            b: byte(2)?,
+            c: byte(3)?,
+            d: byte(4)?,
+            e: byte(5)?,
+            h: byte(6)?,
+            l: byte(7)?,
+            sp: word(8)?,
+            pc: word(9)?,
+            pcmem: [0; 4],
+        };
+        let pcmem = value(10)?;
+        let bytes: Vec<&str> = pcmem.split(',').collect();
+        if bytes.len() != line.pcmem.len() {
+            return Err(TraceParseError::InvalidValue {
+                field: "PCMEM",
+                digits: 2,
+                value: pcmem.to_owned(),
+            });
+        }
+        for (byte, text) in line.pcmem.iter_mut().zip(bytes) {
+            *byte = hex("PCMEM", text, 2)? as u8;
+        }
+        Ok(line)
+    }
+}
+
+/// Parse exactly `digits` hex digits
+fn hex(field: &'static str, value: &str, digits: usize) -> Result<u16, TraceParseError> {
+    let invalid = || TraceParseError::InvalidValue {
+        field,
+        digits,
+        value: value.to_owned(),
+    };
+    if value.len() != digits || !value.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+    u16::from_str_radix(value, 16).map_err(|_| invalid())
+}
+
+/// In the Gameboy Doctor format
+impl fmt::Display for TraceLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for field in &ALL_FIELDS[..10] {
+            write!(f, "{} ", self.field_text(*field))?;
+        }
+        f.write_str(&self.field_text(Field::Pcmem(0)))
+    }
+}
+
+impl TraceLine {
+    /// The instruction at PC, going by PCMEM, or nothing if it isn't a valid opcode
+    pub fn disassemble(&self) -> String {
+        disassemble(&self.pcmem, self.pc).map_or_else(String::new, |(text, _)| text)
+    }
+
+    fn register(&self, field: Field) -> u16 {
+        match field {
+            Field::A => self.a as u16,
+            Field::F => self.f as u16,
+            Field::B => self.b as u16,
+            Field::C => self.c as u16,
+            Field::D => self.d as u16,
+            Field::E => self.e as u16,
+            Field::H => self.h as u16,
+            Field::L => self.l as u16,
+            Field::Sp => self.sp,
+            Field::Pc => self.pc,
+            Field::Flag(flag) => (self.f >> flag.bit() & 1) as u16,
+            Field::Pcmem(i) => self.pcmem[i] as u16,
+        }
+    }
+
+    /// How `field` is written in a line. Flags and PCMEM bytes are written as the whole of F and
+    /// PCMEM.
+    fn field_text(&self, field: Field) -> String {
+        match field {
+            Field::Sp | Field::Pc => {
+                format!("{}:{:04X}", FIELDS[field.index()], self.register(field))
+            }
+            Field::Flag(_) => self.field_text(Field::F),
+            Field::Pcmem(_) => {
+                let [a, b, c, d] = self.pcmem;
+                format!("PCMEM:{:02X},{:02X},{:02X},{:02X}", a, b, c, d)
+            }
+            _ => format!("{}:{:02X}", FIELDS[field.index()], self.register(field)),
+        }
+    }
+}
+
+/// A flag in F
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flag {
+    Z,
+    N,
+    H,
+    C,
+}
+
+impl Flag {
+    fn bit(self) -> u8 {
+        match self {
+            Flag::Z => 7,
+            Flag::N => 6,
+            Flag::H => 5,
+            Flag::C => 4,
+        }
+    }
+}
+
+/// A part of a [`TraceLine`] that can differ
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    A,
+    /// The lower 4 bits of F, which are always 0 on hardware. Differences in the upper 4 are
+    /// reported for each [`Flag`].
+    F,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    Sp,
+    Pc,
+    Flag(Flag),
+    /// One of the bytes in PCMEM, at PC plus the index
+    Pcmem(usize),
+}
+
+/// The fields in the order they are compared and written
+const ALL_FIELDS: [Field; 18] = [
+    Field::A,
+    Field::F,
+    Field::B,
+    Field::C,
+    Field::D,
+    Field::E,
+    Field::H,
+    Field::L,
+    Field::Sp,
+    Field::Pc,
+    Field::Flag(Flag::Z),
+    Field::Flag(Flag::N),
+    Field::Flag(Flag::H),
+    Field::Flag(Flag::C),
+    Field::Pcmem(0),
+    Field::Pcmem(1),
+    Field::Pcmem(2),
+    Field::Pcmem(3),
+];
+
+impl Field {
+    /// The index into [`FIELDS`] of the field this is part of
+    fn index(self) -> usize {
+        match self {
+            Field::A => 0,
+            Field::F | Field::Flag(_) => 1,
+            Field::B => 2,
+            Field::C => 3,
+            Field::D => 4,
+            Field::E => 5,
+            Field::H => 6,
+            Field::L => 7,
+            Field::Sp => 8,
+            Field::Pc => 9,
+            Field::Pcmem(_) => 10,
+        }
+    }
+
+    /// Whether `ours` and `theirs` differ in this field
+    fn differs(self, ours: &TraceLine, theirs: &TraceLine) -> bool {
+        match self {
+            Field::F => ours.f & 0x0F != theirs.f & 0x0F,
+            _ => ours.register(self) != theirs.register(self),
+        }
+    }
+}
+
+/// `A`, `flag Z`, or `PCMEM[2] ($0102)`
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Field::F => f.write_str("F (lower bits)"),
+            Field::Flag(flag) => write!(f, "flag {:?}", flag),
+            Field::Pcmem(i) => write!(f, "PCMEM[{}]", i),
+            field => f.write_str(FIELDS[field.index()]),
+        }
+    }
+}
+
+/// Which fields count when comparing lines
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Tolerance {
+    /// Every field has to match
+    #[default]
+    Strict,
+    /// Ignore PCMEM bytes read from the unusable region at $FEA0-$FEFF or the IO registers at
+    /// $FF00-$FF7F, which depend on the model and on timing that traces don't show
+    Lenient,
+}
+
+impl Tolerance {
+    fn ignores(self, field: Field, line: &TraceLine) -> bool {
+        match (self, field) {
+            (Tolerance::Lenient, Field::Pcmem(i)) => {
+                matches!(line.pc.wrapping_add(i as u16), 0xFEA0..=0xFF7F)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// How [`diff`] compares traces
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiffOptions {
+    /// How many instructions before the divergence to keep for the report
+    pub context: usize,
+    pub tolerance: Tolerance,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        DiffOptions {
+            context: 10,
+            tolerance: Tolerance::Strict,
+        }
+    }
+}
+
+/// Which of the two traces
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Ours,
+    Theirs,
+}
+
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Side::Ours => "our",
+            Side::Theirs => "their",
+        })
+    }
+}
+
+/// Errors produced while comparing traces
+#[derive(Debug, thiserror::Error)]
+pub enum TraceDiffError {
+    /// `line` counts from 1, like a text editor
+    #[error("{side} trace, line {line}: {error}")]
+    Parse {
+        side: Side,
+        line: usize,
+        error: TraceParseError,
+    },
+    #[error("reading {side} trace: {error}")]
+    Io { side: Side, error: io::Error },
+}
+
+/// A field that differs between the two lines where the traces diverge
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldDiff {
+    pub field: Field,
+    pub ours: u16,
+    pub theirs: u16,
+}
+
+/// Where two traces first differ
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// Number of instructions that matched before this one
+    pub index: u64,
+    /// The line number in each trace, counting from 1. Blank lines are skipped, so these can
+    /// differ from each other and from the index.
+    pub line_ours: usize,
+    pub line_theirs: usize,
+    /// `None` if the trace ended before the other one
+    pub ours: Option<TraceLine>,
+    pub theirs: Option<TraceLine>,
+    /// Empty if one of the traces ended
+    pub differences: Vec<FieldDiff>,
+    /// Up to [`DiffOptions::context`] of our lines before this one, oldest first
+    pub context: Vec<TraceLine>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Traces diverge at instruction {} (line {} of ours, line {} of theirs)",
+            self.index, self.line_ours, self.line_theirs
+        )?;
+        writeln!(f)?;
+        let start = self.index - self.context.len() as u64;
+        for (i, line) in self.context.iter().enumerate() {
+            write_line(f, " ", start + i as u64, line)?;
+        }
+        match &self.ours {
+            Some(line) => write_line(f, ">", self.index, line)?,
+            None => writeln!(f, "> {:>10}  (end of our trace)", self.index)?,
+        }
+        writeln!(f)?;
+
+        let (ours, theirs) = match (&self.ours, &self.theirs) {
+            (Some(ours), Some(theirs)) => (ours, theirs),
+            (Some(_), None) => return writeln!(f, "Their trace ends here"),
+            _ => return writeln!(f, "Our trace ends here"),
+        };
+        // Both lines, with the fields that differ underlined
+        let mut marks = String::new();
+        for (i, field) in ALL_FIELDS[..10]
+            .iter()
+            .chain(&[Field::Pcmem(0)])
+            .enumerate()
+        {
+            let width = ours.field_text(*field).len();
+            let differs = self
+                .differences
+                .iter()
+                .any(|diff| diff.field.index() == field.index());
+            if i > 0 {
+                marks.push(' ');
+            }
+            marks.extend(std::iter::repeat(if differs { '^' } else { ' ' }).take(width));
+        }
+        writeln!(f, "  ours:   {}", ours)?;
+        writeln!(f, "  theirs: {}", theirs)?;
+        writeln!(f, "          {}", marks.trim_end())?;
+        writeln!(f)?;
+
+        for diff in &self.differences {
+            match diff.field {
+                Field::Flag(_) => writeln!(
+                    f,
+                    "  {}: ours {}, theirs {}",
+                    diff.field, diff.ours, diff.theirs
+                )?,
+                Field::Sp | Field::Pc => writeln!(
+                    f,
+                    "  {}: ours ${:04X}, theirs ${:04X}",
+                    diff.field, diff.ours, diff.theirs
+                )?,
+                Field::Pcmem(i) => writeln!(
+                    f,
+                    "  {} (${:04X}): ours ${:02X}, theirs ${:02X}",
+                    diff.field,
+                    ours.pc.wrapping_add(i as u16),
+                    diff.ours,
+                    diff.theirs
+                )?,
+                _ => writeln!(
+                    f,
+                    "  {}: ours ${:02X}, theirs ${:02X}",
+                    diff.field, diff.ours, diff.theirs
+                )?,
+            }
+        }
+        if self
+            .differences
+            .iter()
+            .any(|diff| matches!(diff.field, Field::Pcmem(_)))
+        {
+            writeln!(f, "  their instruction: {}", theirs.disassemble())?;
+        }
+        Ok(())
+    }
+}
+
+/// One line of the context, with its index and disassembly
+fn write_line(
+    f: &mut fmt::Formatter<'_>,
+    marker: &str,
+    index: u64,
+    line: &TraceLine,
+) -> fmt::Result {
+    writeln!(
+        f,
+        "{} {:>10}  ${:04X}  {:<20} {}",
+        marker,
+        index,
+        line.pc,
+        line.disassemble(),
+        line
+    )
+}
+
+/// Reads a trace a line at a time, skipping blank lines
+struct TraceReader<R> {
+    reader: R,
+    side: Side,
+    buffer: String,
+    line: usize,
+}
+
+impl<R: BufRead> TraceReader<R> {
+    fn new(reader: R, side: Side) -> Self {
+        TraceReader {
+            reader,
+            side,
+            buffer: String::new(),
+            line: 0,
+        }
+    }
+
+    /// The next line, or `None` at the end of the trace
+    fn next(&mut self) -> Result<Option<TraceLine>, TraceDiffError> {
+        loop {
+            self.buffer.clear();
+            let read =
+                self.reader
+                    .read_line(&mut self.buffer)
+                    .map_err(|error| TraceDiffError::Io {
+                        side: self.side,
+                        error,
+                    })?;
+            if read == 0 {
+                return Ok(None);
+            }
+            self.line += 1;
+            if self.buffer.trim().is_empty() {
+                continue;
+            }
+            return self
+                .buffer
+                .parse()
+                .map(Some)
+                .map_err(|error| TraceDiffError::Parse {
+                    side: self.side,
+                    line: self.line,
+                    error,
+                });
+        }
+    }
+}
+
+/// Compare two traces line by line, and return where they first differ, or `None` if they are the
+/// same. Only the last [`DiffOptions::context`] lines are kept in memory.
+pub fn diff(
+    ours: impl BufRead,
+    theirs: impl BufRead,
+    options: &DiffOptions,
+) -> Result<Option<Divergence>, TraceDiffError> {
+    let mut ours = TraceReader::new(ours, Side::Ours);
+    let mut theirs = TraceReader::new(theirs, Side::Theirs);
+    let mut context = VecDeque::with_capacity(options.context + 1);
+    let mut index = 0;
+    loop {
+        let (our_line, their_line) = (ours.next()?, theirs.next()?);
+        let differences = match (&our_line, &their_line) {
+            (None, None) => return Ok(None),
+            (Some(a), Some(b)) => ALL_FIELDS
+                .iter()
+                .filter(|field| field.differs(a, b) && !options.tolerance.ignores(**field, a))
+                .map(|&field| FieldDiff {
+                    field,
+                    ours: a.register(field),
+                    theirs: b.register(field),
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        if our_line.is_none() || their_line.is_none() || !differences.is_empty() {
+            return Ok(Some(Divergence {
+                index,
+                line_ours: ours.line,
+                line_theirs: theirs.line,
+                ours: our_line,
+                theirs: their_line,
+                differences,
+                context: context.into(),
+            }));
+        }
+        if options.context > 0 {
+            if context.len() == options.context {
+                context.pop_front();
+            }
+            context.push_back(our_line.unwrap());
+        }
+        index += 1;
+    }
+}
@@ -0,0 +1,95 @@
+//! Reports of code doing things that are almost always bugs, for homebrew developers.
+//!
+//! Writes to ROM on a cartridge without a mapper are silently ignored by the hardware, and so is
+//! jumping into VRAM or the IO registers until the CPU runs into something that crashes it. With
+//! [`Gameboy::set_rom_write_policy`](super::Gameboy::set_rom_write_policy) and
+//! [`Gameboy::set_execution_check`](super::Gameboy::set_execution_check), each one is logged to
+//! `gb::violation` and published as an [`Event::Violation`](super::events::Event::Violation).
+
+use std::fmt;
+
+use super::{logging, ppu::consts::FRAME_T_CYCLES};
+
+/// At most this many violations are reported per frame's worth of T-cycles, so that a loop
+/// writing to ROM doesn't flood the log. Counted by cycles rather than frames, since the PPU
+/// doesn't finish frames while the LCD is off.
+pub const VIOLATIONS_PER_FRAME: u32 = 16;
+
+/// What happens to writes to $0000-$7FFF
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RomWritePolicy {
+    /// Pass them to the cartridge, which ignores the ones that don't set a mapper register, like
+    /// the hardware does
+    #[default]
+    MapperRegisters,
+    /// Drop them before they reach the cartridge, so its mapper registers can't change
+    Ignore,
+    /// Pass them to the cartridge, and report the ones that don't set a mapper register as a
+    /// [`Violation::RomWrite`]
+    ReportViolation,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Violation {
+    /// The instruction at `pc` wrote `value` to `addr` in ROM, where the cartridge has no mapper
+    /// register
+    RomWrite { pc: u16, addr: u16, value: u8 },
+    /// An opcode was fetched from VRAM, OAM or the IO registers
+    Execution { pc: u16 },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Violation::RomWrite { pc, addr, value } => write!(
+                f,
+                "${:04X}: wrote ${:02X} to ROM at ${:04X}, which has no mapper register",
+                pc, value, addr
+            ),
+            Violation::Execution { pc } => {
+                write!(f, "executing outside ROM and RAM at ${:04X}", pc)
+            }
+        }
+    }
+}
+
+/// Whether fetching an opcode from `pc` is reported by the execution check. High RAM is left
+/// out, since OAM DMA routines have to run from there.
+pub(crate) fn is_bad_pc(pc: u16) -> bool {
+    matches!(pc, 0x8000..=0x9FFF | 0xFE00..=0xFF7F | 0xFFFF)
+}
+
+#[derive(Default)]
+pub(crate) struct Violations {
+    pub rom_write_policy: RomWritePolicy,
+    pub check_execution: bool,
+    /// The frame's worth of T-cycles that `reported` counts violations in
+    window: u64,
+    reported: u32,
+}
+
+impl Violations {
+    /// Start counting again from cycle 0, after a reset
+    pub fn restart(&mut self) {
+        self.window = 0;
+        self.reported = 0;
+    }
+
+    /// Count a violation at `cycle`, and return whether it should be reported
+    pub fn allow(&mut self, cycle: u64) -> bool {
+        let window = cycle / FRAME_T_CYCLES as u64;
+        if window != self.window {
+            self.window = window;
+            self.reported = 0;
+        }
+        self.reported = self.reported.saturating_add(1);
+        if self.reported == VIOLATIONS_PER_FRAME + 1 {
+            log::warn!(
+                target: logging::VIOLATION,
+                "more than {} violations this frame, the rest are not reported",
+                VIOLATIONS_PER_FRAME
+            );
+        }
+        self.reported <= VIOLATIONS_PER_FRAME
+    }
+}
@@ -4,4 +4,10 @@
 #![feature(assert_matches)]
 #![feature(array_chunks)]
 
+pub mod error;
+pub mod frontend;
 pub mod gameboy;
+pub mod prelude;
+pub mod threading;
+
+pub use error::GbError;
@@ -0,0 +1,39 @@
+//! Everything a frontend needs to load a game, run it, show its frames, take input and keep its
+//! saves and savestates, in one import:
+//!
+//! ```no_run
+//! use gb_core::prelude::*;
+//!
+//! # fn main() -> Result<(), GbError> {
+//! let rom = std::fs::read("game.gb").unwrap();
+//! let mut gameboy = Gameboy::builder().rom(rom).model(Model::Dmg).build()?;
+//! gameboy.reset(ResetKind::PowerCycle);
+//! gameboy.joypad.press(Button::Start);
+//! let frame: &Frame = gameboy.run_frames(1);
+//! let pixels: Vec<RgbaColor> = frame.colors().collect();
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The rest of [`gameboy`](crate::gameboy) is for debuggers, tools and tests, and follows the
+//! emulator's internals more closely.
+
+pub use crate::{
+    gameboy::{
+        cart::SaveBlock,
+        joypad::{Button, Joypad},
+        memory::RamInit,
+        ppu::{
+            color::RgbaColor,
+            consts::FRAME_T_CYCLES,
+            frame::{scale, Frame, Shade},
+            frame_info::FrameInfo,
+            frame_pool::SharedFrame,
+            frame_sink::{FrameReceiver, PresentationMode},
+        },
+        rtc::{RtcTimeSource, SystemClock},
+        savestate::{state_info, StateError, StateInfo},
+        AccuracyLevel, Gameboy, GameboyBuilder, Model, PpuBackend, ResetKind, T_CYCLES_PER_SECOND,
+    },
+    GbError,
+};
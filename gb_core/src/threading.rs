@@ -0,0 +1,37 @@
+//! What a [`Gameboy`](crate::gameboy::Gameboy) needs from the things stored in it to be moved to
+//! another thread.
+//!
+//! By default, a Gameboy is `Send`, so everything it stores, from callbacks to chips plugged into
+//! it, has to be too. With the `single-thread` feature, for wasm and embedded frontends where
+//! everything lives on one thread, none of it has to be, and frames are shared with `Rc` instead
+//! of `Arc`. Nothing else about the API changes.
+
+/// `Send`, unless built with the `single-thread` feature. This is what callbacks and trait
+/// objects given to a [`Gameboy`](crate::gameboy::Gameboy) must implement.
+#[cfg(not(feature = "single-thread"))]
+pub trait MaybeSend: Send {}
+
+#[cfg(not(feature = "single-thread"))]
+impl<T: Send + ?Sized> MaybeSend for T {}
+
+/// `Send`, unless built with the `single-thread` feature. This is what callbacks and trait
+/// objects given to a [`Gameboy`](crate::gameboy::Gameboy) must implement.
+#[cfg(feature = "single-thread")]
+pub trait MaybeSend {}
+
+#[cfg(feature = "single-thread")]
+impl<T: ?Sized> MaybeSend for T {}
+
+/// `dyn $trait + Send`, or just `dyn $trait` with the `single-thread` feature. Only auto traits
+/// can be added to a trait object, so [`MaybeSend`] can't be used there.
+#[cfg(not(feature = "single-thread"))]
+macro_rules! dyn_maybe_send {
+    ($($trait:tt)*) => { dyn $($trait)* + Send };
+}
+
+#[cfg(feature = "single-thread")]
+macro_rules! dyn_maybe_send {
+    ($($trait:tt)*) => { dyn $($trait)* };
+}
+
+pub(crate) use dyn_maybe_send;
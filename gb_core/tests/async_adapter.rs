@@ -0,0 +1,143 @@
+//! Run with `cargo test -p gb_core --features async --test async_adapter`.
+#![cfg(feature = "async")]
+
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll, Wake, Waker},
+};
+
+use gb_core::gameboy::{
+    async_adapter::{AsyncGameboy, AsyncSerialConnection, ExchangeFuture, FrameHandle},
+    test_pattern, Gameboy, ResetKind,
+};
+
+struct Flag(AtomicBool);
+
+impl Wake for Flag {
+    fn wake(self: Arc<Self>) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Polls `future` on this thread until it completes, calling `between` after every poll that
+/// returns `Pending`, and returns its output and how many times it was polled. Panics if the future
+/// is pending without having arranged to be woken.
+fn block_on<T>(future: impl Future<Output = T>, mut between: impl FnMut()) -> (T, usize) {
+    let woken = Arc::new(Flag(AtomicBool::new(false)));
+    let waker = Waker::from(woken.clone());
+    let mut cx = Context::from_waker(&waker);
+    let mut future = Box::pin(future);
+    let mut polls = 0;
+    loop {
+        polls += 1;
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return (output, polls);
+        }
+        between();
+        assert!(
+            woken.0.swap(false, Ordering::SeqCst),
+            "pending without a wake"
+        );
+    }
+}
+
+#[cfg(not(feature = "single-thread"))]
+fn assert_send<T: Send>(_: &T) {}
+
+#[test]
+fn run_frame_yields_between_chunks() {
+    let mut gameboy = Gameboy::new(test_pattern::rom()).unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    // The ROM turns the LCD off and on during the first frame
+    gameboy.run_frames(1);
+    let mut gameboy = AsyncGameboy::new(gameboy);
+    let future = gameboy.run_frame();
+    #[cfg(not(feature = "single-thread"))]
+    assert_send(&future);
+    let (first, polls): (FrameHandle, _) = block_on(future, || ());
+    // A frame is 70224 T-cycles, so there are at least 7 chunks of 10000
+    assert!(polls >= 7, "{} polls", polls);
+
+    gameboy.set_chunk_cycles(1000);
+    let (second, polls) = block_on(gameboy.run_frame(), || ());
+    assert!(polls >= 70, "{} polls", polls);
+    assert_eq!(second.info.number, first.info.number + 1);
+    assert_eq!(second.info.cycle - first.info.cycle, 70224);
+    assert_eq!(second.frame[(8, 0)], test_pattern::shade(8, 0));
+}
+
+/// The other end of a link cable over a slow network. Each exchange answers with the complement
+/// of the byte sent, once the test delivers it.
+#[derive(Clone, Default)]
+struct MockPeer {
+    /// The bytes sent, and whether each has been answered
+    exchanges: Arc<Mutex<Vec<(u8, bool)>>>,
+    waker: Arc<Mutex<Option<Waker>>>,
+}
+
+impl MockPeer {
+    fn deliver(&self) {
+        if let Some(exchange) = self.exchanges.lock().unwrap().last_mut() {
+            exchange.1 = true;
+        }
+        if let Some(waker) = self.waker.lock().unwrap().take() {
+            waker.wake();
+        }
+    }
+}
+
+impl AsyncSerialConnection for MockPeer {
+    fn exchange(&mut self, byte: u8) -> ExchangeFuture {
+        self.exchanges.lock().unwrap().push((byte, false));
+        let peer = self.clone();
+        Box::pin(std::future::poll_fn(move |cx| {
+            match peer.exchanges.lock().unwrap().last() {
+                Some(&(sent, true)) => Poll::Ready(!sent),
+                _ => {
+                    *peer.waker.lock().unwrap() = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        }))
+    }
+}
+
+#[test]
+fn serial_exchange_awaits_the_peer() {
+    #[rustfmt::skip]
+    let gameboy = Gameboy::with_program(&[
+        0x3E, 0x42,       // LD A, $42
+        0xE0, 0x01,       // LDH (SB), A
+        0x3E, 0x81,       // LD A, $81
+        0xE0, 0x02,       // LDH (SC), A
+        // wait:
+        0xF0, 0x02,       // LDH A, (SC)
+        0x87,             // ADD A
+        0x38, 0xFB,       // JR C, wait
+        0xF0, 0x01,       // LDH A, (SB)
+        0xEA, 0x00, 0xC0, // LD ($C000), A
+        0x18, 0xFE,       // JR -2
+    ], 0x0150).unwrap();
+    let mut gameboy = AsyncGameboy::new(gameboy);
+    let peer = MockPeer::default();
+    gameboy.connect_serial(Box::new(peer.clone()));
+
+    // The peer doesn't answer during the first frame, so the transfer is still in progress
+    block_on(gameboy.run_frame(), || ());
+    assert_eq!(*peer.exchanges.lock().unwrap(), [(0x42, false)]);
+    assert_eq!(gameboy.peek(0xC000), 0x00);
+
+    // It answers partway through the second
+    let mut chunks = 0;
+    block_on(gameboy.run_frame(), || {
+        chunks += 1;
+        if chunks == 3 {
+            peer.deliver();
+        }
+    });
+    assert_eq!(gameboy.peek(0xC000), 0xBD);
+}
@@ -0,0 +1,91 @@
+//! Pacing emulation to the host's vsyncs and audio
+
+use gb_core::{
+    frontend::sync::{AudioSync, SyncStrategy, VideoSync},
+    gameboy::{
+        ppu::consts::{FRAME_T_CYCLES, LINE_T_CYCLES},
+        Gameboy, T_CYCLES_PER_SECOND,
+    },
+};
+
+const TEN_MINUTES_AT_60HZ: u64 = 10 * 60 * 60;
+
+/// Like `Gameboy::run_cycles`, which rounds up to whole M-cycles
+fn run(cycles: &mut u64, t_cycles: u64) {
+    *cycles += (t_cycles + 3) / 4 * 4;
+}
+
+#[test]
+fn real_time_stays_within_a_line() {
+    let start = 1234;
+    let mut cycles = start;
+    let mut sync = VideoSync::new(60.0, SyncStrategy::RealTime, cycles);
+    let mut longer = 0;
+    for vsync in 1..=TEN_MINUTES_AT_60HZ {
+        let t_cycles = sync.vsync(cycles);
+        // Whole lines, apart from rounding up to M-cycles last time
+        let lines = (t_cycles + 3) / LINE_T_CYCLES as u64;
+        assert!(lines == 153 || lines == 154, "{} lines", lines);
+        longer += (lines == 154) as u64;
+        run(&mut cycles, t_cycles);
+
+        let host = vsync as f64 * T_CYCLES_PER_SECOND as f64 / 60.0;
+        let drift = (cycles - start) as f64 - host;
+        assert!(drift.abs() < LINE_T_CYCLES as f64, "drift {}", drift);
+    }
+    assert!(longer > 0);
+}
+
+#[test]
+fn frame_locked_runs_one_frame_per_vsync() {
+    let mut cycles = 0;
+    let mut sync = VideoSync::new(60.0, SyncStrategy::FrameLocked, cycles);
+    for vsync in 1..=TEN_MINUTES_AT_60HZ {
+        let t_cycles = sync.vsync(cycles);
+        run(&mut cycles, t_cycles);
+        assert_eq!(cycles / FRAME_T_CYCLES as u64, vsync);
+    }
+}
+
+#[test]
+fn audio_follows_video() {
+    for &strategy in &[SyncStrategy::RealTime, SyncStrategy::FrameLocked] {
+        let mut cycles = 0;
+        let mut video = VideoSync::new(60.0, strategy, cycles);
+        let mut audio = AudioSync::new(48000, &video, cycles);
+        let mut samples = 0;
+        for vsync in 1..=TEN_MINUTES_AT_60HZ {
+            let t_cycles = video.vsync(cycles);
+            run(&mut cycles, t_cycles);
+            samples += audio.samples_until(cycles);
+
+            // However fast emulation runs, the host plays 800 samples per vsync
+            let expected = vsync * 800;
+            assert!(
+                samples.max(expected) - samples.min(expected) < 800,
+                "{:?}: {} samples after {} vsyncs",
+                strategy,
+                samples,
+                vsync
+            );
+        }
+        let emulated_seconds = cycles as f64 / video.cycles_per_second();
+        assert!((emulated_seconds - 600.0).abs() < 1.0 / 60.0);
+    }
+    let video = VideoSync::new(60.0, SyncStrategy::RealTime, 0);
+    let audio = AudioSync::new(48000, &video, 0);
+    assert!((audio.cycles_per_sample() - 87.381).abs() < 0.001);
+}
+
+#[test]
+fn drives_a_gameboy() {
+    let mut gameboy = Gameboy::with_program(&[0x18, 0xFE], 0x0150).unwrap();
+    let start = gameboy.cycles();
+    let mut sync = VideoSync::new(60.0, SyncStrategy::RealTime, start);
+    for _ in 0..60 {
+        let t_cycles = sync.vsync(gameboy.cycles());
+        gameboy.run_cycles(t_cycles);
+    }
+    let second = (gameboy.cycles() - start) as i64 - T_CYCLES_PER_SECOND as i64;
+    assert!(second.abs() < LINE_T_CYCLES as i64);
+}
@@ -0,0 +1,73 @@
+//! Running many Gameboys at once. Run with `cargo test -p gb_core --features batch --test batch`.
+#![cfg(feature = "batch")]
+
+use std::sync::Arc;
+
+use gb_core::gameboy::{batch::BatchRunner, joypad::Button, test_pattern, Gameboy, ResetKind};
+
+const COUNTER_ROM: &[u8] = include_bytes!("fixtures/start_counter.gb");
+
+/// 64-bit FNV-1a of the last frame
+fn frame_hash(gameboy: &Gameboy) -> u64 {
+    gameboy
+        .get_frame()
+        .iter()
+        .fold(0xCBF2_9CE4_8422_2325, |hash, &b| {
+            (hash ^ b as u64).wrapping_mul(0x0000_0100_0000_01B3)
+        })
+}
+
+#[test]
+fn instances_run_concurrently_and_agree() {
+    let runner = BatchRunner::with_threads(8).unwrap();
+    let hashes = runner.run_roms(vec![test_pattern::rom(); 8], |mut gameboy| {
+        gameboy.run_frames(30);
+        frame_hash(&gameboy)
+    });
+    assert_eq!(hashes.len(), 8);
+    let first = hashes[0].clone().unwrap();
+    assert!(hashes.iter().all(|hash| *hash == Ok(first)));
+    assert_eq!(
+        Gameboy::new(test_pattern::rom())
+            .map(|mut gameboy| {
+                gameboy.reset(ResetKind::PowerCycle);
+                gameboy.run_frames(30);
+                frame_hash(&gameboy)
+            })
+            .unwrap(),
+        first,
+        "running in a batch gives the same result as running alone"
+    );
+}
+
+#[test]
+fn inputs_share_one_rom() {
+    // Hold Start for a different number of frames in each instance. The counter ROM counts
+    // presses, so every instance but the first sees one.
+    let inputs: Vec<u32> = (0..8).collect();
+    let hashes = BatchRunner::new()
+        .run_inputs(Arc::from(COUNTER_ROM), inputs, |mut gameboy, held| {
+            gameboy.run_frames(30);
+            gameboy.joypad.press(Button::Start);
+            gameboy.run_frames(held);
+            gameboy.joypad.release(Button::Start);
+            gameboy.run_frames(30);
+            frame_hash(&gameboy)
+        })
+        .unwrap();
+    assert_eq!(hashes.len(), 8);
+    assert_ne!(hashes[0], hashes[1]);
+    assert!(hashes[2..].iter().all(|&hash| hash == hashes[1]));
+}
+
+#[test]
+fn bad_roms_fail_alone() {
+    let roms = vec![test_pattern::rom(), vec![0; 0x10], test_pattern::rom()];
+    let results = BatchRunner::new().run_roms(roms, |mut gameboy| {
+        gameboy.run_frames(1);
+        gameboy.cycles()
+    });
+    assert!(results[0].is_ok());
+    assert!(results[1].is_err());
+    assert!(results[2].is_ok());
+}
@@ -0,0 +1,163 @@
+use gb_core::gameboy::{
+    ppu::{
+        consts::{FRAME_T_CYCLES, LINE_T_CYCLES},
+        registers::LCDC,
+        Ppu,
+    },
+    test_pattern, Gameboy, PpuBackend, ResetKind,
+};
+use gb_cpu::CpuOutputPins;
+
+/// Where the PPU says it is, and how far it says the next mode change and VBlank are
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Position {
+    line: u8,
+    dot: u16,
+    until_mode_change: u16,
+    until_vblank: u32,
+}
+
+fn position(ppu: &Ppu) -> Position {
+    Position {
+        line: ppu.current_line(),
+        dot: ppu.current_dot_in_line(),
+        until_mode_change: ppu.dots_until_mode_change(),
+        until_vblank: ppu.dots_until_vblank(),
+    }
+}
+
+fn mode(ppu: &Ppu) -> u8 {
+    ppu.stat.bits() & 3
+}
+
+/// Clock `ppu` for `dots` dots, and return its position before each dot and its mode after it
+fn record(ppu: &mut Ppu, dots: usize) -> Vec<(Position, u8)> {
+    (0..dots)
+        .map(|_| {
+            let position = position(ppu);
+            ppu.clock_t_state();
+            (position, mode(ppu))
+        })
+        .collect()
+}
+
+/// Check that each countdown in `recording` runs out on the dot where the mode changes, or VBlank
+/// starts, where `mode` is the mode before the first dot
+fn check_countdowns(recording: &[(Position, u8)], mode: u8) {
+    let modes: Vec<u8> = std::iter::once(mode)
+        .chain(recording.iter().map(|&(_, mode)| mode))
+        .collect();
+    // Worked out backwards from the end, for each dot: how many dots until the mode changes, and
+    // until VBlank starts, counting that dot
+    let (mut change, mut vblank) = (None, None);
+    for dot in (0..recording.len()).rev() {
+        let (before, after) = (modes[dot], modes[dot + 1]);
+        change = if after != before {
+            Some(1)
+        } else {
+            change.map(|n| n + 1)
+        };
+        vblank = if after == 1 && before != 1 {
+            Some(1)
+        } else {
+            vblank.map(|n| n + 1)
+        };
+        let position = recording[dot].0;
+        if let Some(change) = change {
+            assert_eq!(position.until_mode_change, change, "{:?}", position);
+        }
+        if let Some(vblank) = vblank {
+            assert_eq!(position.until_vblank, vblank, "{:?}", position);
+        }
+    }
+}
+
+#[test]
+fn counters_follow_the_mode_schedule() {
+    for &backend in &[PpuBackend::DotAccurate, PpuBackend::Scanline] {
+        let mut ppu = Ppu::new();
+        ppu.backend = backend;
+        let recording = record(&mut ppu, 2 * FRAME_T_CYCLES);
+        for (dots, &(position, _)) in recording.iter().enumerate() {
+            let dots = dots % FRAME_T_CYCLES;
+            assert_eq!(position.line as usize, dots / LINE_T_CYCLES);
+            assert_eq!(position.dot as usize, dots % LINE_T_CYCLES);
+        }
+        // Mode 2 for 80 dots, mode 3 for 174 with nothing to draw, then HBlank
+        let (start, _) = recording[456 * 10];
+        assert_eq!(start.until_mode_change, 1);
+        assert_eq!(recording[456 * 10 + 1].0.until_mode_change, 80);
+        assert_eq!(recording[456 * 10 + 81].0.until_mode_change, 174);
+        assert_eq!(recording[456 * 10 + 255].0.until_mode_change, 202);
+        check_countdowns(&recording, 0);
+    }
+}
+
+#[test]
+fn line_153_is_not_ly() {
+    let mut ppu = Ppu::new();
+    record(&mut ppu, 153 * LINE_T_CYCLES + 10);
+    assert_eq!(ppu.ly, 0);
+    assert_eq!(ppu.current_line(), 153);
+    assert_eq!(ppu.current_dot_in_line(), 10);
+    // VBlank doesn't end until the line does
+    assert_eq!(ppu.dots_until_mode_change(), 456 - 10 + 1);
+    assert_eq!(
+        ppu.dots_until_vblank(),
+        (456 - 10 + 144 * LINE_T_CYCLES + 1) as u32
+    );
+}
+
+#[test]
+fn first_line_after_lcd_on() {
+    let mut ppu = Ppu::new();
+    ppu.lcdc.remove(LCDC::LCD_ENABLE);
+    record(&mut ppu, 1000);
+    assert_eq!(ppu.current_line(), 0);
+    assert_eq!(ppu.current_dot_in_line(), 0);
+    assert_eq!(ppu.dots_until_mode_change(), u16::MAX);
+    assert_eq!(ppu.dots_until_vblank(), u32::MAX);
+
+    ppu.perform_io(
+        CpuOutputPins::Write {
+            addr: 0xFF40,
+            data: 0x91,
+        },
+        &mut 0xFF,
+        &mut 0,
+    );
+    let recording = record(&mut ppu, FRAME_T_CYCLES + 1000);
+    // STAT stays in mode 0 through the OAM scan, and the line is 4 dots short
+    assert_eq!(recording[1].0.until_mode_change, 80);
+    assert_eq!(
+        recording[1].0.until_vblank,
+        (144 * LINE_T_CYCLES - 4) as u32
+    );
+    assert_eq!((recording[451].0.line, recording[451].0.dot), (0, 451));
+    assert_eq!((recording[452].0.line, recording[452].0.dot), (1, 0));
+    assert_eq!(recording[452].0.until_mode_change, 1);
+    check_countdowns(&recording[1..], 0);
+}
+
+#[test]
+fn run_until_line_returns_the_lines_drawn() {
+    let mut gameboy = Gameboy::new(test_pattern::rom()).unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    gameboy.run_frames(3);
+
+    let lines = gameboy.run_until_line(72).len() / 160;
+    assert_eq!(gameboy.ppu.current_line(), 72);
+    assert_eq!(lines, 72);
+    let top = gameboy.run_until_line(144).to_vec();
+    assert_eq!(top.len(), 72 * 160);
+    assert_eq!(gameboy.ppu.current_line(), 144);
+
+    // The frame was completed when VBlank started
+    let frame = gameboy.get_frame();
+    let bottom: Vec<_> = (72..144).flat_map(|y| frame.row_colors(y)).collect();
+    assert_eq!(top, bottom);
+
+    // Already on the line, so a whole frame goes by
+    assert_eq!(gameboy.run_until_line(144).len(), 144 * 160);
+    assert_eq!(gameboy.run_until_line(0).len(), 0);
+}
@@ -0,0 +1,69 @@
+use gb_core::gameboy::{breakpoints::Breakpoint, ppu::consts::FRAME_T_CYCLES, Gameboy};
+use gb_cpu::assembler::assemble;
+
+/// Counts up in C, storing each count at $C345
+const COUNT: &str = "
+    .org $0150
+        ld c, 0
+    loop:
+        inc c
+        ld a, c
+        ld [$C345], a
+        jr loop
+";
+
+fn counter() -> Gameboy {
+    Gameboy::with_program(&assemble(COUNT).unwrap(), 0x0150).unwrap()
+}
+
+const LOOP: u16 = 0x0152;
+const FRAME: u64 = FRAME_T_CYCLES as u64;
+
+#[test]
+fn unconditional_breakpoints_stop_every_time() {
+    let mut gameboy = counter();
+    let id = gameboy.add_breakpoint(Breakpoint::at(LOOP));
+    for count in 0..3 {
+        assert_eq!(gameboy.run_until_breakpoint(FRAME), Some(id));
+        // The INC hasn't run yet
+        assert_eq!(gameboy.cpu.cpu.registers.c, count);
+        assert_eq!(gameboy.eval("PC"), Ok(LOOP));
+    }
+
+    assert!(gameboy.remove_breakpoint(id));
+    assert!(!gameboy.remove_breakpoint(id));
+    assert_eq!(gameboy.run_until_breakpoint(FRAME), None);
+}
+
+#[test]
+fn conditional_breakpoint_fires_on_the_fifth_iteration() {
+    let mut gameboy = counter();
+    let breakpoint = Breakpoint::at(LOOP)
+        .with_condition("[$C345] >= 4 && A == 4")
+        .unwrap();
+    let id = gameboy.add_breakpoint(breakpoint);
+    assert_eq!(gameboy.run_until_breakpoint(FRAME), Some(id));
+    let hit_at = gameboy.cycles();
+    assert_eq!(gameboy.cpu.cpu.registers.c, 4);
+    // It doesn't fire again until C wraps around, since A has moved on
+    assert_eq!(gameboy.run_until_breakpoint(200 * 28), None);
+
+    // The same place as stopping at the loop five times
+    let mut gameboy = counter();
+    gameboy.add_breakpoint(Breakpoint::at(LOOP));
+    for _ in 0..5 {
+        gameboy.run_until_breakpoint(FRAME).unwrap();
+    }
+    assert_eq!(gameboy.cycles(), hit_at);
+}
+
+#[test]
+fn conditions_must_parse() {
+    assert_eq!(
+        Breakpoint::at(LOOP)
+            .with_condition("A ==")
+            .unwrap_err()
+            .offset(),
+        4
+    );
+}
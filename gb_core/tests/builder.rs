@@ -0,0 +1,157 @@
+use std::ops::RangeInclusive;
+
+use gb_core::{
+    gameboy::{Chip, ClockContext, Gameboy, GameboyBuilder, Model, ResetKind},
+    GbError,
+};
+use gb_cpu::CpuOutputPins;
+
+/// A cartridge made entirely of writable memory
+struct RamCart {
+    data: Vec<u8>,
+}
+
+impl RamCart {
+    /// Create a cartridge with `code` placed at `offset`
+    fn with_code(code: &[u8], offset: usize) -> Self {
+        let mut data = vec![0; 0x8000];
+        data[offset..offset + code.len()].copy_from_slice(code);
+        RamCart { data }
+    }
+}
+
+impl Chip for RamCart {
+    fn clock(
+        &mut self,
+        input: CpuOutputPins,
+        data: &mut u8,
+        _interrupt_request: &mut u8,
+        _ctx: &ClockContext,
+    ) {
+        match input {
+            CpuOutputPins::Read {
+                addr: addr @ 0x0000..=0x7FFF,
+            } => *data = self.data[addr as usize],
+            CpuOutputPins::Write {
+                addr: addr @ 0x0000..=0x7FFF,
+                data,
+            } => self.data[addr as usize] = data,
+            _ => (),
+        }
+    }
+
+    fn chip_select(&self) -> Vec<RangeInclusive<u16>> {
+        vec![0x0000..=0x7FFF]
+    }
+}
+
+/// A chip that claims the work RAM area
+struct WramShadow;
+
+impl Chip for WramShadow {
+    fn clock(
+        &mut self,
+        _input: CpuOutputPins,
+        _data: &mut u8,
+        _interrupt_request: &mut u8,
+        _ctx: &ClockContext,
+    ) {
+    }
+
+    fn chip_select(&self) -> Vec<RangeInclusive<u16>> {
+        vec![0xD000..=0xD0FF]
+    }
+}
+
+fn run_instructions(gameboy: &mut Gameboy, n: usize) {
+    for _ in 0..n {
+        gameboy.step_instruction();
+    }
+}
+
+#[test]
+fn run_from_ram_cart() {
+    #[rustfmt::skip]
+    let code = [
+        0x3E, 0x5A,       // LD A, $5A
+        0xEA, 0x00, 0xC0, // LD ($C000), A
+        0xEA, 0x00, 0x20, // LD ($2000), A
+        0xFA, 0x00, 0x20, // LD A, ($2000)
+        0x3C,             // INC A
+        0xEA, 0x01, 0xC0, // LD ($C001), A
+        0x18, 0xFE,       // JR -2
+    ];
+    let mut gameboy = GameboyBuilder::new()
+        .cartridge(Box::new(RamCart::with_code(&code, 0x100)))
+        .model(Model::Dmg)
+        .build()
+        .unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+
+    run_instructions(&mut gameboy, 10);
+
+    assert_eq!(gameboy.model(), Model::Dmg);
+    assert_eq!(gameboy.memory.read(0xC000), Ok(0x5A));
+    assert_eq!(gameboy.memory.read(0xC001), Ok(0x5B));
+}
+
+#[test]
+fn boot_rom_overlay() {
+    let mut boot_rom = vec![0; 0x100];
+    #[rustfmt::skip]
+    boot_rom[..4].copy_from_slice(&[
+        0x3E, 0x01, // LD A, $01
+        0xE0, 0x50, // LDH ($50), A
+    ]);
+
+    #[rustfmt::skip]
+    let code = [
+        0xFA, 0x00, 0x00, // LD A, ($0000)
+        0xEA, 0x00, 0xC0, // LD ($C000), A
+        0x18, 0xFE,       // JR -2
+    ];
+    let mut cart = RamCart::with_code(&code, 0x04);
+    cart.data[0] = 0x42;
+
+    let mut gameboy = GameboyBuilder::new()
+        .cartridge(Box::new(cart))
+        .boot_rom(boot_rom)
+        .build()
+        .unwrap();
+
+    run_instructions(&mut gameboy, 6);
+
+    assert_eq!(gameboy.memory.read(0xC000), Ok(0x42));
+}
+
+#[test]
+fn boot_rom_wrong_size() {
+    let result = GameboyBuilder::new()
+        .cartridge(Box::new(RamCart::with_code(&[], 0)))
+        .boot_rom(vec![0; 0x80])
+        .build();
+    assert!(matches!(result, Err(GbError::InvalidRom(_))));
+}
+
+#[test]
+fn missing_cartridge() {
+    assert!(matches!(
+        GameboyBuilder::new().build(),
+        Err(GbError::InvalidState(_))
+    ));
+}
+
+#[test]
+fn chip_conflict() {
+    let result = Gameboy::builder()
+        .cartridge(Box::new(RamCart::with_code(&[], 0)))
+        .chip(Box::new(WramShadow))
+        .build();
+    assert_eq!(result.err(), Some(GbError::ChipConflict(0xD000)));
+
+    let result = Gameboy::builder()
+        .cartridge(Box::new(RamCart::with_code(&[], 0)))
+        .chip(Box::new(RamCart::with_code(&[], 0)))
+        .build();
+    assert_eq!(result.err(), Some(GbError::ChipConflict(0x0000)));
+}
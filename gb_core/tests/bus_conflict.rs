@@ -0,0 +1,121 @@
+use std::{
+    ops::RangeInclusive,
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Arc,
+    },
+};
+
+use gb_core::{
+    gameboy::{
+        cart::header::flat_rom,
+        events::{Event, EventMask},
+        Chip, ClockContext, Gameboy, ResetKind,
+    },
+    GbError,
+};
+use gb_cpu::CpuOutputPins;
+
+/// A byte register over $D000-$D0FF, which overlaps work RAM. Its value is shared with the test.
+struct Latch(Arc<AtomicU8>);
+
+impl Chip for Latch {
+    fn clock(
+        &mut self,
+        input: CpuOutputPins,
+        data: &mut u8,
+        _interrupt_request: &mut u8,
+        _ctx: &ClockContext,
+    ) {
+        match input {
+            CpuOutputPins::Read {
+                addr: 0xD000..=0xD0FF,
+            } => *data = self.0.load(Ordering::Relaxed),
+            CpuOutputPins::Write {
+                addr: 0xD000..=0xD0FF,
+                data,
+            } => self.0.store(data, Ordering::Relaxed),
+            _ => (),
+        }
+    }
+
+    fn chip_select(&self) -> Vec<RangeInclusive<u16>> {
+        vec![0xD000..=0xD0FF]
+    }
+
+    fn name(&self) -> &'static str {
+        "latch"
+    }
+}
+
+#[rustfmt::skip]
+const PROGRAM: [u8; 13] = [
+    0x3E, 0x3C,       // LD A, $3C
+    0xEA, 0x00, 0xD0, // LD ($D000), A
+    0xFA, 0x00, 0xD0, // LD A, ($D000)
+    0xEA, 0x00, 0xC0, // LD ($C000), A
+    0x18, 0xFE,       // JR -2
+];
+
+fn gameboy(latch: &Arc<AtomicU8>) -> Gameboy {
+    let mut gameboy = Gameboy::builder()
+        .rom(flat_rom(&PROGRAM, 0x0150, "").unwrap())
+        .chip(Box::new(Latch(latch.clone())))
+        .allow_chip_conflicts()
+        .build()
+        .unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    gameboy
+}
+
+#[test]
+fn writes_reach_every_claimant_and_reads_are_anded() {
+    let latch = Arc::new(AtomicU8::new(0));
+    let mut gameboy = gameboy(&latch);
+    // NOP and JP at the entry point, LD A, and the write, up to the fetch of the read
+    for _ in 0..5 {
+        gameboy.step_instruction();
+    }
+    assert_eq!(latch.load(Ordering::Relaxed), 0x3C);
+    assert_eq!(gameboy.peek(0xD000), 0x3C);
+
+    latch.store(0x0F, Ordering::Relaxed);
+    gameboy.run_cycles(4 * 16);
+    assert_eq!(gameboy.peek(0xC000), 0x3C & 0x0F);
+}
+
+#[cfg(any(debug_assertions, feature = "strict-bus"))]
+#[test]
+fn conflicts_are_reported() {
+    let latch = Arc::new(AtomicU8::new(0));
+    let mut gameboy = gameboy(&latch);
+    let events = gameboy.subscribe(EventMask::BUS_CONFLICT);
+    gameboy.run_cycles(4 * 20);
+
+    let events: Vec<_> = events.drain().map(|record| record.event).collect();
+    let conflict = Event::BusConflict {
+        addr: 0xD000,
+        chips: ["memory", "latch"],
+    };
+    // The write, then the read
+    assert_eq!(events, [conflict, conflict]);
+}
+
+#[test]
+fn addresses_the_gameboy_handles_stay_reserved() {
+    struct InterruptFlag;
+    impl Chip for InterruptFlag {
+        fn clock(&mut self, _: CpuOutputPins, _: &mut u8, _: &mut u8, _: &ClockContext) {}
+
+        fn chip_select(&self) -> Vec<RangeInclusive<u16>> {
+            vec![0xFF0E..=0xFF0F]
+        }
+    }
+
+    let result = Gameboy::builder()
+        .rom(flat_rom(&PROGRAM, 0x0150, "").unwrap())
+        .chip(Box::new(InterruptFlag))
+        .allow_chip_conflicts()
+        .build();
+    assert_eq!(result.err(), Some(GbError::ChipConflict(0xFF0F)));
+}
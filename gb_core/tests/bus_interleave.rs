@@ -0,0 +1,134 @@
+use gb_core::gameboy::{
+    events::{Event, EventMask},
+    BusInterleave, Gameboy, GameboyBuilder, ResetKind,
+};
+use gb_cpu::assembler::assemble;
+
+/// Sets BGP to $FC a few M-cycles into mode 3 of line 40, and back to $E4 at the start of line 41
+const MID_LINE_BGP: &str = "
+    .org $0150
+        ld a, $E4
+        ldh [$47], a
+    frame:
+        ldh a, [$44]
+        cp 40
+        jr nz, frame
+    mode_3:
+        ldh a, [$41]
+        and 3
+        cp 3
+        jr nz, mode_3
+        nop
+        nop
+        nop
+        nop
+        ld a, $FC
+        ldh [$47], a
+    next_line:
+        ldh a, [$44]
+        cp 41
+        jr nz, next_line
+        ld a, $E4
+        ldh [$47], a
+        jr frame
+";
+
+/// A Gameboy running [`MID_LINE_BGP`], with every background pixel in color 1
+fn gameboy(interleave: BusInterleave) -> Gameboy {
+    let rom = gb_core::gameboy::cart::header::flat_rom(
+        &assemble(MID_LINE_BGP).unwrap(),
+        0x0150,
+        "INTERLEAVE",
+    )
+    .unwrap();
+    let mut gameboy = GameboyBuilder::new()
+        .rom(rom)
+        .bus_interleave(interleave)
+        .build()
+        .unwrap();
+    for row in gameboy.ppu.tile_data[..16].chunks_mut(2) {
+        row.copy_from_slice(&[0xFF, 0x00]);
+    }
+    gameboy
+}
+
+/// The first pixel of line 40 drawn with the new palette, after checking that the rest of the
+/// frame was drawn with the old one
+fn palette_change(gameboy: &mut Gameboy) -> usize {
+    let frame = gameboy.run_frames(3);
+    for (y, row) in frame.rows().enumerate().filter(|&(y, _)| y != 40) {
+        assert!(row.iter().all(|&shade| shade == 1), "line {}", y);
+    }
+    let row = frame.row(40);
+    let x = row.iter().position(|&shade| shade == 3).unwrap();
+    assert!(row[..x].iter().all(|&shade| shade == 1));
+    assert!(row[x..].iter().all(|&shade| shade == 3));
+    x
+}
+
+#[test]
+fn writes_land_on_the_last_dot() {
+    let m_cycle = palette_change(&mut gameboy(BusInterleave::MCycle));
+    let t_cycle = palette_change(&mut gameboy(BusInterleave::TCycle));
+    // Mode 3 outputs a pixel every dot once it is underway, and 3 of the M-cycle's dots run
+    // before the write lands
+    assert!(m_cycle > 8);
+    assert_eq!(t_cycle, m_cycle + 3);
+}
+
+#[test]
+fn interleave_can_change_between_frames() {
+    let mut gameboy = gameboy(BusInterleave::MCycle);
+    let m_cycle = palette_change(&mut gameboy);
+    gameboy.ppu.interleave = BusInterleave::TCycle;
+    assert_eq!(palette_change(&mut gameboy), m_cycle + 3);
+    gameboy.reset(ResetKind::PowerCycle);
+    assert_eq!(gameboy.ppu.interleave, BusInterleave::TCycle);
+}
+
+fn hazards(gameboy: &mut Gameboy, frames: u32) -> Vec<Event> {
+    let receiver = gameboy.subscribe(EventMask::ORDERING_HAZARD);
+    gameboy.run_frames(frames);
+    receiver.drain().map(|record| record.event).collect()
+}
+
+#[test]
+fn mid_line_writes_are_hazards() {
+    for interleave in [BusInterleave::MCycle, BusInterleave::TCycle] {
+        let mut gameboy = gameboy(interleave);
+        assert!(hazards(&mut gameboy, 2).is_empty());
+
+        gameboy.set_ordering_check(true);
+        let hazards = hazards(&mut gameboy, 2);
+        // Only the write during mode 3, and not the one in mode 2, when BGP isn't used
+        assert_eq!(hazards.len(), 2, "{:?}", interleave);
+        for hazard in hazards {
+            assert!(
+                matches!(
+                    hazard,
+                    Event::OrderingHazard {
+                        addr: 0xFF47,
+                        value: 0xFC,
+                        ly: 40,
+                        dot: 80..=252,
+                    }
+                ),
+                "{:?}",
+                hazard
+            );
+        }
+    }
+}
+
+#[test]
+fn hblank_writes_are_not_hazards() {
+    // Scrolls the background during every HBlank
+    let mut gameboy = GameboyBuilder::new()
+        .rom(&include_bytes!("fixtures/ppu_scene.gb")[..])
+        .bus_interleave(BusInterleave::TCycle)
+        .build()
+        .unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    gameboy.set_ordering_check(true);
+    assert_eq!(hazards(&mut gameboy, 3), []);
+}
@@ -0,0 +1,101 @@
+use gb_core::gameboy::{
+    bus_trace::{BusOp, BusTrace, ChipId},
+    BusMaster, Gameboy, ResetKind,
+};
+
+/// Copies $C000 to OAM, then counts in HRAM forever
+#[rustfmt::skip]
+const PROGRAM: [u8; 13] = [
+    0x3E, 0xC0,       // LD A, $C0
+    0xE0, 0x46,       // LDH (DMA), A
+    0xAF,             // XOR A
+    // loop:
+    0x3C,             // INC A
+    0xE0, 0x80,       // LDH ($80), A
+    0xEA, 0x00, 0xC0, // LD ($C000), A
+    0x18, 0xF8,       // JR loop
+];
+
+fn gameboy() -> Gameboy {
+    let mut gameboy = Gameboy::with_program(&PROGRAM, 0x0150).unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    gameboy
+}
+
+#[test]
+fn fetches_match_the_instructions_stepped() {
+    let mut gameboy = gameboy();
+    gameboy.start_bus_trace(BusTrace::with_capacity(1024));
+    for _ in 0..100 {
+        gameboy.step_instruction();
+        let pc = gameboy.pc_history().last().unwrap().pc;
+        // Stepping stops just after the next opcode is fetched
+        let fetch = gameboy.bus_trace().last().unwrap();
+        assert_eq!((fetch.op, fetch.addr), (BusOp::Read, pc));
+        assert_eq!(fetch.master, BusMaster::Cpu);
+        assert_eq!(fetch.chip, Some(ChipId::Cart));
+        assert_eq!(fetch.data, gameboy.peek(pc));
+    }
+}
+
+#[test]
+fn dma_and_chips_are_tagged() {
+    let mut gameboy = gameboy();
+    gameboy.start_bus_trace(BusTrace::with_capacity(1024));
+    gameboy.run_cycles(4 * 400);
+    let events: Vec<_> = gameboy.bus_trace().collect();
+    assert_eq!(events.len(), 400);
+    assert!(events.windows(2).all(|w| w[1].cycle == w[0].cycle + 4));
+
+    let dma: Vec<_> = events
+        .iter()
+        .filter(|event| event.master == BusMaster::Dma)
+        .collect();
+    assert_eq!(dma.len(), 160);
+    assert_eq!(dma[0].addr, 0xC000);
+    assert!(dma
+        .iter()
+        .all(|event| event.op == BusOp::Read && event.chip == Some(ChipId::Memory)));
+
+    let write = events.iter().find(|event| event.addr == 0xFF46).unwrap();
+    assert_eq!(write.op, BusOp::Write);
+    assert_eq!((write.data, write.chip), (0xC0, Some(ChipId::Ppu)));
+    let hram = events.iter().find(|event| event.addr == 0xFF80).unwrap();
+    assert_eq!((hram.op, hram.chip), (BusOp::Write, Some(ChipId::Memory)));
+}
+
+#[test]
+fn oldest_events_are_overwritten() {
+    let mut gameboy = gameboy();
+    assert_eq!(gameboy.bus_trace().count(), 0);
+    gameboy.start_bus_trace(BusTrace::with_capacity(16));
+    gameboy.run_cycles(4 * 100);
+    let events: Vec<_> = gameboy.bus_trace().collect();
+    assert_eq!(events.len(), 16);
+    assert_eq!(events[15].cycle, gameboy.cycles() - 4);
+    assert_eq!(gameboy.bus_trace().count(), 0);
+
+    gameboy.run_cycles(4);
+    let trace = gameboy.stop_bus_trace().unwrap();
+    assert_eq!((trace.len(), trace.capacity()), (1, 16));
+    gameboy.run_cycles(4);
+    assert_eq!(gameboy.bus_trace().count(), 0);
+}
+
+#[test]
+fn open_bus_has_no_chip() {
+    #[rustfmt::skip]
+    let mut gameboy = Gameboy::with_program(&[
+        0xF0, 0x0F, // LDH A, (IF)
+        0xF0, 0x03, // LDH A, ($03)
+        0x18, 0xFA, // JR -6
+    ], 0x0150).unwrap();
+    gameboy.start_bus_trace(BusTrace::with_capacity(64));
+    gameboy.run_cycles(4 * 16);
+    let events: Vec<_> = gameboy.bus_trace().collect();
+    let unmapped = events.iter().find(|event| event.addr == 0xFF03).unwrap();
+    assert_eq!((unmapped.data, unmapped.chip), (0xFF, None));
+    let interrupts = events.iter().find(|event| event.addr == 0xFF0F).unwrap();
+    assert_eq!(interrupts.chip, Some(ChipId::Interrupts));
+    assert_eq!(interrupts.data & 0xE0, 0xE0);
+}
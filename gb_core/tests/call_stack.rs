@@ -0,0 +1,139 @@
+use gb_core::gameboy::{call_stack::StackFrame, cart::header, Gameboy, ResetKind};
+use gb_cpu::assembler::assemble;
+
+/// A ROM that starts running `main` at $0150 and has `function` at $0200
+fn rom_with_code(main: &[u8], function: &[u8]) -> Vec<u8> {
+    let mut rom = header::flat_rom(main, 0x0150, "").unwrap();
+    rom[0x200..0x200 + function.len()].copy_from_slice(function);
+    rom
+}
+
+fn gameboy(rom: Vec<u8>) -> Gameboy {
+    let mut gameboy = Gameboy::new(rom).unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    gameboy.track_call_stack(true);
+    gameboy
+}
+
+fn call(call_site: u16, target: u16, sp_at_entry: u16) -> StackFrame {
+    StackFrame {
+        call_site,
+        target,
+        sp_at_entry,
+        bank: 1,
+        interrupt: false,
+    }
+}
+
+/// Step through `steps` instructions, and return the address of each instruction fetched along
+/// with the call stack at that point
+fn trace(gameboy: &mut Gameboy, steps: usize) -> Vec<(u16, Vec<StackFrame>)> {
+    (0..steps)
+        .map(|_| {
+            gameboy.step_instruction();
+            let addr = gameboy.cpu.cpu.registers.pc.wrapping_sub(1);
+            (addr, gameboy.call_stack().to_vec())
+        })
+        .collect()
+}
+
+#[test]
+fn nested_calls_and_returns() {
+    let code = assemble(
+        "
+        .org $0150
+            call outer
+            jr $
+        .org $0200
+        outer:
+            call inner
+            ret
+        .org $0210
+        inner:
+            xor a
+            call nz, innermost
+            call z, innermost
+            ret
+        .org $0220
+        innermost:
+            ret
+        ",
+    )
+    .unwrap();
+    let mut gameboy = gameboy(header::flat_rom(&code, 0x0150, "").unwrap());
+
+    let trace = trace(&mut gameboy, 12);
+    let outer = call(0x0150, 0x0200, 0xFFFC);
+    let inner = call(0x0200, 0x0210, 0xFFFA);
+    let innermost = call(0x0214, 0x0220, 0xFFF8);
+    let stack_at = |addr: u16| {
+        trace
+            .iter()
+            .find(|(a, _)| *a == addr)
+            .map(|(_, stack)| stack.clone())
+            .unwrap_or_else(|| panic!("{:#06X} was never executed", addr))
+    };
+    assert_eq!(stack_at(0x0200), [outer]);
+    assert_eq!(stack_at(0x0210), [outer, inner]);
+    // The untaken call doesn't add a frame
+    assert_eq!(stack_at(0x0214), [outer, inner]);
+    assert_eq!(stack_at(0x0220), [outer, inner, innermost]);
+    assert_eq!(stack_at(0x0217), [outer, inner]);
+    assert_eq!(stack_at(0x0203), [outer]);
+    assert_eq!(stack_at(0x0153), []);
+}
+
+/// The timer interrupt fires while the CPU is inside a function, and the handler never returns
+#[test]
+fn interrupt_frame_on_top() {
+    #[rustfmt::skip]
+    let mut rom = rom_with_code(
+        &[
+            0x3E, 0x05,       // LD A, $05
+            0xE0, 0x07,       // LDH (TAC), A
+            0x3E, 0x04,       // LD A, $04
+            0xE0, 0xFF,       // LDH (IE), A
+            0xFB,             // EI
+            0xCD, 0x00, 0x02, // CALL $0200
+        ],
+        &[0x18, 0xFE], // JR -2
+    );
+    rom[0x50..0x52].copy_from_slice(&[0x18, 0xFE]); // JR -2
+    let mut gameboy = gameboy(rom);
+    gameboy.run_frames(1);
+
+    assert_eq!(
+        gameboy.call_stack(),
+        [
+            call(0x0159, 0x0200, 0xFFFC),
+            StackFrame {
+                call_site: 0x0200,
+                target: 0x0050,
+                sp_at_entry: 0xFFFA,
+                bank: 1,
+                interrupt: true,
+            }
+        ]
+    );
+}
+
+/// A function that discards its return address and jumps back to the caller
+#[test]
+fn resynchronizes_when_sp_is_moved() {
+    #[rustfmt::skip]
+    let rom = rom_with_code(
+        &[0xCD, 0x00, 0x02], // CALL $0200
+        &[
+            0x33,             // INC SP
+            0x33,             // INC SP
+            0xC3, 0x50, 0x01, // JP $0150
+        ],
+    );
+    let mut gameboy = gameboy(rom);
+    let trace = trace(&mut gameboy, 1000);
+    assert!(trace.iter().all(|(_, stack)| stack.len() <= 1));
+
+    gameboy.track_call_stack(false);
+    gameboy.step_instruction();
+    assert!(gameboy.call_stack().is_empty());
+}
@@ -0,0 +1,88 @@
+//! Screenshots and GIF recordings.
+//!
+//! Run with `cargo test -p gb_core --features capture --test capture`.
+#![cfg(feature = "capture")]
+
+use gb_core::gameboy::{capture::GifOptions, ppu::color::COLORS, test_pattern, Gameboy, ResetKind};
+
+fn gameboy() -> Gameboy {
+    let mut gameboy = Gameboy::new(test_pattern::rom()).unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    gameboy
+}
+
+fn decode_gif(bytes: &[u8]) -> (gif::Repeat, Vec<u16>, Vec<Vec<u8>>) {
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::Indexed);
+    let mut decoder = options.read_info(bytes).unwrap();
+    let (mut delays, mut frames) = (Vec::new(), Vec::new());
+    while let Some(frame) = decoder.read_next_frame().unwrap() {
+        delays.push(frame.delay);
+        frames.push(frame.buffer.to_vec());
+    }
+    (decoder.repeat(), delays, frames)
+}
+
+#[test]
+fn screenshot_is_a_png_of_the_front_frame() {
+    let mut gameboy = gameboy();
+    gameboy.run_frames(3);
+    let png = gameboy.screenshot();
+
+    assert_eq!(png[..8], [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]);
+    // IHDR: 160x144, 8 bits per channel, RGBA
+    assert_eq!(png[12..16], *b"IHDR");
+    assert_eq!(png[16..20], 160u32.to_be_bytes());
+    assert_eq!(png[20..24], 144u32.to_be_bytes());
+    assert_eq!(png[24..26], [8, 6]);
+
+    let mut reader = png::Decoder::new(&png[..]).read_info().unwrap();
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    reader.next_frame(&mut pixels).unwrap();
+    for (i, pixel) in pixels.chunks_exact(4).enumerate() {
+        let (x, y) = (i % 160, i / 160);
+        let color = COLORS[test_pattern::shade(x, y) as usize];
+        assert_eq!(pixel, color.to_le_bytes(), "pixel ({}, {})", x, y);
+    }
+}
+
+#[test]
+fn gif_records_every_frame_at_the_real_rate() {
+    let mut gameboy = gameboy();
+    gameboy.run_frames(3);
+    gameboy.start_gif_recording(GifOptions::default());
+    assert!(gameboy.is_recording());
+    gameboy.run_frames(60);
+    let gif = gameboy.stop_recording().unwrap();
+    assert!(!gameboy.is_recording());
+
+    assert_eq!(gif[..6], *b"GIF89a");
+    let (repeat, delays, frames) = decode_gif(&gif);
+    assert_eq!(repeat, gif::Repeat::Infinite);
+    assert_eq!(frames.len(), 60);
+    // 60 frames last 1.0046s
+    assert_eq!(delays.iter().map(|&d| d as u32).sum::<u32>(), 100);
+    assert!(delays.iter().all(|&d| d == 1 || d == 2));
+    for (i, &index) in frames[59].iter().enumerate() {
+        assert_eq!(index, test_pattern::shade(i % 160, i / 160));
+    }
+}
+
+#[test]
+fn gif_can_skip_frames_and_play_once() {
+    let mut gameboy = gameboy();
+    gameboy.start_gif_recording(GifOptions {
+        every_nth: 3,
+        repeat: false,
+    });
+    gameboy.run_frames(60);
+    let (repeat, delays, frames) = decode_gif(&gameboy.stop_recording().unwrap());
+    assert_eq!(repeat, gif::Repeat::Finite(0));
+    assert_eq!(frames.len(), 20);
+    assert!(delays.iter().all(|&d| d == 5));
+}
+
+#[test]
+fn stopping_without_recording() {
+    assert_eq!(gameboy().stop_recording(), None);
+}
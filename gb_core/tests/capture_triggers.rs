@@ -0,0 +1,112 @@
+#[allow(dead_code)]
+mod common;
+
+use common::{frame_counter, rom_with_handlers};
+use gb_core::gameboy::{breakpoints::Breakpoint, capture_triggers::Trigger, Gameboy};
+
+/// Run after each VBlank is counted. Changes BGP each time so that every frame looks different.
+/// On the 42nd it writes $99 to $C001, and on the 50th it turns the LCD off for a frame.
+const EACH_FRAME: &str = "
+        ldh [$47], a
+        cp 42
+        jr nz, not_42
+        ld a, $99
+        ld [$C001], a
+    not_42:
+        cp 50
+        jr nz, done
+        ld hl, $FF40
+        res 7, [hl]
+    lcd_off:
+        ldh a, [$04]
+        and a
+        jr nz, lcd_off
+        set 7, [hl]
+    done:
+";
+
+fn gameboy() -> Gameboy {
+    Gameboy::new(rom_with_handlers(&frame_counter(EACH_FRAME), "TRIGGERS")).unwrap()
+}
+
+/// The first frame at the end of which $C001 holds $99, and what it looks like
+fn reference() -> (u64, Vec<u8>) {
+    let mut gameboy = gameboy();
+    while gameboy.peek(0xC001) != 0x99 {
+        gameboy.run_frames(1);
+    }
+    assert_eq!(gameboy.peek(0xC000), 42);
+    let frame = gameboy.get_frame().iter().copied().collect();
+    (gameboy.ppu.frame_count, frame)
+}
+
+#[test]
+fn memory_trigger_captures_once() {
+    let (number, pixels) = reference();
+    let mut gameboy = gameboy();
+    // Skipped frames are still drawn while a trigger is waiting
+    gameboy.set_frame_skip(3);
+    let id = gameboy.add_capture_trigger(Trigger::OnMemoryEquals {
+        addr: 0xC001,
+        value: 0x99,
+    });
+    gameboy.run_frames(60);
+
+    let captures = gameboy.take_captures();
+    assert_eq!(captures.len(), 1);
+    let (capture_id, capture_number, frame) = &captures[0];
+    assert_eq!((*capture_id, *capture_number), (id, number));
+    assert!(frame.iter().eq(pixels.iter()));
+    assert_eq!(gameboy.capture_triggers().count(), 0);
+    assert!(gameboy.take_captures().is_empty());
+}
+
+#[test]
+fn repeating_triggers_are_bounded() {
+    let mut gameboy = gameboy();
+    gameboy.set_capture_limit(5);
+    let id = gameboy.add_repeating_capture_trigger(Trigger::OnMemoryEquals {
+        addr: 0xC001,
+        value: 0x99,
+    });
+    let (number, _) = reference();
+    gameboy.run_frames(number as u32 + 9);
+
+    let captures = gameboy.take_captures();
+    let numbers: Vec<_> = captures.iter().map(|&(_, number, _)| number).collect();
+    assert_eq!(numbers, (number + 5..number + 10).collect::<Vec<_>>());
+    assert!(captures.iter().all(|&(capture_id, ..)| capture_id == id));
+    assert_eq!(gameboy.dropped_captures(), 5);
+
+    assert!(gameboy.remove_capture_trigger(id));
+    gameboy.run_frames(5);
+    assert!(gameboy.take_captures().is_empty());
+}
+
+#[test]
+fn frame_breakpoint_and_lcd_triggers() {
+    let mut gameboy = gameboy();
+    let breakpoint = gameboy.add_breakpoint(Breakpoint::at(0x0200));
+    let on_frame = gameboy.add_capture_trigger(Trigger::OnFrameNumber(10));
+    let on_breakpoint = gameboy.add_capture_trigger(Trigger::OnBreakpointHit(breakpoint));
+    let on_lcd = gameboy.add_capture_trigger(Trigger::OnLcdEnable);
+    gameboy.run_frames(80);
+
+    let captures = gameboy.take_captures();
+    let fired: Vec<_> = captures
+        .iter()
+        .map(|&(id, number, _)| (id, number))
+        .collect();
+    let (&(_, first_handler), &(_, lcd_on)) = (&fired[0], &fired[2]);
+    assert_eq!(
+        fired,
+        [
+            (on_breakpoint, first_handler),
+            (on_frame, 10),
+            (on_lcd, lcd_on)
+        ]
+    );
+    // The handler runs just after a frame is completed, so it is captured with the next one
+    assert!(first_handler < 10);
+    assert!(lcd_on > 50);
+}
@@ -0,0 +1,146 @@
+//! RAM enable behavior that every cartridge type must share
+
+use gb_core::gameboy::{cart::Cart, Chip, ClockContext};
+use gb_cpu::CpuOutputPins;
+
+/// Cartridge types, and what their RAM reads before anything is written to it, if they have any
+const CART_TYPES: [(u8, Option<u8>); 11] = [
+    // ROM only
+    (0x00, None),
+    // MBC1
+    (0x01, None),
+    // MBC1+RAM
+    (0x02, Some(0x00)),
+    // MBC1+RAM+BATTERY
+    (0x03, Some(0x00)),
+    // MBC2, whose built-in RAM only stores the low nibble, and reads the high one as 1s
+    (0x05, Some(0xF0)),
+    // MBC2+BATTERY
+    (0x06, Some(0xF0)),
+    // MBC3+TIMER+BATTERY
+    (0x0F, None),
+    // MBC3+TIMER+RAM+BATTERY
+    (0x10, Some(0x00)),
+    // MBC3
+    (0x11, None),
+    // MBC3+RAM
+    (0x12, Some(0x00)),
+    // MBC3+RAM+BATTERY
+    (0x13, Some(0x00)),
+];
+
+/// MBC2's RAM repeats every 512 bytes, so these are also all in different places within it
+const ADDRESSES: [u16; 4] = [0xA000, 0xA001, 0xB100, 0xBFFF];
+
+fn cart(cart_type: u8) -> Cart {
+    let mut rom = vec![0; 0x8000];
+    rom[0x147] = cart_type;
+    // 8 KiB, for the mappers that take their RAM size from the header
+    rom[0x149] = 0x02;
+    Cart::new(rom).unwrap()
+}
+
+fn write(cart: &mut Cart, addr: u16, data: u8) {
+    cart.clock(
+        CpuOutputPins::Write { addr, data },
+        &mut 0xFF,
+        &mut 0,
+        &ClockContext::default(),
+    );
+}
+
+fn read(cart: &mut Cart, addr: u16) -> u8 {
+    let mut data = 0xFF;
+    cart.clock(
+        CpuOutputPins::Read { addr },
+        &mut data,
+        &mut 0,
+        &ClockContext::default(),
+    );
+    data
+}
+
+/// The high nibble is all 1s, so that MBC2 reads it back the same
+fn write_pattern(cart: &mut Cart) {
+    for (i, &addr) in ADDRESSES.iter().enumerate() {
+        write(cart, addr, 0xF0 + i as u8);
+    }
+}
+
+fn read_pattern(cart: &mut Cart) -> Vec<u8> {
+    ADDRESSES.iter().map(|&addr| read(cart, addr)).collect()
+}
+
+#[test]
+fn disabled_ram_is_open_bus() {
+    for (cart_type, blank) in CART_TYPES {
+        let mut cart = cart(cart_type);
+        write_pattern(&mut cart);
+        assert_eq!(
+            read_pattern(&mut cart),
+            [0xFF; 4],
+            "type {:#04X}",
+            cart_type
+        );
+
+        // The pattern is still missing once RAM is turned on
+        write(&mut cart, 0x0000, 0x0A);
+        assert_eq!(
+            read_pattern(&mut cart),
+            [blank.unwrap_or(0xFF); 4],
+            "type {:#04X}",
+            cart_type
+        );
+    }
+}
+
+#[test]
+fn pattern_survives_disable() {
+    for (cart_type, blank) in CART_TYPES {
+        let mut cart = cart(cart_type);
+        write(&mut cart, 0x0000, 0x0A);
+        write_pattern(&mut cart);
+        write(&mut cart, 0x0000, 0x00);
+        assert_eq!(
+            read_pattern(&mut cart),
+            [0xFF; 4],
+            "type {:#04X}",
+            cart_type
+        );
+        write(&mut cart, 0x0000, 0x0A);
+
+        let expected = if blank.is_some() {
+            vec![0xF0, 0xF1, 0xF2, 0xF3]
+        } else {
+            vec![0xFF; 4]
+        };
+        assert_eq!(read_pattern(&mut cart), expected, "type {:#04X}", cart_type);
+    }
+}
+
+#[test]
+fn only_low_nibble_a_enables() {
+    for (cart_type, blank) in CART_TYPES {
+        let mut cart = cart(cart_type);
+        write(&mut cart, 0x0000, 0x0A);
+        write(&mut cart, 0xA000, 0xF2);
+
+        for (value, enabled) in [(0x00, false), (0x1A, true), (0xA0, false), (0xFA, true)] {
+            // Any address in $0000-$1FFF is the RAM enable register, except that MBC2 also needs
+            // address bit 8 to be clear
+            write(&mut cart, 0x1EFF, value);
+            let expected = if enabled && blank.is_some() {
+                0xF2
+            } else {
+                0xFF
+            };
+            assert_eq!(
+                read(&mut cart, 0xA000),
+                expected,
+                "type {:#04X}, {:#04X}",
+                cart_type,
+                value
+            );
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use gb_core::gameboy::{
+    cart::header,
+    cheats::{Cheat, CheatParseError},
+    Gameboy, ResetKind,
+};
+
+#[test]
+fn parse_codes() {
+    assert_eq!(
+        "421-51F-ABE".parse(),
+        Ok(Cheat::GameGenie {
+            address: 0x0151,
+            data: 0x42,
+            compare: Some(0x11),
+        })
+    );
+    assert_eq!(
+        "421-51F".parse(),
+        Ok(Cheat::GameGenie {
+            address: 0x0151,
+            data: 0x42,
+            compare: None,
+        })
+    );
+    assert_eq!(
+        "016300C0".parse(),
+        Ok(Cheat::GameShark {
+            bank: None,
+            address: 0xC000,
+            data: 0x63,
+        })
+    );
+    assert_eq!(
+        "826310A0".parse(),
+        Ok(Cheat::GameShark {
+            bank: Some(2),
+            address: 0xA010,
+            data: 0x63,
+        })
+    );
+
+    assert_eq!(
+        "421-51".parse::<Cheat>(),
+        Err(CheatParseError::InvalidLength(5))
+    );
+    assert_eq!(
+        "421-51G".parse::<Cheat>(),
+        Err(CheatParseError::InvalidDigit('G'))
+    );
+    // F is inverted, so 7 is address $8151
+    assert_eq!(
+        "421-517".parse::<Cheat>(),
+        Err(CheatParseError::NotRomAddress(0x8151))
+    );
+    assert_eq!(
+        "01630080".parse::<Cheat>(),
+        Err(CheatParseError::NotRamAddress(0x8000))
+    );
+    assert_eq!(
+        "826300C0".parse::<Cheat>(),
+        Err(CheatParseError::UnsupportedBank(0x82))
+    );
+}
+
+fn stored_after_frame(gameboy: &mut Gameboy) -> u8 {
+    gameboy.run_frames(1);
+    gameboy.memory.read(0xC000).unwrap()
+}
+
+/// Stores $11 to $C000 in a loop. The Game Genie code replaces the $11 with $42.
+#[test]
+fn game_genie_patches_rom_reads() {
+    #[rustfmt::skip]
+    let mut gameboy = Gameboy::with_program(&[
+        0x3E, 0x11,       // LD A, $11
+        0xEA, 0x00, 0xC0, // LD ($C000), A
+        0x18, 0xF9,       // JR -7
+    ], 0x0150).unwrap();
+    assert_eq!(stored_after_frame(&mut gameboy), 0x11);
+
+    let id = gameboy.add_cheat("421-51F-ABE").unwrap();
+    assert_eq!(stored_after_frame(&mut gameboy), 0x42);
+
+    assert!(gameboy.disable_cheat(id));
+    assert_eq!(stored_after_frame(&mut gameboy), 0x11);
+    assert!(gameboy.enable_cheat(id));
+    assert_eq!(stored_after_frame(&mut gameboy), 0x42);
+
+    // Removing the code restores the original ROM contents
+    assert!(gameboy.remove_cheat(id));
+    assert!(!gameboy.remove_cheat(id));
+    assert_eq!(stored_after_frame(&mut gameboy), 0x11);
+
+    // A compare byte that doesn't match the ROM leaves the read alone
+    gameboy.add_cheat("421-51F-ABA").unwrap();
+    assert_eq!(stored_after_frame(&mut gameboy), 0x11);
+}
+
+/// Increments $C000 as fast as it can. The GameShark code keeps resetting it to $63.
+#[test]
+fn game_shark_pins_wram() {
+    #[rustfmt::skip]
+    let mut gameboy = Gameboy::with_program(&[
+        0x21, 0x00, 0xC0, // LD HL, $C000
+        0x34,             // INC (HL)
+        0x18, 0xFD,       // JR -3
+    ], 0x0150).unwrap();
+
+    let id = gameboy.add_cheat("016300C0").unwrap();
+    for _ in 0..5 {
+        gameboy.run_frames(1);
+        assert_eq!(gameboy.memory.read(0xC000).unwrap(), 0x63);
+    }
+
+    gameboy.disable_cheat(id);
+    gameboy.run_frames(1);
+    assert_ne!(gameboy.memory.read(0xC000).unwrap(), 0x63);
+    assert_eq!(gameboy.cheats().count(), 1);
+}
+
+#[test]
+fn game_shark_banked_cart_ram() {
+    let mut rom = header::flat_rom(&[0x18, 0xFE], 0x0150, "").unwrap(); // JR -2
+    rom[0x147] = 0x02; // MBC1 + RAM
+    let mut gameboy = Gameboy::new(rom).unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+
+    // Bank 0 exists and is written even though RAM is disabled, but bank 1 doesn't exist
+    gameboy.add_cheat("805A10A0").unwrap();
+    gameboy.add_cheat("815B11A0").unwrap();
+    gameboy.run_frames(1);
+    let ram = gameboy.cart.ram().unwrap();
+    assert_eq!((ram[0x10], ram[0x11]), (0x5A, 0x00));
+}
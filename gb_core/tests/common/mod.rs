@@ -0,0 +1,68 @@
+//! Fixtures shared by several test files. Each test file is its own crate and only uses some of
+//! them, so they are declared with `#[allow(dead_code)] mod common;`.
+
+use gb_core::gameboy::{
+    cart::header::{flat_rom, update_checksums},
+    ppu::registers::LCDC,
+    Gameboy,
+};
+use gb_cpu::assembler::assemble;
+
+/// A Gameboy showing a black tile in the top left corner of a white screen
+pub fn black_tile_in_corner() -> Gameboy {
+    let mut gameboy = Gameboy::with_program(&[0x18, 0xFE], 0x0150).unwrap(); // JR -2
+    for addr in 0x8010..0x8020 {
+        gameboy.poke(addr, 0xFF);
+    }
+    gameboy.poke(0x9800, 1);
+    gameboy.ppu.lcdc = LCDC::LCD_ENABLE | LCDC::BG_ENABLE | LCDC::BG_TILE_DATA_AREA;
+    gameboy.ppu.bgp = 0xE4;
+    gameboy
+}
+
+/// Where [`rom_with_handlers`] points the VBlank and STAT interrupt vectors
+pub const VBLANK_HANDLER: u16 = 0x0200;
+pub const STAT_HANDLER: u16 = 0x0210;
+
+/// A ROM running `program`, which is assembled from $0150, with the VBlank interrupt vector
+/// jumping to [`VBLANK_HANDLER`] and the STAT one to [`STAT_HANDLER`], since `flat_rom` leaves
+/// them empty. The program has to put a handler at each one it enables.
+pub fn rom_with_handlers(program: &str, title: &str) -> Vec<u8> {
+    let mut rom = flat_rom(&assemble(program).unwrap(), 0x0150, title).unwrap();
+    for (vector, handler) in [(0x40, VBLANK_HANDLER), (0x48, STAT_HANDLER)] {
+        let [low, high] = handler.to_le_bytes();
+        rom[vector..vector + 3].copy_from_slice(&[0xC3, low, high]); // JP handler
+    }
+    update_checksums(&mut rom);
+    rom
+}
+
+/// A program for [`rom_with_handlers`] that counts VBlanks at $C000, starting from 0 with $C001
+/// and B also cleared. After each count, the VBlank handler runs `then` with the count in A.
+pub fn frame_counter(then: &str) -> String {
+    format!(
+        "
+    .org $0150
+        ld sp, $DFFE
+        xor a
+        ld [$C000], a
+        ld [$C001], a
+        ld b, a
+        ld a, $01
+        ldh [$FF], a
+        ei
+    loop:
+        halt
+        jr loop
+
+    .org ${:04X}
+    vblank:
+        ld a, [$C000]
+        inc a
+        ld [$C000], a
+{}
+        reti
+",
+        VBLANK_HANDLER, then
+    )
+}
@@ -0,0 +1,87 @@
+use gb_core::gameboy::{
+    cart::header,
+    coverage::{Bank, EXPORT_MAGIC},
+    Gameboy, ResetKind,
+};
+
+/// Runs `code` from $0150 with coverage tracking enabled
+fn gameboy(code: &[u8]) -> Gameboy {
+    let mut gameboy = Gameboy::with_program(code, 0x0150).unwrap();
+    gameboy.track_coverage(true);
+    gameboy
+}
+
+/// Fills VRAM with $AA, then reads WRAM forever
+#[rustfmt::skip]
+const FILL_VRAM: [u8; 15] = [
+    0x21, 0x00, 0x80, // LD HL, $8000
+    0x3E, 0xAA,       // LD A, $AA
+    0x22,             // LD (HL+), A
+    0xCB, 0x6C,       // BIT 5, H
+    0x28, 0xFB,       // JR Z, -5
+    0xFA, 0x00, 0xC0, // LD A, ($C000)
+    0x18, 0xFB,       // JR -5
+];
+
+#[test]
+fn executed_matches_code() {
+    let mut gameboy = gameboy(&FILL_VRAM);
+    gameboy.run_frames(5);
+    let coverage = gameboy.coverage();
+
+    assert_eq!(
+        coverage.executed_ranges().collect::<Vec<_>>(),
+        [(Bank::Fixed, 0x0150..=0x015E)]
+    );
+    assert!(!(0x8000..=0x9FFF).any(|addr| coverage.is_executed(Bank::Fixed, addr)));
+
+    assert_eq!(
+        coverage.written_ranges().collect::<Vec<_>>(),
+        [(Bank::Fixed, 0x8000..=0x9FFF)]
+    );
+    assert!(coverage.is_read(Bank::Fixed, 0xC000));
+    assert!(!coverage.is_read(Bank::Fixed, 0xC001));
+}
+
+#[test]
+fn switchable_banks() {
+    #[rustfmt::skip]
+    let mut rom = header::flat_rom(&[
+        0x3E, 0x02,       // LD A, $02
+        0xEA, 0x00, 0x20, // LD ($2000), A
+        0xC3, 0x00, 0x40, // JP $4000
+    ], 0x0150, "").unwrap();
+    rom[0x147] = 0x01; // MBC1
+    rom.resize(0x10000, 0);
+    rom[0x8000..0x8002].copy_from_slice(&[0x18, 0xFE]); // Bank 2, $4000: JR -2
+    let mut gameboy = Gameboy::new(rom).unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    gameboy.track_coverage(true);
+    gameboy.run_frames(1);
+    let coverage = gameboy.coverage();
+
+    assert!(coverage
+        .executed_ranges()
+        .any(|range| range == (Bank::Rom(2), 0x4000..=0x4001)));
+    assert!(!coverage.is_executed(Bank::Rom(1), 0x4000));
+}
+
+#[test]
+fn export_and_disable() {
+    let mut gameboy = gameboy(&FILL_VRAM);
+    gameboy.step_instruction();
+    gameboy.step_instruction();
+
+    let bytes = gameboy.coverage().to_bytes();
+    assert_eq!(&bytes[..8], EXPORT_MAGIC);
+    assert_eq!(bytes[8..10], [1, 0]);
+    assert_eq!(bytes.len(), 10 + 3 + 3 * 0x2000);
+    // The executed bitmap of the fixed bank starts after the bank header
+    let executed = &bytes[13..13 + 0x2000];
+    // LD HL, $8000, and the opcode fetch of the next instruction
+    assert_eq!(executed[0x150 / 8], 0b0000_1111);
+
+    gameboy.track_coverage(false);
+    gameboy.step_instruction();
+    assert_eq!(gameboy.coverage().executed_ranges().count(), 0);
+}
@@ -0,0 +1,124 @@
+#[allow(dead_code)]
+mod common;
+
+use common::rom_with_handlers;
+use gb_core::gameboy::Gameboy;
+
+/// M-cycles in a frame, and in a line
+const FRAME_M_CYCLES: u32 = 70224 / 4;
+const LINE_M_CYCLES: u16 = 456 / 4;
+
+/// Waits for each VBlank with HALT, the way most games do
+const HALT_LOOP: &str = "
+    .org $0150
+        ld sp, $DFFE
+        ld a, $01
+        ldh [$FF], a
+        ei
+    loop:
+        halt
+        jr loop
+
+    .org $0200
+    vblank:
+        reti
+";
+
+/// Never halts
+const BUSY_LOOP: &str = "
+    .org $0150
+        ld sp, $DFFE
+        ld a, $01
+        ldh [$FF], a
+        ei
+    loop:
+        jr loop
+
+    .org $0200
+    vblank:
+        reti
+";
+
+/// Waits for each VBlank with HALT, but spends 400 M-cycles in a raster interrupt on line 72
+const RASTER_LOOP: &str = "
+    .org $0150
+        ld sp, $DFFE
+        ld a, 72
+        ldh [$45], a
+        ld a, $40
+        ldh [$41], a
+        ld a, $03
+        ldh [$FF], a
+        ei
+    loop:
+        halt
+        jr loop
+
+    .org $0200
+    vblank:
+        reti
+
+    .org $0210
+    stat:
+        ld b, 100
+    wait:
+        dec b
+        jr nz, wait
+        reti
+";
+
+fn gameboy(program: &str) -> Gameboy {
+    let mut gameboy = Gameboy::new(rom_with_handlers(program, "CPU USAGE")).unwrap();
+    gameboy.run_frames(2);
+    gameboy
+}
+
+#[test]
+fn halting_games_have_headroom() {
+    let mut gameboy = gameboy(HALT_LOOP);
+    gameboy.run_frames(1);
+    let usage = gameboy.ppu.frame_info().cpu;
+    assert_eq!(usage.cycles, FRAME_M_CYCLES);
+    assert_eq!(usage.dispatch_cycles, 5);
+    assert!(usage.idle_percent() > 99.0, "{}", usage.idle_percent());
+}
+
+#[test]
+fn busy_games_have_none() {
+    let mut gameboy = gameboy(BUSY_LOOP);
+    gameboy.run_frames(1);
+    let usage = gameboy.ppu.frame_info().cpu;
+    assert_eq!(usage.cycles, FRAME_M_CYCLES);
+    assert_eq!(usage.halted_cycles, 0);
+    assert_eq!(usage.idle_percent(), 0.0);
+    assert_eq!(usage.dispatch_cycles, 5);
+}
+
+#[test]
+fn scanlines_show_where_the_time_goes() {
+    let mut gameboy = gameboy(HALT_LOOP);
+    assert_eq!(gameboy.scanline_idle_cycles(), None);
+    gameboy.set_scanline_idle_tracking(true);
+    gameboy.run_frames(2);
+    assert!(gameboy
+        .scanline_idle_cycles()
+        .unwrap()
+        .iter()
+        .all(|&cycles| cycles == LINE_M_CYCLES));
+
+    let mut gameboy = self::gameboy(RASTER_LOOP);
+    gameboy.set_scanline_idle_tracking(true);
+    gameboy.run_frames(2);
+    let lines = gameboy.scanline_idle_cycles().unwrap();
+    assert_eq!(lines[71], LINE_M_CYCLES);
+    assert!(lines[72] < LINE_M_CYCLES);
+    assert_eq!(lines[73..75], [0, 0]);
+    assert!(lines[75] < LINE_M_CYCLES);
+    assert_eq!(lines[76], LINE_M_CYCLES);
+    let usage = gameboy.ppu.frame_info().cpu;
+    assert_eq!(usage.dispatch_cycles, 10);
+    assert!((95.0..98.0).contains(&usage.idle_percent()));
+
+    gameboy.set_scanline_idle_tracking(false);
+    assert_eq!(gameboy.scanline_idle_cycles(), None);
+}
@@ -0,0 +1,66 @@
+//! The PC history and the crash dumps taken from it
+
+use gb_core::gameboy::{crash_dump::PC_HISTORY_LEN, Gameboy};
+
+/// Runs a few instructions from $0150, and then $D3, which locks up the CPU
+#[rustfmt::skip]
+const LOCK_UP: [u8; 6] = [
+    0x31, 0xFE, 0xDF, // LD SP, $DFFE
+    0x3E, 0x42,       // LD A, $42
+    0xD3,             // illegal
+];
+
+#[test]
+fn lock_up_takes_a_dump() {
+    let mut gameboy = Gameboy::with_program(&LOCK_UP, 0x0150).unwrap();
+    assert!(gameboy.last_crash().is_none());
+    gameboy.run_frames(1);
+
+    let crash = gameboy.last_crash().expect("no crash dump").clone();
+    let history: Vec<u16> = crash.history.iter().map(|record| record.pc).collect();
+    assert_eq!(history[history.len() - 3..], [0x0150, 0x0153, 0x0155]);
+    assert_eq!(crash.registers.a, 0x42);
+    assert_eq!(crash.registers.sp, 0xDFFE);
+    // The windows are read from ROM and RAM like any other peek
+    for (i, &byte) in crash.code.bytes.iter().enumerate() {
+        assert_eq!(byte, gameboy.peek(crash.code.start.wrapping_add(i as u16)));
+    }
+    assert_eq!(crash.code.center, 0x0155);
+    assert_eq!(crash.code.bytes[0x0155 - crash.code.start as usize], 0xD3);
+    assert_eq!(crash.rom_bank, 1);
+
+    // The CPU stays locked up without fetching anything else
+    let cycles = gameboy.cycles();
+    gameboy.run_frames(1);
+    assert!(gameboy.cycles() > cycles);
+    assert_eq!(gameboy.pc_history().last().unwrap().pc, 0x0155);
+    assert_eq!(gameboy.last_crash(), Some(&crash));
+}
+
+#[test]
+fn dump_is_readable() {
+    let mut gameboy = Gameboy::with_program(&LOCK_UP, 0x0150).unwrap();
+    gameboy.run_frames(1);
+    let report = gameboy.last_crash().unwrap().to_string();
+    assert!(report.contains("SP=DFFE"), "{}", report);
+    assert!(report.contains("[D3]"), "{}", report);
+    assert!(report.contains("0150     0153     0155"), "{}", report);
+}
+
+#[test]
+fn history_keeps_the_latest() {
+    #[rustfmt::skip]
+    let mut gameboy = Gameboy::with_program(&[
+        0x00,       // NOP
+        0x18, 0xFD, // JR -3
+    ], 0x0150).unwrap();
+    gameboy.run_frames(1);
+    let history: Vec<u16> = gameboy.pc_history().map(|record| record.pc).collect();
+    assert_eq!(history.len(), PC_HISTORY_LEN);
+    assert!(history.windows(2).all(|pair| pair[0] != pair[1]));
+    assert!(history.iter().all(|&pc| pc == 0x0150 || pc == 0x0151));
+    // Asking for a dump doesn't need a crash
+    let dump = gameboy.crash_dump();
+    assert!(dump.history.iter().map(|record| record.pc).eq(history));
+    assert!(gameboy.last_crash().is_none());
+}
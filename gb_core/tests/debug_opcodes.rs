@@ -0,0 +1,130 @@
+use std::sync::{Arc, Mutex};
+
+use gb_core::gameboy::{
+    breakpoints::BreakpointId,
+    debug_opcodes::{DebugOpcode, DebugOpcodeAction, DebugOpcodeEvent, MAX_MESSAGE_LEN},
+    ppu::consts::FRAME_T_CYCLES,
+    Gameboy,
+};
+use gb_cpu::assembler::assemble;
+
+const FRAME: u64 = FRAME_T_CYCLES as u64;
+
+/// Prints "PASS", fills $C000-$C1FF with "A"s and prints that too, then passes the mooneye way,
+/// with the Fibonacci numbers in B-L, and counts in A
+const PROGRAM: &str = "
+    .org $0150
+        ld hl, pass
+        ld d, d
+        ld hl, $C000
+        ld bc, $0200
+    fill:
+        ld a, $41
+        ld [hl+], a
+        dec bc
+        ld a, b
+        or c
+        jr nz, fill
+        ld hl, $C000
+        ld d, d
+        ld b, 3
+        ld c, 5
+        ld d, 8
+        ld e, 13
+        ld h, 21
+        ld l, 34
+        xor a
+        ld b, b
+    done:
+        inc a
+        jr done
+    pass:
+        .db $50, $41, $53, $53, $00
+";
+
+fn build() -> Gameboy {
+    Gameboy::with_program(&assemble(PROGRAM).unwrap(), 0x0150).unwrap()
+}
+
+/// Set a handler that records every event and answers `action`
+fn record(gameboy: &mut Gameboy, action: DebugOpcodeAction) -> Arc<Mutex<Vec<DebugOpcodeEvent>>> {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let recorded = events.clone();
+    gameboy.set_debug_opcode_handler(move |event| {
+        recorded.lock().unwrap().push(event);
+        action
+    });
+    events
+}
+
+#[test]
+fn messages_and_breakpoints() {
+    let mut gameboy = build();
+    let events = record(&mut gameboy, DebugOpcodeAction::Break);
+
+    // Each message stops too, since the handler always says so
+    for _ in 0..3 {
+        assert_eq!(
+            gameboy.run_until_breakpoint(FRAME),
+            Some(BreakpointId::DEBUG_OPCODE)
+        );
+    }
+    let events = events.lock().unwrap();
+    let opcodes: Vec<_> = events.iter().map(|event| event.opcode).collect();
+    assert_eq!(
+        opcodes,
+        [
+            DebugOpcode::Message,
+            DebugOpcode::Message,
+            DebugOpcode::Breakpoint
+        ]
+    );
+    assert_eq!(events[0].pc, 0x0153);
+    assert_eq!(events[0].message.as_deref(), Some("PASS"));
+    // Cut short, with no NUL in sight
+    assert_eq!(
+        events[1].message.as_deref(),
+        Some("A".repeat(MAX_MESSAGE_LEN).as_str())
+    );
+
+    let breakpoint = &events[2];
+    let registers = breakpoint.registers;
+    assert_eq!(breakpoint.message, None);
+    assert_eq!(
+        [
+            registers.b,
+            registers.c,
+            registers.d,
+            registers.e,
+            registers.h,
+            registers.l
+        ],
+        [3, 5, 8, 13, 21, 34]
+    );
+    assert_eq!(registers.a, 0);
+    // Stopped with the instruction fetched but not run past
+    assert_eq!(gameboy.cpu.cpu.registers.a, 0);
+}
+
+#[test]
+fn continue_does_not_stop() {
+    let mut gameboy = build();
+    let events = record(&mut gameboy, DebugOpcodeAction::Continue);
+    assert_eq!(gameboy.run_until_breakpoint(FRAME), None);
+    assert_eq!(events.lock().unwrap().len(), 3);
+    assert_ne!(gameboy.cpu.cpu.registers.a, 0);
+}
+
+#[test]
+fn without_a_handler_they_are_plain_loads() {
+    let mut gameboy = build();
+    let events = record(&mut gameboy, DebugOpcodeAction::Break);
+    gameboy.clear_debug_opcode_handler();
+    assert_eq!(gameboy.run_until_breakpoint(FRAME), None);
+    assert!(events.lock().unwrap().is_empty());
+
+    // The same as if the handler had never been set
+    let mut plain = build();
+    plain.run_until_breakpoint(FRAME);
+    assert_eq!(gameboy.cpu.cpu.registers, plain.cpu.cpu.registers);
+}
@@ -0,0 +1,183 @@
+use std::sync::{Arc, Mutex};
+
+use gb_core::gameboy::{
+    cart::header,
+    events::{Event, EventMask, EventRecord, Interrupt},
+    Gameboy, ResetKind, EVENT_QUEUE_CAPACITY,
+};
+
+#[test]
+fn ppu_mode_changes() {
+    let mut gameboy = Gameboy::with_program(&[0x18, 0xFE], 0x0150).unwrap(); // JR -2
+
+    // Line up with the start of VBlank
+    gameboy.run_frames(1);
+    let receiver = gameboy.subscribe(EventMask::PPU_MODE_CHANGE);
+    gameboy.run_frames(2);
+
+    let records: Vec<EventRecord> = receiver.drain().collect();
+    assert_eq!(receiver.dropped(), 0);
+    assert!(records.windows(2).all(|w| w[0].cycle <= w[1].cycle));
+
+    let mut expected = Vec::new();
+    for _ in 0..2 {
+        for _ in 0..144 {
+            expected.extend([2, 3, 0]);
+        }
+        expected.push(1);
+    }
+    let modes: Vec<u8> = records
+        .iter()
+        .map(|r| match r.event {
+            Event::PpuModeChange { mode, .. } => mode,
+            e => panic!("unexpected event {:?}", e),
+        })
+        .collect();
+    assert_eq!(modes, expected);
+
+    for (i, record) in records.iter().enumerate() {
+        if let Event::PpuModeChange { ly, mode, dot } = record.event {
+            let line = i % 433 / 3;
+            match mode {
+                1 => assert_eq!((ly, dot), (144, 0)),
+                2 => assert_eq!((ly, dot), (line as u8, 0)),
+                3 => assert_eq!((ly, dot), (line as u8, 80)),
+                _ => {
+                    assert_eq!(ly, line as u8);
+                    assert!((80 + 160..456).contains(&dot), "HBlank at dot {}", dot);
+                }
+            }
+        }
+    }
+}
+
+/// Services the timer interrupt, which fires every 4096 T-cycles
+#[test]
+fn interrupts_raised_and_serviced() {
+    #[rustfmt::skip]
+    let mut rom = header::flat_rom(&[
+        0x3E, 0x05, // LD A, $05
+        0xE0, 0x07, // LDH (TAC), A
+        0x3E, 0x04, // LD A, $04
+        0xE0, 0xFF, // LDH (IE), A
+        0xFB,       // EI
+        0x18, 0xFE, // JR -2
+    ], 0x0150, "").unwrap();
+    rom[0x50] = 0xD9; // RETI
+    let mut gameboy = Gameboy::new(rom).unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+
+    let records = Arc::new(Mutex::new(Vec::new()));
+    let id = gameboy.subscribe_callback(
+        EventMask::INTERRUPT_RAISED | EventMask::INTERRUPT_SERVICED,
+        {
+            let records = records.clone();
+            move |record| records.lock().unwrap().push(*record)
+        },
+    );
+    gameboy.run_frames(1);
+    assert!(gameboy.unsubscribe(id));
+    assert!(!gameboy.unsubscribe(id));
+    gameboy.run_frames(1);
+
+    let records = std::mem::take(&mut *records.lock().unwrap());
+    let timer: Vec<&EventRecord> = records
+        .iter()
+        .filter(|r| r.event != Event::InterruptRaised(Interrupt::VBlank))
+        .collect();
+    assert!(timer.len() >= 30, "{} events", timer.len());
+    for pair in timer.chunks_exact(2) {
+        assert_eq!(pair[0].event, Event::InterruptRaised(Interrupt::Timer));
+        assert_eq!(
+            pair[1].event,
+            Event::InterruptServiced {
+                vector: Interrupt::Timer.vector(),
+                pc: 0x0159,
+            }
+        );
+        assert!(pair[0].cycle < pair[1].cycle);
+    }
+    for pair in timer.chunks_exact(2).collect::<Vec<_>>().windows(2) {
+        assert_eq!(pair[1][0].cycle - pair[0][0].cycle, 4096);
+    }
+}
+
+#[test]
+fn dma_and_serial() {
+    #[rustfmt::skip]
+    let mut gameboy = Gameboy::with_program(&[
+        0x3E, 0xC1, // LD A, $C1
+        0xE0, 0x46, // LDH (DMA), A
+        0x3E, 0x42, // LD A, $42
+        0xE0, 0x01, // LDH (SB), A
+        0x3E, 0x81, // LD A, $81
+        0xE0, 0x02, // LDH (SC), A
+        0x18, 0xFE, // JR -2
+    ], 0x0150).unwrap();
+    let receiver = gameboy.subscribe(EventMask::OAM_DMA_START | EventMask::SERIAL_BYTE);
+    gameboy.run_frames(1);
+
+    let records: Vec<EventRecord> = receiver.drain().collect();
+    let events: Vec<Event> = records.iter().map(|r| r.event).collect();
+    assert_eq!(
+        events,
+        [
+            Event::OamDmaStart { source: 0xC100 },
+            Event::SerialByte(0x42)
+        ]
+    );
+    // The transfer takes 1024 M-cycles after SC is written
+    assert!(records[1].cycle - records[0].cycle > 1024 * 4);
+}
+
+#[test]
+fn rom_bank_switches() {
+    #[rustfmt::skip]
+    let mut rom = header::flat_rom(&[
+        0x3E, 0x03,       // LD A, $03
+        0xEA, 0x00, 0x20, // LD ($2000), A
+        0xEA, 0x00, 0x20, // LD ($2000), A
+        0xAF,             // XOR A
+        0xEA, 0x00, 0x30, // LD ($3000), A
+        0x18, 0xFE,       // JR -2
+    ], 0x0150, "").unwrap();
+    rom[0x147] = 0x01; // MBC1
+    rom[0x148] = 0x01; // 64KiB
+    rom.resize(0x10000, 0);
+    let mut gameboy = Gameboy::new(rom).unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+
+    let receiver = gameboy.subscribe(EventMask::ROM_BANK_SWITCH);
+    gameboy.run_frames(1);
+
+    let events: Vec<Event> = receiver.drain().map(|r| r.event).collect();
+    // Bank 0 can't be mapped at $4000, so it selects bank 1
+    assert_eq!(
+        events,
+        [
+            Event::RomBankSwitch { from: 1, to: 3 },
+            Event::RomBankSwitch { from: 3, to: 1 }
+        ]
+    );
+}
+
+#[test]
+fn full_queue_drops_events() {
+    let mut gameboy = Gameboy::with_program(&[0x18, 0xFE], 0x0150).unwrap(); // JR -2
+    let receiver = gameboy.subscribe(EventMask::PPU_MODE_CHANGE);
+    // 433 mode changes per frame
+    gameboy.run_frames(10);
+    assert_eq!(receiver.dropped(), 4330 - EVENT_QUEUE_CAPACITY as u64);
+    assert_eq!(receiver.drain().count(), EVENT_QUEUE_CAPACITY);
+
+    gameboy.run_frames(1);
+    assert_eq!(
+        receiver.try_recv().map(|r| r.event),
+        Some(Event::PpuModeChange {
+            ly: 0,
+            mode: 2,
+            dot: 0
+        })
+    );
+    assert!(gameboy.unsubscribe(receiver.id()));
+}
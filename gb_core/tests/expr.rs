@@ -0,0 +1,153 @@
+use gb_core::gameboy::{
+    cart::header::flat_rom,
+    expr::{BinaryOp, Expr, ExprError, Register},
+    Gameboy, ResetKind,
+};
+use gb_cpu::assembler::assemble;
+
+fn gameboy() -> Gameboy {
+    let mut gameboy = Gameboy::with_program(&[0x18, 0xFE], 0x0150).unwrap();
+    gameboy.step_instruction();
+    let registers = &mut gameboy.cpu.cpu.registers;
+    registers.a = 0x12;
+    registers.set_bc(0x3456);
+    registers.set_hl(0xC000);
+    gameboy.poke(0xC000, 0x34);
+    gameboy.poke(0xC001, 0x12);
+    gameboy.poke(0xC345, 9);
+    gameboy
+}
+
+#[test]
+fn parsing() {
+    let reg = |r| Box::new(Expr::Register(r));
+    let lit = |v| Box::new(Expr::Literal(v));
+    assert_eq!(
+        "[0xC345] > 8 && a == 0".parse(),
+        Ok(Expr::Binary(
+            BinaryOp::And,
+            Box::new(Expr::Binary(
+                BinaryOp::Gt,
+                Box::new(Expr::Byte(lit(0xC345))),
+                lit(8)
+            )),
+            Box::new(Expr::Binary(BinaryOp::Eq, reg(Register::A), lit(0))),
+        ))
+    );
+    assert_eq!(
+        "word[$FF00 + C]".parse(),
+        Ok(Expr::Word(Box::new(Expr::Binary(
+            BinaryOp::Add,
+            lit(0xFF00),
+            reg(Register::C)
+        ))))
+    );
+}
+
+#[test]
+fn numbers_and_registers() {
+    let gameboy = gameboy();
+    assert_eq!(gameboy.eval("0x4FA0"), Ok(0x4FA0));
+    assert_eq!(gameboy.eval("$4fa0"), Ok(0x4FA0));
+    assert_eq!(gameboy.eval("65535"), Ok(0xFFFF));
+    assert_eq!(gameboy.eval("A"), Ok(0x12));
+    assert_eq!(gameboy.eval("bc"), Ok(0x3456));
+    assert_eq!(gameboy.eval("B"), Ok(0x34));
+    assert_eq!(gameboy.eval("HL"), Ok(0xC000));
+    // The JR being executed, not the address after its opcode
+    assert_eq!(gameboy.eval("PC"), Ok(0x0150));
+}
+
+#[test]
+fn precedence() {
+    let gameboy = gameboy();
+    assert_eq!(gameboy.eval("1 + 2 << 3"), Ok(24));
+    assert_eq!(gameboy.eval("1 | 2 & 0"), Ok(1));
+    assert_eq!(gameboy.eval("6 ^ 3 & 1"), Ok(7));
+    // Bitwise operators bind tighter than comparisons
+    assert_eq!(gameboy.eval("A & 0x10 == 0x10"), Ok(1));
+    assert_eq!(gameboy.eval("0 || 1 && 0"), Ok(0));
+    assert_eq!(gameboy.eval("(0 || 1) && 2"), Ok(1));
+    assert_eq!(gameboy.eval("10 - 3 - 2"), Ok(5));
+    assert_eq!(gameboy.eval("-1"), Ok(0xFFFF));
+    assert_eq!(gameboy.eval("~A & 0xFF"), Ok(0xED));
+    assert_eq!(gameboy.eval("!A + 1"), Ok(1));
+    assert_eq!(gameboy.eval("1 << 16"), Ok(0));
+}
+
+#[test]
+fn memory_reads() {
+    let gameboy = gameboy();
+    assert_eq!(gameboy.eval("[HL]"), Ok(0x34));
+    assert_eq!(gameboy.eval("[HL + 1]"), Ok(0x12));
+    assert_eq!(gameboy.eval("word[HL]"), Ok(0x1234));
+    assert_eq!(gameboy.eval("[[HL] + 0xC311]"), Ok(9));
+    assert_eq!(gameboy.eval("[$C345] > 8 && A == 0x12"), Ok(1));
+}
+
+#[test]
+fn memory_reads_use_the_mapped_bank() {
+    let code = assemble(
+        "
+        .org $0150
+            ld a, 2
+            ld [$2000], a
+            jr $
+        ",
+    )
+    .unwrap();
+    let mut rom = flat_rom(&code, 0x0150, "").unwrap();
+    rom.resize(0x10000, 0);
+    rom[0x147] = 0x01;
+    rom[0x148] = 0x01;
+    rom[0x4000] = 0x11;
+    rom[0x8000] = 0x22;
+    rom[0xC000] = 0x33;
+    let mut gameboy = Gameboy::new(rom).unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    gameboy.cpu.cpu.registers.pc = 0x0150;
+    assert_eq!(gameboy.eval("[0x4000]"), Ok(0x11));
+    gameboy.run_frames(1);
+    assert_eq!(gameboy.eval("[0x4000]"), Ok(0x22));
+}
+
+#[test]
+fn errors_point_at_the_token() {
+    let error = |expr: &str| expr.parse::<Expr>().unwrap_err();
+    assert_eq!(
+        error("A == )"),
+        ExprError::UnexpectedToken {
+            offset: 5,
+            found: ")".into(),
+            expected: "a number, register or memory read",
+        }
+    );
+    assert_eq!(
+        error("[$C000"),
+        ExprError::UnexpectedEnd {
+            offset: 6,
+            expected: "\"]\"",
+        }
+    );
+    assert_eq!(
+        error("A + Q"),
+        ExprError::UnknownName {
+            offset: 4,
+            name: "Q".into(),
+        }
+    );
+    assert_eq!(
+        error("0x10000 == 0"),
+        ExprError::InvalidNumber {
+            offset: 0,
+            text: "0x10000".into(),
+        }
+    );
+    assert_eq!(error("A B").offset(), 2);
+    assert_eq!(error("word A").offset(), 5);
+    assert_eq!(error("A @ 1").offset(), 2);
+    assert_eq!(
+        error("(A == 1").to_string(),
+        "expected \")\" at offset 7, found the end of the expression"
+    );
+}
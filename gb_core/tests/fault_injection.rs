@@ -0,0 +1,151 @@
+//! Runs games with a misbehaving cartridge, which must never make the emulator panic
+
+use gb_core::gameboy::{
+    cart::{header, Cart},
+    fault_injection::{Fault, FaultControl, FaultInjector, FaultKind},
+    ppu::consts::FRAME_T_CYCLES,
+    Chip, ClockContext, Gameboy, Model, ResetKind,
+};
+use gb_cpu::CpuOutputPins;
+
+/// Fault configurations tried by `random_faults_never_panic`. Raise it to search further.
+const FUZZ_SEEDS: u64 = 24;
+
+const START_COUNTER: &[u8] = include_bytes!("fixtures/start_counter.gb");
+
+/// A small LCG, so that the faults don't depend on an external crate
+struct Lcg(u32);
+
+impl Lcg {
+    fn next(&mut self) -> u32 {
+        self.0 = self.0.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        self.0 >> 16
+    }
+
+    fn below(&mut self, n: u32) -> u32 {
+        self.next() % n
+    }
+}
+
+/// start_counter.gb relabelled as a ROM only, MBC1 or MBC2 cartridge, so that random writes hit
+/// the mappers' registers
+fn rom(seed: u64) -> Vec<u8> {
+    let mut rom = START_COUNTER.to_vec();
+    let (cart_type, ram_size) = [(0x00, 0x00), (0x03, 0x02), (0x06, 0x00)][seed as usize % 3];
+    rom[0x147] = cart_type;
+    rom[0x149] = ram_size;
+    header::update_checksums(&mut rom);
+    rom
+}
+
+fn gameboy(seed: u64) -> (Gameboy, FaultControl) {
+    let injector = FaultInjector::new(Cart::new(rom(seed)).unwrap(), seed);
+    let control = injector.control();
+    // The SGB's packet receiver is another thing that can be fed garbage through the joypad
+    let model = if seed % 2 == 0 {
+        Model::Dmg
+    } else {
+        Model::Sgb
+    };
+    let mut gameboy = Gameboy::builder()
+        .model(model)
+        .cartridge(Box::new(injector))
+        .build()
+        .unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    (gameboy, control)
+}
+
+fn random_fault(rng: &mut Lcg) -> Fault {
+    let kind = [
+        FaultKind::CorruptReads,
+        FaultKind::SwallowWrites,
+        FaultKind::Deny,
+        FaultKind::Claim,
+    ][rng.below(4) as usize];
+    let start = rng.next() as u16;
+    let end = start.saturating_add(rng.below(0x4000) as u16);
+    let first = rng.below(20) as u64 * FRAME_T_CYCLES as u64;
+    let length = 1 + rng.below(4 * FRAME_T_CYCLES as u32) as u64;
+    Fault {
+        kind,
+        addresses: start..=end,
+        cycles: first..first + length,
+    }
+}
+
+#[test]
+fn without_faults_nothing_changes() {
+    let mut plain = Gameboy::new(rom(0)).unwrap();
+    plain.reset(ResetKind::PowerCycle);
+    let (mut wrapped, _control) = gameboy(0);
+    for _ in 0..30 {
+        assert!(plain.run_frames(1).iter().eq(wrapped.run_frames(1).iter()));
+    }
+    assert_eq!(plain.cycles(), wrapped.cycles());
+}
+
+#[test]
+fn random_faults_never_panic() {
+    let mut rng = Lcg(0x5EED);
+    for seed in 0..FUZZ_SEEDS {
+        let (mut gameboy, control) = gameboy(seed);
+        for _ in 0..1 + rng.below(4) {
+            control.add(random_fault(&mut rng));
+        }
+        // Garbage from the whole address space makes the CPU run random code, which writes
+        // random values to every register
+        if seed % 4 == 0 {
+            control.add(Fault::always(FaultKind::CorruptReads, 0x0000..=0x7FFF));
+        }
+        gameboy.run_frames(16);
+
+        // Once the cartridge behaves again, the Gameboy keeps running whatever state it was left in
+        control.clear();
+        let cycles = gameboy.cycles();
+        gameboy.run_frames(4);
+        assert!(
+            gameboy.cycles() - cycles >= 3 * FRAME_T_CYCLES as u64,
+            "seed {}",
+            seed
+        );
+    }
+}
+
+#[test]
+fn swallowed_writes_and_denied_reads() {
+    let (mut gameboy, control) = gameboy(1);
+    control.add(Fault::always(FaultKind::Deny, 0x0150..=0x0150));
+    let mut reads = Vec::new();
+    for _ in 0..1000 {
+        let info = gameboy.tick();
+        if info.pins.addr() == 0x0150 && !info.dma {
+            reads.push(info.data);
+        }
+    }
+    // Open bus, since nothing else answers for ROM
+    assert!(reads.iter().all(|&data| data == 0xFF));
+    assert_eq!(control.faults().len(), 1);
+
+    // An MBC1 cartridge with RAM, which is turned on before the fault starts
+    let mut cart = FaultInjector::new(Cart::new(rom(1)).unwrap(), 1);
+    let control = cart.control();
+    let mut write = |addr, data| {
+        let pins = CpuOutputPins::Write { addr, data };
+        cart.clock(pins, &mut 0xFF, &mut 0, &ClockContext::default());
+    };
+    write(0x0000, 0x0A);
+    write(0xA000, 0x42);
+    control.add(Fault::always(FaultKind::SwallowWrites, 0xA000..=0xBFFF));
+    write(0xA000, 0x99);
+    write(0xA001, 0x99);
+    assert_eq!(control.swallowed_writes(), 2);
+    let mut read = |addr| {
+        let mut data = 0xFF;
+        let pins = CpuOutputPins::Read { addr };
+        cart.clock(pins, &mut data, &mut 0, &ClockContext::default());
+        data
+    };
+    assert_eq!(read(0xA000), 0x42);
+    assert_eq!(read(0xA001), 0x00);
+}
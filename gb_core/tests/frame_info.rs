@@ -0,0 +1,83 @@
+use gb_core::gameboy::{
+    ppu::{
+        consts::FRAME_T_CYCLES,
+        frame_info::{CpuUsage, FrameInfo},
+        registers::LCDC,
+        Ppu,
+    },
+    Gameboy,
+};
+
+fn run(ppu: &mut Ppu, dots: usize) {
+    for _ in 0..dots {
+        ppu.clock_t_state();
+    }
+}
+
+/// Lines 0-7 are covered by 12 sprites, of which only the first 10 are drawn. Lines 8-15 are
+/// covered by one sprite that is drawn, one at X=0 and one past the right edge of the screen. The
+/// window covers lines 100-143.
+fn scene() -> Ppu {
+    let mut ppu = Ppu::new();
+    for i in 0..12 {
+        ppu.oam[i * 4..i * 4 + 2].copy_from_slice(&[16, 8 + 8 * i as u8]);
+    }
+    for (i, &xpos) in [20, 0, 170].iter().enumerate() {
+        ppu.oam[48 + i * 4..48 + i * 4 + 2].copy_from_slice(&[24, xpos]);
+    }
+    ppu.lcdc.insert(LCDC::OBJ_ENABLE | LCDC::WINDOW_ENABLE);
+    ppu.wy = 100;
+    ppu.wx = 7;
+    ppu
+}
+
+#[test]
+fn counts_sprites_and_window_lines() {
+    let mut ppu = scene();
+    run(&mut ppu, 2 * FRAME_T_CYCLES);
+    assert_eq!(
+        ppu.frame_info(),
+        FrameInfo {
+            // The frame is handed off at the start of line 144
+            cycle: (FRAME_T_CYCLES + 144 * 456) as u64,
+            number: 2,
+            window_lines: 44,
+            sprites_drawn: 10 * 8 + 8,
+            peak_sprites_per_line: 10,
+            lcd_was_disabled: false,
+            // Only a Gameboy counts what its CPU does
+            cpu: CpuUsage::default(),
+        }
+    );
+}
+
+#[test]
+fn lcd_off_is_reported_until_the_next_frame() {
+    let mut ppu = scene();
+    run(&mut ppu, FRAME_T_CYCLES);
+    ppu.lcdc.remove(LCDC::LCD_ENABLE);
+    run(&mut ppu, 1000);
+    // The blank frame shown while the LCD is off
+    let info = ppu.frame_info();
+    assert!(info.lcd_was_disabled);
+    assert_eq!(info.sprites_drawn, 0);
+
+    ppu.lcdc.insert(LCDC::LCD_ENABLE);
+    run(&mut ppu, FRAME_T_CYCLES);
+    assert!(ppu.frame_info().lcd_was_disabled);
+    assert_eq!(ppu.frame_info().sprites_drawn, 88);
+    run(&mut ppu, FRAME_T_CYCLES);
+    assert!(!ppu.frame_info().lcd_was_disabled);
+}
+
+#[test]
+fn info_is_handed_off_with_the_frame() {
+    let mut gameboy = Gameboy::with_program(&[0x18, 0xFE], 0x0150).unwrap(); // JR -2
+    let frames = gameboy.frame_receiver();
+    gameboy.run_frames(3);
+    let info = gameboy.ppu.frame_info();
+    assert_eq!(frames.latest().info(), info);
+    assert_eq!(info.number, gameboy.ppu.frame_count);
+    // The CPU finishes the M-cycle in which the frame was handed off
+    assert!(gameboy.cycles() - info.cycle <= 4);
+}
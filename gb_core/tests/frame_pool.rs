@@ -0,0 +1,60 @@
+use gb_core::gameboy::ppu::{
+    consts::FRAME_T_CYCLES,
+    frame::Frame,
+    frame_pool::{FramePool, FrameRc, POOL_CAPACITY},
+    registers::LCDC,
+    Ppu,
+};
+
+fn advance_frame(ppu: &mut Ppu) {
+    for _ in 0..FRAME_T_CYCLES {
+        ppu.clock_t_state();
+    }
+}
+
+#[test]
+fn held_frame_never_changes() {
+    let mut ppu = Ppu::new();
+    ppu.lcdc = LCDC::LCD_ENABLE | LCDC::BG_ENABLE;
+    advance_frame(&mut ppu);
+    let held = ppu.get_frame();
+    let copy: Frame = (*held).clone();
+
+    for bgp in 1..=10 {
+        // Every pixel is color 0, so this changes the shade of the whole frame
+        ppu.bgp = bgp;
+        advance_frame(&mut ppu);
+        assert_eq!(ppu.get_frame()[0], bgp & 3);
+        assert!(held.iter().eq(copy.iter()), "frame changed while held");
+    }
+}
+
+#[test]
+fn released_buffers_are_reused() {
+    let mut pool = FramePool::new();
+    let buffer = pool.take();
+    let ptr = FrameRc::as_ptr(&buffer);
+    let frame = pool.share(buffer);
+    assert_eq!(pool.free_len(), 0);
+
+    // The frame is still held, so a new buffer is needed
+    let other = pool.take();
+    assert_ne!(FrameRc::as_ptr(&other), ptr);
+
+    drop(frame);
+    assert_eq!(pool.free_len(), 1);
+    assert_eq!(FrameRc::as_ptr(&pool.take()), ptr);
+}
+
+#[test]
+fn pool_is_bounded() {
+    let mut pool = FramePool::new();
+    let frames: Vec<_> = (0..POOL_CAPACITY + 2)
+        .map(|_| {
+            let buffer = pool.take();
+            pool.share(buffer)
+        })
+        .collect();
+    drop(frames);
+    assert_eq!(pool.free_len(), POOL_CAPACITY);
+}
@@ -0,0 +1,104 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+#[cfg(not(feature = "differential"))]
+use gb_core::gameboy::ppu::registers::LCDC;
+use gb_core::gameboy::{ppu::frame_sink::PresentationMode, test_pattern, Gameboy, ResetKind};
+
+/// Counts the allocations made on each thread, since the tests run on several at once
+struct Counting;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        // The counter may already be gone while the thread is exiting
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+#[cfg(not(feature = "differential"))]
+fn allocations() -> usize {
+    ALLOCATIONS.with(Cell::get)
+}
+
+fn gameboy() -> Gameboy {
+    let mut gameboy = Gameboy::new(test_pattern::rom()).unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    gameboy.set_presentation_mode(PresentationMode::LatestOnly);
+    gameboy
+}
+
+/// Put all 40 sprites over the test pattern, three or so to a line. Later ones in OAM are further
+/// left, so they have to be reordered on every line they cover.
+#[cfg(not(feature = "differential"))]
+fn add_sprites(gameboy: &mut Gameboy) {
+    for (i, entry) in gameboy.ppu.oam.chunks_exact_mut(4).enumerate() {
+        let i = i as u8;
+        entry.copy_from_slice(&[16 + i * 3, 168 - i * 4, 1, 0]);
+    }
+    gameboy.ppu.lcdc.insert(LCDC::OBJ_ENABLE);
+}
+
+/// Takes every 10th frame and holds on to it until the next, so most frames are dropped. Not run
+/// with `differential`, whose check against the reference PPU allocates on every line.
+#[cfg(not(feature = "differential"))]
+#[test]
+fn slow_consumer_without_allocations() {
+    let mut gameboy = gameboy();
+    // The program only sets up the screen once, before the first frame is drawn
+    gameboy.run_frames(2);
+    add_sprites(&mut gameboy);
+    let _receiver = gameboy.frame_receiver();
+    let mut held = None;
+    let mut run = |gameboy: &mut Gameboy, frames| {
+        for frame in 1..=frames {
+            gameboy.run_frames(1);
+            if frame % 10 == 0 {
+                held = Some(gameboy.take_frame().unwrap());
+            }
+        }
+    };
+    // Let the frame pool and everything else fill up first
+    run(&mut gameboy, 100);
+
+    let (produced, presented) = (gameboy.frames_produced(), gameboy.frames_presented());
+    let before = allocations();
+    run(&mut gameboy, 1000);
+    assert_eq!(allocations() - before, 0);
+    assert_eq!(gameboy.frames_produced() - produced, 1000);
+    assert_eq!(gameboy.frames_presented() - presented, 100);
+}
+
+#[test]
+fn receivers_only_see_taken_frames() {
+    let mut gameboy = gameboy();
+    let receiver = gameboy.frame_receiver();
+    gameboy.run_frames(3);
+    let first = receiver.latest().number();
+
+    gameboy.run_frames(5);
+    assert_eq!(receiver.latest().number(), first);
+    let frame = gameboy.take_frame().unwrap();
+    assert!(gameboy.take_frame().is_none());
+    assert_eq!(receiver.latest().number(), gameboy.ppu.frame_count);
+    assert!(receiver.latest().iter().eq(frame.iter()));
+
+    // Every frame goes to receivers again, whether or not it is taken
+    gameboy.set_presentation_mode(PresentationMode::EveryFrame);
+    gameboy.run_frames(1);
+    assert_eq!(receiver.latest().number(), gameboy.ppu.frame_count);
+    assert_eq!(gameboy.frames_presented(), 1);
+}
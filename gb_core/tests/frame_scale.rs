@@ -0,0 +1,98 @@
+use gb_core::{
+    gameboy::ppu::{
+        color::{COLOR_BLACK, COLOR_LIGHTGRAY, COLOR_WHITE},
+        frame::{scale, Frame},
+    },
+    GbError,
+};
+
+/// A white frame with a black pixel at (0, 0) and a light gray pixel at (1, 0)
+fn test_frame() -> Frame {
+    let mut frame = Frame::new();
+    frame[(0, 0)] = 3;
+    frame[(1, 0)] = 1;
+    frame
+}
+
+/// The RGBA bytes of the pixel at `(x, y)` of an image scaled by `factor`
+fn pixel(out: &[u8], factor: usize, x: usize, y: usize) -> [u8; 4] {
+    let i = (y * 160 * factor + x) * 4;
+    [out[i], out[i + 1], out[i + 2], out[i + 3]]
+}
+
+fn rgba(color: u32) -> [u8; 4] {
+    color.to_le_bytes()
+}
+
+#[test]
+fn palette_colors() {
+    let mut frame = test_frame();
+    assert_eq!(frame.color(0, 0), COLOR_BLACK);
+    assert_eq!(frame.color(1, 0), COLOR_LIGHTGRAY);
+    assert_eq!(frame.row_colors(0)[2], COLOR_WHITE);
+
+    frame.set_palette([0xFF00FF00, 0, 0, 0xFF0000FF]);
+    assert_eq!(frame.color(0, 0), 0xFF0000FF);
+    assert_eq!(frame.colors().nth(2), Some(0xFF00FF00));
+}
+
+#[test]
+fn nearest() {
+    let mut out = vec![0; scale::buffer_len(3)];
+    assert_eq!(out.len(), 480 * 432 * 4);
+    scale::scale_nearest(&test_frame(), 3, &mut out).unwrap();
+
+    for (x, y) in [(0, 0), (2, 0), (0, 2), (2, 2)] {
+        assert_eq!(pixel(&out, 3, x, y), rgba(COLOR_BLACK));
+    }
+    assert_eq!(pixel(&out, 3, 3, 2), rgba(COLOR_LIGHTGRAY));
+    assert_eq!(pixel(&out, 3, 6, 0), rgba(COLOR_WHITE));
+    assert_eq!(pixel(&out, 3, 0, 3), rgba(COLOR_WHITE));
+    assert_eq!(pixel(&out, 3, 479, 431), rgba(COLOR_WHITE));
+}
+
+#[test]
+fn scanlines() {
+    let mut out = vec![0; scale::buffer_len(2)];
+    scale::scale_scanlines(&test_frame(), 2, &mut out).unwrap();
+
+    assert_eq!(pixel(&out, 2, 5, 0), rgba(COLOR_WHITE));
+    assert_eq!(pixel(&out, 2, 5, 1), [0xBF, 0xBF, 0xBF, 0xFF]);
+    assert_eq!(pixel(&out, 2, 2, 1), [0x7F, 0x7F, 0x7F, 0xFF]);
+    // Black can't get any darker
+    assert_eq!(pixel(&out, 2, 1, 1), rgba(COLOR_BLACK));
+    assert_eq!(pixel(&out, 2, 5, 2), rgba(COLOR_WHITE));
+}
+
+#[test]
+fn lcd_grid() {
+    let mut out = vec![0; scale::buffer_len(4)];
+    scale::scale_lcd_grid(&test_frame(), 4, &mut out).unwrap();
+
+    let grid = [0xDF, 0xDF, 0xDF, 0xFF];
+    assert_eq!(pixel(&out, 4, 8, 0), rgba(COLOR_WHITE));
+    assert_eq!(pixel(&out, 4, 11, 0), grid);
+    assert_eq!(pixel(&out, 4, 8, 3), grid);
+    assert_eq!(pixel(&out, 4, 10, 2), rgba(COLOR_WHITE));
+    assert_eq!(pixel(&out, 4, 639, 575), grid);
+
+    // There's no room for a grid without scaling
+    let mut grid = vec![0; scale::buffer_len(1)];
+    let mut nearest = vec![0; scale::buffer_len(1)];
+    scale::scale_lcd_grid(&test_frame(), 1, &mut grid).unwrap();
+    scale::scale_nearest(&test_frame(), 1, &mut nearest).unwrap();
+    assert_eq!(grid, nearest);
+}
+
+#[test]
+fn wrong_buffer_size() {
+    let mut out = vec![0xAA; scale::buffer_len(2) - 4];
+    assert_eq!(
+        scale::scale_nearest(&test_frame(), 2, &mut out),
+        Err(GbError::BufferSize {
+            expected: 320 * 288 * 4,
+            actual: 320 * 288 * 4 - 4
+        })
+    );
+    assert!(out.iter().all(|&b| b == 0xAA));
+}
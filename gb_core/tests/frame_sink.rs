@@ -0,0 +1,118 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+};
+
+use gb_core::gameboy::{ppu::frame::Frame, Gameboy};
+
+const FRAMES: u32 = 60;
+const READERS: usize = 2;
+
+/// Rotates BGP once at the start of every VBlank. The tile data is blank, so each frame is a single
+/// color, and consecutive frames have different colors. A frame with more than one color in it is
+/// made of parts of different frames.
+fn palette_cycle() -> Gameboy {
+    #[rustfmt::skip]
+    let code = [
+        0x3E, 0xE4, // LD A, $E4
+        0xE0, 0x47, // LDH (BGP), A
+        // Wait for line 144
+        0xF0, 0x44, // LDH A, (LY)
+        0xFE, 0x90, // CP 144
+        0x20, 0xFA, // JR NZ, -6
+        0xF0, 0x47, // LDH A, (BGP)
+        0x07,       // RLCA
+        0x07,       // RLCA
+        0xE0, 0x47, // LDH (BGP), A
+        // Wait for line 144 to end
+        0xF0, 0x44, // LDH A, (LY)
+        0xFE, 0x90, // CP 144
+        0x28, 0xFA, // JR Z, -6
+        0x18, 0xEC, // JR -20
+    ];
+    Gameboy::with_program(&code, 0x0150).unwrap()
+}
+
+/// The shade of the frame, or `None` if it is torn
+fn frame_color(frame: &Frame) -> Option<u8> {
+    let first = frame[0];
+    frame.iter().all(|&pix| pix == first).then_some(first)
+}
+
+#[test]
+fn frames_are_never_torn() {
+    let mut gameboy = palette_cycle();
+    let frames = gameboy.frame_receiver();
+    let done = Arc::new(AtomicBool::new(false));
+
+    // Only the receivers go to other threads, so this runs without the Gameboy being `Send`
+    let readers: Vec<_> = (0..READERS)
+        .map(|_| {
+            let frames = frames.clone();
+            let done = done.clone();
+            thread::spawn(move || {
+                let mut seen = HashMap::new();
+                let mut last_number = 0;
+                while !done.load(Ordering::SeqCst) {
+                    let frame = frames.latest();
+                    let color = frame_color(&frame)
+                        .unwrap_or_else(|| panic!("frame {} is torn", frame.number()));
+                    assert!(frame.number() >= last_number, "frames went backwards");
+                    last_number = frame.number();
+                    seen.insert(frame.number(), color);
+                }
+                seen
+            })
+        })
+        .collect();
+
+    for _ in 0..FRAMES {
+        gameboy.run_frames(1);
+        thread::yield_now();
+    }
+    done.store(true, Ordering::SeqCst);
+    let mut seen = HashMap::new();
+    for reader in readers {
+        for (number, color) in reader.join().unwrap() {
+            // Every reader must see the same thing for the same frame
+            assert_eq!(
+                *seen.entry(number).or_insert(color),
+                color,
+                "frame {}",
+                number
+            );
+        }
+    }
+
+    assert_eq!(frames.latest().number(), FRAMES as u64);
+    assert!(
+        seen.len() > 1,
+        "the readers only saw {} frame(s)",
+        seen.len()
+    );
+    // The palette repeats every 4 frames
+    let mut colors = HashMap::new();
+    for (number, color) in seen.iter().filter(|(number, _)| **number > 0) {
+        assert_eq!(
+            *colors.entry(number % 4).or_insert(*color),
+            *color,
+            "frame {}",
+            number
+        );
+    }
+}
+
+#[test]
+fn receiver_starts_with_current_frame() {
+    let mut gameboy = palette_cycle();
+    let drawn = gameboy.run_frames(3).clone();
+
+    let frames = gameboy.frame_receiver();
+    let latest = frames.latest();
+    assert_eq!(latest.number(), 3);
+    assert!(latest.iter().eq(drawn.iter()));
+}
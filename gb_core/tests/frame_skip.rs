@@ -0,0 +1,106 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+use gb_core::gameboy::{Gameboy, ResetKind};
+
+/// A ROM that counts VBlank interrupts at $C000 while logging LY and STAT into WRAM
+fn timing_sensitive_rom() -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    #[rustfmt::skip]
+    rom[0x40..0x49].copy_from_slice(&[
+        0xF5,             // PUSH AF
+        0xE5,             // PUSH HL
+        0x21, 0x00, 0xC0, // LD HL, $C000
+        0x34,             // INC (HL)
+        0xE1,             // POP HL
+        0xF1,             // POP AF
+        0xD9,             // RETI
+    ]);
+    #[rustfmt::skip]
+    rom[0x100..0x117].copy_from_slice(&[
+        0x3E, 0x01,       // LD A, $01
+        0xE0, 0xFF,       // LDH ($FF), A
+        0xFB,             // EI
+        0x21, 0x00, 0xC1, // LD HL, $C100
+        // loop:
+        0xF0, 0x44,       // LDH A, ($44)
+        0x22,             // LD (HL+), A
+        0xF0, 0x41,       // LDH A, ($41)
+        0x22,             // LD (HL+), A
+        0x7C,             // LD A, H
+        0xFE, 0xD0,       // CP $D0
+        0x20, 0xF5,       // JR NZ, loop
+        0x26, 0xC1,       // LD H, $C1
+        0x18, 0xF1,       // JR loop
+    ]);
+    rom
+}
+
+/// Hash everything observable about the machine except for the frame buffers
+fn state_hash(gameboy: &Gameboy) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    format!("{:?}", gameboy.cpu).hash(&mut hasher);
+    format!("{:?}", *gameboy.ppu).hash(&mut hasher);
+    gameboy.ppu.tile_data.hash(&mut hasher);
+    gameboy.ppu.bg_map_1.hash(&mut hasher);
+    gameboy.ppu.bg_map_2.hash(&mut hasher);
+    gameboy.ppu.oam.hash(&mut hasher);
+    for addr in (0xC000..=0xDFFF).chain(0xFF80..=0xFFFE) {
+        gameboy.memory.read(addr).unwrap().hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+fn run(frame_skip: u32) -> Gameboy {
+    let mut gameboy = Gameboy::new(timing_sensitive_rom()).unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    gameboy.set_frame_skip(frame_skip);
+    while gameboy.ppu.frame_count < 120 {
+        gameboy.clock();
+    }
+    gameboy
+}
+
+#[test]
+fn frame_skip_is_cycle_identical() {
+    let normal = run(0);
+    let skipped = run(1);
+
+    // The VBlank counter should have been incremented by the interrupt handler
+    assert!(normal.memory.read(0xC000).unwrap() > 100);
+    assert_eq!(state_hash(&normal), state_hash(&skipped));
+}
+
+#[test]
+fn run_frames_is_cycle_identical() {
+    let normal = run(0);
+
+    let mut gameboy = Gameboy::new(timing_sensitive_rom()).unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    gameboy.run_frames(120);
+
+    assert_eq!(gameboy.ppu.frame_count, 120);
+    assert_eq!(state_hash(&normal), state_hash(&gameboy));
+}
+
+#[test]
+fn skipped_frames_keep_last_drawn_frame() {
+    let mut gameboy = Gameboy::new(vec![0; 0x8000]).unwrap();
+    gameboy.set_frame_skip(u32::MAX);
+    gameboy.ppu.bgp = 0xFF;
+    gameboy.run_frames(1);
+
+    // Every pixel is now black, which should stay on the front frame while frames are skipped
+    gameboy.ppu.bgp = 0x00;
+    for _ in 0..gb_core::gameboy::ppu::consts::FRAME_T_CYCLES / 4 * 2 {
+        gameboy.clock();
+    }
+    let black = gb_core::gameboy::ppu::color::COLOR_BLACK;
+    assert!(gameboy.get_frame().colors().all(|pix| pix == black));
+
+    let frame = gameboy.run_frames(1);
+    let white = gb_core::gameboy::ppu::color::COLOR_WHITE;
+    assert!(frame.colors().all(|pix| pix == white));
+}
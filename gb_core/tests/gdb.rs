@@ -0,0 +1,167 @@
+//! Talks to the GDB server over a socket, the way GDB does. Run with
+//! `cargo test -p gb_core --features gdb --test gdb`. The server runs on a thread of its own, so
+//! these don't run with the `single-thread` feature.
+#![cfg(all(feature = "gdb", not(feature = "single-thread")))]
+
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    thread,
+    time::Duration,
+};
+
+use gb_core::gameboy::{cart::header::flat_rom, gdb::GdbServer, Gameboy};
+use gb_cpu::assembler::assemble;
+
+/// Counts up in A and copies it to $C000. `loop` is at $0158.
+const PROGRAM: &str = "
+    .org $0150
+        ld sp, $DFFE
+        ld a, $12
+        ld bc, $3456
+    loop:
+        inc a
+        ld [$C000], a
+        jr loop
+";
+
+struct Client {
+    stream: TcpStream,
+}
+
+impl Client {
+    fn send(&mut self, packet: &str) {
+        let sum = packet.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        write!(self.stream, "${}#{:02x}", packet, sum).unwrap();
+        assert_eq!(self.byte(), b'+', "{}", packet);
+    }
+
+    fn byte(&mut self) -> u8 {
+        let mut byte = [0];
+        self.stream.read_exact(&mut byte).unwrap();
+        byte[0]
+    }
+
+    fn receive(&mut self) -> String {
+        assert_eq!(self.byte(), b'$');
+        let mut packet = Vec::new();
+        loop {
+            match self.byte() {
+                b'#' => break,
+                byte => packet.push(byte),
+            }
+        }
+        let checksum = [self.byte(), self.byte()];
+        let sum = packet.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+        assert_eq!(
+            std::str::from_utf8(&checksum).unwrap(),
+            format!("{:02x}", sum)
+        );
+        self.stream.write_all(b"+").unwrap();
+        String::from_utf8(packet).unwrap()
+    }
+
+    fn request(&mut self, packet: &str) -> String {
+        self.send(packet);
+        self.receive()
+    }
+
+    /// AF, BC, DE, HL, SP and PC
+    fn registers(&mut self) -> Vec<u16> {
+        let reply = self.request("g");
+        assert_eq!(reply.len(), 24);
+        (0..24)
+            .step_by(4)
+            .map(|i| {
+                u16::from_str_radix(&reply[i..i + 4], 16)
+                    .unwrap()
+                    .swap_bytes()
+            })
+            .collect()
+    }
+}
+
+/// Serve a Gameboy running [`PROGRAM`] on another thread, which returns it once the session ends
+fn connect() -> (Client, thread::JoinHandle<Gameboy>) {
+    let rom = flat_rom(&assemble(PROGRAM).unwrap(), 0x0150, "GDB").unwrap();
+    let gameboy = Gameboy::new(rom).unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let mut server = GdbServer::new(gameboy, listener);
+        server.serve().unwrap();
+        server.into_inner()
+    });
+    let stream = TcpStream::connect(addr).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+    (Client { stream }, server)
+}
+
+#[test]
+fn breakpoint_continue_and_registers() {
+    let (mut gdb, server) = connect();
+    assert!(gdb.request("qSupported:swbreak+").contains("PacketSize="));
+    assert_eq!(gdb.request("?"), "S05");
+
+    assert_eq!(gdb.request("Z0,158,1"), "OK");
+    assert_eq!(gdb.request("c"), "S05");
+    let registers = gdb.registers();
+    assert_eq!(registers[0] >> 8, 0x12);
+    assert_eq!(registers[1..], [0x3456, 0x0000, 0x0000, 0xDFFE, 0x0158]);
+
+    // One time around the loop
+    assert_eq!(gdb.request("s"), "S05");
+    assert_eq!(gdb.request("p5"), "5901");
+    assert_eq!(gdb.request("c"), "S05");
+    assert_eq!(gdb.registers()[5], 0x0158);
+    assert_eq!(gdb.request("mc000,1"), "13");
+
+    assert_eq!(gdb.request("D"), "OK");
+    let gameboy = server.join().unwrap();
+    assert_eq!(gameboy.breakpoints().count(), 0);
+    assert_eq!(gameboy.peek(0xC000), 0x13);
+}
+
+#[test]
+fn writing_registers_and_memory() {
+    let (mut gdb, server) = connect();
+    assert_eq!(gdb.request("Z0,158,1"), "OK");
+    assert_eq!(gdb.request("c"), "S05");
+
+    assert_eq!(gdb.request("Mc001,2:abcd"), "OK");
+    assert_eq!(gdb.request("mc000,3"), "00abcd");
+    // A = $41, so the loop stores $42
+    assert_eq!(gdb.request("P0=0041"), "OK");
+    assert_eq!(gdb.request("c"), "S05");
+    assert_eq!(gdb.request("mc000,1"), "42");
+
+    // Jumping back to `ld a, $12` fetches from there instead
+    assert_eq!(gdb.request("P5=5301"), "OK");
+    assert_eq!(gdb.request("p5"), "5301");
+    assert_eq!(gdb.request("s"), "S05");
+    let registers = gdb.registers();
+    assert_eq!((registers[0] >> 8, registers[5]), (0x12, 0x0155));
+
+    assert_eq!(gdb.request("D"), "OK");
+    server.join().unwrap();
+}
+
+#[test]
+fn interrupting_a_running_game() {
+    let (mut gdb, server) = connect();
+    assert_eq!(gdb.request("Z0,158,1"), "OK");
+    assert_eq!(gdb.request("z0,158,1"), "OK");
+    // Nothing stops it now, so it runs until the debugger interrupts
+    gdb.send("c");
+    thread::sleep(Duration::from_millis(50));
+    gdb.stream.write_all(&[0x03]).unwrap();
+    assert_eq!(gdb.receive(), "S02");
+    let pc = gdb.registers()[5];
+    assert!((0x0158..0x015E).contains(&pc), "{:04X}", pc);
+
+    gdb.send("k");
+    let gameboy = server.join().unwrap();
+    assert_ne!(gameboy.peek(0xC000), 0);
+}
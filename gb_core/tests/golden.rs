@@ -0,0 +1,86 @@
+//! End-to-end determinism check: runs `fixtures/start_counter.gb` for 10 seconds of emulated time
+//! with scripted input, and compares hashes of every 60th frame and of the final machine state
+//! against `fixtures/golden.txt`.
+//!
+//! Run with `cargo test -p gb_core --features golden --test golden`.
+//!
+//! The same ROM and input must give identical hashes on every run and every platform. Between
+//! releases, the hashes may only change in a commit that deliberately changes emulated behaviour,
+//! which must regenerate them by running the test with `GOLDEN_UPDATE=1` and say why they changed.
+#![cfg(feature = "golden")]
+
+use std::{fmt::Write, fs};
+
+use gb_core::gameboy::{joypad::Button, memory::RamInit, rtc::FixedClock, Gameboy, ResetKind};
+
+const ROM: &[u8] = include_bytes!("fixtures/start_counter.gb");
+const GOLDEN_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/golden.txt");
+const FRAMES: u32 = 600;
+
+/// 64-bit FNV-1a
+fn fnv1a(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    bytes.into_iter().fold(0xCBF2_9CE4_8422_2325, |hash, b| {
+        (hash ^ b as u64).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
+
+/// Start is held for 5 frames out of every 45, with the interval shrinking as the run goes on so
+/// that presses land at different points in the ROM's polling loop
+fn start_held(frame: u32) -> bool {
+    let period = 45 - frame / 60;
+    frame % period < 5
+}
+
+/// Everything the CPU can observe: its registers, the cycle count, and memory from VRAM to IE
+fn state_hash(gameboy: &Gameboy) -> u64 {
+    let cpu = &gameboy.cpu.cpu;
+    let r = &cpu.registers;
+    let registers = [r.get_af(), r.get_bc(), r.get_de(), r.get_hl(), r.sp, r.pc];
+    let bytes = registers
+        .iter()
+        .flat_map(|reg| reg.to_le_bytes())
+        .chain([cpu.ime as u8])
+        .chain(gameboy.cycles().to_le_bytes())
+        .chain((0x8000..=0xFFFF).map(|addr| gameboy.peek(addr)))
+        .chain(gameboy.cart.ram().unwrap_or(&[]).iter().copied());
+    fnv1a(bytes)
+}
+
+fn run() -> String {
+    let mut gameboy = Gameboy::builder()
+        .rom(ROM)
+        .rtc(Box::new(FixedClock(0)))
+        .build()
+        .unwrap();
+    gameboy.set_ram_init(RamInit::Random(0x5EED));
+    gameboy.reset(ResetKind::PowerCycle);
+
+    let mut hashes = String::new();
+    for frame in 1..=FRAMES {
+        if start_held(frame) {
+            gameboy.joypad.press(Button::Start);
+        } else {
+            gameboy.joypad.release(Button::Start);
+        }
+        let shades = gameboy.run_frames(1).iter().copied();
+        if frame % 60 == 0 {
+            writeln!(hashes, "frame {} {:016x}", frame, fnv1a(shades)).unwrap();
+        }
+    }
+    writeln!(hashes, "state {:016x}", state_hash(&gameboy)).unwrap();
+    hashes
+}
+
+#[test]
+fn matches_golden_hashes() {
+    let hashes = run();
+    if std::env::var_os("GOLDEN_UPDATE").is_some() {
+        fs::write(GOLDEN_PATH, &hashes).unwrap();
+        return;
+    }
+    let golden = fs::read_to_string(GOLDEN_PATH).unwrap();
+    assert_eq!(
+        hashes, golden,
+        "emulation no longer matches the golden hashes, see the top of this file for the policy"
+    );
+}
@@ -0,0 +1,125 @@
+use gb_core::{
+    gameboy::{
+        cart::header::{self, CartridgeHeader, NINTENDO_LOGO},
+        test_pattern, Gameboy, ResetKind,
+    },
+    GbError,
+};
+
+#[test]
+fn checksums_match_header() {
+    let rom = header::flat_rom(&[0x18, 0xFE], 0x0150, "CHECKSUMS").unwrap();
+    assert_eq!(&rom[0x104..0x134], NINTENDO_LOGO);
+    assert_eq!(&rom[0x134..0x13D], b"CHECKSUMS");
+    assert_eq!(rom[0x148], 0x00);
+
+    // The boot ROM's check: adding $19 and every byte of $0134-$014D together gives 0
+    let sum = rom[0x134..=0x14D]
+        .iter()
+        .fold(0x19u8, |sum, &b| sum.wrapping_add(b));
+    assert_eq!(sum, 0);
+    assert_eq!(rom[0x14D], header::header_checksum(&rom));
+
+    let global = u16::from_be_bytes([rom[0x14E], rom[0x14F]]);
+    let sum = rom
+        .iter()
+        .fold(0u16, |sum, &b| sum.wrapping_add(b as u16))
+        .wrapping_sub(rom[0x14E] as u16 + rom[0x14F] as u16);
+    assert_eq!(global, sum);
+}
+
+#[test]
+fn parse_header() {
+    let mut rom = header::flat_rom(&[0x18, 0xFE], 0x0150, "HEADER").unwrap();
+    let parsed = CartridgeHeader::parse(&rom).unwrap();
+    assert_eq!(
+        parsed,
+        CartridgeHeader {
+            title: "HEADER".to_string(),
+            cart_type: 0x00,
+            rom_size: 0x8000,
+            ram_size_id: 0x00,
+            logo_valid: true,
+            header_checksum_valid: true,
+            global_checksum_valid: true,
+        }
+    );
+
+    rom[0x134] = 0x80;
+    rom[0x147] = 0x13;
+    rom[0x104] = 0;
+    let parsed = CartridgeHeader::parse(&rom).unwrap();
+    assert_eq!(parsed.title, "?EADER");
+    assert_eq!(parsed.cart_type, 0x13);
+    assert!(!parsed.logo_valid);
+    assert!(!parsed.header_checksum_valid);
+    assert!(!parsed.global_checksum_valid);
+
+    rom[0x148] = 0x20;
+    assert!(matches!(
+        CartridgeHeader::parse(&rom),
+        Err(GbError::InvalidRom(_))
+    ));
+    assert!(matches!(
+        CartridgeHeader::parse(&rom[..0x100]),
+        Err(GbError::InvalidRom(_))
+    ));
+}
+
+#[test]
+fn rom_size_ids() {
+    assert_eq!(header::rom_size_id(0x8000), Some(0x00));
+    assert_eq!(header::rom_size_id(0x10000), Some(0x01));
+    assert_eq!(header::rom_size_id(0x800000), Some(0x08));
+    assert_eq!(header::rom_size_id(0x9000), None);
+
+    let mut rom = vec![0; 0x9000];
+    assert!(matches!(
+        header::write_header(&mut rom, ""),
+        Err(GbError::InvalidRom(_))
+    ));
+}
+
+#[test]
+fn program_must_fit() {
+    assert!(Gameboy::with_program(&[0x00; 0x10], 0x7FF0).is_ok());
+    assert!(matches!(
+        Gameboy::with_program(&[0x00; 0x11], 0x7FF0),
+        Err(GbError::InvalidRom(_))
+    ));
+    assert!(matches!(
+        Gameboy::with_program(&[0x00], 0xC000),
+        Err(GbError::InvalidRom(_))
+    ));
+}
+
+/// Code loaded over the header replaces it, and the checksums are recalculated around it
+#[test]
+fn program_over_header() {
+    #[rustfmt::skip]
+    let code = [
+        0x3E, 0x42,       // LD A, $42
+        0xEA, 0x00, 0xC0, // LD ($C000), A
+        0x18, 0xFE,       // JR -2
+    ];
+    let rom = header::flat_rom(&code, 0x0134, "").unwrap();
+    assert_eq!(rom[0x134..0x13B], code);
+    assert_eq!(rom[0x14D], header::header_checksum(&rom));
+
+    let mut gameboy = Gameboy::with_program(&code, 0x0134).unwrap();
+    gameboy.run_frames(1);
+    assert_eq!(gameboy.peek(0xC000), 0x42);
+}
+
+#[test]
+fn test_pattern_draws_stripes() {
+    let mut gameboy = Gameboy::new(test_pattern::rom()).unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    let frame = gameboy.run_frames(3).clone();
+    for y in 0..144 {
+        for x in 0..160 {
+            assert_eq!(frame[(x, y)], test_pattern::shade(x, y), "({}, {})", x, y);
+        }
+    }
+    assert_eq!(frame[(8, 8)], 2);
+}
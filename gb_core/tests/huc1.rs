@@ -0,0 +1,165 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use gb_core::{
+    gameboy::{
+        cart::{
+            huc1::{IrPort, Unconnected},
+            Cart,
+        },
+        Chip, ClockContext,
+    },
+    GbError,
+};
+use gb_cpu::CpuOutputPins;
+
+/// A 1MiB HuC-1 cartridge with 32KiB of RAM, where the first byte of each bank is its bank number
+fn cart() -> Cart {
+    let mut rom = vec![0; 0x100000];
+    rom[0x147] = 0xFF; // HuC-1+RAM+BATTERY
+    rom[0x148] = 0x05; // 1MiB
+    rom[0x149] = 0x03; // 32KiB
+    for bank in 1..64 {
+        rom[bank * 0x4000] = bank as u8;
+    }
+    Cart::new(rom).unwrap()
+}
+
+fn write(cart: &mut Cart, addr: u16, data: u8) {
+    cart.clock(
+        CpuOutputPins::Write { addr, data },
+        &mut 0xFF,
+        &mut 0,
+        &ClockContext::default(),
+    );
+}
+
+fn read(cart: &mut Cart, addr: u16) -> u8 {
+    let mut data = 0xFF;
+    cart.clock(
+        CpuOutputPins::Read { addr },
+        &mut data,
+        &mut 0,
+        &ClockContext::default(),
+    );
+    data
+}
+
+/// Shines a light into the transceiver when `light` is set, and keeps track of the LED
+#[derive(Default)]
+struct Link {
+    light: Arc<AtomicBool>,
+    led: Arc<AtomicBool>,
+}
+
+impl IrPort for Link {
+    fn light_seen(&mut self) -> bool {
+        self.light.load(Ordering::Relaxed)
+    }
+
+    fn set_led(&mut self, on: bool) {
+        self.led.store(on, Ordering::Relaxed);
+    }
+}
+
+#[test]
+fn rom_bank_is_6_bits() {
+    let mut cart = cart();
+    assert_eq!(read(&mut cart, 0x4000), 1);
+    write(&mut cart, 0x2000, 0x3F);
+    assert_eq!(read(&mut cart, 0x4000), 63);
+    assert_eq!(cart.rom_bank(), 63);
+    write(&mut cart, 0x3FFF, 0x45);
+    assert_eq!(read(&mut cart, 0x4000), 5);
+    write(&mut cart, 0x2000, 0x00);
+    assert_eq!(read(&mut cart, 0x4000), 1);
+    assert_eq!(read(&mut cart, 0x0000), 0);
+
+    // No mode select, so $0000-$3FFF always has bank 0
+    write(&mut cart, 0x4000, 0x03);
+    write(&mut cart, 0x6000, 0x01);
+    assert_eq!(read(&mut cart, 0x0000), 0);
+    assert_eq!(read(&mut cart, 0x4000), 1);
+    assert!(!cart.is_mapper_register(0x6000));
+}
+
+#[test]
+fn ram_has_4_banks_and_no_enable() {
+    let mut cart = cart();
+    for bank in 0..4 {
+        write(&mut cart, 0x4000, bank);
+        write(&mut cart, 0xA000, 0x10 + bank);
+    }
+    // Only the lower 2 bits of the bank are used
+    write(&mut cart, 0x4000, 0x06);
+    assert_eq!(cart.ram_bank(), 2);
+    assert_eq!(read(&mut cart, 0xA000), 0x12);
+    for bank in 0..4 {
+        assert_eq!(cart.ram().unwrap()[bank * 0x2000], 0x10 + bank as u8);
+    }
+}
+
+#[test]
+fn ir_mode_maps_the_transceiver_over_ram() {
+    let mut cart = cart();
+    write(&mut cart, 0xA000, 0x42);
+
+    write(&mut cart, 0x0000, 0x0E);
+    assert_eq!(read(&mut cart, 0xA000), 0xC0);
+    // The LED is written instead of RAM
+    write(&mut cart, 0xA000, 0x01);
+    assert_eq!(cart.ram().unwrap()[0], 0x42);
+
+    let link = Link::default();
+    let (light, led) = (link.light.clone(), link.led.clone());
+    cart.set_ir_port(Box::new(link)).unwrap();
+    assert!(led.load(Ordering::Relaxed));
+    light.store(true, Ordering::Relaxed);
+    assert_eq!(read(&mut cart, 0xBFFF), 0xC1);
+    write(&mut cart, 0xA000, 0x00);
+    assert!(!led.load(Ordering::Relaxed));
+
+    // Anything else maps RAM back
+    write(&mut cart, 0x0000, 0x0A);
+    assert_eq!(read(&mut cart, 0xA000), 0x42);
+    write(&mut cart, 0x0000, 0x00);
+    assert_eq!(read(&mut cart, 0xA000), 0x42);
+}
+
+#[test]
+fn saves_like_mbc1_with_battery() {
+    let mut saved = cart();
+    assert!(saved.has_battery());
+    write(&mut saved, 0x4000, 0x03);
+    write(&mut saved, 0xA123, 0x99);
+    // Writes in IR mode don't touch the save
+    write(&mut saved, 0x0000, 0x0E);
+    write(&mut saved, 0xA124, 0x01);
+    let save = saved.take_save().unwrap().to_vec();
+    assert_eq!(save.len(), 0x8000);
+    assert_eq!(save[0x6123], 0x99);
+    assert_eq!(save[0x6124], 0x00);
+    assert!(saved.take_save().is_none());
+
+    let mut loaded = cart();
+    loaded.load_ram(&save).unwrap();
+    write(&mut loaded, 0x4000, 0x03);
+    assert_eq!(read(&mut loaded, 0xA123), 0x99);
+    assert!(matches!(
+        loaded.load_ram(&[0; 0x2000]),
+        Err(GbError::InvalidSaveData(_))
+    ));
+}
+
+#[test]
+fn only_huc1_has_an_ir_port() {
+    let mut rom = vec![0; 0x8000];
+    rom[0x147] = 0x00;
+    let mut cart = Cart::new(rom).unwrap();
+    assert!(matches!(
+        cart.set_ir_port(Box::new(Unconnected)),
+        Err(GbError::InvalidState(_))
+    ));
+}
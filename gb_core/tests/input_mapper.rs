@@ -0,0 +1,211 @@
+use gb_core::{
+    frontend::input::{
+        Binding, HostInput, InputMapper, InputProfile, OppositePolicy, ProfileParseError,
+    },
+    gameboy::joypad::{Button, Joypad},
+};
+
+const KEY_LEFT: u32 = 37;
+const KEY_RIGHT: u32 = 39;
+const KEY_Z: u32 = 90;
+
+fn profile(policy: OppositePolicy) -> InputProfile {
+    let mut profile = InputProfile::default();
+    profile.opposite_policy = policy;
+    profile.bind(Binding::Key(KEY_LEFT), Button::Left);
+    profile.bind(Binding::Key(KEY_RIGHT), Button::Right);
+    profile.bind(Binding::AxisNegative(0), Button::Left);
+    profile.bind(Binding::Key(KEY_Z), Button::A);
+    profile
+}
+
+/// Whether `button` was pressed on each of the next `frames` frames
+fn latch_frames(
+    mapper: &mut InputMapper,
+    joypad: &mut Joypad,
+    button: Button,
+    frames: usize,
+) -> Vec<bool> {
+    (0..frames)
+        .map(|_| {
+            mapper.latch(joypad);
+            joypad.is_pressed(button)
+        })
+        .collect()
+}
+
+#[test]
+fn events_take_effect_at_the_latch() {
+    let mut mapper = InputMapper::new(profile(OppositePolicy::Neutral));
+    let mut joypad = Joypad::default();
+
+    mapper.handle(HostInput::KeyDown(KEY_Z));
+    assert!(!joypad.is_pressed(Button::A));
+    mapper.latch(&mut joypad);
+    assert!(joypad.is_pressed(Button::A));
+
+    mapper.handle(HostInput::KeyUp(KEY_Z));
+    mapper.latch(&mut joypad);
+    assert!(!joypad.is_pressed(Button::A));
+}
+
+#[test]
+fn axis_past_threshold_presses() {
+    let mut mapper = InputMapper::new(profile(OppositePolicy::Neutral));
+    let mut joypad = Joypad::default();
+
+    mapper.handle(HostInput::GamepadAxis {
+        axis: 0,
+        value: -0.3,
+    });
+    mapper.latch(&mut joypad);
+    assert!(!joypad.is_pressed(Button::Left));
+
+    mapper.handle(HostInput::GamepadAxis {
+        axis: 0,
+        value: -0.9,
+    });
+    mapper.latch(&mut joypad);
+    assert!(joypad.is_pressed(Button::Left));
+
+    mapper.handle(HostInput::GamepadAxis {
+        axis: 0,
+        value: 0.0,
+    });
+    mapper.latch(&mut joypad);
+    assert!(!joypad.is_pressed(Button::Left));
+}
+
+#[test]
+fn turbo_cadence_is_deterministic() {
+    let mut profile = profile(OppositePolicy::Neutral);
+    profile.set_turbo(Button::A, Some(4));
+    let mut mapper = InputMapper::new(profile.clone());
+    let mut joypad = Joypad::default();
+
+    // Idle frames before the press don't shift the phase
+    latch_frames(&mut mapper, &mut joypad, Button::A, 3);
+    mapper.handle(HostInput::KeyDown(KEY_Z));
+    let first = latch_frames(&mut mapper, &mut joypad, Button::A, 8);
+    assert_eq!(first, [true, true, false, false, true, true, false, false]);
+
+    // Releasing and pressing again restarts the cadence
+    mapper.handle(HostInput::KeyUp(KEY_Z));
+    mapper.latch(&mut joypad);
+    mapper.handle(HostInput::KeyDown(KEY_Z));
+    assert_eq!(latch_frames(&mut mapper, &mut joypad, Button::A, 8), first);
+
+    // Odd periods round the pressed half up
+    profile.set_turbo(Button::A, Some(3));
+    let mut mapper = InputMapper::new(profile);
+    mapper.handle(HostInput::KeyDown(KEY_Z));
+    assert_eq!(
+        latch_frames(&mut mapper, &mut joypad, Button::A, 6),
+        [true, true, false, true, true, false]
+    );
+}
+
+#[test]
+fn opposite_directions_allow() {
+    let mut mapper = InputMapper::new(profile(OppositePolicy::Allow));
+    let mut joypad = Joypad::default();
+    mapper.handle(HostInput::KeyDown(KEY_LEFT));
+    mapper.handle(HostInput::KeyDown(KEY_RIGHT));
+    mapper.latch(&mut joypad);
+    assert!(joypad.is_pressed(Button::Left));
+    assert!(joypad.is_pressed(Button::Right));
+}
+
+#[test]
+fn opposite_directions_neutral() {
+    let mut mapper = InputMapper::new(profile(OppositePolicy::Neutral));
+    let mut joypad = Joypad::default();
+    mapper.handle(HostInput::KeyDown(KEY_LEFT));
+    mapper.latch(&mut joypad);
+    assert!(joypad.is_pressed(Button::Left));
+
+    mapper.handle(HostInput::KeyDown(KEY_RIGHT));
+    mapper.latch(&mut joypad);
+    assert!(!joypad.is_pressed(Button::Left));
+    assert!(!joypad.is_pressed(Button::Right));
+
+    mapper.handle(HostInput::KeyUp(KEY_LEFT));
+    mapper.latch(&mut joypad);
+    assert!(joypad.is_pressed(Button::Right));
+}
+
+#[test]
+fn opposite_directions_last_wins() {
+    let mut mapper = InputMapper::new(profile(OppositePolicy::LastWins));
+    let mut joypad = Joypad::default();
+
+    mapper.handle(HostInput::KeyDown(KEY_LEFT));
+    mapper.handle(HostInput::KeyDown(KEY_RIGHT));
+    mapper.latch(&mut joypad);
+    assert!(!joypad.is_pressed(Button::Left));
+    assert!(joypad.is_pressed(Button::Right));
+
+    // Pressing a second binding for Left makes it the latest again
+    mapper.handle(HostInput::GamepadAxis {
+        axis: 0,
+        value: -1.0,
+    });
+    mapper.latch(&mut joypad);
+    assert!(joypad.is_pressed(Button::Left));
+    assert!(!joypad.is_pressed(Button::Right));
+
+    mapper.handle(HostInput::GamepadAxis {
+        axis: 0,
+        value: 0.0,
+    });
+    mapper.latch(&mut joypad);
+    assert!(!joypad.is_pressed(Button::Left));
+    assert!(joypad.is_pressed(Button::Right));
+}
+
+#[test]
+fn profile_round_trips() {
+    let mut profile = profile(OppositePolicy::LastWins);
+    profile.bind(Binding::GamepadButton(3), Button::Start);
+    profile.bind(Binding::AxisPositive(1), Button::Down);
+    profile.set_turbo(Button::B, Some(6));
+
+    let text = profile.to_string();
+    assert_eq!(text.parse::<InputProfile>(), Ok(profile));
+}
+
+#[test]
+fn parse_profile() {
+    let profile: InputProfile = "
+        # Arrow keys
+        Left = key 37
+        turbo A = 2  # fast
+        opposite = allow
+    "
+    .parse()
+    .unwrap();
+    assert_eq!(profile.bindings, [(Binding::Key(37), Button::Left)]);
+    assert_eq!(profile.turbo(Button::A), Some(2));
+    assert_eq!(profile.opposite_policy, OppositePolicy::Allow);
+
+    assert_eq!(
+        "Left key 37".parse::<InputProfile>(),
+        Err(ProfileParseError::MissingEquals(1))
+    );
+    assert_eq!(
+        "\nL = key 37".parse::<InputProfile>(),
+        Err(ProfileParseError::UnknownButton(2, "L".to_owned()))
+    );
+    assert_eq!(
+        "Left = mouse 1".parse::<InputProfile>(),
+        Err(ProfileParseError::InvalidBinding(1, "mouse 1".to_owned()))
+    );
+    assert_eq!(
+        "turbo A = 1".parse::<InputProfile>(),
+        Err(ProfileParseError::InvalidTurbo(1, "1".to_owned()))
+    );
+    assert_eq!(
+        "opposite = first-wins".parse::<InputProfile>(),
+        Err(ProfileParseError::UnknownPolicy(1, "first-wins".to_owned()))
+    );
+}
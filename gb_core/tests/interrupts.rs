@@ -0,0 +1,99 @@
+use gb_core::gameboy::{cart::header, Gameboy, ResetKind};
+
+/// Where the interrupt in [`program`] is dispatched from
+const RETURN_ADDR: u16 = 0x0161;
+
+/// Turns the LCD off so that nothing else requests interrupts, sets SP, IE and IF, and then enables
+/// interrupts, so that an interrupt is dispatched from [`RETURN_ADDR`]
+#[rustfmt::skip]
+fn program(sp: u16, ie: u8, if_: u8) -> [u8; 19] {
+    let [sp_lo, sp_hi] = sp.to_le_bytes();
+    [
+        0xF3,              // DI
+        0xAF,              // XOR A
+        0xE0, 0x40,        // LDH (LCDC), A
+        0x31, sp_lo, sp_hi, // LD SP, sp
+        0x3E, ie,          // LD A, ie
+        0xE0, 0xFF,        // LDH (IE), A
+        0x3E, if_,         // LD A, if_
+        0xE0, 0x0F,        // LDH (IF), A
+        0xFB,              // EI
+        0x00,              // NOP
+        0x18, 0xFE,        // $0161: JR -2
+    ]
+}
+
+/// Runs [`program`] with a handler at $0000 and at every interrupt vector, which stores its address
+/// plus one to $C000
+fn run(sp: u16, ie: u8, if_: u8) -> Gameboy {
+    let mut rom = header::flat_rom(&program(sp, ie, if_), 0x0150, "").unwrap();
+    for vector in [0x00, 0x40, 0x48, 0x50, 0x58, 0x60] {
+        #[rustfmt::skip]
+        rom[vector..vector + 7].copy_from_slice(&[
+            0x3E, vector as u8 + 1, // LD A, vector + 1
+            0xEA, 0x00, 0xC0,       // LD ($C000), A
+            0x18, 0xFE,             // JR -2
+        ]);
+    }
+    let mut gameboy = Gameboy::new(rom).unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    gameboy.run_frames(1);
+    gameboy
+}
+
+#[test]
+fn highest_priority_first() {
+    let gameboy = run(0xFFFE, 0x1F, 0x14);
+    assert_eq!(gameboy.peek(0xC000), 0x51);
+    // Only the serviced interrupt is acknowledged
+    assert_eq!(gameboy.peek(0xFF0F) & 0x1F, 0x10);
+    assert_eq!(
+        u16::from_le_bytes([gameboy.peek(0xFFFC), gameboy.peek(0xFFFD)]),
+        RETURN_ADDR
+    );
+}
+
+/// Pushing the upper byte of PC to $FFFF disables the timer interrupt before the vector is decided
+#[test]
+fn ie_overwritten_by_upper_byte_cancels() {
+    let gameboy = run(0x0000, 0x04, 0x04);
+    assert_eq!(gameboy.peek(0xC000), 0x01);
+    assert_eq!(gameboy.peek(0xFFFF), (RETURN_ADDR >> 8) as u8);
+    assert_eq!(gameboy.peek(0xFFFE), RETURN_ADDR as u8);
+    // Nothing was acknowledged, and interrupts stay disabled
+    assert_eq!(gameboy.peek(0xFF0F) & 0x1F, 0x04);
+    assert!(!gameboy.cpu.cpu.ime);
+}
+
+/// The upper byte of PC enables VBlank, which is also requested and takes priority over the timer
+#[test]
+fn ie_overwritten_by_upper_byte_retargets() {
+    let gameboy = run(0x0000, 0x04, 0x05);
+    assert_eq!(gameboy.peek(0xC000), 0x41);
+    assert_eq!(gameboy.peek(0xFF0F) & 0x1F, 0x04);
+}
+
+/// By the time the lower byte is pushed to $FFFF, the vector has already been decided
+#[test]
+fn ie_overwritten_by_lower_byte_does_not_cancel() {
+    let gameboy = run(0x0001, 0x04, 0x04);
+    assert_eq!(gameboy.peek(0xC000), 0x51);
+    assert_eq!(gameboy.peek(0xFFFF), RETURN_ADDR as u8);
+    assert_eq!(gameboy.peek(0xFF0F) & 0x1F, 0x00);
+}
+
+/// The PPU's VBlank line stays high for all of VBlank, but IF only latches its rising edge
+#[test]
+fn vblank_request_is_latched() {
+    let mut gameboy = Gameboy::with_program(&[0x18, 0xFE], 0x0150).unwrap(); // JR -2
+    gameboy.run_frames(1);
+    gameboy.run_cycles(4);
+    assert_eq!(gameboy.peek(0xFF0F) & 0x01, 0x01);
+
+    gameboy.poke(0xFF0F, 0x00);
+    gameboy.run_cycles(456 * 4);
+    assert_eq!(gameboy.peek(0xFF0F) & 0x01, 0x00);
+    gameboy.run_frames(1);
+    gameboy.run_cycles(4);
+    assert_eq!(gameboy.peek(0xFF0F) & 0x01, 0x01);
+}
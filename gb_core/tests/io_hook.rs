@@ -0,0 +1,112 @@
+use std::sync::{Arc, Mutex};
+
+use gb_core::{
+    gameboy::{io_hook::IoHook, ClockContext, Gameboy},
+    GbError,
+};
+
+/// The accesses a [`Register`] has seen
+#[derive(Debug, Default)]
+struct Accesses {
+    reads: u32,
+    writes: Vec<u8>,
+    /// The cycle count of each access
+    cycles: Vec<u64>,
+}
+
+/// A register that reads back the last value written to it, plus one
+struct Register {
+    value: u8,
+    accesses: Arc<Mutex<Accesses>>,
+}
+
+impl IoHook for Register {
+    fn read(&mut self, ctx: &ClockContext) -> u8 {
+        let mut accesses = self.accesses.lock().unwrap();
+        accesses.reads += 1;
+        accesses.cycles.push(ctx.cycles);
+        self.value.wrapping_add(1)
+    }
+
+    fn write(&mut self, value: u8, ctx: &ClockContext) {
+        let mut accesses = self.accesses.lock().unwrap();
+        accesses.writes.push(value);
+        accesses.cycles.push(ctx.cycles);
+        self.value = value;
+    }
+}
+
+fn register() -> (Box<Register>, Arc<Mutex<Accesses>>) {
+    let accesses = Arc::new(Mutex::new(Accesses::default()));
+    let register = Register {
+        value: 0,
+        accesses: accesses.clone(),
+    };
+    (Box::new(register), accesses)
+}
+
+#[rustfmt::skip]
+const PROGRAM: [u8; 16] = [
+    0x3E, 0x41,       // LD A, $41
+    0xE0, 0x7F,       // LDH ($7F), A
+    0xF0, 0x7F,       // LDH A, ($7F)
+    0xEA, 0x00, 0xC0, // LD ($C000), A
+    0xF0, 0x7E,       // LDH A, ($7E)
+    0xEA, 0x01, 0xC0, // LD ($C001), A
+    0x18, 0xFE,       // JR -2
+];
+const INSTRUCTIONS: usize = 7;
+
+#[test]
+fn program_accesses_hook() {
+    let mut gameboy = Gameboy::with_program(&PROGRAM, 0x0150).unwrap();
+    let (register, accesses) = register();
+    gameboy.register_io_hook(0xFF7F, register).unwrap();
+    for _ in 0..INSTRUCTIONS {
+        gameboy.step_instruction();
+    }
+
+    assert_eq!(gameboy.peek(0xC000), 0x42);
+    // Registers without a hook still read as $FF
+    assert_eq!(gameboy.peek(0xC001), 0xFF);
+
+    let accesses = accesses.lock().unwrap();
+    assert_eq!(accesses.reads, 1);
+    assert_eq!(accesses.writes, [0x41]);
+    // LDH (n), A and LDH A, (n) both access the register on their last M-cycle
+    let [write, read] = accesses.cycles[..] else {
+        panic!("{:?}", accesses.cycles)
+    };
+    assert_eq!(read - write, 12);
+}
+
+#[test]
+fn removed_hook_reads_ff() {
+    let mut gameboy = Gameboy::with_program(&PROGRAM, 0x0150).unwrap();
+    let (register, accesses) = register();
+    gameboy.register_io_hook(0xFF7F, register).unwrap();
+    assert!(gameboy.remove_io_hook(0xFF7F).is_some());
+    for _ in 0..INSTRUCTIONS {
+        gameboy.step_instruction();
+    }
+
+    assert_eq!(gameboy.peek(0xC000), 0xFF);
+    assert_eq!(accesses.lock().unwrap().reads, 0);
+}
+
+#[test]
+fn hooks_cannot_shadow_chips() {
+    let mut gameboy = Gameboy::with_program(&PROGRAM, 0x0150).unwrap();
+    for (addr, err) in [
+        // LCDC
+        (0xFF40, GbError::ChipConflict(0xFF40)),
+        // IF is handled by the Gameboy itself
+        (0xFF0F, GbError::ChipConflict(0xFF0F)),
+        (0xC000, GbError::AddressOutOfRange(0xC000)),
+        (0xFF80, GbError::AddressOutOfRange(0xFF80)),
+    ] {
+        assert_eq!(gameboy.register_io_hook(addr, register().0), Err(err));
+    }
+    // The CGB infrared port is free on a DMG
+    assert_eq!(gameboy.register_io_hook(0xFF56, register().0), Ok(()));
+}
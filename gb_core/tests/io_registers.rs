@@ -0,0 +1,103 @@
+use std::ops::RangeInclusive;
+
+use gb_core::gameboy::Gameboy;
+
+/// Register range, value read after writing $00, value read after writing $FF, and a mask of the
+/// bits that are checked. Bits that change on their own (like the LY counter) are not checked.
+#[rustfmt::skip]
+const REGISTERS: &[(RangeInclusive<u16>, u8, u8, u8)] = &[
+    (0xFF00..=0xFF00, 0xCF, 0xFF, 0xFF), // P1
+    (0xFF01..=0xFF01, 0x00, 0xFF, 0xFF), // SB
+    (0xFF02..=0xFF02, 0x7C, 0xFF, 0xFF), // SC, keeping the CGB fast clock bit
+    (0xFF03..=0xFF03, 0xFF, 0xFF, 0xFF),
+    (0xFF04..=0xFF04, 0x00, 0x00, 0xFF), // DIV
+    (0xFF05..=0xFF06, 0x00, 0xFF, 0xFF), // TIMA, TMA
+    (0xFF07..=0xFF07, 0xF8, 0xFF, 0xFF), // TAC
+    (0xFF08..=0xFF0E, 0xFF, 0xFF, 0xFF),
+    (0xFF0F..=0xFF0F, 0xE0, 0xFF, 0xE0), // IF
+    (0xFF10..=0xFF10, 0x80, 0xFF, 0xFF), // NR10
+    (0xFF11..=0xFF11, 0x3F, 0xFF, 0xFF),
+    (0xFF12..=0xFF12, 0x00, 0xFF, 0xFF),
+    (0xFF13..=0xFF13, 0xFF, 0xFF, 0xFF),
+    (0xFF14..=0xFF14, 0xBF, 0xFF, 0xFF),
+    (0xFF15..=0xFF15, 0xFF, 0xFF, 0xFF),
+    (0xFF16..=0xFF16, 0x3F, 0xFF, 0xFF), // NR21
+    (0xFF17..=0xFF17, 0x00, 0xFF, 0xFF),
+    (0xFF18..=0xFF18, 0xFF, 0xFF, 0xFF),
+    (0xFF19..=0xFF19, 0xBF, 0xFF, 0xFF),
+    (0xFF1A..=0xFF1A, 0x7F, 0xFF, 0xFF), // NR30
+    (0xFF1B..=0xFF1B, 0xFF, 0xFF, 0xFF),
+    (0xFF1C..=0xFF1C, 0x9F, 0xFF, 0xFF),
+    (0xFF1D..=0xFF1D, 0xFF, 0xFF, 0xFF),
+    (0xFF1E..=0xFF1E, 0xBF, 0xFF, 0xFF),
+    (0xFF1F..=0xFF1F, 0xFF, 0xFF, 0xFF),
+    (0xFF20..=0xFF20, 0xFF, 0xFF, 0xFF), // NR41
+    (0xFF21..=0xFF22, 0x00, 0xFF, 0xFF),
+    (0xFF23..=0xFF23, 0xBF, 0xFF, 0xFF),
+    (0xFF24..=0xFF25, 0x00, 0xFF, 0xFF), // NR50, NR51
+    (0xFF26..=0xFF26, 0x70, 0xF0, 0xFF), // NR52
+    (0xFF27..=0xFF2F, 0xFF, 0xFF, 0xFF),
+    (0xFF30..=0xFF3F, 0x00, 0xFF, 0xFF), // Wave RAM
+    (0xFF40..=0xFF40, 0x00, 0xFF, 0xFF), // LCDC
+    (0xFF41..=0xFF41, 0x80, 0xF8, 0xF8), // STAT
+    (0xFF42..=0xFF43, 0x00, 0xFF, 0xFF), // SCY, SCX
+    (0xFF44..=0xFF44, 0x00, 0x00, 0x00), // LY
+    (0xFF45..=0xFF4B, 0x00, 0xFF, 0xFF), // LYC, DMA, BGP, OBP0, OBP1, WY, WX
+    (0xFF4C..=0xFF7F, 0xFF, 0xFF, 0xFF),
+    (0xFFFF..=0xFFFF, 0x00, 0xFF, 0xFF), // IE
+];
+
+/// A program that writes $00 and then $FF to every register, storing what is read back after each
+/// write in WRAM from $C000. It ends with an infinite loop.
+fn write_read_program() -> Vec<u8> {
+    let mut code = Vec::new();
+    let io_addrs = REGISTERS.iter().flat_map(|(range, ..)| range.clone());
+    for (i, addr) in io_addrs.enumerate() {
+        for (j, value) in [0x00, 0xFF].iter().enumerate() {
+            let [result_lo, result_hi] = (0xC000 + i as u16 * 2 + j as u16).to_le_bytes();
+            let [addr_lo, addr_hi] = addr.to_le_bytes();
+            #[rustfmt::skip]
+            code.extend_from_slice(&[
+                0x3E, *value,                 // LD A, value
+                0xEA, addr_lo, addr_hi,       // LD (addr), A
+                0xFA, addr_lo, addr_hi,       // LD A, (addr)
+                0xEA, result_lo, result_hi,   // LD (result), A
+            ]);
+        }
+    }
+    code.extend_from_slice(&[0x18, 0xFE]); // JR -2
+    code
+}
+
+#[test]
+fn io_register_read_back() {
+    let code = write_read_program();
+    let end = 0x150 + code.len() as u16 - 2;
+    let mut gameboy = Gameboy::with_program(&code, 0x0150).unwrap();
+    for _ in 0..10_000 {
+        gameboy.step_instruction();
+    }
+    // The JR instruction may be partway through executing
+    assert!(
+        (end..=end + 2).contains(&gameboy.cpu.cpu.registers.pc),
+        "test ROM did not finish"
+    );
+
+    let expected = REGISTERS
+        .iter()
+        .flat_map(|(range, after_00, after_ff, mask)| {
+            range
+                .clone()
+                .map(move |addr| (addr, *after_00, *after_ff, *mask))
+        });
+    for (i, (addr, after_00, after_ff, mask)) in expected.enumerate() {
+        let read_00 = gameboy.memory.read(0xC000 + i as u16 * 2).unwrap();
+        let read_ff = gameboy.memory.read(0xC000 + i as u16 * 2 + 1).unwrap();
+        assert_eq!(
+            (read_00 & mask, read_ff & mask),
+            (after_00 & mask, after_ff & mask),
+            "register {:#06X}",
+            addr
+        );
+    }
+}
@@ -0,0 +1,133 @@
+use gb_core::gameboy::{
+    joypad::{Button, JoypadLatchMode},
+    ppu::{consts::FRAME_T_CYCLES, registers::LCDC},
+    Gameboy, GameboyBuilder, ResetKind,
+};
+
+/// Selects the d-pad, then copies P1 to $C000 over and over
+#[rustfmt::skip]
+const POLL: [u8; 10] = [
+    0x3E, 0x20,       // LD A, $20
+    0xE0, 0x00,       // LDH (P1), A
+    // loop:
+    0xF0, 0x00,       // LDH A, (P1)
+    0xEA, 0x00, 0xC0, // LD ($C000), A
+    0x18,             // JR loop
+];
+
+fn polling(mode: JoypadLatchMode) -> Gameboy {
+    let mut code = POLL.to_vec();
+    code.push(0xF9);
+    let rom = gb_core::gameboy::cart::header::flat_rom(&code, 0x0150, "").unwrap();
+    let mut gameboy = GameboyBuilder::new()
+        .rom(rom)
+        .joypad_latch_mode(mode)
+        .build()
+        .unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    gameboy.cpu.cpu.registers.pc = 0x0150;
+    // Halfway through the second frame
+    gameboy.run_frames(1);
+    gameboy.run_cycles(FRAME_T_CYCLES as u64 / 2);
+    gameboy
+}
+
+fn d_pad(gameboy: &Gameboy) -> u8 {
+    gameboy.peek(0xC000) & 0x0F
+}
+
+#[test]
+fn immediate_presses_are_seen_the_same_frame() {
+    let mut gameboy = polling(JoypadLatchMode::Immediate);
+    assert_eq!(d_pad(&gameboy), 0x0F);
+    gameboy.joypad.press(Button::Right);
+    assert!(gameboy.joypad.is_pressed(Button::Right));
+    gameboy.run_cycles(100);
+    assert_eq!(d_pad(&gameboy), 0x0E);
+}
+
+#[test]
+fn vblank_presses_wait_for_the_next_frame() {
+    let mut gameboy = polling(JoypadLatchMode::VBlank);
+    gameboy.joypad.press(Button::Right);
+    assert!(!gameboy.joypad.is_pressed(Button::Right));
+    gameboy.run_cycles(FRAME_T_CYCLES as u64 / 4);
+    assert_eq!(d_pad(&gameboy), 0x0F);
+
+    gameboy.run_frames(1);
+    gameboy.run_cycles(100);
+    assert!(gameboy.joypad.is_pressed(Button::Right));
+    assert_eq!(d_pad(&gameboy), 0x0E);
+}
+
+#[test]
+fn switching_to_immediate_applies_waiting_presses() {
+    let mut gameboy = polling(JoypadLatchMode::VBlank);
+    gameboy.joypad.press(Button::Left);
+    gameboy.joypad.set_latch_mode(JoypadLatchMode::Immediate);
+    assert!(gameboy.joypad.is_pressed(Button::Left));
+}
+
+/// The number of M-cycles until the joypad interrupt is requested, and whether the press could be
+/// read by then
+fn cycles_until_interrupt(gameboy: &mut Gameboy) -> (u32, bool) {
+    for cycles in 1.. {
+        let info = gameboy.tick();
+        if info.interrupts_raised & 0x10 != 0 {
+            return (cycles, gameboy.joypad.is_pressed(Button::Right));
+        }
+        assert!(cycles < 2 * FRAME_T_CYCLES as u32 / 4);
+    }
+    unreachable!()
+}
+
+#[test]
+fn the_interrupt_fires_when_the_press_becomes_visible() {
+    let mut gameboy = polling(JoypadLatchMode::Immediate);
+    gameboy.joypad.press(Button::Right);
+    assert_eq!(cycles_until_interrupt(&mut gameboy), (1, true));
+
+    let mut gameboy = polling(JoypadLatchMode::VBlank);
+    gameboy.joypad.press(Button::Right);
+    let (cycles, visible) = cycles_until_interrupt(&mut gameboy);
+    assert!(visible);
+    // Half a frame was left when the button was pressed
+    assert!(cycles > FRAME_T_CYCLES as u32 / 8, "{} M-cycles", cycles);
+}
+
+#[test]
+fn each_line_that_falls_requests_the_interrupt() {
+    let mut gameboy = polling(JoypadLatchMode::Immediate);
+    gameboy.joypad.press(Button::Right);
+    assert_ne!(gameboy.tick().interrupts_raised & 0x10, 0);
+    // Acknowledge it, like the handler would
+    gameboy.poke(0xFF0F, 0);
+    gameboy.joypad.press(Button::Left);
+    assert_ne!(gameboy.tick().interrupts_raised & 0x10, 0);
+    gameboy.poke(0xFF0F, 0);
+    // Releasing a button raises its line, which doesn't request it
+    gameboy.joypad.release(Button::Right);
+    assert_eq!(gameboy.tick().interrupts_raised & 0x10, 0);
+}
+
+#[test]
+fn vblank_presses_are_seen_while_the_lcd_is_off() {
+    let mut gameboy = polling(JoypadLatchMode::VBlank);
+    gameboy.ppu.lcdc.remove(LCDC::LCD_ENABLE);
+    gameboy.run_cycles(1000);
+    gameboy.joypad.press(Button::Right);
+    gameboy.run_cycles(FRAME_T_CYCLES as u64 + 100);
+    assert!(gameboy.joypad.is_pressed(Button::Right));
+    assert_eq!(d_pad(&gameboy), 0x0E);
+}
+
+#[test]
+fn only_the_last_change_before_vblank_is_seen() {
+    let mut gameboy = polling(JoypadLatchMode::VBlank);
+    gameboy.joypad.press(Button::Right);
+    gameboy.joypad.release(Button::Right);
+    gameboy.joypad.press(Button::Left);
+    gameboy.run_frames(1);
+    assert!(!gameboy.joypad.is_pressed(Button::Right));
+    assert!(gameboy.joypad.is_pressed(Button::Left));
+}
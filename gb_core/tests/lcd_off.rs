@@ -0,0 +1,87 @@
+#[allow(dead_code)]
+mod common;
+
+use common::black_tile_in_corner;
+use gb_core::gameboy::{
+    events::{DisplayEvent, Event, EventMask},
+    ppu::{
+        color::{COLOR_BLACK, COLOR_WHITE},
+        registers::{LCDC, STAT},
+    },
+};
+
+const FRAME_T_CYCLES: u64 = 70224;
+
+#[test]
+fn lcd_off_shows_one_blank_frame_and_keeps_pacing() {
+    let mut gameboy = black_tile_in_corner();
+    let receiver = gameboy.frame_receiver();
+    let events = gameboy.subscribe(EventMask::DISPLAY);
+    gameboy.run_frames(2);
+    assert_eq!(gameboy.get_frame().color(0, 0), COLOR_BLACK);
+
+    gameboy.ppu.lcdc.remove(LCDC::LCD_ENABLE);
+    let mut cycles = gameboy.cycles();
+    let mut blank_frame = None;
+    for i in 0..10 {
+        gameboy.run_frames(1);
+        let elapsed = gameboy.cycles() - cycles;
+        cycles = gameboy.cycles();
+        if i == 0 {
+            // The PPU only notices on its next M-cycle
+            assert!((FRAME_T_CYCLES..=FRAME_T_CYCLES + 4).contains(&elapsed));
+        } else {
+            assert_eq!(elapsed, FRAME_T_CYCLES);
+        }
+
+        let frame = gameboy.get_frame();
+        assert!(frame.colors().all(|color| color == COLOR_WHITE));
+        assert_eq!(gameboy.ppu.ly, 0);
+
+        // The blank frame was only pushed once
+        let number = receiver.latest().number();
+        assert_eq!(*blank_frame.get_or_insert(number), number);
+    }
+    let disabled: Vec<Event> = events.drain().map(|record| record.event).collect();
+    assert_eq!(disabled, [Event::Display(DisplayEvent::LcdDisabled)]);
+
+    gameboy.ppu.lcdc.insert(LCDC::LCD_ENABLE);
+    gameboy.run_frames(1);
+    assert_eq!(gameboy.get_frame().color(0, 0), COLOR_BLACK);
+    assert_eq!(gameboy.get_frame().color(8, 0), COLOR_WHITE);
+    assert!(receiver.latest().number() > blank_frame.unwrap());
+    let enabled: Vec<Event> = events.drain().map(|record| record.event).collect();
+    assert_eq!(enabled, [Event::Display(DisplayEvent::LcdEnabled)]);
+}
+
+#[test]
+fn blank_frame_uses_color_0_of_bgp() {
+    let mut gameboy = black_tile_in_corner();
+    gameboy.run_frames(1);
+    // Color 0 is the darkest shade
+    gameboy.ppu.bgp = 0x03;
+    gameboy.ppu.lcdc.remove(LCDC::LCD_ENABLE);
+    gameboy.run_frames(1);
+    assert!(gameboy
+        .get_frame()
+        .colors()
+        .all(|color| color == COLOR_BLACK));
+}
+
+#[test]
+fn lcd_off_mid_frame_stops_the_ppu() {
+    let mut gameboy = black_tile_in_corner();
+    gameboy.run_frames(1);
+    while gameboy.ppu.ly != 60 {
+        gameboy.tick();
+    }
+    gameboy.ppu.lcdc.remove(LCDC::LCD_ENABLE);
+    gameboy.tick();
+    assert_eq!(gameboy.ppu.ly, 0);
+    assert_eq!(gameboy.ppu.stat.mode(), STAT::MODE_0);
+
+    // VBlank isn't reached while the LCD is off
+    gameboy.poke(0xFF0F, 0);
+    gameboy.run_frames(3);
+    assert_eq!(gameboy.peek(0xFF0F) & 0x01, 0);
+}
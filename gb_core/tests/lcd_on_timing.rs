@@ -0,0 +1,77 @@
+//! The first line after the LCD is turned on has no mode 2 as far as STAT can tell, and is 4 dots
+//! shorter than the others. Each test turns the LCD on by writing LCDC, clocks the PPU a number
+//! of dots, and reads the registers, as a CPU access at that dot would.
+
+use gb_core::gameboy::ppu::{registers::LCDC, Ppu};
+use gb_cpu::CpuOutputPins;
+
+/// A PPU that has been off for a while, with the STAT interrupts in `stat` enabled
+fn lcd_off(stat: u8) -> Ppu {
+    let mut ppu = Ppu::new();
+    ppu.lcdc.remove(LCDC::LCD_ENABLE);
+    for _ in 0..1000 {
+        ppu.clock_t_state();
+    }
+    write(&mut ppu, 0xFF41, stat);
+    ppu
+}
+
+fn write(ppu: &mut Ppu, addr: u16, data: u8) {
+    ppu.perform_io(CpuOutputPins::Write { addr, data }, &mut 0xFF, &mut 0);
+}
+
+fn read(ppu: &mut Ppu, addr: u16) -> u8 {
+    let mut data = 0xFF;
+    ppu.perform_io(CpuOutputPins::Read { addr }, &mut data, &mut 0);
+    data
+}
+
+/// STAT's mode bits and LY on dot `dot` after the LCD is turned on, where dot 0 is the first
+/// dot of line 0
+fn after_enable(dot: usize) -> (u8, u8) {
+    let mut ppu = lcd_off(0);
+    write(&mut ppu, 0xFF40, 0x91);
+    for _ in 0..=dot {
+        ppu.clock_t_state();
+    }
+    (read(&mut ppu, 0xFF41) & 0x03, read(&mut ppu, 0xFF44))
+}
+
+#[test]
+fn first_line_reports_mode_0_during_oam_scan() {
+    for dot in [0, 1, 40, 79] {
+        assert_eq!(after_enable(dot), (0, 0), "dot {}", dot);
+    }
+    // Mode 3 starts on time, and with nothing to draw takes as long as usual
+    assert_eq!(after_enable(80), (3, 0));
+    assert_eq!(after_enable(253), (3, 0));
+    assert_eq!(after_enable(254), (0, 0));
+}
+
+#[test]
+fn first_line_is_4_dots_short() {
+    assert_eq!(after_enable(451), (0, 0));
+    assert_eq!(after_enable(452), (2, 1));
+    // The next line is back to 456 dots
+    assert_eq!(after_enable(452 + 455), (0, 1));
+    assert_eq!(after_enable(452 + 456), (2, 2));
+}
+
+#[test]
+fn first_line_raises_no_mode_2_interrupt() {
+    // Mode 2 interrupt enabled
+    let mut ppu = lcd_off(0x20);
+    write(&mut ppu, 0xFF40, 0x91);
+    let first_raised = (0..1000).find(|_| {
+        ppu.clock_t_state();
+        let mut interrupt_request = 0;
+        ppu.perform_io(
+            CpuOutputPins::Read { addr: 0xFF00 },
+            &mut 0xFF,
+            &mut interrupt_request,
+        );
+        interrupt_request & 0x02 != 0
+    });
+    // Only at the start of line 1
+    assert_eq!(first_raised, Some(452));
+}
@@ -0,0 +1,105 @@
+use gb_core::{
+    gameboy::{
+        cart::{header::flat_rom, HeaderWorkaround},
+        events::{Event, EventMask},
+        Gameboy, GameboyBuilder,
+    },
+    GbError,
+};
+use gb_cpu::assembler::assemble;
+
+/// Writes $42 to cartridge RAM and reads it back into $C001, then selects ROM bank 2 and copies
+/// its first byte to $C000, and cartridge RAM to $C002 again
+const PROGRAM: &str = "
+    .org $0150
+        ld sp, $DFFE
+        ld a, $42
+        ld [$A000], a
+        ld a, [$A000]
+        ld [$C001], a
+        ld a, 2
+        ld [$2000], a
+        ld a, [$4000]
+        ld [$C000], a
+        ld a, [$A000]
+        ld [$C002], a
+    loop:
+        jr loop
+";
+
+/// A ROM of `len` bytes that declares no mapper and no RAM, where bank 2 starts with $B2
+fn misdeclared_rom(len: usize) -> Vec<u8> {
+    let mut rom = flat_rom(&assemble(PROGRAM).unwrap(), 0x0150, "").unwrap();
+    rom.resize(len, 0);
+    if len > 0x8000 {
+        rom[0x8000] = 0xB2;
+    }
+    rom
+}
+
+fn build(rom: Vec<u8>, lenient: bool) -> Result<Gameboy, GbError> {
+    GameboyBuilder::new()
+        .rom(rom)
+        .lenient_header(lenient)
+        .build()
+}
+
+/// $C000-$C002 after running the program
+fn results(gameboy: &mut Gameboy) -> [u8; 3] {
+    gameboy.run_frames(1);
+    [0xC000, 0xC001, 0xC002].map(|addr| gameboy.peek(addr))
+}
+
+#[test]
+fn strict_headers_are_taken_at_their_word() {
+    assert!(matches!(
+        build(misdeclared_rom(0x10000), false),
+        Err(GbError::InvalidRom(_))
+    ));
+    let mut gameboy = build(misdeclared_rom(0x8000), false).unwrap();
+    assert_eq!(results(&mut gameboy), [0x00, 0xFF, 0xFF]);
+    assert!(gameboy.cartridge_info().workarounds.is_empty());
+}
+
+#[test]
+fn banked_rom_without_a_mapper_becomes_mbc1() {
+    let mut gameboy = build(misdeclared_rom(0x10000), true).unwrap();
+    let receiver = gameboy.subscribe(EventMask::HEADER_WORKAROUND);
+    // The writes that gave it away weren't lost
+    assert_eq!(results(&mut gameboy), [0xB2, 0x42, 0x42]);
+    assert_eq!(gameboy.cart.rom_bank(), 2);
+
+    let workarounds = [HeaderWorkaround::AddedRam, HeaderWorkaround::PromotedToMbc1];
+    let events: Vec<Event> = receiver.drain().map(|record| record.event).collect();
+    assert_eq!(events, workarounds.map(Event::HeaderWorkaround));
+    let info = gameboy.cartridge_info();
+    assert_eq!(info.workarounds, workarounds);
+    assert_eq!(info.ram_size, 0x2000);
+    assert_eq!(info.header.unwrap().cart_type, 0x00);
+    assert!(!info.has_battery);
+}
+
+#[test]
+fn small_rom_only_gets_ram() {
+    let mut gameboy = build(misdeclared_rom(0x8000), true).unwrap();
+    assert_eq!(results(&mut gameboy), [0x00, 0x42, 0x42]);
+    assert_eq!(gameboy.cart.rom_bank(), 1);
+    assert_eq!(
+        gameboy.cartridge_info().workarounds,
+        [HeaderWorkaround::AddedRam]
+    );
+    assert_eq!(gameboy.cart.ram().unwrap()[0], 0x42);
+}
+
+#[test]
+fn correct_headers_need_no_workarounds() {
+    let mut rom = misdeclared_rom(0x10000);
+    rom[0x147] = 0x03;
+    rom[0x148] = 0x01;
+    let mut gameboy = build(rom, true).unwrap();
+    // MBC1 RAM starts disabled, so the write to it is ignored as usual
+    assert_eq!(results(&mut gameboy), [0xB2, 0xFF, 0xFF]);
+    let info = gameboy.cartridge_info();
+    assert!(info.workarounds.is_empty());
+    assert!(info.has_battery);
+}
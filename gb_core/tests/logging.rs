@@ -0,0 +1,50 @@
+//! Log records are emitted on the documented targets. The logger is global, so everything that
+//! needs it is in one test.
+
+use std::sync::Mutex;
+
+use gb_core::gameboy::{logging, Gameboy};
+use log::{Level, Log, Metadata, Record};
+
+struct Capture(Mutex<Vec<(Level, String, String)>>);
+
+impl Log for Capture {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        self.0.lock().unwrap().push((
+            record.level(),
+            record.target().to_string(),
+            record.args().to_string(),
+        ));
+    }
+
+    fn flush(&self) {}
+}
+
+static CAPTURE: Capture = Capture(Mutex::new(Vec::new()));
+
+#[test]
+fn oam_dma_is_logged() {
+    log::set_logger(&CAPTURE).unwrap();
+    log::set_max_level(log::LevelFilter::Debug);
+
+    #[rustfmt::skip]
+    let mut gameboy = Gameboy::with_program(&[
+        0x3E, 0xC1, // LD A, $C1
+        0xE0, 0x46, // LDH (DMA), A
+        0x18, 0xFE, // JR -2
+    ], 0x0150).unwrap();
+    gameboy.run_frames(1);
+
+    let records = CAPTURE.0.lock().unwrap();
+    let dma: Vec<&str> = records
+        .iter()
+        .filter(|(_, target, _)| target == logging::DMA)
+        .map(|(_, _, message)| message.as_str())
+        .collect();
+    assert_eq!(dma, ["OAM DMA started from $C100", "OAM DMA finished"]);
+    assert!(records.iter().all(|&(level, _, _)| level <= Level::Debug));
+}
@@ -0,0 +1,118 @@
+use gb_core::gameboy::{
+    cart::{
+        header::{flat_rom, update_checksums},
+        Cart, Mbc1Wiring,
+    },
+    Chip, ClockContext, GameboyBuilder,
+};
+use gb_cpu::CpuOutputPins;
+
+/// A 1MiB MBC1 cartridge, where the first byte of each bank is its bank number. A multicart has
+/// the same header at the start of each of its four games.
+fn rom(multicart: bool) -> Vec<u8> {
+    let mut rom = flat_rom(&[0x18, 0xFE], 0x0150, "MBC1M").unwrap();
+    rom.resize(0x100000, 0);
+    rom[0x147] = 0x01; // MBC1
+    rom[0x148] = 0x05; // 1MiB
+    if multicart {
+        let header = rom[0x100..0x150].to_vec();
+        for game in 1..4 {
+            rom[game * 0x40000 + 0x100..game * 0x40000 + 0x150].copy_from_slice(&header);
+        }
+    }
+    for bank in 0..64 {
+        rom[bank * 0x4000] = bank as u8;
+    }
+    update_checksums(&mut rom);
+    rom
+}
+
+fn write(cart: &mut Cart, addr: u16, data: u8) {
+    cart.clock(
+        CpuOutputPins::Write { addr, data },
+        &mut 0xFF,
+        &mut 0,
+        &ClockContext::default(),
+    );
+}
+
+fn read(cart: &mut Cart, addr: u16) -> u8 {
+    let mut data = 0xFF;
+    cart.clock(
+        CpuOutputPins::Read { addr },
+        &mut data,
+        &mut 0,
+        &ClockContext::default(),
+    );
+    data
+}
+
+#[test]
+fn multicarts_are_detected() {
+    let cart = Cart::new(rom(true)).unwrap();
+    assert!(cart.info().multicart);
+    let cart = Cart::new(rom(false)).unwrap();
+    assert!(!cart.info().multicart);
+    // Only 1MiB multicarts were made
+    let mut small = rom(true);
+    small.truncate(0x80000);
+    small[0x148] = 0x04;
+    update_checksums(&mut small);
+    assert!(!Cart::new(small).unwrap().info().multicart);
+}
+
+#[test]
+fn upper_register_selects_the_game() {
+    let mut cart = Cart::new(rom(true)).unwrap();
+    write(&mut cart, 0x6000, 0x01);
+    for game in 0..4 {
+        write(&mut cart, 0x4000, game);
+        assert_eq!(read(&mut cart, 0x0000), game << 4);
+        write(&mut cart, 0x2000, 0x0F);
+        assert_eq!(read(&mut cart, 0x4000), game << 4 | 0x0F);
+        // Bit 4 isn't connected, but still counts when bank 0 is replaced with bank 1
+        write(&mut cart, 0x2000, 0x12);
+        assert_eq!(read(&mut cart, 0x4000), game << 4 | 0x02);
+        write(&mut cart, 0x2000, 0x10);
+        assert_eq!(read(&mut cart, 0x4000), game << 4);
+        write(&mut cart, 0x2000, 0x00);
+        assert_eq!(read(&mut cart, 0x4000), game << 4 | 0x01);
+    }
+
+    // In mode 0 the first game is always at $0000
+    write(&mut cart, 0x6000, 0x00);
+    assert_eq!(read(&mut cart, 0x0000), 0);
+    assert_eq!(read(&mut cart, 0x4000), 0x31);
+}
+
+#[test]
+fn standard_wiring_is_unchanged() {
+    let mut cart = Cart::new(rom(false)).unwrap();
+    write(&mut cart, 0x6000, 0x01);
+    write(&mut cart, 0x4000, 0x01);
+    write(&mut cart, 0x2000, 0x12);
+    assert_eq!(read(&mut cart, 0x0000), 0x20);
+    assert_eq!(read(&mut cart, 0x4000), 0x32);
+    write(&mut cart, 0x2000, 0x00);
+    assert_eq!(read(&mut cart, 0x4000), 0x21);
+}
+
+#[test]
+fn wiring_can_be_forced() {
+    let build = |multicart, wiring| {
+        GameboyBuilder::new()
+            .rom(rom(multicart))
+            .mbc1_wiring(wiring)
+            .build()
+            .unwrap()
+    };
+    let gameboy = build(true, Mbc1Wiring::Standard);
+    assert!(!gameboy.cartridge_info().multicart);
+    let mut gameboy = build(false, Mbc1Wiring::Multicart);
+    assert!(gameboy.cartridge_info().multicart);
+
+    // Kept for the next cartridge
+    gameboy.swap_cartridge(&rom(true)).unwrap();
+    assert!(gameboy.cartridge_info().multicart);
+    assert_eq!(gameboy.cart.mbc1_wiring(), Mbc1Wiring::Multicart);
+}
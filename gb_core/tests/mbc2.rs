@@ -0,0 +1,133 @@
+use gb_core::{
+    gameboy::{cart::Cart, Chip, ClockContext},
+    GbError,
+};
+use gb_cpu::CpuOutputPins;
+
+/// A 256KiB MBC2 cartridge, where the first byte of each bank is its bank number
+fn cart() -> Cart {
+    let mut rom = vec![0; 0x40000];
+    rom[0x147] = 0x06; // MBC2+BATTERY
+    rom[0x148] = 0x03; // 256KiB
+    for bank in 1..16 {
+        rom[bank * 0x4000] = bank as u8;
+    }
+    Cart::new(rom).unwrap()
+}
+
+fn write(cart: &mut Cart, addr: u16, data: u8) {
+    cart.clock(
+        CpuOutputPins::Write { addr, data },
+        &mut 0xFF,
+        &mut 0,
+        &ClockContext::default(),
+    );
+}
+
+fn read(cart: &mut Cart, addr: u16) -> u8 {
+    let mut data = 0xFF;
+    cart.clock(
+        CpuOutputPins::Read { addr },
+        &mut data,
+        &mut 0,
+        &ClockContext::default(),
+    );
+    data
+}
+
+#[test]
+fn address_bit_8_selects_register() {
+    let mut cart = cart();
+    assert_eq!(read(&mut cart, 0x4000), 1);
+
+    // Bit 8 set: ROM bank, anywhere in $0000-$3FFF
+    write(&mut cart, 0x0100, 0x0A);
+    assert_eq!(read(&mut cart, 0x4000), 10);
+    assert_eq!(cart.rom_bank(), 10);
+    // ...so RAM is still disabled
+    write(&mut cart, 0xA000, 0x05);
+    assert_eq!(read(&mut cart, 0xA000), 0xFF);
+
+    // Bit 8 clear: RAM enable, which leaves the ROM bank alone
+    write(&mut cart, 0x3E00, 0x0A);
+    assert_eq!(read(&mut cart, 0x4000), 10);
+    write(&mut cart, 0xA000, 0x05);
+    assert_eq!(read(&mut cart, 0xA000), 0xF5);
+
+    write(&mut cart, 0x0000, 0x00);
+    assert_eq!(read(&mut cart, 0xA000), 0xFF);
+}
+
+#[test]
+fn rom_bank_is_4_bits() {
+    let mut cart = cart();
+    write(&mut cart, 0x2100, 0x13);
+    assert_eq!(read(&mut cart, 0x4000), 3);
+    // Bank 0 maps to bank 1, including when the upper bits are set
+    write(&mut cart, 0x2100, 0x00);
+    assert_eq!(read(&mut cart, 0x4000), 1);
+    write(&mut cart, 0x2100, 0x10);
+    assert_eq!(read(&mut cart, 0x4000), 1);
+    // Bank 0 stays at $0000-$3FFF
+    assert_eq!(read(&mut cart, 0x0000), 0);
+}
+
+#[test]
+fn ram_stores_low_nibbles() {
+    let mut cart = cart();
+    write(&mut cart, 0x0000, 0x0A);
+    write(&mut cart, 0xA000, 0xAB);
+    write(&mut cart, 0xA1FF, 0x3C);
+    assert_eq!(read(&mut cart, 0xA000), 0xFB);
+    assert_eq!(read(&mut cart, 0xA1FF), 0xFC);
+    // Untouched bytes still read the upper nibble as 1s
+    assert_eq!(read(&mut cart, 0xA001), 0xF0);
+}
+
+#[test]
+fn ram_echoes_every_512_bytes() {
+    let mut cart = cart();
+    write(&mut cart, 0x0000, 0x0A);
+    write(&mut cart, 0xA005, 0x07);
+    for addr in [0xA205, 0xA405, 0xB005, 0xBE05] {
+        assert_eq!(read(&mut cart, addr), 0xF7, "{:#06X}", addr);
+    }
+
+    // Writes through the echo land in the same place
+    write(&mut cart, 0xBFFF, 0x09);
+    assert_eq!(read(&mut cart, 0xA1FF), 0xF9);
+}
+
+#[test]
+fn save_is_512_masked_bytes() {
+    let mut saved = cart();
+    write(&mut saved, 0x0000, 0x0A);
+    write(&mut saved, 0xA000, 0xFF);
+    write(&mut saved, 0xA010, 0x42);
+
+    let save = saved.ram().unwrap().to_vec();
+    assert_eq!(save.len(), 512);
+    assert_eq!(save[0x000], 0x0F);
+    assert_eq!(save[0x010], 0x02);
+    assert!(save.iter().all(|b| b & 0xF0 == 0));
+
+    // Loading throws away the upper nibbles
+    let mut loaded = cart();
+    loaded.load_ram(&[0xA5; 512]).unwrap();
+    assert!(loaded.ram().unwrap().iter().all(|b| *b == 0x05));
+    write(&mut loaded, 0x0000, 0x0A);
+    assert_eq!(read(&mut loaded, 0xA123), 0xF5);
+
+    assert!(matches!(
+        loaded.load_ram(&[0; 0x2000]),
+        Err(GbError::InvalidSaveData(_))
+    ));
+}
+
+#[test]
+fn too_large_for_mbc2() {
+    let mut rom = vec![0; 0x80000];
+    rom[0x147] = 0x05; // MBC2
+    rom[0x148] = 0x04; // 512KiB
+    assert!(matches!(Cart::new(rom), Err(GbError::InvalidRom(_))));
+}
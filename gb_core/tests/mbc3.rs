@@ -0,0 +1,303 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use gb_core::gameboy::{
+    cart::Cart,
+    rtc::{RtcRegisters, RtcSource, RtcTimeSource, SAVE_FOOTER_LEN},
+    test_pattern, Chip, ClockContext, GameboyBuilder,
+};
+use gb_cpu::CpuOutputPins;
+
+/// A wall clock that the test moves by hand
+#[derive(Clone, Default)]
+struct TestClock(Arc<AtomicU64>);
+
+impl TestClock {
+    fn advance(&self, seconds: u64) {
+        self.0.fetch_add(seconds, Ordering::Relaxed);
+    }
+}
+
+impl RtcSource for TestClock {
+    fn now(&mut self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// A 1MiB MBC3+TIMER+RAM+BATTERY ROM with 32KiB of RAM, where the first byte of each bank is its
+/// bank number
+fn rom() -> Vec<u8> {
+    let mut rom = vec![0; 0x100000];
+    rom[0x147] = 0x10;
+    rom[0x148] = 0x05; // 1MiB
+    rom[0x149] = 0x03; // 32KiB
+    for bank in 1..64 {
+        rom[bank * 0x4000] = bank as u8;
+    }
+    rom
+}
+
+/// The cartridge for [`rom`], with RAM and the clock enabled, and the clock following `clock`
+fn cart(clock: &TestClock) -> Cart {
+    let mut cart = Cart::with_rtc(rom(), Box::new(clock.clone())).unwrap();
+    cart.set_rtc_time_source(RtcTimeSource::WallClock);
+    write(&mut cart, 0x0000, 0x0A);
+    cart
+}
+
+fn write(cart: &mut Cart, addr: u16, data: u8) {
+    cart.clock(
+        CpuOutputPins::Write { addr, data },
+        &mut 0xFF,
+        &mut 0,
+        &ClockContext::default(),
+    );
+}
+
+fn read(cart: &mut Cart, addr: u16) -> u8 {
+    let mut data = 0xFF;
+    cart.clock(
+        CpuOutputPins::Read { addr },
+        &mut data,
+        &mut 0,
+        &ClockContext::default(),
+    );
+    data
+}
+
+/// Set the clock through its registers, the way a game would
+fn set_time(cart: &mut Cart, days: u16, hours: u8, minutes: u8, seconds: u8) {
+    let registers = [seconds, minutes, hours, days as u8, (days >> 8) as u8];
+    for (select, data) in (0x08..).zip(registers) {
+        write(cart, 0x4000, select);
+        write(cart, 0xA000, data);
+    }
+}
+
+/// Latch the clock and read it back
+fn time(cart: &mut Cart) -> [u8; 5] {
+    write(cart, 0x6000, 0x00);
+    write(cart, 0x6000, 0x01);
+    let mut registers = [0; 5];
+    for (select, register) in (0x08..).zip(&mut registers) {
+        write(cart, 0x4000, select);
+        *register = read(cart, 0xA000);
+    }
+    registers
+}
+
+#[test]
+fn rom_bank_is_7_bits_and_ram_has_4_banks() {
+    let clock = TestClock::default();
+    let mut cart = cart(&clock);
+    write(&mut cart, 0x2000, 0x3F);
+    assert_eq!(read(&mut cart, 0x4000), 0x3F);
+    write(&mut cart, 0x2000, 0x00);
+    assert_eq!(read(&mut cart, 0x4000), 1);
+
+    for bank in 0..4 {
+        write(&mut cart, 0x4000, bank);
+        write(&mut cart, 0xA000, 0x10 + bank);
+    }
+    for bank in 0..4 {
+        write(&mut cart, 0x4000, bank);
+        assert_eq!(read(&mut cart, 0xA000), 0x10 + bank);
+    }
+    assert_eq!(cart.ram().unwrap()[0x6000], 0x13);
+}
+
+#[test]
+fn reads_hold_still_until_latched() {
+    let clock = TestClock::default();
+    let mut cart = cart(&clock);
+    set_time(&mut cart, 0, 12, 0, 0);
+    assert_eq!(time(&mut cart), [0, 0, 12, 0, 0]);
+
+    clock.advance(5);
+    write(&mut cart, 0x4000, 0x08);
+    assert_eq!(read(&mut cart, 0xA000), 0);
+    assert_eq!(time(&mut cart)[0], 5);
+    // Writing 1 again without a 0 first doesn't latch
+    clock.advance(5);
+    write(&mut cart, 0x6000, 0x01);
+    write(&mut cart, 0x4000, 0x08);
+    assert_eq!(read(&mut cart, 0xA000), 5);
+}
+
+#[test]
+fn loading_a_save_catches_up_across_a_day_boundary() {
+    let clock = TestClock::default();
+    clock.advance(1_000_000);
+    let mut saved = cart(&clock);
+    set_time(&mut saved, 3, 23, 59, 30);
+    write(&mut saved, 0x4000, 0x00);
+    write(&mut saved, 0xA000, 0x42);
+    let save = saved.take_save().unwrap().to_vec();
+    assert_eq!(save.len(), 0x8000 + SAVE_FOOTER_LEN);
+    assert_eq!(save[0x8000 + 40..], 1_000_000u64.to_le_bytes());
+
+    // An hour, a minute and 15 seconds later
+    clock.advance(3675);
+    let mut loaded = cart(&clock);
+    loaded.load_ram(&save).unwrap();
+    assert_eq!(loaded.ram().unwrap()[0], 0x42);
+    assert_eq!(time(&mut loaded), [45, 0, 1, 4, 0]);
+    assert!(!loaded.is_save_dirty());
+
+    // A save without the footer leaves the clock alone
+    let mut loaded = cart(&clock);
+    loaded.load_ram(&save[..0x8000]).unwrap();
+    assert_eq!(time(&mut loaded), [0; 5]);
+}
+
+#[test]
+fn day_overflow_while_saved_sets_carry() {
+    let clock = TestClock::default();
+    let mut saved = cart(&clock);
+    set_time(&mut saved, 511, 0, 0, 0);
+    let save = saved.take_save().unwrap().to_vec();
+
+    clock.advance(2 * 86400 + 10);
+    let mut loaded = cart(&clock);
+    loaded.load_ram(&save).unwrap();
+    // Day 513 wraps to day 1
+    assert_eq!(time(&mut loaded), [10, 0, 0, 1, 0x80]);
+    let state = loaded.rtc_state().unwrap();
+    assert!(state.live.carry);
+    assert_eq!(state.live.days, 1);
+
+    // The carry stays set until the game clears it
+    clock.advance(86400);
+    assert_eq!(time(&mut loaded)[4], 0x80);
+    write(&mut loaded, 0x4000, 0x0C);
+    write(&mut loaded, 0xA000, 0x00);
+    assert_eq!(time(&mut loaded)[4], 0x00);
+}
+
+#[test]
+fn halted_clock_does_not_catch_up() {
+    let clock = TestClock::default();
+    let mut saved = cart(&clock);
+    set_time(&mut saved, 0x100, 1, 2, 3);
+    write(&mut saved, 0x4000, 0x0C);
+    write(&mut saved, 0xA000, 0x41);
+    let save = saved.take_save().unwrap().to_vec();
+
+    clock.advance(1000);
+    let mut loaded = cart(&clock);
+    loaded.load_ram(&save).unwrap();
+    assert_eq!(time(&mut loaded), [3, 2, 1, 0, 0x41]);
+}
+
+#[test]
+fn clocks_only_count_emulated_time_by_default() {
+    let mut cart = Cart::new(rom()).unwrap();
+    assert_eq!(cart.rtc_time_source(), Some(RtcTimeSource::EmulatedCycles));
+    let gameboy = GameboyBuilder::new().rom(rom()).build().unwrap();
+    assert_eq!(
+        gameboy.cart.rtc_time_source(),
+        Some(RtcTimeSource::EmulatedCycles)
+    );
+
+    // Saves are stamped with the default time source's time, which never moves
+    write(&mut cart, 0x0000, 0x0A);
+    write(&mut cart, 0xA000, 0x01);
+    let save = cart.take_save().unwrap();
+    assert_eq!(save[0x8000 + 40..], 0u64.to_le_bytes());
+}
+
+#[test]
+fn emulated_cycles_count_a_second_every_4194304_cycles() {
+    let clock = TestClock::default();
+    let mut cart = cart(&clock);
+    cart.set_rtc_time_source(RtcTimeSource::EmulatedCycles);
+    assert_eq!(cart.rtc_time_source(), Some(RtcTimeSource::EmulatedCycles));
+    clock.advance(1000);
+
+    // Every access is an M-cycle, and `time` makes 12 of them
+    assert_eq!(time(&mut cart)[0], 0);
+    let mut cycles = 12 * 4;
+    while cycles < 4_194_304 - 8 {
+        read(&mut cart, 0x0000);
+        cycles += 4;
+    }
+    // The latch's second write is the cycle that finishes the second
+    write(&mut cart, 0x6000, 0x00);
+    write(&mut cart, 0x6000, 0x01);
+    assert_eq!(cart.rtc_state().unwrap().latched.seconds, 1);
+    assert_eq!(cart.rtc_state().unwrap().subsecond_cycles, 0);
+
+    // Wall clock time doesn't count, even when a save is loaded
+    write(&mut cart, 0x4000, 0x00);
+    write(&mut cart, 0xA000, 0x01);
+    let save = cart.take_save().unwrap().to_vec();
+    clock.advance(1000);
+    cart.load_ram(&save).unwrap();
+    assert_eq!(cart.rtc_state().unwrap().live.seconds, 1);
+
+    // Switching back to the wall clock starts counting from now
+    cart.set_rtc_time_source(RtcTimeSource::WallClock);
+    clock.advance(2);
+    assert_eq!(cart.rtc_state().unwrap().live.seconds, 3);
+}
+
+#[test]
+fn swapped_in_cartridges_keep_the_configured_clock() {
+    let clock = TestClock::default();
+    clock.advance(1000);
+    let mut gameboy = GameboyBuilder::new()
+        .rom(test_pattern::rom())
+        .rtc(Box::new(clock.clone()))
+        .rtc_time_source(RtcTimeSource::WallClock)
+        .build()
+        .unwrap();
+    // The first cartridge has no clock, so the time source waits for one that does
+    gameboy.swap_cartridge(&rom()).unwrap();
+    write(&mut gameboy.cart, 0x0000, 0x0A);
+    clock.advance(100);
+    assert_eq!(time(&mut gameboy.cart), [40, 1, 0, 0, 0]);
+
+    gameboy
+        .cart
+        .set_rtc_time_source(RtcTimeSource::EmulatedCycles);
+    gameboy.swap_cartridge(&rom()).unwrap();
+    assert_eq!(
+        gameboy.cart.rtc_time_source(),
+        Some(RtcTimeSource::EmulatedCycles)
+    );
+
+    // A cartridge that fails to load leaves the time source where it was
+    gameboy.cart.set_rtc_time_source(RtcTimeSource::WallClock);
+    assert!(gameboy.swap_cartridge(&[0; 0x100]).is_err());
+    write(&mut gameboy.cart, 0x0000, 0x0A);
+    clock.advance(5);
+    assert_eq!(time(&mut gameboy.cart), [5, 0, 0, 0, 0]);
+}
+
+#[test]
+fn state_keeps_latched_registers_apart_from_live_ones() {
+    let clock = TestClock::default();
+    let mut taken = cart(&clock);
+    set_time(&mut taken, 0, 0, 0, 10);
+    time(&mut taken);
+    clock.advance(20);
+    let state = taken.rtc_state().unwrap();
+    assert_eq!(state.latched.seconds, 10);
+    assert_eq!(state.live.seconds, 30);
+
+    clock.advance(100);
+    let mut restored = cart(&clock);
+    restored.set_rtc_state(state);
+    // The state is restored as it was, without counting the time since it was taken
+    assert_eq!(
+        restored.rtc_state().unwrap().live,
+        RtcRegisters {
+            seconds: 30,
+            ..Default::default()
+        }
+    );
+    write(&mut restored, 0x4000, 0x08);
+    assert_eq!(read(&mut restored, 0xA000), 10);
+}
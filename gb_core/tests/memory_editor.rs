@@ -0,0 +1,123 @@
+use gb_core::{
+    gameboy::{memory_editor::PokeOutcome, Gameboy},
+    GbError,
+};
+use gb_cpu::assembler::assemble;
+
+fn mode(gameboy: &Gameboy) -> u8 {
+    gameboy.ppu.stat.bits() & 3
+}
+
+fn run_until_mode(gameboy: &mut Gameboy, wanted: u8) {
+    while mode(gameboy) != wanted {
+        gameboy.tick();
+    }
+}
+
+/// Loops forever without touching memory
+fn idle() -> Gameboy {
+    let mut gameboy = Gameboy::with_program(&[0x18, 0xFE], 0x0150).unwrap();
+    gameboy.run_frames(1);
+    gameboy
+}
+
+#[test]
+fn vram_is_locked_while_drawing_unless_poked_silently() {
+    let mut gameboy = idle();
+    run_until_mode(&mut gameboy, 3);
+    let old = gameboy.peek(0x9000);
+    assert_eq!(gameboy.poke(0x9000, !old), PokeOutcome::Ignored);
+    assert_eq!(gameboy.peek(0x9000), old);
+    gameboy.poke_silent(0x9000, !old).unwrap();
+    assert_eq!(gameboy.peek(0x9000), !old);
+    assert_eq!(mode(&gameboy), 3);
+
+    run_until_mode(&mut gameboy, 0);
+    assert_eq!(gameboy.poke(0x9000, old), PokeOutcome::WroteRam);
+    assert_eq!(gameboy.peek(0x9000), old);
+}
+
+#[test]
+fn poking_dma_starts_a_transfer() {
+    let mut gameboy = idle();
+    for i in 0..0xA0 {
+        gameboy.poke_silent(0xC000 + i, i as u8 ^ 0x5A).unwrap();
+    }
+    assert_eq!(gameboy.poke(0xFF46, 0xC0), PokeOutcome::WroteRegister);
+    assert!(gameboy.ppu.dma_active());
+    for _ in 0..200 {
+        gameboy.tick();
+    }
+    assert!(!gameboy.ppu.dma_active());
+    assert!((0..0xA0).all(|i| gameboy.peek(0xFE00 + i) == i as u8 ^ 0x5A));
+}
+
+#[test]
+fn outcomes() {
+    let mut gameboy = idle();
+    // The cartridge has no mapper
+    assert_eq!(gameboy.poke(0x2000, 1), PokeOutcome::Ignored);
+    assert_eq!(gameboy.poke(0xC123, 1), PokeOutcome::WroteRam);
+    assert_eq!(gameboy.poke(0xFF80, 1), PokeOutcome::WroteRam);
+    assert_eq!(gameboy.poke(0xFEA0, 1), PokeOutcome::Ignored);
+    assert_eq!(gameboy.poke(0xFF03, 1), PokeOutcome::Ignored);
+    assert_eq!(gameboy.poke(0xFF43, 7), PokeOutcome::WroteRegister);
+    assert_eq!(gameboy.ppu.scx, 7);
+    assert_eq!(gameboy.poke(0xFFFF, 0x1F), PokeOutcome::WroteRegister);
+    assert_eq!(gameboy.peek(0xFFFF), 0x1F);
+
+    for &addr in &[0x0000, 0xFF43, 0xFF0F, 0xFFFF] {
+        assert_eq!(
+            gameboy.poke_silent(addr, 0),
+            Err(GbError::AddressOutOfRange(addr))
+        );
+    }
+    assert_eq!(gameboy.ppu.scx, 7);
+}
+
+/// Copies $C000 to $C001 and overwrites it with $FF once a frame, on line 72
+const OVERWRITE: &str = "
+    .org $0150
+    top:
+        ldh a, [$44]
+        cp 72
+        jr nz, top
+        ld a, [$C000]
+        ld [$C001], a
+        ld a, $FF
+        ld [$C000], a
+    wait:
+        ldh a, [$44]
+        cp 72
+        jr z, wait
+        jr top
+";
+
+#[test]
+fn frozen_address_survives_the_game_writing_over_it() {
+    let mut gameboy = Gameboy::with_program(&assemble(OVERWRITE).unwrap(), 0x0150).unwrap();
+    let id = gameboy.freeze(0xC000, 42).unwrap();
+    assert_eq!(gameboy.peek(0xC000), 42);
+    assert_eq!(gameboy.frozen().collect::<Vec<_>>(), [(id, 0xC000, 42)]);
+
+    for _ in 0..3 {
+        // The game sees the frozen value, then overwrites it until the next VBlank
+        gameboy.run_until_line(100);
+        assert_eq!(gameboy.peek(0xC000), 0xFF);
+        gameboy.run_frames(1);
+        assert_eq!(gameboy.peek(0xC000), 42);
+        assert_eq!(gameboy.peek(0xC001), 42);
+    }
+
+    assert!(gameboy.unfreeze(id));
+    assert!(!gameboy.unfreeze(id));
+    assert_eq!(gameboy.frozen().count(), 0);
+    gameboy.run_frames(2);
+    assert_eq!(gameboy.peek(0xC000), 0xFF);
+    assert_eq!(gameboy.peek(0xC001), 0xFF);
+
+    assert_eq!(
+        gameboy.freeze(0x9000, 1),
+        Err(GbError::AddressOutOfRange(0x9000))
+    );
+}
@@ -0,0 +1,104 @@
+use gb_core::gameboy::{memory_search::MemorySearch, Gameboy, ResetKind};
+
+/// Increments $C123 once per frame, as the PPU enters VBlank
+#[rustfmt::skip]
+const COUNTER: [u8; 18] = [
+    0x21, 0x23, 0xC1, // LD HL, $C123
+    // wait:
+    0xF0, 0x44,       // LDH A, (LY)
+    0xFE, 0x90,       // CP 144
+    0x20, 0xFA,       // JR NZ, wait
+    0x34,             // INC (HL)
+    // leave:
+    0xF0, 0x44,       // LDH A, (LY)
+    0xFE, 0x90,       // CP 144
+    0x28, 0xFA,       // JR Z, leave
+    0x18, 0xF1,       // JR wait
+];
+
+fn gameboy() -> Gameboy {
+    let mut gameboy = Gameboy::with_program(&COUNTER, 0x0150).unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    gameboy.run_frames(2);
+    gameboy
+}
+
+#[test]
+fn two_increases_find_the_counter() {
+    let mut gameboy = gameboy();
+    let mut search = MemorySearch::<u8>::new(&gameboy);
+    assert_eq!(search.len(), 0x2000 + 0x7F);
+
+    gameboy.run_frames(1);
+    search.increased(&gameboy);
+    gameboy.run_frames(1);
+    search.increased(&gameboy);
+    assert_eq!(search.candidates(), [(0xC123, gameboy.peek(0xC123))]);
+
+    // Which can then be changed
+    gameboy.poke(0xC123, 100);
+    gameboy.run_frames(1);
+    assert_eq!(gameboy.peek(0xC123), 101);
+}
+
+#[test]
+fn filters_compare_against_the_last_read() {
+    let mut gameboy = gameboy();
+    let mut search = MemorySearch::<u8>::new(&gameboy);
+    gameboy.run_frames(3);
+    assert_eq!(search.changed_by(&gameboy, 3), 1);
+    assert_eq!(search.unchanged(&gameboy), 1);
+    gameboy.run_frames(1);
+    assert_eq!(search.decreased(&gameboy), 0);
+    assert!(search.is_empty());
+
+    let mut search = MemorySearch::<u8>::new(&gameboy);
+    let count = gameboy.peek(0xC123);
+    gameboy.run_frames(1);
+    search.changed(&gameboy);
+    assert_eq!(search.equal_to(&gameboy, count + 1), 1);
+}
+
+#[test]
+fn words_are_little_endian() {
+    let mut gameboy = gameboy();
+    gameboy.poke(0xC122, 0x34);
+    gameboy.poke(0xC124, 0x12);
+    let mut search = MemorySearch::<u16>::new(&gameboy);
+    assert_eq!(search.len(), 0x1FFF + 0x7E);
+
+    gameboy.run_frames(1);
+    search.increased(&gameboy);
+    // The counter is the high byte of one word and the low byte of the next
+    let count = gameboy.peek(0xC123) as u16;
+    assert_eq!(
+        search.candidates(),
+        [(0xC122, count << 8 | 0x34), (0xC123, 0x1200 | count)]
+    );
+    assert_eq!(search.changed_by(&gameboy, 0), 2);
+}
+
+#[test]
+fn cartridge_ram_is_searched() {
+    #[rustfmt::skip]
+    let mut rom = gb_core::gameboy::cart::header::flat_rom(&[
+        0x3E, 0x0A,       // LD A, $0A
+        0xEA, 0x00, 0x00, // LD ($0000), A
+        0x21, 0x00, 0xA0, // LD HL, $A000
+        0x34,             // INC (HL)
+        0x18, 0xFD,       // JR -3
+    ], 0x0150, "").unwrap();
+    rom[0x147] = 0x03;
+    rom[0x149] = 0x02;
+    gb_core::gameboy::cart::header::update_checksums(&mut rom);
+    let mut gameboy = Gameboy::new(rom).unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    gameboy.run_cycles(4 * 40);
+
+    let mut search = MemorySearch::<u8>::new(&gameboy);
+    assert_eq!(search.len(), 0x2000 + 0x2000 + 0x7F);
+    // Each INC (HL) and JR takes 6 M-cycles
+    gameboy.run_cycles(4 * 6);
+    assert_eq!(search.changed_by(&gameboy, 1), 1);
+    assert_eq!(search.candidates()[0].0, 0xA000);
+}
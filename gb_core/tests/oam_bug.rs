@@ -0,0 +1,100 @@
+use gb_core::gameboy::Gameboy;
+
+/// The last three words of every row, which are the same for all rows so that copying them from
+/// the preceding row changes nothing
+const TAIL: [u16; 3] = [0x3C5A, 0x0FF0, 0xA5C3];
+
+/// First word of each OAM row
+fn first_words() -> [u16; 20] {
+    let mut seed = 0x1234u16;
+    [0; 20].map(|_| {
+        seed = seed.wrapping_mul(25173).wrapping_add(13849);
+        seed
+    })
+}
+
+fn oam_with_first_words(words: &[u16; 20]) -> [u8; 0xA0] {
+    let mut oam = [0; 0xA0];
+    for (row, &first) in words.iter().enumerate() {
+        for (i, word) in [first].iter().chain(&TAIL).enumerate() {
+            oam[row * 8 + i * 2..row * 8 + i * 2 + 2].copy_from_slice(&word.to_le_bytes());
+        }
+    }
+    oam
+}
+
+/// Run `code` in a loop for a few frames with OAM filled in by [`oam_with_first_words`], and return
+/// the first word of each row afterwards
+fn run_with_oam(code: &[u8], oam_bug: bool) -> [u16; 20] {
+    let mut gameboy = Gameboy::with_program(code, 0x0150).unwrap();
+    gameboy.set_oam_bug_emulation(oam_bug);
+    gameboy.ppu.oam = oam_with_first_words(&first_words());
+    gameboy.run_frames(3);
+
+    let oam = gameboy.ppu.oam;
+    let word =
+        |row: usize, i: usize| u16::from_le_bytes([oam[row * 8 + i * 2], oam[row * 8 + i * 2 + 1]]);
+    let mut first = [0; 20];
+    for (row, first) in first.iter_mut().enumerate() {
+        assert_eq!(
+            [word(row, 1), word(row, 2), word(row, 3)],
+            TAIL,
+            "row {}",
+            row
+        );
+        *first = word(row, 0);
+    }
+    first
+}
+
+#[rustfmt::skip]
+const INC_DEC_LOOP: [u8; 7] = [
+    0x21, 0x40, 0xFE, // LD HL, $FE40
+    0x23,             // INC HL
+    0x2B,             // DEC HL
+    0x18, 0xFC,       // JR -4
+];
+
+#[rustfmt::skip]
+const READ_LOOP: [u8; 6] = [
+    0x21, 0x40, 0xFE, // LD HL, $FE40
+    0x7E,             // LD A, (HL)
+    0x18, 0xFD,       // JR -3
+];
+
+#[test]
+fn disabled_by_default() {
+    assert_eq!(run_with_oam(&INC_DEC_LOOP, false), first_words());
+    assert_eq!(run_with_oam(&READ_LOOP, false), first_words());
+}
+
+#[test]
+fn inc_dec_outside_oam() {
+    let mut code = INC_DEC_LOOP;
+    code[2] = 0xC0; // LD HL, $C040
+    assert_eq!(run_with_oam(&code, true), first_words());
+}
+
+/// The write corruption sets the first word of a row to `((a ^ c) & (b ^ c)) ^ c`. Here `c` is the
+/// same for every row, so after being corrupted enough times, the bits of each row that differ
+/// from `c` are the bits that differ in every row up to it.
+#[test]
+fn write_corruption() {
+    let c = TAIL[1];
+    let mut expected = first_words();
+    for row in 1..20 {
+        expected[row] = ((expected[row] ^ c) & (expected[row - 1] ^ c)) ^ c;
+    }
+    assert_eq!(run_with_oam(&INC_DEC_LOOP, true), expected);
+}
+
+/// The read corruption sets the first word of a row to `b | (a & c)`
+#[test]
+fn read_corruption() {
+    let c = TAIL[1];
+    let mut expected = first_words();
+    for row in 1..20 {
+        expected[row] = expected[row - 1] | (expected[row] & c);
+    }
+    assert_eq!(run_with_oam(&READ_LOOP, true), expected);
+}
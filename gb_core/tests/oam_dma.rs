@@ -0,0 +1,134 @@
+//! Where OAM DMA reads from, which isn't always where the CPU would, and how it shares the bus
+//! with a CPU running from HRAM
+
+use gb_core::gameboy::Gameboy;
+use gb_cpu::CpuOutputPins;
+
+/// Waits for LY 10, where the PPU is drawing, and starts a DMA from `source` * $100
+fn dma_on_line_10(source: u8) -> Gameboy {
+    #[rustfmt::skip]
+    let gameboy = Gameboy::with_program(&[
+        0xF0, 0x44,   // LDH A, (LY)
+        0xFE, 0x0A,   // CP 10
+        0x20, 0xFA,   // JR NZ, -6
+        0x3E, source, // LD A, source
+        0xE0, 0x46,   // LDH (DMA), A
+        0x18, 0xFE,   // JR -2
+    ], 0x0150).unwrap();
+    gameboy
+}
+
+#[test]
+fn vram_is_read_during_mode_3() {
+    let mut gameboy = dma_on_line_10(0x80);
+    for (i, b) in gameboy.ppu.tile_data.iter_mut().enumerate() {
+        *b = i as u8 ^ 0x5A;
+    }
+    let mut mode_3_reads = 0;
+    for _ in 0..2 * 17556 {
+        let info = gameboy.tick();
+        if info.dma && gameboy.ppu.stat.bits() & 0x03 == 3 {
+            mode_3_reads += 1;
+        }
+    }
+    // The transfer runs over several lines, so it must have read while the PPU was drawing
+    assert!(mode_3_reads > 0);
+    assert_eq!(gameboy.ppu.oam[..], gameboy.ppu.tile_data[..0xA0]);
+}
+
+#[test]
+fn high_sources_read_work_ram() {
+    for &(source, wram) in &[(0xE1u8, 0xC100usize), (0xFE, 0xDE00), (0xFF, 0xDF00)] {
+        let mut gameboy = dma_on_line_10(source);
+        for i in 0..0xA0 {
+            gameboy.memory[(wram + i) as u16] = i as u8 + 1;
+        }
+        let addresses: Vec<u16> = (0..2 * 17556)
+            .map(|_| gameboy.tick())
+            .filter(|info| info.dma)
+            .map(|info| info.pins.addr())
+            .collect();
+        assert_eq!(addresses[0], wram as u16, "source ${:02X}", source);
+        let expected: Vec<u8> = (1..=0xA0).collect();
+        assert_eq!(gameboy.ppu.oam[..], expected[..], "source ${:02X}", source);
+    }
+}
+
+/// Fills $C000-$C09F with 1, 2, 3... and $C100-$C19F with $81, $82, $83..., then runs `routine`
+/// from HRAM with A = $C0 and HL = $FE00
+fn run_from_hram(routine: &[u8]) -> Gameboy {
+    #[rustfmt::skip]
+    let mut gameboy = Gameboy::with_program(&[
+        0x3E, 0xC0,       // LD A, $C0
+        0x21, 0x00, 0xFE, // LD HL, $FE00
+        0xC3, 0x80, 0xFF, // JP $FF80
+    ], 0x0150).unwrap();
+    for i in 0..0xA0 {
+        gameboy.memory[0xC000 + i] = i as u8 + 1;
+        gameboy.memory[0xC100 + i] = (i as u8).wrapping_add(0x81);
+    }
+    for (i, &b) in routine.iter().enumerate() {
+        gameboy.memory[0xFF80 + i as u16] = b;
+    }
+    gameboy
+}
+
+#[test]
+fn transfer_starts_after_a_setup_cycle() {
+    #[rustfmt::skip]
+    let mut gameboy = run_from_hram(&[
+        0xE0, 0x46,       // LDH (DMA), A
+        // Fetched during the setup cycle, and reads while byte 2 is copied
+        0xFA, 0x00, 0xC0, // LD A, ($C000)
+        0xE0, 0x90,       // LDH ($90), A
+        0x7E,             // LD A, (HL)
+        0xE0, 0x91,       // LDH ($91), A
+        0x18, 0xFE,       // JR -2
+    ]);
+    let infos: Vec<_> = (0..400).map(|_| gameboy.tick()).collect();
+    let write = infos
+        .iter()
+        .position(|info| info.pins.addr() == 0xFF46)
+        .unwrap();
+    assert!(!infos[write + 1].dma);
+    assert!(infos[write + 2..write + 162].iter().all(|info| info.dma));
+    assert!(!infos[write + 162].dma);
+
+    // The CPU reads the byte DMA is copying, and OAM is busy
+    assert_eq!(gameboy.memory[0xFF90], 3);
+    assert_eq!(gameboy.memory[0xFF91], 0xFF);
+    let expected: Vec<u8> = (1..=0xA0).collect();
+    assert_eq!(gameboy.ppu.oam[..], expected[..]);
+}
+
+#[test]
+fn restart_overlaps_the_running_transfer() {
+    #[rustfmt::skip]
+    let mut gameboy = run_from_hram(&[
+        0xE0, 0x46,       // LDH (DMA), A
+        0x3E, 0xC1,       // LD A, $C1
+        0xE0, 0x46,       // LDH (DMA), A
+        0xFA, 0x00, 0xC0, // LD A, ($C000)
+        0xE0, 0x90,       // LDH ($90), A
+        0x18, 0xFE,       // JR -2
+    ]);
+    while !matches!(
+        gameboy.tick().pins,
+        CpuOutputPins::Write {
+            addr: 0xFF46,
+            data: 0xC1
+        }
+    ) {}
+    // The first transfer has copied bytes 0-3, and copies byte 4 during the restart's setup cycle
+    assert_eq!(gameboy.ppu.oam[..5], [1, 2, 3, 4, 0]);
+    assert!(gameboy.tick().dma);
+    assert_eq!(gameboy.ppu.oam[..6], [1, 2, 3, 4, 5, 0]);
+    // Then the new transfer starts again from byte 0
+    assert!(gameboy.tick().dma);
+    assert_eq!(gameboy.ppu.oam[..6], [0x81, 2, 3, 4, 5, 0]);
+
+    gameboy.run_cycles(4 * 200);
+    assert_eq!(gameboy.memory[0xFF90], 0x83);
+    let expected: Vec<u8> = (0x81..=0xFF).chain(0..=0x20).collect();
+    assert_eq!(gameboy.ppu.oam[..], expected[..]);
+}
@@ -0,0 +1,120 @@
+#[allow(dead_code)]
+mod common;
+
+use std::sync::Arc;
+
+use common::{frame_counter, rom_with_handlers};
+use gb_core::gameboy::{observer::SyncPoint, Gameboy};
+
+/// Counts VBlanks at $C000, and keeps a copy of the count in B
+fn gameboy() -> Gameboy {
+    let rom = rom_with_handlers(&frame_counter("ld b, a"), "OBSERVER");
+    let mut gameboy = Gameboy::new(rom).unwrap();
+    gameboy.run_frames(1);
+    gameboy
+}
+
+// Observations hold frames, which can't be sent to other threads with the `single-thread` feature
+#[cfg(not(feature = "single-thread"))]
+#[test]
+fn observers_see_coherent_snapshots() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    let mut gameboy = gameboy();
+    let done = Arc::new(AtomicBool::new(false));
+    let threads: Vec<_> = (0..3)
+        .map(|i| {
+            let observer = gameboy.observer();
+            let done = done.clone();
+            std::thread::spawn(move || {
+                let mut previous = observer.latest();
+                let mut seen = 0;
+                // Readers may not get to run until the emulator has finished, so each reads once
+                // more after that
+                loop {
+                    let finished = done.load(Ordering::SeqCst);
+                    if i == 0 {
+                        observer.request_memory();
+                    }
+                    let observation = observer.latest();
+                    // Published at the start of VBlank, while the CPU is halted
+                    assert_eq!(observation.ppu.ly, 144);
+                    assert!(observation.registers.halted);
+                    let frames = observation.frame_count - previous.frame_count;
+                    assert_eq!(observation.cycle - previous.cycle, frames * 70224);
+                    if let Some(memory) = &observation.memory {
+                        assert_eq!(memory.wram[0], observation.registers.registers.b);
+                    }
+                    seen += (frames > 0) as u32;
+                    previous = observation;
+                    if finished {
+                        return seen;
+                    }
+                    std::thread::yield_now();
+                }
+            })
+        })
+        .collect();
+
+    for _ in 0..60 {
+        gameboy.run_frames(1);
+    }
+    done.store(true, Ordering::SeqCst);
+    for thread in threads {
+        assert!(thread.join().unwrap() > 0);
+    }
+    let latest = gameboy.observer().latest();
+    assert_eq!(latest.frame_count, gameboy.ppu.frame_count);
+}
+
+#[test]
+fn memory_is_only_copied_when_requested() {
+    let mut gameboy = gameboy();
+    let observer = gameboy.observer();
+    gameboy.run_frames(1);
+    assert!(observer.latest().memory.is_none());
+
+    observer.request_memory();
+    gameboy.run_frames(1);
+    let memory = observer.latest().memory.clone().unwrap();
+    assert_eq!(memory.wram[0], gameboy.peek(0xC000));
+    assert_eq!(memory.vram.len(), 0x2000);
+
+    gameboy.run_frames(1);
+    assert!(observer.latest().memory.is_none());
+}
+
+#[test]
+fn sync_points() {
+    let mut gameboy = gameboy();
+    let observer = gameboy.observer();
+    assert_eq!(gameboy.observer_sync(), Some(SyncPoint::EveryFrame));
+
+    gameboy.set_observer_sync(SyncPoint::EveryNthFrame(10));
+    gameboy.run_frames(25);
+    let latest = observer.latest();
+    assert_eq!(latest.frame_count % 10, 0);
+    assert!(gameboy.ppu.frame_count - latest.frame_count < 10);
+
+    gameboy.set_observer_sync(SyncPoint::Manual);
+    gameboy.run_frames(25);
+    assert!(Arc::ptr_eq(&observer.latest(), &latest));
+    gameboy.run_cycles(100);
+    gameboy.publish_observation();
+    assert_eq!(observer.latest().cycle, gameboy.cycles());
+}
+
+#[test]
+fn memory_requests_wait_until_the_memory_is_seen() {
+    let mut gameboy = gameboy();
+    let observer = gameboy.observer();
+    observer.request_memory();
+    // Nobody looks at the first observation with memory before it is replaced
+    gameboy.run_frames(2);
+    let latest = observer.latest();
+    assert_eq!(latest.frame_count, gameboy.ppu.frame_count);
+    assert!(latest.memory.is_some());
+
+    gameboy.run_frames(1);
+    assert!(observer.latest().memory.is_none());
+}
@@ -0,0 +1,103 @@
+#[allow(dead_code)]
+mod common;
+
+use std::num::NonZeroU8;
+
+use common::rom_with_handlers;
+use gb_core::gameboy::{timer::TimerClock, Gameboy};
+
+/// Counts up in HL as fast as it can, copying it to $C000, and copies DIV to $C002 in the VBlank
+/// handler
+const BUSY_LOOP: &str = "
+    .org $0150
+        ld sp, $DFFE
+        ld a, $01
+        ldh [$FF], a
+        ei
+        ld hl, 0
+    loop:
+        inc hl
+        ld a, l
+        ld [$C000], a
+        ld a, h
+        ld [$C001], a
+        jr loop
+
+    .org $0200
+    vblank:
+        push af
+        ldh a, [$04]
+        ld [$C002], a
+        pop af
+        reti
+";
+
+fn gameboy(factor: u8, timer_clock: TimerClock) -> Gameboy {
+    let mut gameboy = Gameboy::new(rom_with_handlers(BUSY_LOOP, "OVERCLOCK")).unwrap();
+    gameboy.set_cpu_overclock(NonZeroU8::new(factor).unwrap());
+    gameboy.set_timer_clock(timer_clock);
+    gameboy.run_frames(2);
+    gameboy
+}
+
+/// How far the counter and DIV move on during a frame
+fn per_frame(gameboy: &mut Gameboy) -> (u16, u8) {
+    let read = |gameboy: &Gameboy| {
+        let count = u16::from_le_bytes([gameboy.peek(0xC000), gameboy.peek(0xC001)]);
+        (count, gameboy.peek(0xC002))
+    };
+    let (count, div) = read(gameboy);
+    gameboy.run_frames(1);
+    let (next_count, next_div) = read(gameboy);
+    (next_count.wrapping_sub(count), next_div.wrapping_sub(div))
+}
+
+#[test]
+fn cpu_runs_faster_and_the_rest_does_not() {
+    let (stock_count, stock_div) = per_frame(&mut gameboy(1, TimerClock::Stock));
+    let mut overclocked = gameboy(2, TimerClock::Stock);
+    let (count, div) = per_frame(&mut overclocked);
+
+    let ratio = count as f64 / stock_count as f64;
+    assert!((1.98..2.02).contains(&ratio), "{}", ratio);
+    // 70224 T-cycles is 274.3 DIV ticks, so it can be either side
+    assert!((stock_div.wrapping_sub(div) as i8).abs() <= 1);
+    let start = overclocked.cycles();
+    overclocked.run_frames(1);
+    assert_eq!(overclocked.cycles() - start, 70224);
+}
+
+#[test]
+fn timer_can_follow_the_cpu() {
+    let (_, stock_div) = per_frame(&mut gameboy(1, TimerClock::Stock));
+    let (_, div) = per_frame(&mut gameboy(2, TimerClock::Cpu));
+    // 548.6 DIV ticks, less two whole turns
+    assert!((div.wrapping_sub(stock_div.wrapping_mul(2)) as i8).abs() <= 2);
+}
+
+#[test]
+fn ppu_timing_is_unchanged() {
+    let mut stock = gameboy(1, TimerClock::Stock);
+    let mut overclocked = gameboy(3, TimerClock::Stock);
+    assert_eq!(stock.cycles(), overclocked.cycles());
+    for _ in 0..200 {
+        stock.run_cycles(1000);
+        overclocked.run_cycles(1000);
+        assert_eq!(stock.cycles(), overclocked.cycles());
+        assert_eq!(
+            (stock.ppu.ly, stock.ppu.stat.bits() & 0x07),
+            (overclocked.ppu.ly, overclocked.ppu.stat.bits() & 0x07),
+        );
+    }
+}
+
+#[test]
+fn savestates_keep_the_overclock() {
+    let mut gameboy = gameboy(2, TimerClock::Cpu);
+    let state = gameboy.save_state();
+    let mut loaded = self::gameboy(1, TimerClock::Stock);
+    loaded.load_state(&state).unwrap();
+    assert_eq!(loaded.cpu_overclock().get(), 2);
+    assert_eq!(loaded.timer_clock(), TimerClock::Cpu);
+    assert_eq!(per_frame(&mut loaded), per_frame(&mut gameboy));
+}
@@ -0,0 +1,90 @@
+use gb_core::gameboy::{ppu::consts::FRAME_T_CYCLES, Gameboy, ResetKind};
+
+fn gameboy() -> Gameboy {
+    let mut gameboy = Gameboy::new(vec![0; 0x8000]).unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    gameboy
+}
+
+#[test]
+fn frames_and_cycles_agree() {
+    let mut gameboy = gameboy();
+    let stats = gameboy.perf_stats();
+    assert_eq!((stats.frames, stats.cycles), (0, 0));
+
+    gameboy.run_frames(1);
+    let first = gameboy.perf_stats();
+    assert_eq!(first.frames, 1);
+    assert_eq!(first.cycles, gameboy.cycles());
+
+    // Every frame after the first takes exactly the same number of cycles
+    gameboy.run_frames(10);
+    let stats = gameboy.perf_stats();
+    assert_eq!(stats.frames, 11);
+    assert_eq!(stats.cycles - first.cycles, 10 * FRAME_T_CYCLES as u64);
+}
+
+#[test]
+fn reset_starts_from_zero() {
+    let mut gameboy = gameboy();
+    gameboy.run_frames(3);
+    gameboy.reset_perf_stats();
+    let stats = gameboy.perf_stats();
+    assert_eq!((stats.frames, stats.cycles), (0, 0));
+    assert_eq!(stats.timing, None);
+
+    gameboy.run_frames(4);
+    let stats = gameboy.perf_stats();
+    assert_eq!(stats.frames, 4);
+    assert_eq!(stats.cycles, 4 * FRAME_T_CYCLES as u64);
+}
+
+#[test]
+fn going_back_in_time_starts_from_zero() {
+    let mut gameboy = gameboy();
+    gameboy.run_frames(2);
+    let state = gameboy.save_state();
+    gameboy.run_frames(3);
+
+    // The counts go back further than where the stats were last reset
+    gameboy.reset_perf_stats();
+    gameboy.reset(ResetKind::PowerCycle);
+    let stats = gameboy.perf_stats();
+    assert_eq!((stats.frames, stats.cycles), (0, 0));
+
+    gameboy.run_frames(3);
+    gameboy.reset_perf_stats();
+    gameboy.load_state(&state).unwrap();
+    let stats = gameboy.perf_stats();
+    assert_eq!((stats.frames, stats.cycles), (0, 0));
+    gameboy.run_frames(1);
+    assert_eq!(gameboy.perf_stats().frames, 1);
+}
+
+#[cfg(not(feature = "perf-stats"))]
+#[test]
+fn no_timing_without_feature() {
+    let mut gameboy = gameboy();
+    gameboy.run_frames(5);
+    assert_eq!(gameboy.perf_stats().timing, None);
+}
+
+#[cfg(feature = "perf-stats")]
+#[test]
+fn timing_with_feature() {
+    let mut gameboy = gameboy();
+    gameboy.set_perf_stats_window(4);
+    gameboy.run_frames(1);
+    // Only one frame has been timed, so there is nothing to average over yet
+    assert_eq!(gameboy.perf_stats().timing, None);
+
+    gameboy.run_frames(10);
+    let timing = gameboy.perf_stats().timing.unwrap();
+    assert!(timing.frames_per_second > 0.0);
+    assert!(timing.speed > 0.0);
+    // Every subsystem does some work in every frame
+    let frame_time = timing.frame_time;
+    assert!(frame_time.cpu > Default::default());
+    assert!(frame_time.ppu > Default::default());
+    assert!(frame_time.bus > Default::default());
+}
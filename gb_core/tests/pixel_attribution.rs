@@ -0,0 +1,113 @@
+use gb_core::gameboy::{
+    ppu::{
+        consts::FRAME_T_CYCLES,
+        frame::attribution::{PaletteRegister, PixelLayer, PixelSource},
+        registers::LCDC,
+        Ppu,
+    },
+    Gameboy,
+};
+
+/// A frame with BG tile 5 at map position (2, 1), tile 9 at (1, 1), OAM entry 3 drawing tile 7
+/// at screen position (20, 8) with OBP1, and the window from line 100 down
+fn ppu() -> Ppu {
+    let mut ppu = Ppu::new();
+    for row in ppu.tile_data[5 * 16..6 * 16].chunks_exact_mut(2) {
+        row[0] = 0xFF;
+    }
+    ppu.tile_data[7 * 16..8 * 16].fill(0xFF);
+    ppu.bg_map_1[32 + 2] = 5;
+    ppu.bg_map_1[32 + 1] = 9;
+    ppu.oam[3 * 4..4 * 4].copy_from_slice(&[24, 28, 7, 0x10]);
+    ppu.lcdc.insert(LCDC::OBJ_ENABLE | LCDC::WINDOW_ENABLE);
+    ppu.wy = 100;
+    ppu.wx = 7;
+    ppu
+}
+
+fn run_frame(ppu: &mut Ppu) {
+    for _ in 0..FRAME_T_CYCLES {
+        ppu.clock_t_state();
+    }
+}
+
+#[test]
+fn pixels_name_their_layer_tile_and_palette() {
+    let mut ppu = ppu();
+    ppu.set_pixel_attribution(true);
+    run_frame(&mut ppu);
+    let frame = ppu.get_frame();
+    let attribution = frame.attribution().unwrap();
+
+    assert_eq!(
+        attribution.get(20, 8),
+        PixelSource {
+            layer: PixelLayer::Sprite {
+                tile: 7,
+                oam_index: 3
+            },
+            color: 3,
+            palette: PaletteRegister::Obp1,
+        }
+    );
+    // The sprite covers 20-27, and the background shows either side of it
+    assert_eq!(attribution.get(27, 15).layer.tile(), 7);
+    assert_eq!(
+        attribution.get(16, 8),
+        PixelSource {
+            layer: PixelLayer::Background {
+                tile: 5,
+                map_x: 2,
+                map_y: 1
+            },
+            color: 1,
+            palette: PaletteRegister::Bgp,
+        }
+    );
+    assert_eq!(
+        attribution.get(28, 8).layer,
+        PixelLayer::Background {
+            tile: 0,
+            map_x: 3,
+            map_y: 1
+        }
+    );
+    assert_eq!(attribution.get(28, 8).color, 0);
+
+    assert_eq!(
+        attribution.get(0, 100).layer,
+        PixelLayer::Window {
+            tile: 0,
+            map_x: 0,
+            window_line: 0
+        }
+    );
+    assert_eq!(
+        attribution.get(9, 110).layer,
+        PixelLayer::Window {
+            tile: 9,
+            map_x: 1,
+            window_line: 10
+        }
+    );
+}
+
+#[test]
+fn frames_have_no_attribution_unless_enabled() {
+    let mut ppu = ppu();
+    run_frame(&mut ppu);
+    assert!(ppu.get_frame().attribution().is_none());
+
+    ppu.set_pixel_attribution(true);
+    run_frame(&mut ppu);
+    assert!(ppu.get_frame().attribution().is_some());
+    // Reused frames forget it again
+    ppu.set_pixel_attribution(false);
+    run_frame(&mut ppu);
+    run_frame(&mut ppu);
+    assert!(ppu.get_frame().attribution().is_none());
+
+    let mut gameboy = Gameboy::with_program(&[0x18, 0xFE], 0x0150).unwrap();
+    gameboy.set_pixel_attribution(true);
+    assert!(gameboy.run_frames(2).attribution().is_some());
+}
@@ -0,0 +1,186 @@
+use gb_core::{
+    gameboy::{
+        cart::{
+            pocket_camera::{extract_photos, last_capture, PHOTO_HEIGHT, PHOTO_WIDTH, RAM_SIZE},
+            Cart,
+        },
+        Chip, ClockContext,
+    },
+    GbError,
+};
+use gb_cpu::CpuOutputPins;
+
+/// Write the shade of every pixel of a photo into `tiles`, as 16 by 14 tiles
+fn encode_tiles(tiles: &mut [u8], shade: impl Fn(usize, usize) -> u8) {
+    for tile_y in 0..14 {
+        for tile_x in 0..16 {
+            for row in 0..8 {
+                let (mut low, mut high) = (0, 0);
+                for column in 0..8 {
+                    let shade = shade(tile_x * 8 + column, tile_y * 8 + row);
+                    low |= (shade & 1) << (7 - column);
+                    high |= (shade >> 1) << (7 - column);
+                }
+                let offset = (tile_y * 16 + tile_x) * 16 + row * 2;
+                tiles[offset] = low;
+                tiles[offset + 1] = high;
+            }
+        }
+    }
+}
+
+/// The RAM of a camera with photos in slots 0 and 2, where slot 2 comes first in the album
+fn sram() -> Vec<u8> {
+    let mut sram = vec![0; RAM_SIZE];
+    sram[0x11B2..0x11B2 + 30].fill(0xFF);
+    sram[0x11B2] = 1;
+    sram[0x11B2 + 2] = 0;
+    encode_tiles(&mut sram[0x2000..], |_, _| 2);
+    encode_tiles(&mut sram[0x4000..], |x, y| ((x / 8 + y) % 4) as u8);
+    sram
+}
+
+#[test]
+fn photos_come_out_in_album_order() {
+    let photos = extract_photos(&sram());
+    assert_eq!(photos.len(), 2);
+    assert_eq!(photos[0].slot, Some(2));
+    assert_eq!(photos[1].slot, Some(0));
+
+    let photo = &photos[0];
+    for y in 0..PHOTO_HEIGHT {
+        for x in 0..PHOTO_WIDTH {
+            assert_eq!(photo.shade(x, y), ((x / 8 + y) % 4) as u8, "({}, {})", x, y);
+        }
+    }
+    assert!(photos[1].rows().flatten().all(|&shade| shade == 2));
+
+    let palette = [10, 20, 30, 40];
+    assert_eq!(photo.colors(&palette).nth(PHOTO_WIDTH + 8), Some(30));
+
+    // Truncated RAM loses the slots that don't fit
+    assert_eq!(extract_photos(&sram()[..0x3000]).len(), 1);
+    assert!(extract_photos(&[]).is_empty());
+}
+
+#[test]
+fn photos_sit_in_the_middle_of_a_frame() {
+    let photo = extract_photos(&sram()).remove(0);
+    let frame = photo.to_frame();
+    assert_eq!(frame[(16, 16)], photo.shade(0, 0));
+    assert_eq!(frame[(16 + 127, 16 + 111)], photo.shade(127, 111));
+    assert_eq!(frame[(16 + 8, 17)], 2);
+    assert_eq!(frame[(15, 16)], 0);
+    assert_eq!(frame[(16, 128)], 0);
+}
+
+/// A 1MiB Pocket Camera ROM
+fn camera() -> Cart {
+    let mut rom = vec![0; 0x100000];
+    rom[0x147] = 0xFC;
+    rom[0x148] = 0x05;
+    rom[0x149] = 0x04;
+    rom[0x3F * 0x4000] = 0x3F;
+    Cart::new(rom).unwrap()
+}
+
+fn write(cart: &mut Cart, addr: u16, data: u8) {
+    cart.clock(
+        CpuOutputPins::Write { addr, data },
+        &mut 0xFF,
+        &mut 0,
+        &ClockContext::default(),
+    );
+}
+
+fn read_at(cart: &mut Cart, addr: u16, cycles: u64) -> u8 {
+    let mut data = 0xFF;
+    cart.clock(
+        CpuOutputPins::Read { addr },
+        &mut data,
+        &mut 0,
+        &ClockContext {
+            cycles,
+            ..Default::default()
+        },
+    );
+    data
+}
+
+#[test]
+fn rom_has_64_banks_and_ram_has_16() {
+    let mut cart = camera();
+    write(&mut cart, 0x2000, 0x3F);
+    assert_eq!(read_at(&mut cart, 0x4000, 0), 0x3F);
+    assert_eq!(cart.rom_bank(), 0x3F);
+
+    write(&mut cart, 0x0000, 0x0A);
+    for bank in 0..16 {
+        write(&mut cart, 0x4000, bank);
+        write(&mut cart, 0xA000, 0x20 + bank);
+    }
+    assert_eq!(cart.ram().unwrap().len(), 0x20000);
+    assert_eq!(cart.ram().unwrap()[15 * 0x2000], 0x2F);
+
+    // Disabling RAM only stops writes
+    write(&mut cart, 0x0000, 0x00);
+    write(&mut cart, 0xA000, 0x00);
+    assert_eq!(read_at(&mut cart, 0xA000, 0), 0x2F);
+}
+
+#[test]
+fn taking_a_picture_dithers_the_image_into_ram() {
+    let mut cart = camera();
+    assert!(cart.has_battery());
+    // Dark on the left half, light on the right
+    let image: Vec<u8> = (0..PHOTO_WIDTH * PHOTO_HEIGHT)
+        .map(|i| if i % PHOTO_WIDTH < 64 { 0x10 } else { 0xF0 })
+        .collect();
+    cart.set_camera_image(&image).unwrap();
+
+    write(&mut cart, 0x4000, 0x10);
+    // Every threshold in the matrix is $40, $80 or $C0
+    for register in 0..16 {
+        write(&mut cart, 0xA006 + register * 3, 0x40);
+        write(&mut cart, 0xA007 + register * 3, 0x80);
+        write(&mut cart, 0xA008 + register * 3, 0xC0);
+    }
+    // No exposure, and no extra step
+    write(&mut cart, 0xA001, 0x80);
+    write(&mut cart, 0xA000, 0x01);
+    assert_eq!(read_at(&mut cart, 0xA000, 0), 0x01);
+    assert_eq!(read_at(&mut cart, 0xA080, 4 * 32445), 0x01);
+    assert!(!cart.is_save_dirty());
+    assert_eq!(read_at(&mut cart, 0xA000, 4 * 32446), 0x00);
+    assert!(cart.is_save_dirty());
+    // The registers hide RAM until a RAM bank is selected again
+    write(&mut cart, 0xA100, 0x00);
+    assert_eq!(read_at(&mut cart, 0xA100, 0), 0x00);
+
+    let photo = last_capture(cart.ram().unwrap()).unwrap();
+    assert_eq!(photo.slot, None);
+    assert_eq!(photo.shade(0, 0), 3);
+    assert_eq!(photo.shade(127, 111), 0);
+    write(&mut cart, 0x4000, 0x00);
+    assert_eq!(read_at(&mut cart, 0xA100, 0), 0xFF);
+}
+
+#[test]
+fn camera_image_must_fit_the_sensor() {
+    let mut cart = camera();
+    assert_eq!(
+        cart.set_camera_image(&[0; 160 * 144]),
+        Err(GbError::BufferSize {
+            expected: 128 * 112,
+            actual: 160 * 144
+        })
+    );
+
+    let mut rom = vec![0; 0x8000];
+    rom[0x148] = 0x00;
+    let mut cart = Cart::new(rom).unwrap();
+    assert!(matches!(
+        cart.set_camera_image(&[0; 128 * 112]),
+        Err(GbError::InvalidState(_))
+    ));
+}
@@ -0,0 +1,156 @@
+#[allow(dead_code)]
+mod common;
+
+use common::black_tile_in_corner;
+use gb_core::gameboy::ppu::{
+    color::{COLORS, COLOR_BLACK, COLOR_WHITE},
+    frame::{
+        post_process::{ColorCorrection, FrameBlend, PostProcess},
+        scale, Frame,
+    },
+};
+
+/// Swaps the red and green channels
+const SWAP_RED_GREEN: [[f32; 3]; 3] = [[0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]];
+
+fn filled(shade: u8) -> Frame {
+    let mut frame = Frame::new();
+    frame.iter_mut().for_each(|pixel| *pixel = shade);
+    frame
+}
+
+fn gray(level: u8) -> u32 {
+    u32::from_le_bytes([level, level, level, 0xFF])
+}
+
+#[test]
+fn blend_mixes_in_the_previous_output() {
+    let mut blend = FrameBlend::new(0.5);
+
+    // The first frame has nothing to blend with
+    let mut first = filled(0);
+    blend.process(&mut first);
+    assert!(first.colors().all(|color| color == COLOR_WHITE));
+
+    let mut second = filled(3);
+    blend.process(&mut second);
+    assert!(second.colors().all(|color| color == gray(0x80)));
+
+    // Halfway between black and the previous output, rather than the previous frame drawn
+    let mut third = filled(3);
+    blend.process(&mut third);
+    assert!(third.colors().all(|color| color == gray(0x40)));
+
+    blend.reset();
+    let mut fourth = filled(0);
+    blend.process(&mut fourth);
+    assert!(fourth.colors().all(|color| color == COLOR_WHITE));
+}
+
+#[test]
+fn blend_ratio_limits() {
+    let mut none = FrameBlend::new(0.0);
+    let mut all = FrameBlend::new(1.0);
+    for blend in [&mut none, &mut all] {
+        blend.process(&mut filled(0));
+    }
+
+    let mut frame = filled(3);
+    none.process(&mut frame);
+    assert!(frame.colors().all(|color| color == COLOR_BLACK));
+    let mut frame = filled(3);
+    all.process(&mut frame);
+    assert!(frame.colors().all(|color| color == COLOR_WHITE));
+}
+
+#[test]
+fn blend_keeps_alpha_of_the_current_frame() {
+    let mut blend = FrameBlend::new(0.25);
+    let mut first = filled(0);
+    first.set_palette([0x0000_0000; 4]);
+    blend.process(&mut first);
+
+    let mut second = filled(0);
+    second.set_palette([0xFFFF_FFFF; 4]);
+    blend.process(&mut second);
+    // 3/4 of $FF plus 1/4 of $00, rounded
+    assert_eq!(second.color(0, 0), 0xFF_BF_BF_BF);
+}
+
+#[test]
+fn color_correction_mixes_channels() {
+    let swap = ColorCorrection::new(1.0, 1.0, SWAP_RED_GREEN);
+    assert_eq!(swap.correct(0xFF0000FF), 0xFF00FF00);
+    assert_eq!(swap.correct(0x80123456), 0x80125634);
+
+    let identity = ColorCorrection::default();
+    for color in COLORS {
+        assert_eq!(identity.correct(color), color);
+    }
+
+    // Decoding with a gamma of 2 and encoding linearly squares each channel
+    let darken = ColorCorrection::new(2.0, 1.0, ColorCorrection::IDENTITY);
+    assert_eq!(darken.correct(gray(0x80)), gray(0x40));
+}
+
+#[test]
+fn color_correction_only_touches_the_palette() {
+    let mut correction = ColorCorrection::new(1.0, 1.0, SWAP_RED_GREEN);
+    let mut frame = filled(1);
+    frame.set_palette([0xFF0000FF, 0xFF00FF00, 0, 0]);
+    correction.process(&mut frame);
+    assert!(frame.pixel_colors().is_none());
+    assert_eq!(frame.palette()[..2], [0xFF00FF00, 0xFF0000FF]);
+
+    // Once pixels have colors of their own, they are corrected one by one
+    frame.colors_mut()[1] = 0xFF0000FF;
+    correction.process(&mut frame);
+    assert_eq!(frame.color(0, 0), 0xFF00FF00);
+    assert_eq!(frame.color(1, 0), 0xFF00FF00);
+}
+
+#[test]
+fn scaling_uses_pixel_colors() {
+    let mut frame = filled(0);
+    frame.colors_mut()[0] = 0xFF123456;
+    let mut out = vec![0; scale::buffer_len(1)];
+    scale::scale_nearest(&frame, 1, &mut out).unwrap();
+    assert_eq!(out[..8], [0x56, 0x34, 0x12, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+}
+
+#[test]
+fn chain_runs_on_every_drawn_frame() {
+    let mut gameboy = black_tile_in_corner();
+    gameboy.set_post_processing(vec![
+        Box::new(FrameBlend::new(0.5)),
+        Box::new(ColorCorrection::new(1.0, 1.0, SWAP_RED_GREEN)),
+    ]);
+    let receiver = gameboy.frame_receiver();
+    gameboy.run_frames(3);
+
+    let frame = gameboy.get_frame();
+    assert!(frame.pixel_colors().is_some());
+    assert_eq!(frame.color(0, 0), COLOR_BLACK);
+    assert_eq!(frame.color(8, 0), COLOR_WHITE);
+    let received = receiver.latest();
+    assert_eq!(received.color(0, 0), COLOR_BLACK);
+    assert!(received.pixel_colors().is_some());
+}
+
+#[test]
+fn empty_chain_leaves_frames_untouched() {
+    let mut gameboy = black_tile_in_corner();
+    gameboy.run_frames(2);
+    let frame = gameboy.get_frame();
+    assert!(frame.pixel_colors().is_none());
+    assert_eq!(*frame.palette(), COLORS);
+
+    // Buffers that were post-processed go back to using the palette once they are reused
+    gameboy.set_post_processing(vec![Box::new(FrameBlend::new(0.5))]);
+    gameboy.run_frames(4);
+    gameboy.set_post_processing(Vec::new());
+    for _ in 0..4 {
+        gameboy.run_frames(1);
+        assert!(gameboy.get_frame().pixel_colors().is_none());
+    }
+}
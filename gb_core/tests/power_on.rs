@@ -0,0 +1,125 @@
+//! Without a boot ROM, the cartridge starts with DIV, the PPU and the IO registers where the DMG
+//! boot ROM leaves them. These follow mooneye's boot_div and boot_hwio tests.
+
+use gb_core::gameboy::{Gameboy, Model, ResetKind};
+
+/// `count` reads of the IO register at $FF00+`reg`, 5 M-cycles apart, stored from $C000. The first
+/// read is 5 + `nops` M-cycles after the M-cycle that fetches the first instruction.
+fn read_repeatedly(reg: u8, count: usize, nops: usize) -> Gameboy {
+    let mut code = vec![0x21, 0x00, 0xC0]; // LD HL, $C000
+    code.extend(std::iter::repeat(0x00).take(nops));
+    for _ in 0..count {
+        code.extend_from_slice(&[
+            0xF0, reg,  // LDH A, (reg)
+            0x22, // LD (HL+), A
+        ]);
+    }
+    code.extend_from_slice(&[0x18, 0xFE]); // JR -2
+                                           // Start straight from the code, as if it were at $0100
+    let mut gameboy = Gameboy::with_program(&code, 0x0150).unwrap();
+    gameboy.run_frames(1);
+    gameboy
+}
+
+fn results(gameboy: &Gameboy, count: usize) -> Vec<u8> {
+    (0..count as u16)
+        .map(|i| gameboy.peek(0xC000 + i))
+        .collect()
+}
+
+#[test]
+fn div_increments_13_m_cycles_after_0100() {
+    // Reads 12 M-cycles in still see $AB, and reads 13 M-cycles in see $AC
+    assert_eq!(
+        results(&read_repeatedly(0x04, 4, 0), 4),
+        [0xAB, 0xAB, 0xAC, 0xAC]
+    );
+    assert_eq!(
+        results(&read_repeatedly(0x04, 4, 2), 4),
+        [0xAB, 0xAB, 0xAC, 0xAC]
+    );
+    assert_eq!(results(&read_repeatedly(0x04, 2, 3), 2), [0xAB, 0xAC]);
+    assert_eq!(
+        Model::Dmg.boot_counter() >> 8,
+        0xAB,
+        "DIV is the top of the counter"
+    );
+}
+
+#[test]
+fn ppu_hands_over_in_line_153() {
+    // 5, 10 and 15 M-cycles in. Line 0 starts 14 M-cycles in.
+    assert_eq!(results(&read_repeatedly(0x41, 3, 0), 3), [0x85, 0x85, 0x86]);
+    assert_eq!(results(&read_repeatedly(0x44, 3, 0), 3), [0x00, 0x00, 0x00]);
+    // 13 and 18 M-cycles in
+    assert_eq!(results(&read_repeatedly(0x41, 2, 8), 2), [0x85, 0x86]);
+}
+
+#[test]
+fn io_registers_after_boot() {
+    #[rustfmt::skip]
+    let code = [
+        0x21, 0x00, 0xFF, // LD HL, $FF00
+        0x11, 0x00, 0xC0, // LD DE, $C000
+        0x2A,             // LD A, (HL+)
+        0x12,             // LD (DE), A
+        0x1C,             // INC E
+        0xCB, 0x7D,       // BIT 7, L
+        0x28, 0xF9,       // JR Z, -7
+        0x18, 0xFE,       // JR -2
+    ];
+    let mut gameboy = Gameboy::with_program(&code, 0x0150).unwrap();
+    gameboy.run_frames(1);
+
+    // Address, expected value and the bits checked. The sound channel status bits of NR52 aren't
+    // emulated, so channel 1 doesn't show as playing.
+    #[rustfmt::skip]
+    let expected: &[(u16, u8, u8)] = &[
+        (0xFF00, 0xCF, 0xFF), (0xFF01, 0x00, 0xFF), (0xFF02, 0x7E, 0xFF),
+        (0xFF05, 0x00, 0xFF), (0xFF06, 0x00, 0xFF), (0xFF07, 0xF8, 0xFF),
+        (0xFF0F, 0xE1, 0xFF),
+        (0xFF10, 0x80, 0xFF), (0xFF11, 0xBF, 0xFF), (0xFF12, 0xF3, 0xFF), (0xFF14, 0xBF, 0xFF),
+        (0xFF16, 0x3F, 0xFF), (0xFF17, 0x00, 0xFF), (0xFF19, 0xBF, 0xFF),
+        (0xFF1A, 0x7F, 0xFF), (0xFF1C, 0x9F, 0xFF), (0xFF1E, 0xBF, 0xFF),
+        (0xFF20, 0xFF, 0xFF), (0xFF21, 0x00, 0xFF), (0xFF22, 0x00, 0xFF), (0xFF23, 0xBF, 0xFF),
+        (0xFF24, 0x77, 0xFF), (0xFF25, 0xF3, 0xFF), (0xFF26, 0xF1, 0xF0),
+        (0xFF40, 0x91, 0xFF), (0xFF42, 0x00, 0xFF), (0xFF43, 0x00, 0xFF),
+        (0xFF45, 0x00, 0xFF), (0xFF46, 0xFF, 0xFF), (0xFF47, 0xFC, 0xFF),
+        (0xFF4A, 0x00, 0xFF), (0xFF4B, 0x00, 0xFF),
+    ];
+    for &(addr, value, mask) in expected {
+        let read = gameboy.peek(0xC000 + (addr - 0xFF00));
+        assert_eq!(
+            read & mask,
+            value & mask,
+            "${:04X} read ${:02X}",
+            addr,
+            read
+        );
+    }
+}
+
+#[test]
+fn boot_rom_starts_from_power_on() {
+    let mut boot_rom = vec![0; 0x100];
+    #[rustfmt::skip]
+    boot_rom[..7].copy_from_slice(&[
+        0xF0, 0x04,       // LDH A, (DIV)
+        0xEA, 0x00, 0xC0, // LD ($C000), A
+        0x18, 0xFE,       // JR -2
+    ]);
+    let rom = gb_core::gameboy::cart::header::flat_rom(&[0x18, 0xFE], 0x0150, "").unwrap();
+    let mut gameboy = Gameboy::builder()
+        .rom(rom)
+        .boot_rom(boot_rom)
+        .build()
+        .unwrap();
+    gameboy.poke(0xC000, 0xFF);
+    gameboy.reset(ResetKind::PowerCycle);
+    assert_eq!(gameboy.cpu.cpu.registers.pc, 0x0000);
+    for _ in 0..3 {
+        gameboy.step_instruction();
+    }
+    assert_eq!(gameboy.peek(0xC000), 0x00);
+    assert_eq!(gameboy.peek(0xFF0F), 0xE0);
+}
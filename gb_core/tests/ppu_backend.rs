@@ -0,0 +1,105 @@
+use std::sync::{Arc, Mutex};
+
+use gb_core::gameboy::{
+    events::{Event, EventMask, Interrupt},
+    Gameboy, GameboyBuilder, PpuBackend, ResetKind,
+};
+
+/// 40 sprites, the window, and the background scrolled during every HBlank. See `ppu_scene.S`.
+const PPU_SCENE: &[u8] = include_bytes!("fixtures/ppu_scene.gb");
+
+fn scene(backend: PpuBackend) -> Gameboy {
+    let mut gameboy = GameboyBuilder::new()
+        .rom(PPU_SCENE)
+        .ppu_backend(backend)
+        .build()
+        .unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    gameboy
+}
+
+/// The T-cycle of each event `keep` picks out while running `frames` frames
+fn events(gameboy: &mut Gameboy, frames: u32, keep: fn(&Event) -> bool) -> Vec<u64> {
+    let cycles = Arc::new(Mutex::new(Vec::new()));
+    let log = cycles.clone();
+    let mask = EventMask::INTERRUPT_RAISED | EventMask::PPU_MODE_CHANGE;
+    let id = gameboy.subscribe_callback(mask, move |record| {
+        if keep(&record.event) {
+            log.lock().unwrap().push(record.cycle);
+        }
+    });
+    gameboy.run_frames(frames);
+    gameboy.unsubscribe(id);
+    let cycles = cycles.lock().unwrap().clone();
+    cycles
+}
+
+#[test]
+fn backends_draw_the_same_frames() {
+    let mut dot = scene(PpuBackend::DotAccurate);
+    let mut scanline = scene(PpuBackend::Scanline);
+    for _ in 0..5 {
+        dot.run_frames(1);
+        scanline.run_frames(1);
+        assert!(dot.get_frame().iter().eq(scanline.get_frame().iter()));
+        assert_eq!(dot.ppu.frame_info(), scanline.ppu.frame_info());
+    }
+}
+
+#[test]
+fn line_and_vblank_timing_is_shared() {
+    let mut dot = scene(PpuBackend::DotAccurate);
+    let mut scanline = scene(PpuBackend::Scanline);
+    let mode_1_or_2 = |event: &Event| matches!(event, Event::PpuModeChange { mode: 1 | 2, .. });
+    let expected = events(&mut dot, 4, mode_1_or_2);
+    assert!(expected.len() >= 2 * 145);
+    assert_eq!(events(&mut scanline, 4, mode_1_or_2), expected);
+}
+
+#[test]
+fn hblank_interrupts_stay_close() {
+    let mut dot = scene(PpuBackend::DotAccurate);
+    let mut scanline = scene(PpuBackend::Scanline);
+    let stat = |event: &Event| *event == Event::InterruptRaised(Interrupt::Stat);
+    let expected = events(&mut dot, 2, stat);
+    let raised = events(&mut scanline, 2, stat);
+    assert_eq!(raised.len(), expected.len());
+    // Mode 3 is only estimated, so HBlank may start a few dots apart
+    for (raised, expected) in raised.iter().zip(&expected) {
+        assert!(
+            raised.abs_diff(*expected) <= 4,
+            "{} vs {}",
+            raised,
+            expected
+        );
+    }
+}
+
+#[test]
+fn attribution_matches() {
+    let mut dot = scene(PpuBackend::DotAccurate);
+    let mut scanline = scene(PpuBackend::Scanline);
+    dot.set_pixel_attribution(true);
+    scanline.set_pixel_attribution(true);
+    // The scene starts by turning the LCD off, which blanks the frame and its attribution
+    dot.run_frames(3);
+    scanline.run_frames(3);
+    let (dot, scanline) = (dot.get_frame(), scanline.get_frame());
+    let (dot, scanline) = (dot.attribution().unwrap(), scanline.attribution().unwrap());
+    for y in 0..144 {
+        assert_eq!(dot.row(y), scanline.row(y), "line {}", y);
+    }
+}
+
+#[test]
+fn backend_can_change_between_frames() {
+    let mut dot = scene(PpuBackend::DotAccurate);
+    let mut switched = scene(PpuBackend::Scanline);
+    switched.run_frames(2);
+    switched.ppu.backend = PpuBackend::DotAccurate;
+    dot.run_frames(2);
+    switched.run_frames(2);
+    dot.run_frames(2);
+    assert_eq!(switched.cycles(), dot.cycles());
+    assert!(dot.get_frame().iter().eq(switched.get_frame().iter()));
+}
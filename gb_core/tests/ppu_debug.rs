@@ -0,0 +1,120 @@
+use gb_core::gameboy::ppu::{
+    self,
+    debug::{self, OverlayOptions, BACKGROUND_SIZE},
+    frame::Frame,
+    registers::*,
+    Ppu,
+};
+
+fn advance_frame(ppu: &mut Ppu) {
+    for _ in 0..ppu::consts::FRAME_T_CYCLES {
+        ppu.clock_t_state();
+    }
+}
+
+fn set_sprite(ppu: &mut Ppu, index: usize, (x, y): (u8, u8), flags: OamEntryFlags) {
+    ppu.oam[index * 4..index * 4 + 4].copy_from_slice(&[y + 16, x + 8, 0, flags.bits()]);
+}
+
+/// The pixels of a 160x144 image that are `color`
+fn pixels_of(image: &[u32], color: u32) -> Vec<(usize, usize)> {
+    (0..144)
+        .flat_map(|y| (0..160).map(move |x| (x, y)))
+        .filter(|&(x, y)| image[y * 160 + x] == color)
+        .collect()
+}
+
+/// The pixels on the outline of a `width` by `height` rectangle at `(x, y)`
+fn outline(x: usize, y: usize, width: usize, height: usize) -> Vec<(usize, usize)> {
+    let mut pixels: Vec<_> = (y..y + height)
+        .flat_map(|py| (x..x + width).map(move |px| (px, py)))
+        .filter(|&(px, py)| px == x || px == x + width - 1 || py == y || py == y + height - 1)
+        .collect();
+    pixels.sort_by_key(|&(px, py)| (py, px));
+    pixels
+}
+
+#[test]
+fn sprite_outlines() {
+    let mut ppu = Ppu::new();
+    ppu.lcdc = LCDC::LCD_ENABLE | LCDC::OBJ_ENABLE;
+    set_sprite(&mut ppu, 0, (20, 10), OamEntryFlags::empty());
+    set_sprite(&mut ppu, 5, (100, 50), OamEntryFlags::PALETTE_OBP1);
+    // Partly off the top left of the screen
+    set_sprite(&mut ppu, 9, (0, 0), OamEntryFlags::empty());
+    ppu.oam[9 * 4..9 * 4 + 2].copy_from_slice(&[12, 4]);
+    advance_frame(&mut ppu);
+
+    // Moving a sprite after the frame doesn't move its outline
+    set_sprite(&mut ppu, 0, (60, 60), OamEntryFlags::empty());
+    let overlay = debug::render_overlay(&ppu, &Frame::new(), OverlayOptions::default());
+
+    let mut expected = outline(20, 10, 8, 8);
+    // Only the bottom right corner of the outline is on screen
+    expected.extend([(0, 3), (1, 3), (2, 3), (3, 3), (3, 0), (3, 1), (3, 2)]);
+    expected.sort_by_key(|&(x, y)| (y, x));
+    expected.dedup();
+    assert_eq!(pixels_of(&overlay, debug::COLOR_SPRITE_OBP0), expected);
+    assert_eq!(
+        pixels_of(&overlay, debug::COLOR_SPRITE_OBP1),
+        outline(100, 50, 8, 8)
+    );
+
+    let indices: Vec<_> = ppu.selected_sprites().map(|s| s.index).collect();
+    assert_eq!(indices, [0, 5, 9]);
+}
+
+#[test]
+fn tall_sprites_and_window() {
+    let mut ppu = Ppu::new();
+    ppu.lcdc = LCDC::LCD_ENABLE | LCDC::OBJ_ENABLE | LCDC::OBJ_SIZE | LCDC::WINDOW_ENABLE;
+    ppu.wy = 100;
+    ppu.wx = 40 + 7;
+    set_sprite(&mut ppu, 3, (16, 16), OamEntryFlags::empty());
+    advance_frame(&mut ppu);
+
+    let overlay = debug::render_overlay(&ppu, &Frame::new(), OverlayOptions::default());
+    assert_eq!(
+        pixels_of(&overlay, debug::COLOR_SPRITE_OBP0),
+        outline(16, 16, 8, 16)
+    );
+    assert_eq!(
+        pixels_of(&overlay, debug::COLOR_WINDOW),
+        outline(40, 100, 120, 44)
+    );
+
+    let sprites_only = OverlayOptions {
+        window: false,
+        ..Default::default()
+    };
+    let overlay = debug::render_overlay(&ppu, &Frame::new(), sprites_only);
+    assert!(pixels_of(&overlay, debug::COLOR_WINDOW).is_empty());
+}
+
+#[test]
+fn viewport_wraps_around_background() {
+    let mut ppu = Ppu::new();
+    ppu.scx = 250;
+    ppu.scy = 200;
+    let background = debug::render_background(&ppu);
+    assert_eq!(background.len(), BACKGROUND_SIZE * BACKGROUND_SIZE);
+
+    let overlay = debug::render_background_overlay(&ppu, &background, OverlayOptions::default());
+    let marked: Vec<_> = (0..BACKGROUND_SIZE * BACKGROUND_SIZE)
+        .filter(|&i| overlay[i] == debug::COLOR_VIEWPORT)
+        .map(|i| (i % BACKGROUND_SIZE, i / BACKGROUND_SIZE))
+        .collect();
+    // The right and bottom edges wrap around to x = 153 and y = 87
+    for pos in [
+        (250, 200),
+        (255, 200),
+        (0, 200),
+        (153, 200),
+        (153, 87),
+        (250, 87),
+    ] {
+        assert!(marked.contains(&pos), "{:?} is not outlined", pos);
+    }
+    assert!(!marked.contains(&(154, 200)));
+    assert_eq!(marked.len(), 2 * 160 + 2 * 144 - 4);
+}
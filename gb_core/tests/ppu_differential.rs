@@ -0,0 +1,134 @@
+//! Compares the pixel FIFOs against `ppu::simple_renderer` on scenes full of noise.
+//!
+//! Run with `cargo test -p gb_core --features differential --test ppu_differential`.
+#![cfg(feature = "differential")]
+
+use gb_core::gameboy::{
+    ppu::{consts::FRAME_T_CYCLES, registers::LCDC, Ppu},
+    Gameboy, PpuBackend,
+};
+
+/// A small LCG, so that the scenes don't depend on an external crate
+struct Lcg(u32);
+
+impl Lcg {
+    fn next(&mut self) -> u8 {
+        self.0 = self.0.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        (self.0 >> 16) as u8
+    }
+}
+
+/// Fill VRAM and OAM with noise, and pick random scroll and window positions
+fn noise(seed: u32, lcdc: LCDC) -> Ppu {
+    let mut rng = Lcg(seed);
+    let mut ppu = Ppu::new();
+    ppu.tile_data.iter_mut().for_each(|b| *b = rng.next());
+    ppu.bg_map_1.iter_mut().for_each(|b| *b = rng.next());
+    ppu.bg_map_2.iter_mut().for_each(|b| *b = rng.next());
+    for sprite in ppu.oam.chunks_exact_mut(4) {
+        sprite[0] = rng.next() % 170;
+        sprite[1] = rng.next() % 176;
+        sprite[2] = rng.next();
+        sprite[3] = rng.next() & 0xF0;
+    }
+    ppu.lcdc = lcdc;
+    ppu.scx = rng.next();
+    ppu.scy = rng.next();
+    ppu.wx = 7 + rng.next() % 160;
+    ppu.wy = rng.next() % 144;
+    ppu.bgp = rng.next();
+    ppu.obp0 = rng.next();
+    ppu.obp1 = rng.next();
+    ppu
+}
+
+fn run(ppu: &mut Ppu, frames: usize) {
+    for _ in 0..frames * FRAME_T_CYCLES {
+        ppu.clock_t_state();
+    }
+}
+
+fn assert_no_divergences(ppu: &Ppu, scene: &str) {
+    let divergences: Vec<String> = ppu.divergences().iter().map(|d| d.to_string()).collect();
+    assert!(
+        divergences.is_empty(),
+        "{}:\n{}",
+        scene,
+        divergences.join("\n")
+    );
+}
+
+/// A different combination of the LCDC options for each seed up to 64
+fn scene_lcdc(seed: u32) -> LCDC {
+    let mut lcdc = LCDC::LCD_ENABLE | LCDC::OBJ_ENABLE;
+    let options = [
+        LCDC::BG_ENABLE,
+        LCDC::BG_TILE_DATA_AREA,
+        LCDC::BG_TILEMAP_AREA,
+        LCDC::WINDOW_ENABLE,
+        LCDC::WINDOW_TILEMAP_AREA,
+        LCDC::OBJ_SIZE,
+    ];
+    for (i, &option) in options.iter().enumerate() {
+        lcdc.set(option, seed & (1 << i) != 0);
+    }
+    lcdc
+}
+
+#[test]
+fn noise_scenes_match() {
+    for seed in 0..64 {
+        let lcdc = scene_lcdc(seed);
+        let mut ppu = noise(seed, lcdc);
+        run(&mut ppu, 2);
+        assert_no_divergences(&ppu, &format!("seed {} LCDC={:02X}", seed, lcdc.bits()));
+    }
+}
+
+#[test]
+fn scanline_backend_matches_on_noise_scenes() {
+    for seed in 0..64 {
+        let lcdc = scene_lcdc(seed);
+        let mut dot = noise(seed, lcdc);
+        let mut scanline = noise(seed, lcdc);
+        scanline.backend = PpuBackend::Scanline;
+        run(&mut dot, 2);
+        run(&mut scanline, 2);
+        assert!(
+            dot.get_frame().iter().eq(scanline.get_frame().iter()),
+            "seed {} LCDC={:02X}",
+            seed,
+            lcdc.bits()
+        );
+    }
+}
+
+#[test]
+fn lines_written_during_drawing_are_skipped() {
+    // Scrolls the background mid-line, which the simple renderer can't draw
+    #[rustfmt::skip]
+    let mut gameboy = Gameboy::with_program(&[
+        0x3C,       // INC A
+        0xE0, 0x43, // LDH (SCX), A
+        0x18, 0xFB, // JR -5
+    ], 0x0150).unwrap();
+    for (i, b) in gameboy.ppu.tile_data.iter_mut().enumerate() {
+        *b = i as u8;
+    }
+    gameboy.run_frames(3);
+    assert_no_divergences(&gameboy.ppu, "SCX writes");
+}
+
+#[test]
+fn divergences_are_reported() {
+    let mut ppu = noise(1, LCDC::LCD_ENABLE | LCDC::BG_ENABLE);
+    // Writing a register directly isn't seen as a write during the line
+    for _ in 0..10 * 456 + 200 {
+        ppu.clock_t_state();
+    }
+    ppu.scx = ppu.scx.wrapping_add(4);
+    run(&mut ppu, 1);
+    let divergence = ppu.divergences()[0];
+    assert_eq!(divergence.ly, 10);
+    assert!(divergence.x < 160);
+}
@@ -0,0 +1,121 @@
+use gb_core::gameboy::ppu::{
+    debug::{FetcherStep, FifoSnapshot, TileMapPosition},
+    registers::*,
+    Ppu,
+};
+
+/// Run through OAM search on line 0, so that the next dot is the first of mode 3
+fn start_mode_3(ppu: &mut Ppu) {
+    for _ in 0..80 {
+        ppu.clock_t_state();
+    }
+}
+
+fn next_dot(ppu: &mut Ppu) -> FifoSnapshot {
+    ppu.clock_t_state();
+    ppu.fifo_snapshot().unwrap().clone()
+}
+
+#[test]
+fn snapshots_are_opt_in() {
+    let mut ppu = Ppu::new();
+    start_mode_3(&mut ppu);
+    ppu.clock_t_state();
+    assert_eq!(ppu.fifo_snapshot(), None);
+
+    ppu.enable_fifo_snapshots(true);
+    assert_eq!(ppu.fifo_snapshot(), Some(&FifoSnapshot::default()));
+    ppu.enable_fifo_snapshots(false);
+    assert_eq!(ppu.fifo_snapshot(), None);
+}
+
+#[test]
+fn fetcher_steps_take_two_dots() {
+    use FetcherStep::*;
+
+    let mut ppu = Ppu::new();
+    ppu.lcdc = LCDC::LCD_ENABLE | LCDC::BG_ENABLE | LCDC::BG_TILE_DATA_AREA;
+    ppu.enable_fifo_snapshots(true);
+    ppu.bg_map_1[0] = 1;
+    ppu.bg_map_1[1] = 2;
+    // Tile 1 is color 3 and tile 2 is color 1
+    ppu.tile_data[16..18].copy_from_slice(&[0xFF, 0xFF]);
+    ppu.tile_data[32..34].copy_from_slice(&[0xFF, 0x00]);
+    start_mode_3(&mut ppu);
+
+    let snapshots: Vec<_> = (0..16).map(|_| next_dot(&mut ppu)).collect();
+    let steps: Vec<_> = snapshots.iter().map(|s| s.bg_fetcher).collect();
+    #[rustfmt::skip]
+    let expected = [
+        ReadTileNumber, ReadTileNumber, ReadDataLow, ReadDataLow,
+        ReadDataHigh, ReadDataHigh, Push, Push,
+        ReadTileNumber, ReadTileNumber, ReadDataLow, ReadDataLow,
+        ReadDataHigh, ReadDataHigh, Push, Push,
+    ];
+    assert_eq!(steps, expected);
+    assert!(snapshots
+        .iter()
+        .all(|s| s.ly == 0 && s.sprite_fetcher.is_none()));
+
+    // The first tile is pushed on the 7th dot, and the tile map moves on to the next entry
+    assert!(snapshots[5].bg_fifo.is_empty());
+    assert_eq!(
+        snapshots[5].tile_map,
+        TileMapPosition {
+            x: 0,
+            y: 0,
+            window: false
+        }
+    );
+    let first = &snapshots[6];
+    assert_eq!(first.bg_fifo.len(), 8);
+    assert!(first.bg_fifo.iter().all(|p| p.tile == 1 && p.color == 3));
+    assert_eq!(
+        first.tile_map,
+        TileMapPosition {
+            x: 1,
+            y: 0,
+            window: false
+        }
+    );
+
+    // Pixels are only shifted out while the FIFO holds more than 8, starting on the same dot the
+    // second tile is pushed
+    assert_eq!(snapshots[13].lx, 0);
+    let second = &snapshots[14];
+    assert_eq!(second.lx, 1);
+    assert_eq!(second.bg_fifo.len(), 15);
+    assert!(second.bg_fifo[7..]
+        .iter()
+        .all(|p| p.tile == 2 && p.color == 1));
+    assert_eq!(snapshots[15].lx, 2);
+    assert_eq!(snapshots[15].bg_fifo.len(), 14);
+}
+
+#[test]
+fn sprite_fetch_pauses_the_bg_fetcher() {
+    let mut ppu = Ppu::new();
+    ppu.lcdc = LCDC::LCD_ENABLE | LCDC::BG_ENABLE | LCDC::OBJ_ENABLE;
+    ppu.enable_fifo_snapshots(true);
+    // A sprite at X = 0, using tile 5
+    ppu.oam[0..4].copy_from_slice(&[16, 8, 5, OamEntryFlags::PALETTE_OBP1.bits()]);
+    ppu.tile_data[5 * 16..5 * 16 + 2].copy_from_slice(&[0xFF, 0x00]);
+    start_mode_3(&mut ppu);
+
+    let snapshots: Vec<_> = (0..30).map(|_| next_dot(&mut ppu)).collect();
+    let fetching: Vec<_> = snapshots
+        .iter()
+        .filter(|s| s.sprite_fetcher.is_some())
+        .collect();
+    assert_eq!(fetching.len(), 4);
+    assert!(fetching.iter().all(|s| s.lx == 0));
+    let pushed = snapshots
+        .iter()
+        .find(|s| !s.sprite_fifo.is_empty())
+        .unwrap();
+    assert_eq!(pushed.sprite_fifo.len(), 8);
+    assert!(pushed
+        .sprite_fifo
+        .iter()
+        .all(|p| p.tile == 5 && p.color == 1 && p.palette == 1));
+}
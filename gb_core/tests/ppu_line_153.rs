@@ -0,0 +1,104 @@
+//! LY wraps to 0 a few dots into line 153, rather than at the end of it
+
+use gb_core::gameboy::ppu::{consts::LINE_153_DOTS, registers::STAT, Ppu};
+use gb_cpu::CpuOutputPins;
+
+struct Harness {
+    ppu: Ppu,
+    t_cycles: usize,
+    interrupt_request: u8,
+}
+
+impl Harness {
+    fn new() -> Self {
+        Harness {
+            ppu: Ppu::new(),
+            t_cycles: 0,
+            interrupt_request: 0,
+        }
+    }
+
+    /// Run until the PPU is on `dot` of line `ly`, counting from the start of the first frame
+    fn run_to(&mut self, ly: usize, dot: usize) {
+        while self.t_cycles <= ly * 456 + dot {
+            self.ppu.clock_t_state();
+            self.t_cycles += 1;
+        }
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        let mut data = 0xFF;
+        self.ppu.perform_io(
+            CpuOutputPins::Read { addr },
+            &mut data,
+            &mut self.interrupt_request,
+        );
+        data
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.ppu.perform_io(
+            CpuOutputPins::Write { addr, data },
+            &mut 0xFF,
+            &mut self.interrupt_request,
+        );
+    }
+
+    fn ly(&mut self) -> u8 {
+        self.read(0xFF44)
+    }
+
+    fn coincidence(&mut self) -> bool {
+        STAT::from_bits_truncate(self.read(0xFF41)).contains(STAT::LYC_EQUALS_LY)
+    }
+}
+
+#[test]
+fn ly_wraps_early() {
+    let mut harness = Harness::new();
+    harness.run_to(152, 455);
+    assert_eq!(harness.ly(), 152);
+    for dot in 0..LINE_153_DOTS {
+        harness.run_to(153, dot);
+        assert_eq!(harness.ly(), 153, "dot {}", dot);
+    }
+    for dot in LINE_153_DOTS..456 {
+        harness.run_to(153, dot);
+        assert_eq!(harness.ly(), 0, "dot {}", dot);
+    }
+    // The next frame still starts at the end of line 153
+    harness.run_to(154, 455);
+    assert_eq!(harness.ly(), 0);
+    harness.run_to(155, 0);
+    assert_eq!(harness.ly(), 1);
+}
+
+#[test]
+fn lyc_0_matches_during_line_153() {
+    let mut harness = Harness::new();
+    harness.write(0xFF45, 0);
+    harness.write(0xFF41, STAT::LYC_INTERRUPT_ENABLE.bits());
+    harness.run_to(153, 0);
+    harness.interrupt_request = 0;
+    assert!(!harness.coincidence());
+
+    harness.run_to(153, LINE_153_DOTS);
+    assert!(harness.coincidence());
+    assert_eq!(harness.interrupt_request & 0b10, 0b10, "STAT interrupt");
+
+    // LY is already 0 when line 0 begins, so there's no second interrupt
+    harness.interrupt_request = 0;
+    harness.run_to(154, 8);
+    assert!(harness.coincidence());
+    assert_eq!(harness.interrupt_request & 0b10, 0);
+}
+
+#[test]
+fn lyc_153_matches_briefly() {
+    let mut harness = Harness::new();
+    harness.write(0xFF45, 153);
+    harness.run_to(153, 0);
+    assert!(harness.coincidence());
+    harness.run_to(153, LINE_153_DOTS);
+    assert!(!harness.coincidence());
+}
@@ -34,7 +34,7 @@ fn ppu_singlecolor() {
         set_tile_singlecolor(&mut ppu, 0, color);
         advance_frame(&mut ppu);
         let frame = ppu.get_frame();
-        frame.iter().for_each(|&pix| {
+        frame.colors().for_each(|pix| {
             assert_eq!(
                 pix,
                 color::COLORS[color::calculate_monochrome_color_id(ppu.bgp, color)]
@@ -65,12 +65,12 @@ fn ppu_bgp() {
         for color in 0..=3 {
             let i = color as usize * 8; // 8 pixel wide tiles
             assert_eq!(
-                frame[i],
+                frame.color(i, 0),
                 color::COLORS[color::calculate_monochrome_color_id(bgp, color)],
                 "\nBGP: {:#04b}\nraw color: {:#04b}\nExpected color: {:#X}\nActual color: {:#X}",
                 bgp,
                 color,
-                frame[i],
+                frame.color(i, 0),
                 color::COLORS[color::calculate_monochrome_color_id(bgp, color)]
             );
         }
@@ -101,3 +101,151 @@ fn calculate_monochrome_color() {
         }
     }
 }
+
+/// Run one frame from the start, calling `f` before every dot
+fn run_frame_with(ppu: &mut Ppu, mut f: impl FnMut(usize, &mut Ppu)) {
+    for dot in 0..ppu::consts::FRAME_T_CYCLES {
+        f(dot, ppu);
+        ppu.clock_t_state();
+    }
+}
+
+fn row_is(frame: &ppu::frame::Frame, y: usize, color: color::RgbaColor) -> bool {
+    frame.row_colors(y).iter().all(|&pix| pix == color)
+}
+
+#[test]
+fn bg_disable_renders_white() {
+    let mut ppu = Ppu::new();
+
+    // Background and window are both black, except that the background is disabled
+    set_tile_singlecolor(&mut ppu, 0, 0b11);
+    set_tile_singlecolor(&mut ppu, 1, 0b01);
+    ppu.bg_map_1.fill(0);
+    ppu.lcdc = LCDC::LCD_ENABLE | LCDC::BG_TILE_DATA_AREA | LCDC::WINDOW_ENABLE | LCDC::OBJ_ENABLE;
+    ppu.wx = 7;
+    ppu.bgp = 0b11100100;
+    ppu.obp0 = 0b11100100;
+
+    // A sprite in the top left corner. It has BG priority, but should still be drawn since the
+    // background counts as color 0.
+    ppu.oam[..4].copy_from_slice(&[16, 8, 1, OamEntryFlags::BG_PRIORITY.bits()]);
+
+    advance_frame(&mut ppu);
+    let frame = ppu.get_frame();
+    for y in 0..144 {
+        for x in 0..160 {
+            let expected = if x < 8 && y < 8 {
+                color::COLOR_LIGHTGRAY
+            } else {
+                color::COLOR_WHITE
+            };
+            assert_eq!(frame.color(x, y), expected, "x: {}, y: {}", x, y);
+        }
+    }
+}
+
+#[test]
+fn mid_frame_tile_data_switch() {
+    let mut ppu = Ppu::new();
+
+    // Tile 0 is black at $8000 and white at $9000
+    set_tile_singlecolor(&mut ppu, 0, 0b11);
+    set_tile_singlecolor(&mut ppu, 0x100, 0b00);
+    ppu.bg_map_1.fill(0);
+    ppu.lcdc = LCDC::LCD_ENABLE | LCDC::BG_ENABLE | LCDC::BG_TILE_DATA_AREA;
+    ppu.bgp = 0b11100100;
+
+    run_frame_with(&mut ppu, |dot, ppu| {
+        if dot == 72 * 456 {
+            ppu.lcdc.remove(LCDC::BG_TILE_DATA_AREA);
+        }
+    });
+
+    let frame = ppu.get_frame();
+    for y in 0..144 {
+        let expected = if y < 72 {
+            color::COLOR_BLACK
+        } else {
+            color::COLOR_WHITE
+        };
+        assert!(row_is(&frame, y, expected), "line {}", y);
+    }
+}
+
+#[test]
+fn mid_line_tilemap_switch() {
+    let mut ppu = Ppu::new();
+
+    set_tile_singlecolor(&mut ppu, 0, 0b11);
+    set_tile_singlecolor(&mut ppu, 1, 0b00);
+    ppu.bg_map_1.fill(0);
+    ppu.bg_map_2.fill(1);
+    ppu.lcdc = LCDC::LCD_ENABLE | LCDC::BG_ENABLE | LCDC::BG_TILE_DATA_AREA;
+    ppu.bgp = 0b11100100;
+
+    // Switch partway through mode 3 of line 10
+    run_frame_with(&mut ppu, |dot, ppu| {
+        if dot == 10 * 456 + 80 + 60 {
+            ppu.lcdc.insert(LCDC::BG_TILEMAP_AREA);
+        }
+    });
+
+    let frame = ppu.get_frame();
+    for y in (0..10).chain(11..144) {
+        let expected = if y < 10 {
+            color::COLOR_BLACK
+        } else {
+            color::COLOR_WHITE
+        };
+        assert!(row_is(&frame, y, expected), "line {}", y);
+    }
+
+    // Tiles fetched after the switch come from the other map, so line 10 changes at a tile boundary
+    let row = frame.row_colors(10);
+    let switch = row
+        .iter()
+        .position(|&pix| pix == color::COLOR_WHITE)
+        .unwrap();
+    assert!(switch > 0 && switch % 8 == 0, "switched at x = {}", switch);
+    assert!(row[..switch].iter().all(|&pix| pix == color::COLOR_BLACK));
+    assert!(row[switch..].iter().all(|&pix| pix == color::COLOR_WHITE));
+}
+
+#[test]
+fn mid_frame_window_switches() {
+    let mut ppu = Ppu::new();
+
+    // The window covers the whole screen. Its tiles are black in map 1 and white in map 2, and the
+    // background uses map 2 so that it can't be mistaken for the window.
+    set_tile_singlecolor(&mut ppu, 0, 0b11);
+    set_tile_singlecolor(&mut ppu, 1, 0b00);
+    ppu.bg_map_1.fill(0);
+    ppu.bg_map_2.fill(1);
+    ppu.lcdc = LCDC::LCD_ENABLE
+        | LCDC::BG_ENABLE
+        | LCDC::BG_TILE_DATA_AREA
+        | LCDC::BG_TILEMAP_AREA
+        | LCDC::WINDOW_ENABLE;
+    ppu.wx = 7;
+    ppu.bgp = 0b11100100;
+
+    run_frame_with(&mut ppu, |dot, ppu| {
+        if dot == 48 * 456 {
+            ppu.lcdc.insert(LCDC::WINDOW_TILEMAP_AREA);
+        } else if dot == 96 * 456 {
+            // Back to the black tiles, but the window is blanked along with the background
+            ppu.lcdc.remove(LCDC::WINDOW_TILEMAP_AREA | LCDC::BG_ENABLE);
+        }
+    });
+
+    let frame = ppu.get_frame();
+    for y in 0..144 {
+        let expected = if y < 48 {
+            color::COLOR_BLACK
+        } else {
+            color::COLOR_WHITE
+        };
+        assert!(row_is(&frame, y, expected), "line {}", y);
+    }
+}
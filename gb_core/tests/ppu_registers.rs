@@ -0,0 +1,89 @@
+//! Checks the read-back table in the documentation of `gb_core::gameboy::ppu`
+
+use gb_core::gameboy::{cart::header::flat_rom, Gameboy};
+use gb_cpu::assembler::assemble;
+
+/// Register, value written, value read back two frames later, and a mask of the bits that are
+/// checked
+#[rustfmt::skip]
+const REGISTERS: &[(u16, u8, u8, u8)] = &[
+    (0xFF40, 0x93, 0x93, 0xFF), // LCDC, with the LCD still on
+    (0xFF41, 0x48, 0xC8, 0xF8), // STAT
+    (0xFF42, 0x12, 0x12, 0xFF), // SCY
+    (0xFF43, 0x34, 0x34, 0xFF), // SCX
+    (0xFF44, 0x56, 0x90, 0xFF), // LY, which ignores the write and is read early in line 144
+    (0xFF45, 0x78, 0x78, 0xFF), // LYC
+    (0xFF46, 0xC1, 0xC1, 0xFF), // DMA, long after the transfer has finished
+    (0xFF47, 0x9A, 0x9A, 0xFF), // BGP
+    (0xFF48, 0xBC, 0xBC, 0xFF), // OBP0
+    (0xFF49, 0xDE, 0xDE, 0xFF), // OBP1
+    (0xFF4A, 0x21, 0x21, 0xFF), // WY
+    (0xFF4B, 0x43, 0x43, 0xFF), // WX
+    (0xFF4F, 0x00, 0xFF, 0xFF), // VBK
+    (0xFF51, 0x00, 0xFF, 0xFF), // HDMA1
+    (0xFF52, 0x00, 0xFF, 0xFF), // HDMA2
+    (0xFF53, 0x00, 0xFF, 0xFF), // HDMA3
+    (0xFF54, 0x00, 0xFF, 0xFF), // HDMA4
+    (0xFF55, 0x00, 0xFF, 0xFF), // HDMA5
+    (0xFF68, 0x00, 0xFF, 0xFF), // BCPS
+    (0xFF69, 0x00, 0xFF, 0xFF), // BCPD
+    (0xFF6A, 0x00, 0xFF, 0xFF), // OCPS
+    (0xFF6B, 0x00, 0xFF, 0xFF), // OCPD
+    (0xFF6C, 0x00, 0xFF, 0xFF), // OPRI
+];
+
+/// Writes every register, waits for two VBlanks, then stores what each reads back from $C000
+fn program() -> String {
+    let mut source = String::from(".org $0150\n");
+    for &(addr, written, ..) in REGISTERS {
+        source += &format!("ld a, ${:02X}\nldh [${:02X}], a\n", written, addr & 0xFF);
+    }
+    source += "
+        ld b, 2
+    frame:
+        ldh a, [$44]
+        cp 144
+        jr z, frame
+    wait_vblank:
+        ldh a, [$44]
+        cp 144
+        jr nz, wait_vblank
+        dec b
+        jr nz, frame
+    ";
+    for (i, &(addr, ..)) in REGISTERS.iter().enumerate() {
+        source += &format!(
+            "ldh a, [${:02X}]\nld [${:04X}], a\n",
+            addr & 0xFF,
+            0xC000 + i
+        );
+    }
+    source += "done:\njr done\n";
+    source
+}
+
+fn check(gameboy: &Gameboy) {
+    for (i, &(addr, _, expected, mask)) in REGISTERS.iter().enumerate() {
+        assert_eq!(
+            gameboy.peek(0xC000 + i as u16) & mask,
+            expected & mask,
+            "register {:#06X}",
+            addr
+        );
+    }
+}
+
+#[test]
+fn ppu_registers_read_back() {
+    let rom = flat_rom(&assemble(&program()).unwrap(), 0x0150, "PPU REGS").unwrap();
+    let mut gameboy = Gameboy::new(rom.clone()).unwrap();
+    gameboy.run_frames(4);
+    check(&gameboy);
+    assert!(!gameboy.ppu.dma_active());
+
+    // DMA is kept apart from the transfer, which has finished, so it has to be saved on its own
+    let mut loaded = Gameboy::new(rom).unwrap();
+    loaded.load_state(&gameboy.save_state()).unwrap();
+    assert_eq!(loaded.ppu.dma, 0xC1);
+    check(&loaded);
+}
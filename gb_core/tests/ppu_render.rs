@@ -0,0 +1,107 @@
+//! Pixel-exact regression tests of the renderer, against frames stored in `fixtures/`. Set
+//! `GOLDEN_UPDATE=1` to regenerate the frames after a deliberate change to the renderer.
+
+use std::fs;
+
+use gb_core::gameboy::ppu::{self, registers::LCDC, Ppu};
+
+/// A small LCG, so that the snapshots don't depend on an external crate
+struct Lcg(u32);
+
+impl Lcg {
+    fn next(&mut self) -> u8 {
+        self.0 = self.0.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        (self.0 >> 16) as u8
+    }
+}
+
+/// Fill VRAM and OAM with noise, and place the window and scroll registers somewhere
+/// interesting. Every sprite is on screen, so each line has more than 10 sprites to choose from.
+pub fn vram_snapshot(seed: u32, lcdc: LCDC) -> Ppu {
+    let mut rng = Lcg(seed);
+    let mut ppu = Ppu::new();
+    ppu.tile_data.iter_mut().for_each(|b| *b = rng.next());
+    ppu.bg_map_1.iter_mut().for_each(|b| *b = rng.next());
+    ppu.bg_map_2.iter_mut().for_each(|b| *b = rng.next());
+    for sprite in ppu.oam.chunks_exact_mut(4) {
+        sprite[0] = rng.next() % 160;
+        sprite[1] = rng.next() % 168;
+        sprite[2] = rng.next();
+        sprite[3] = rng.next() & 0xF0;
+    }
+    ppu.lcdc = lcdc;
+    ppu.scx = 3;
+    ppu.scy = 77;
+    ppu.wx = 87;
+    ppu.wy = 40;
+    ppu.bgp = 0b11_10_01_00;
+    ppu.obp0 = 0b11_01_10_00;
+    ppu.obp1 = 0b00_01_10_11;
+    ppu
+}
+
+/// The shades of a frame, packed 4 pixels to a byte
+fn render(mut ppu: Ppu) -> Vec<u8> {
+    for _ in 0..ppu::consts::FRAME_T_CYCLES {
+        ppu.clock_t_state();
+    }
+    ppu.get_frame()
+        .iter()
+        .collect::<Vec<_>>()
+        .chunks_exact(4)
+        .map(|pixels| {
+            pixels
+                .iter()
+                .enumerate()
+                .fold(0, |byte, (i, &&shade)| byte | (shade & 3) << (i * 2))
+        })
+        .collect()
+}
+
+fn check(name: &str, ppu: Ppu) {
+    let path = format!(
+        "{}/tests/fixtures/{}.2bpp",
+        env!("CARGO_MANIFEST_DIR"),
+        name
+    );
+    let frame = render(ppu);
+    if std::env::var_os("GOLDEN_UPDATE").is_some() {
+        fs::write(&path, &frame).unwrap();
+        return;
+    }
+    let expected = fs::read(&path).unwrap();
+    if let Some(i) = (0..frame.len() * 4).find(|i| {
+        let shade = |bytes: &[u8]| bytes[i / 4] >> (i % 4 * 2) & 3;
+        shade(&frame) != shade(&expected)
+    }) {
+        panic!("{}: pixel ({}, {}) differs", name, i % 160, i / 160);
+    }
+}
+
+#[test]
+fn unsigned_tiles_8x8_sprites() {
+    let lcdc = LCDC::LCD_ENABLE
+        | LCDC::BG_ENABLE
+        | LCDC::BG_TILE_DATA_AREA
+        | LCDC::WINDOW_ENABLE
+        | LCDC::WINDOW_TILEMAP_AREA
+        | LCDC::OBJ_ENABLE;
+    check("render_8x8", vram_snapshot(1, lcdc));
+}
+
+#[test]
+fn signed_tiles_8x16_sprites() {
+    let lcdc = LCDC::LCD_ENABLE
+        | LCDC::BG_ENABLE
+        | LCDC::BG_TILEMAP_AREA
+        | LCDC::WINDOW_ENABLE
+        | LCDC::OBJ_SIZE
+        | LCDC::OBJ_ENABLE;
+    check("render_8x16", vram_snapshot(2, lcdc));
+}
+
+#[test]
+fn bg_disabled() {
+    let lcdc = LCDC::LCD_ENABLE | LCDC::BG_TILE_DATA_AREA | LCDC::WINDOW_ENABLE | LCDC::OBJ_ENABLE;
+    check("render_bg_disabled", vram_snapshot(3, lcdc));
+}
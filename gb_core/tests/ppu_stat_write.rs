@@ -0,0 +1,100 @@
+//! On DMG, writing STAT briefly enables the HBlank, VBlank and LYC interrupt sources
+
+use gb_core::gameboy::ppu::{registers::STAT, Ppu};
+use gb_cpu::CpuOutputPins;
+
+struct Harness {
+    ppu: Ppu,
+    t_cycles: usize,
+    interrupt_request: u8,
+}
+
+impl Harness {
+    fn new() -> Self {
+        let mut harness = Harness {
+            ppu: Ppu::new(),
+            t_cycles: 0,
+            interrupt_request: 0,
+        };
+        // Keep LYC from matching any line
+        harness.write(0xFF45, 200);
+        harness
+    }
+
+    /// Run until the PPU is on `dot` of line `ly`, counting from the start of the first frame
+    fn run_to(&mut self, ly: usize, dot: usize) {
+        while self.t_cycles <= ly * 456 + dot {
+            self.ppu.clock_t_state();
+            self.t_cycles += 1;
+        }
+        self.interrupt_request = 0;
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.ppu.perform_io(
+            CpuOutputPins::Write { addr, data },
+            &mut 0xFF,
+            &mut self.interrupt_request,
+        );
+    }
+
+    fn stat_interrupt(&self) -> bool {
+        self.interrupt_request & 0b10 != 0
+    }
+}
+
+#[test]
+fn write_during_hblank_raises_stat_interrupt() {
+    let mut harness = Harness::new();
+    harness.run_to(10, 300);
+    assert_eq!(harness.ppu.stat.mode(), STAT::MODE_0);
+    harness.write(0xFF41, 0x00);
+    assert!(harness.stat_interrupt());
+    // Only the write itself raises it
+    assert_eq!(harness.ppu.stat.bits() & 0x78, 0);
+    harness.run_to(10, 400);
+    assert!(!harness.stat_interrupt());
+}
+
+#[test]
+fn write_during_vblank_or_lyc_match_raises_stat_interrupt() {
+    let mut harness = Harness::new();
+    harness.run_to(148, 100);
+    harness.write(0xFF41, 0x00);
+    assert!(harness.stat_interrupt());
+
+    // LY=LYC counts in any mode. This is line 20 of the next frame.
+    harness.write(0xFF45, 20);
+    harness.run_to(154 + 20, 100);
+    assert_eq!(harness.ppu.stat.mode(), STAT::MODE_3);
+    harness.write(0xFF41, 0x00);
+    assert!(harness.stat_interrupt());
+}
+
+#[test]
+fn write_during_oam_scan_or_drawing_does_nothing() {
+    let mut harness = Harness::new();
+    for dot in [40, 100] {
+        harness.run_to(30, dot);
+        harness.write(0xFF41, 0x00);
+        assert!(!harness.stat_interrupt(), "dot {}", dot);
+    }
+}
+
+#[test]
+fn blocked_while_stat_line_is_high() {
+    let mut harness = Harness::new();
+    harness.write(0xFF41, STAT::HBLANK_INTERRUPT_ENABLE.bits());
+    harness.run_to(50, 300);
+    harness.write(0xFF41, 0x00);
+    assert!(!harness.stat_interrupt());
+}
+
+#[test]
+fn only_with_the_bug_enabled() {
+    let mut harness = Harness::new();
+    harness.ppu.stat_write_bug = false;
+    harness.run_to(10, 300);
+    harness.write(0xFF41, 0x00);
+    assert!(!harness.stat_interrupt());
+}
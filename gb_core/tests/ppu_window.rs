@@ -0,0 +1,121 @@
+use gb_core::gameboy::ppu::{registers::LCDC, Ppu};
+use gb_cpu::CpuOutputPins;
+
+/// A PPU with the window enabled at the left edge of the screen. Every window tile is dark on its
+/// first row and white elsewhere, so the dark rows of the frame are the lines where the window
+/// drew a multiple of 8 of its own lines.
+struct Window {
+    ppu: Ppu,
+    t_cycles: usize,
+}
+
+impl Window {
+    fn new(wy: u8) -> Self {
+        let mut ppu = Ppu::new();
+        ppu.tile_data[16..18].copy_from_slice(&[0xFF, 0xFF]);
+        ppu.bg_map_2.fill(1);
+        ppu.lcdc = LCDC::LCD_ENABLE
+            | LCDC::BG_ENABLE
+            | LCDC::BG_TILE_DATA_AREA
+            | LCDC::WINDOW_ENABLE
+            | LCDC::WINDOW_TILEMAP_AREA;
+        ppu.bgp = 0b11_10_01_00;
+        ppu.wx = 7;
+        ppu.wy = wy;
+        Window { ppu, t_cycles: 0 }
+    }
+
+    fn run_to(&mut self, ly: usize, dot: usize) {
+        while self.t_cycles < ly * 456 + dot {
+            self.ppu.clock_t_state();
+            self.t_cycles += 1;
+        }
+    }
+
+    /// Write a register as the CPU would
+    fn write(&mut self, addr: u16, data: u8) {
+        self.ppu
+            .perform_io(CpuOutputPins::Write { addr, data }, &mut 0xFF, &mut 0);
+    }
+
+    /// Finish the frame and return its dark rows
+    fn dark_rows(&mut self) -> Vec<usize> {
+        self.run_to(144, 1);
+        let frame = self.ppu.get_frame();
+        (0..144).filter(|&y| frame.row(y)[0] == 3).collect()
+    }
+}
+
+fn every_8th_line(from: usize) -> Vec<usize> {
+    (from..144).step_by(8).collect()
+}
+
+#[test]
+fn window_starts_at_wy() {
+    let mut window = Window::new(40);
+    assert_eq!(window.dark_rows(), every_8th_line(40));
+    let snapshot = window.ppu.debug_snapshot();
+    assert!(snapshot.wy_latch);
+    assert_eq!(snapshot.window_line, 144 - 40);
+}
+
+#[test]
+fn wy_changed_before_match() {
+    let mut window = Window::new(40);
+    window.run_to(20, 100);
+    window.write(0xFF4A, 100);
+    assert!(!window.ppu.debug_snapshot().wy_latch);
+    assert_eq!(window.dark_rows(), every_8th_line(100));
+}
+
+#[test]
+fn wy_changed_after_match() {
+    let mut window = Window::new(40);
+    window.run_to(60, 100);
+    window.write(0xFF4A, 100);
+    assert_eq!(window.dark_rows(), every_8th_line(40));
+}
+
+#[test]
+fn wy_set_to_passed_line() {
+    let mut window = Window::new(200);
+    window.run_to(60, 100);
+    window.write(0xFF4A, 30);
+    assert_eq!(window.dark_rows(), []);
+    assert!(!window.ppu.debug_snapshot().wy_latch);
+}
+
+#[test]
+fn wy_set_to_current_line() {
+    let mut window = Window::new(200);
+    window.run_to(60, 100);
+    window.write(0xFF4A, 60);
+    assert!(window.ppu.debug_snapshot().wy_latch);
+    assert_eq!(window.dark_rows(), every_8th_line(61));
+}
+
+#[test]
+fn window_line_pauses_while_hidden() {
+    let mut window = Window::new(0);
+    window.run_to(4, 0);
+    window.write(0xFF4B, 200);
+    window.run_to(10, 0);
+    assert_eq!(window.ppu.debug_snapshot().window_line, 4);
+    window.write(0xFF4B, 7);
+    // Line 10 draws the 5th line of the window, so its 9th line is on line 14
+    let rows = window.dark_rows();
+    assert_eq!(rows[..2], [0, 14]);
+    assert_eq!(window.ppu.debug_snapshot().window_line, 144 - 6);
+}
+
+#[test]
+fn latch_resets_each_frame() {
+    let mut window = Window::new(200);
+    window.run_to(60, 100);
+    window.write(0xFF4A, 60);
+    window.run_to(154, 0);
+    window.write(0xFF4A, 100);
+    // Start counting from the new frame
+    window.t_cycles = 0;
+    assert_eq!(window.dark_rows(), every_8th_line(100));
+}
@@ -0,0 +1,77 @@
+//! A frontend's whole loop, written against nothing but the prelude
+
+use gb_core::prelude::*;
+
+const START_COUNTER: &[u8] = include_bytes!("fixtures/start_counter.gb");
+
+fn gameboy() -> Gameboy {
+    let mut gameboy = Gameboy::builder()
+        .rom(START_COUNTER.to_vec())
+        .model(Model::Dmg)
+        .accuracy(AccuracyLevel::Fast)
+        .ram_init(RamInit::Zero)
+        .build()
+        .unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    gameboy
+}
+
+/// Hold Start for a few frames, and return the picture afterwards
+fn press_start(gameboy: &mut Gameboy) -> Vec<RgbaColor> {
+    gameboy.joypad.press(Button::Start);
+    gameboy.run_frames(4);
+    gameboy.joypad.release(Button::Start);
+    let frame: &Frame = gameboy.run_frames(4);
+    frame.colors().collect()
+}
+
+#[test]
+fn run_a_game() {
+    let mut gameboy = gameboy();
+    let frames = gameboy.frame_receiver();
+    let before: Vec<RgbaColor> = gameboy.run_frames(10).colors().collect();
+    let after = press_start(&mut gameboy);
+    assert_eq!(after.len(), 160 * 144);
+    assert_ne!(before, after);
+
+    // Frames can also be shown from another thread
+    let latest = frames.latest();
+    assert!(latest.number() > 0);
+    let mut scaled = vec![0; 160 * 144 * 4 * 4];
+    scale::scale_nearest(&latest, 2, &mut scaled).unwrap();
+    let shared: SharedFrame = gameboy.get_frame();
+    assert_eq!(shared.colors().collect::<Vec<_>>(), after);
+
+    let info: FrameInfo = gameboy.ppu.frame_info();
+    assert_eq!(info.number, latest.number());
+    assert!(gameboy.cycles() >= 17 * FRAME_T_CYCLES as u64);
+}
+
+#[test]
+fn saves_and_errors() {
+    let mut gameboy = gameboy();
+    gameboy.run_frames(10);
+    // start_counter.gb has no battery-backed RAM, so there is never anything to save
+    let dirty: Option<Vec<SaveBlock>> = gameboy.take_dirty_save_blocks();
+    assert!(dirty.is_none());
+    assert!(gameboy.cart.ram().is_none());
+
+    let missing: Result<Gameboy, GbError> = GameboyBuilder::new().build();
+    assert!(missing.is_err());
+}
+
+#[test]
+fn savestates() {
+    let mut gameboy = gameboy();
+    gameboy.run_frames(10);
+    let state: Vec<u8> = gameboy.save_state();
+    let info: StateInfo = state_info(&state).unwrap();
+    assert!(info.matches(&gameboy));
+
+    let before = press_start(&mut gameboy);
+    gameboy.load_state(&state).unwrap();
+    assert_eq!(press_start(&mut gameboy), before);
+
+    let garbage: Result<(), StateError> = gameboy.load_state(b"not a state");
+    assert_eq!(garbage, Err(StateError::NotAState));
+}
@@ -0,0 +1,151 @@
+//! `fixtures/printer.bin` is the bytes a game sends to print a 160x32 image: an init packet, a
+//! band of plain data, a band of compressed data, an empty data packet, a print packet with 1
+//! sheet, margins $13, palette $E4 and exposure $40, then 12 status packets.
+
+use std::sync::{Arc, Mutex};
+
+use gb_core::gameboy::{
+    cart::header::{flat_rom, update_checksums},
+    serial::printer::{GbPrinter, PrintedImage, PrinterStatus},
+    GameboyBuilder, SerialConnection,
+};
+use gb_cpu::assembler::assemble;
+
+const STREAM: &[u8] = include_bytes!("fixtures/printer.bin");
+
+/// A printer, and the images it has printed
+fn printer() -> (GbPrinter, Arc<Mutex<Vec<PrintedImage>>>) {
+    let printed = Arc::new(Mutex::new(vec![]));
+    let sink = Arc::clone(&printed);
+    let printer = GbPrinter::new(move |image| sink.lock().unwrap().push(image));
+    (printer, printed)
+}
+
+/// The status sent back at the end of each packet, which follows the $81 in place of the first of
+/// the two zeros
+fn statuses(replies: &[u8]) -> Vec<u8> {
+    let ends: Vec<usize> = (0..replies.len()).filter(|&i| replies[i] == 0x81).collect();
+    assert_eq!(
+        replies.iter().filter(|&&byte| byte != 0).count(),
+        // Every status is nonzero after the first data packet
+        ends.len() * 2 - 2,
+    );
+    ends.iter().map(|&i| replies[i + 1]).collect()
+}
+
+/// FNV-1a of the shades, row by row
+fn checksum(image: &PrintedImage) -> u32 {
+    image.rows().flatten().fold(0x811C9DC5, |hash, &shade| {
+        (hash ^ shade as u32).wrapping_mul(0x01000193)
+    })
+}
+
+fn check_image(image: &PrintedImage) {
+    assert_eq!((image.width(), image.height()), (160, 32));
+    assert_eq!(
+        (image.sheets, image.margins, image.palette, image.exposure),
+        (1, 0x13, 0xE4, 0x40)
+    );
+    assert_eq!(checksum(image), 0x2E0BD7E1);
+}
+
+#[test]
+fn captured_stream_prints() {
+    let (mut printer, printed) = printer();
+    let replies: Vec<u8> = STREAM.iter().map(|&byte| printer.exchange(byte)).collect();
+
+    // Init, 3 data packets and print, then printing for 8 status packets and done for the rest
+    let mut expected = vec![0x00, 0x00, 0x08, 0x08, 0x08];
+    expected.extend([0x06; 8]);
+    expected.extend([0x04; 4]);
+    assert_eq!(statuses(&replies), expected);
+    assert_eq!(printer.status(), PrinterStatus::IMAGE_DATA_FULL);
+
+    let printed = printed.lock().unwrap();
+    assert_eq!(printed.len(), 1);
+    check_image(&printed[0]);
+}
+
+#[test]
+fn wrong_checksum_is_reported_and_ignored() {
+    let (mut printer, printed) = printer();
+    // The print packet's checksum is wrong
+    let print = STREAM
+        .windows(4)
+        .position(|window| window == [0x88, 0x33, 0x02, 0x00])
+        .unwrap();
+    let mut stream = STREAM[..print + 14].to_vec();
+    stream[print + 11] ^= 1;
+    let replies: Vec<u8> = stream.iter().map(|&byte| printer.exchange(byte)).collect();
+    assert_eq!(replies[replies.len() - 1], 0x09);
+    assert!(printed.lock().unwrap().is_empty());
+
+    // It still has the image, and printing it again works
+    for &byte in &STREAM[print..print + 14] {
+        printer.exchange(byte);
+    }
+    assert_eq!(
+        printer.status(),
+        PrinterStatus::PRINTING | PrinterStatus::IMAGE_DATA_FULL
+    );
+    check_image(&printed.lock().unwrap()[0]);
+}
+
+#[test]
+fn noise_between_packets_is_skipped() {
+    let (mut printer, printed) = printer();
+    for &byte in [0x00, 0x88, 0x88, 0x12, 0x33].iter().chain(STREAM) {
+        printer.exchange(byte);
+    }
+    check_image(&printed.lock().unwrap()[0]);
+}
+
+/// Sends the `LEN` bytes at $1000 over the link cable with the internal clock, storing the replies
+/// from $C000
+const SEND: &str = "
+    .org $0150
+        ld sp, $DFFE
+        ld hl, $1000
+        ld de, $C000
+        ld bc, LEN
+    send:
+        ld a, [hl+]
+        ldh [$01], a
+        ld a, $81
+        ldh [$02], a
+    wait:
+        ldh a, [$02]
+        bit 7, a
+        jr nz, wait
+        ldh a, [$01]
+        ld [de], a
+        inc de
+        dec bc
+        ld a, b
+        or c
+        jr nz, send
+    done:
+        jr done
+";
+
+#[test]
+fn printing_from_a_game() {
+    let source = format!("LEN equ {}\n{}", STREAM.len(), SEND);
+    let mut rom = flat_rom(&assemble(&source).unwrap(), 0x0150, "PRINTER").unwrap();
+    rom[0x1000..0x1000 + STREAM.len()].copy_from_slice(STREAM);
+    update_checksums(&mut rom);
+    let (printer, printed) = printer();
+    let mut gameboy = GameboyBuilder::new()
+        .rom(rom)
+        .serial(Box::new(printer))
+        .build()
+        .unwrap();
+    // Each byte takes 1024 M-cycles, so a frame fits about 17
+    gameboy.run_frames(STREAM.len() as u32 / 17 + 2);
+
+    let replies: Vec<u8> = (0..STREAM.len())
+        .map(|i| gameboy.peek(0xC000 + i as u16))
+        .collect();
+    assert_eq!(statuses(&replies)[4..7], [0x08, 0x06, 0x06]);
+    check_image(&printed.lock().unwrap()[0]);
+}
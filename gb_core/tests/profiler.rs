@@ -0,0 +1,78 @@
+use gb_core::gameboy::{cart::header, Gameboy, ResetKind};
+
+#[test]
+fn tight_loop_dominates() {
+    #[rustfmt::skip]
+    let mut gameboy = Gameboy::with_program(&[
+        0x21, 0x00, 0xC0, // LD HL, $C000
+        0x34,             // INC (HL)
+        0x18, 0xFD,       // JR -3
+    ], 0x0150).unwrap();
+    assert!(gameboy.profile_report(10).is_empty());
+
+    gameboy.enable_profiling(true);
+    gameboy.run_frames(2);
+    let report = gameboy.profile_report(10);
+
+    let addresses: Vec<u16> = report.iter().take(2).map(|e| e.address).collect();
+    assert_eq!(addresses, [0x0153, 0x0154]);
+    // INC (HL) takes 3 M-cycles, and JR takes 3
+    let (inc, jr) = (report[0], report[1]);
+    assert_eq!(inc.bank, Some(0));
+    assert!(inc.hits.abs_diff(jr.hits) <= 1);
+    assert!(inc.cycles.abs_diff(inc.hits as u64 * 3) <= 3);
+    assert!(inc.cycle_share + jr.cycle_share > 0.99);
+
+    let total: f64 = report.iter().map(|e| e.cycle_share).sum();
+    assert!((total - 1.0).abs() < 1e-9);
+    assert_eq!(gameboy.profile_report(1).len(), 1);
+
+    // Counts are kept while profiling is off
+    gameboy.enable_profiling(false);
+    gameboy.run_frames(1);
+    assert_eq!(gameboy.profile_report(10), report);
+
+    gameboy.reset_profile();
+    assert!(gameboy.profile_report(10).is_empty());
+}
+
+/// Calls the same address in two ROM banks, which have different code there
+#[test]
+fn banks_are_profiled_separately() {
+    #[rustfmt::skip]
+    let mut rom = header::flat_rom(&[
+        0x3E, 0x02,       // LD A, 2
+        0xEA, 0x00, 0x20, // LD ($2000), A
+        0xCD, 0x00, 0x40, // CALL $4000
+        0x3E, 0x03,       // LD A, 3
+        0xEA, 0x00, 0x20, // LD ($2000), A
+        0xCD, 0x00, 0x40, // CALL $4000
+        0x18, 0xEE,       // JR -18
+    ], 0x0150, "").unwrap();
+    rom[0x147] = 0x01; // MBC1
+    rom[0x148] = 0x01; // 64KiB
+    rom.resize(0x10000, 0);
+    rom[0x8000] = 0xC9; // Bank 2: RET
+    rom[0xC000..0xC002].copy_from_slice(&[0x00, 0xC9]); // Bank 3: NOP, RET
+
+    let mut gameboy = Gameboy::new(rom).unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    gameboy.enable_profiling(true);
+    gameboy.run_frames(1);
+
+    let report = gameboy.profile_report(usize::MAX);
+    let find = |address: u16, bank: u16| {
+        report
+            .iter()
+            .find(|e| (e.address, e.bank) == (address, Some(bank)))
+            .copied()
+    };
+    let ret_2 = find(0x4000, 2).unwrap();
+    let nop_3 = find(0x4000, 3).unwrap();
+    let ret_3 = find(0x4001, 3).unwrap();
+    assert!(ret_2.hits.abs_diff(nop_3.hits) <= 1);
+    assert_eq!(nop_3.cycles, nop_3.hits as u64);
+    assert!(ret_3.cycles.abs_diff(ret_3.hits as u64 * 4) <= 4);
+    assert!(find(0x4001, 2).is_none());
+    assert!(report.iter().all(|e| e.bank != Some(1)));
+}
@@ -0,0 +1,87 @@
+use gb_core::gameboy::{cart::header, memory::RamInit, Gameboy, ResetKind};
+
+/// Every address whose power on contents [`RamInit`] decides: VRAM, work RAM, OAM and high RAM
+fn all_ram(gameboy: &Gameboy) -> Vec<u8> {
+    (0x8000..=0x9FFF)
+        .chain(0xC000..=0xDFFF)
+        .chain(0xFE00..=0xFE9F)
+        .chain(0xFF80..=0xFFFE)
+        .map(|addr| gameboy.peek(addr))
+        .collect()
+}
+
+fn built_with(init: RamInit) -> Gameboy {
+    let rom = header::flat_rom(&[0x18, 0xFE], 0x0150, "").unwrap(); // JR -2
+    Gameboy::builder().rom(rom).ram_init(init).build().unwrap()
+}
+
+fn ram_after(init: RamInit) -> Vec<u8> {
+    let mut gameboy = Gameboy::with_program(&[0x18, 0xFE], 0x0150).unwrap(); // JR -2
+    gameboy.set_ram_init(init);
+    (0xC000..=0xDFFF)
+        .chain(0xFF80..=0xFFFE)
+        .map(|addr| gameboy.peek(addr))
+        .collect()
+}
+
+#[test]
+fn zero_by_default() {
+    let gameboy = Gameboy::with_program(&[0x18, 0xFE], 0x0150).unwrap();
+    assert!((0xC000..=0xDFFF).all(|addr| gameboy.peek(addr) == 0));
+    assert!(ram_after(RamInit::Zero).iter().all(|&b| b == 0));
+}
+
+#[test]
+fn nintendo_pattern() {
+    let ram = ram_after(RamInit::Nintendo);
+    assert_eq!(
+        ram[..16],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]
+    );
+    assert_eq!(ram[0x1FF0..0x2000], ram[..16]);
+}
+
+#[test]
+fn random_is_seeded() {
+    let ram = ram_after(RamInit::Random(1));
+    assert_eq!(ram, ram_after(RamInit::Random(1)));
+    assert_ne!(ram, ram_after(RamInit::Random(2)));
+    // SplitMix64 with seed 1, so that the sequence can't change between platforms or releases
+    assert_eq!(ram[..8], 0x910A_2DEC_8902_5CC1u64.to_le_bytes());
+    // Roughly half of the bits are set
+    let ones: u32 = ram.iter().map(|b| b.count_ones()).sum();
+    assert!((30_000..35_000).contains(&ones), "{} bits set", ones);
+}
+
+#[test]
+fn builder_fills_vram_and_oam() {
+    let gameboy = built_with(RamInit::Nintendo);
+    let pattern = [
+        0, 0, 0, 0, 0, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    ];
+    for start in [0x8000, 0x9FF0, 0xC000, 0xFE00, 0xFE90] {
+        let bytes: Vec<u8> = (start..start + 16).map(|addr| gameboy.peek(addr)).collect();
+        assert_eq!(bytes, pattern, "at ${:04X}", start);
+    }
+    assert!(all_ram(&built_with(RamInit::Zero)).iter().all(|&b| b == 0));
+}
+
+#[test]
+fn same_seed_gives_identical_instances() {
+    let ram = all_ram(&built_with(RamInit::Random(0x1234)));
+    assert_eq!(ram, all_ram(&built_with(RamInit::Random(0x1234))));
+    // Each memory gets its own part of the sequence
+    assert_ne!(ram[..0x100], ram[0x2000..0x2100]);
+    // Building with a seed is the same as setting it straight after building
+    let mut gameboy = built_with(RamInit::Zero);
+    gameboy.set_ram_init(RamInit::Random(0x1234));
+    assert_eq!(ram, all_ram(&gameboy));
+}
+
+#[test]
+fn post_boot_init_keeps_power_on_contents() {
+    let mut gameboy = built_with(RamInit::Random(7));
+    let ram = all_ram(&gameboy);
+    gameboy.reset(ResetKind::PowerCycle);
+    assert_eq!(ram, all_ram(&gameboy));
+}
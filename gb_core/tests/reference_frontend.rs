@@ -0,0 +1,149 @@
+//! Runs `examples/reference_frontend.rs` end to end, as a check that the APIs it wires together
+//! still fit.
+
+#[allow(dead_code)]
+#[path = "../examples/reference_frontend.rs"]
+mod reference_frontend;
+
+use std::{fs, path::PathBuf, sync::mpsc};
+
+use gb_core::gameboy::{
+    cart::header::{flat_rom, update_checksums},
+    savestate::state_info,
+};
+use gb_cpu::assembler::assemble;
+use reference_frontend::{parse_input, run, Command, Exit, Options};
+
+const PPU_SCENE: &[u8] = include_bytes!("fixtures/ppu_scene.gb");
+
+/// Counts boots in battery-backed RAM and sends the count as a digit over the link cable. Then it
+/// waits for Start, sends "S", and locks up on an illegal opcode.
+const BOOT_COUNTER: &str = "
+    .org $0150
+        ld a, $0A
+        ld [$0000], a
+        ld hl, $A000
+        inc [hl]
+        ld a, [hl]
+        add $30
+        call send
+        ld a, $10
+        ldh [$00], a
+    wait:
+        ldh a, [$00]
+        bit 3, a
+        jr nz, wait
+        ld a, $53
+        call send
+        .db $D3
+
+    send:
+        ldh [$01], a
+        ld a, $81
+        ldh [$02], a
+    busy:
+        ldh a, [$02]
+        bit 7, a
+        jr nz, busy
+        ret
+";
+
+/// 64-bit FNV-1a
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xCBF2_9CE4_8422_2325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
+
+/// A fresh directory for one test's files
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "gb_reference_frontend_{}_{}",
+        std::process::id(),
+        name
+    ));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Run the frontend uncapped with nothing typed on stdin, and return how it ended and what it
+/// wrote to stdout
+fn run_uncapped(options: &mut Options) -> (Exit, String) {
+    options.speed = f64::INFINITY;
+    let (_, stdin) = mpsc::channel();
+    let mut out = Vec::new();
+    let exit = run(options, &stdin, &mut out).unwrap();
+    (exit, String::from_utf8(out).unwrap())
+}
+
+#[test]
+fn screenshots_of_a_fixture() {
+    let dir = scratch("screenshots");
+    let rom = dir.join("ppu_scene.gb");
+    fs::write(&rom, PPU_SCENE).unwrap();
+    let mut options = Options::new(&rom);
+    options.frames = Some(300);
+    options.screenshots = Some(dir.join("shots"));
+    options.screenshot_every = 100;
+
+    let (exit, out) = run_uncapped(&mut options);
+    assert!(matches!(exit, Exit::Finished), "{:?}", exit);
+    assert!(out.is_empty());
+    // The ROM has no battery, so nothing is saved
+    assert!(!rom.with_extension("sav").exists());
+
+    // The scene is redrawn the same way every frame once it is set up
+    for frame in [100, 200, 300] {
+        let image = fs::read(dir.join(format!("shots/frame_{:05}.ppm", frame))).unwrap();
+        assert!(image.starts_with(b"P6\n160 144\n255\n"));
+        assert_eq!(fnv1a(&image), 0x57A7_A70F_A87D_D105, "frame {}", frame);
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn saves_serial_input_and_lock_ups() {
+    let dir = scratch("saves");
+    let mut rom = flat_rom(&assemble(BOOT_COUNTER).unwrap(), 0x0150, "BOOTS").unwrap();
+    rom[0x147] = 0x03; // MBC1+RAM+BATTERY
+    rom[0x149] = 0x02; // 8KiB
+    update_checksums(&mut rom);
+    let rom_path = dir.join("boots.gb");
+    fs::write(&rom_path, rom).unwrap();
+    let input = dir.join("input.txt");
+    fs::write(
+        &input,
+        "# Save, then press Start\n20 press Start\n10 save 4\n",
+    )
+    .unwrap();
+
+    for boot in 1..=2 {
+        let mut options = Options::new(&rom_path);
+        options.input = Some(input.clone());
+        let (exit, out) = run_uncapped(&mut options);
+        assert!(matches!(exit, Exit::LockedUp(_)), "{:?}", exit);
+        assert_eq!(out, format!("{}S", boot));
+        // Saved on the way out, and loaded on the next boot
+        assert_eq!(fs::read(rom_path.with_extension("sav")).unwrap()[0], boot);
+    }
+    let state = fs::read(rom_path.with_extension("ss4")).unwrap();
+    assert_eq!(state_info(&state).unwrap().title, "BOOTS");
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn input_files() {
+    let commands = parse_input("5 release A # comment\n\n2 press A\n2 quit\n").unwrap();
+    assert_eq!(
+        commands,
+        [
+            (2, "press A".parse().unwrap()),
+            (2, Command::Quit),
+            (5, "release A".parse().unwrap()),
+        ]
+    );
+    assert!(parse_input("press A").is_err());
+    assert!(parse_input("1 save 10").is_err());
+    assert!(parse_input("1 press X").is_err());
+}
@@ -0,0 +1,175 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex,
+};
+
+use gb_core::gameboy::{
+    breakpoints::Breakpoint, cart::header::flat_rom, memory::RamInit, ppu::consts::FRAME_T_CYCLES,
+    test_pattern, Gameboy, GameboyBuilder, ResetKind,
+};
+use gb_cpu::assembler::assemble;
+
+/// Sets up the mapper, the timer and an OAM DMA, then keeps copying DIV to WRAM and TIMA to
+/// cartridge RAM
+const BUSY: &str = "
+    DIV equ $FF04
+    TIMA equ $FF05
+    TAC equ $FF07
+    DMA equ $FF46
+
+    .org $0150
+        ld a, $0A
+        ld [$0000], a
+        ld a, 3
+        ld [$2000], a
+        ld a, $05
+        ldh [TAC], a
+        ld a, $C0
+        ldh [DMA], a
+        ld hl, $C100
+    loop:
+        ldh a, [DIV]
+        ld [hl+], a
+        ldh a, [TIMA]
+        ld [$A000], a
+        ld a, h
+        cp $D0
+        jr nz, loop
+        ld hl, $C100
+        jr loop
+";
+
+/// A 64KiB MBC1 cartridge with 8KiB of battery-backed RAM, running [`BUSY`]
+fn busy_rom() -> Vec<u8> {
+    let mut rom = flat_rom(&assemble(BUSY).unwrap(), 0x0150, "").unwrap();
+    rom.resize(0x10000, 0);
+    rom[0x147] = 0x03;
+    rom[0x148] = 0x01;
+    rom[0x149] = 0x02;
+    rom
+}
+
+fn build(rom: Vec<u8>) -> Gameboy {
+    let mut gameboy = GameboyBuilder::new()
+        .rom(rom)
+        .ram_init(RamInit::Random(7))
+        .build()
+        .unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    gameboy
+}
+
+/// Everything that can be seen from outside, apart from cartridge RAM, is the same
+fn assert_same_state(gameboy: &Gameboy, fresh: &Gameboy) {
+    assert_eq!(gameboy.cpu.cpu.registers, fresh.cpu.cpu.registers);
+    assert_eq!(gameboy.cycles(), fresh.cycles());
+    assert_eq!(gameboy.ppu.frame_count, fresh.ppu.frame_count);
+    assert_eq!(gameboy.ppu.ly, fresh.ppu.ly);
+    assert_eq!(gameboy.ppu.stat.bits(), fresh.ppu.stat.bits());
+    assert_eq!(gameboy.ppu.dma_active(), fresh.ppu.dma_active());
+    assert_eq!(gameboy.cart.rom_bank(), fresh.cart.rom_bank());
+    for addr in (0..=0xFFFF).filter(|addr| !(0xA000..=0xBFFF).contains(addr)) {
+        assert_eq!(gameboy.peek(addr), fresh.peek(addr), "${:04X}", addr);
+    }
+}
+
+#[test]
+fn power_cycle_matches_a_fresh_gameboy() {
+    let mut gameboy = build(busy_rom());
+    let lines = Arc::new(AtomicUsize::new(0));
+    let counter = lines.clone();
+    gameboy.on_scanline(move |_, _| {
+        counter.fetch_add(1, Ordering::Relaxed);
+    });
+    gameboy.add_breakpoint(Breakpoint::at(0x4000));
+    gameboy.run_frames(5);
+    // Run the setup again and stop in the middle of the DMA, to check it doesn't carry on
+    gameboy.cpu.cpu.registers.pc = 0x0150;
+    let dma_started = gameboy.add_breakpoint(Breakpoint::at(0x0162));
+    assert_eq!(
+        gameboy.run_until_breakpoint(FRAME_T_CYCLES as u64),
+        Some(dma_started)
+    );
+    gameboy.remove_breakpoint(dma_started);
+    assert!(gameboy.ppu.dma_active());
+    let sram = gameboy.cart.ram().unwrap().to_vec();
+    assert_ne!(sram[0], 0);
+
+    gameboy.reset(ResetKind::PowerCycle);
+    let mut fresh = build(busy_rom());
+    assert_same_state(&gameboy, &fresh);
+    assert_eq!(gameboy.cart.ram().unwrap(), &sram[..]);
+    assert_eq!(gameboy.breakpoints().count(), 1);
+
+    lines.store(0, Ordering::Relaxed);
+    gameboy.run_frames(3);
+    fresh.run_frames(3);
+    assert_same_state(&gameboy, &fresh);
+    assert!(gameboy.get_frame().iter().eq(fresh.get_frame().iter()));
+    // Only the last frame is drawn
+    assert_eq!(lines.load(Ordering::Relaxed), 144);
+}
+
+#[test]
+fn soft_reset_keeps_memory() {
+    let mut gameboy = build(busy_rom());
+    gameboy.run_frames(2);
+    let wram: Vec<u8> = (0xC000..=0xDFFF).map(|addr| gameboy.peek(addr)).collect();
+    let vram: Vec<u8> = (0x8000..=0x9FFF).map(|addr| gameboy.peek(addr)).collect();
+
+    gameboy.reset(ResetKind::Soft);
+    let fresh = build(busy_rom());
+    assert_eq!(gameboy.cpu.cpu.registers, fresh.cpu.cpu.registers);
+    assert_eq!(gameboy.cycles(), fresh.cycles());
+    assert_eq!(gameboy.cart.rom_bank(), 1);
+    assert!((0xC000..=0xDFFF).map(|addr| gameboy.peek(addr)).eq(wram));
+    assert!((0x8000..=0x9FFF).map(|addr| gameboy.peek(addr)).eq(vram));
+    assert_ne!(gameboy.peek(0xC100), fresh.peek(0xC100));
+}
+
+#[test]
+fn reset_maps_the_boot_rom_again() {
+    // LD A, 1; LDH ($50), A
+    let mut boot_rom = vec![0x3E, 0x01, 0xE0, 0x50];
+    boot_rom.resize(0x100, 0);
+    let mut gameboy = GameboyBuilder::new()
+        .rom(flat_rom(&[0x18, 0xFE], 0x0150, "").unwrap())
+        .boot_rom(boot_rom)
+        .build()
+        .unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    assert_eq!(gameboy.peek(0x0000), 0x3E);
+    gameboy.run_frames(1);
+    assert_eq!(gameboy.peek(0x0000), 0x00);
+
+    gameboy.reset(ResetKind::PowerCycle);
+    assert_eq!(gameboy.peek(0x0000), 0x3E);
+    assert_eq!(gameboy.cpu.cpu.registers.pc, 0x0000);
+}
+
+#[test]
+fn swapping_cartridges() {
+    let mut gameboy = build(busy_rom());
+    let saves = Arc::new(Mutex::new(Vec::new()));
+    let writer_saves = saves.clone();
+    gameboy.set_save_writer(1000, move |save| {
+        writer_saves.lock().unwrap().push(save.to_vec())
+    });
+    gameboy.run_frames(2);
+    let sram = gameboy.cart.ram().unwrap().to_vec();
+
+    assert!(gameboy.swap_cartridge(&[0; 0x100]).is_err());
+    assert_eq!(gameboy.cart.ram().unwrap(), &sram[..]);
+    assert!(saves.lock().unwrap().is_empty());
+
+    gameboy.swap_cartridge(&test_pattern::rom()).unwrap();
+    // The old game was saved one last time
+    assert_eq!(*saves.lock().unwrap(), vec![sram]);
+    assert!(gameboy.cart.ram().is_none());
+
+    let mut fresh = build(test_pattern::rom());
+    assert_same_state(&gameboy, &fresh);
+    gameboy.run_frames(3);
+    fresh.run_frames(3);
+    assert!(gameboy.get_frame().iter().eq(fresh.get_frame().iter()));
+}
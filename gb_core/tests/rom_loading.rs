@@ -0,0 +1,171 @@
+use std::sync::Arc;
+
+use gb_core::{
+    gameboy::{cart::header, test_pattern, Gameboy, ResetKind},
+    GbError,
+};
+
+/// Build a blank 32KB ROM with the given cartridge type and ROM size header bytes
+fn rom_with_header(cart_type: u8, rom_size: u8) -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    rom[0x147] = cart_type;
+    rom[0x148] = rom_size;
+    rom
+}
+
+#[test]
+fn valid_rom() {
+    assert!(Gameboy::new(rom_with_header(0x00, 0x00)).is_ok());
+    assert!(Gameboy::new(rom_with_header(0x01, 0x00)).is_ok());
+}
+
+#[test]
+fn one_byte_rom() {
+    assert!(matches!(
+        Gameboy::new(vec![0x00]),
+        Err(GbError::InvalidRom(_))
+    ));
+}
+
+#[test]
+fn empty_rom() {
+    assert!(matches!(Gameboy::new(vec![]), Err(GbError::InvalidRom(_))));
+}
+
+#[test]
+fn header_claims_8mb() {
+    for cart_type in [0x00, 0x01, 0x02, 0x03] {
+        assert!(
+            matches!(
+                Gameboy::new(rom_with_header(cart_type, 0x08)),
+                Err(GbError::InvalidRom(_))
+            ),
+            "cart type: {:#04X}",
+            cart_type
+        );
+    }
+}
+
+#[test]
+fn unknown_rom_size() {
+    assert!(matches!(
+        Gameboy::new(rom_with_header(0x00, 0xFF)),
+        Err(GbError::InvalidRom(_))
+    ));
+}
+
+#[test]
+fn unknown_mapper() {
+    assert_eq!(
+        Gameboy::new(rom_with_header(0xFD, 0x00)).err(),
+        Some(GbError::UnsupportedMapper(0xFD))
+    );
+}
+
+#[test]
+fn oversized_rom_only_cart() {
+    let mut rom = rom_with_header(0x00, 0x00);
+    rom.resize(0x10000, 0);
+    assert!(matches!(Gameboy::new(rom), Err(GbError::InvalidRom(_))));
+}
+
+#[test]
+fn full_size_mbc1() {
+    let mut rom = rom_with_header(0x01, 0x06);
+    rom.resize(0x200000, 0);
+    assert!(Gameboy::new(rom).is_ok());
+}
+
+#[test]
+fn memory_out_of_range() {
+    let mut gameboy = Gameboy::new(rom_with_header(0x00, 0x00)).unwrap();
+    assert_eq!(
+        gameboy.memory.read(0x0000),
+        Err(GbError::AddressOutOfRange(0x0000))
+    );
+    assert_eq!(gameboy.memory.write(0xC000, 0x12), Ok(()));
+    assert_eq!(gameboy.memory.read(0xC000), Ok(0x12));
+    assert!(gameboy.ppu.oam(39).is_ok());
+    assert!(gameboy.ppu.oam(40).is_err());
+}
+
+#[test]
+fn save_ram_round_trip() {
+    let mut gameboy = Gameboy::new(rom_with_header(0x02, 0x00)).unwrap();
+    assert_eq!(gameboy.cart.ram().map(<[u8]>::len), Some(0x2000));
+
+    let save: Vec<u8> = (0..0x2000).map(|i| i as u8).collect();
+    gameboy.cart.load_ram(&save).unwrap();
+    assert_eq!(gameboy.cart.ram(), Some(&save[..]));
+
+    assert!(matches!(
+        gameboy.cart.load_ram(&save[..0x100]),
+        Err(GbError::InvalidSaveData(_))
+    ));
+}
+
+#[test]
+fn save_ram_without_ram() {
+    let mut gameboy = Gameboy::new(rom_with_header(0x01, 0x00)).unwrap();
+    assert_eq!(gameboy.cart.ram(), None);
+    assert!(matches!(
+        gameboy.cart.load_ram(&[0; 0x2000]),
+        Err(GbError::InvalidSaveData(_))
+    ));
+}
+
+/// The header claims 4 banks, but the file stops 256 bytes into bank 2
+#[test]
+fn truncated_mbc1_rom() {
+    #[rustfmt::skip]
+    let mut rom = header::flat_rom(&[
+        0x3E, 0x02,       // LD A, 2
+        0xEA, 0x00, 0x20, // LD ($2000), A
+        0xFA, 0xFF, 0x40, // LD A, ($40FF)
+        0xEA, 0x00, 0xC0, // LD ($C000), A
+        0xFA, 0x00, 0x41, // LD A, ($4100)
+        0xEA, 0x01, 0xC0, // LD ($C001), A
+        0x3E, 0x05,       // LD A, 5
+        0xEA, 0x00, 0x20, // LD ($2000), A
+        0xFA, 0x00, 0x40, // LD A, ($4000)
+        0xEA, 0x02, 0xC0, // LD ($C002), A
+        0x3E, 0x03,       // LD A, 3
+        0xEA, 0x00, 0x20, // LD ($2000), A
+        0xFA, 0x00, 0x40, // LD A, ($4000)
+        0xEA, 0x03, 0xC0, // LD ($C003), A
+        0x18, 0xFE,       // JR -2
+    ], 0x0150, "").unwrap();
+    rom[0x147] = 0x01; // MBC1
+    rom[0x148] = 0x01; // 64KiB
+    rom[0x4000] = 0x11;
+    rom.resize(0x8100, 0);
+    rom[0x80FF] = 0x22;
+    let mut gameboy = Gameboy::new(rom).unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    gameboy.run_frames(1);
+
+    // The last byte in the file, and the first one missing
+    assert_eq!(gameboy.peek(0xC000), 0x22);
+    assert_eq!(gameboy.peek(0xC001), 0xFF);
+    // Bank 5 wraps around to bank 1, but bank 3 is missing entirely
+    assert_eq!(gameboy.peek(0xC002), 0x11);
+    assert_eq!(gameboy.peek(0xC003), 0xFF);
+}
+
+#[test]
+fn instances_share_rom() {
+    let rom: Arc<[u8]> = test_pattern::rom().into();
+    let mut first = Gameboy::from_shared_rom(rom.clone()).unwrap();
+    let mut second = Gameboy::builder().rom(rom.clone()).build().unwrap();
+    assert_eq!(Arc::strong_count(&rom), 3);
+
+    first.reset(ResetKind::PowerCycle);
+    second.reset(ResetKind::PowerCycle);
+    first.run_frames(3);
+    second.run_frames(3);
+    assert!(first.get_frame().iter().eq(second.get_frame().iter()));
+    assert_eq!(first.get_frame()[(8, 0)], test_pattern::shade(8, 0));
+
+    drop(first);
+    assert_eq!(Arc::strong_count(&rom), 2);
+}
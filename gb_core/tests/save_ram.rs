@@ -0,0 +1,112 @@
+//! Tracking which parts of battery-backed cartridge RAM need saving
+
+use std::sync::{Arc, Mutex};
+
+use gb_core::gameboy::{cart::header, Gameboy, ResetKind};
+
+/// A ROM of `cart_type` running `code` from $0150
+fn gameboy(cart_type: u8, code: &[u8]) -> Gameboy {
+    let mut rom = header::flat_rom(code, 0x0150, "").unwrap();
+    rom[0x147] = cart_type;
+    // 8KiB of RAM
+    rom[0x149] = 0x02;
+    header::update_checksums(&mut rom);
+    let mut gameboy = Gameboy::new(rom).unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    gameboy
+}
+
+/// Counts frames by waiting for VBlank, and writes $42 to $A010 and $99 to $A1FF on the 10th
+#[rustfmt::skip]
+const SAVE_ON_FRAME_10: [u8; 37] = [
+    0x3E, 0x0A,       // LD A, $0A
+    0xEA, 0x00, 0x00, // LD ($0000), A
+    0x06, 0x00,       // LD B, 0
+    // loop:
+    0xF0, 0x44,       // LDH A, (LY)
+    0xFE, 0x90,       // CP 144
+    0x20, 0xFA,       // JR NZ, loop
+    0xF0, 0x44,       // LDH A, (LY)
+    0xFE, 0x90,       // CP 144
+    0x28, 0xFA,       // JR Z, -6
+    0x04,             // INC B
+    0x78,             // LD A, B
+    0xFE, 0x0A,       // CP 10
+    0x20, 0xEE,       // JR NZ, loop
+    0x3E, 0x42,       // LD A, $42
+    0xEA, 0x10, 0xA0, // LD ($A010), A
+    0x3E, 0x99,       // LD A, $99
+    0xEA, 0xFF, 0xA1, // LD ($A1FF), A
+    0x18, 0xE2,       // JR loop
+];
+
+#[test]
+fn save_writer_fires_once_per_save() {
+    let mut gameboy = gameboy(0x03, &SAVE_ON_FRAME_10);
+    let saves = Arc::new(Mutex::new(Vec::new()));
+    let writer_saves = saves.clone();
+    gameboy.set_save_writer(30, move |save| {
+        writer_saves.lock().unwrap().push(save.to_vec())
+    });
+    gameboy.run_frames(120);
+
+    let saves = saves.lock().unwrap();
+    assert_eq!(saves.len(), 1);
+    assert_eq!(saves[0].len(), 0x2000);
+    assert_eq!(saves[0][0x010], 0x42);
+    assert_eq!(saves[0][0x1FF], 0x99);
+    assert_eq!(saves[0].iter().filter(|&&b| b != 0).count(), 2);
+}
+
+#[test]
+fn dirty_blocks_are_taken_once() {
+    let mut gameboy = gameboy(0x03, &SAVE_ON_FRAME_10);
+    gameboy.run_frames(5);
+    assert_eq!(gameboy.take_dirty_save_blocks(), None);
+    gameboy.run_frames(10);
+
+    let blocks = gameboy.take_dirty_save_blocks().unwrap();
+    let offsets: Vec<usize> = blocks.iter().map(|&(offset, _)| offset).collect();
+    assert_eq!(offsets, [0x000, 0x100]);
+    assert_eq!(blocks[0].1[0x10], 0x42);
+    assert_eq!(blocks[1].1[0xFF], 0x99);
+    assert_eq!(gameboy.take_dirty_save_blocks(), None);
+}
+
+#[test]
+fn loading_clears_dirty_blocks() {
+    let mut gameboy = gameboy(0x03, &SAVE_ON_FRAME_10);
+    gameboy.run_frames(15);
+    assert!(gameboy.cart.is_save_dirty());
+    gameboy.cart.load_ram(&[0x11; 0x2000]).unwrap();
+    assert!(!gameboy.cart.is_save_dirty());
+    assert_eq!(gameboy.take_dirty_save_blocks(), None);
+}
+
+#[test]
+fn unchanged_ram_is_not_dirty() {
+    #[rustfmt::skip]
+    let mut gameboy = gameboy(0x03, &[
+        0x3E, 0x0A,       // LD A, $0A
+        0xEA, 0x00, 0x00, // LD ($0000), A
+        0xAF,             // XOR A
+        0xEA, 0x00, 0xA0, // LD ($A000), A
+        0xEA, 0x00, 0x00, // LD ($0000), A
+        0x3C,             // INC A
+        0xEA, 0x00, 0xA1, // LD ($A100), A
+        0x18, 0xFE,       // JR -2
+    ]);
+    gameboy.run_frames(1);
+    // Rewriting the same value and writing with RAM disabled change nothing
+    assert!(!gameboy.cart.is_save_dirty());
+}
+
+#[test]
+fn ram_without_battery_is_not_tracked() {
+    let mut gameboy = gameboy(0x02, &SAVE_ON_FRAME_10);
+    gameboy.set_save_writer(1, |_| panic!("nothing to save"));
+    gameboy.run_frames(15);
+    assert_eq!(gameboy.cart.ram().unwrap()[0x10], 0x42);
+    assert!(!gameboy.cart.has_battery());
+    assert_eq!(gameboy.take_dirty_save_blocks(), None);
+}
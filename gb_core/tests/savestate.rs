@@ -0,0 +1,282 @@
+//! Savestates round trip, and the states in `fixtures/states` keep loading. Those were saved by
+//! the version of the emulator that added each type of cartridge to the format, and must never be
+//! changed; a missing one is only written when `GOLDEN_UPDATE` is set.
+
+use std::{convert::TryInto, fs, path::PathBuf};
+
+use gb_core::gameboy::{
+    cart::header::{flat_rom, update_checksums},
+    ppu::consts::FRAME_T_CYCLES,
+    rtc::{FixedClock, RtcRegisters, RtcState},
+    savestate::{state_info, StateError, FORMAT_VERSION, MAGIC},
+    Gameboy, GameboyBuilder,
+};
+use gb_cpu::assembler::assemble;
+
+/// Counts in $C000, $8000 and cartridge RAM, and copies the first byte of the switchable ROM bank
+/// to $C001
+const PROGRAM: &str = "
+    .org $0150
+        ld sp, $DFFE
+        ld a, $0A
+        ld [$0000], a
+        ld a, 2
+        ld [$2100], a
+    loop:
+        ld hl, $C000
+        inc [hl]
+        ld a, [hl]
+        ld [$8000], a
+        ld [$8001], a
+        ld hl, $A000
+        inc [hl]
+        ld a, [$4000]
+        ld [$C001], a
+        jr loop
+";
+
+/// The cartridge types saved in `fixtures/states`, with their RAM size byte
+const CARTS: [(&str, u8, u8); 5] = [
+    ("rom_only", 0x00, 0x00),
+    ("mbc1", 0x03, 0x02),
+    ("mbc2", 0x06, 0x00),
+    ("mbc3", 0x10, 0x02),
+    ("camera", 0xFC, 0x04),
+];
+
+/// A 64KiB cartridge of `cart_type` running [`PROGRAM`], where bank 2 starts with $B2
+fn rom(cart_type: u8, ram_size: u8) -> Vec<u8> {
+    let mut rom = flat_rom(&assemble(PROGRAM).unwrap(), 0x0150, "SAVESTATE").unwrap();
+    if cart_type != 0x00 {
+        rom.resize(0x10000, 0);
+        rom[0x148] = 0x01;
+        rom[0x8000] = 0xB2;
+    }
+    rom[0x147] = cart_type;
+    rom[0x149] = ram_size;
+    update_checksums(&mut rom);
+    rom
+}
+
+fn gameboy(cart_type: u8, ram_size: u8) -> Gameboy {
+    GameboyBuilder::new()
+        .rom(rom(cart_type, ram_size))
+        .rtc(Box::new(FixedClock(1_000_000)))
+        .build()
+        .unwrap()
+}
+
+/// A Gameboy partway through its fourth frame, with the clock set to 1d 2:03:04 if it has one
+fn running(cart_type: u8, ram_size: u8) -> Gameboy {
+    let mut gameboy = gameboy(cart_type, ram_size);
+    gameboy.cart.set_rtc_state(RtcState {
+        live: RtcRegisters {
+            seconds: 4,
+            minutes: 3,
+            hours: 2,
+            days: 1,
+            ..RtcRegisters::default()
+        },
+        ..RtcState::default()
+    });
+    gameboy.run_frames(3);
+    gameboy.run_cycles(12_345);
+    gameboy
+}
+
+/// Where the timestamp in the `INFO` chunk is
+const TIMESTAMP: std::ops::Range<usize> = 18..26;
+
+fn without_timestamp(mut state: Vec<u8>) -> Vec<u8> {
+    state[TIMESTAMP].fill(0);
+    state
+}
+
+fn shades(gameboy: &mut Gameboy, frames: u32) -> Vec<u8> {
+    gameboy.run_frames(frames).iter().copied().collect()
+}
+
+#[test]
+fn states_round_trip() {
+    for (name, cart_type, ram_size) in CARTS {
+        let mut original = running(cart_type, ram_size);
+        let state = original.save_state();
+        let mut loaded = gameboy(cart_type, ram_size);
+        loaded.load_state(&state).unwrap();
+        assert_eq!(
+            without_timestamp(loaded.save_state()),
+            without_timestamp(state.clone()),
+            "{}",
+            name
+        );
+        for _ in 0..3 {
+            assert_eq!(shades(&mut loaded, 1), shades(&mut original, 1), "{}", name);
+            assert_eq!(
+                loaded.save_state()[TIMESTAMP.end..],
+                original.save_state()[TIMESTAMP.end..]
+            );
+        }
+    }
+}
+
+#[test]
+fn loading_rewinds() {
+    let mut gameboy = running(0x03, 0x02);
+    let state = gameboy.save_state();
+    let counter = gameboy.peek(0xC000);
+    gameboy.run_cycles(FRAME_T_CYCLES as u64 / 2);
+    assert_ne!(gameboy.peek(0xC000), counter);
+    gameboy.load_state(&state).unwrap();
+    assert_eq!(gameboy.peek(0xC000), counter);
+    assert_eq!(gameboy.peek(0xC001), 0xB2);
+}
+
+#[test]
+fn info() {
+    let mut gameboy = running(0x10, 0x02);
+    let info = state_info(&gameboy.save_state()).unwrap();
+    assert_eq!(info.version, FORMAT_VERSION);
+    assert_eq!(info.title, "SAVESTATE");
+    assert!(info.matches(&gameboy));
+    assert!(!info.matches(&self::gameboy(0x00, 0x00)));
+}
+
+#[test]
+fn bad_states_change_nothing() {
+    let mut gameboy = running(0x03, 0x02);
+    let state = gameboy.save_state();
+    let before = gameboy.save_state();
+
+    assert_eq!(gameboy.load_state(b"GBSTATE"), Err(StateError::NotAState));
+    let mut newer = state.clone();
+    newer[MAGIC.len()] = FORMAT_VERSION + 1;
+    assert_eq!(
+        gameboy.load_state(&newer),
+        Err(StateError::UnsupportedVersion(FORMAT_VERSION + 1))
+    );
+    assert_eq!(
+        gameboy.load_state(&state[..state.len() - 1]),
+        Err(StateError::Truncated)
+    );
+    let mut newer_chunk = state.clone();
+    newer_chunk[TIMESTAMP.start - 5] = 2;
+    assert_eq!(
+        gameboy.load_state(&newer_chunk),
+        Err(StateError::NewerChunk {
+            chunk: "INFO".into(),
+            version: 2
+        })
+    );
+    assert!(matches!(
+        self::gameboy(0x01, 0x00).load_state(&state),
+        Err(StateError::InvalidChunk { chunk, .. }) if chunk == "MBC"
+    ));
+    let mbc3 = running(0x10, 0x02).save_state();
+    assert!(matches!(
+        self::gameboy(0x10, 0x03).load_state(&mbc3),
+        Err(StateError::InvalidChunk { chunk, .. }) if chunk == "MBC"
+    ));
+    assert_eq!(
+        without_timestamp(gameboy.save_state()),
+        without_timestamp(before)
+    );
+}
+
+/// The chunks of a state, as (ID, version, contents)
+fn chunks(state: &[u8]) -> Vec<([u8; 4], u8, Vec<u8>)> {
+    let mut chunks = vec![];
+    let mut rest = &state[MAGIC.len() + 1..];
+    while !rest.is_empty() {
+        let len = u32::from_le_bytes(rest[5..9].try_into().unwrap()) as usize;
+        chunks.push((
+            rest[..4].try_into().unwrap(),
+            rest[4],
+            rest[9..9 + len].to_vec(),
+        ));
+        rest = &rest[9 + len..];
+    }
+    chunks
+}
+
+fn join(chunks: &[([u8; 4], u8, Vec<u8>)]) -> Vec<u8> {
+    let mut state = MAGIC.to_vec();
+    state.push(FORMAT_VERSION);
+    for (id, version, contents) in chunks {
+        state.extend_from_slice(id);
+        state.push(*version);
+        state.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+        state.extend_from_slice(contents);
+    }
+    state
+}
+
+#[test]
+fn unknown_chunks_are_skipped_and_missing_ones_reset() {
+    let mut gameboy = running(0x03, 0x02);
+    let mut chunks = chunks(&gameboy.save_state());
+    chunks.insert(2, (*b"NEW!", 7, vec![1, 2, 3]));
+    let mut loaded = self::gameboy(0x03, 0x02);
+    loaded.load_state(&join(&chunks)).unwrap();
+    assert_eq!(loaded.peek(0xC000), gameboy.peek(0xC000));
+
+    chunks.retain(|(id, ..)| id != b"WRAM");
+    loaded.load_state(&join(&chunks)).unwrap();
+    assert_eq!(loaded.peek(0xC000), 0);
+
+    chunks.retain(|(id, ..)| id != b"CPU ");
+    assert_eq!(
+        loaded.load_state(&join(&chunks)),
+        Err(StateError::MissingChunk("CPU".into()))
+    );
+}
+
+fn fixture(name: &str) -> PathBuf {
+    [env!("CARGO_MANIFEST_DIR"), "tests/fixtures/states"]
+        .iter()
+        .collect::<PathBuf>()
+        .join(format!("{}.state", name))
+}
+
+/// Load the fixture for a cartridge type, writing it first if it is missing and `GOLDEN_UPDATE` is
+/// set
+fn load_fixture(name: &str, cart_type: u8, ram_size: u8) -> Gameboy {
+    let path = fixture(name);
+    if !path.exists() && std::env::var_os("GOLDEN_UPDATE").is_some() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, running(cart_type, ram_size).save_state()).unwrap();
+    }
+    let state = fs::read(&path).unwrap();
+    let info = state_info(&state).unwrap();
+    assert_eq!((info.version, info.title.as_str()), (1, "SAVESTATE"));
+    let mut gameboy = gameboy(cart_type, ram_size);
+    assert!(info.matches(&gameboy), "{}", name);
+    gameboy.load_state(&state).unwrap();
+    gameboy
+}
+
+#[test]
+fn fixtures_load() {
+    // Cartridge RAM, and the first byte of bank 2
+    let expected = [
+        (0xFF, 0x00),
+        (0x6F, 0xB2),
+        (0x0F, 0xB2),
+        (0x6F, 0xB2),
+        (0x6F, 0xB2),
+    ];
+    for ((name, cart_type, ram_size), (ram, bank)) in CARTS.iter().zip(expected) {
+        let mut gameboy = load_fixture(name, *cart_type, *ram_size);
+        let observed = (gameboy.peek(0xA000), gameboy.peek(0xC001));
+        assert_eq!(observed, (ram, bank), "{}", name);
+        assert_eq!(gameboy.peek(0xC000), 0x6F, "{}", name);
+        assert_eq!(gameboy.peek(0x8000), 0x6F, "{}", name);
+        assert_eq!(gameboy.ppu.frame_count, 3, "{}", name);
+        let rtc = gameboy.cart.rtc_state().map(|rtc| rtc.live);
+        if *cart_type == 0x10 {
+            let time = rtc.map(|rtc| (rtc.days, rtc.hours, rtc.minutes, rtc.seconds));
+            assert_eq!(time, Some((1, 2, 3, 4)));
+        }
+        gameboy.run_frames(1);
+        assert_ne!(gameboy.peek(0xC000), 0x6F, "{}", name);
+    }
+}
@@ -0,0 +1,95 @@
+use gb_core::gameboy::{joypad::Button, ppu::consts::FRAME_T_CYCLES, Gameboy};
+
+/// Waits for the timer or serial interrupt over and over. Each time, it copies TIMA to NR12 and
+/// picks the timer's rate from it, starts a serial transfer of DIV unless one is running, reads
+/// the joypad into $C000, and every 8th time resets DIV and starts an OAM DMA.
+#[rustfmt::skip]
+const BUSY: [u8; 62] = [
+    0x3E, 0x05,       // LD A, $05
+    0xE0, 0x07,       // LDH (TAC), A
+    0x3E, 0x0C,       // LD A, $0C
+    0xE0, 0xFF,       // LDH (IE), A
+    0x3E, 0x20,       // LD A, $20
+    0xE0, 0x00,       // LDH (P1), A
+    0xF3,             // DI
+    // loop:
+    0x76,             // HALT
+    0xAF,             // XOR A
+    0xE0, 0x0F,       // LDH (IF), A
+    0xF0, 0x05,       // LDH A, (TIMA)
+    0xE0, 0x12,       // LDH (NR12), A
+    0xE6, 0x03,       // AND $03
+    0xF6, 0x04,       // OR $04
+    0xE0, 0x07,       // LDH (TAC), A
+    0xF0, 0x02,       // LDH A, (SC)
+    0x07,             // RLCA
+    0x38, 0x08,       // JR C, +8
+    0xF0, 0x04,       // LDH A, (DIV)
+    0xE0, 0x01,       // LDH (SB), A
+    0x3E, 0x81,       // LD A, $81
+    0xE0, 0x02,       // LDH (SC), A
+    0xF0, 0x00,       // LDH A, (P1)
+    0xEA, 0x00, 0xC0, // LD ($C000), A
+    0x21, 0x01, 0xC0, // LD HL, $C001
+    0x34,             // INC (HL)
+    0x7E,             // LD A, (HL)
+    0xE6, 0x07,       // AND $07
+    0x20, 0x06,       // JR NZ, +6
+    0xE0, 0x04,       // LDH (DIV), A
+    0x3E, 0xC0,       // LD A, $C0
+    0xE0, 0x46,       // LDH (DMA), A
+    0x18, 0xCF,       // JR loop
+];
+
+const M_CYCLES_PER_FRAME: usize = FRAME_T_CYCLES / 4;
+
+#[test]
+fn scheduling_chips_changes_nothing_the_cpu_can_see() {
+    let mut scheduled = Gameboy::with_program(&BUSY, 0x0150).unwrap();
+    let mut every_chip = Gameboy::with_program(&BUSY, 0x0150).unwrap();
+    assert!(scheduled.chip_scheduling());
+    every_chip.set_chip_scheduling(false);
+
+    let mut interrupts = 0;
+    for cycle in 0..5 * M_CYCLES_PER_FRAME {
+        // Hold down Right for a frame
+        if cycle % (2 * M_CYCLES_PER_FRAME) == M_CYCLES_PER_FRAME {
+            scheduled.joypad.press(Button::Right);
+            every_chip.joypad.press(Button::Right);
+        } else if cycle % (2 * M_CYCLES_PER_FRAME) == 0 {
+            scheduled.joypad.release(Button::Right);
+            every_chip.joypad.release(Button::Right);
+        }
+
+        let (expected, actual) = (every_chip.tick(), scheduled.tick());
+        assert_eq!(
+            format!("{:?}", actual),
+            format!("{:?}", expected),
+            "M-cycle {}",
+            cycle
+        );
+        assert_eq!(
+            scheduled.cpu.cpu.registers, every_chip.cpu.cpu.registers,
+            "M-cycle {}",
+            cycle
+        );
+        assert_eq!(scheduled.peek(0xFF0F), every_chip.peek(0xFF0F));
+        interrupts |= actual.interrupts_raised;
+    }
+    // The timer, serial and joypad interrupts were all raised along the way
+    assert_eq!(interrupts & 0x1C, 0x1C);
+    assert_eq!(scheduled.peek(0xC001), every_chip.peek(0xC001));
+}
+
+#[test]
+fn turning_scheduling_back_on_catches_up() {
+    let mut scheduled = Gameboy::with_program(&BUSY, 0x0150).unwrap();
+    let mut every_chip = Gameboy::with_program(&BUSY, 0x0150).unwrap();
+    every_chip.set_chip_scheduling(false);
+    for toggle in 0..8 {
+        scheduled.set_chip_scheduling(toggle % 2 == 1);
+        scheduled.run_cycles(FRAME_T_CYCLES as u64 / 3);
+        every_chip.run_cycles(FRAME_T_CYCLES as u64 / 3);
+        assert_eq!(scheduled.cpu.cpu.registers, every_chip.cpu.cpu.registers);
+    }
+}
@@ -0,0 +1,73 @@
+#![cfg(feature = "scripting")]
+
+use gb_core::gameboy::{
+    script::{ScriptError, ScriptHost, ScriptOutcome},
+    Gameboy, ResetKind,
+};
+
+const START_COUNTER: &[u8] = include_bytes!("fixtures/start_counter.gb");
+
+fn host() -> ScriptHost {
+    let mut gameboy = Gameboy::new(START_COUNTER.to_vec()).unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    ScriptHost::new(gameboy)
+}
+
+fn stopped_with(outcome: ScriptOutcome) -> String {
+    match outcome {
+        ScriptOutcome::Stopped(result) => result.to_string(),
+        ScriptOutcome::TimedOut => panic!("script timed out"),
+    }
+}
+
+#[test]
+fn press_start_example() {
+    let mut host = host();
+    let outcome = host
+        .run(include_str!("../examples/scripts/press_start.rhai"), 600)
+        .unwrap();
+    assert_eq!(stopped_with(outcome), "true");
+
+    let gameboy = host.into_gameboy();
+    assert_eq!(gameboy.peek(0xC000), 3);
+    assert_eq!(gameboy.ppu.frame_count, 70);
+}
+
+#[test]
+fn watch_memory_example() {
+    let mut host = host();
+    let outcome = host
+        .run(include_str!("../examples/scripts/watch_memory.rhai"), 300)
+        .unwrap();
+    // Nothing presses Start, so the counter stays at 0
+    assert_eq!(stopped_with(outcome), "0");
+}
+
+#[test]
+fn times_out() {
+    let mut host = host();
+    let outcome = host.run("on_frame(|| poke(0xC001, frame_count()));", 5);
+    assert!(matches!(outcome, Ok(ScriptOutcome::TimedOut)));
+    assert_eq!(host.gameboy().peek(0xC001), 5);
+}
+
+#[test]
+fn errors() {
+    let mut host = host();
+    assert!(matches!(
+        host.run("on_frame(||", 1),
+        Err(ScriptError::Parse(_))
+    ));
+    assert!(matches!(
+        host.run(r#"press("Turbo");"#, 1),
+        Err(ScriptError::Runtime(_))
+    ));
+    assert!(matches!(
+        host.run("on_frame(|| peek(0x10000));", 1),
+        Err(ScriptError::Runtime(_))
+    ));
+    // Stopping from the top level doesn't run any frames
+    let outcome = host.run("stop(42); poke(0xC002, 1);", 10).unwrap();
+    assert_eq!(stopped_with(outcome), "42");
+    assert_eq!(host.gameboy().peek(0xC002), 0);
+}
@@ -0,0 +1,196 @@
+use gb_core::gameboy::{serial::Serial, Gameboy, SerialConnection};
+use gb_cpu::assembler::assemble;
+
+/// Sends $42 with the internal clock, and stores the byte received at $C000
+#[rustfmt::skip]
+const SEND_42: [u8; 18] = [
+    0x3E, 0x42,       // LD A, $42
+    0xE0, 0x01,       // LDH (SB), A
+    0x3E, 0x81,       // LD A, $81
+    0xE0, 0x02,       // LDH (SC), A
+    // wait:
+    0xF0, 0x02,       // LDH A, (SC)
+    0x87,             // ADD A
+    0x38, 0xFB,       // JR C, wait
+    0xF0, 0x01,       // LDH A, (SB)
+    0xEA, 0x00, 0xC0, // LD ($C000), A
+];
+
+/// Replies with the complement of each byte after being polled `latency` times
+struct Late {
+    latency: u32,
+    polls: u32,
+    sent: Option<u8>,
+}
+
+impl SerialConnection for Late {
+    fn exchange(&mut self, byte: u8) -> u8 {
+        !byte
+    }
+
+    fn begin_exchange(&mut self, byte: u8) -> Option<u8> {
+        self.sent = Some(byte);
+        self.polls = 0;
+        None
+    }
+
+    fn poll_reply(&mut self) -> Option<u8> {
+        self.polls += 1;
+        if self.polls < self.latency {
+            return None;
+        }
+        self.sent.take().map(|byte| !byte)
+    }
+}
+
+fn gameboy(latency: u32) -> Gameboy {
+    let mut code = SEND_42.to_vec();
+    code.extend_from_slice(&[0x18, 0xFE]); // JR -2
+    let mut gameboy = Gameboy::with_program(&code, 0x0150).unwrap();
+    gameboy.serial = Serial::new(Box::new(Late {
+        latency,
+        polls: 0,
+        sent: None,
+    }));
+    gameboy
+}
+
+#[test]
+fn transfer_waits_for_a_late_reply() {
+    // A transfer takes 1024 M-cycles at 8192Hz, but the reply comes 5000 M-cycles after it
+    // starts, and the first bit can't be shifted in until then
+    let mut gameboy = gameboy(5000);
+    gameboy.run_cycles(4 * 5000);
+    assert_eq!(gameboy.peek(0xC000), 0x00);
+    gameboy.run_cycles(4 * 2000);
+    assert_eq!(gameboy.peek(0xC000), 0xBD);
+    // The serial interrupt is raised when the reply arrives
+    assert_eq!(gameboy.peek(0xFF0F) & 0x08, 0x08);
+}
+
+#[test]
+fn immediate_reply_keeps_transfer_timing() {
+    let mut gameboy = gameboy(0);
+    gameboy.run_cycles(4 * 1024);
+    assert_eq!(gameboy.peek(0xC000), 0x00);
+    gameboy.run_cycles(4 * 50);
+    assert_eq!(gameboy.peek(0xC000), 0xBD);
+}
+
+/// Starts a transfer with `sc` after putting $42 in SB, then stores SB and SC at $C000 once the
+/// transfer is done, along with the count of M-cycles it waited for at $C002
+fn transfer(sc: u8) -> Gameboy {
+    let source = format!(
+        "
+        .org $0150
+            ld a, $42
+            ldh [$01], a
+            ld a, ${:02X}
+            ldh [$02], a
+            ld bc, 0
+        wait:
+            inc bc
+            ldh a, [$02]
+            add a
+            jr c, wait
+            ldh a, [$01]
+            ld [$C000], a
+            ldh a, [$02]
+            ld [$C001], a
+            ld a, c
+            ld [$C002], a
+            ld a, b
+            ld [$C003], a
+        done:
+            jr done
+        ",
+        sc
+    );
+    Gameboy::with_program(&assemble(&source).unwrap(), 0x0150).unwrap()
+}
+
+#[test]
+fn internal_clock_without_a_peer_shifts_in_ones() {
+    let mut gameboy = transfer(0x81);
+    gameboy.run_cycles(4 * 1000);
+    assert_eq!(gameboy.peek(0xC000), 0x00);
+    gameboy.run_cycles(4 * 100);
+    assert_eq!(gameboy.peek(0xC000), 0xFF);
+    assert_eq!(gameboy.peek(0xC001), 0x7D);
+    assert_eq!(gameboy.peek(0xFF0F) & 0x08, 0x08);
+}
+
+#[test]
+fn external_clock_without_a_peer_never_completes() {
+    // The fast clock bit is kept, even though there is no CGB to use it
+    let mut gameboy = transfer(0x02);
+    gameboy.run_frames(1);
+    assert_eq!(gameboy.peek(0xC001), 0x7E);
+
+    let mut gameboy = transfer(0x80);
+    gameboy.run_frames(120);
+    assert_eq!(gameboy.peek(0xC000), 0x00);
+    assert_eq!(gameboy.peek(0xFF0F) & 0x08, 0x00);
+}
+
+/// Drives the clock for transfers using the external clock, sending $A5 after being polled
+/// `latency` times, and keeps the bytes it received
+struct Master {
+    latency: u32,
+    polls: u32,
+    received: std::sync::Arc<std::sync::Mutex<Vec<u8>>>,
+}
+
+impl SerialConnection for Master {
+    fn exchange(&mut self, _byte: u8) -> u8 {
+        0xFF
+    }
+
+    fn drive_exchange(&mut self, byte: u8) -> Option<u8> {
+        self.polls += 1;
+        if self.polls < self.latency {
+            return None;
+        }
+        self.polls = 0;
+        self.received.lock().unwrap().push(byte);
+        Some(0xA5)
+    }
+}
+
+#[test]
+fn peer_drives_an_external_transfer() {
+    let received = Default::default();
+    let mut gameboy = transfer(0x80);
+    gameboy.serial = Serial::new(Box::new(Master {
+        latency: 3000,
+        polls: 0,
+        received: std::sync::Arc::clone(&received),
+    }));
+    gameboy.run_frames(1);
+    assert_eq!(gameboy.peek(0xC000), 0xA5);
+    assert_eq!(gameboy.peek(0xC001), 0x7C);
+    assert_eq!(*received.lock().unwrap(), [0x42]);
+    // The loop takes 9 M-cycles
+    let waited = u16::from_le_bytes([gameboy.peek(0xC002), gameboy.peek(0xC003)]);
+    assert!((3000 / 9..=3000 / 9 + 1).contains(&waited), "{}", waited);
+}
+
+#[test]
+fn savestate_mid_transfer() {
+    let mut gameboy = self::gameboy(0);
+    gameboy.run_cycles(4 * 600);
+    let state = gameboy.save_state();
+    gameboy.run_cycles(4 * 500);
+    assert_eq!(gameboy.peek(0xC000), 0xBD);
+
+    // The byte sent back is in the state, so the other end of the cable doesn't matter, and
+    // the bits already shifted carry on from where they were
+    let mut loaded = self::gameboy(0);
+    loaded.serial = Serial::default();
+    loaded.load_state(&state).unwrap();
+    loaded.run_cycles(4 * 400);
+    assert_eq!(loaded.peek(0xC000), 0x00);
+    loaded.run_cycles(4 * 100);
+    assert_eq!(loaded.peek(0xC000), 0xBD);
+    assert_eq!(loaded.cycles(), gameboy.cycles());
+}
@@ -0,0 +1,138 @@
+use std::sync::{Arc, Mutex};
+
+use gb_core::gameboy::{Chip, ClockContext, Gameboy, Model, ResetKind};
+use gb_cpu::CpuOutputPins;
+
+fn gameboy(model: Model) -> Gameboy {
+    let mut gameboy = Gameboy::builder()
+        .rom(vec![0; 0x8000])
+        .model(model)
+        .build()
+        .unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    gameboy
+}
+
+fn write_p1(gameboy: &mut Gameboy, v: u8) {
+    gameboy.joypad.clock(
+        CpuOutputPins::Write {
+            addr: 0xFF00,
+            data: v,
+        },
+        &mut 0xFF,
+        &mut 0,
+        &ClockContext::default(),
+    );
+}
+
+fn read_p1(gameboy: &mut Gameboy) -> u8 {
+    let mut data = 0xFF;
+    gameboy.joypad.clock(
+        CpuOutputPins::Read { addr: 0xFF00 },
+        &mut data,
+        &mut 0,
+        &ClockContext::default(),
+    );
+    data
+}
+
+/// The values written to P1 to send `packet`: a reset pulse, then each bit starting from the
+/// lowest bit of the first byte, then a 0 stop bit. Every value is written `repeat` times, like
+/// games do to give the SGB time to notice.
+fn packet_writes(packet: &[u8; 16], repeat: usize) -> Vec<u8> {
+    let bits = (0..128).map(|i| packet[i / 8] >> (i % 8) & 1 != 0);
+    let mut writes = vec![0x00, 0x30];
+    for bit in bits.chain([false]) {
+        writes.extend([if bit { 0x10 } else { 0x20 }, 0x30]);
+    }
+    writes
+        .into_iter()
+        .flat_map(|v| std::iter::repeat(v).take(repeat))
+        .collect()
+}
+
+fn send_packet(gameboy: &mut Gameboy, packet: &[u8; 16]) {
+    for v in packet_writes(packet, 2) {
+        write_p1(gameboy, v);
+    }
+}
+
+fn collect_packets(gameboy: &mut Gameboy) -> Arc<Mutex<Vec<[u8; 16]>>> {
+    let packets = Arc::new(Mutex::new(Vec::new()));
+    gameboy.on_sgb_packet({
+        let packets = packets.clone();
+        move |packet| packets.lock().unwrap().push(*packet)
+    });
+    packets
+}
+
+/// PAL01, setting palettes 0 and 1
+const PAL01: [u8; 16] = [
+    0x01, 0xFF, 0x7F, 0x1F, 0x42, 0xF2, 0x1C, 0x00, 0x00, 0x3F, 0x03, 0xE0, 0x7C, 0x00, 0x00, 0x00,
+];
+
+/// MLT_REQ, enabling two players
+const MLT_REQ_2P: [u8; 16] = [0x89, 0x01, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+
+#[test]
+fn receives_packets() {
+    let mut gameboy = gameboy(Model::Sgb);
+    let packets = collect_packets(&mut gameboy);
+
+    send_packet(&mut gameboy, &PAL01);
+    send_packet(&mut gameboy, &MLT_REQ_2P);
+    assert_eq!(*packets.lock().unwrap(), [PAL01, MLT_REQ_2P]);
+}
+
+#[test]
+fn bad_stop_bit_discards_packet() {
+    let mut gameboy = gameboy(Model::Sgb);
+    let packets = collect_packets(&mut gameboy);
+
+    let mut writes = packet_writes(&PAL01, 1);
+    let stop = writes.len() - 2;
+    writes[stop] = 0x10;
+    for v in writes {
+        write_p1(&mut gameboy, v);
+    }
+    assert!(packets.lock().unwrap().is_empty());
+}
+
+#[test]
+fn mlt_req_handshake() {
+    let mut gameboy = gameboy(Model::Sgb);
+    write_p1(&mut gameboy, 0x30);
+    assert_eq!(read_p1(&mut gameboy), 0xFF);
+
+    send_packet(&mut gameboy, &MLT_REQ_2P);
+    // Selecting and deselecting the action buttons switches between the two joypads
+    let mut ids = Vec::new();
+    for _ in 0..4 {
+        write_p1(&mut gameboy, 0x10);
+        write_p1(&mut gameboy, 0x30);
+        ids.push(read_p1(&mut gameboy) & 0x0F);
+    }
+    assert_eq!(ids, [0x0E, 0x0F, 0x0E, 0x0F]);
+}
+
+#[test]
+fn dmg_ignores_packets() {
+    let mut gameboy = gameboy(Model::Dmg);
+    let packets = collect_packets(&mut gameboy);
+
+    send_packet(&mut gameboy, &MLT_REQ_2P);
+    write_p1(&mut gameboy, 0x10);
+    write_p1(&mut gameboy, 0x30);
+    assert_eq!(read_p1(&mut gameboy) & 0x0F, 0x0F);
+    assert!(packets.lock().unwrap().is_empty());
+}
+
+#[test]
+fn boot_registers() {
+    let registers = gameboy(Model::Dmg).cpu.cpu.registers;
+    assert_eq!((registers.get_af(), registers.get_bc()), (0x01B0, 0x0013));
+
+    let registers = gameboy(Model::Sgb).cpu.cpu.registers;
+    assert_eq!((registers.get_af(), registers.get_bc()), (0x0100, 0x0014));
+    assert_eq!((registers.sp, registers.pc), (0xFFFE, 0x0100));
+}
@@ -0,0 +1,57 @@
+//! Half of these only run with the `single-thread` feature. Run them with
+//! `cargo test -p gb_core --features single-thread --test single_thread`.
+
+use gb_core::gameboy::{test_pattern, Gameboy, ResetKind};
+
+fn gameboy() -> Gameboy {
+    let mut gameboy = Gameboy::new(test_pattern::rom()).unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    gameboy
+}
+
+/// The last frame after running `frames` of them
+fn run(mut gameboy: Gameboy, frames: u32) -> Vec<u8> {
+    gameboy.run_frames(frames);
+    gameboy.get_frame().iter().copied().collect()
+}
+
+#[cfg(not(feature = "single-thread"))]
+#[test]
+fn gameboys_run_on_other_threads() {
+    let gameboy = gameboy();
+    let other = std::thread::spawn(move || run(gameboy, 10));
+    assert_eq!(other.join().unwrap(), run(self::gameboy(), 10));
+}
+
+#[cfg(feature = "single-thread")]
+#[test]
+fn callbacks_need_not_be_send() {
+    use std::{cell::Cell, rc::Rc};
+
+    static_assertions::assert_not_impl_any!(Gameboy: Send);
+
+    let lines = Rc::new(Cell::new(0));
+    let mut gameboy = gameboy();
+    gameboy.on_scanline({
+        let lines = lines.clone();
+        move |_, _| lines.set(lines.get() + 1)
+    });
+    // Only the last of the frames run is drawn
+    gameboy.run_frames(3);
+    assert_eq!(lines.get(), 144);
+    assert_eq!(Rc::strong_count(&lines), 2);
+    gameboy.clear_scanline_callback();
+    assert_eq!(Rc::strong_count(&lines), 1);
+}
+
+#[cfg(feature = "single-thread")]
+#[test]
+fn frames_are_shared_on_one_thread() {
+    let mut gameboy = gameboy();
+    gameboy.run_frames(5);
+    let held = gameboy.get_frame();
+    let copy: Vec<u8> = held.iter().copied().collect();
+    gameboy.run_frames(5);
+    assert!(held.iter().eq(copy.iter()), "frame changed while held");
+    assert_eq!(run(gameboy, 0), run(self::gameboy(), 10));
+}
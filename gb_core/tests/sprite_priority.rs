@@ -0,0 +1,280 @@
+use std::convert::TryInto;
+
+use gb_core::gameboy::{
+    ppu::{
+        consts::FRAME_T_CYCLES,
+        frame::attribution::PixelLayer,
+        priority::{mix_pixel, resolve_sprite_priority, MixResult, PriorityMode},
+        registers::{OamEntry, LCDC},
+        Pixel, Ppu,
+    },
+    Gameboy, PpuBackend,
+};
+
+fn at_x(xpos: u8) -> OamEntry {
+    OamEntry {
+        xpos,
+        ypos: 16,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn dmg_orders_by_x_then_oam_index() {
+    let sprites = [at_x(30), at_x(10), at_x(30), at_x(20), at_x(10)];
+    assert_eq!(
+        *resolve_sprite_priority(&sprites, PriorityMode::Dmg),
+        [1, 4, 3, 0, 2]
+    );
+}
+
+#[test]
+fn cgb_orders_by_oam_index() {
+    let sprites = [at_x(30), at_x(10), at_x(30), at_x(20), at_x(10)];
+    assert_eq!(
+        *resolve_sprite_priority(&sprites, PriorityMode::Cgb),
+        [0, 1, 2, 3, 4]
+    );
+}
+
+#[test]
+fn opri_bit_0_selects_dmg_ordering() {
+    assert_eq!(PriorityMode::from_opri(0x01), PriorityMode::Dmg);
+    assert_eq!(PriorityMode::from_opri(0xFE), PriorityMode::Cgb);
+    assert_eq!(PriorityMode::Cgb.opri(), 0xFE);
+}
+
+/// The colours of the first line of a frame with two overlapping 8x8 sprites. The first sprite in
+/// OAM is solid colour 3 at screen X 12, and the second is solid colour 1 at screen X 8.
+fn overlap(mode: PriorityMode) -> [u8; 24] {
+    let mut ppu = Ppu::new();
+    ppu.tile_data[0x10..0x20].fill(0xFF);
+    for row in ppu.tile_data[0x20..0x30].chunks_exact_mut(2) {
+        row[0] = 0xFF;
+    }
+    ppu.oam[..8].copy_from_slice(&[16, 20, 1, 0, 16, 16, 2, 0]);
+    ppu.lcdc.insert(LCDC::OBJ_ENABLE);
+    ppu.bgp = 0b11_10_01_00;
+    ppu.obp0 = 0b11_10_01_00;
+    ppu.priority_mode = mode;
+    for _ in 0..FRAME_T_CYCLES {
+        ppu.clock_t_state();
+    }
+    ppu.get_frame().row(0)[..24].try_into().unwrap()
+}
+
+#[test]
+fn overlapping_sprites_are_drawn_in_priority_order() {
+    let mut expected = [0; 24];
+    expected[8..12].fill(1);
+    expected[12..20].fill(3);
+    assert_eq!(overlap(PriorityMode::Cgb), expected);
+
+    expected[12..16].fill(1);
+    assert_eq!(overlap(PriorityMode::Dmg), expected);
+}
+
+/// What ends up on screen for each BG color (rows) and sprite color (columns), where `B` is the
+/// BG pixel and `S` the sprite pixel, as described by the OAM and LCDC pages of Pan Docs
+#[rustfmt::skip]
+const TRUTH_TABLE: [(bool, bool, [&str; 4]); 4] = [
+    // BG enabled, no BG priority: any visible sprite pixel is drawn
+    (true, false, ["BSSS", "BSSS", "BSSS", "BSSS"]),
+    // BG enabled, BG priority: the sprite only shows through BG color 0
+    (true, true, ["BSSS", "BBBB", "BBBB", "BBBB"]),
+    // BG disabled: the BG is color 0, so sprites always win whatever their flag
+    (false, false, ["BSSS", "BSSS", "BSSS", "BSSS"]),
+    (false, true, ["BSSS", "BSSS", "BSSS", "BSSS"]),
+];
+
+#[test]
+fn mixing_follows_the_dmg_truth_table() {
+    for &(bg_enable, bg_priority, rows) in &TRUTH_TABLE {
+        let lcdc = if bg_enable {
+            LCDC::BG_ENABLE | LCDC::OBJ_ENABLE
+        } else {
+            LCDC::OBJ_ENABLE
+        };
+        for (bg_color, row) in rows.iter().enumerate() {
+            for (sprite_color, expected) in row.chars().enumerate() {
+                let bg = Pixel {
+                    color: bg_color as u8,
+                    ..Default::default()
+                };
+                let sprite = Pixel {
+                    color: sprite_color as u8,
+                    palette: 1,
+                    bg_priority,
+                    ..Default::default()
+                };
+                let case = format!(
+                    "BG enable {}, BG priority {}, BG color {}, sprite color {}",
+                    bg_enable, bg_priority, bg_color, sprite_color
+                );
+                match (expected, mix_pixel(bg, sprite, lcdc)) {
+                    ('B', MixResult::Bg(pixel)) => {
+                        let color = if bg_enable { bg_color as u8 } else { 0 };
+                        assert_eq!(pixel.color, color, "{}", case);
+                    }
+                    ('S', MixResult::Sprite(pixel)) => assert_eq!(pixel, sprite, "{}", case),
+                    (expected, actual) => {
+                        panic!("{}: expected {}, got {:?}", case, expected, actual)
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The first line of a frame over a background of color 2, with a solid colour 3 sprite at
+/// screen X 8 that has BG priority, and then a solid colour 1 sprite without it at screen X 12
+fn overlap_with_bg_priority() -> [u8; 24] {
+    let mut ppu = Ppu::new();
+    for row in ppu.tile_data[..0x10].chunks_exact_mut(2) {
+        row[1] = 0xFF;
+    }
+    ppu.tile_data[0x10..0x20].fill(0xFF);
+    for row in ppu.tile_data[0x20..0x30].chunks_exact_mut(2) {
+        row[0] = 0xFF;
+    }
+    ppu.oam[..8].copy_from_slice(&[16, 16, 1, 0x80, 16, 20, 2, 0]);
+    ppu.lcdc.insert(LCDC::BG_ENABLE | LCDC::OBJ_ENABLE);
+    ppu.bgp = 0b11_10_01_00;
+    ppu.obp0 = 0b11_10_01_00;
+    for _ in 0..FRAME_T_CYCLES {
+        ppu.clock_t_state();
+    }
+    ppu.get_frame().row(0)[..24].try_into().unwrap()
+}
+
+#[test]
+fn the_top_sprites_bg_priority_hides_the_sprite_under_it() {
+    // The sprite on top keeps its pixels in the FIFO, so the sprite under it never shows through
+    // them, even though that one doesn't have BG priority
+    let mut expected = [2; 24];
+    expected[16..20].fill(1);
+    assert_eq!(overlap_with_bg_priority(), expected);
+}
+
+#[test]
+fn monochrome_models_have_no_opri() {
+    #[rustfmt::skip]
+    let mut gameboy = Gameboy::with_program(&[
+        0xAF,             // XOR A
+        0xE0, 0x6C,       // LDH (OPRI), A
+        0xF0, 0x6C,       // LDH A, (OPRI)
+        0xEA, 0x00, 0xC0, // LD ($C000), A
+        0x18, 0xFE,       // JR -2
+    ], 0x0150).unwrap();
+    gameboy.run_frames(1);
+    assert_eq!(gameboy.peek(0xC000), 0xFF);
+    assert_eq!(gameboy.ppu.priority_mode, PriorityMode::Dmg);
+}
+
+/// Run a frame on a PPU set up by `setup` with each backend, and return the first 24 pixels of
+/// line 0 as (tile, color index) pairs, where the tile of a BG or window pixel is `None`
+fn first_line(setup: impl Fn(&mut Ppu)) -> Vec<Vec<(Option<u8>, u8)>> {
+    [PpuBackend::DotAccurate, PpuBackend::Scanline]
+        .iter()
+        .map(|&backend| {
+            let mut ppu = Ppu::new();
+            setup(&mut ppu);
+            ppu.backend = backend;
+            ppu.set_pixel_attribution(true);
+            for _ in 0..FRAME_T_CYCLES {
+                ppu.clock_t_state();
+            }
+            let frame = ppu.get_frame();
+            let row = frame.attribution().unwrap().row(0);
+            row[..24]
+                .iter()
+                .map(|source| match source.layer {
+                    PixelLayer::Sprite { tile, .. } => (Some(tile), source.color),
+                    _ => (None, source.color),
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Two sprites at screen X 8: tile 1, which is colour 3 in its left half and transparent in its
+/// right half, and tile 2, which is solid colour 1. `first` is the tile of the one earlier in OAM.
+fn same_x(first: u8, mode: PriorityMode) -> Vec<Vec<(Option<u8>, u8)>> {
+    first_line(|ppu| {
+        ppu.tile_data[0x10..0x20].fill(0xF0);
+        for row in ppu.tile_data[0x20..0x30].chunks_exact_mut(2) {
+            row[0] = 0xFF;
+        }
+        let second = 3 - first;
+        ppu.oam[..8].copy_from_slice(&[16, 16, first, 0, 16, 16, second, 0]);
+        ppu.lcdc.insert(LCDC::OBJ_ENABLE);
+        ppu.priority_mode = mode;
+    })
+}
+
+#[test]
+fn sprites_at_the_same_x_are_drawn_in_oam_order() {
+    for &mode in &[PriorityMode::Dmg, PriorityMode::Cgb] {
+        // The first sprite keeps its visible pixels, and the second only shows through its
+        // transparent ones
+        let mut expected = vec![(None, 0); 24];
+        expected[8..12].fill((Some(1), 3));
+        expected[12..16].fill((Some(2), 1));
+        for line in same_x(1, mode) {
+            assert_eq!(line, expected, "{:?}", mode);
+        }
+
+        // The solid sprite hides the other one completely
+        expected[8..12].fill((Some(2), 1));
+        for line in same_x(2, mode) {
+            assert_eq!(line, expected, "{:?}", mode);
+        }
+    }
+}
+
+/// A solid colour 1 sprite at screen X 8-15, over the background, which is colour 0, and the
+/// window, which starts at screen X 12. Window tiles are colour 0 in their first two columns and
+/// colour 2 in the rest.
+fn across_window_seam(bg_priority: bool) -> Vec<Vec<(Option<u8>, u8)>> {
+    first_line(|ppu| {
+        for row in ppu.tile_data[0x10..0x20].chunks_exact_mut(2) {
+            row[1] = 0x3F;
+        }
+        for row in ppu.tile_data[0x20..0x30].chunks_exact_mut(2) {
+            row[0] = 0xFF;
+        }
+        ppu.bg_map_2.fill(1);
+        ppu.oam[..4].copy_from_slice(&[16, 16, 2, if bg_priority { 0x80 } else { 0 }]);
+        ppu.lcdc.insert(
+            LCDC::BG_ENABLE
+                | LCDC::BG_TILE_DATA_AREA
+                | LCDC::OBJ_ENABLE
+                | LCDC::WINDOW_ENABLE
+                | LCDC::WINDOW_TILEMAP_AREA,
+        );
+        ppu.wy = 0;
+        ppu.wx = 12 + 7;
+    })
+}
+
+#[test]
+fn sprites_are_mixed_with_the_window_like_the_background() {
+    // Window columns 0-1 and 8-9 are colour 0
+    let mut expected = vec![(None, 0); 24];
+    expected[14..20].fill((None, 2));
+    expected[22..24].fill((None, 2));
+
+    // Without BG priority, the sprite covers the window too
+    let mut over = expected.clone();
+    over[8..16].fill((Some(2), 1));
+    for line in across_window_seam(false) {
+        assert_eq!(line, over);
+    }
+
+    // With it, the sprite only shows through colour 0, whether of the background or the window
+    let mut under = expected;
+    under[8..14].fill((Some(2), 1));
+    for line in across_window_seam(true) {
+        assert_eq!(line, under);
+    }
+}
@@ -0,0 +1,145 @@
+//! The stack pointer can point anywhere, and pushes to OAM, IO registers, IE and the unusable
+//! region are just writes, with the same effects as any other.
+
+use gb_core::gameboy::{
+    call_stack::StackFrame,
+    cart::header::{flat_rom, update_checksums},
+    Gameboy, ResetKind,
+};
+use gb_cpu::assembler::assemble;
+
+/// Assemble `source`, which starts at $0150, into an MBC1 cartridge with RAM, with `handler` at
+/// `vector`
+fn build(source: &str, vector: usize, handler: &[u8]) -> Gameboy {
+    let mut rom = flat_rom(&assemble(source).unwrap(), 0x0150, "STACK").unwrap();
+    rom[vector..vector + handler.len()].copy_from_slice(handler);
+    rom[0x147] = 0x02; // MBC1+RAM
+    rom[0x149] = 0x02; // 8KiB
+    update_checksums(&mut rom);
+    let mut gameboy = Gameboy::new(rom).unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    gameboy
+}
+
+/// [`build`], and run for a frame
+fn run(source: &str, vector: usize, handler: &[u8]) -> Gameboy {
+    let mut gameboy = build(source, vector, handler);
+    gameboy.run_frames(1);
+    gameboy
+}
+
+/// Stores A to $C000 and loops
+#[rustfmt::skip]
+const STORE_A: [u8; 5] = [
+    0xEA, 0x00, 0xC0, // LD ($C000), A
+    0x18, 0xFE,       // JR -2
+];
+
+/// Waits for HBlank, and pushes $015C to $FE01 and $FE00
+#[test]
+fn call_writes_oam_in_hblank() {
+    let gameboy = run(
+        "
+        .org $0150
+            ld sp, $FE02
+        wait:
+            ldh a, [$41]
+            and 3
+            jr nz, wait
+            call $0200
+        ",
+        0x0200,
+        &[0x18, 0xFE], // JR -2
+    );
+    assert_eq!(gameboy.cpu.cpu.registers.get_sp(), 0xFE00);
+    assert_eq!(gameboy.peek(0xFE01), 0x01);
+    assert_eq!(gameboy.peek(0xFE00), 0x5C);
+}
+
+/// The pushes land on SCX and SCY, and in the unusable region, where they are dropped
+#[test]
+fn push_to_io_registers_and_the_unusable_region() {
+    let gameboy = run(
+        "
+        .org $0150
+            ld bc, $1234
+            ld sp, $FF44
+            push bc
+            ld sp, $FF00
+            push bc
+            ld sp, $C100
+            push bc
+        stop:
+            jr stop
+        ",
+        0,
+        &[],
+    );
+    assert_eq!(gameboy.ppu.scx, 0x12);
+    assert_eq!(gameboy.ppu.scy, 0x34);
+    assert_eq!(gameboy.peek(0xFEFF), 0xFF);
+    assert_eq!(gameboy.peek(0xC0FF), 0x12);
+}
+
+/// SP wraps from $0000 to $FFFF: the upper byte of PC goes to the RAM enable register of the MBC,
+/// and the lower byte to IE
+#[test]
+fn rst_wraps_through_a_mapper_register_to_ie() {
+    let gameboy = run(
+        "
+        .org $0150
+            ld a, $0A
+            ld [$0000], a
+            ld a, $42
+            ld [$A000], a
+            ld sp, $0001
+            rst $38
+        ",
+        0x38,
+        &[&[0xFA, 0x00, 0xA0][..], &STORE_A].concat(), // LD A, ($A000)
+    );
+    assert_eq!(gameboy.cpu.cpu.registers.get_sp(), 0xFFFF);
+    assert_eq!(gameboy.peek(0xFFFF), 0x5E);
+    // $01 turned cartridge RAM back off, so it reads as open bus
+    assert_eq!(gameboy.peek(0xC000), 0xFF);
+}
+
+/// With SP at $0000, pushing the upper byte of PC to IE cancels the dispatch, and the CPU jumps to
+/// $0000 instead. The call stack follows it there.
+#[test]
+fn dispatch_cancelled_by_pushing_to_ie() {
+    let mut gameboy = build(
+        "
+        .org $0150
+            di
+            ld sp, $0000
+            ld a, $04
+            ldh [$FF], a
+            ldh [$0F], a
+            ld a, $99
+            ei
+            nop
+        stop:
+            jr stop
+        ",
+        0x0000,
+        &STORE_A,
+    );
+    gameboy.track_call_stack(true);
+    gameboy.run_frames(1);
+    assert_eq!(gameboy.peek(0xC000), 0x99);
+    assert_eq!(gameboy.peek(0xFFFF), 0x01);
+    assert_eq!(gameboy.peek(0xFFFE), 0x5E);
+    assert_eq!(gameboy.peek(0xFF0F) & 0x1F, 0x04);
+    assert_eq!(
+        gameboy.call_stack(),
+        [StackFrame {
+            call_site: 0x015E,
+            target: 0x0000,
+            sp_at_entry: 0xFFFE,
+            bank: 1,
+            interrupt: true,
+        }]
+    );
+    assert_eq!(gameboy.crash_dump().stack.center, 0xFFFE);
+}
@@ -0,0 +1,160 @@
+use gb_core::gameboy::{cart::header, Gameboy, ResetKind};
+use gb_cpu::Registers;
+
+/// Starts an OAM DMA, turns the timer on and the LCD off, then loops forever writing to VRAM, IO
+/// registers, WRAM, HRAM, cartridge RAM, the MBC and the stack. The PPU isn't rewound, so VRAM is
+/// only written with the LCD off, where it doesn't matter what the PPU is doing.
+#[rustfmt::skip]
+const PROGRAM: [u8; 44] = [
+    0x3E, 0x0A,       // LD A, $0A
+    0xEA, 0x00, 0x00, // LD ($0000), A (enable cartridge RAM)
+    0x3E, 0x05,       // LD A, $05
+    0xE0, 0x07,       // LDH ($07), A (TAC)
+    0x3E, 0xC0,       // LD A, $C0
+    0xE0, 0x46,       // LDH ($46), A (DMA)
+    0xAF,             // XOR A
+    0xE0, 0x40,       // LDH ($40), A (LCDC)
+    0x21, 0x00, 0x80, // LD HL, $8000
+    // loop:
+    0x7D,             // LD A, L
+    0x22,             // LD (HL+), A
+    0xE0, 0x47,       // LDH ($47), A (BGP)
+    0xE0, 0x43,       // LDH ($43), A (SCX)
+    0xE0, 0x05,       // LDH ($05), A (TIMA)
+    0xE0, 0x24,       // LDH ($24), A (NR50)
+    0xEA, 0x00, 0xC0, // LD ($C000), A
+    0xE0, 0x80,       // LDH ($80), A
+    0xEA, 0x00, 0xA0, // LD ($A000), A
+    0xEA, 0x00, 0x20, // LD ($2000), A (ROM bank)
+    0xE5,             // PUSH HL
+    0xE1,             // POP HL
+    0x18, 0xE7,       // JR loop
+];
+
+fn gameboy() -> Gameboy {
+    let mut rom = header::flat_rom(&PROGRAM, 0x0150, "").unwrap();
+    rom[0x147] = 0x03; // MBC1+RAM+BATTERY
+    let mut gameboy = Gameboy::new(rom).unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    gameboy.cpu.cpu.registers.pc = 0x0150;
+    gameboy.debug_journal(true);
+    // The journal starts at the first opcode fetch
+    gameboy.step_instruction();
+    gameboy
+}
+
+/// Everything the journal restores that can be seen from outside. LY and the mode bits of STAT
+/// are left out, since the PPU isn't rewound.
+#[derive(Debug, PartialEq, Eq)]
+struct Snapshot {
+    registers: Registers,
+    ime: bool,
+    halted: bool,
+    cycles: u64,
+    memory: Vec<u8>,
+    cart_ram: Vec<u8>,
+    rom_bank: u16,
+    ppu_registers: [u8; 11],
+    dma_active: bool,
+}
+
+fn snapshot(gameboy: &Gameboy) -> Snapshot {
+    let ppu = &gameboy.ppu;
+    Snapshot {
+        registers: gameboy.cpu.cpu.registers,
+        ime: gameboy.cpu.cpu.ime,
+        halted: gameboy.cpu.cpu.halted,
+        cycles: gameboy.cycles(),
+        memory: (0x8000..=0xFFFF).map(|addr| gameboy.peek(addr)).collect(),
+        cart_ram: gameboy.cart.ram().unwrap().to_vec(),
+        rom_bank: gameboy.cart.rom_bank(),
+        ppu_registers: [
+            ppu.lcdc.bits(),
+            ppu.stat.bits() & 0x78,
+            ppu.scy,
+            ppu.scx,
+            ppu.lyc,
+            ppu.dma,
+            ppu.bgp,
+            ppu.obp0,
+            ppu.obp1,
+            ppu.wy,
+            ppu.wx,
+        ],
+        dma_active: ppu.dma_active(),
+    }
+}
+
+#[test]
+fn step_back_restores_everything() {
+    let mut gameboy = gameboy();
+    let start = snapshot(&gameboy);
+
+    for _ in 0..100 {
+        gameboy.step_instruction();
+    }
+    let end = snapshot(&gameboy);
+    assert_ne!(end.memory, start.memory);
+    assert_ne!(end.cart_ram, start.cart_ram);
+    assert_ne!(end.ppu_registers, start.ppu_registers);
+
+    for i in 0..100 {
+        assert!(gameboy.step_back_instruction().is_some(), "step {}", i);
+    }
+    assert_eq!(snapshot(&gameboy), start);
+    assert_eq!(gameboy.step_back_instruction(), None);
+
+    // Running forward again from the restored state ends up in the same place
+    for _ in 0..100 {
+        gameboy.step_instruction();
+    }
+    assert_eq!(snapshot(&gameboy), end);
+}
+
+#[test]
+fn records_describe_the_undone_instruction() {
+    let mut gameboy = gameboy();
+    // LD A, $0A
+    gameboy.step_instruction();
+    let record = gameboy.step_back_instruction().unwrap();
+    assert_eq!(record.pc, 0x0150);
+    assert_eq!(record.opcode, 0x3E);
+    assert_eq!(record.t_cycles, 8);
+    assert!(record.writes.is_empty());
+
+    // Run into the loop until the PUSH
+    while gameboy.cpu.cpu.registers.pc != 0x0179 {
+        gameboy.step_instruction();
+    }
+    gameboy.step_instruction();
+    let record = gameboy.step_back_instruction().unwrap();
+    assert_eq!(record.opcode, 0xE5);
+    assert_eq!(record.t_cycles, 16);
+    assert_eq!(record.writes, [0xFFFD, 0xFFFC]);
+}
+
+#[test]
+fn journal_is_bounded() {
+    let mut gameboy = gameboy();
+    gameboy.set_journal_capacity(10);
+    for _ in 0..20 {
+        gameboy.step_instruction();
+    }
+    for _ in 0..10 {
+        assert!(gameboy.step_back_instruction().is_some());
+    }
+    assert_eq!(gameboy.step_back_instruction(), None);
+}
+
+#[test]
+fn only_steps_back_between_instructions() {
+    let mut gameboy = gameboy();
+    gameboy.step_instruction();
+    // LD ($0000), A takes 4 M-cycles
+    gameboy.tick();
+    assert_eq!(gameboy.step_back_instruction(), None);
+
+    gameboy.debug_journal(false);
+    gameboy.step_instruction();
+    assert_eq!(gameboy.step_back_instruction(), None);
+}
@@ -0,0 +1,173 @@
+use gb_core::gameboy::{
+    breakpoints::Breakpoint,
+    cart::header::flat_rom,
+    ppu::consts::FRAME_T_CYCLES,
+    symbols::{SymError, SymbolRef, SymbolTable},
+    Gameboy, ResetKind,
+};
+use gb_cpu::assembler::assemble;
+
+const SYMBOLS: &str = include_str!("fixtures/symbols.sym");
+
+/// Calls a function at $4000 in bank 2 and then one at $4000 in bank 1, forever
+const MAIN: &str = "
+    .org $0150
+    Main:
+        ld sp, $DFFE
+    .loop:
+        ld a, 2
+        ld [$2000], a
+        call $4000
+        ld a, 1
+        ld [$2000], a
+        call $4000
+        jr .loop
+";
+
+/// Counts in `counter`, in a function at $4000
+fn function(counter: u16) -> Vec<u8> {
+    let source = format!(".org $4000\nld hl, {}\ninc [hl]\nret", counter);
+    assemble(&source).unwrap()
+}
+
+/// A 64KiB MBC1 cartridge, laid out like `fixtures/symbols.sym` says
+fn banked_rom() -> Vec<u8> {
+    let mut rom = flat_rom(&assemble(MAIN).unwrap(), 0x0150, "").unwrap();
+    rom.resize(0x10000, 0);
+    rom[0x147] = 0x01;
+    rom[0x148] = 0x01;
+    let player = function(0xC000);
+    rom[0x4000..0x4000 + player.len()].copy_from_slice(&player);
+    let level = function(0xC001);
+    rom[0x8000..0x8000 + level.len()].copy_from_slice(&level);
+    rom
+}
+
+fn gameboy() -> Gameboy {
+    let mut gameboy = Gameboy::new(banked_rom()).unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    assert_eq!(gameboy.load_symbols(SYMBOLS), Ok(7));
+    gameboy
+}
+
+fn symbol(name: &str, offset: u16) -> Option<SymbolRef<'_>> {
+    Some(SymbolRef { name, offset })
+}
+
+#[test]
+fn symbolizing() {
+    let symbols = SymbolTable::parse(SYMBOLS).unwrap();
+    assert_eq!(symbols.symbolize(0, 0x0150), symbol("Main", 0));
+    // Local labels are named after the label before them
+    assert_eq!(symbols.symbolize(0, 0x0158), symbol("Main.loop", 5));
+    // The bank is ignored where memory isn't banked
+    assert_eq!(symbols.symbolize(3, 0xC001), symbol("wLevelTicks", 0));
+    assert_eq!(symbols.symbolize(0, 0xC010), symbol("wLevelTicks", 0xF));
+    assert_eq!(
+        symbols.symbolize(1, 0x4004),
+        symbol("UpdatePlayer.store", 1)
+    );
+    assert_eq!(symbols.symbolize(2, 0x4004), symbol("LoadLevel", 4));
+    assert_eq!(symbols.symbolize(3, 0x4004), None);
+    assert_eq!(symbols.symbolize(0, 0x0100), None);
+    // Labels don't reach into the next area of memory
+    assert_eq!(symbols.symbolize(0, 0x3FFF), symbol("Main.loop", 0x3EAC));
+    assert_eq!(symbols.symbolize(1, 0x8000), None);
+    assert_eq!(
+        symbol("UpdatePlayer.store", 0x12).unwrap().to_string(),
+        "UpdatePlayer.store+0x12"
+    );
+}
+
+#[test]
+fn parse_errors() {
+    assert_eq!(
+        SymbolTable::parse("00:0150 Main\n\n01:40G0 Broken ; comment"),
+        Err(SymError::InvalidLine {
+            line: 3,
+            text: "01:40G0 Broken".into()
+        })
+    );
+    assert!(SymbolTable::parse("Main").is_err());
+    let symbols = SymbolTable::parse(SYMBOLS).unwrap();
+    assert_eq!(
+        symbols.resolve("Nowhere"),
+        Err(SymError::UnknownSymbol("Nowhere".into()))
+    );
+    assert_eq!(
+        symbols.resolve("Main+x"),
+        Err(SymError::InvalidOffset("x".into()))
+    );
+    assert_eq!(symbols.resolve("UpdatePlayer+0x3"), Ok((1, 0x4003)));
+    assert_eq!(symbols.resolve("Main.loop + 2"), Ok((0, 0x0155)));
+}
+
+#[test]
+fn the_mapped_bank_picks_the_label() {
+    let mut gameboy = gameboy();
+    let level = gameboy.add_breakpoint(Breakpoint::at(0x4000));
+    assert_eq!(
+        gameboy.run_until_breakpoint(FRAME_T_CYCLES as u64),
+        Some(level)
+    );
+    assert_eq!(gameboy.cart.rom_bank(), 2);
+    assert_eq!(gameboy.symbolize(0x4000), symbol("LoadLevel", 0));
+    assert_eq!(
+        gameboy.run_until_breakpoint(FRAME_T_CYCLES as u64),
+        Some(level)
+    );
+    assert_eq!(gameboy.cart.rom_bank(), 1);
+    assert_eq!(gameboy.symbolize(0x4000), symbol("UpdatePlayer", 0));
+    assert_eq!(gameboy.symbolize(0xC000), symbol("wPlayerTicks", 0));
+}
+
+#[test]
+fn symbolic_breakpoints() {
+    let mut gameboy = gameboy();
+    let breakpoint = Breakpoint::at_symbol("UpdatePlayer+3", gameboy.symbols()).unwrap();
+    assert_eq!((breakpoint.addr, breakpoint.bank), (0x4003, Some(1)));
+    let store = gameboy.add_breakpoint(breakpoint);
+    let main = Breakpoint::at_symbol("Main.loop", gameboy.symbols()).unwrap();
+    assert_eq!(main.bank, None);
+    let main = gameboy.add_breakpoint(main);
+
+    assert_eq!(
+        gameboy.run_until_breakpoint(FRAME_T_CYCLES as u64),
+        Some(main)
+    );
+    // Bank 2 has code at $4003 too, which doesn't stop
+    assert_eq!(
+        gameboy.run_until_breakpoint(FRAME_T_CYCLES as u64),
+        Some(store)
+    );
+    assert_eq!(gameboy.cart.rom_bank(), 1);
+    assert_eq!(gameboy.peek(0xC001), 1);
+    assert_eq!(gameboy.peek(0xC000), 0);
+    assert_eq!(
+        gameboy.run_until_breakpoint(FRAME_T_CYCLES as u64),
+        Some(main)
+    );
+}
+
+#[test]
+fn reports_use_the_names() {
+    let mut gameboy = gameboy();
+    gameboy.track_call_stack(true);
+    let store = Breakpoint::at_symbol("UpdatePlayer.store", gameboy.symbols()).unwrap();
+    gameboy.add_breakpoint(store);
+    gameboy.run_until_breakpoint(FRAME_T_CYCLES as u64).unwrap();
+    assert_eq!(
+        gameboy.call_stack_report(),
+        "01:4000 UpdatePlayer called from 0160 Main.loop+0xD\n"
+    );
+    gameboy.step_instruction();
+    let dump = gameboy.crash_dump().to_string();
+    assert!(dump.contains("\n     0160 Main.loop+0xD\n"), "{}", dump);
+    assert!(dump.contains("\n  01:4000 UpdatePlayer\n"), "{}", dump);
+    assert!(
+        dump.contains("\n  01:4003 UpdatePlayer.store\n"),
+        "{}",
+        dump
+    );
+    assert!(dump.contains("\n     0153 Main.loop\n"), "{}", dump);
+}
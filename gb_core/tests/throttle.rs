@@ -0,0 +1,138 @@
+use std::{cell::RefCell, rc::Rc, time::Duration};
+
+use gb_core::{
+    frontend::{HostClock, Throttle, MAX_LAG},
+    gameboy::{
+        ppu::{consts::FRAME_T_CYCLES, registers::LCDC},
+        Gameboy, ResetKind, T_CYCLES_PER_SECOND,
+    },
+};
+
+/// A clock that only moves when slept on, or when the test advances it
+#[derive(Default)]
+struct MockTime {
+    now: Duration,
+    slept: Duration,
+}
+
+#[derive(Default, Clone)]
+struct MockClock(Rc<RefCell<MockTime>>);
+
+impl MockClock {
+    fn advance(&self, duration: Duration) {
+        self.0.borrow_mut().now += duration;
+    }
+
+    /// Total time slept since the last call
+    fn take_slept(&self) -> Duration {
+        std::mem::take(&mut self.0.borrow_mut().slept)
+    }
+}
+
+impl HostClock for MockClock {
+    fn now(&mut self) -> Duration {
+        self.0.borrow().now
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        let mut time = self.0.borrow_mut();
+        time.now += duration;
+        time.slept += duration;
+    }
+}
+
+/// Allow for rounding in the conversion from cycles to `Duration`
+fn assert_close(actual: Duration, expected: Duration) {
+    let diff = actual.max(expected) - actual.min(expected);
+    assert!(
+        diff < Duration::from_micros(1),
+        "{:?} != {:?}",
+        actual,
+        expected
+    );
+}
+
+#[test]
+fn sleeps_for_emulated_time() {
+    let clock = MockClock::default();
+    let mut throttle = Throttle::with_clock(1.0, clock.clone());
+
+    throttle.sync(0);
+    assert_eq!(clock.take_slept(), Duration::ZERO);
+
+    throttle.sync(T_CYCLES_PER_SECOND);
+    assert_close(clock.take_slept(), Duration::from_secs(1));
+
+    // Time spent emulating counts towards the wait
+    clock.advance(Duration::from_millis(300));
+    throttle.sync(2 * T_CYCLES_PER_SECOND);
+    assert_close(clock.take_slept(), Duration::from_millis(700));
+}
+
+#[test]
+fn speed_multiplier() {
+    for (speed, expected) in [(2.0, 500), (4.0, 250), (0.5, 2000)] {
+        let clock = MockClock::default();
+        let mut throttle = Throttle::with_clock(speed, clock.clone());
+        throttle.sync(0);
+        throttle.sync(T_CYCLES_PER_SECOND);
+        assert_close(clock.take_slept(), Duration::from_millis(expected));
+    }
+}
+
+#[test]
+fn uncapped_never_sleeps() {
+    let clock = MockClock::default();
+    let mut throttle = Throttle::with_clock(f64::INFINITY, clock.clone());
+    for i in 0..10 {
+        throttle.sync(i * T_CYCLES_PER_SECOND);
+    }
+    assert_eq!(clock.take_slept(), Duration::ZERO);
+}
+
+#[test]
+fn resyncs_after_stall() {
+    let clock = MockClock::default();
+    let mut throttle = Throttle::with_clock(1.0, clock.clone());
+    throttle.sync(0);
+
+    // Small lag is made up by not sleeping
+    clock.advance(Duration::from_millis(1050));
+    throttle.sync(T_CYCLES_PER_SECOND);
+    assert_eq!(clock.take_slept(), Duration::ZERO);
+    throttle.sync(2 * T_CYCLES_PER_SECOND);
+    assert_close(clock.take_slept(), Duration::from_millis(950));
+
+    // A long stall is forgotten about instead of fast-forwarding
+    clock.advance(Duration::from_secs(10) + MAX_LAG);
+    throttle.sync(3 * T_CYCLES_PER_SECOND);
+    assert_eq!(clock.take_slept(), Duration::ZERO);
+    throttle.sync(4 * T_CYCLES_PER_SECOND);
+    assert_close(clock.take_slept(), Duration::from_secs(1));
+}
+
+#[test]
+fn cycle_counter() {
+    let mut gameboy = Gameboy::new(vec![0; 0x8000]).unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    assert_eq!(gameboy.cycles(), 0);
+
+    assert_eq!(gameboy.clock().t_cycles, 4);
+    assert_eq!(gameboy.cycles(), 4);
+
+    // NOP takes one M-cycle
+    assert_eq!(gameboy.step_instruction(), 4);
+
+    for _ in 0..FRAME_T_CYCLES / 4 {
+        gameboy.clock();
+    }
+    let cycles = gameboy.cycles();
+    assert_eq!(cycles, FRAME_T_CYCLES as u64 + 8);
+
+    // Turn the LCD off; the counter should keep going
+    gameboy.ppu.lcdc.remove(LCDC::LCD_ENABLE);
+    for _ in 0..100 {
+        gameboy.step_instruction();
+    }
+    assert_eq!(gameboy.cycles(), cycles + 400);
+}
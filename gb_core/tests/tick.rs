@@ -0,0 +1,89 @@
+use gb_core::gameboy::{ppu::consts::FRAME_T_CYCLES, Gameboy};
+
+/// Keeps writing an incrementing value over all of VRAM, so every frame looks different
+#[rustfmt::skip]
+const SCRIBBLE_VRAM: [u8; 15] = [
+    0x3E, 0xE4,       // LD A, $E4
+    0xE0, 0x47,       // LDH (BGP), A
+    0x21, 0x00, 0x80, // LD HL, $8000
+    0x22,             // LD (HL+), A
+    0x3C,             // INC A
+    0xCB, 0x6C,       // BIT 5, H
+    0x28, 0xFA,       // JR Z, -6
+    0x18, 0xF5,       // JR -11
+];
+
+fn gameboy(code: &[u8]) -> Gameboy {
+    Gameboy::with_program(code, 0x0150).unwrap()
+}
+
+/// Tick until a frame is completed, and return the number of ticks
+fn ticks_until_frame(gameboy: &mut Gameboy) -> u64 {
+    let mut ticks = 1;
+    while !gameboy.tick().frame_completed {
+        ticks += 1;
+    }
+    ticks
+}
+
+#[test]
+fn ticks_per_frame() {
+    let mut gameboy = gameboy(&SCRIBBLE_VRAM);
+    ticks_until_frame(&mut gameboy);
+    for _ in 0..3 {
+        assert_eq!(ticks_until_frame(&mut gameboy), FRAME_T_CYCLES as u64 / 4);
+    }
+}
+
+#[test]
+fn ticking_matches_run_frames() {
+    let mut ticked = gameboy(&SCRIBBLE_VRAM);
+    let mut vblanks = 0;
+    for _ in 0..3 {
+        // IF only shows a request being raised if it wasn't already pending
+        ticked.poke(0xFF0F, 0);
+        let mut info = ticked.tick();
+        while !info.frame_completed {
+            vblanks += (info.interrupts_raised & 1) as u32;
+            info = ticked.tick();
+        }
+        vblanks += (info.interrupts_raised & 1) as u32;
+    }
+
+    let mut run = gameboy(&SCRIBBLE_VRAM);
+    run.run_frames(3);
+
+    assert_eq!(ticked.cycles(), run.cycles());
+    assert_eq!(ticked.cpu.cpu.registers, run.cpu.cpu.registers);
+    assert!(ticked.get_frame().iter().eq(run.get_frame().iter()));
+    assert!((0x8000..=0x9FFF).all(|addr| ticked.peek(addr) == run.peek(addr)));
+    // The VBlank interrupt of the third frame is raised on a later tick
+    assert_eq!(vblanks, 2);
+}
+
+#[test]
+fn run_cycles_rounds_up_to_m_cycles() {
+    let mut gameboy = gameboy(&SCRIBBLE_VRAM);
+    assert_eq!(gameboy.run_cycles(10), 12);
+    assert_eq!(gameboy.run_cycles(8), 8);
+    assert_eq!(gameboy.cycles(), 20);
+}
+
+#[test]
+fn dma_drives_the_bus() {
+    #[rustfmt::skip]
+    let mut gameboy = gameboy(&[
+        0x3E, 0xC0, // LD A, $C0
+        0xE0, 0x46, // LDH (DMA), A
+        0x18, 0xFE, // JR -2
+    ]);
+    let infos: Vec<_> = (0..400).map(|_| gameboy.tick()).collect();
+    let first = infos.iter().position(|info| info.dma).unwrap();
+    let dma_ticks = infos.iter().filter(|info| info.dma).count();
+    assert!(infos[first..first + dma_ticks].iter().all(|info| info.dma));
+    assert_eq!(dma_ticks, 160);
+    // The CPU fetches the next opcode during the setup cycle after the write to DMA
+    assert_eq!(infos[first - 2].pins.addr(), 0xFF46);
+    assert!(infos[first - 1].is_fetch_cycle);
+    assert_eq!(infos[first + 1].pins.addr(), 0xC001);
+}
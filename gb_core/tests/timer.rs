@@ -0,0 +1,40 @@
+use gb_core::gameboy::Gameboy;
+
+/// Start the timer at 262144Hz, which counts on the falling edge of bit 3 of the system counter,
+/// then write to `$FF00 + target` 32 times, once every 7 M-cycles. Finally, copy TIMA to $FF80.
+#[rustfmt::skip]
+fn write_loop(target: u8) -> [u8; 20] {
+    [
+        0x3E, 0x05,       // LD A, $05
+        0xE0, 0x07,       // LDH (TAC), A
+        0xAF,             // XOR A
+        0xE0, 0x05,       // LDH (TIMA), A
+        0x06, 0x20,       // LD B, $20
+        0xE0, target,     // LDH (target), A
+        0x05,             // DEC B
+        0x20, 0xFB,       // JR NZ, -5
+        0xF0, 0x05,       // LDH A, (TIMA)
+        0xE0, 0x80,       // LDH ($80), A
+        0x18, 0xFE,       // JR -2
+    ]
+}
+
+fn run(code: [u8; 20]) -> u8 {
+    let mut gameboy = Gameboy::with_program(&code, 0x0150).unwrap();
+    gameboy.run_frames(1);
+    gameboy.peek(0xFF80)
+}
+
+#[test]
+fn div_write_on_set_bit_increments_tima() {
+    // Writing to HRAM instead takes the same time, so TIMA just counts every 4 M-cycles. Where the
+    // edges fall depends on the counter's value at $0100.
+    let free_running = run(write_loop(0x90));
+    assert_eq!(free_running, 56);
+
+    // Each DIV write resets the counter, so it only ever counts from 4 to 28 between writes.
+    // Bit 3 falls once on the way from 12 to 16, and again when the reset clears it at 28, so TIMA
+    // counts twice per write instead of 7/4 times. Without the second edge it would stay below 40.
+    let with_resets = run(write_loop(0x04));
+    assert_eq!(with_resets, 64);
+}
@@ -0,0 +1,166 @@
+use std::fmt::Write;
+
+use gb_core::gameboy::trace_diff::{
+    diff, DiffOptions, Divergence, Field, FieldDiff, Flag, Side, Tolerance, TraceDiffError,
+    TraceLine, TraceParseError,
+};
+
+/// A trace of `count` instructions stepping through NOPs from $0100
+fn trace(count: u16) -> Vec<TraceLine> {
+    (0..count)
+        .map(|i| TraceLine {
+            a: 0x01,
+            f: 0xB0,
+            c: 0x13,
+            e: 0xD8,
+            h: 0x01,
+            l: 0x4D,
+            sp: 0xFFFE,
+            pc: 0x0100 + i,
+            ..TraceLine::default()
+        })
+        .collect()
+}
+
+fn log(lines: &[TraceLine]) -> String {
+    lines.iter().fold(String::new(), |mut log, line| {
+        writeln!(log, "{}", line).unwrap();
+        log
+    })
+}
+
+fn run(ours: &str, theirs: &str, options: &DiffOptions) -> Option<Divergence> {
+    diff(ours.as_bytes(), theirs.as_bytes(), options).unwrap()
+}
+
+#[test]
+fn lines_round_trip() {
+    let text = "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02";
+    let line: TraceLine = text.parse().unwrap();
+    assert_eq!(line.pc, 0x0100);
+    assert_eq!(line.pcmem, [0x00, 0xC3, 0x13, 0x02]);
+    assert_eq!(line.to_string(), text);
+    assert_eq!(line.disassemble(), "nop");
+}
+
+#[test]
+fn finds_the_first_divergent_line() {
+    let ours = trace(50);
+    let mut theirs = ours.clone();
+    // Both Z and A differ at instruction 30, and H further on
+    theirs[30].f = 0x30;
+    theirs[30].a = 0x02;
+    theirs[40].h = 0x99;
+    let options = DiffOptions {
+        context: 4,
+        ..DiffOptions::default()
+    };
+    let divergence = run(&log(&ours), &log(&theirs), &options).unwrap();
+    assert_eq!(divergence.index, 30);
+    assert_eq!((divergence.line_ours, divergence.line_theirs), (31, 31));
+    assert_eq!(divergence.ours, Some(ours[30]));
+    assert_eq!(divergence.theirs, Some(theirs[30]));
+    assert_eq!(divergence.context, &ours[26..30]);
+    assert_eq!(
+        divergence.differences,
+        [
+            FieldDiff {
+                field: Field::A,
+                ours: 0x01,
+                theirs: 0x02
+            },
+            FieldDiff {
+                field: Field::Flag(Flag::Z),
+                ours: 1,
+                theirs: 0
+            },
+        ]
+    );
+
+    let report = divergence.to_string();
+    assert!(report.contains("instruction 30 (line 31 of ours, line 31 of theirs)"));
+    assert!(report.contains("$011D  nop"));
+    assert!(report.contains("flag Z: ours 1, theirs 0"));
+    assert!(report.contains("A: ours $01, theirs $02"));
+    assert!(!report.contains("$0119"));
+}
+
+#[test]
+fn identical_traces_and_blank_lines() {
+    let ours = log(&trace(20));
+    let theirs = ours.replace('\n', "\n\n");
+    assert_eq!(run(&ours, &theirs, &DiffOptions::default()), None);
+}
+
+#[test]
+fn a_trace_ending_early_is_a_divergence() {
+    let ours = trace(20);
+    let divergence = run(&log(&ours), &log(&ours[..12]), &DiffOptions::default()).unwrap();
+    assert_eq!(divergence.index, 12);
+    assert_eq!(divergence.ours, Some(ours[12]));
+    assert_eq!(divergence.theirs, None);
+    assert!(divergence.differences.is_empty());
+    assert!(divergence.to_string().contains("Their trace ends here"));
+}
+
+#[test]
+fn lenient_ignores_pcmem_in_io_registers() {
+    let mut ours = trace(3);
+    ours[2].pc = 0xFF4E;
+    ours[2].pcmem = [0x01, 0x02, 0x03, 0x04];
+    let mut theirs = ours.clone();
+    theirs[2].pcmem = [0xFF, 0xFF, 0x03, 0x04];
+    let (ours, theirs) = (log(&ours), log(&theirs));
+
+    let divergence = run(&ours, &theirs, &DiffOptions::default()).unwrap();
+    assert_eq!(divergence.index, 2);
+    let fields: Vec<_> = divergence.differences.iter().map(|d| d.field).collect();
+    assert_eq!(fields, [Field::Pcmem(0), Field::Pcmem(1)]);
+    assert!(divergence
+        .to_string()
+        .contains("PCMEM[1] ($FF4F): ours $02, theirs $FF"));
+    assert!(divergence.to_string().contains("their instruction: "));
+
+    let lenient = DiffOptions {
+        tolerance: Tolerance::Lenient,
+        ..DiffOptions::default()
+    };
+    assert_eq!(run(&ours, &theirs, &lenient), None);
+}
+
+#[test]
+fn malformed_lines() {
+    let good = log(&trace(3));
+    let cases = [
+        ("A:01 F:B0", TraceParseError::MissingField("B")),
+        ("A01", TraceParseError::MalformedField("A01".to_owned())),
+        ("Q:01", TraceParseError::UnknownField("Q".to_owned())),
+        ("A:01 A:02", TraceParseError::DuplicateField("A")),
+    ];
+    for (bad, expected) in cases {
+        let theirs = good.replacen(&trace(3)[1].to_string(), bad, 1);
+        match diff(good.as_bytes(), theirs.as_bytes(), &DiffOptions::default()) {
+            Err(TraceDiffError::Parse {
+                side: Side::Theirs,
+                line: 2,
+                error,
+            }) => assert_eq!(error, expected),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    let line = trace(1)[0].to_string();
+    for (from, to, field) in [
+        ("SP:FFFE", "SP:FFF", "SP"),
+        ("A:01", "A:0G", "A"),
+        ("PCMEM:00,00,00,00", "PCMEM:00,00,00", "PCMEM"),
+        ("PCMEM:00,00,00,00", "PCMEM:00,00,00,100", "PCMEM"),
+    ] {
+        let error = line.replace(from, to).parse::<TraceLine>().unwrap_err();
+        assert!(
+            matches!(error, TraceParseError::InvalidValue { field: f, .. } if f == field),
+            "{}",
+            error
+        );
+    }
+}
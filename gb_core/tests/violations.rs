@@ -0,0 +1,98 @@
+use gb_core::gameboy::{
+    cart::header::flat_rom,
+    events::{Event, EventMask, EventRecord},
+    ppu::consts::FRAME_T_CYCLES,
+    violations::{RomWritePolicy, Violation, VIOLATIONS_PER_FRAME},
+    Gameboy, ResetKind,
+};
+
+#[rustfmt::skip]
+const WRITE_TO_ROM: [u8; 7] = [
+    0x3E, 0x02,       // LD A, $02
+    0xEA, 0x00, 0x20, // LD ($2000), A
+    0x18, 0xFE,       // JR -2
+];
+
+fn violations(gameboy: &mut Gameboy, frames: u32) -> Vec<EventRecord> {
+    let receiver = gameboy.subscribe(EventMask::VIOLATION);
+    gameboy.run_frames(frames);
+    receiver.drain().collect()
+}
+
+/// A 64KiB MBC1 cartridge running `code` from $0150
+fn mbc1(code: &[u8]) -> Gameboy {
+    let mut rom = flat_rom(code, 0x0150, "").unwrap();
+    rom.resize(0x10000, 0);
+    rom[0x147] = 0x01;
+    rom[0x148] = 0x01;
+    let mut gameboy = Gameboy::new(rom).unwrap();
+    gameboy.reset(ResetKind::PowerCycle);
+    gameboy.cpu.cpu.registers.pc = 0x0150;
+    gameboy
+}
+
+#[test]
+fn rom_writes_without_a_mapper_are_reported() {
+    let mut gameboy = Gameboy::with_program(&WRITE_TO_ROM, 0x0150).unwrap();
+    assert_eq!(gameboy.rom_write_policy(), RomWritePolicy::MapperRegisters);
+    assert!(violations(&mut gameboy, 1).is_empty());
+
+    gameboy.set_rom_write_policy(RomWritePolicy::ReportViolation);
+    gameboy.cpu.cpu.registers.pc = 0x0150;
+    let events = violations(&mut gameboy, 1);
+    assert_eq!(events.len(), 1);
+    assert_eq!(
+        events[0].event,
+        Event::Violation(Violation::RomWrite {
+            pc: 0x0152,
+            addr: 0x2000,
+            value: 0x02
+        })
+    );
+}
+
+#[test]
+fn mapper_register_writes_are_not_violations() {
+    let mut gameboy = mbc1(&WRITE_TO_ROM);
+    assert!(gameboy.cart.is_mapper_register(0x2000));
+    gameboy.set_rom_write_policy(RomWritePolicy::ReportViolation);
+    assert!(violations(&mut gameboy, 1).is_empty());
+    assert_eq!(gameboy.cart.rom_bank(), 2);
+}
+
+#[test]
+fn ignored_rom_writes_leave_the_mapper_alone() {
+    let mut gameboy = mbc1(&WRITE_TO_ROM);
+    gameboy.set_rom_write_policy(RomWritePolicy::Ignore);
+    assert!(violations(&mut gameboy, 1).is_empty());
+    assert_eq!(gameboy.cart.rom_bank(), 1);
+}
+
+#[test]
+fn execution_from_vram_is_reported_a_limited_number_of_times() {
+    #[rustfmt::skip]
+    let mut gameboy = Gameboy::with_program(&[
+        0xAF,             // XOR A
+        0xE0, 0x40,       // LDH (LCDC), A
+        0x3E, 0x18,       // LD A, $18
+        0xEA, 0x00, 0x80, // LD ($8000), A
+        0x3E, 0xFE,       // LD A, $FE
+        0xEA, 0x01, 0x80, // LD ($8001), A
+        0xC3, 0x00, 0x80, // JP $8000
+    ], 0x0150).unwrap();
+    gameboy.set_execution_check(true);
+    // JR -2 in VRAM, which runs thousands of times a frame
+    let events = violations(&mut gameboy, 3);
+    assert!(events
+        .iter()
+        .all(|record| record.event == Event::Violation(Violation::Execution { pc: 0x8000 })));
+
+    let mut per_frame = std::collections::BTreeMap::new();
+    for record in &events {
+        *per_frame
+            .entry(record.cycle / FRAME_T_CYCLES as u64)
+            .or_insert(0) += 1;
+    }
+    assert!(per_frame.values().all(|&n| n <= VIOLATIONS_PER_FRAME));
+    assert!(per_frame.values().any(|&n| n == VIOLATIONS_PER_FRAME));
+}
@@ -0,0 +1,68 @@
+//! What the CPU reads from VRAM while the PPU is drawing
+
+use gb_core::gameboy::{cart::header, ppu::Ppu, AccuracyLevel, Gameboy};
+use gb_cpu::CpuOutputPins;
+
+/// Read $8010 after `dots` dots of the first line, which uses tile 1 and then tile 2
+fn read_vram(accuracy: AccuracyLevel, dots: usize) -> u8 {
+    let mut ppu = Ppu::new();
+    ppu.accuracy = accuracy;
+    ppu.bg_map_1[0] = 0x01;
+    ppu.bg_map_1[1] = 0x02;
+    ppu.tile_data[0x10..0x12].copy_from_slice(&[0xA5, 0x5A]);
+    ppu.tile_data[0x20..0x22].copy_from_slice(&[0xC3, 0x3C]);
+    for _ in 0..dots {
+        ppu.clock_t_state();
+    }
+    let mut data = 0xFF;
+    ppu.perform_io(CpuOutputPins::Read { addr: 0x8010 }, &mut data, &mut 0);
+    data
+}
+
+#[test]
+fn fast_reads_ff_during_mode_3() {
+    assert_eq!(read_vram(AccuracyLevel::Fast, 40), 0xA5);
+    for dots in 81..100 {
+        assert_eq!(read_vram(AccuracyLevel::Fast, dots), 0xFF, "dot {}", dots);
+    }
+}
+
+#[test]
+fn accurate_reads_what_the_fetcher_read() {
+    assert_eq!(read_vram(AccuracyLevel::Accurate, 40), 0xA5);
+    // Each fetch reads the tile number, then the two bytes of tile data, 2 dots apart, and then
+    // waits 2 dots to push the pixels
+    let expected = [
+        (81, 0x01),
+        (82, 0x01),
+        (83, 0xA5),
+        (85, 0x5A),
+        (88, 0x5A),
+        (89, 0x02),
+        (91, 0xC3),
+        (93, 0x3C),
+    ];
+    for (dots, value) in expected {
+        assert_eq!(
+            read_vram(AccuracyLevel::Accurate, dots),
+            value,
+            "dot {}",
+            dots
+        );
+    }
+}
+
+#[test]
+fn builder_sets_accuracy() {
+    let rom = header::flat_rom(&[0x18, 0xFE], 0x0150, "").unwrap();
+    let gameboy = Gameboy::new(rom.clone()).unwrap();
+    assert_eq!(gameboy.accuracy(), AccuracyLevel::Fast);
+
+    let gameboy = Gameboy::builder()
+        .rom(rom)
+        .accuracy(AccuracyLevel::Accurate)
+        .build()
+        .unwrap();
+    assert_eq!(gameboy.accuracy(), AccuracyLevel::Accurate);
+    assert_eq!(gameboy.ppu.accuracy, AccuracyLevel::Accurate);
+}
@@ -1 +1,841 @@
+//! A small SM83 assembler, for writing test programs and fixtures without hand-encoding bytes
+//!
+//! It reads the syntax written by [`disassembler`](crate::disassembler), which is close to RGBDS:
+//!
+//! ```
+//! # use gb_cpu::assembler::assemble;
+//! let code = assemble(
+//!     "
+//!     LY equ $FF44
+//!     .org $150
+//!     main:
+//!         ld hl, $C000
+//!     .loop:
+//!         ldh a, [LY]
+//!         ld [hl+], a
+//!         jr .loop
+//!     ",
+//! )
+//! .unwrap();
+//! assert_eq!(code, [0x21, 0x00, 0xC0, 0xF0, 0x44, 0x22, 0x18, 0xFB]);
+//! ```
+//!
+//! - Mnemonics and registers are case insensitive. Memory operands may use `[...]` or `(...)`,
+//!   `[hl+]` and `[hl-]` may be written `[hli]` and `[hld]`, and the `a,` of ALU instructions is
+//!   optional.
+//! - Labels end with `:`. Labels starting with `.` are local to the label before them.
+//! - `NAME equ value` defines a constant.
+//! - `.org addr` moves to `addr`, padding with zeros if anything has already been assembled. The
+//!   output starts at the first address assembled to.
+//! - `.db` and `.dw` emit comma-separated bytes and little-endian words.
+//! - Numbers are decimal, `$` or `0x` hex, or `%` or `0b` binary. Operands can add and subtract
+//!   numbers, symbols and `$`, the address of the current instruction.
+//! - `jr` takes the address to jump to, like `jp`, and works out the offset itself.
+//!
+//! Symbols can be used before they are defined, except in `.org`.
 
+use alloc::{
+    borrow::ToOwned,
+    collections::BTreeMap,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::{fmt, ops::RangeInclusive};
+
+/// Why a line couldn't be assembled, and where
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AsmError {
+    /// Starting from 1
+    pub line: usize,
+    /// The text that couldn't be assembled, or an empty string for the end of the line
+    pub token: String,
+    pub kind: AsmErrorKind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AsmErrorKind {
+    /// A token other than the one the syntax allows here
+    Expected(&'static str),
+    UnknownMnemonic,
+    /// The mnemonic exists, but not with these operands
+    InvalidOperands,
+    InvalidNumber,
+    UnknownSymbol,
+    DuplicateSymbol,
+    /// A number doesn't fit in its operand
+    OutOfRange,
+    /// The target of a `jr` is more than 128 bytes away
+    JumpTooFar,
+    /// `.org` to an address below one already assembled
+    OrgBackwards,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match self.kind {
+            AsmErrorKind::Expected(expected) if self.token.is_empty() => {
+                return write!(f, "expected {}, found the end of the line", expected)
+            }
+            AsmErrorKind::Expected(expected) => write!(f, "expected {}, found", expected)?,
+            AsmErrorKind::UnknownMnemonic => f.write_str("unknown mnemonic")?,
+            AsmErrorKind::InvalidOperands => f.write_str("invalid operands for")?,
+            AsmErrorKind::InvalidNumber => f.write_str("invalid number")?,
+            AsmErrorKind::UnknownSymbol => f.write_str("unknown symbol")?,
+            AsmErrorKind::DuplicateSymbol => f.write_str("symbol defined twice:")?,
+            AsmErrorKind::OutOfRange => f.write_str("value out of range:")?,
+            AsmErrorKind::JumpTooFar => f.write_str("relative jump too far:")?,
+            AsmErrorKind::OrgBackwards => f.write_str(".org before the current address:")?,
+        }
+        write!(f, " {:?}", self.token)
+    }
+}
+
+#[cfg(any(test, feature = "std"))]
+impl std::error::Error for AsmError {}
+
+/// Assembles `source` into the bytes from the first address it assembles to.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    let statements = parse(source)?;
+    let mut assembler = Assembler::default();
+    // Every instruction has the same length whatever its operands' values, so the first pass only
+    // needs to find where the labels are
+    assembler.pass(&statements)?;
+    assembler.final_pass = true;
+    assembler.pass(&statements)?;
+    Ok(assembler.out)
+}
+
+const ALU: [&str; 8] = ["add", "adc", "sub", "sbc", "and", "xor", "or", "cp"];
+const ROT: [&str; 8] = ["rlc", "rrc", "rl", "rr", "sla", "sra", "swap", "srl"];
+const BIT: [&str; 3] = ["bit", "res", "set"];
+const OTHERS: [&str; 11] = [
+    "ld", "ldh", "inc", "dec", "push", "pop", "jr", "jp", "call", "ret", "rst",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TokenKind {
+    Ident,
+    Number,
+    /// `$` on its own
+    Here,
+    Punct,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Token<'a> {
+    kind: TokenKind,
+    text: &'a str,
+}
+
+fn tokenize(line: &str, line_number: usize) -> Result<Vec<Token<'_>>, AsmError> {
+    let mut tokens = Vec::new();
+    let mut rest = line;
+    let word_len = |rest: &str| {
+        rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+            .unwrap_or(rest.len())
+    };
+    loop {
+        rest = rest.trim_start();
+        let Some(c) = rest.chars().next() else {
+            return Ok(tokens);
+        };
+        let (kind, len) = match c {
+            ';' => return Ok(tokens),
+            '$' if word_len(&rest[1..]) == 0 => (TokenKind::Here, 1),
+            '$' | '%' => (TokenKind::Number, 1 + word_len(&rest[1..])),
+            '0'..='9' => (TokenKind::Number, word_len(rest)),
+            'a'..='z' | 'A'..='Z' | '_' | '.' => (TokenKind::Ident, word_len(rest)),
+            ',' | '[' | ']' | '(' | ')' | '+' | '-' | ':' => (TokenKind::Punct, 1),
+            _ => {
+                return Err(AsmError {
+                    line: line_number,
+                    token: c.to_string(),
+                    kind: AsmErrorKind::Expected("an instruction, operand or directive"),
+                })
+            }
+        };
+        tokens.push(Token {
+            kind,
+            text: &rest[..len],
+        });
+        rest = &rest[len..];
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Term {
+    Number(i64),
+    Symbol(String),
+    Here,
+}
+
+#[derive(Clone, Debug)]
+struct Expr {
+    /// Terms to add, or subtract if the flag is set
+    terms: Vec<(bool, Term)>,
+    /// The source, for errors
+    text: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Reg16 {
+    BC,
+    DE,
+    HL,
+    SP,
+    AF,
+}
+
+#[derive(Clone, Debug)]
+enum Operand {
+    /// An index into table "r", so `[hl]` is 6
+    R8(u8),
+    R16(Reg16),
+    /// NZ, Z or NC. C is parsed as the register.
+    Condition(u8),
+    IndBC,
+    IndDE,
+    IndHLInc,
+    IndHLDec,
+    /// `[c]`, or `[$FF00 + c]`
+    IndC,
+    Ind(Expr),
+    /// `sp+e`
+    SpOffset(Expr),
+    Imm(Expr),
+}
+
+const A: u8 = 7;
+const IND_HL: u8 = 6;
+
+impl Operand {
+    /// The index into table "cc" of a condition
+    fn condition(&self) -> Option<u8> {
+        match *self {
+            Operand::Condition(cc) => Some(cc),
+            Operand::R8(1) => Some(3),
+            _ => None,
+        }
+    }
+
+    /// The index into table "rp" of a register pair, which doesn't include AF
+    fn rp(&self) -> Option<u8> {
+        match *self {
+            Operand::R16(Reg16::AF) => None,
+            Operand::R16(reg) => Some(reg as u8),
+            _ => None,
+        }
+    }
+
+    /// The index into table "rp2" of a register pair, which doesn't include SP
+    fn rp2(&self) -> Option<u8> {
+        match *self {
+            Operand::R16(Reg16::SP) => None,
+            Operand::R16(Reg16::AF) => Some(3),
+            Operand::R16(reg) => Some(reg as u8),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Body {
+    Empty,
+    Constant(String, Expr),
+    Org(Expr),
+    Bytes(Vec<Expr>),
+    Words(Vec<Expr>),
+    Instruction {
+        mnemonic: String,
+        operands: Vec<Operand>,
+        /// The source, for errors
+        text: String,
+    },
+}
+
+#[derive(Clone, Debug)]
+struct Statement {
+    line: usize,
+    label: Option<String>,
+    body: Body,
+}
+
+fn parse(source: &str) -> Result<Vec<Statement>, AsmError> {
+    let mut statements = Vec::new();
+    let mut scope = "";
+    for (i, text) in source.lines().enumerate() {
+        let mut parser = Parser {
+            text,
+            tokens: tokenize(text, i + 1)?,
+            pos: 0,
+            line: i + 1,
+            scope,
+        };
+        statements.push(parser.statement()?);
+        scope = parser.scope;
+    }
+    Ok(statements)
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    tokens: Vec<Token<'a>>,
+    pos: usize,
+    line: usize,
+    /// The last label not starting with `.`, which local labels belong to
+    scope: &'a str,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<Token<'a>> {
+        self.tokens.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        let token = self.peek();
+        self.pos += 1;
+        token
+    }
+
+    /// Consumes the next token if it is the punctuation `p`
+    fn eat(&mut self, p: &str) -> bool {
+        let found =
+            matches!(self.peek(), Some(token) if token.kind == TokenKind::Punct && token.text == p);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    /// Consumes the next token if it is the identifier `name`, ignoring case
+    fn eat_name(&mut self, name: &str) -> bool {
+        let found = matches!(self.peek(), Some(token) if token.kind == TokenKind::Ident && token.text.eq_ignore_ascii_case(name));
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn error(&self, token: Option<Token<'_>>, kind: AsmErrorKind) -> AsmError {
+        AsmError {
+            line: self.line,
+            token: token.map_or("", |token| token.text).to_owned(),
+            kind,
+        }
+    }
+
+    /// The source from the token at `start` up to the current one
+    fn source_from(&self, start: usize) -> &'a str {
+        let offset = |token: &Token<'_>| token.text.as_ptr() as usize - self.text.as_ptr() as usize;
+        match (
+            self.tokens.get(start),
+            self.tokens[..self.pos.min(self.tokens.len())].last(),
+        ) {
+            (Some(first), Some(last)) if start < self.pos => {
+                &self.text[offset(first)..offset(last) + last.text.len()]
+            }
+            _ => "",
+        }
+    }
+
+    /// Makes local labels into `global.local`
+    fn symbol_name(&self, name: &str) -> String {
+        if name.starts_with('.') {
+            let mut full = self.scope.to_owned();
+            full.push_str(name);
+            full
+        } else {
+            name.to_owned()
+        }
+    }
+
+    fn statement(&mut self) -> Result<Statement, AsmError> {
+        let mut statement = Statement {
+            line: self.line,
+            label: None,
+            body: Body::Empty,
+        };
+        if let [Token {
+            kind: TokenKind::Ident,
+            text: name,
+        }, Token {
+            kind: TokenKind::Punct,
+            text: ":",
+        }, ..] = self.tokens[..]
+        {
+            self.pos = 2;
+            if !name.starts_with('.') {
+                self.scope = name;
+            }
+            statement.label = Some(self.symbol_name(name));
+        }
+        let start = self.pos;
+        let Some(first) = self.next() else {
+            return Ok(statement);
+        };
+        if first.kind != TokenKind::Ident {
+            return Err(self.error(
+                Some(first),
+                AsmErrorKind::Expected("an instruction or directive"),
+            ));
+        }
+        statement.body = match first.text.to_ascii_lowercase().as_str() {
+            ".org" => Body::Org(self.expr()?),
+            ".db" => Body::Bytes(self.list()?),
+            ".dw" => Body::Words(self.list()?),
+            _ if self.eat_name("equ") => Body::Constant(self.symbol_name(first.text), self.expr()?),
+            mnemonic => {
+                let mut operands = Vec::new();
+                while self.peek().is_some() {
+                    if !operands.is_empty() && !self.eat(",") {
+                        return Err(self.error(self.peek(), AsmErrorKind::Expected("\",\"")));
+                    }
+                    operands.push(self.operand()?);
+                }
+                Body::Instruction {
+                    mnemonic: mnemonic.to_owned(),
+                    operands,
+                    text: self.source_from(start).to_owned(),
+                }
+            }
+        };
+        match self.peek() {
+            None => Ok(statement),
+            token => Err(self.error(token, AsmErrorKind::Expected("the end of the line"))),
+        }
+    }
+
+    fn list(&mut self) -> Result<Vec<Expr>, AsmError> {
+        let mut exprs = Vec::from([self.expr()?]);
+        while self.eat(",") {
+            exprs.push(self.expr()?);
+        }
+        Ok(exprs)
+    }
+
+    fn expr(&mut self) -> Result<Expr, AsmError> {
+        let start = self.pos;
+        let mut terms = Vec::new();
+        let mut negative = self.eat("-");
+        loop {
+            let token = self.next();
+            let term = match token {
+                Some(Token {
+                    kind: TokenKind::Number,
+                    text,
+                }) => Term::Number(
+                    parse_number(text)
+                        .ok_or_else(|| self.error(token, AsmErrorKind::InvalidNumber))?,
+                ),
+                Some(Token {
+                    kind: TokenKind::Ident,
+                    text,
+                }) => Term::Symbol(self.symbol_name(text)),
+                Some(Token {
+                    kind: TokenKind::Here,
+                    ..
+                }) => Term::Here,
+                _ => return Err(self.error(token, AsmErrorKind::Expected("a number or symbol"))),
+            };
+            terms.push((negative, term));
+            if self.eat("+") {
+                negative = false;
+            } else if self.eat("-") {
+                negative = true;
+            } else {
+                let text = self.source_from(start).to_owned();
+                return Ok(Expr { terms, text });
+            }
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand, AsmError> {
+        let close = if self.eat("[") {
+            "]"
+        } else if self.eat("(") {
+            ")"
+        } else if let Some(register) = self.register()? {
+            return Ok(register);
+        } else {
+            return Ok(Operand::Imm(self.expr()?));
+        };
+        let operand = if self.eat_name("hl") {
+            if self.eat("+") {
+                Operand::IndHLInc
+            } else if self.eat("-") {
+                Operand::IndHLDec
+            } else {
+                Operand::R8(IND_HL)
+            }
+        } else if self.eat_name("hli") {
+            Operand::IndHLInc
+        } else if self.eat_name("hld") {
+            Operand::IndHLDec
+        } else if self.eat_name("bc") {
+            Operand::IndBC
+        } else if self.eat_name("de") {
+            Operand::IndDE
+        } else if self.eat_name("c") {
+            Operand::IndC
+        } else {
+            let expr = self.expr()?;
+            match &expr.terms[..] {
+                [(false, Term::Number(0xFF00)), (false, Term::Symbol(c))]
+                    if c.eq_ignore_ascii_case("c") =>
+                {
+                    Operand::IndC
+                }
+                _ => Operand::Ind(expr),
+            }
+        };
+        if !self.eat(close) {
+            let expected = if close == "]" { "\"]\"" } else { "\")\"" };
+            return Err(self.error(self.peek(), AsmErrorKind::Expected(expected)));
+        }
+        Ok(operand)
+    }
+
+    /// A register or condition, or `sp+e`
+    fn register(&mut self) -> Result<Option<Operand>, AsmError> {
+        let Some(token) = self.peek().filter(|token| token.kind == TokenKind::Ident) else {
+            return Ok(None);
+        };
+        let register = match token.text.to_ascii_lowercase().as_str() {
+            "b" => Operand::R8(0),
+            "c" => Operand::R8(1),
+            "d" => Operand::R8(2),
+            "e" => Operand::R8(3),
+            "h" => Operand::R8(4),
+            "l" => Operand::R8(5),
+            "a" => Operand::R8(A),
+            "bc" => Operand::R16(Reg16::BC),
+            "de" => Operand::R16(Reg16::DE),
+            "hl" => Operand::R16(Reg16::HL),
+            "sp" => Operand::R16(Reg16::SP),
+            "af" => Operand::R16(Reg16::AF),
+            "nz" => Operand::Condition(0),
+            "z" => Operand::Condition(1),
+            "nc" => Operand::Condition(2),
+            _ => return Ok(None),
+        };
+        self.pos += 1;
+        if matches!(register, Operand::R16(Reg16::SP)) {
+            // `sp-e` is left for the expression to parse
+            if self.eat("+") || matches!(self.peek(), Some(token) if token.text == "-") {
+                return Ok(Some(Operand::SpOffset(self.expr()?)));
+            }
+        }
+        Ok(Some(register))
+    }
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    let lower = text.to_ascii_lowercase();
+    let (digits, radix) = if let Some(hex) = lower.strip_prefix('$').or(lower.strip_prefix("0x")) {
+        (hex, 16)
+    } else if let Some(bin) = lower.strip_prefix('%').or(lower.strip_prefix("0b")) {
+        (bin, 2)
+    } else {
+        (lower.as_str(), 10)
+    };
+    let value = i64::from_str_radix(digits, radix).ok()?;
+    (value <= 0xFFFF).then_some(value)
+}
+
+#[derive(Default)]
+struct Assembler {
+    symbols: BTreeMap<String, i64>,
+    out: Vec<u8>,
+    /// Set once something has been assembled, after which `.org` pads `out`
+    started: bool,
+    pc: u32,
+    /// The address of the current statement
+    here: u32,
+    line: usize,
+    /// Unknown symbols are errors and values are range checked, which the first pass can't do
+    final_pass: bool,
+}
+
+impl Assembler {
+    fn error(&self, token: &str, kind: AsmErrorKind) -> AsmError {
+        AsmError {
+            line: self.line,
+            token: token.to_owned(),
+            kind,
+        }
+    }
+
+    fn pass(&mut self, statements: &[Statement]) -> Result<(), AsmError> {
+        self.out.clear();
+        self.started = false;
+        self.pc = 0;
+        for statement in statements {
+            self.line = statement.line;
+            self.here = self.pc;
+            if let Some(label) = &statement.label {
+                self.define(label, self.pc as i64)?;
+            }
+            match &statement.body {
+                Body::Empty => {}
+                Body::Constant(name, expr) => {
+                    let value = self.eval(expr)?;
+                    self.define(name, value)?;
+                }
+                Body::Org(expr) => self.org(expr)?,
+                Body::Bytes(exprs) => {
+                    for expr in exprs {
+                        let byte = self.n8(expr)?;
+                        self.emit(&[byte], &expr.text)?;
+                    }
+                }
+                Body::Words(exprs) => {
+                    for expr in exprs {
+                        let word = self.n16(expr)?;
+                        self.emit(&word, &expr.text)?;
+                    }
+                }
+                Body::Instruction {
+                    mnemonic,
+                    operands,
+                    text,
+                } => match self.encode(mnemonic, operands)? {
+                    Some(bytes) => self.emit(&bytes, text)?,
+                    None if is_mnemonic(mnemonic) => {
+                        return Err(self.error(text, AsmErrorKind::InvalidOperands))
+                    }
+                    None => {
+                        let mnemonic = text.split_whitespace().next().unwrap_or_default();
+                        return Err(self.error(mnemonic, AsmErrorKind::UnknownMnemonic));
+                    }
+                },
+            }
+        }
+        Ok(())
+    }
+
+    fn define(&mut self, name: &str, value: i64) -> Result<(), AsmError> {
+        if self.symbols.insert(name.to_owned(), value).is_some() && !self.final_pass {
+            return Err(self.error(name, AsmErrorKind::DuplicateSymbol));
+        }
+        Ok(())
+    }
+
+    fn org(&mut self, expr: &Expr) -> Result<(), AsmError> {
+        // Both passes have to put the labels in the same place, so this can't be left unknown
+        let final_pass = core::mem::replace(&mut self.final_pass, true);
+        let addr = self.value(expr, 0..=0xFFFF);
+        self.final_pass = final_pass;
+        let addr = addr? as u32;
+        if self.started {
+            if addr < self.pc {
+                return Err(self.error(&expr.text, AsmErrorKind::OrgBackwards));
+            }
+            self.out
+                .resize(self.out.len() + (addr - self.pc) as usize, 0);
+        }
+        self.pc = addr;
+        Ok(())
+    }
+
+    fn emit(&mut self, bytes: &[u8], text: &str) -> Result<(), AsmError> {
+        if self.pc + bytes.len() as u32 > 0x10000 {
+            return Err(self.error(text, AsmErrorKind::OutOfRange));
+        }
+        self.started = true;
+        self.out.extend_from_slice(bytes);
+        self.pc += bytes.len() as u32;
+        Ok(())
+    }
+
+    fn eval(&self, expr: &Expr) -> Result<i64, AsmError> {
+        let mut value = 0;
+        for (negative, term) in &expr.terms {
+            let term = match term {
+                Term::Number(n) => *n,
+                Term::Here => self.here as i64,
+                Term::Symbol(name) => match self.symbols.get(name) {
+                    Some(&value) => value,
+                    None if !self.final_pass => 0,
+                    None => return Err(self.error(name, AsmErrorKind::UnknownSymbol)),
+                },
+            };
+            value = if *negative {
+                value - term
+            } else {
+                value + term
+            };
+        }
+        Ok(value)
+    }
+
+    /// Evaluates `expr`, checking it is in `range` on the final pass
+    fn value(&self, expr: &Expr, range: RangeInclusive<i64>) -> Result<i64, AsmError> {
+        let value = self.eval(expr)?;
+        if self.final_pass && !range.contains(&value) {
+            return Err(self.error(&expr.text, AsmErrorKind::OutOfRange));
+        }
+        Ok(value)
+    }
+
+    fn n8(&self, expr: &Expr) -> Result<u8, AsmError> {
+        Ok(self.value(expr, -0x80..=0xFF)? as u8)
+    }
+
+    fn e8(&self, expr: &Expr) -> Result<u8, AsmError> {
+        Ok(self.value(expr, -0x80..=0x7F)? as u8)
+    }
+
+    fn n16(&self, expr: &Expr) -> Result<[u8; 2], AsmError> {
+        Ok((self.value(expr, -0x8000..=0xFFFF)? as u16).to_le_bytes())
+    }
+
+    /// The offset to `target` from the end of a `jr`
+    fn relative(&self, target: &Expr) -> Result<u8, AsmError> {
+        let addr = self.value(target, 0..=0xFFFF)? as u16;
+        let offset = addr.wrapping_sub(self.here as u16).wrapping_sub(2) as i16;
+        if self.final_pass && i8::try_from(offset).is_err() {
+            return Err(self.error(&target.text, AsmErrorKind::JumpTooFar));
+        }
+        Ok(offset as u8)
+    }
+
+    /// The low byte of a `ldh` address, which can be given as $00-$FF or $FF00-$FFFF
+    fn high_page(&self, expr: &Expr) -> Result<u8, AsmError> {
+        let addr = self.eval(expr)?;
+        if self.final_pass && !(0..=0xFF).contains(&addr) && !(0xFF00..=0xFFFF).contains(&addr) {
+            return Err(self.error(&expr.text, AsmErrorKind::OutOfRange));
+        }
+        Ok(addr as u8)
+    }
+
+    /// Encodes an instruction, or returns `None` if there isn't one with these operands
+    fn encode(&self, mnemonic: &str, operands: &[Operand]) -> Result<Option<Vec<u8>>, AsmError> {
+        use Operand::*;
+
+        if let Some(opcode) = implied(mnemonic) {
+            return Ok(operands.is_empty().then(|| vec![opcode]));
+        }
+        if let Some(alu) = ALU.iter().position(|&name| name == mnemonic) {
+            let alu = alu as u8;
+            // The `a,` is optional
+            match operands {
+                [R8(A), R8(r)] | [R8(r)] => return Ok(Some(vec![0x80 | alu << 3 | r])),
+                [R8(A), Imm(expr)] | [Imm(expr)] => {
+                    return Ok(Some(vec![0xC6 | alu << 3, self.n8(expr)?]))
+                }
+                _ => {}
+            }
+        }
+        if let Some(rot) = ROT.iter().position(|&name| name == mnemonic) {
+            return Ok(match operands {
+                [R8(r)] => Some(vec![0xCB, (rot as u8) << 3 | r]),
+                _ => None,
+            });
+        }
+        if let Some(x) = BIT.iter().position(|&name| name == mnemonic) {
+            return Ok(match operands {
+                [Imm(bit), R8(r)] => {
+                    let bit = self.value(bit, 0..=7)? as u8;
+                    Some(vec![0xCB, (x as u8 + 1) << 6 | bit << 3 | r])
+                }
+                _ => None,
+            });
+        }
+
+        let condition = operands.first().and_then(Operand::condition);
+        let rp = operands.first().and_then(Operand::rp);
+        let rp2 = operands.first().and_then(Operand::rp2);
+        let with_n16 = |opcode: u8, expr: &Expr| -> Result<Vec<u8>, AsmError> {
+            let [low, high] = self.n16(expr)?;
+            Ok(vec![opcode, low, high])
+        };
+        let bytes = match (mnemonic, operands) {
+            ("ld", [R8(IND_HL), R8(IND_HL)]) => return Ok(None),
+            ("ld", [R8(d), R8(s)]) => vec![0x40 | d << 3 | s],
+            ("ld", [R8(d), Imm(expr)]) => vec![0x06 | d << 3, self.n8(expr)?],
+            ("ld", [R16(_), Imm(expr)]) if rp.is_some() => with_n16(0x01 | rp.unwrap() << 4, expr)?,
+            ("ld", [Ind(expr), R16(Reg16::SP)]) => with_n16(0x08, expr)?,
+            ("ld", [IndBC, R8(A)]) => vec![0x02],
+            ("ld", [IndDE, R8(A)]) => vec![0x12],
+            ("ld", [IndHLInc, R8(A)]) => vec![0x22],
+            ("ld", [IndHLDec, R8(A)]) => vec![0x32],
+            ("ld", [R8(A), IndBC]) => vec![0x0A],
+            ("ld", [R8(A), IndDE]) => vec![0x1A],
+            ("ld", [R8(A), IndHLInc]) => vec![0x2A],
+            ("ld", [R8(A), IndHLDec]) => vec![0x3A],
+            ("ld", [Ind(expr), R8(A)]) => with_n16(0xEA, expr)?,
+            ("ld", [R8(A), Ind(expr)]) => with_n16(0xFA, expr)?,
+            ("ld" | "ldh", [IndC, R8(A)]) => vec![0xE2],
+            ("ld" | "ldh", [R8(A), IndC]) => vec![0xF2],
+            ("ld", [R16(Reg16::SP), R16(Reg16::HL)]) => vec![0xF9],
+            ("ld", [R16(Reg16::HL), SpOffset(expr)]) => vec![0xF8, self.e8(expr)?],
+            ("ldh", [Ind(expr), R8(A)]) => vec![0xE0, self.high_page(expr)?],
+            ("ldh", [R8(A), Ind(expr)]) => vec![0xF0, self.high_page(expr)?],
+            ("add", [R16(Reg16::HL), R16(_)]) => match operands[1].rp() {
+                Some(rp) => vec![0x09 | rp << 4],
+                None => return Ok(None),
+            },
+            ("add", [R16(Reg16::SP), Imm(expr)]) => vec![0xE8, self.e8(expr)?],
+            ("inc", [R8(r)]) => vec![0x04 | r << 3],
+            ("dec", [R8(r)]) => vec![0x05 | r << 3],
+            ("inc", [_]) if rp.is_some() => vec![0x03 | rp.unwrap() << 4],
+            ("dec", [_]) if rp.is_some() => vec![0x0B | rp.unwrap() << 4],
+            ("push", [_]) if rp2.is_some() => vec![0xC5 | rp2.unwrap() << 4],
+            ("pop", [_]) if rp2.is_some() => vec![0xC1 | rp2.unwrap() << 4],
+            ("jr", [Imm(target)]) => vec![0x18, self.relative(target)?],
+            ("jr", [_, Imm(target)]) if condition.is_some() => {
+                vec![0x20 | condition.unwrap() << 3, self.relative(target)?]
+            }
+            ("jp", [Imm(expr)]) => with_n16(0xC3, expr)?,
+            ("jp", [_, Imm(expr)]) if condition.is_some() => {
+                with_n16(0xC2 | condition.unwrap() << 3, expr)?
+            }
+            ("jp", [R16(Reg16::HL) | R8(IND_HL)]) => vec![0xE9],
+            ("call", [Imm(expr)]) => with_n16(0xCD, expr)?,
+            ("call", [_, Imm(expr)]) if condition.is_some() => {
+                with_n16(0xC4 | condition.unwrap() << 3, expr)?
+            }
+            ("ret", []) => vec![0xC9],
+            ("ret", [_]) if condition.is_some() => vec![0xC0 | condition.unwrap() << 3],
+            ("rst", [Imm(expr)]) => {
+                let vector = self.eval(expr)?;
+                if self.final_pass && vector & !0x38 != 0 {
+                    return Err(self.error(&expr.text, AsmErrorKind::OutOfRange));
+                }
+                vec![0xC7 | (vector as u8 & 0x38)]
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(bytes))
+    }
+}
+
+/// The opcodes of instructions without operands
+fn implied(mnemonic: &str) -> Option<u8> {
+    Some(match mnemonic {
+        "nop" => 0x00,
+        "stop" => 0x10,
+        "halt" => 0x76,
+        "rlca" => 0x07,
+        "rrca" => 0x0F,
+        "rla" => 0x17,
+        "rra" => 0x1F,
+        "daa" => 0x27,
+        "cpl" => 0x2F,
+        "scf" => 0x37,
+        "ccf" => 0x3F,
+        "di" => 0xF3,
+        "ei" => 0xFB,
+        "reti" => 0xD9,
+        _ => return None,
+    })
+}
+
+fn is_mnemonic(mnemonic: &str) -> bool {
+    implied(mnemonic).is_some()
+        || [&ALU[..], &ROT, &BIT, &OTHERS]
+            .iter()
+            .any(|names| names.contains(&mnemonic))
+}
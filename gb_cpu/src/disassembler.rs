@@ -0,0 +1,106 @@
+//! Turns machine code back into the syntax read by `assembler`, which is behind the `asm` feature
+//!
+//! The syntax is close to RGBDS: lowercase mnemonics, memory operands in square brackets, and
+//! numbers in uppercase hex with a `$` prefix. Relative jumps are shown with the address they jump
+//! to rather than their offset, which is why [`disassemble`] needs to know where the instruction
+//! is. Opcodes that the CPU doesn't implement come out as `.db` directives.
+
+use alloc::{format, string::String};
+
+use crate::decode::Opcode;
+
+const R: [&str; 8] = ["b", "c", "d", "e", "h", "l", "[hl]", "a"];
+const RP: [&str; 4] = ["bc", "de", "hl", "sp"];
+const RP2: [&str; 4] = ["bc", "de", "hl", "af"];
+const CC: [&str; 4] = ["nz", "z", "nc", "c"];
+const ALU: [&str; 8] = [
+    "add a,", "adc a,", "sub", "sbc a,", "and", "xor", "or", "cp",
+];
+const ROT: [&str; 8] = ["rlc", "rrc", "rl", "rr", "sla", "sra", "swap", "srl"];
+const ACCUMULATOR: [&str; 8] = ["rlca", "rrca", "rla", "rra", "daa", "cpl", "scf", "ccf"];
+
+/// Disassembles the instruction at the start of `bytes`, which is at `addr` in memory.
+///
+/// Returns its text and length, or `None` if `bytes` ends before the instruction does.
+pub fn disassemble(bytes: &[u8], addr: u16) -> Option<(String, u16)> {
+    let opcode = Opcode(*bytes.first()?);
+    let (x, y, z, p, q) = (opcode.x(), opcode.y(), opcode.z(), opcode.p(), opcode.q());
+    let n8 = || bytes.get(1).copied();
+    let n16 = || Some(u16::from_le_bytes([*bytes.get(1)?, *bytes.get(2)?]));
+    let e8 = || n8().map(|e| e as i8);
+    let target = || e8().map(|e| addr.wrapping_add(2).wrapping_add(e as u16));
+
+    let (text, len) = match (x, z) {
+        (0, 0) => match y {
+            0 => ("nop".into(), 1),
+            1 => (format!("ld [${:04X}], sp", n16()?), 3),
+            2 => ("stop".into(), 1),
+            3 => (format!("jr ${:04X}", target()?), 2),
+            _ => (format!("jr {}, ${:04X}", CC[y as usize - 4], target()?), 2),
+        },
+        (0, 1) if q == 0 => (format!("ld {}, ${:04X}", RP[p as usize], n16()?), 3),
+        (0, 1) => (format!("add hl, {}", RP[p as usize]), 1),
+        (0, 2) => {
+            let indirect = ["[bc]", "[de]", "[hl+]", "[hl-]"][p as usize];
+            if q == 0 {
+                (format!("ld {}, a", indirect), 1)
+            } else {
+                (format!("ld a, {}", indirect), 1)
+            }
+        }
+        (0, 3) => (
+            format!("{} {}", ["inc", "dec"][q as usize], RP[p as usize]),
+            1,
+        ),
+        (0, 4) => (format!("inc {}", R[y as usize]), 1),
+        (0, 5) => (format!("dec {}", R[y as usize]), 1),
+        (0, 6) => (format!("ld {}, ${:02X}", R[y as usize], n8()?), 2),
+        (0, _) => (ACCUMULATOR[y as usize].into(), 1),
+        (1, 6) if y == 6 => ("halt".into(), 1),
+        (1, _) => (format!("ld {}, {}", R[y as usize], R[z as usize]), 1),
+        (2, _) => (format!("{} {}", ALU[y as usize], R[z as usize]), 1),
+        (3, 0) => match y {
+            0..=3 => (format!("ret {}", CC[y as usize]), 1),
+            4 => (format!("ldh [$FF{:02X}], a", n8()?), 2),
+            5 => (format!("add sp, {}", e8()?), 2),
+            6 => (format!("ldh a, [$FF{:02X}]", n8()?), 2),
+            _ => match e8()? {
+                e if e < 0 => (format!("ld hl, sp{}", e), 2),
+                e => (format!("ld hl, sp+{}", e), 2),
+            },
+        },
+        (3, 1) if q == 0 => (format!("pop {}", RP2[p as usize]), 1),
+        (3, 1) => (["ret", "reti", "jp hl", "ld sp, hl"][p as usize].into(), 1),
+        (3, 2) => match y {
+            0..=3 => (format!("jp {}, ${:04X}", CC[y as usize], n16()?), 3),
+            4 => ("ldh [c], a".into(), 1),
+            5 => (format!("ld [${:04X}], a", n16()?), 3),
+            6 => ("ldh a, [c]".into(), 1),
+            _ => (format!("ld a, [${:04X}]", n16()?), 3),
+        },
+        (3, 3) => match y {
+            0 => (format!("jp ${:04X}", n16()?), 3),
+            1 => (disassemble_cb(n8()?), 2),
+            6 => ("di".into(), 1),
+            7 => ("ei".into(), 1),
+            _ => (format!(".db ${:02X}", opcode.0), 1),
+        },
+        (3, 4) if y < 4 => (format!("call {}, ${:04X}", CC[y as usize], n16()?), 3),
+        (3, 5) if q == 0 => (format!("push {}", RP2[p as usize]), 1),
+        (3, 5) if p == 0 => (format!("call ${:04X}", n16()?), 3),
+        (3, 6) => (format!("{} ${:02X}", ALU[y as usize], n8()?), 2),
+        (3, 7) => (format!("rst ${:02X}", y * 8), 1),
+        _ => (format!(".db ${:02X}", opcode.0), 1),
+    };
+    Some((text, len))
+}
+
+/// The instruction after a $CB prefix
+fn disassemble_cb(opcode: u8) -> String {
+    let opcode = Opcode(opcode);
+    let (y, r) = (opcode.y(), R[opcode.z() as usize]);
+    match opcode.x() {
+        0 => format!("{} {}", ROT[y as usize], r),
+        x => format!("{} {}, {}", ["bit", "res", "set"][x as usize - 1], y, r),
+    }
+}
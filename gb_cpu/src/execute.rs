@@ -2,6 +2,7 @@
 
 use super::decode;
 use super::{CpuInputPins, CpuOutputPins, FRegister};
+use alloc::boxed::Box;
 
 impl super::Cpu {
     /// Set the output pins to fetch the memory located at the address in the PC register, and then increment the PC register.
@@ -365,29 +366,91 @@ pub struct CpuRunnerYield {
     pub pins: CpuOutputPins,
     /// Indicates that the CPU is fetching the next opcode. Used for debug purposes.
     pub is_fetch_cycle: bool,
+    /// The CPU is halted, waiting for an interrupt, and does nothing else this cycle
+    pub is_halted_cycle: bool,
+    /// The cycle is one of the 5 the CPU spends dispatching an interrupt
+    pub is_dispatch_cycle: bool,
+    /// The value being incremented or decremented by the 16-bit increment/decrement unit this
+    /// cycle, if it is in use (not counting PC). The value is also placed on the address bus, which
+    /// causes the DMG's OAM corruption bug if it points into OAM.
+    pub inc_dec: Option<u16>,
+    /// Set on the last cycle of the interrupt service routine
+    pub interrupt: Option<InterruptDispatch>,
+    /// The bit of IF to clear, set on the cycle the interrupt service routine decides which
+    /// interrupt it is servicing
+    pub acknowledge: Option<u8>,
 }
 
-type CpuRunnerGen = std::pin::Pin<
+/// An interrupt that the CPU has started servicing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InterruptDispatch {
+    /// Address of the interrupt handler, or $0000 if the interrupt was cancelled because IE
+    /// changed partway through
+    pub vector: u16,
+    /// The address that was pushed onto the stack, which execution returns to afterwards
+    pub return_addr: u16,
+}
+
+/// Only ever resumed through `&mut`, so it doesn't need to be `Sync`
+type CpuRunnerGen = core::pin::Pin<
     Box<
-        dyn std::ops::Coroutine<
+        dyn core::ops::Coroutine<
                 (super::Cpu, CpuInputPins),
                 Yield = (super::Cpu, CpuRunnerYield),
                 Return = !,
-            > + Send
-            + Sync,
+            > + Send,
     >,
 >;
 
 /// Provides a wrapper to use around the generator underneath the CPU execution logic.
 pub struct CpuRunner {
     pub cpu: super::Cpu,
+    /// Only resumed by `clock`, which needs the `unstable-internals` feature
+    #[cfg_attr(not(any(test, feature = "unstable-internals")), allow(dead_code))]
     gen: CpuRunnerGen,
 }
 
+/// The parts of the CPU's state that decide what it does before fetching the next opcode
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CpuResumeFlags {
+    /// Waiting in HALT for an interrupt
+    pub halted: bool,
+    /// Hung by an illegal opcode
+    pub locked: bool,
+    /// EI was the last instruction, so IME is set after the next fetch
+    pub ime_scheduled: bool,
+}
+
 impl CpuRunner {
-    /// Clock the CPU by exactly one M-cycle
+    /// A runner that picks up from `cpu` between two instructions, so that the next `clock`
+    /// fetches the opcode at PC, unless `flags` says the CPU is halted or locked up, or an
+    /// interrupt is dispatched first. The flags replace the ones in `cpu`.
+    ///
+    /// The state must have been taken at an instruction boundary, with PC pointing at the next
+    /// opcode. A [`CpuRunner::cpu`] taken partway through an instruction can't be resumed, since
+    /// the rest of the instruction only exists inside the old runner.
+    pub fn from_state(mut cpu: super::Cpu, flags: CpuResumeFlags) -> Self {
+        cpu.halted = flags.halted;
+        cpu.locked = flags.locked;
+        cpu.ei_pending = flags.ime_scheduled;
+        cpu.runner()
+    }
+
+    /// The flags to give [`CpuRunner::from_state`] along with [`CpuRunner::cpu`]
+    pub fn state_flags(&self) -> CpuResumeFlags {
+        CpuResumeFlags {
+            halted: self.cpu.halted,
+            locked: self.cpu.locked,
+            ime_scheduled: self.cpu.ei_pending,
+        }
+    }
+
+    /// Clock the CPU by exactly one M-cycle. What it returns follows how the CPU is emulated, so
+    /// this is only public with the `unstable-internals` feature.
+    #[cfg(any(test, feature = "unstable-internals"))]
     pub fn clock(&mut self, pins: CpuInputPins) -> CpuRunnerYield {
-        use std::ops::CoroutineState;
+        use core::ops::CoroutineState;
         match self.gen.as_mut().resume((self.cpu, pins)) {
             CoroutineState::Yielded((cpu, pins_out)) => {
                 self.cpu = cpu;
@@ -398,33 +461,57 @@ impl CpuRunner {
     }
 }
 
-impl std::fmt::Debug for CpuRunner {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl core::fmt::Debug for CpuRunner {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("CpuRunner")
             .field("Cpu", &self.cpu)
             .finish_non_exhaustive()
     }
 }
 
+/// The highest priority interrupt that is both enabled and requested, as a bit number of IF
+fn pending_interrupt(pins: &CpuInputPins) -> Option<u8> {
+    [
+        pins.interrupt_40h,
+        pins.interrupt_48h,
+        pins.interrupt_50h,
+        pins.interrupt_58h,
+        pins.interrupt_60h,
+    ]
+    .iter()
+    .position(|&requested| requested)
+    .map(|bit| bit as u8)
+}
+
 /// Yields a generator containing state that will run the cpu
-fn cpu_runner_gen() -> impl std::ops::Coroutine<
+fn cpu_runner_gen() -> impl core::ops::Coroutine<
     (super::Cpu, CpuInputPins),
     Yield = (super::Cpu, CpuRunnerYield),
     Return = !,
-> + Send
-       + Sync {
+> + Send {
     // Every `yield` here will cause the CPU to wait for one memory cycle.
     #[allow(unused_assignments)]
     move |t: (super::Cpu, CpuInputPins)| {
         let (mut cpu, mut pins) = t;
-        let mut halted = false;
         let mut fetch = false;
+        let mut halted = false;
+        let mut dispatching = false;
+        let mut dispatch = None;
+        let mut acknowledge = None;
         loop {
             macro_rules! cpu_yield {
                 ($pins:expr) => {
+                    cpu_yield!($pins, None);
+                };
+                ($pins:expr, $inc_dec:expr) => {
                     let _yielded = CpuRunnerYield {
                         pins: $pins,
                         is_fetch_cycle: fetch,
+                        is_halted_cycle: halted,
+                        is_dispatch_cycle: dispatching,
+                        inc_dec: $inc_dec,
+                        interrupt: dispatch.take(),
+                        acknowledge: acknowledge.take(),
                     };
                     (cpu, pins) = yield (cpu, _yielded);
                 };
@@ -471,33 +558,22 @@ fn cpu_runner_gen() -> impl std::ops::Coroutine<
                 };
             }
 
+            // Illegal opcodes hang the CPU, and interrupts can't wake it
+            if cpu.locked {
+                cpu_yield!(cpu.nop());
+                continue;
+            }
+
             // Handle interrupts
-            let interrupt = if pins.interrupt_40h {
-                Some(0x40)
-            } else if pins.interrupt_48h {
-                Some(0x48)
-            } else if pins.interrupt_50h {
-                Some(0x50)
-            } else if pins.interrupt_58h {
-                Some(0x58)
-            } else if pins.interrupt_60h {
-                Some(0x60)
-            } else {
-                None
-            };
-
-            if let Some(vector) = interrupt {
-                halted = false;
+            if pending_interrupt(&pins).is_some() {
+                cpu.halted = false;
                 if cpu.ime {
                     // Interrupt Service Routine (5 clock cycles)
                     // https://gbdev.io/pandocs/Interrupts.html#interrupt-handling
-
-                    // Normally, these two cycles would both be NOPs, but in this architecture we
-                    // have to take advantage of these cycles to reset the IF flag.
-                    cpu_yield!(cpu.read_byte(0xFF0F));
-                    let interrupt_flag = pins.data;
-                    let if_mask = !(1 << ((vector - 0x40) / 8));
-                    cpu_yield!(cpu.write_byte(0xFF0F, interrupt_flag & if_mask));
+                    cpu.ime = false;
+                    dispatching = true;
+                    cpu_yield!(cpu.nop());
+                    cpu_yield!(cpu.nop());
 
                     let pc = cpu.registers.get_pc();
                     let pc_lo = (pc & 0xFF) as u8;
@@ -506,20 +582,32 @@ fn cpu_runner_gen() -> impl std::ops::Coroutine<
                     // Push PC onto the stack
                     cpu.registers.modify_sp(|sp| sp.wrapping_sub(1));
                     cpu_yield!(cpu.write_byte(cpu.registers.get_sp(), pc_hi));
+
+                    // The vector is only decided now, after the upper byte has been pushed. If
+                    // that push overwrote IE, a different interrupt may be serviced instead, or
+                    // none at all, in which case the CPU jumps to $0000 and IF is left alone.
+                    let interrupt = pending_interrupt(&pins);
+                    let vector = interrupt.map_or(0x0000, |bit| 0x40 + bit as u16 * 8);
+                    acknowledge = interrupt;
                     cpu.registers.modify_sp(|sp| sp.wrapping_sub(1));
                     cpu_yield!(cpu.write_byte(cpu.registers.get_sp(), pc_lo));
 
                     cpu.registers.set_pc(vector);
 
-                    cpu.ime = false;
-
+                    dispatch = Some(InterruptDispatch {
+                        vector,
+                        return_addr: pc,
+                    });
                     cpu_yield!(cpu.nop());
+                    dispatching = false;
                 }
             }
 
             // If the CPU is halted, stop processing instructions, and wait for an interrupt to wake up the CPU.
-            if halted {
+            if cpu.halted {
+                halted = true;
                 cpu_yield!(cpu.nop());
+                halted = false;
                 continue;
             }
 
@@ -528,6 +616,10 @@ fn cpu_runner_gen() -> impl std::ops::Coroutine<
             cpu_yield!(cpu.fetch_byte());
             fetch = false;
             let opcode = super::decode::Opcode(pins.data);
+            if cpu.ei_pending {
+                cpu.ei_pending = false;
+                cpu.ime = true;
+            }
 
             // Decode & execute
             //
@@ -554,20 +646,19 @@ fn cpu_runner_gen() -> impl std::ops::Coroutine<
                             let sp_hi = (sp >> 8) as u8;
 
                             cpu_yield!(cpu.write_byte(addr, sp_lo));
-                            cpu_yield!(cpu.write_byte(addr + 1, sp_hi));
+                            cpu_yield!(cpu.write_byte(addr.wrapping_add(1), sp_hi));
                             continue;
                         }
                         2 => {
                             // STOP
                             // STOP is too wierd. just alias it to HALT for now
-                            halted = true;
+                            cpu.halted = true;
                         }
                         3 => {
                             // JR d
                             cpu_yield!(cpu.fetch_byte());
-                            let offset = pins.data as i8 as i16;
-                            let pc = cpu.registers.get_pc() as i16;
-                            let new_pc = (pc + offset) as u16;
+                            let offset = pins.data as i8 as u16;
+                            let new_pc = cpu.registers.get_pc().wrapping_add(offset);
                             cpu.registers.set_pc(new_pc);
 
                             cpu_yield!(cpu.nop());
@@ -578,11 +669,10 @@ fn cpu_runner_gen() -> impl std::ops::Coroutine<
                             // JR d
                             let cond = decode::cc(y - 4);
                             cpu_yield!(cpu.fetch_byte());
-                            let offset = pins.data as i8 as i16;
+                            let offset = pins.data as i8 as u16;
 
                             if cpu.test_condition(cond) {
-                                let pc = cpu.registers.get_pc() as i16;
-                                let new_pc = (pc + offset) as u16;
+                                let new_pc = cpu.registers.get_pc().wrapping_add(offset);
                                 cpu.registers.set_pc(new_pc);
 
                                 cpu_yield!(cpu.nop());
@@ -645,8 +735,9 @@ fn cpu_runner_gen() -> impl std::ops::Coroutine<
                             }
                             _ => unreachable!(),
                         };
+                        let inc_dec = (opcode.p() >= 2).then_some(addr);
 
-                        cpu_yield!(cpu.write_byte(addr, cpu.registers.get_a()));
+                        cpu_yield!(cpu.write_byte(addr, cpu.registers.get_a()), inc_dec);
                     }
                     2 if opcode.q() == 1 => {
                         // LD from memory
@@ -665,8 +756,9 @@ fn cpu_runner_gen() -> impl std::ops::Coroutine<
                             }
                             _ => unreachable!(),
                         };
+                        let inc_dec = (opcode.p() >= 2).then_some(addr);
 
-                        cpu_yield!(cpu.read_byte(addr));
+                        cpu_yield!(cpu.read_byte(addr), inc_dec);
                         cpu.registers.set_a(pins.data);
                         continue;
                     }
@@ -677,7 +769,7 @@ fn cpu_runner_gen() -> impl std::ops::Coroutine<
                         let v = cpu.read_16_bits(dst);
                         let nv = v.wrapping_add(1);
                         // Pause for a cycle
-                        cpu_yield!(cpu.nop());
+                        cpu_yield!(cpu.nop(), Some(v));
                         cpu.store_16_bits(nv, dst);
                         continue;
                     }
@@ -688,7 +780,7 @@ fn cpu_runner_gen() -> impl std::ops::Coroutine<
                         let v = cpu.read_16_bits(dst);
                         let nv = v.wrapping_sub(1);
                         // Pause for a cycle
-                        cpu_yield!(cpu.nop());
+                        cpu_yield!(cpu.nop(), Some(v));
                         cpu.store_16_bits(nv, dst);
                         continue;
                     }
@@ -818,7 +910,7 @@ fn cpu_runner_gen() -> impl std::ops::Coroutine<
                 },
                 1 if opcode.z() == 6 && opcode.y() == 6 => {
                     // HALT
-                    halted = true;
+                    cpu.halted = true;
                     continue;
                 }
                 1 => {
@@ -925,10 +1017,12 @@ fn cpu_runner_gen() -> impl std::ops::Coroutine<
                         // POP
                         let dst = decode::rp2(opcode.p());
 
-                        cpu_yield!(cpu.read_byte(cpu.registers.get_sp()));
+                        let sp = cpu.registers.get_sp();
+                        cpu_yield!(cpu.read_byte(sp), Some(sp));
                         let low = pins.data;
                         cpu.registers.modify_sp(|sp| sp.wrapping_add(1));
-                        cpu_yield!(cpu.read_byte(cpu.registers.get_sp()));
+                        let sp = cpu.registers.get_sp();
+                        cpu_yield!(cpu.read_byte(sp), Some(sp));
                         let high = pins.data;
                         cpu.registers.modify_sp(|sp| sp.wrapping_add(1));
 
@@ -1055,6 +1149,8 @@ fn cpu_runner_gen() -> impl std::ops::Coroutine<
 
                             let addr = ((high as u16) << 8) | (low as u16);
                             cpu.registers.set_pc(addr);
+                            // Pause for a cycle
+                            cpu_yield!(cpu.nop());
                             continue;
                         }
                         1 => {
@@ -1106,10 +1202,14 @@ fn cpu_runner_gen() -> impl std::ops::Coroutine<
                         }
                         7 => {
                             // EI
-                            cpu.ime = true;
+                            cpu.ei_pending = true;
+                            continue;
+                        }
+                        _ => {
+                            // Illegal opcode
+                            cpu.locked = true;
                             continue;
                         }
-                        _ => panic!("Unidentified opcode: {:?}, {:X?}", cpu, opcode),
                     },
                     4 => match opcode.y() {
                         y @ 0..=3 => {
@@ -1140,7 +1240,11 @@ fn cpu_runner_gen() -> impl std::ops::Coroutine<
                                 continue;
                             }
                         }
-                        4..=7 => panic!(),
+                        4..=7 => {
+                            // Illegal opcode
+                            cpu.locked = true;
+                            continue;
+                        }
                         _ => unreachable!(),
                     },
                     5 if opcode.q() == 0 => {
@@ -1148,9 +1252,12 @@ fn cpu_runner_gen() -> impl std::ops::Coroutine<
                         let from = decode::rp2(opcode.p());
                         let v = cpu.read_16_bits(from);
 
+                        // Pause for a cycle
+                        cpu_yield!(cpu.nop(), Some(cpu.registers.get_sp()));
                         cpu.registers.modify_sp(|sp| sp.wrapping_sub(1));
                         let high = (v >> 8) as u8;
-                        cpu_yield!(cpu.write_byte(cpu.registers.get_sp(), high));
+                        let sp = cpu.registers.get_sp();
+                        cpu_yield!(cpu.write_byte(sp, high), Some(sp));
                         cpu.registers.modify_sp(|sp| sp.wrapping_sub(1));
                         let low = (v & 0x00ff) as u8;
                         cpu_yield!(cpu.write_byte(cpu.registers.get_sp(), low));
@@ -1181,7 +1288,11 @@ fn cpu_runner_gen() -> impl std::ops::Coroutine<
 
                             continue;
                         }
-                        1..=3 => panic!(),
+                        1..=3 => {
+                            // Illegal opcode
+                            cpu.locked = true;
+                            continue;
+                        }
                         _ => unreachable!(),
                     },
                     6 => {
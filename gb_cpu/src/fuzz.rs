@@ -0,0 +1,381 @@
+//! Differential testing of [`CpuRunner`] against the [`Reference`] interpreter.
+//!
+//! Random instruction sequences are run on both, and the registers, memory and cycle counts are
+//! compared after every instruction. The number of sequences and the seed can be changed with the
+//! `GB_CPU_FUZZ_ITERATIONS` and `GB_CPU_FUZZ_SEED` environment variables.
+//!
+//! The same programs also check that a runner restored with [`CpuRunner::from_state`] partway
+//! through carries on exactly like the one it was restored from.
+
+use std::{boxed::Box, env, fmt::Write, string::String, vec, vec::Vec};
+
+use crate::{
+    reference::{Bus, Reference},
+    Cpu, CpuInputPins, CpuOutputPins, CpuRunner, CpuRunnerYield, FRegister, Registers,
+};
+
+const DEFAULT_ITERATIONS: u64 = 300;
+const DEFAULT_SEED: u64 = 0x5EED;
+const MAX_INSTRUCTIONS: usize = 48;
+const CODE_START: u16 = 0x0100;
+/// Writes below here are ignored, like a cartridge ROM, so that the code can't be overwritten
+const RAM_START: u16 = 0x8000;
+
+/// xorshift64*
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn byte(&mut self) -> u8 {
+        (self.next() >> 56) as u8
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+/// 64KB of flat memory
+#[derive(Clone, PartialEq, Eq)]
+struct Ram(Box<[u8; 0x10000]>);
+
+impl Bus for Ram {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.0[addr as usize]
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        if addr >= RAM_START {
+            self.0[addr as usize] = data;
+        }
+    }
+}
+
+/// Length in bytes of the instruction starting with `opcode`, or `None` if it is illegal or can't
+/// be tested. HALT and STOP are excluded, since nothing can wake the CPU up again.
+fn instruction_length(opcode: u8) -> Option<u16> {
+    match opcode {
+        0xD3 | 0xDB | 0xDD | 0xE3 | 0xE4 | 0xEB | 0xEC | 0xED | 0xF4 | 0xFC | 0xFD => None,
+        0x10 | 0x76 => None,
+        // LD rr, nn; LD (nn), SP; JP; CALL; LD (nn), A; LD A, (nn)
+        0x01 | 0x11 | 0x21 | 0x31 | 0x08 => Some(3),
+        0xC2 | 0xC3 | 0xCA | 0xD2 | 0xDA | 0xEA | 0xFA => Some(3),
+        0xC4 | 0xCC | 0xCD | 0xD4 | 0xDC => Some(3),
+        // JR; LD r, n; ALU n; LDH; ADD SP, e; LD HL, SP+e; CB prefix
+        0x18 | 0x20 | 0x28 | 0x30 | 0x38 => Some(2),
+        0x06 | 0x0E | 0x16 | 0x1E | 0x26 | 0x2E | 0x36 | 0x3E => Some(2),
+        0xC6 | 0xCE | 0xD6 | 0xDE | 0xE6 | 0xEE | 0xF6 | 0xFE => Some(2),
+        0xE0 | 0xF0 | 0xE8 | 0xF8 | 0xCB => Some(2),
+        _ => Some(1),
+    }
+}
+
+struct Program {
+    /// The bytes of each instruction, which are laid out contiguously from `CODE_START`
+    instructions: Vec<Vec<u8>>,
+}
+
+impl Program {
+    fn generate(rng: &mut Rng) -> Self {
+        let len = 1 + rng.below(MAX_INSTRUCTIONS);
+        let mut instructions: Vec<Vec<u8>> = (0..len)
+            .map(|_| loop {
+                let opcode = rng.byte();
+                if let Some(n) = instruction_length(opcode) {
+                    let mut bytes = vec![opcode];
+                    bytes.extend((1..n).map(|_| rng.byte()));
+                    break bytes;
+                }
+            })
+            .collect();
+
+        // Aim most jumps at the start of another instruction, so that they stay inside the program
+        let addrs = Self::layout(&instructions);
+        for (i, bytes) in instructions.iter_mut().enumerate() {
+            if rng.below(4) == 0 {
+                continue;
+            }
+            let target = addrs[rng.below(addrs.len())];
+            match bytes[0] {
+                0x18 | 0x20 | 0x28 | 0x30 | 0x38 => {
+                    let offset = target as i32 - (addrs[i] as i32 + 2);
+                    if let Ok(offset) = i8::try_from(offset) {
+                        bytes[1] = offset as u8;
+                    }
+                }
+                0xC2 | 0xC3 | 0xCA | 0xD2 | 0xDA | 0xC4 | 0xCC | 0xCD | 0xD4 | 0xDC => {
+                    bytes[1..].copy_from_slice(&target.to_le_bytes());
+                }
+                _ => (),
+            }
+        }
+
+        Program { instructions }
+    }
+
+    /// Addresses of each instruction, and then the address just after the last one
+    fn layout(instructions: &[Vec<u8>]) -> Vec<u16> {
+        let mut addrs = vec![CODE_START];
+        for bytes in instructions {
+            addrs.push(addrs.last().unwrap() + bytes.len() as u16);
+        }
+        addrs
+    }
+
+    fn listing(&self, highlight: Option<u16>) -> String {
+        let mut s = String::new();
+        for (addr, bytes) in Self::layout(&self.instructions)
+            .iter()
+            .zip(&self.instructions)
+        {
+            let marker = if Some(*addr) == highlight { ">" } else { " " };
+            writeln!(s, "{} {:04X}: {:02X?}", marker, addr, bytes).unwrap();
+        }
+        s
+    }
+}
+
+/// Wraps a [`CpuRunner`] so that it can be stepped one instruction at a time
+struct Runner {
+    runner: CpuRunner,
+    /// The fetch cycle of the next instruction, which has not been given its data yet
+    next: CpuRunnerYield,
+}
+
+impl Runner {
+    fn new(cpu: Cpu) -> Self {
+        Self::resume(cpu.runner())
+    }
+
+    /// Wrap a runner that is about to fetch an instruction
+    fn resume(mut runner: CpuRunner) -> Self {
+        let next = runner.clock(CpuInputPins::default());
+        assert!(next.is_fetch_cycle);
+        Runner { runner, next }
+    }
+
+    /// PC and IME as they were at the end of the last instruction, before the next fetch
+    fn cpu(&self) -> Cpu {
+        let mut cpu = self.runner.cpu;
+        cpu.registers.pc = self.next.pins.addr();
+        cpu
+    }
+
+    /// Run until the next instruction fetch, and return the number of M-cycles that took, or
+    /// `None` if it took unreasonably long.
+    fn step(&mut self, ram: &mut Ram) -> Option<u32> {
+        for cycles in 1..=8 {
+            let data = match self.next.pins {
+                CpuOutputPins::Read { addr } => ram.read(addr),
+                CpuOutputPins::Write { addr, data } => {
+                    ram.write(addr, data);
+                    0xFF
+                }
+            };
+            self.next = self.runner.clock(CpuInputPins {
+                data,
+                ..Default::default()
+            });
+            if self.next.is_fetch_cycle {
+                return Some(cycles);
+            }
+        }
+        None
+    }
+}
+
+fn random_state(rng: &mut Rng, program: &Program) -> (Cpu, Ram) {
+    let mut ram = Ram(Box::new([0; 0x10000]));
+    ram.0.iter_mut().for_each(|b| *b = rng.byte());
+    let code: Vec<u8> = program.instructions.concat();
+    let start = CODE_START as usize;
+    ram.0[start..start + code.len()].copy_from_slice(&code);
+
+    let cpu = Cpu {
+        registers: Registers {
+            a: rng.byte(),
+            f: FRegister::from(rng.byte()),
+            b: rng.byte(),
+            c: rng.byte(),
+            d: rng.byte(),
+            e: rng.byte(),
+            h: rng.byte(),
+            l: rng.byte(),
+            // Keep the stack in RAM so that calls can return
+            sp: RAM_START + rng.below(0x7FFF) as u16,
+            pc: CODE_START,
+        },
+        ime: rng.below(2) == 0,
+        ..Default::default()
+    };
+    (cpu, ram)
+}
+
+fn run_program(iteration: u64, rng: &mut Rng) {
+    let program = Program::generate(rng);
+    let (cpu, ram) = random_state(rng, &program);
+    let addrs = Program::layout(&program.instructions);
+    let end = *addrs.last().unwrap();
+
+    let mut reference = Reference::new(cpu);
+    let mut reference_ram = ram.clone();
+    let mut runner = Runner::new(cpu);
+    let mut runner_ram = ram;
+
+    for _ in 0..MAX_INSTRUCTIONS * 4 {
+        let pc = reference.cpu.registers.pc;
+        // Stop once control flow leaves the program, or lands in the middle of an instruction
+        if pc == end || !addrs.contains(&pc) {
+            break;
+        }
+
+        let expected_cycles = reference.step(&mut reference_ram);
+        let cycles = runner.step(&mut runner_ram);
+        let actual = runner.cpu();
+
+        let mut differences = String::new();
+        if actual.registers != reference.cpu.registers {
+            writeln!(
+                differences,
+                "registers:\n  expected {:?}\n  actual   {:?}",
+                reference.cpu.registers, actual.registers
+            )
+            .unwrap();
+        }
+        if actual.ime != reference.cpu.ime {
+            writeln!(
+                differences,
+                "IME: expected {}, actual {}",
+                reference.cpu.ime, actual.ime
+            )
+            .unwrap();
+        }
+        if cycles != Some(expected_cycles) {
+            writeln!(
+                differences,
+                "M-cycles: expected {}, actual {:?}",
+                expected_cycles, cycles
+            )
+            .unwrap();
+        }
+        if runner_ram != reference_ram {
+            let diffs = (0..=0xFFFF)
+                .filter(|&addr| runner_ram.0[addr] != reference_ram.0[addr])
+                .take(8);
+            for addr in diffs {
+                writeln!(
+                    differences,
+                    "memory at {:04X}: expected {:02X}, actual {:02X}",
+                    addr, reference_ram.0[addr], runner_ram.0[addr]
+                )
+                .unwrap();
+            }
+        }
+
+        if !differences.is_empty() {
+            panic!(
+                "CPU diverged from the reference in iteration {} (seed {:#X}) at {:04X}\n\
+                 {}\ninitial state: {:?}, IME {}\nprogram:\n{}",
+                iteration,
+                seed(),
+                pc,
+                differences,
+                cpu.registers,
+                cpu.ime,
+                program.listing(Some(pc))
+            );
+        }
+        if cycles.is_none() {
+            break;
+        }
+    }
+}
+
+fn env_u64(name: &str) -> Option<u64> {
+    let s = env::var(name).ok()?;
+    match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+fn seed() -> u64 {
+    env_u64("GB_CPU_FUZZ_SEED").unwrap_or(DEFAULT_SEED)
+}
+
+/// Run a program, and partway through restore a second runner from the first one's state, then
+/// check that they carry on in lockstep
+fn resume_program(iteration: u64, rng: &mut Rng) {
+    let program = Program::generate(rng);
+    let (cpu, ram) = random_state(rng, &program);
+    let addrs = Program::layout(&program.instructions);
+
+    let mut original = Runner::new(cpu);
+    let mut original_ram = ram;
+    for _ in 0..rng.below(MAX_INSTRUCTIONS) {
+        if original.step(&mut original_ram).is_none() {
+            return;
+        }
+    }
+    let flags = original.runner.state_flags();
+    let mut resumed = Runner::resume(CpuRunner::from_state(original.cpu(), flags));
+    let mut resumed_ram = original_ram.clone();
+    assert_eq!(resumed.runner.state_flags(), flags);
+
+    for _ in 0..MAX_INSTRUCTIONS {
+        let pc = original.cpu().registers.pc;
+        if !addrs.contains(&pc) {
+            break;
+        }
+        let expected_cycles = original.step(&mut original_ram);
+        let cycles = resumed.step(&mut resumed_ram);
+        let (expected, actual) = (original.cpu(), resumed.cpu());
+        assert!(
+            cycles == expected_cycles
+                && actual.registers == expected.registers
+                && actual.ime == expected.ime
+                && resumed_ram == original_ram,
+            "resumed CPU diverged in iteration {} (seed {:#X}) at {:04X}\n\
+             expected {:?}, IME {}, {:?} M-cycles\nactual   {:?}, IME {}, {:?} M-cycles\n\
+             program:\n{}",
+            iteration,
+            seed(),
+            pc,
+            expected.registers,
+            expected.ime,
+            expected_cycles,
+            actual.registers,
+            actual.ime,
+            cycles,
+            program.listing(Some(pc))
+        );
+        if cycles.is_none() {
+            break;
+        }
+    }
+}
+
+#[test]
+fn differential_fuzz() {
+    let iterations = env_u64("GB_CPU_FUZZ_ITERATIONS").unwrap_or(DEFAULT_ITERATIONS);
+    // xorshift gets stuck at 0
+    let mut rng = Rng(seed() | 1);
+    for iteration in 0..iterations {
+        run_program(iteration, &mut rng);
+    }
+}
+
+#[test]
+fn resuming_from_state_matches_running_through() {
+    let iterations = env_u64("GB_CPU_FUZZ_ITERATIONS").unwrap_or(DEFAULT_ITERATIONS);
+    let mut rng = Rng(seed() | 1);
+    for iteration in 0..iterations {
+        resume_program(iteration, &mut rng);
+    }
+}
@@ -1,18 +1,42 @@
+#![no_std]
 #![feature(coroutines, coroutine_trait, never_type)]
 
+// The CPU itself only needs `core` and `alloc`. The `std` feature is kept on by default so that
+// hosted frontends can rely on std-only trait impls as they are added.
+extern crate alloc;
+#[cfg(any(test, feature = "std"))]
+extern crate std;
+
+#[cfg(feature = "asm")]
 pub mod assembler;
 mod decode;
+pub mod disassembler;
 mod execute;
+#[cfg(test)]
+mod fuzz;
+#[cfg(any(test, feature = "reference"))]
+pub mod reference;
 mod registers;
 
-pub use execute::{CpuRunner, CpuRunnerYield};
+/// What the CPU drives the bus with each M-cycle. This follows how the CPU is emulated, so it is
+/// only public with the `unstable-internals` feature, which `gb_core` wires the CPU to the bus with.
+#[cfg(any(test, feature = "unstable-internals"))]
+pub use execute::CpuRunnerYield;
+pub use execute::{CpuResumeFlags, CpuRunner, InterruptDispatch};
 pub use registers::{FRegister, Registers};
 
 /// Contains the state of a LR35902 CPU.
 #[derive(Clone, Copy, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Cpu {
     pub registers: Registers,
     pub ime: bool,
+    /// Set by HALT until an interrupt is requested
+    pub halted: bool,
+    /// EI only takes effect after the following instruction, so it sets this instead of IME
+    pub ei_pending: bool,
+    /// Set by an illegal opcode, after which the CPU does nothing until this is cleared
+    pub locked: bool,
 }
 
 #[derive(Debug, Clone, Copy)]
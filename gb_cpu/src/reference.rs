@@ -0,0 +1,577 @@
+//! A naive reference interpreter, used to check [`CpuRunner`](crate::CpuRunner) in differential
+//! tests.
+//!
+//! This is written to be easy to check against the documentation rather than to be fast. Each call
+//! to [`Reference::step`] runs a whole instruction at once, and interrupts are not handled.
+
+use crate::decode::{self, Opcode};
+use crate::execute::{FlagCondition, LoadDest, LoadDest16Bit, MathOperation, RotateShiftOperation};
+use crate::{Cpu, FRegister};
+
+/// The memory seen by the [`Reference`] interpreter
+pub trait Bus {
+    fn read(&mut self, addr: u16) -> u8;
+    fn write(&mut self, addr: u16, data: u8);
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Reference {
+    pub cpu: Cpu,
+    pub halted: bool,
+    /// Set by EI. IME is enabled just before the instruction after EI is executed.
+    pub ei_pending: bool,
+}
+
+fn flags(z: bool, n: bool, h: bool, c: bool) -> FRegister {
+    let mut f = FRegister::EMPTY;
+    f.set_value(FRegister::ZERO, z);
+    f.set_value(FRegister::NEGATIVE, n);
+    f.set_value(FRegister::HALFCARRY, h);
+    f.set_value(FRegister::CARRY, c);
+    f
+}
+
+impl Reference {
+    pub fn new(cpu: Cpu) -> Self {
+        Reference {
+            cpu,
+            ..Default::default()
+        }
+    }
+
+    fn carry(&self) -> bool {
+        self.cpu.registers.f.contains(FRegister::CARRY)
+    }
+
+    fn fetch(&mut self, bus: &mut impl Bus) -> u8 {
+        let pc = self.cpu.registers.pc;
+        self.cpu.registers.pc = pc.wrapping_add(1);
+        bus.read(pc)
+    }
+
+    fn fetch_16(&mut self, bus: &mut impl Bus) -> u16 {
+        let low = self.fetch(bus);
+        let high = self.fetch(bus);
+        u16::from_le_bytes([low, high])
+    }
+
+    fn push(&mut self, bus: &mut impl Bus, v: u16) {
+        let [low, high] = v.to_le_bytes();
+        let regs = &mut self.cpu.registers;
+        regs.sp = regs.sp.wrapping_sub(1);
+        bus.write(regs.sp, high);
+        regs.sp = regs.sp.wrapping_sub(1);
+        bus.write(regs.sp, low);
+    }
+
+    fn pop(&mut self, bus: &mut impl Bus) -> u16 {
+        let regs = &mut self.cpu.registers;
+        let low = bus.read(regs.sp);
+        regs.sp = regs.sp.wrapping_add(1);
+        let high = bus.read(regs.sp);
+        regs.sp = regs.sp.wrapping_add(1);
+        u16::from_le_bytes([low, high])
+    }
+
+    fn get_r(&mut self, bus: &mut impl Bus, r: LoadDest) -> u8 {
+        let regs = &self.cpu.registers;
+        match r {
+            LoadDest::B => regs.b,
+            LoadDest::C => regs.c,
+            LoadDest::D => regs.d,
+            LoadDest::E => regs.e,
+            LoadDest::H => regs.h,
+            LoadDest::L => regs.l,
+            LoadDest::IndHL => bus.read(regs.get_hl()),
+            LoadDest::A => regs.a,
+        }
+    }
+
+    fn set_r(&mut self, bus: &mut impl Bus, r: LoadDest, v: u8) {
+        let regs = &mut self.cpu.registers;
+        match r {
+            LoadDest::B => regs.b = v,
+            LoadDest::C => regs.c = v,
+            LoadDest::D => regs.d = v,
+            LoadDest::E => regs.e = v,
+            LoadDest::H => regs.h = v,
+            LoadDest::L => regs.l = v,
+            LoadDest::IndHL => bus.write(regs.get_hl(), v),
+            LoadDest::A => regs.a = v,
+        }
+    }
+
+    fn get_rp(&self, rp: LoadDest16Bit) -> u16 {
+        let regs = &self.cpu.registers;
+        match rp {
+            LoadDest16Bit::AF => regs.get_af(),
+            LoadDest16Bit::BC => regs.get_bc(),
+            LoadDest16Bit::DE => regs.get_de(),
+            LoadDest16Bit::HL => regs.get_hl(),
+            LoadDest16Bit::SP => regs.sp,
+        }
+    }
+
+    fn set_rp(&mut self, rp: LoadDest16Bit, v: u16) {
+        let regs = &mut self.cpu.registers;
+        match rp {
+            LoadDest16Bit::AF => regs.set_af(v),
+            LoadDest16Bit::BC => regs.set_bc(v),
+            LoadDest16Bit::DE => regs.set_de(v),
+            LoadDest16Bit::HL => regs.set_hl(v),
+            LoadDest16Bit::SP => regs.sp = v,
+        }
+    }
+
+    fn condition(&self, cc: FlagCondition) -> bool {
+        let f = self.cpu.registers.f;
+        match cc {
+            FlagCondition::NZ => !f.contains(FRegister::ZERO),
+            FlagCondition::Z => f.contains(FRegister::ZERO),
+            FlagCondition::NC => !f.contains(FRegister::CARRY),
+            FlagCondition::C => f.contains(FRegister::CARRY),
+        }
+    }
+
+    fn alu(&mut self, op: MathOperation, v: u8) {
+        let a = self.cpu.registers.a;
+        let c = self.carry() as u8;
+        let (result, f) = match op {
+            MathOperation::Add => {
+                let r = a as u16 + v as u16;
+                let h = (a & 0xF) + (v & 0xF) > 0xF;
+                (r as u8, flags(r as u8 == 0, false, h, r > 0xFF))
+            }
+            MathOperation::Adc => {
+                let r = a as u16 + v as u16 + c as u16;
+                let h = (a & 0xF) + (v & 0xF) + c > 0xF;
+                (r as u8, flags(r as u8 == 0, false, h, r > 0xFF))
+            }
+            MathOperation::Sub | MathOperation::Cp => {
+                let r = a.wrapping_sub(v);
+                let h = (a & 0xF) < (v & 0xF);
+                (r, flags(r == 0, true, h, a < v))
+            }
+            MathOperation::Sbc => {
+                let r = a.wrapping_sub(v).wrapping_sub(c);
+                let h = (a & 0xF) < (v & 0xF) + c;
+                let carry = (a as u16) < v as u16 + c as u16;
+                (r, flags(r == 0, true, h, carry))
+            }
+            MathOperation::And => (a & v, flags(a & v == 0, false, true, false)),
+            MathOperation::Xor => (a ^ v, flags(a ^ v == 0, false, false, false)),
+            MathOperation::Or => (a | v, flags(a | v == 0, false, false, false)),
+        };
+        if op != MathOperation::Cp {
+            self.cpu.registers.a = result;
+        }
+        self.cpu.registers.f = f;
+    }
+
+    /// Perform a CB-prefixed rotate or shift and update all of the flags
+    fn rot(&mut self, op: RotateShiftOperation, v: u8) -> u8 {
+        let c = self.carry() as u8;
+        let (result, carry) = match op {
+            RotateShiftOperation::RLC => (v.rotate_left(1), v & 0x80 != 0),
+            RotateShiftOperation::RRC => (v.rotate_right(1), v & 0x01 != 0),
+            RotateShiftOperation::RL => ((v << 1) | c, v & 0x80 != 0),
+            RotateShiftOperation::RR => ((v >> 1) | (c << 7), v & 0x01 != 0),
+            RotateShiftOperation::SLA => (v << 1, v & 0x80 != 0),
+            RotateShiftOperation::SRA => ((v >> 1) | (v & 0x80), v & 0x01 != 0),
+            RotateShiftOperation::SWAP => (v.rotate_left(4), false),
+            RotateShiftOperation::SRL => (v >> 1, v & 0x01 != 0),
+        };
+        self.cpu.registers.f = flags(result == 0, false, false, carry);
+        result
+    }
+
+    /// Compute SP plus the signed offset `e` for `ADD SP, e` and `LD HL, SP+e`. Their flags come
+    /// from an unsigned add to the low byte of SP.
+    fn add_sp_e(&mut self, e: u8) -> u16 {
+        let sp = self.cpu.registers.sp;
+        let h = (sp & 0xF) + (e as u16 & 0xF) > 0xF;
+        let c = (sp & 0xFF) + e as u16 > 0xFF;
+        self.cpu.registers.f = flags(false, false, h, c);
+        sp.wrapping_add(e as i8 as u16)
+    }
+
+    /// Run one instruction, and return the number of M-cycles it took
+    pub fn step(&mut self, bus: &mut impl Bus) -> u32 {
+        if self.halted {
+            return 1;
+        }
+        if self.ei_pending {
+            self.ei_pending = false;
+            self.cpu.ime = true;
+        }
+
+        let opcode = Opcode(self.fetch(bus));
+        let (x, y, z, p, q) = (opcode.x(), opcode.y(), opcode.z(), opcode.p(), opcode.q());
+        match (x, z) {
+            (0, 0) => match y {
+                // NOP
+                0 => 1,
+                // LD (nn), SP
+                1 => {
+                    let addr = self.fetch_16(bus);
+                    let [low, high] = self.cpu.registers.sp.to_le_bytes();
+                    bus.write(addr, low);
+                    bus.write(addr.wrapping_add(1), high);
+                    5
+                }
+                // STOP, which is treated like HALT
+                2 => {
+                    self.halted = true;
+                    1
+                }
+                // JR e, JR cc, e
+                _ => {
+                    let e = self.fetch(bus) as i8;
+                    if y == 3 || self.condition(decode::cc(y - 4)) {
+                        let pc = &mut self.cpu.registers.pc;
+                        *pc = pc.wrapping_add(e as u16);
+                        3
+                    } else {
+                        2
+                    }
+                }
+            },
+            // LD rr, nn
+            (0, 1) if q == 0 => {
+                let v = self.fetch_16(bus);
+                self.set_rp(decode::rp(p), v);
+                3
+            }
+            // ADD HL, rr
+            (0, 1) => {
+                let hl = self.cpu.registers.get_hl();
+                let v = self.get_rp(decode::rp(p));
+                let zero = self.cpu.registers.f.contains(FRegister::ZERO);
+                let h = (hl & 0xFFF) + (v & 0xFFF) > 0xFFF;
+                let c = hl as u32 + v as u32 > 0xFFFF;
+                self.cpu.registers.f = flags(zero, false, h, c);
+                self.cpu.registers.set_hl(hl.wrapping_add(v));
+                2
+            }
+            // LD (rr), A and LD A, (rr)
+            (0, 2) => {
+                let regs = &mut self.cpu.registers;
+                let addr = match p {
+                    0 => regs.get_bc(),
+                    1 => regs.get_de(),
+                    _ => regs.get_hl(),
+                };
+                match p {
+                    2 => regs.set_hl(addr.wrapping_add(1)),
+                    3 => regs.set_hl(addr.wrapping_sub(1)),
+                    _ => (),
+                }
+                if q == 0 {
+                    bus.write(addr, self.cpu.registers.a);
+                } else {
+                    self.cpu.registers.a = bus.read(addr);
+                }
+                2
+            }
+            // INC rr, DEC rr
+            (0, 3) => {
+                let rp = decode::rp(p);
+                let v = self.get_rp(rp);
+                let v = if q == 0 {
+                    v.wrapping_add(1)
+                } else {
+                    v.wrapping_sub(1)
+                };
+                self.set_rp(rp, v);
+                2
+            }
+            // INC r, DEC r
+            (0, 4) | (0, 5) => {
+                let r = decode::r(y);
+                let v = self.get_r(bus, r);
+                let (result, h) = if z == 4 {
+                    (v.wrapping_add(1), v & 0xF == 0xF)
+                } else {
+                    (v.wrapping_sub(1), v & 0xF == 0)
+                };
+                let c = self.carry();
+                self.cpu.registers.f = flags(result == 0, z == 5, h, c);
+                self.set_r(bus, r, result);
+                if r == LoadDest::IndHL {
+                    3
+                } else {
+                    1
+                }
+            }
+            // LD r, n
+            (0, 6) => {
+                let r = decode::r(y);
+                let n = self.fetch(bus);
+                self.set_r(bus, r, n);
+                if r == LoadDest::IndHL {
+                    3
+                } else {
+                    2
+                }
+            }
+            (0, 7) => {
+                let zero = self.cpu.registers.f.contains(FRegister::ZERO);
+                let c = self.carry();
+                match y {
+                    // RLCA, RRCA, RLA, RRA are like their CB versions, but always clear Z
+                    0..=3 => {
+                        let a = self.rot(decode::rot(y), self.cpu.registers.a);
+                        self.cpu.registers.a = a;
+                        self.cpu.registers.f.unset(FRegister::ZERO);
+                    }
+                    // DAA
+                    4 => {
+                        let f = self.cpu.registers.f;
+                        let n = f.contains(FRegister::NEGATIVE);
+                        let h = f.contains(FRegister::HALFCARRY);
+                        let mut c = c;
+                        let mut a = self.cpu.registers.a;
+                        if !n {
+                            if c || a > 0x99 {
+                                a = a.wrapping_add(0x60);
+                                c = true;
+                            }
+                            if h || a & 0xF > 0x9 {
+                                a = a.wrapping_add(0x06);
+                            }
+                        } else {
+                            if c {
+                                a = a.wrapping_sub(0x60);
+                            }
+                            if h {
+                                a = a.wrapping_sub(0x06);
+                            }
+                        }
+                        self.cpu.registers.a = a;
+                        self.cpu.registers.f = flags(a == 0, n, false, c);
+                    }
+                    // CPL
+                    5 => {
+                        self.cpu.registers.a = !self.cpu.registers.a;
+                        self.cpu.registers.f = flags(zero, true, true, c);
+                    }
+                    // SCF
+                    6 => self.cpu.registers.f = flags(zero, false, false, true),
+                    // CCF
+                    _ => self.cpu.registers.f = flags(zero, false, false, !c),
+                }
+                1
+            }
+            // HALT
+            (1, 6) if y == 6 => {
+                self.halted = true;
+                1
+            }
+            // LD r, r
+            (1, _) => {
+                let v = self.get_r(bus, decode::r(z));
+                self.set_r(bus, decode::r(y), v);
+                if y == 6 || z == 6 {
+                    2
+                } else {
+                    1
+                }
+            }
+            // ALU A, r
+            (2, _) => {
+                let v = self.get_r(bus, decode::r(z));
+                self.alu(decode::alu(y), v);
+                if z == 6 {
+                    2
+                } else {
+                    1
+                }
+            }
+            (3, 0) => match y {
+                // RET cc
+                0..=3 => {
+                    if self.condition(decode::cc(y)) {
+                        self.cpu.registers.pc = self.pop(bus);
+                        5
+                    } else {
+                        2
+                    }
+                }
+                // LDH (n), A
+                4 => {
+                    let n = self.fetch(bus);
+                    bus.write(0xFF00 | n as u16, self.cpu.registers.a);
+                    3
+                }
+                // ADD SP, e
+                5 => {
+                    let e = self.fetch(bus);
+                    self.cpu.registers.sp = self.add_sp_e(e);
+                    4
+                }
+                // LDH A, (n)
+                6 => {
+                    let n = self.fetch(bus);
+                    self.cpu.registers.a = bus.read(0xFF00 | n as u16);
+                    3
+                }
+                // LD HL, SP+e
+                _ => {
+                    let e = self.fetch(bus);
+                    let v = self.add_sp_e(e);
+                    self.cpu.registers.set_hl(v);
+                    3
+                }
+            },
+            // POP rr
+            (3, 1) if q == 0 => {
+                let v = self.pop(bus);
+                self.set_rp(decode::rp2(p), v);
+                3
+            }
+            (3, 1) => match p {
+                // RET
+                0 => {
+                    self.cpu.registers.pc = self.pop(bus);
+                    4
+                }
+                // RETI
+                1 => {
+                    self.cpu.registers.pc = self.pop(bus);
+                    self.cpu.ime = true;
+                    4
+                }
+                // JP HL
+                2 => {
+                    self.cpu.registers.pc = self.cpu.registers.get_hl();
+                    1
+                }
+                // LD SP, HL
+                _ => {
+                    self.cpu.registers.sp = self.cpu.registers.get_hl();
+                    2
+                }
+            },
+            (3, 2) => match y {
+                // JP cc, nn
+                0..=3 => {
+                    let addr = self.fetch_16(bus);
+                    if self.condition(decode::cc(y)) {
+                        self.cpu.registers.pc = addr;
+                        4
+                    } else {
+                        3
+                    }
+                }
+                // LD (C), A
+                4 => {
+                    let addr = 0xFF00 | self.cpu.registers.c as u16;
+                    bus.write(addr, self.cpu.registers.a);
+                    2
+                }
+                // LD (nn), A
+                5 => {
+                    let addr = self.fetch_16(bus);
+                    bus.write(addr, self.cpu.registers.a);
+                    4
+                }
+                // LD A, (C)
+                6 => {
+                    let addr = 0xFF00 | self.cpu.registers.c as u16;
+                    self.cpu.registers.a = bus.read(addr);
+                    2
+                }
+                // LD A, (nn)
+                _ => {
+                    let addr = self.fetch_16(bus);
+                    self.cpu.registers.a = bus.read(addr);
+                    4
+                }
+            },
+            (3, 3) => match y {
+                // JP nn
+                0 => {
+                    self.cpu.registers.pc = self.fetch_16(bus);
+                    4
+                }
+                // CB prefix
+                1 => self.execute_cb(bus),
+                // DI
+                6 => {
+                    self.cpu.ime = false;
+                    1
+                }
+                // EI
+                7 => {
+                    self.ei_pending = true;
+                    1
+                }
+                _ => panic!("illegal opcode {:#04X}", opcode.0),
+            },
+            // CALL cc, nn
+            (3, 4) if y < 4 => {
+                let addr = self.fetch_16(bus);
+                if self.condition(decode::cc(y)) {
+                    self.push(bus, self.cpu.registers.pc);
+                    self.cpu.registers.pc = addr;
+                    6
+                } else {
+                    3
+                }
+            }
+            // PUSH rr
+            (3, 5) if q == 0 => {
+                let v = self.get_rp(decode::rp2(p));
+                self.push(bus, v);
+                4
+            }
+            // CALL nn
+            (3, 5) if p == 0 => {
+                let addr = self.fetch_16(bus);
+                self.push(bus, self.cpu.registers.pc);
+                self.cpu.registers.pc = addr;
+                6
+            }
+            // ALU A, n
+            (3, 6) => {
+                let n = self.fetch(bus);
+                self.alu(decode::alu(y), n);
+                2
+            }
+            // RST
+            (3, 7) => {
+                self.push(bus, self.cpu.registers.pc);
+                self.cpu.registers.pc = y as u16 * 8;
+                4
+            }
+            _ => panic!("illegal opcode {:#04X}", opcode.0),
+        }
+    }
+
+    fn execute_cb(&mut self, bus: &mut impl Bus) -> u32 {
+        let opcode = Opcode(self.fetch(bus));
+        let r = decode::r(opcode.z());
+        let v = self.get_r(bus, r);
+        let indirect = r == LoadDest::IndHL;
+        match opcode.x() {
+            0 => {
+                let result = self.rot(decode::rot(opcode.y()), v);
+                self.set_r(bus, r, result);
+            }
+            // BIT only reads its operand, so it is a cycle shorter
+            1 => {
+                let z = v & (1 << opcode.y()) == 0;
+                let c = self.carry();
+                self.cpu.registers.f = flags(z, false, true, c);
+                return if indirect { 3 } else { 2 };
+            }
+            2 => self.set_r(bus, r, v & !(1 << opcode.y())),
+            _ => self.set_r(bus, r, v | (1 << opcode.y())),
+        }
+        if indirect {
+            4
+        } else {
+            2
+        }
+    }
+}
@@ -1,10 +1,11 @@
-use paste::paste;
-use std::{
+use core::{
     fmt::Debug,
     ops::{BitAnd, BitOr, BitOrAssign, Not},
 };
+use paste::paste;
 
 #[derive(Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Registers {
     pub a: u8,
     pub f: FRegister,
@@ -19,7 +20,7 @@ pub struct Registers {
 }
 
 impl Debug for Registers {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("Registers")
             .field("A", &format_args!("{:02X}", self.a))
             .field("B", &format_args!("{:02X}", self.b))
@@ -108,6 +109,7 @@ impl Registers {
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FRegister(u8);
 
 impl FRegister {
@@ -192,7 +194,7 @@ impl From<FRegister> for u8 {
 }
 
 impl Debug for FRegister {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(
             f,
             "{}",
@@ -0,0 +1,181 @@
+use gb_cpu::{
+    assembler::{assemble, AsmError, AsmErrorKind},
+    disassembler::disassemble,
+};
+
+/// xorshift64*
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn byte(&mut self) -> u8 {
+        (self.next() >> 56) as u8
+    }
+}
+
+/// Disassembles `bytes` at `addr`, then checks that assembling the text gives back the same
+/// instruction, which disassembles to the same text
+fn round_trip(bytes: &[u8], addr: u16) {
+    let (text, len) = disassemble(bytes, addr).unwrap();
+    let source = format!(".org ${:04X}\n{}", addr, text);
+    let assembled = assemble(&source).unwrap_or_else(|e| panic!("{:02X?}: {}", bytes, e));
+    assert_eq!(assembled, &bytes[..len as usize], "{}", text);
+    assert_eq!(disassemble(&assembled, addr).unwrap(), (text, len));
+}
+
+#[test]
+fn every_opcode_round_trips() {
+    let mut rng = Rng(0x5EED);
+    for prefix in [None, Some(0xCB)] {
+        for opcode in 0..=0xFF {
+            for _ in 0..16 {
+                let addr = (rng.next() >> 48) as u16 & 0xEFFF;
+                let operands = [rng.byte(), rng.byte()];
+                let bytes = match prefix {
+                    Some(prefix) => vec![prefix, opcode, operands[0]],
+                    None => vec![opcode, operands[0], operands[1]],
+                };
+                round_trip(&bytes, addr);
+            }
+        }
+    }
+}
+
+#[test]
+fn listings_round_trip() {
+    let mut rng = Rng(0xC0DE);
+    for _ in 0..50 {
+        let bytes: Vec<u8> = (0..256).map(|_| rng.byte()).collect();
+        let mut listing = String::from(".org $4000\n");
+        let mut addr = 0;
+        while let Some((text, len)) = disassemble(&bytes[addr..], 0x4000 + addr as u16) {
+            listing.push_str(&text);
+            listing.push('\n');
+            addr += len as usize;
+        }
+        assert_eq!(assemble(&listing).unwrap(), &bytes[..addr]);
+    }
+}
+
+#[test]
+fn disassembly() {
+    let dis = |bytes: &[u8], addr| disassemble(bytes, addr).unwrap().0;
+    assert_eq!(dis(&[0x18, 0xFE], 0x0150), "jr $0150");
+    assert_eq!(dis(&[0x20, 0x05], 0x0150), "jr nz, $0157");
+    assert_eq!(dis(&[0xE0, 0x40], 0), "ldh [$FF40], a");
+    assert_eq!(dis(&[0xF8, 0xFE], 0), "ld hl, sp-2");
+    assert_eq!(dis(&[0xE8, 0x05], 0), "add sp, 5");
+    assert_eq!(dis(&[0x2A], 0), "ld a, [hl+]");
+    assert_eq!(dis(&[0x96], 0), "sub [hl]");
+    assert_eq!(dis(&[0xCB, 0x7E], 0), "bit 7, [hl]");
+    assert_eq!(dis(&[0xD3], 0), ".db $D3");
+    assert_eq!(disassemble(&[0xCD, 0x00], 0), None);
+}
+
+#[test]
+fn labels_and_directives() {
+    let source = "
+        COUNT equ 3
+        .org $0150
+        start:
+            ld b, COUNT     ; forward and backward references
+            call wait
+        .again:
+            jr nz, .again
+            jp start
+        wait:
+        .again:
+            dec b
+            jr nz, .again
+            ret
+        .org $0160
+        table:
+            .db 1, -1, $FF, %1010
+            .dw table, end - table
+        end:
+    ";
+    #[rustfmt::skip]
+    assert_eq!(assemble(source).unwrap(), [
+        0x06, 0x03,
+        0xCD, 0x5A, 0x01,
+        0x20, 0xFE,
+        0xC3, 0x50, 0x01,
+        0x05,
+        0x20, 0xFD,
+        0xC9,
+        0x00, 0x00,
+        0x01, 0xFF, 0xFF, 0x0A,
+        0x60, 0x01, 0x08, 0x00,
+    ]);
+}
+
+#[test]
+fn alternative_spellings() {
+    let same = |a: &str, b: &str| assert_eq!(assemble(a).unwrap(), assemble(b).unwrap(), "{}", a);
+    same("LD A, (HL+)", "ld a, [hl+]");
+    same("ld a, [hli]", "ld a, [hl+]");
+    same("ld [hld], a", "ld [hl-], a");
+    same("sub a, b", "sub b");
+    same("add b", "add a, b");
+    same("ldh [$40], a", "ldh [$FF40], a");
+    same("ld [$FF00+c], a", "ldh [c], a");
+    same("ld a, [c]", "ldh a, [c]");
+    same("jp [hl]", "jp hl");
+    same("ld hl, sp + 2", "ld hl, sp+2");
+    same("ld a, 0x1F", "ld a, $1F");
+    same(".org 0x100\njr $", ".org $100\njr $0100");
+}
+
+#[test]
+fn errors_point_at_the_token() {
+    let error = |source: &str| assemble(source).unwrap_err();
+    let at = |line, token: &str, kind| AsmError {
+        line,
+        token: token.into(),
+        kind,
+    };
+    assert_eq!(
+        error("nop\nlf a, b"),
+        at(2, "lf", AsmErrorKind::UnknownMnemonic)
+    );
+    assert_eq!(
+        error("loop: ld [bc], b"),
+        at(1, "ld [bc], b", AsmErrorKind::InvalidOperands)
+    );
+    assert_eq!(
+        error("\n\njp nowhere"),
+        at(3, "nowhere", AsmErrorKind::UnknownSymbol)
+    );
+    assert_eq!(error("ld a, 256"), at(1, "256", AsmErrorKind::OutOfRange));
+    assert_eq!(
+        error("ld a, $1G"),
+        at(1, "$1G", AsmErrorKind::InvalidNumber)
+    );
+    assert_eq!(error("x:\nx:"), at(2, "x", AsmErrorKind::DuplicateSymbol));
+    assert_eq!(
+        error("jr far\n.org $100\nfar:"),
+        at(1, "far", AsmErrorKind::JumpTooFar)
+    );
+    assert_eq!(
+        error(".org $200\nnop\n.org $100"),
+        at(3, "$100", AsmErrorKind::OrgBackwards)
+    );
+    assert_eq!(
+        error("ld a, [hl"),
+        at(1, "", AsmErrorKind::Expected("\"]\""))
+    );
+    assert_eq!(
+        error("ld a b").to_string(),
+        "line 1: expected \",\", found \"b\""
+    );
+    assert_eq!(
+        error("nop\nld a, # 1").to_string(),
+        "line 2: expected an instruction, operand or directive, found \"#\""
+    );
+}
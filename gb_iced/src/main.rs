@@ -1,5 +1,6 @@
 use std::path::PathBuf;
 
+use gb_core::prelude::{Button, Gameboy, ResetKind, RtcTimeSource, SystemClock, FRAME_T_CYCLES};
 use iced::{
     keyboard::{key::Named, Key},
     window, Application, Element, Length, Settings,
@@ -7,8 +8,8 @@ use iced::{
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Message {
-    Pressed(gb_core::gameboy::joypad::Button),
-    Released(gb_core::gameboy::joypad::Button),
+    Pressed(Button),
+    Released(Button),
     TickFrame,
     TogglePause,
     DebugCpu,
@@ -18,7 +19,7 @@ enum Message {
 }
 
 struct App {
-    gameboy: gb_core::gameboy::Gameboy,
+    gameboy: Gameboy,
     paused: bool,
     log_instructions: bool,
 }
@@ -36,11 +37,17 @@ impl Application for App {
         rom.read_to_end(&mut buf).unwrap();
 
         let mut app = App {
-            gameboy: gb_core::gameboy::Gameboy::new(buf).unwrap(),
+            // A cartridge's clock keeps real time, as it would in a real Gameboy
+            gameboy: Gameboy::builder()
+                .rom(buf)
+                .rtc(Box::new(SystemClock))
+                .rtc_time_source(RtcTimeSource::WallClock)
+                .build()
+                .unwrap(),
             paused: false,
             log_instructions: false,
         };
-        app.gameboy.reset();
+        app.gameboy.reset(ResetKind::PowerCycle);
 
         let cmd = iced::Command::none();
         (app, cmd)
@@ -58,7 +65,7 @@ impl Application for App {
         match message {
             Message::TickFrame => {
                 if !self.paused {
-                    for _ in 0..gb_core::gameboy::ppu::consts::FRAME_T_CYCLES / 4 {
+                    for _ in 0..FRAME_T_CYCLES / 4 {
                         let debug_info = self.gameboy.clock();
                         if self.log_instructions && debug_info.is_fetch_cycle {
                             println!("{:?}", self.gameboy.cpu);
@@ -84,6 +91,7 @@ impl Application for App {
 
             Message::DebugCpu => {
                 println!("{:?}", self.gameboy.cpu);
+                println!("{:?}", self.gameboy.ppu.debug_snapshot());
                 iced::Command::none()
             }
             Message::StepInstruction => {
@@ -96,7 +104,7 @@ impl Application for App {
                     "{:?}",
                     (0..40)
                         .filter_map(|i| {
-                            let entry = self.gameboy.ppu.oam(i);
+                            let entry = self.gameboy.ppu.oam(i).ok()?;
                             if entry != Default::default() {
                                 Some(entry)
                             } else {
@@ -129,7 +137,7 @@ impl Application for App {
                 iced::widget::Image::new(iced::widget::image::Handle::from_pixels(
                     160,
                     144,
-                    u32_to_bgra(frame.iter().copied()),
+                    u32_to_bgra(frame.colors()),
                 ))
                 .width(Length::FillPortion(5))
                 .height(Length::FillPortion(3)),
@@ -200,16 +208,16 @@ fn u32_to_bgra(x: impl Iterator<Item = u32>) -> Vec<u8> {
     x.flat_map(|p| p.to_le_bytes()).collect()
 }
 
-fn keycode_to_button(key: &Key) -> Option<gb_core::gameboy::joypad::Button> {
+fn keycode_to_button(key: &Key) -> Option<Button> {
     match key {
-        Key::Named(Named::ArrowUp) => Some(gb_core::gameboy::joypad::Button::Up),
-        Key::Named(Named::ArrowLeft) => Some(gb_core::gameboy::joypad::Button::Left),
-        Key::Named(Named::ArrowRight) => Some(gb_core::gameboy::joypad::Button::Right),
-        Key::Named(Named::ArrowDown) => Some(gb_core::gameboy::joypad::Button::Down),
-        Key::Character(c) if c == "z" => Some(gb_core::gameboy::joypad::Button::B),
-        Key::Character(c) if c == "x" => Some(gb_core::gameboy::joypad::Button::A),
-        Key::Character(c) if c == "g" => Some(gb_core::gameboy::joypad::Button::Select),
-        Key::Character(c) if c == "h" => Some(gb_core::gameboy::joypad::Button::Start),
+        Key::Named(Named::ArrowUp) => Some(Button::Up),
+        Key::Named(Named::ArrowLeft) => Some(Button::Left),
+        Key::Named(Named::ArrowRight) => Some(Button::Right),
+        Key::Named(Named::ArrowDown) => Some(Button::Down),
+        Key::Character(c) if c == "z" => Some(Button::B),
+        Key::Character(c) if c == "x" => Some(Button::A),
+        Key::Character(c) if c == "g" => Some(Button::Select),
+        Key::Character(c) if c == "h" => Some(Button::Start),
         _ => None,
     }
 }
@@ -0,0 +1,78 @@
+//! WebAssembly bindings for running the emulator in a browser.
+//!
+//! Build with `wasm-pack build --target web gb_wasm`, then serve `gb_wasm/` and open
+//! `index.html`.
+
+use gb_core::{
+    gameboy::rtc::RtcSource,
+    prelude::{Gameboy, ResetKind},
+};
+use wasm_bindgen::prelude::*;
+
+/// Reads the time from the browser's `Date.now()`
+struct JsClock;
+
+impl RtcSource for JsClock {
+    fn now(&mut self) -> u64 {
+        (js_sys::Date::now() / 1000.0) as u64
+    }
+}
+
+#[wasm_bindgen]
+pub struct WasmGameboy {
+    gameboy: Gameboy,
+}
+
+#[wasm_bindgen]
+impl WasmGameboy {
+    /// Load a ROM and reset the machine, ready to run
+    #[wasm_bindgen(constructor)]
+    pub fn new(rom: &[u8]) -> Result<WasmGameboy, JsValue> {
+        let mut gameboy = Gameboy::builder()
+            .rom(rom)
+            .rtc(Box::new(JsClock))
+            .build()
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        gameboy.reset(ResetKind::PowerCycle);
+        Ok(WasmGameboy { gameboy })
+    }
+
+    /// Run one frame and return it as 160x144 RGBA pixels, suitable for `ImageData`
+    pub fn run_frame(&mut self) -> js_sys::Uint8ClampedArray {
+        let frame = self.gameboy.run_frames(1);
+        let pixels: Vec<u8> = frame.colors().flat_map(|p| p.to_le_bytes()).collect();
+        js_sys::Uint8ClampedArray::from(&pixels[..])
+    }
+
+    /// Set the state of every button at once. A set bit means the button is held.
+    ///
+    /// From bit 0 to bit 7: A, B, Select, Start, Right, Left, Up, Down
+    pub fn set_buttons(&mut self, buttons: u8) {
+        let joypad = &mut self.gameboy.joypad;
+        joypad.a = buttons & 0x01 != 0;
+        joypad.b = buttons & 0x02 != 0;
+        joypad.select = buttons & 0x04 != 0;
+        joypad.start = buttons & 0x08 != 0;
+        joypad.right = buttons & 0x10 != 0;
+        joypad.left = buttons & 0x20 != 0;
+        joypad.up = buttons & 0x40 != 0;
+        joypad.down = buttons & 0x80 != 0;
+    }
+
+    /// Contents of the cartridge RAM, or an empty array if the cartridge has none
+    pub fn save_ram(&self) -> Vec<u8> {
+        self.gameboy
+            .cart
+            .ram()
+            .map(<[u8]>::to_vec)
+            .unwrap_or_default()
+    }
+
+    /// Restore cartridge RAM previously returned by [`WasmGameboy::save_ram`]
+    pub fn load_ram(&mut self, save: &[u8]) -> Result<(), JsValue> {
+        self.gameboy
+            .cart
+            .load_ram(save)
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+}
@@ -1,40 +1,81 @@
 #![feature(try_blocks)]
 
+#[cfg(not(feature = "single-thread"))]
 use std::sync::Arc;
 
-use gb_core::gameboy::Gameboy;
+use gb_core::prelude::{Gameboy, ResetKind, RtcTimeSource, SystemClock, FRAME_T_CYCLES};
+#[cfg(not(feature = "single-thread"))]
 use smol::channel::Receiver;
 
+#[cfg(not(feature = "single-thread"))]
 use smol::lock::Mutex;
+#[cfg(not(feature = "single-thread"))]
 use smol::stream::StreamExt;
-use window::{InputEvent, ViewEvent};
+use window::InputEvent;
+#[cfg(not(feature = "single-thread"))]
+use window::ViewEvent;
 
 mod window;
 
 fn main() {
     let rom_path = std::env::args().nth(1).expect("Expected path to ROM");
     let rom_data = std::fs::read(rom_path).unwrap();
-    let gameboy = Gameboy::new(rom_data).unwrap();
-
-    let (input_send, input_recv) = smol::channel::bounded(8);
-
-    let view = window::ViewSetup::new(input_send);
-    let event_loop_proxy = view.event_loop_proxy();
-
-    std::thread::spawn(move || game_thread(gameboy, input_recv, event_loop_proxy));
+    // A cartridge's clock keeps real time, as it would in a real Gameboy
+    let mut gameboy = Gameboy::builder()
+        .rom(rom_data)
+        .rtc(Box::new(SystemClock))
+        .rtc_time_source(RtcTimeSource::WallClock)
+        .build()
+        .unwrap();
+    let frames = gameboy.frame_receiver();
+
+    #[cfg(not(feature = "single-thread"))]
+    {
+        let (input_send, input_recv) = smol::channel::bounded(8);
+
+        let view = window::ViewSetup::new(input_send, frames);
+        let event_loop_proxy = view.event_loop_proxy();
+
+        std::thread::spawn(move || game_thread(gameboy, input_recv, event_loop_proxy));
+
+        // ViewSetup is not Send or Sync, so it has to run on the thread it was made on.
+        view.run()
+    }
+
+    // With gb_core's `single-thread` feature the Gameboy isn't Send, so it runs on the window's
+    // thread instead. The window doesn't wait on the queue while it is drained once a frame.
+    #[cfg(feature = "single-thread")]
+    {
+        let (input_send, input_recv) = smol::channel::unbounded();
+        let view = window::ViewSetup::new(input_send, frames);
+        gameboy.reset(ResetKind::PowerCycle);
+        view.run_emulating(move || {
+            while let Ok(input) = input_recv.try_recv() {
+                handle_input(&mut gameboy, input);
+            }
+            for _ in 0..FRAME_T_CYCLES / 4 {
+                gameboy.clock();
+            }
+        })
+    }
+}
 
-    // ViewSetup is not Send or Sync, so it has to run on the thread it was made on.
-    view.run()
+fn handle_input(gameboy: &mut Gameboy, input: InputEvent) {
+    match input {
+        InputEvent::ButtonPressed(button) => gameboy.joypad.press(button),
+        InputEvent::ButtonReleased(button) => gameboy.joypad.release(button),
+    }
 }
 
+#[cfg(not(feature = "single-thread"))]
 fn game_thread(
-    mut gameboy: gb_core::gameboy::Gameboy,
+    mut gameboy: Gameboy,
     input_recv: Receiver<window::InputEvent>,
     event_loop_proxy: winit::event_loop::EventLoopProxy<window::ViewEvent>,
 ) {
     let exec = smol::Executor::new();
 
-    gameboy.reset();
+    gameboy.reset(ResetKind::PowerCycle);
 
     let gameboy = Arc::new(Mutex::new(gameboy));
 
@@ -44,12 +85,7 @@ fn game_thread(
         async move {
             loop {
                 let input = input_recv.recv().await.unwrap();
-                match input {
-                    InputEvent::ButtonPressed(button) => gameboy.lock().await.joypad.press(button),
-                    InputEvent::ButtonReleased(button) => {
-                        gameboy.lock().await.joypad.release(button)
-                    }
-                }
+                handle_input(&mut *gameboy.lock().await, input);
             }
         }
     })
@@ -60,14 +96,12 @@ fn game_thread(
         let mut frame_timer = smol::Timer::interval(std::time::Duration::from_millis(16));
         while (frame_timer.next().await).is_some() {
             let mut gameboy = gameboy.lock().await;
-            for _ in 0..gb_core::gameboy::ppu::consts::FRAME_T_CYCLES / 4 {
+            for _ in 0..FRAME_T_CYCLES / 4 {
                 gameboy.clock();
             }
 
-            let frame = gameboy.get_frame();
-
             if event_loop_proxy
-                .send_event(ViewEvent::GameboyFrame { frame })
+                .send_event(ViewEvent::GameboyFrame)
                 .is_err()
             {
                 break;
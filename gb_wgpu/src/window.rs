@@ -1,6 +1,9 @@
-use std::sync::Arc;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use gb_core::gameboy::{joypad::Button, ppu::frame::Frame};
+use gb_core::prelude::{scale, Button, FrameReceiver};
 use smol::channel::Sender;
 use winit::{
     event::{ElementState, Event, KeyEvent, WindowEvent},
@@ -11,13 +14,14 @@ use winit::{
 
 #[derive(Debug)]
 pub enum ViewEvent {
-    GameboyFrame { frame: Box<Frame> },
+    /// A new frame is ready to be read from the [`FrameReceiver`]
+    GameboyFrame,
 }
 
 #[derive(Debug)]
 pub enum InputEvent {
-    ButtonPressed(gb_core::gameboy::joypad::Button),
-    ButtonReleased(gb_core::gameboy::joypad::Button),
+    ButtonPressed(Button),
+    ButtonReleased(Button),
 }
 
 pub struct ViewSetup {
@@ -25,10 +29,11 @@ pub struct ViewSetup {
     window: Arc<Window>,
     event_loop_proxy: EventLoopProxy<ViewEvent>,
     input_send: Sender<InputEvent>,
+    frames: FrameReceiver,
 }
 
 impl ViewSetup {
-    pub fn new(input_send: Sender<InputEvent>) -> Self {
+    pub fn new(input_send: Sender<InputEvent>, frames: FrameReceiver) -> Self {
         let event_loop = winit::event_loop::EventLoopBuilder::with_user_event()
             .build()
             .unwrap();
@@ -44,15 +49,31 @@ impl ViewSetup {
             window,
             event_loop_proxy,
             input_send,
+            frames,
         }
     }
 
+    #[cfg(not(feature = "single-thread"))]
     pub fn event_loop_proxy(&self) -> EventLoopProxy<ViewEvent> {
         self.event_loop_proxy.clone()
     }
 
     /// Permanently blocks the current thread.
+    #[cfg(not(feature = "single-thread"))]
     pub fn run(self) {
+        self.run_with(None::<fn()>)
+    }
+
+    /// Permanently blocks the current thread, calling `emulate_frame` every 16ms and showing the
+    /// frame it finished, for when the emulator can't have a thread of its own.
+    #[cfg(feature = "single-thread")]
+    pub fn run_emulating(self, emulate_frame: impl FnMut()) {
+        self.run_with(Some(emulate_frame))
+    }
+
+    fn run_with(self, mut emulate_frame: Option<impl FnMut()>) {
+        const FRAME_INTERVAL: Duration = Duration::from_millis(16);
+        let mut next_frame = Instant::now();
         let surface = pixels::SurfaceTexture::new(
             self.window.inner_size().width,
             self.window.inner_size().height,
@@ -111,26 +132,28 @@ impl ViewSetup {
                 },
 
                 Event::UserEvent(event) => match event {
-                    ViewEvent::GameboyFrame { frame } => {
-                        let framebuffer = pixels_ctx.frame_mut();
-                        let fb_pitch = 160 * 4;
-
-                        for y in 0..144 {
-                            for x in 0..160 {
-                                let pix = frame[(x, y)];
-                                let [r, g, b, a] = pix.to_le_bytes();
-
-                                let fb_offset = y * fb_pitch + x * 4;
-                                framebuffer[fb_offset] = r;
-                                framebuffer[fb_offset + 1] = g;
-                                framebuffer[fb_offset + 2] = b;
-                                framebuffer[fb_offset + 3] = a;
-                            }
-                        }
+                    ViewEvent::GameboyFrame => {
+                        let frame = self.frames.latest();
+                        scale::scale_nearest(&frame, 1, pixels_ctx.frame_mut()).unwrap();
 
                         self.window.request_redraw();
                     }
                 },
+                Event::AboutToWait => {
+                    if let Some(emulate_frame) = &mut emulate_frame {
+                        let now = Instant::now();
+                        if now >= next_frame {
+                            emulate_frame();
+                            // Shown the same way as a frame from another thread
+                            self.event_loop_proxy
+                                .send_event(ViewEvent::GameboyFrame)
+                                .unwrap();
+                            // Skip ahead rather than catching up after a stall
+                            next_frame = (next_frame + FRAME_INTERVAL).max(now);
+                        }
+                        elwt.set_control_flow(ControlFlow::WaitUntil(next_frame));
+                    }
+                }
                 _ => {}
             })
             .unwrap()